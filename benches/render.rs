@@ -0,0 +1,72 @@
+// Benchmarks for asset loading and frame encoding. Both need a GPU adapter
+// and are skipped without one.
+
+use async_std::task::block_on;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::time::Duration;
+use wr3d::config::Config;
+use wr3d::scene::Scene;
+use wr3d::state::State;
+
+// the same cube as an obj with its mtl and as a glTF with an embedded buffer
+const SCENES: [&str; 2] = ["data/cube.obj", "data/cube.gltf"];
+
+fn request_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+    let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::Default,
+        compatible_surface: None,
+    }))?;
+    block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::default(),
+            shader_validation: true,
+        },
+        None,
+    ))
+    .ok()
+}
+
+// parsing plus vertex, index and texture uploads
+fn load(c: &mut Criterion) {
+    let (device, queue) = match request_device() {
+        Some(device) => device,
+        None => return eprintln!("no GPU adapter available, skipping load benchmarks"),
+    };
+    for path in &SCENES {
+        let name = path.rsplit('/').next().unwrap();
+        c.bench_function(&format!("load {}", name), |b| {
+            b.iter(|| {
+                let scene = Scene::load(&device, &queue, path).unwrap();
+                device.poll(wgpu::Maintain::Wait);
+                scene
+            })
+        });
+    }
+}
+
+// CPU time of one frame of the default scene: the update on its own, and
+// recording the frame graph's passes without the update before it or the
+// submit after it
+fn frame(c: &mut Criterion) {
+    let mut state = match block_on(State::new_headless(1280, 720, &Config::default())) {
+        Ok(state) => state,
+        Err(e) => return eprintln!("skipping frame benchmarks: {}", e),
+    };
+    c.bench_function("update", |b| b.iter(|| state.update()));
+    c.bench_function("encode frame", |b| {
+        b.iter_custom(|iters| {
+            let mut encoding = Duration::default();
+            for _ in 0..iters {
+                state.update();
+                state.render().unwrap();
+                encoding += state.renderer().encode_time();
+            }
+            encoding
+        })
+    });
+}
+
+criterion_group!(benches, load, frame);
+criterion_main!(benches);
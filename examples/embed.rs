@@ -0,0 +1,48 @@
+// draws a model with wr3d's renderer inside an application's own event loop,
+// keeping the scene and camera on the application's side
+use winit::{
+    event::*,
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+use anyhow::Result;
+use std::time::Instant;
+use wr3d::camera::Camera;
+use wr3d::config::{CameraConfig, RendererConfig};
+use wr3d::scene::Scene;
+use wr3d::Renderer;
+
+#[async_std::main]
+async fn main() -> Result<()> {
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new().with_title("wr3d embedded").build(&event_loop)?;
+    let size = window.inner_size();
+    let mut renderer = Renderer::new(&window, &RendererConfig::default()).await?;
+    let scene = Scene::load(renderer.device(), renderer.queue(), "data/cube.obj")?;
+    let mut camera = Camera::from_config(
+        &CameraConfig::default(), size.width as f32 / size.height as f32);
+    let mut last_frame = Instant::now();
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+        match event {
+            Event::WindowEvent { event: WindowEvent::CloseRequested, .. } =>
+                *control_flow = ControlFlow::Exit,
+            Event::WindowEvent { event: WindowEvent::Resized(size), .. } => {
+                renderer.resize(size);
+                camera.aspect_ratio = size.width as f32 / size.height.max(1) as f32;
+            }
+            Event::MainEventsCleared => window.request_redraw(),
+            Event::RedrawRequested(_) => {
+                // animated materials move with the renderer's time
+                let now = Instant::now();
+                renderer.update((now - last_frame).as_secs_f32());
+                last_frame = now;
+                if let Err(e) = renderer.render_frame(&scene, &camera) {
+                    eprintln!("{:?}", e);
+                }
+            }
+            _ => (),
+        }
+    });
+}
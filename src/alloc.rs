@@ -0,0 +1,100 @@
+use log::info;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    // per thread, so scene loads, capture saving, the remote control and
+    // file watchers running next to the frame loop don't count towards it.
+    // a const initializer, so counting doesn't allocate itself
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+// threads being torn down have no counter left, which is fine since they
+// aren't the frame loop
+fn count() {
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+// wraps the system allocator and counts every allocation, installed as the
+// global allocator in debug builds to keep the frame loop allocation-free
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+// made by the calling thread so far
+pub fn allocations() -> usize {
+    ALLOCATIONS.try_with(Cell::get).unwrap_or(0)
+}
+
+// counts the calling thread's allocations made between `begin` and `end`
+pub struct AllocationScope {
+    start: usize,
+}
+
+impl AllocationScope {
+    pub fn begin() -> Self {
+        Self { start: allocations() }
+    }
+
+    pub fn end(self) -> usize {
+        allocations() - self.start
+    }
+}
+
+// frames that may still allocate while buffers grow to fit and caches fill
+pub const WARM_UP_FRAMES: u32 = 120;
+
+// checks the frame loop's allocations once warmed up, see `StatsConfig`.
+// updating can't allocate at all. rendering goes through wgpu, which
+// allocates internally while encoding and submitting: every pass and
+// encoder collects its commands into vectors, and submitting tracks the
+// resources they used. that grows with the passes and draw calls, not
+// with anything the renderer does, so a frame gets `budget` allocations
+// for it. the count of the first frame checked is logged, to set a budget
+// from
+pub struct FrameAudit {
+    frames: u32,
+    budget: usize,
+}
+
+impl FrameAudit {
+    pub fn new(budget: usize) -> Self {
+        Self { frames: 0, budget }
+    }
+
+    // `update` and `render` are the allocations of a frame's two halves,
+    // with no `render` for frames drawing UI, see `UiBackend::is_drawing`
+    pub fn check(&mut self, update: usize, render: Option<usize>) {
+        if self.frames < WARM_UP_FRAMES {
+            self.frames += 1;
+            return;
+        }
+        if let Some(render) = render.filter(|_| self.frames == WARM_UP_FRAMES) {
+            self.frames += 1;
+            info!("frames allocate {} times while rendering, the budget is {}", render, self.budget);
+        }
+        assert_eq!(update, 0, "State::step allocated {} times", update);
+        if let Some(render) = render {
+            assert!(render <= self.budget, "A frame allocated {} times, over the budget of {}", render, self.budget);
+        }
+    }
+}
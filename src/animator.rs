@@ -0,0 +1,398 @@
+use crate::mesh::Transform;
+use crate::skin::Animation;
+use glam::{Quat, Vec3};
+
+// a clip being played, indexing the skin's `animations`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Playback {
+    pub clip: usize,
+    // seconds into the clip
+    pub time: f32,
+    pub speed: f32,
+    // holds the last frame at the end instead of starting over
+    pub looping: bool,
+    // start and end, in seconds, to loop between instead of the whole clip
+    pub region: Option<(f32, f32)>,
+}
+
+impl Playback {
+    pub fn new(clip: usize) -> Self {
+        Self { clip, time: 0.0, speed: 1.0, looping: true, region: None }
+    }
+
+    fn advance(&mut self, dt: f32, clips: &[Animation]) {
+        let duration = clips.get(self.clip).map_or(0.0, |clip| clip.duration);
+        let time = self.time + dt * self.speed;
+        self.time = match self.region.filter(|(start, end)| end > start) {
+            _ if duration <= 0.0 => 0.0,
+            Some((start, end)) => start + (time - start).rem_euclid(end - start),
+            None if self.looping => time.rem_euclid(duration),
+            None => time.clamp(0.0, duration),
+        };
+    }
+}
+
+// adds how far its clip moves the joints from the clip's first frame on top
+// of the clips played, e.g. breathing or a wave over a walk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layer {
+    pub playback: Playback,
+    // 0 leaves the pose alone, 1 adds the whole movement
+    pub weight: f32,
+}
+
+// a state of the state machine, playing one clip
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationState {
+    pub name: String,
+    pub clip: usize,
+    pub speed: f32,
+    pub looping: bool,
+}
+
+// crossfades to the state `to` over `fade` seconds once `parameter` goes
+// above `threshold`, or below it when `above` is false. from the state
+// `from`, or from any other when that's None
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub from: Option<String>,
+    pub to: String,
+    pub parameter: String,
+    pub threshold: f32,
+    pub above: bool,
+    pub fade: f32,
+}
+
+// plays a skin's clips: one at a time, crossfading from the last when
+// switching, with additive layers on top. clips are played directly through
+// `play`, or through a small state machine of named states whose
+// transitions follow parameters the application sets, e.g. an idle state
+// going to walk once "speed" goes above 0.1 and back below it
+#[derive(Debug, Clone, Default)]
+pub struct Animator {
+    current: Option<Playback>,
+    // the clip fading out, how long it has been fading and for how long it
+    // will
+    fading: Option<(Playback, f32, f32)>,
+    pub layers: Vec<Layer>,
+    // holds every clip where it is, e.g. for scrubbing
+    pub paused: bool,
+    states: Vec<AnimationState>,
+    transitions: Vec<Transition>,
+    parameters: Vec<(String, f32)>,
+    state: Option<usize>,
+    // the time of the last `advance`
+    last: Option<f32>,
+    // poses blended in, reused every frame
+    scratch: Vec<Transform>,
+    reference: Vec<Transform>,
+}
+
+impl Animator {
+    pub fn current(&self) -> Option<&Playback> {
+        self.current.as_ref()
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut Playback> {
+        self.current.as_mut()
+    }
+
+    // plays a clip from the start, looping, fading the one playing out over
+    // `fade` seconds. leaves the state machine's state until a transition
+    // fires
+    pub fn play(&mut self, clip: usize, fade: f32) {
+        self.switch(Playback::new(clip), fade);
+        self.state = None;
+    }
+
+    // stops playing, leaving the joints in their last pose
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.fading = None;
+        self.state = None;
+    }
+
+    // sets the weight of the additive layer playing `clip`, adding it when
+    // there's none and removing it at 0
+    pub fn set_layer(&mut self, clip: usize, weight: f32) {
+        match self.layers.iter().position(|layer| layer.playback.clip == clip) {
+            Some(index) if weight <= 0.0 => {
+                self.layers.remove(index);
+            }
+            Some(index) => self.layers[index].weight = weight,
+            None if weight > 0.0 => self.layers.push(Layer { playback: Playback::new(clip), weight }),
+            None => {}
+        }
+    }
+
+    // replaces a state of the same name
+    pub fn add_state(&mut self, state: AnimationState) {
+        match self.states.iter().position(|other| other.name == state.name) {
+            Some(index) => self.states[index] = state,
+            None => self.states.push(state),
+        }
+    }
+
+    pub fn add_transition(&mut self, transition: Transition) {
+        self.transitions.push(transition);
+    }
+
+    pub fn states(&self) -> &[AnimationState] {
+        &self.states
+    }
+
+    // the state machine's current state, None when a clip was played
+    // directly
+    pub fn state(&self) -> Option<&str> {
+        self.state.map(|state| self.states[state].name.as_str())
+    }
+
+    // false when there's no such state
+    pub fn set_state(&mut self, name: &str, fade: f32) -> bool {
+        match self.states.iter().position(|state| state.name == name) {
+            Some(index) => {
+                self.enter(index, fade);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn enter(&mut self, index: usize, fade: f32) {
+        let state = &self.states[index];
+        let playback = Playback { speed: state.speed, looping: state.looping, ..Playback::new(state.clip) };
+        self.switch(playback, fade);
+        self.state = Some(index);
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<f32> {
+        self.parameters.iter().find(|(other, _)| other == name).map(|(_, value)| *value)
+    }
+
+    // transitions watching it fire on the next `advance`
+    pub fn set_parameter(&mut self, name: &str, value: f32) {
+        match self.parameters.iter_mut().find(|(other, _)| other == name) {
+            Some((_, old)) => *old = value,
+            None => self.parameters.push((name.to_string(), value)),
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.current.is_some() || !self.layers.is_empty()
+    }
+
+    // moves the clips on to `time`, in seconds, and follows the first
+    // transition whose condition holds. the first call only starts the
+    // clock, and time going backwards or pausing doesn't move them
+    pub fn advance(&mut self, time: f32, clips: &[Animation]) {
+        let dt = match self.last {
+            Some(last) if !self.paused => (time - last).max(0.0),
+            _ => 0.0,
+        };
+        self.last = Some(time);
+        if let Some((to, fade)) = self.transition() {
+            self.enter(to, fade);
+        }
+        if let Some(current) = &mut self.current {
+            current.advance(dt, clips);
+        }
+        if let Some((fading, elapsed, duration)) = &mut self.fading {
+            fading.advance(dt, clips);
+            *elapsed += dt;
+            if *elapsed >= *duration {
+                self.fading = None;
+            }
+        }
+        for layer in &mut self.layers {
+            layer.playback.advance(dt, clips);
+        }
+    }
+
+    fn transition(&self) -> Option<(usize, f32)> {
+        let current = self.state.map(|state| self.states[state].name.as_str());
+        self.transitions.iter()
+            .filter(|transition| transition.from.is_none() || transition.from.as_deref() == current)
+            .filter(|transition| Some(transition.to.as_str()) != current)
+            .filter(|transition| match self.parameter(&transition.parameter) {
+                Some(value) if transition.above => value > transition.threshold,
+                Some(value) => value < transition.threshold,
+                None => false,
+            })
+            .find_map(|transition| {
+                let to = self.states.iter().position(|state| state.name == transition.to)?;
+                Some((to, transition.fade))
+            })
+    }
+
+    // the joints' local transforms for the clips at their current times.
+    // joints and properties the clips don't move keep their `rest` values
+    pub fn pose(&mut self, clips: &[Animation], rest: &[Transform], pose: &mut [Transform]) {
+        pose.copy_from_slice(rest);
+        if let Some(clip) = self.current.and_then(|current| Some((clips.get(current.clip)?, current.time))) {
+            clip.0.sample(clip.1, pose);
+        }
+        self.scratch.resize(rest.len(), Transform::default());
+        self.reference.resize(rest.len(), Transform::default());
+        if let Some((fading, elapsed, duration)) = self.fading {
+            if let Some(clip) = clips.get(fading.clip) {
+                self.scratch.copy_from_slice(rest);
+                clip.sample(fading.time, &mut self.scratch);
+                let s = (elapsed / duration).clamp(0.0, 1.0);
+                for (pose, faded) in pose.iter_mut().zip(&self.scratch) {
+                    *pose = blend(faded, pose, s);
+                }
+            }
+        }
+        for layer in &self.layers {
+            let clip = match clips.get(layer.playback.clip) {
+                Some(clip) => clip,
+                None => continue,
+            };
+            self.scratch.copy_from_slice(rest);
+            clip.sample(layer.playback.time, &mut self.scratch);
+            self.reference.copy_from_slice(rest);
+            clip.sample(0.0, &mut self.reference);
+            for ((pose, moved), reference) in pose.iter_mut().zip(&self.scratch).zip(&self.reference) {
+                add(pose, reference, moved, layer.weight);
+            }
+        }
+    }
+
+    fn switch(&mut self, playback: Playback, fade: f32) {
+        self.fading = match self.current {
+            Some(current) if fade > 0.0 => Some((current, 0.0, fade)),
+            _ => None,
+        };
+        self.current = Some(playback);
+    }
+}
+
+fn blend(a: &Transform, b: &Transform, s: f32) -> Transform {
+    Transform {
+        translation: a.translation.lerp(b.translation, s),
+        rotation: slerp(a.rotation, b.rotation, s),
+        scale: a.scale.lerp(b.scale, s),
+    }
+}
+
+// adds the movement from `reference` to `moved` to the pose, `weight` of it
+fn add(pose: &mut Transform, reference: &Transform, moved: &Transform, weight: f32) {
+    pose.translation += (moved.translation - reference.translation) * weight;
+    let delta = reference.rotation.conjugate() * moved.rotation;
+    pose.rotation = (pose.rotation * slerp(Quat::identity(), delta, weight)).normalize();
+    let ratio = moved.scale / reference.scale.max(Vec3::splat(1e-6));
+    pose.scale *= Vec3::one().lerp(ratio, weight);
+}
+
+// the shorter way around, which glam's slerp doesn't pick
+fn slerp(a: Quat, b: Quat, s: f32) -> Quat {
+    let b = if a.dot(b) < 0.0 { -b } else { b };
+    a.slerp(b, s).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skin::{Channel, Property};
+
+    // moves the only joint from 0 to `to` along x over a second
+    fn slide(name: &str, to: f32) -> Animation {
+        Animation::new(name.to_string(), vec![Channel {
+            joint: 0,
+            property: Property::Translation,
+            step: false,
+            times: vec![0.0, 1.0],
+            values: vec![[0.0; 4], [to, 0.0, 0.0, 0.0]],
+        }])
+    }
+
+    fn x(animator: &mut Animator, clips: &[Animation]) -> f32 {
+        let mut pose = [Transform::default()];
+        animator.pose(clips, &[Transform::default()], &mut pose);
+        pose[0].translation.x
+    }
+
+    #[test]
+    fn crossfades_between_clips() {
+        let clips = [slide("left", -1.0), slide("right", 1.0)];
+        let mut animator = Animator::default();
+        animator.play(0, 0.0);
+        animator.advance(0.0, &clips);
+        animator.advance(0.5, &clips);
+        assert!((x(&mut animator, &clips) + 0.5).abs() < 1e-4);
+
+        // halfway through the fade the clips weigh the same, the first at
+        // -0.75 and the second at 0.25
+        animator.play(1, 0.5);
+        animator.advance(0.75, &clips);
+        assert!((x(&mut animator, &clips) + 0.25).abs() < 1e-4);
+        animator.advance(1.0, &clips);
+        assert!((x(&mut animator, &clips) - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn layers_add_on_top() {
+        let clips = [slide("walk", 1.0), slide("lean", 2.0)];
+        let mut animator = Animator::default();
+        animator.play(0, 0.0);
+        animator.set_layer(1, 0.5);
+        animator.advance(0.0, &clips);
+        animator.advance(0.5, &clips);
+        // 0.5 from the walk and half of the lean's 1
+        assert!((x(&mut animator, &clips) - 1.0).abs() < 1e-4);
+        animator.set_layer(1, 0.0);
+        assert!(animator.layers.is_empty());
+    }
+
+    #[test]
+    fn pauses_and_loops_a_region() {
+        let clips = [slide("slide", 1.0)];
+        let mut animator = Animator::default();
+        animator.play(0, 0.0);
+        animator.advance(0.0, &clips);
+        animator.paused = true;
+        animator.advance(0.5, &clips);
+        assert_eq!(animator.current().unwrap().time, 0.0);
+
+        animator.paused = false;
+        animator.current_mut().unwrap().region = Some((0.25, 0.5));
+        // 0.3 in is inside the region, 0.6 wraps back to 0.35
+        animator.advance(0.8, &clips);
+        assert!((animator.current().unwrap().time - 0.3).abs() < 1e-4);
+        animator.advance(1.1, &clips);
+        assert!((animator.current().unwrap().time - 0.35).abs() < 1e-4);
+    }
+
+    #[test]
+    fn transitions_follow_parameters() {
+        let clips = [slide("idle", 0.0), slide("walk", 1.0)];
+        let mut animator = Animator::default();
+        for (name, clip) in [("idle", 0), ("walk", 1)].iter() {
+            animator.add_state(AnimationState { name: name.to_string(), clip: *clip, speed: 1.0, looping: true });
+        }
+        let transition = |from: &str, to: &str, above| Transition {
+            from: Some(from.to_string()),
+            to: to.to_string(),
+            parameter: "speed".to_string(),
+            threshold: 0.1,
+            above,
+            fade: 0.0,
+        };
+        animator.add_transition(transition("idle", "walk", true));
+        animator.add_transition(transition("walk", "idle", false));
+        assert!(animator.set_state("idle", 0.0));
+        animator.set_parameter("speed", 0.0);
+        animator.advance(0.0, &clips);
+        assert_eq!(animator.state(), Some("idle"));
+
+        animator.set_parameter("speed", 1.0);
+        animator.advance(0.5, &clips);
+        assert_eq!(animator.state(), Some("walk"));
+        assert_eq!(animator.current().map(|current| current.clip), Some(1));
+
+        animator.set_parameter("speed", 0.0);
+        animator.advance(1.0, &clips);
+        assert_eq!(animator.state(), Some("idle"));
+        assert!(!animator.set_state("run", 0.0));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::streaming::MipChain;
+use crate::texture::Texture;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Deref;
+use std::sync::{Arc, Weak};
+use wgpu;
+
+// a reference counted asset. clones share the same GPU objects, which are
+// freed when the last handle is dropped
+pub struct Handle<T>(Arc<T>);
+
+impl<T> Handle<T> {
+    pub fn new(asset: T) -> Self {
+        Self(Arc::new(asset))
+    }
+
+    // whether both point at the same asset
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        Arc::ptr_eq(&a.0, &b.0)
+    }
+
+    // how many handles share the asset, this one included
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for Handle<T> {
+    fn from(asset: T) -> Self {
+        Self::new(asset)
+    }
+}
+
+// assets of one type by key. entries don't keep their assets alive, once
+// the last handle is gone the next request loads it again
+pub struct Cache<K, T> {
+    entries: HashMap<K, Weak<T>>,
+}
+
+impl<K: Hash + Eq, T> Default for Cache<K, T> {
+    fn default() -> Self {
+        Self { entries: HashMap::new() }
+    }
+}
+
+impl<K: Hash + Eq, T> Cache<K, T> {
+    pub fn get(&self, key: &K) -> Option<Handle<T>> {
+        self.entries.get(key).and_then(Weak::upgrade).map(Handle)
+    }
+
+    // replaces whatever was cached under `key`
+    pub fn insert(&mut self, key: K, asset: T) -> Handle<T> {
+        let handle = Handle::new(asset);
+        self.entries.insert(key, Arc::downgrade(&handle.0));
+        handle
+    }
+
+    // the cached asset, or the one `load` makes when there's none
+    pub fn get_or_try_insert_with<F: FnOnce() -> Result<T>>(&mut self, key: K, load: F) -> Result<Handle<T>> {
+        match self.get(&key) {
+            Some(handle) => Ok(handle),
+            None => Ok(self.insert(key, load()?)),
+        }
+    }
+
+    // forgets the asset, e.g. after its file changed. handles to it keep
+    // working
+    pub fn remove(&mut self, key: &K) {
+        self.entries.remove(key);
+    }
+
+    // assets still in use
+    pub fn len(&self) -> usize {
+        self.entries.values().filter(|entry| entry.strong_count() > 0).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // drops the entries of assets nothing uses anymore
+    pub fn purge(&mut self) {
+        self.entries.retain(|_, entry| entry.strong_count() > 0);
+    }
+}
+
+// shares what's loaded from files between everything asking for it, so
+// e.g. a diffuse map used by several materials is uploaded once. textures
+// are keyed by path and format. meshes and materials aren't shared: each
+// scene owns its own, since skinning, dynamic meshes, material edits and
+// overrides change them in place for that scene only
+#[derive(Default)]
+pub struct Assets {
+    pub textures: Cache<(String, wgpu::TextureFormat), Texture>,
+    // the largest mip textures are uploaded with when they're streamed
+    streaming: Option<u32>,
+}
+
+impl Assets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // uploads the textures loaded from now on only up to `initial_size`
+    // texels across, keeping the whole image on the CPU for
+    // `Scene::stream_textures`. none uploads them whole
+    pub fn set_streaming(&mut self, initial_size: Option<u32>) {
+        self.streaming = initial_size;
+    }
+
+    // the image file uploaded in `format`, read only if it isn't in use
+    pub fn texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, path: &str,
+                   format: wgpu::TextureFormat, label: Option<&str>) -> Result<Handle<Texture>> {
+        self.texture_with(device, queue, path, format, label, || {
+            Texture::load_image(path).with_context(|| format!("Failed to load {}", path))
+        })
+    }
+
+    // same for images that don't come from a file of their own, e.g. ones
+    // embedded in a gltf file, under a key made up for them
+    pub fn texture_with<F: FnOnce() -> Result<image::RgbaImage>>(
+        &mut self, device: &wgpu::Device, queue: &wgpu::Queue, key: &str, format: wgpu::TextureFormat,
+        label: Option<&str>, image: F,
+    ) -> Result<Handle<Texture>> {
+        let streaming = self.streaming;
+        self.textures.get_or_try_insert_with((key.to_string(), format), || match streaming {
+            Some(initial_size) => {
+                let mips = MipChain::new(image()?, format, label);
+                let first = mips.level_for_size(initial_size);
+                Ok(Texture::from_mips(device, queue, Arc::new(mips), first))
+            }
+            None => Ok(Texture::from_image(device, queue, &image()?, format, label)),
+        })
+    }
+
+    // drops the entries of assets nothing uses anymore
+    pub fn purge(&mut self) {
+        self.textures.purge();
+    }
+}
@@ -0,0 +1,36 @@
+#version 450
+
+// the index in BackgroundMode::ALL, the only other mode drawn is the skybox
+#define BACKGROUND_GRADIENT 2u
+
+#define PI 3.14159265
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0)
+  uniform Background {
+  mat4 u_inv_view_proj;
+  vec4 u_eye;
+  vec4 u_top;
+  vec4 u_bottom;
+  uvec4 u_mode;
+  // x added to the panorama's u to turn it, y its brightness
+  vec4 u_skybox;
+};
+layout(set=0, binding=1) uniform texture2D t_skybox;
+layout(set=0, binding=2) uniform sampler s_skybox;
+
+void main() {
+  if (u_mode.x == BACKGROUND_GRADIENT) {
+    f_color = mix(u_top, u_bottom, v_tex_coord.y);
+    return;
+  }
+  // the panorama seen in the view direction through this pixel
+  vec2 ndc = vec2(v_tex_coord.x * 2.0 - 1.0, 1.0 - v_tex_coord.y * 2.0);
+  vec4 far = u_inv_view_proj * vec4(ndc, 1.0, 1.0);
+  vec3 direction = normalize(far.xyz / far.w - u_eye.xyz);
+  vec2 uv = vec2(atan(direction.z, direction.x) / (2.0 * PI) + 0.5 + u_skybox.x,
+                 acos(clamp(direction.y, -1.0, 1.0)) / PI);
+  f_color = vec4(texture(sampler2D(t_skybox, s_skybox), uv).rgb * u_skybox.y, 1.0);
+}
@@ -0,0 +1,247 @@
+use wgpu;
+use anyhow::{Context, Result};
+use crate::camera::Camera;
+use crate::config::{BackgroundConfig, BackgroundMode};
+use crate::ibl;
+use crate::texture::{DepthTexture, Texture};
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BackgroundUniforms {
+    inv_view_proj: [[f32; 4]; 4],
+    eye: [f32; 4],
+    top: [f32; 4],
+    bottom: [f32; 4],
+    // x the mode, as its index in `BackgroundMode::ALL`
+    mode: [u32; 4],
+    // x the skybox's horizontal offset, see `ibl::panorama_offset`, and y
+    // what its colors are scaled by
+    skybox: [f32; 4],
+}
+
+// what's behind the scene. solid colors and transparency are just the main
+// pass's clear color, gradients and skyboxes are drawn by a fullscreen
+// pass before anything else
+pub struct Background {
+    config: BackgroundConfig,
+    // without a panorama the skybox mode falls back to the sky color
+    has_skybox: bool,
+    // degrees around the up axis and brightness, shared with the
+    // environment's lighting
+    rotation: f32,
+    intensity: f32,
+    format: wgpu::TextureFormat,
+    layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    // kept alive for the bind group
+    _skybox: Texture,
+    _sampler: wgpu::Sampler,
+}
+
+impl Background {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, config: &BackgroundConfig,
+               format: wgpu::TextureFormat, sample_count: u32) -> Result<Self> {
+        let skybox = match &config.skybox {
+            Some(path) => Texture::new(device, queue, path, wgpu::TextureFormat::Rgba8UnormSrgb, Some("skybox"))
+                .with_context(|| format!("Failed to load the skybox {}", path))?,
+            None => Texture::from_color(device, queue, [0, 0, 0, 255],
+                                        wgpu::TextureFormat::Rgba8UnormSrgb, Some("skybox")),
+        };
+        // wraps around horizontally, the panorama's left and right edges meet
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("skybox_sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer {
+                            dynamic: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::SampledTexture {
+                            multisampled: false,
+                            dimension: wgpu::TextureViewDimension::D2,
+                            component_type: wgpu::TextureComponentType::Float,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("background_bind_group_layout"),
+            }
+        );
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("background_uniform_buffer"),
+            size: std::mem::size_of::<BackgroundUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&skybox.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("background_bind_group"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("background_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &layout, format, sample_count);
+        Ok(Self {
+            config: config.clone(),
+            has_skybox: config.skybox.is_some(),
+            rotation: 0.0,
+            intensity: 1.0,
+            format,
+            layout,
+            pipeline,
+            uniform_buffer,
+            bind_group,
+            _skybox: skybox,
+            _sampler: sampler,
+        })
+    }
+
+    // rebuilds the pipeline for a main pass with `sample_count` samples
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.pipeline = create_pipeline(device, &self.layout, self.format, sample_count);
+    }
+
+    pub fn mode(&self) -> BackgroundMode {
+        self.config.mode
+    }
+
+    pub fn set_mode(&mut self, mode: BackgroundMode) {
+        self.config.mode = mode;
+    }
+
+    // the solid background's
+    pub fn set_color(&mut self, color: [f32; 3]) {
+        self.config.color = color;
+    }
+
+    // turns the skybox `rotation` degrees around the up axis and scales it
+    // by `intensity`, see `EnvironmentConfig`
+    pub fn set_skybox_view(&mut self, rotation: f32, intensity: f32) {
+        self.rotation = rotation;
+        self.intensity = intensity.max(0.0);
+    }
+
+    // whether the fullscreen pass runs at all
+    fn is_drawn(&self) -> bool {
+        match self.config.mode {
+            BackgroundMode::Gradient => true,
+            BackgroundMode::Skybox => self.has_skybox,
+            _ => false,
+        }
+    }
+
+    // what the main pass clears to, `sky` being the lighting's sky color
+    pub fn clear_color(&self, sky: [f32; 3]) -> wgpu::Color {
+        let color = |[r, g, b]: [f32; 3], a| wgpu::Color { r: r as f64, g: g as f64, b: b as f64, a };
+        match self.config.mode {
+            BackgroundMode::Solid => color(self.config.color, 1.0),
+            BackgroundMode::Transparent => color([0.0; 3], 0.0),
+            _ => color(sky, 1.0),
+        }
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue, camera: &Camera) {
+        if !self.is_drawn() {
+            return;
+        }
+        let [tr, tg, tb] = self.config.top;
+        let [br, bg, bb] = self.config.bottom;
+        let mode = BackgroundMode::ALL.iter().position(|mode| *mode == self.config.mode).unwrap_or(0);
+        let uniforms = BackgroundUniforms {
+            inv_view_proj: camera.build_view_projection_matrix().inverse().to_cols_array_2d(),
+            eye: camera.eye.extend(1.0).into(),
+            top: [tr, tg, tb, 1.0],
+            bottom: [br, bg, bb, 1.0],
+            mode: [mode as u32, 0, 0, 0],
+            skybox: [ibl::panorama_offset(self.rotation), self.intensity, 0.0, 0.0],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        if !self.is_drawn() {
+            return;
+        }
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, format: wgpu::TextureFormat,
+                   sample_count: u32) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("fullscreen.vert.spv"));
+    let fs_module = device.create_shader_module(wgpu::include_spirv!("background.frag.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("background_pipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        color_states: &[format.into()],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        // behind everything, so it neither tests nor writes depth
+        depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+            format: DepthTexture::FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilStateDescriptor::default(),
+        }),
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[],
+        },
+        sample_count,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
@@ -0,0 +1,134 @@
+use crate::collision::Aabb;
+use crate::mesh::{Mesh, Model, ObjectFlags, SubMesh, Transform};
+use crate::readback::Readbacks;
+use crate::scene::Scene;
+use crate::vertex::Vertex;
+use anyhow::Result;
+use glam::Vec3;
+use std::collections::{BTreeMap, HashMap};
+use wgpu;
+
+// a model made of static models' parts, drawn with one draw call
+pub(crate) struct Batch {
+    pub model: Model,
+    pub material: usize,
+    pub own_material: usize,
+}
+
+// the parts merged into one batch, which share everything but where they
+// were placed
+struct Group<'a> {
+    material: usize,
+    own_material: usize,
+    flags: ObjectFlags,
+    params: &'a BTreeMap<String, Vec<f32>>,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+}
+
+// reads the vertices of the `statics` back from the GPU, moves them to
+// where their models' transforms put them and merges the parts drawn the
+// same way into a batch each, in the order they're first drawn
+pub(crate) fn bake(device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene,
+                   statics: &[usize]) -> Result<Vec<Batch>> {
+    let mut readbacks = Readbacks::new();
+    let reads: Vec<_> = statics.iter()
+        .map(|&model| {
+            let mesh = &scene.models[model].mesh;
+            let vertex_size = (mesh.num_vertices as usize * std::mem::size_of::<Vertex>()) as wgpu::BufferAddress;
+            let index_size = (mesh.num_indices as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+            (readbacks.read_buffer(device, queue, &mesh.vertex_buffer, 0, vertex_size),
+             readbacks.read_buffer(device, queue, &mesh.index_buffer, 0, index_size))
+        })
+        .collect();
+    readbacks.wait(device);
+
+    let mut groups: Vec<Group> = vec![];
+    for (&model, (vertices, indices)) in statics.iter().zip(reads) {
+        let source = &scene.models[model];
+        let vertices = transformed(&source.transform, &cast::<Vertex>(&vertices.recv()??));
+        let indices = cast::<u32>(&indices.recv()??);
+        for draw in scene.draws.iter().filter(|draw| draw.model == model) {
+            let existing = groups.iter().position(|group| {
+                group.material == draw.material && group.own_material == draw.own_material
+                    && group.flags == source.flags && *group.params == source.params
+            });
+            let group = match existing {
+                Some(index) => &mut groups[index],
+                None => {
+                    groups.push(Group {
+                        material: draw.material,
+                        own_material: draw.own_material,
+                        flags: source.flags,
+                        params: &source.params,
+                        vertices: vec![],
+                        indices: vec![],
+                    });
+                    groups.last_mut().unwrap()
+                }
+            };
+            // only the vertices the part uses, numbered from the group's end
+            let mut renumbered = HashMap::new();
+            for &index in &indices[draw.indices.start as usize..draw.indices.end as usize] {
+                let new = match renumbered.get(&index) {
+                    Some(&new) => new,
+                    None => {
+                        let new = group.vertices.len() as u32;
+                        group.vertices.push(vertices[index as usize]);
+                        renumbered.insert(index, new);
+                        new
+                    }
+                };
+                group.indices.push(new);
+            }
+        }
+    }
+
+    Ok(groups.into_iter().enumerate()
+        .map(|(index, group)| {
+            let name = format!("static batch {}", index);
+            let submeshes = vec![SubMesh { material: group.own_material, indices: 0..group.indices.len() as u32 }];
+            let mesh = Mesh::from_vertices(device, &group.vertices, &group.indices, submeshes, &name);
+            let bounds = Aabb::from_points(group.vertices.iter().map(|vertex| Vec3::from(vertex.position)));
+            let mut model = Model::from_mesh(mesh, bounds, &name);
+            model.flags = group.flags;
+            model.params = group.params.clone();
+            Batch { model, material: group.material, own_material: group.own_material }
+        })
+        .collect())
+}
+
+// the vertices in world space, with the normals and tangents turned along
+fn transformed(transform: &Transform, vertices: &[Vertex]) -> Vec<Vertex> {
+    if *transform == Transform::default() {
+        return vertices.to_vec();
+    }
+    let matrix = transform.matrix();
+    let normal_matrix = matrix.inverse().transpose();
+    vertices.iter()
+        .map(|vertex| {
+            let [x, y, z, w] = vertex.tangent;
+            let tangent = unit(matrix.transform_vector3(Vec3::new(x, y, z)));
+            Vertex {
+                position: matrix.transform_point3(Vec3::from(vertex.position)).into(),
+                normal: unit(normal_matrix.transform_vector3(Vec3::from(vertex.normal))).into(),
+                tangent: [tangent.x, tangent.y, tangent.z, w],
+                ..*vertex
+            }
+        })
+        .collect()
+}
+
+// missing normals and tangents are zero and stay that way
+fn unit(vector: Vec3) -> Vec3 {
+    let length = vector.length();
+    if length > 0.0 { vector / length } else { vector }
+}
+
+// bytes read back from a buffer, which aren't aligned for `T`
+pub(crate) fn cast<T: bytemuck::Pod + Default>(bytes: &[u8]) -> Vec<T> {
+    let mut values = vec![T::default(); bytes.len() / std::mem::size_of::<T>()];
+    let len = values.len() * std::mem::size_of::<T>();
+    bytemuck::cast_slice_mut(&mut values).copy_from_slice(&bytes[..len]);
+    values
+}
@@ -0,0 +1,274 @@
+use wgpu;
+use crate::config::BloomConfig;
+use crate::texture::{RenderTexture, SamplerDesc};
+use crate::tonemap;
+
+// each pass' uniforms sit at their own offset of one buffer, which has to
+// be aligned like this for binding
+const UNIFORM_STRIDE: wgpu::BufferAddress = 256;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniforms {
+    // one texel of the pass' input in uv units
+    texel: [f32; 2],
+    // the brightness the first downsample keeps what's above of, 0 for the
+    // others
+    threshold: f32,
+    // what an upsample's result is scaled by before it's added
+    intensity: f32,
+}
+
+// the glow around bright parts of the HDR image: the first pass keeps what's
+// above `threshold`, a chain of half sized targets blurs it as it's
+// downsampled, and upsampling back through the chain adds each level onto
+// the one above until the result is added onto the image scaled by
+// `intensity`
+pub struct Bloom {
+    config: BloomConfig,
+    down_pipeline: wgpu::RenderPipeline,
+    up_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    // the chain from half the frame's size down
+    levels: Vec<RenderTexture>,
+    // downsample i reads level i - 1, the first reads the frame
+    down_bind_groups: Vec<wgpu::BindGroup>,
+    // upsample i reads level i
+    up_bind_groups: Vec<wgpu::BindGroup>,
+    size: (u32, u32),
+}
+
+impl Bloom {
+    pub fn new(device: &wgpu::Device, config: &BloomConfig, width: u32, height: u32) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        multisampled: false,
+                        dimension: wgpu::TextureViewDimension::D2,
+                        component_type: wgpu::TextureComponentType::Float,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("bloom_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bloom_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let down_module = device.create_shader_module(wgpu::include_spirv!("bloom_down.frag.spv"));
+        let up_module = device.create_shader_module(wgpu::include_spirv!("bloom_up.frag.spv"));
+        let down_pipeline = create_pipeline(device, &layout, &down_module, wgpu::BlendDescriptor::REPLACE);
+        // added onto the level above, keeping its alpha
+        let up_pipeline = create_pipeline(device, &layout, &up_module, wgpu::BlendDescriptor {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        });
+        let sampler = SamplerDesc { mag_filter: wgpu::FilterMode::Linear, min_filter: wgpu::FilterMode::Linear,
+                                    ..SamplerDesc::default() }.create(device, Some("bloom_sampler"));
+        let levels = config.levels.clamp(1, 8) as usize;
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bloom_uniform_buffer"),
+            size: UNIFORM_STRIDE * 2 * levels as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut bloom = Self {
+            config: config.clone(),
+            down_pipeline,
+            up_pipeline,
+            bind_group_layout,
+            sampler,
+            uniform_buffer,
+            levels: vec![],
+            down_bind_groups: vec![],
+            up_bind_groups: vec![],
+            size: (0, 0),
+        };
+        bloom.resize(device, width, height);
+        bloom
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.size = (width, height);
+        let count = self.config.levels.clamp(1, 8) as usize;
+        self.levels = (0..count)
+            .map(|i| {
+                let (width, height) = self.level_size(i);
+                RenderTexture::with_size(device, width, height, tonemap::FORMAT, Some("bloom_level"))
+            })
+            .collect();
+        // the uniforms of downsample i are at i, those of upsample i at
+        // count + i
+        self.down_bind_groups = (1..count)
+            .map(|i| self.bind_group(device, &self.levels[i - 1].view, i))
+            .collect();
+        self.up_bind_groups = (0..count)
+            .map(|i| self.bind_group(device, &self.levels[i].view, count + i))
+            .collect();
+    }
+
+    fn level_size(&self, i: usize) -> (u32, u32) {
+        ((self.size.0 >> (i + 1)).max(1), (self.size.1 >> (i + 1)).max(1))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.config.enabled = enabled;
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.config.threshold
+    }
+
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.config.threshold = threshold.max(0.0);
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.config.intensity
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.config.intensity = intensity.max(0.0);
+    }
+
+    // the texture and the uniforms at `pass`
+    fn bind_group(&self, device: &wgpu::Device, view: &wgpu::TextureView, pass: usize) -> wgpu::BindGroup {
+        let offset = UNIFORM_STRIDE * pass as wgpu::BufferAddress;
+        let size = std::mem::size_of::<BloomUniforms>() as wgpu::BufferAddress;
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(self.uniform_buffer.slice(offset..offset + size)),
+                },
+            ],
+            label: Some("bloom_bind_group"),
+        })
+    }
+
+    // adds the bloom of `hdr`, the frame sized HDR image, onto it
+    pub fn render(&self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder,
+                  hdr: &wgpu::TextureView) {
+        let count = self.levels.len();
+        let texel = |(width, height): (u32, u32)| [1.0 / width as f32, 1.0 / height as f32];
+        // downsample i reads the frame or level i - 1, upsample i level i
+        let down = (0..count).map(|i| BloomUniforms {
+            texel: texel(if i == 0 { self.size } else { self.level_size(i - 1) }),
+            threshold: if i == 0 { self.config.threshold } else { 0.0 },
+            intensity: 1.0,
+        });
+        let up = (0..count).map(|i| BloomUniforms {
+            texel: texel(self.level_size(i)),
+            threshold: 0.0,
+            intensity: if i == 0 { self.config.intensity } else { 1.0 },
+        });
+        let uniforms: Vec<BloomUniforms> = down.chain(up).collect();
+        for (pass, uniforms) in uniforms.iter().enumerate() {
+            queue.write_buffer(&self.uniform_buffer, UNIFORM_STRIDE * pass as wgpu::BufferAddress,
+                               bytemuck::bytes_of(uniforms));
+        }
+
+        // the frame's HDR image is a transient texture, which may be another
+        // one next frame
+        let frame = self.bind_group(device, hdr, 0);
+        for (i, level) in self.levels.iter().enumerate() {
+            let input = if i == 0 { &frame } else { &self.down_bind_groups[i - 1] };
+            draw(encoder, &level.view, wgpu::LoadOp::Clear(wgpu::Color::BLACK), &self.down_pipeline, input);
+        }
+        for i in (0..count).rev() {
+            let target = if i == 0 { hdr } else { &self.levels[i - 1].view };
+            draw(encoder, target, wgpu::LoadOp::Load, &self.up_pipeline, &self.up_bind_groups[i]);
+        }
+    }
+}
+
+// one fullscreen triangle into `target`
+fn draw(encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView, load: wgpu::LoadOp<wgpu::Color>,
+        pipeline: &wgpu::RenderPipeline, bind_group: &wgpu::BindGroup) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+            attachment: target,
+            resolve_target: None,
+            ops: wgpu::Operations { load, store: true },
+        }],
+        depth_stencil_attachment: None,
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, fs_module: &wgpu::ShaderModule,
+                   color_blend: wgpu::BlendDescriptor) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("fullscreen.vert.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("bloom_pipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        color_states: &[wgpu::ColorStateDescriptor {
+            format: tonemap::FORMAT,
+            color_blend,
+            alpha_blend: wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            write_mask: wgpu::ColorWrite::ALL,
+        }],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        depth_stencil_state: None,
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[],
+        },
+        sample_count: 1,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
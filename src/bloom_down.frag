@@ -0,0 +1,30 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_color;
+layout(set=0, binding=1) uniform sampler s_color;
+layout(set=0, binding=2) uniform Bloom {
+  vec2 b_texel;
+  float b_threshold;
+  float b_intensity;
+};
+
+vec3 tap(vec2 offset) {
+  return texture(sampler2D(t_color, s_color), v_tex_coord + offset * b_texel).rgb;
+}
+
+// halves the input with a 13 tap filter, which doesn't flicker as bright
+// pixels move, keeping only what's brighter than the threshold
+void main() {
+  vec3 center = tap(vec2(0.0));
+  vec3 inner = tap(vec2(-1.0, -1.0)) + tap(vec2(1.0, -1.0)) + tap(vec2(-1.0, 1.0)) + tap(vec2(1.0, 1.0));
+  vec3 corners = tap(vec2(-2.0, -2.0)) + tap(vec2(2.0, -2.0)) + tap(vec2(-2.0, 2.0)) + tap(vec2(2.0, 2.0));
+  vec3 sides = tap(vec2(-2.0, 0.0)) + tap(vec2(2.0, 0.0)) + tap(vec2(0.0, -2.0)) + tap(vec2(0.0, 2.0));
+  vec3 color = center * 0.125 + inner * 0.125 + corners * 0.03125 + sides * 0.0625;
+
+  float brightness = max(color.r, max(color.g, color.b));
+  color *= max(brightness - b_threshold, 0.0) / max(brightness, 1e-4);
+  f_color = vec4(color, 1.0);
+}
@@ -0,0 +1,24 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_color;
+layout(set=0, binding=1) uniform sampler s_color;
+layout(set=0, binding=2) uniform Bloom {
+  vec2 b_texel;
+  float b_threshold;
+  float b_intensity;
+};
+
+vec3 tap(vec2 offset) {
+  return texture(sampler2D(t_color, s_color), v_tex_coord + offset * b_texel).rgb;
+}
+
+// doubles the input with a 3x3 tent filter, added onto the level above
+void main() {
+  vec3 color = tap(vec2(0.0)) * 4.0
+             + (tap(vec2(-1.0, 0.0)) + tap(vec2(1.0, 0.0)) + tap(vec2(0.0, -1.0)) + tap(vec2(0.0, 1.0))) * 2.0
+             + tap(vec2(-1.0, -1.0)) + tap(vec2(1.0, -1.0)) + tap(vec2(-1.0, 1.0)) + tap(vec2(1.0, 1.0));
+  f_color = vec4(color / 16.0 * b_intensity, 0.0);
+}
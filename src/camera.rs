@@ -1,4 +1,9 @@
 use glam::{Vec3, Mat4};
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, MouseButton, VirtualKeyCode};
+use crate::collision::{Aabb, Bvh};
+use crate::config::{CameraConfig, FlyConfig, OrbitConfig, WalkConfig};
+use crate::input::InputEvent;
 
 pub struct Camera {
     pub eye: Vec3,
@@ -11,9 +16,425 @@ pub struct Camera {
 }
 
 impl Camera {
+    pub fn from_config(config: &CameraConfig, aspect_ratio: f32) -> Self {
+        Self {
+            eye: Vec3::from(config.eye),
+            center: Vec3::from(config.center),
+            up: Vec3::unit_y(),
+            aspect_ratio,
+            fov: config.fov,
+            z_near: config.z_near,
+            z_far: config.z_far,
+        }
+    }
+
     pub fn build_view_projection_matrix(&self) -> Mat4 {
-        let view = Mat4::look_at_rh(self.eye, self.center, self.up);
-        let proj = Mat4::perspective_rh(self.fov, self.aspect_ratio, self.z_near, self.z_far);
-        proj * view
+        self.build_projection_matrix() * self.build_view_matrix()
+    }
+
+    pub fn build_view_matrix(&self) -> Mat4 {
+        Mat4::look_at_rh(self.eye, self.center, self.up)
+    }
+
+    pub fn build_projection_matrix(&self) -> Mat4 {
+        Mat4::perspective_rh(self.fov, self.aspect_ratio, self.z_near, self.z_far)
+    }
+}
+
+// turntable-style inspection: dragging rotates the eye around the
+// camera's center and scrolling moves it closer or further away. input is
+// accumulated between frames and applied in `update`
+pub struct OrbitCameraController {
+    config: OrbitConfig,
+    rotating: bool,
+    cursor: Option<PhysicalPosition<f64>>,
+    yaw: f32,
+    pitch: f32,
+    // scroll steps, positive towards the center
+    zoom: f32,
+    snap: Option<Snap>,
+}
+
+// a turn to a new view direction in progress, as yaw and pitch around the
+// center
+struct Snap {
+    from: (f32, f32),
+    to: (f32, f32),
+    elapsed: f32,
+}
+
+impl OrbitCameraController {
+    pub fn new(config: &OrbitConfig) -> Self {
+        Self {
+            config: config.clone(),
+            rotating: false,
+            cursor: None,
+            yaw: 0.0,
+            pitch: 0.0,
+            zoom: 0.0,
+            snap: None,
+        }
+    }
+
+    // returns whether the event was used
+    pub fn process_events(&mut self, event: &InputEvent) -> bool {
+        match *event {
+            InputEvent::Button { button: MouseButton::Left, state } => {
+                self.rotating = state == ElementState::Pressed;
+                true
+            }
+            InputEvent::CursorMoved { x, y } => {
+                if let (true, Some(last)) = (self.rotating, self.cursor) {
+                    // dragging takes over from a snap
+                    self.snap = None;
+                    self.yaw -= (x - last.x) as f32 * self.config.sensitivity;
+                    self.pitch += (y - last.y) as f32 * self.config.sensitivity;
+                }
+                self.cursor = Some(PhysicalPosition::new(x, y));
+                self.rotating
+            }
+            InputEvent::Scroll { lines } => {
+                self.zoom += lines;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // turns the eye around the center until it looks from `direction`,
+    // easing in and out over the configured snap time. straight up and
+    // down stop just short of the poles, like dragging
+    pub fn snap_to(&mut self, camera: &Camera, direction: Vec3) {
+        let (yaw, pitch) = Self::angles(camera);
+        let mut to = (yaw, (direction.y / direction.length()).clamp(-1.0, 1.0).asin());
+        if direction.x != 0.0 || direction.z != 0.0 {
+            to.0 = direction.z.atan2(direction.x);
+            // the short way around
+            let turn = std::f32::consts::PI * 2.0;
+            to.0 = yaw + ((to.0 - yaw) % turn + turn * 1.5) % turn - turn * 0.5;
+        }
+        self.snap = Some(Snap { from: (yaw, pitch), to, elapsed: 0.0 });
+    }
+
+    // the yaw and pitch of the eye around the center
+    fn angles(camera: &Camera) -> (f32, f32) {
+        let offset = camera.eye - camera.center;
+        let distance = offset.length().max(f32::EPSILON);
+        (offset.z.atan2(offset.x), (offset.y / distance).clamp(-1.0, 1.0).asin())
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, dt: f32) {
+        if self.yaw == 0.0 && self.pitch == 0.0 && self.zoom == 0.0 && self.snap.is_none() {
+            return;
+        }
+        let distance = (camera.eye - camera.center).length().max(f32::EPSILON);
+        let limit = std::f32::consts::FRAC_PI_2 - 0.01;
+        let (mut yaw, mut pitch) = Self::angles(camera);
+        yaw += self.yaw;
+        pitch += self.pitch;
+        if let Some(snap) = &mut self.snap {
+            snap.elapsed += dt;
+            let t = (snap.elapsed / self.config.snap_time.max(f32::EPSILON)).min(1.0);
+            let t = t * t * (3.0 - 2.0 * t);
+            yaw = snap.from.0 + (snap.to.0 - snap.from.0) * t;
+            pitch = snap.from.1 + (snap.to.1 - snap.from.1) * t;
+            if t >= 1.0 {
+                self.snap = None;
+            }
+        }
+        let pitch = pitch.clamp(-limit, limit);
+        // zooming is relative to the distance, so it feels the same close
+        // up and far away, and never reaches the center
+        let distance = (distance * (-self.zoom * self.config.zoom_speed).exp()).max(camera.z_near);
+        camera.eye = camera.center
+            + Vec3::new(pitch.cos() * yaw.cos(), pitch.sin(), pitch.cos() * yaw.sin()) * distance;
+        self.yaw = 0.0;
+        self.pitch = 0.0;
+        self.zoom = 0.0;
+    }
+}
+
+// which controller moves the camera in the viewer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    Orbit,
+    Fly,
+    Walk,
+}
+
+impl CameraMode {
+    pub fn next(self) -> Self {
+        match self {
+            CameraMode::Orbit => CameraMode::Fly,
+            CameraMode::Fly => CameraMode::Walk,
+            CameraMode::Walk => CameraMode::Orbit,
+        }
+    }
+
+    // the first-person modes look around with the mouse
+    pub fn grabs_cursor(self) -> bool {
+        self != CameraMode::Orbit
+    }
+}
+
+// free flight: WASD moves along the view direction, E and Q up and down,
+// shift speeds up and the mouse looks around
+pub struct FlyCamera {
+    config: FlyConfig,
+    yaw: f32,
+    pitch: f32,
+    forward: bool,
+    backward: bool,
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+    boost: bool,
+}
+
+impl FlyCamera {
+    pub fn new(config: &FlyConfig) -> Self {
+        Self {
+            config: config.clone(),
+            yaw: 0.0,
+            pitch: 0.0,
+            forward: false,
+            backward: false,
+            left: false,
+            right: false,
+            up: false,
+            down: false,
+            boost: false,
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.config.speed = speed;
+    }
+
+    // starts flying from wherever the camera currently is
+    pub fn enter(&mut self, camera: &Camera) {
+        let direction = (camera.center - camera.eye).normalize();
+        self.yaw = direction.z.atan2(direction.x);
+        self.pitch = direction.y.asin();
+    }
+
+    pub fn process_keyboard(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
+        let pressed = state == ElementState::Pressed;
+        match key {
+            VirtualKeyCode::W | VirtualKeyCode::Up => self.forward = pressed,
+            VirtualKeyCode::S | VirtualKeyCode::Down => self.backward = pressed,
+            VirtualKeyCode::A | VirtualKeyCode::Left => self.left = pressed,
+            VirtualKeyCode::D | VirtualKeyCode::Right => self.right = pressed,
+            VirtualKeyCode::E => self.up = pressed,
+            VirtualKeyCode::Q => self.down = pressed,
+            VirtualKeyCode::LShift | VirtualKeyCode::RShift => self.boost = pressed,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn process_mouse(&mut self, dx: f64, dy: f64) {
+        let limit = std::f32::consts::FRAC_PI_2 - 0.01;
+        self.yaw += dx as f32 * self.config.sensitivity;
+        self.pitch = (self.pitch - dy as f32 * self.config.sensitivity).clamp(-limit, limit);
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, dt: f32) {
+        let direction = Vec3::new(
+            self.yaw.cos() * self.pitch.cos(), self.pitch.sin(), self.yaw.sin() * self.pitch.cos());
+        let right = direction.cross(Vec3::unit_y()).normalize();
+        let mut movement = Vec3::zero();
+        if self.forward { movement += direction; }
+        if self.backward { movement -= direction; }
+        if self.right { movement += right; }
+        if self.left { movement -= right; }
+        if self.up { movement += Vec3::unit_y(); }
+        if self.down { movement -= Vec3::unit_y(); }
+        if movement.length_squared() > 0.0 {
+            let speed = if self.boost { self.config.speed * self.config.boost } else { self.config.speed };
+            camera.eye += movement.normalize() * speed * dt;
+        }
+        camera.center = camera.eye + direction;
+    }
+}
+
+// how far below the scene a walker can fall before being put back where
+// walk mode was entered
+const KILL_DEPTH: f32 = 10.0;
+// resolving overlaps once can push the capsule into another triangle, a few
+// passes settle corners
+const COLLISION_PASSES: usize = 3;
+
+// first-person navigation at human scale: gravity, a capsule collider
+// against the scene's triangles and steps up to `step_height`
+pub struct WalkController {
+    config: WalkConfig,
+    // the walker's feet, the camera sits `eye_height` above them
+    position: Vec3,
+    spawn: Vec3,
+    yaw: f32,
+    pitch: f32,
+    vertical_velocity: f32,
+    grounded: bool,
+    forward: bool,
+    backward: bool,
+    left: bool,
+    right: bool,
+    jump: bool,
+}
+
+impl WalkController {
+    pub fn new(config: &WalkConfig) -> Self {
+        Self {
+            config: config.clone(),
+            position: Vec3::zero(),
+            spawn: Vec3::zero(),
+            yaw: 0.0,
+            pitch: 0.0,
+            vertical_velocity: 0.0,
+            grounded: false,
+            forward: false,
+            backward: false,
+            left: false,
+            right: false,
+            jump: false,
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.config.speed = speed;
+    }
+
+    // starts walking from wherever the camera currently is
+    pub fn enter(&mut self, camera: &Camera) {
+        let direction = (camera.center - camera.eye).normalize();
+        self.yaw = direction.z.atan2(direction.x);
+        self.pitch = direction.y.asin();
+        self.position = camera.eye - Vec3::unit_y() * self.config.eye_height;
+        self.spawn = self.position;
+        self.vertical_velocity = 0.0;
+        self.grounded = false;
+    }
+
+    // puts the walker's feet at `position`, looking the same way, and
+    // respawns there after a fall
+    pub fn teleport(&mut self, camera: &mut Camera, position: Vec3) {
+        self.position = position;
+        self.spawn = position;
+        self.vertical_velocity = 0.0;
+        self.grounded = false;
+        let direction = camera.center - camera.eye;
+        camera.eye = position + Vec3::unit_y() * self.config.eye_height;
+        camera.center = camera.eye + direction;
+    }
+
+    pub fn process_keyboard(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
+        let pressed = state == ElementState::Pressed;
+        match key {
+            VirtualKeyCode::W | VirtualKeyCode::Up => self.forward = pressed,
+            VirtualKeyCode::S | VirtualKeyCode::Down => self.backward = pressed,
+            VirtualKeyCode::A | VirtualKeyCode::Left => self.left = pressed,
+            VirtualKeyCode::D | VirtualKeyCode::Right => self.right = pressed,
+            VirtualKeyCode::Space => self.jump = pressed,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn process_mouse(&mut self, dx: f64, dy: f64) {
+        let limit = std::f32::consts::FRAC_PI_2 - 0.01;
+        self.yaw += dx as f32 * self.config.sensitivity;
+        self.pitch = (self.pitch - dy as f32 * self.config.sensitivity).max(-limit).min(limit);
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, collision: &Bvh, dt: f32) {
+        let forward = Vec3::new(self.yaw.cos(), 0.0, self.yaw.sin());
+        let right = forward.cross(Vec3::unit_y());
+        let mut movement = Vec3::zero();
+        if self.forward { movement += forward; }
+        if self.backward { movement -= forward; }
+        if self.right { movement += right; }
+        if self.left { movement -= right; }
+        if movement.length_squared() > 0.0 {
+            self.position += movement.normalize() * self.config.speed * dt;
+        }
+
+        if let Some(bounds) = collision.bounds() {
+            if self.grounded && self.jump {
+                self.vertical_velocity = self.config.jump_speed;
+                self.grounded = false;
+            }
+            if !self.grounded {
+                self.vertical_velocity -= self.config.gravity * dt;
+            }
+            self.position.y += self.vertical_velocity * dt;
+
+            for _ in 0..COLLISION_PASSES {
+                self.resolve_overlaps(collision);
+            }
+            self.snap_to_ground(collision);
+
+            if self.position.y < bounds.min.y - KILL_DEPTH {
+                self.position = self.spawn;
+                self.vertical_velocity = 0.0;
+                self.grounded = false;
+            }
+        }
+
+        let direction = Vec3::new(
+            self.yaw.cos() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.sin() * self.pitch.cos(),
+        );
+        camera.eye = self.position + Vec3::unit_y() * self.config.eye_height;
+        camera.center = camera.eye + direction;
+        camera.up = Vec3::unit_y();
+    }
+
+    // the capsule is approximated by spheres from just above step height up
+    // to the top of the head, anything lower is left to `snap_to_ground` so
+    // that steps are climbed instead of pushing the walker back
+    fn resolve_overlaps(&mut self, collision: &Bvh) {
+        let radius = self.config.radius;
+        let bottom = self.config.step_height + radius;
+        let top = (self.config.eye_height + radius * 0.5 - radius).max(bottom);
+        let spheres = [bottom, (bottom + top) * 0.5, top];
+        for height in spheres.iter() {
+            let center = self.position + Vec3::unit_y() * *height;
+            let bounds = Aabb {
+                min: center - Vec3::splat(radius),
+                max: center + Vec3::splat(radius),
+            };
+            let mut push = Vec3::zero();
+            collision.for_each_overlapping(&bounds, |triangle| {
+                let offset = center + push - triangle.closest_point(center + push);
+                let distance = offset.length();
+                if distance < radius && distance > f32::EPSILON {
+                    push += offset / distance * (radius - distance);
+                }
+            });
+            self.position += push;
+            if push.y < 0.0 && self.vertical_velocity > 0.0 {
+                // bumped a ceiling
+                self.vertical_velocity = 0.0;
+            }
+        }
+    }
+
+    fn snap_to_ground(&mut self, collision: &Bvh) {
+        let step = self.config.step_height;
+        // keep following the floor down stairs while grounded, otherwise
+        // only catch the floor once the feet reach it
+        let reach = if self.grounded { step * 2.0 } else { step };
+        let origin = self.position + Vec3::unit_y() * step;
+        match collision.raycast(origin, -Vec3::unit_y(), reach) {
+            Some(t) if self.vertical_velocity <= 0.0 => {
+                self.position.y = origin.y - t;
+                self.vertical_velocity = 0.0;
+                self.grounded = true;
+            }
+            _ => self.grounded = false,
+        }
     }
 }
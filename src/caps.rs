@@ -0,0 +1,91 @@
+use wgpu;
+use anyhow::{Context, Result};
+
+// asked for whenever the adapter has them, nothing depends on getting them
+const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::from_bits_truncate(
+    wgpu::Features::DEPTH_CLAMPING.bits()
+        | wgpu::Features::TEXTURE_COMPRESSION_BC.bits()
+        | wgpu::Features::PUSH_CONSTANTS.bits()
+);
+
+// what the device was actually granted, which subsystems check before
+// using anything beyond the defaults every adapter has. anisotropic
+// filtering isn't a feature in this wgpu, samplers get it where the
+// adapter supports it and ignore it elsewhere, and there are no timestamp
+// queries to ask for yet
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub adapter: wgpu::AdapterInfo,
+    pub features: wgpu::Features,
+    pub limits: wgpu::Limits,
+}
+
+impl Capabilities {
+    // picks an adapter and creates the device with every optional feature
+    // it has, at the default limits apart from push constants
+    pub(crate) async fn request(
+        instance: &wgpu::Instance,
+        compatible_surface: Option<&wgpu::Surface>,
+    ) -> Result<(wgpu::Device, wgpu::Queue, Self)> {
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::Default,
+                compatible_surface,
+            }
+        ).await.context("Failed to create adapter")?;
+        let features = adapter.features() & OPTIONAL_FEATURES;
+        let max_push_constant_size = if features.contains(wgpu::Features::PUSH_CONSTANTS) {
+            adapter.limits().max_push_constant_size
+        } else {
+            0
+        };
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features,
+                limits: wgpu::Limits {
+                    max_push_constant_size,
+                    ..wgpu::Limits::default()
+                },
+                shader_validation: true,
+            },
+            None,
+        ).await?;
+        let caps = Self {
+            adapter: adapter.get_info(),
+            features: device.features(),
+            limits: device.limits(),
+        };
+        Ok((device, queue, caps))
+    }
+
+    // depth outside the near and far planes is clamped instead of clipped
+    pub fn depth_clamping(&self) -> bool {
+        self.features.contains(wgpu::Features::DEPTH_CLAMPING)
+    }
+
+    // BC1 to BC7 compressed textures can be sampled
+    pub fn compressed_textures(&self) -> bool {
+        self.features.contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+    }
+
+    // bytes of push constants a pipeline can use, 0 without them
+    pub fn push_constant_size(&self) -> u32 {
+        if self.features.contains(wgpu::Features::PUSH_CONSTANTS) {
+            self.limits.max_push_constant_size
+        } else {
+            0
+        }
+    }
+
+    // the widest 2d texture the device takes. this wgpu has no limit for it,
+    // so it's the size every adapter supports
+    pub fn max_texture_size(&self) -> u32 {
+        8192
+    }
+
+    // compute shaders writing storage textures, which the environment maps
+    // are built with
+    pub fn storage_textures(&self) -> bool {
+        self.limits.max_storage_textures_per_shader_stage > 0
+    }
+}
@@ -0,0 +1,195 @@
+use wgpu;
+use wgpu::util::DeviceExt;
+use bytemuck::Zeroable;
+use glam::{Mat4, Vec2};
+use crate::camera::Camera;
+use crate::upscale;
+
+// the grid lights are binned into: tiles across and down the screen, and
+// depth slices from the near to the far plane. must match the defines in
+// cluster.comp, shader.frag and deferred.frag
+pub const CLUSTERS: [u32; 3] = [16, 9, 24];
+// the lights one cluster lists. when more reach a cluster, its fragments
+// go through every light instead
+pub const MAX_CLUSTER_LIGHTS: u32 = 127;
+// a cluster's light count and indices
+const CLUSTER_STRIDE: u32 = MAX_CLUSTER_LIGHTS + 1;
+
+// bound at set 3 binding 12 of the lit pipelines, and to the compute pass
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ClusterUniforms {
+    view: [[f32; 4]; 4],
+    // of the projection the frame is drawn with, jitter included, so the
+    // clusters line up with where fragments end up
+    inv_proj: [[f32; 4]; 4],
+    near: f32,
+    far: f32,
+    light_count: u32,
+    // whether the shaders read the clusters or go through every light
+    enabled: u32,
+}
+
+// bins the lights into a grid of clusters on the GPU every frame, so a
+// fragment only evaluates the lights whose range reaches its cluster.
+// owned by `Lights`, which binds the result next to the lights
+pub(crate) struct LightClusters {
+    uniform_buffer: wgpu::Buffer,
+    // zeroed, telling the shaders to go through every light
+    disabled_uniform_buffer: wgpu::Buffer,
+    cluster_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+    enabled: bool,
+}
+
+impl LightClusters {
+    // `lights` is the storage buffer of `LightRaw`s the shaders read
+    pub(crate) fn new(device: &wgpu::Device, lights: &wgpu::Buffer, enabled: bool) -> Self {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cluster_uniform_buffer"),
+            size: std::mem::size_of::<ClusterUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let disabled_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("cluster_disabled_uniform_buffer"),
+            contents: bytemuck::bytes_of(&ClusterUniforms::zeroed()),
+            usage: wgpu::BufferUsage::UNIFORM,
+        });
+        let clusters = CLUSTERS.iter().product::<u32>() * CLUSTER_STRIDE;
+        let cluster_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("cluster_light_buffer"),
+            size: (clusters as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::STORAGE,
+            mapped_at_creation: false,
+        });
+        let storage = |binding, readonly| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::COMPUTE,
+            ty: wgpu::BindingType::StorageBuffer {
+                dynamic: false,
+                min_binding_size: None,
+                readonly,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::COMPUTE,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, false),
+            ],
+            label: Some("cluster_bind_group_layout"),
+        });
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, &uniform_buffer, lights, &cluster_buffer);
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("cluster_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let module = device.create_shader_module(wgpu::include_spirv!("cluster.comp.spv"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("cluster_pipeline"),
+            layout: Some(&layout),
+            compute_stage: wgpu::ProgrammableStageDescriptor {
+                module: &module,
+                entry_point: "main",
+            },
+        });
+        Self {
+            uniform_buffer,
+            disabled_uniform_buffer,
+            cluster_buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+            enabled,
+        }
+    }
+
+    fn create_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, uniform_buffer: &wgpu::Buffer,
+                         lights: &wgpu::Buffer, cluster_buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(lights.slice(..)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(cluster_buffer.slice(..)),
+                },
+            ],
+            label: Some("cluster_bind_group"),
+        })
+    }
+
+    // after the lights' storage buffer was replaced by a bigger one
+    pub(crate) fn rebind(&mut self, device: &wgpu::Device, lights: &wgpu::Buffer) {
+        self.bind_group = Self::create_bind_group(
+            device, &self.bind_group_layout, &self.uniform_buffer, lights, &self.cluster_buffer);
+    }
+
+    // the uniforms and cluster lists at bindings 12 and 13 of the lights'
+    // bind group. without `clustered` the shaders ignore the lists
+    pub(crate) fn bind_group_entries(&self, clustered: bool) -> [wgpu::BindGroupEntry<'_>; 2] {
+        let uniform_buffer = if clustered { &self.uniform_buffer } else { &self.disabled_uniform_buffer };
+        [
+            wgpu::BindGroupEntry {
+                binding: 12,
+                resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+            },
+            wgpu::BindGroupEntry {
+                binding: 13,
+                resource: wgpu::BindingResource::Buffer(self.cluster_buffer.slice(..)),
+            },
+        ]
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    // bins `light_count` lights for a frame seen by `camera`, drawn with
+    // `jitter`. while disabled only tells the shaders to skip the clusters
+    pub(crate) fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, camera: &Camera,
+                           jitter: Vec2, light_count: usize) {
+        let view = Mat4::look_at_rh(camera.eye, camera.center, camera.up);
+        let proj = Mat4::perspective_rh(camera.fov, camera.aspect_ratio, camera.z_near, camera.z_far);
+        let uniforms = ClusterUniforms {
+            view: view.to_cols_array_2d(),
+            inv_proj: upscale::jittered(proj, jitter).inverse().to_cols_array_2d(),
+            near: camera.z_near,
+            far: camera.z_far,
+            light_count: light_count as u32,
+            enabled: self.enabled as u32,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+        if !self.enabled {
+            return;
+        }
+        let mut pass = encoder.begin_compute_pass();
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch(CLUSTERS.iter().product::<u32>().div_ceil(64), 1, 1);
+    }
+}
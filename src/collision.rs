@@ -0,0 +1,311 @@
+use glam::Vec3;
+use std::cell::Cell;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Triangle {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub c: Vec3,
+}
+
+impl Triangle {
+    fn centroid(&self) -> Vec3 {
+        (self.a + self.b + self.c) / 3.0
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb {
+            min: self.a.min(self.b).min(self.c),
+            max: self.a.max(self.b).max(self.c),
+        }
+    }
+
+    // Ericson, "Real-Time Collision Detection" 5.1.5
+    pub fn closest_point(&self, p: Vec3) -> Vec3 {
+        let (a, b, c) = (self.a, self.b, self.c);
+        let ab = b - a;
+        let ac = c - a;
+        let ap = p - a;
+        let d1 = ab.dot(ap);
+        let d2 = ac.dot(ap);
+        if d1 <= 0.0 && d2 <= 0.0 {
+            return a;
+        }
+        let bp = p - b;
+        let d3 = ab.dot(bp);
+        let d4 = ac.dot(bp);
+        if d3 >= 0.0 && d4 <= d3 {
+            return b;
+        }
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+            return a + ab * (d1 / (d1 - d3));
+        }
+        let cp = p - c;
+        let d5 = ab.dot(cp);
+        let d6 = ac.dot(cp);
+        if d6 >= 0.0 && d5 <= d6 {
+            return c;
+        }
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+            return a + ac * (d2 / (d2 - d6));
+        }
+        let va = d3 * d6 - d5 * d4;
+        if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+            return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+        }
+        let denom = 1.0 / (va + vb + vc);
+        a + ab * (vb * denom) + ac * (vc * denom)
+    }
+
+    // Möller–Trumbore, returns the distance along `direction`
+    pub fn raycast(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        let ab = self.b - self.a;
+        let ac = self.c - self.a;
+        let p = direction.cross(ac);
+        let det = ab.dot(p);
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let s = origin - self.a;
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(ab);
+        let v = direction.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = ac.dot(q) * inv_det;
+        if t >= 0.0 {
+            Some(t)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn from_points<I: IntoIterator<Item = Vec3>>(points: I) -> Self {
+        points.into_iter().fold(Self::empty(), |bounds, point| bounds.grow(&Aabb { min: point, max: point }))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    // radius of the sphere around `center` enclosing the box
+    pub fn radius(&self) -> f32 {
+        (self.max - self.min).length() * 0.5
+    }
+
+    pub(crate) fn empty() -> Self {
+        Self {
+            min: Vec3::splat(f32::INFINITY),
+            max: Vec3::splat(f32::NEG_INFINITY),
+        }
+    }
+
+    pub(crate) fn grow(&self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x && self.max.x >= other.min.x
+            && self.min.y <= other.max.y && self.max.y >= other.min.y
+            && self.min.z <= other.max.z && self.max.z >= other.min.z
+    }
+
+    // slab test, returns whether the ray enters the box before `max_t`
+    fn hit_by(&self, origin: Vec3, inv_direction: Vec3, max_t: f32) -> bool {
+        let t1 = (self.min - origin) * inv_direction;
+        let t2 = (self.max - origin) * inv_direction;
+        let t_min = t1.min(t2).max_element();
+        let t_max = t1.max(t2).min_element();
+        t_max >= t_min.max(0.0) && t_min <= max_t
+    }
+}
+
+// interior nodes store their children at `first` and `first + 1`, leaves
+// store `count` triangles starting at `first`
+struct Node {
+    bounds: Aabb,
+    first: u32,
+    count: u32,
+}
+
+const LEAF_SIZE: usize = 4;
+const STACK_SIZE: usize = 64;
+
+// a bounding volume hierarchy over the scene's triangles for collision
+// queries. queries use a fixed-size stack so they can run every frame
+// without allocating
+pub struct Bvh {
+    triangles: Vec<Triangle>,
+    nodes: Vec<Node>,
+}
+
+impl Bvh {
+    pub fn new(mut triangles: Vec<Triangle>) -> Self {
+        let mut nodes = Vec::with_capacity(2 * triangles.len() / LEAF_SIZE + 1);
+        nodes.push(Node {
+            bounds: Aabb::empty(),
+            first: 0,
+            count: triangles.len() as u32,
+        });
+        if !triangles.is_empty() {
+            subdivide(&mut nodes, &mut triangles, 0);
+        }
+        Self { triangles, nodes }
+    }
+
+    // rebuilds the hierarchy with more triangles added
+    pub fn extend<I: IntoIterator<Item = Triangle>>(&mut self, triangles: I) {
+        let mut all = std::mem::take(&mut self.triangles);
+        all.extend(triangles);
+        *self = Self::new(all);
+    }
+
+    // rebuilds the hierarchy without `triangles`, e.g. the ones of models
+    // taken out of the scene. each is matched exactly, once
+    pub fn remove(&mut self, triangles: &[Triangle]) {
+        let key = |triangle: &Triangle| {
+            let (a, b, c) = (triangle.a, triangle.b, triangle.c);
+            [a.x.to_bits(), a.y.to_bits(), a.z.to_bits(), b.x.to_bits(), b.y.to_bits(), b.z.to_bits(),
+             c.x.to_bits(), c.y.to_bits(), c.z.to_bits()]
+        };
+        let mut removed: HashMap<[u32; 9], usize> = HashMap::new();
+        for triangle in triangles {
+            *removed.entry(key(triangle)).or_insert(0) += 1;
+        }
+        let mut all = std::mem::take(&mut self.triangles);
+        all.retain(|triangle| match removed.get_mut(&key(triangle)) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        });
+        *self = Self::new(all);
+    }
+
+    pub fn into_triangles(self) -> Vec<Triangle> {
+        self.triangles
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        if self.triangles.is_empty() {
+            None
+        } else {
+            Some(self.nodes[0].bounds)
+        }
+    }
+
+    // the distance to the closest triangle along the ray, up to `max_t`
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_t: f32) -> Option<f32> {
+        let inv_direction = Vec3::one() / direction;
+        // shared by both closures so nodes behind the closest hit are skipped
+        let closest = Cell::new(max_t);
+        let mut hit = false;
+        self.traverse(
+            |bounds| bounds.hit_by(origin, inv_direction, closest.get()),
+            |triangle| {
+                if let Some(t) = triangle.raycast(origin, direction) {
+                    if t <= closest.get() {
+                        closest.set(t);
+                        hit = true;
+                    }
+                }
+            },
+        );
+        if hit { Some(closest.get()) } else { None }
+    }
+
+    // calls `f` with every triangle whose bounds overlap `bounds`
+    pub fn for_each_overlapping<F: FnMut(&Triangle)>(&self, bounds: &Aabb, f: F) {
+        self.traverse(|node_bounds| node_bounds.overlaps(bounds), f);
+    }
+
+    fn traverse<V, F>(&self, mut visit: V, mut f: F)
+    where
+        V: FnMut(&Aabb) -> bool,
+        F: FnMut(&Triangle),
+    {
+        if self.triangles.is_empty() {
+            return;
+        }
+        let mut stack = [0u32; STACK_SIZE];
+        let mut len = 1;
+        while len > 0 {
+            len -= 1;
+            let node = &self.nodes[stack[len] as usize];
+            if !visit(&node.bounds) {
+                continue;
+            }
+            if node.count > 0 {
+                let first = node.first as usize;
+                for triangle in &self.triangles[first..first + node.count as usize] {
+                    f(triangle);
+                }
+            } else if len + 2 <= STACK_SIZE {
+                stack[len] = node.first;
+                stack[len + 1] = node.first + 1;
+                len += 2;
+            }
+        }
+    }
+}
+
+// splits along the longest axis of the centroids at the median
+fn subdivide(nodes: &mut Vec<Node>, triangles: &mut [Triangle], index: usize) {
+    let first = nodes[index].first as usize;
+    let count = nodes[index].count as usize;
+    let slice = &mut triangles[first..first + count];
+    nodes[index].bounds = slice.iter()
+        .fold(Aabb::empty(), |bounds, triangle| bounds.grow(&triangle.bounds()));
+    if count <= LEAF_SIZE {
+        return;
+    }
+
+    let centroids = slice.iter().fold(Aabb::empty(), |bounds, triangle| {
+        let centroid = triangle.centroid();
+        bounds.grow(&Aabb { min: centroid, max: centroid })
+    });
+    let extent = centroids.max - centroids.min;
+    let axis = if extent.x >= extent.y && extent.x >= extent.z {
+        0
+    } else if extent.y >= extent.z {
+        1
+    } else {
+        2
+    };
+    slice.sort_by(|a, b| {
+        a.centroid()[axis].partial_cmp(&b.centroid()[axis]).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let half = count / 2;
+    let left = nodes.len();
+    nodes.push(Node { bounds: Aabb::empty(), first: first as u32, count: half as u32 });
+    nodes.push(Node { bounds: Aabb::empty(), first: (first + half) as u32, count: (count - half) as u32 });
+    nodes[index].first = left as u32;
+    nodes[index].count = 0;
+    subdivide(nodes, triangles, left);
+    subdivide(nodes, triangles, left + 1);
+}
@@ -0,0 +1,966 @@
+use anyhow::{bail, Context, Result};
+use log::warn;
+use serde::Deserialize;
+use crate::import::ImportOptions;
+use crate::lighting::Preset;
+use crate::quality::Quality;
+use std::path::Path;
+
+// startup settings, read from `wr3d.toml`. every field can be overridden
+// with an environment variable holding a toml value, named after its path
+// with the tables separated by `__`, e.g. `WR3D_CAMERA__FOV=0.9` or
+// `WR3D_RENDERER__SHADOWS__ENABLED=false`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub window: WindowConfig,
+    pub renderer: RendererConfig,
+    pub scene: SceneConfig,
+    pub camera: CameraConfig,
+    pub orbit: OrbitConfig,
+    pub fly: FlyConfig,
+    pub walk: WalkConfig,
+    pub remote: RemoteConfig,
+    pub hot_reload: HotReloadConfig,
+    pub determinism: DeterminismConfig,
+    pub stats: StatsConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    // lets the desktop show through wherever nothing is drawn, see the
+    // readme for where this works
+    pub transparent: bool,
+    // the cube in the corner showing the camera's orientation, see
+    // `NavCube`
+    pub nav_cube: bool,
+    // a top-down view of the scene in the bottom left corner, see `Minimap`
+    pub minimap: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: "wr3d".to_string(),
+            width: 1024,
+            height: 768,
+            transparent: false,
+            nav_cube: true,
+            minimap: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RendererConfig {
+    pub backend: Backend,
+    pub vsync: bool,
+    // samples per pixel, 1 (off), 2, 4 or 8. ignored while upscaling
+    pub msaa: u32,
+    // lights the meshes once per pixel from a g-buffer instead of per
+    // fragment, see `Renderer::set_deferred`
+    pub deferred: bool,
+    // shades with only the lights reaching a fragment's cluster, see
+    // `Renderer::set_clustered`
+    pub clustered: bool,
+    // antialiases the finished image, see `Renderer::set_fxaa`
+    pub fxaa: bool,
+    pub hdr: HdrConfig,
+    pub bloom: BloomConfig,
+    pub motion_blur: MotionBlurConfig,
+    pub ssao: SsaoConfig,
+    pub imposters: ImposterConfig,
+    pub upscale: UpscaleConfig,
+    pub ground: GroundConfig,
+    pub shadows: ShadowConfig,
+    pub environment: EnvironmentConfig,
+    pub debug: DebugConfig,
+    pub background: BackgroundConfig,
+    // the lighting preset to start with
+    pub lighting: Preset,
+    // a graphics preset overriding the shadow resolution, msaa and render
+    // scale above
+    pub quality: Option<Quality>,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Primary,
+            vsync: true,
+            msaa: 1,
+            deferred: false,
+            clustered: true,
+            fxaa: false,
+            hdr: HdrConfig::default(),
+            bloom: BloomConfig::default(),
+            motion_blur: MotionBlurConfig::default(),
+            ssao: SsaoConfig::default(),
+            imposters: ImposterConfig::default(),
+            upscale: UpscaleConfig::default(),
+            ground: GroundConfig::default(),
+            shadows: ShadowConfig::default(),
+            environment: EnvironmentConfig::default(),
+            debug: DebugConfig::default(),
+            background: BackgroundConfig::default(),
+            lighting: Preset::default(),
+            quality: None,
+        }
+    }
+}
+
+// draws the scene into a floating point target and maps it onto the
+// window's range with the `tonemap` curve afterwards, so bright lights keep
+// their hue instead of clipping to white. chosen at startup
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HdrConfig {
+    pub enabled: bool,
+    pub tonemap: Tonemap,
+    // scales the scene before the curve, on top of the lighting's exposure
+    pub exposure: f32,
+}
+
+impl Default for HdrConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tonemap: Tonemap::Aces,
+            exposure: 1.0,
+        }
+    }
+}
+
+// must match the TONEMAP_ constants in tonemap.frag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tonemap {
+    // c / (1 + c), gentle but washes out highlights
+    Reinhard,
+    // the filmic ACES fit, more contrast and saturation
+    Aces,
+}
+
+impl Tonemap {
+    pub const ALL: [Tonemap; 2] = [Tonemap::Reinhard, Tonemap::Aces];
+}
+
+// a glow around what's brighter than `threshold` in the HDR image, blurred
+// through `levels` halvings and added back scaled by `intensity`. only
+// drawn with HDR on, see `Bloom`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BloomConfig {
+    pub enabled: bool,
+    pub threshold: f32,
+    pub intensity: f32,
+    // how far the glow spreads, 1 to 8
+    pub levels: u32,
+}
+
+impl Default for BloomConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 1.0,
+            intensity: 0.1,
+            levels: 5,
+        }
+    }
+}
+
+// blurs the image along the camera's motion since the last frame, averaging
+// `samples` points over `shutter` times the distance each pixel moved, so
+// 0.5 is a film camera's half frame shutter. see `MotionBlur`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MotionBlurConfig {
+    pub enabled: bool,
+    // up to 32
+    pub samples: u32,
+    pub shutter: f32,
+}
+
+impl Default for MotionBlurConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            samples: 8,
+            shutter: 0.5,
+        }
+    }
+}
+
+// darkens the ambient light in creases and corners, from the g-buffer's
+// depth and normals so only with deferred shading. see `Ssao`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SsaoConfig {
+    pub enabled: bool,
+    // how far around a point occluders are looked for, in world units
+    pub radius: f32,
+    // how much fully occluded points are darkened, 1 takes all their
+    // ambient light
+    pub intensity: f32,
+    // depth lookups per pixel, up to 64
+    pub samples: u32,
+}
+
+impl Default for SsaoConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            radius: 0.5,
+            intensity: 1.0,
+            samples: 16,
+        }
+    }
+}
+
+// renders the scene at `scale` times the window's resolution and scales
+// it up to fit, trading sharpness for speed in heavy scenes
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UpscaleConfig {
+    pub scale: f32,
+    pub mode: UpscaleMode,
+    // strength of the sharpening after a bilinear upscale
+    pub sharpness: f32,
+}
+
+impl Default for UpscaleConfig {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            mode: UpscaleMode::Bilinear,
+            sharpness: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpscaleMode {
+    Bilinear,
+    // accumulates jittered frames over time, sharper on still images but
+    // can smear fast motion
+    Temporal,
+}
+
+// models further than `distance` from the camera are drawn as sprites
+// baked from the current view, rebaked once the view direction has turned
+// more than `angle` radians away from the one they were baked from
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ImposterConfig {
+    pub enabled: bool,
+    pub distance: f32,
+    pub angle: f32,
+    // width and height of each model's sprite in pixels
+    pub resolution: u32,
+}
+
+impl Default for ImposterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            distance: 50.0,
+            angle: 0.1,
+            resolution: 256,
+        }
+    }
+}
+
+// a floor under the scene's lowest point with soft contact shadows below
+// each model, so a single model doesn't look like it's floating
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GroundConfig {
+    pub enabled: bool,
+    pub color: [f32; 3],
+    // how dark the shadows are, 0 to 1
+    pub shadow: f32,
+}
+
+impl Default for GroundConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            color: [0.5, 0.5, 0.5],
+            shadow: 0.7,
+        }
+    }
+}
+
+// shadows cast by the sun, from cascaded shadow maps covering the view up
+// to `distance`. each cascade covers a slice of the view further out than
+// the last with the same `resolution`, so detail is spent close to the
+// camera. point and spot lights that ask for shadows get a cube of six
+// maps in a shared atlas, each up to `point_resolution` wide and smaller
+// the less of the view the light covers
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ShadowConfig {
+    pub enabled: bool,
+    // 1 to 4
+    pub cascades: u32,
+    // width and height of each cascade's map in texels
+    pub resolution: u32,
+    pub distance: f32,
+    // how the slices are split, 0 evenly and 1 logarithmically
+    pub split: f32,
+    // the fraction of each cascade's edge over which it fades into the next
+    pub blend: f32,
+    // width and height of each face of a point light's cube, at most
+    pub point_resolution: u32,
+    // width and height of the atlas all the cubes share, which bounds
+    // their memory. lights that don't fit get smaller faces
+    pub point_atlas: u32,
+}
+
+impl Default for ShadowConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cascades: 4,
+            resolution: 2048,
+            distance: 50.0,
+            split: 0.75,
+            blend: 0.1,
+            point_resolution: 512,
+            point_atlas: 4096,
+        }
+    }
+}
+
+// image based lighting: the ambient light comes from a panorama instead of
+// the lighting preset's constant color. the panorama is blurred for diffuse
+// and for each roughness once at startup
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EnvironmentConfig {
+    pub enabled: bool,
+    // an equirectangular panorama, the background's skybox without one
+    pub panorama: Option<String>,
+    // scales the light from the panorama, and the skybox along with it
+    pub intensity: f32,
+    // degrees the panorama and the skybox are turned around the up axis,
+    // counterclockwise seen from above
+    pub rotation: f32,
+    // width of the sharpest specular map, half of it tall
+    pub resolution: u32,
+    // width of each cube face drawn when capturing the environment in the
+    // scene, and how far from the capture surfaces reflect it
+    pub probe_resolution: u32,
+    pub probe_radius: f32,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            panorama: None,
+            intensity: 1.0,
+            rotation: 0.0,
+            resolution: 256,
+            probe_resolution: 128,
+            probe_radius: 5.0,
+        }
+    }
+}
+
+// what's drawn behind the scene
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BackgroundConfig {
+    pub mode: BackgroundMode,
+    pub color: [f32; 3],
+    // the gradient's colors at the top and bottom of the view
+    pub top: [f32; 3],
+    pub bottom: [f32; 3],
+    // an equirectangular panorama, needed for the skybox
+    pub skybox: Option<String>,
+}
+
+impl Default for BackgroundConfig {
+    fn default() -> Self {
+        Self {
+            mode: BackgroundMode::Sky,
+            color: [0.1, 0.2, 0.3],
+            top: [0.35, 0.45, 0.6],
+            bottom: [0.08, 0.08, 0.1],
+            skybox: None,
+        }
+    }
+}
+
+// must match the BACKGROUND_ constants in background.frag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackgroundMode {
+    // the lighting preset's sky color
+    #[default]
+    Sky,
+    // `color`
+    Solid,
+    // from `top` to `bottom`
+    Gradient,
+    // the `skybox` panorama around the camera, the sky color without one
+    Skybox,
+    // cleared to zero alpha, for compositing the image over something else
+    Transparent,
+}
+
+impl BackgroundMode {
+    pub const ALL: [BackgroundMode; 5] = [
+        BackgroundMode::Sky, BackgroundMode::Solid, BackgroundMode::Gradient,
+        BackgroundMode::Skybox, BackgroundMode::Transparent,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|mode| *mode == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+// diagnostic views replacing the shading of every mesh
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DebugConfig {
+    pub view: DebugView,
+    // checker cells per unit of uv space
+    pub checker_density: f32,
+    // the texels per world unit shown as green in the texel density view
+    pub texel_density: f32,
+    // where frame dumps go, each into a folder of its own
+    pub dump_dir: String,
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            view: DebugView::Shaded,
+            checker_density: 8.0,
+            texel_density: 512.0,
+            dump_dir: "dumps".to_string(),
+        }
+    }
+}
+
+// the first three must match the DEBUG_ constants in shader.frag, the
+// heatmaps replace the main pass altogether
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DebugView {
+    #[default]
+    Shaded,
+    // a checker over uv space with every cell numbered, to spot stretching,
+    // seams and flipped islands
+    UvChecker,
+    // texels of the diffuse map per world unit, blue below
+    // `texel_density`, green around it and red above
+    TexelDensity,
+    // how many fragments were rasterized at each pixel, hidden or not
+    Overdraw,
+    // visible triangles colored by their area on screen, red for the
+    // smallest
+    TriangleDensity,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 5] = [
+        DebugView::Shaded, DebugView::UvChecker, DebugView::TexelDensity,
+        DebugView::Overdraw, DebugView::TriangleDensity,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|view| *view == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // whether the view is drawn by `heatmap::Heatmaps` instead of the main pass
+    pub fn is_heatmap(self) -> bool {
+        matches!(self, DebugView::Overdraw | DebugView::TriangleDensity)
+    }
+}
+
+impl RendererConfig {
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        if self.vsync {
+            wgpu::PresentMode::Fifo
+        } else {
+            wgpu::PresentMode::Immediate
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    // vulkan, metal, dx12 or webgpu, whichever the platform has
+    Primary,
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl,
+}
+
+impl Backend {
+    pub fn to_wgpu(self) -> wgpu::BackendBit {
+        match self {
+            Backend::Primary => wgpu::BackendBit::PRIMARY,
+            Backend::Vulkan => wgpu::BackendBit::VULKAN,
+            Backend::Metal => wgpu::BackendBit::METAL,
+            Backend::Dx12 => wgpu::BackendBit::DX12,
+            Backend::Dx11 => wgpu::BackendBit::DX11,
+            Backend::Gl => wgpu::BackendBit::GL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SceneConfig {
+    pub model: String,
+    // more model files drawn along with `model`, with their own materials
+    pub extra_models: Vec<String>,
+    // a toml file of raymarched primitives, see `sdf::Primitive`
+    pub sdf: Option<String>,
+    // a toml file of curves, see `curve::Curve`
+    pub curves: Option<String>,
+    // a MagicaVoxel file drawn alongside the model
+    pub voxels: Option<String>,
+    // the width of one voxel in world units
+    pub voxel_size: f32,
+    // a toml file listing model files paged in around the camera, see
+    // `tiles::TilePager`
+    pub tiles: Option<String>,
+    // fixes applied to the model's vertices as it's loaded
+    pub import: ImportOptions,
+    // merges the models flagged static once they're loaded, see
+    // `Scene::batch_static`
+    pub static_batching: bool,
+    pub streaming: StreamingConfig,
+    pub paging: PagingConfig,
+}
+
+impl Default for SceneConfig {
+    fn default() -> Self {
+        Self {
+            model: "data/cube.obj".to_string(),
+            extra_models: vec![],
+            sdf: None,
+            curves: None,
+            voxels: None,
+            voxel_size: 0.1,
+            tiles: None,
+            import: ImportOptions::default(),
+            static_batching: false,
+            streaming: StreamingConfig::default(),
+            paging: PagingConfig::default(),
+        }
+    }
+}
+
+// uploads textures at `initial_size` texels across at most, then streams
+// in the finer mips their models need up close, and drops them again when
+// they're far away, keeping what's on the GPU under `budget` megabytes.
+// see `Scene::stream_textures`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StreamingConfig {
+    pub enabled: bool,
+    pub initial_size: u32,
+    pub budget: u32,
+    // textures given finer mips per frame, so a turn of the camera doesn't
+    // stall on uploads
+    pub uploads_per_frame: usize,
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            initial_size: 64,
+            budget: 256,
+            uploads_per_frame: 4,
+        }
+    }
+}
+
+// loads the tiles with bounds within `load_distance` of the camera,
+// nearest first, and drops them past `unload_distance`, so tiles on the
+// edge don't load and unload with every step. in world units
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PagingConfig {
+    pub load_distance: f32,
+    pub unload_distance: f32,
+    // the most tiles kept at once, the farthest go first
+    pub max_loaded: usize,
+    // tiles loading in the background at the same time
+    pub loads_at_once: usize,
+}
+
+impl Default for PagingConfig {
+    fn default() -> Self {
+        Self {
+            load_distance: 200.0,
+            unload_distance: 300.0,
+            max_loaded: 64,
+            loads_at_once: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    pub eye: [f32; 3],
+    pub center: [f32; 3],
+    pub fov: f32,
+    pub z_near: f32,
+    pub z_far: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            eye: [0.0, 1.0, 2.0],
+            center: [0.0, 0.0, 0.0],
+            fov: 0.7,
+            z_near: 0.1,
+            z_far: 100.0,
+        }
+    }
+}
+
+// orbiting the camera's center by dragging with the left mouse button and
+// zooming with the scroll wheel
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct OrbitConfig {
+    // radians per pixel of dragging
+    pub sensitivity: f32,
+    // the fraction of the distance to the center one scroll step zooms by
+    pub zoom_speed: f32,
+    // seconds a click on the navigation cube takes to turn the camera
+    pub snap_time: f32,
+}
+
+impl Default for OrbitConfig {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.005,
+            zoom_speed: 0.1,
+            snap_time: 0.3,
+        }
+    }
+}
+
+// free first-person flight without collision, in world units and seconds
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FlyConfig {
+    pub speed: f32,
+    // how much faster the camera moves while shift is held
+    pub boost: f32,
+    // radians per pixel of mouse movement
+    pub sensitivity: f32,
+}
+
+impl Default for FlyConfig {
+    fn default() -> Self {
+        Self {
+            speed: 3.0,
+            boost: 4.0,
+            sensitivity: 0.003,
+        }
+    }
+}
+
+// first-person walk mode, in meters and seconds
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WalkConfig {
+    pub speed: f32,
+    pub eye_height: f32,
+    pub radius: f32,
+    pub step_height: f32,
+    pub gravity: f32,
+    pub jump_speed: f32,
+    // radians per pixel of mouse movement
+    pub sensitivity: f32,
+}
+
+impl Default for WalkConfig {
+    fn default() -> Self {
+        Self {
+            speed: 1.5,
+            eye_height: 1.7,
+            radius: 0.3,
+            step_height: 0.35,
+            gravity: 9.81,
+            jump_speed: 4.0,
+            sensitivity: 0.003,
+        }
+    }
+}
+
+// the websocket remote control, only available with the `remote` feature
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    pub enabled: bool,
+    pub address: String,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:9002".to_string(),
+        }
+    }
+}
+
+// reproducible frames for headless captures and golden image tests: the
+// state steps by `timestep` seconds per update instead of following the
+// clock, and its animation time starts at `time`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DeterminismConfig {
+    pub enabled: bool,
+    pub timestep: f32,
+    pub time: f32,
+}
+
+impl Default for DeterminismConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timestep: 1.0 / 60.0,
+            time: 0.0,
+        }
+    }
+}
+
+// the stats HUD's frame budget, in milliseconds. the time a frame keeps the
+// CPU busy, and each part of it, is shown in yellow past 80% of it and in
+// red beyond
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StatsConfig {
+    pub budget: f32,
+    // how many times a warmed up frame may allocate in debug builds, see
+    // `alloc::FrameAudit`
+    pub allocation_budget: usize,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            budget: 1000.0 / 60.0,
+            allocation_budget: 4096,
+        }
+    }
+}
+
+// recompiling shaders edited in `dir` and reloading the scene's models and
+// textures edited in `assets`, only available with the `hot-reload` feature
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HotReloadConfig {
+    pub enabled: bool,
+    pub dir: String,
+    pub assets: String,
+    // where compiled shaders are kept between runs, see `ShaderCache`
+    pub cache: Option<String>,
+}
+
+impl Default for HotReloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: "src".to_string(),
+            assets: "data".to_string(),
+            cache: None,
+        }
+    }
+}
+
+const ENV_PREFIX: &str = "WR3D_";
+
+// where the viewer reads its configuration from, in the working directory
+pub const PATH: &str = "wr3d.toml";
+
+impl Config {
+    // a missing file isn't an error, everything falls back to the defaults.
+    // keys and overrides that don't match a setting are warned about
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let value = if path.exists() {
+            let text = std::fs::read_to_string(path)?;
+            text.parse::<toml::Value>()
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        } else {
+            toml::Value::Table(toml::value::Table::new())
+        };
+        let (config, unknown) = parse(value, std::env::vars())
+            .with_context(|| format!("Invalid configuration in {}", path.display()))?;
+        for unknown in unknown {
+            match unknown {
+                Unknown::Key(key) => warn!("Ignoring {} in {}, which isn't a setting", key, path.display()),
+                Unknown::Variable(name) => warn!("Ignoring {}, which doesn't name a setting", name),
+            }
+        }
+        Ok(config)
+    }
+}
+
+// what `parse` ignored
+#[derive(Debug, PartialEq)]
+enum Unknown {
+    // a dotted path in the file
+    Key(String),
+    // an environment variable
+    Variable(String),
+}
+
+// the configuration in `value` with `vars` applied, see
+// `apply_env_overrides`, and everything in them that isn't a setting
+fn parse<I>(mut value: toml::Value, vars: I) -> Result<(Config, Vec<Unknown>)>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let overrides = apply_env_overrides(&mut value, vars)?;
+    let mut ignored = vec![];
+    let config = serde_ignored::deserialize(value, |path| ignored.push(path.to_string()))?;
+    let unknown = ignored.into_iter()
+        .map(|key| {
+            // an unknown table holds the whole override
+            let variable = overrides.iter().find(|(_, path)| {
+                *path == key || (path.starts_with(&key) && path[key.len()..].starts_with('.'))
+            });
+            match variable {
+                Some((name, _)) => Unknown::Variable(name.clone()),
+                None => Unknown::Key(key),
+            }
+        })
+        .collect();
+    Ok((config, unknown))
+}
+
+// `WR3D_CAMERA__Z_NEAR=0.01` sets `z_near` in the `[camera]` table, and
+// `WR3D_RENDERER__SHADOWS__ENABLED=false` `enabled` in
+// `[renderer.shadows]`. tables are separated by `__` since keys have
+// single underscores of their own. values are parsed as toml, falling back
+// to a plain string. returns each variable applied with its dotted path
+fn apply_env_overrides<I>(value: &mut toml::Value, vars: I) -> Result<Vec<(String, String)>>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut applied = vec![];
+    for (name, raw) in vars {
+        let path = match name.strip_prefix(ENV_PREFIX) {
+            Some(path) => path.to_lowercase(),
+            None => continue,
+        };
+        let keys: Vec<&str> = path.split("__").collect();
+        if keys.iter().any(|key| key.is_empty()) {
+            bail!("{} isn't a setting's path, tables are separated by __", name);
+        }
+        let override_value = format!("value = {}", raw)
+            .parse::<toml::Value>()
+            .ok()
+            .and_then(|parsed| parsed.get("value").cloned())
+            .unwrap_or(toml::Value::String(raw));
+        let (key, tables) = keys.split_last().unwrap();
+        let mut table = value.as_table_mut().context("Configuration must be a table")?;
+        for (depth, section) in tables.iter().enumerate() {
+            table = table
+                .entry(section.to_string())
+                .or_insert_with(|| toml::Value::Table(toml::value::Table::new()))
+                .as_table_mut()
+                .with_context(|| format!("[{}] must be a table for {}", tables[..=depth].join("."), name))?;
+        }
+        table.insert(key.to_string(), override_value);
+        applied.push((name, keys.join(".")));
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn table(text: &str) -> toml::Value {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn overrides_nested_tables() {
+        let mut value = table("[renderer]\nvsync = true\n");
+        let applied = apply_env_overrides(&mut value, vars(&[
+            ("WR3D_RENDERER__VSYNC", "false"),
+            ("WR3D_RENDERER__SHADOWS__ENABLED", "false"),
+            ("WR3D_HOT_RELOAD__ENABLED", "true"),
+            ("WR3D_SCENE__MODEL", "data/other.obj"),
+            ("PATH", "/usr/bin"),
+        ])).unwrap();
+        assert_eq!(value["renderer"]["vsync"], toml::Value::Boolean(false));
+        assert_eq!(value["renderer"]["shadows"]["enabled"], toml::Value::Boolean(false));
+        assert_eq!(value["hot_reload"]["enabled"], toml::Value::Boolean(true));
+        assert_eq!(value["scene"]["model"], toml::Value::String("data/other.obj".to_string()));
+        assert_eq!(applied.len(), 4);
+        assert_eq!(applied[1], ("WR3D_RENDERER__SHADOWS__ENABLED".to_string(), "renderer.shadows.enabled".to_string()));
+    }
+
+    #[test]
+    fn parses_values_as_toml() {
+        let mut value = table("");
+        apply_env_overrides(&mut value, vars(&[
+            ("WR3D_CAMERA__FOV", "0.9"),
+            ("WR3D_WINDOW__WIDTH", "640"),
+            ("WR3D_RENDERER__BACKGROUND__COLOR", "[0.1, 0.2, 0.3]"),
+            ("WR3D_SCENE__MODEL", "not toml, so a string"),
+        ])).unwrap();
+        assert_eq!(value["camera"]["fov"], toml::Value::Float(0.9));
+        assert_eq!(value["window"]["width"], toml::Value::Integer(640));
+        assert!(value["renderer"]["background"]["color"].is_array());
+        assert_eq!(value["scene"]["model"], toml::Value::String("not toml, so a string".to_string()));
+    }
+
+    #[test]
+    fn rejects_empty_keys_and_values_in_the_way() {
+        assert!(apply_env_overrides(&mut table(""), vars(&[("WR3D_RENDERER____VSYNC", "true")])).is_err());
+        assert!(apply_env_overrides(&mut table(""), vars(&[("WR3D_", "true")])).is_err());
+        let mut value = table("[scene]\nmodel = \"data/cube.obj\"\n");
+        assert!(apply_env_overrides(&mut value, vars(&[("WR3D_SCENE__MODEL__PATH", "x")])).is_err());
+    }
+
+    #[test]
+    fn reports_what_isnt_a_setting() {
+        let (config, unknown) = parse(table("[window]\nwidht = 640\n"), vars(&[
+            ("WR3D_RENDERER__SHADOWS__ENABLED", "false"),
+            ("WR3D_RENDERER_VSYNC", "false"),
+            ("WR3D_HOT__RELOAD", "true"),
+        ])).unwrap();
+        assert!(!config.renderer.shadows.enabled);
+        assert_eq!(unknown, [
+            Unknown::Variable("WR3D_HOT__RELOAD".to_string()),
+            Unknown::Variable("WR3D_RENDERER_VSYNC".to_string()),
+            Unknown::Key("window.widht".to_string()),
+        ]);
+    }
+}
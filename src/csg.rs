@@ -0,0 +1,244 @@
+use anyhow::{bail, Result};
+use glam::Vec3;
+use wgpu;
+use crate::assets::Handle;
+use crate::collision::{Aabb, Triangle};
+use crate::material::Material;
+use crate::mesh::{Mesh, Model, SubMesh};
+use crate::sdf::{Operation, Primitive, Shape, MAX_PRIMITIVES};
+use crate::texture::Texture;
+use crate::vertex::Vertex;
+
+// what sdf.frag starts folding from
+const MAX_DISTANCE: f32 = 100.0;
+
+// the finest grid `mesh` samples, along the primitives' longest side
+pub const MAX_RESOLUTION: u32 = 256;
+
+// the primitives combined into one triangle mesh, with the material and
+// collision triangles to add it to a scene with
+pub struct CsgMesh {
+    pub model: Model,
+    pub material: Material,
+    pub triangles: Vec<Triangle>,
+}
+
+// the combined shapes' signed distance at `p` and the primitive whose color
+// shows there, folded in order as `scene` in sdf.frag does
+pub fn evaluate(primitives: &[Primitive], p: Vec3) -> (f32, usize) {
+    let mut distance = MAX_DISTANCE;
+    let mut color = 0;
+    for (i, primitive) in primitives.iter().enumerate() {
+        let d = shape_distance(primitive, p);
+        let k = primitive.smoothness.max(1e-4);
+        if i == 0 || primitive.operation == Operation::Union {
+            let h = (0.5 + 0.5 * (d - distance) / k).clamp(0.0, 1.0);
+            if i == 0 || h < 0.5 {
+                color = i;
+            }
+            distance = d + (distance - d) * h - k * h * (1.0 - h);
+        } else if primitive.operation == Operation::Subtract {
+            let h = (0.5 - 0.5 * (distance + d) / k).clamp(0.0, 1.0);
+            distance = distance + (-d - distance) * h + k * h * (1.0 - h);
+        } else {
+            let h = (0.5 - 0.5 * (d - distance) / k).clamp(0.0, 1.0);
+            distance = d + (distance - d) * h + k * h * (1.0 - h);
+        }
+    }
+    (distance, color)
+}
+
+fn shape_distance(primitive: &Primitive, p: Vec3) -> f32 {
+    let q = p - Vec3::from(primitive.position);
+    let length2 = |x: f32, y: f32| (x * x + y * y).sqrt();
+    match primitive.shape {
+        Shape::Sphere { radius } => q.length() - radius,
+        Shape::Box { size } => {
+            let d = q.abs() - Vec3::from(size);
+            d.max(Vec3::zero()).length() + d.max_element().min(0.0)
+        }
+        Shape::Torus { radius, thickness } => length2(length2(q.x, q.z) - radius, q.y) - thickness,
+        Shape::Cylinder { radius, height } => {
+            let (dx, dy) = (length2(q.x, q.z).abs() - radius, q.y.abs() - height);
+            dx.max(dy).min(0.0) + length2(dx.max(0.0), dy.max(0.0))
+        }
+    }
+}
+
+// what the shapes added by union can cover, grown by their blending.
+// subtracted and intersected ones only take away
+pub fn bounds(primitives: &[Primitive]) -> Aabb {
+    let corners = primitives.iter().enumerate().filter(|(i, primitive)| {
+        *i == 0 || primitive.operation == Operation::Union
+    }).flat_map(|(_, primitive)| {
+        let extent = match primitive.shape {
+            Shape::Sphere { radius } => Vec3::splat(radius),
+            Shape::Box { size } => Vec3::from(size),
+            Shape::Torus { radius, thickness } => Vec3::new(radius + thickness, thickness, radius + thickness),
+            Shape::Cylinder { radius, height } => Vec3::new(radius, height, radius),
+        } + Vec3::splat(primitive.smoothness.max(0.0));
+        let center = Vec3::from(primitive.position);
+        vec![center - extent, center + extent]
+    });
+    Aabb::from_points(corners)
+}
+
+// meshes the combined primitives with surface nets (Gibson, "Constrained
+// Elastic Surface Nets"), sampling their distance on a grid `resolution`
+// cells across their longest side. the primitives' colors end up in a
+// palette texture, each vertex taking the color of the shape on top there
+pub fn mesh(device: &wgpu::Device, queue: &wgpu::Queue, primitives: &[Primitive], resolution: u32,
+            name: &str) -> Result<CsgMesh> {
+    if primitives.is_empty() {
+        bail!("There are no primitives to mesh");
+    }
+    if primitives.len() > MAX_PRIMITIVES {
+        bail!("{} primitives, at most {} are supported", primitives.len(), MAX_PRIMITIVES);
+    }
+    if !(2..=MAX_RESOLUTION).contains(&resolution) {
+        bail!("The resolution has to be between 2 and {}, not {}", MAX_RESOLUTION, resolution);
+    }
+    let (vertices, indices) = surface_net(primitives, resolution);
+    if indices.is_empty() {
+        bail!("The primitives leave nothing solid to mesh");
+    }
+
+    let mut image = image::RgbaImage::new(primitives.len() as u32, 1);
+    for (i, primitive) in primitives.iter().enumerate() {
+        let [r, g, b] = primitive.color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        image.put_pixel(i as u32, 0, image::Rgba([r, g, b, 255]));
+    }
+    // linear, like the colors the raymarched shapes are shaded with
+    let texture = Texture::from_image(device, queue, &image, wgpu::TextureFormat::Rgba8Unorm, Some("csg_palette"));
+    let material = Material::from_diffuse_texture(device, queue, name, Handle::new(texture));
+
+    let triangles = indices.chunks_exact(3).map(|face| Triangle {
+        a: Vec3::from(vertices[face[0] as usize].position),
+        b: Vec3::from(vertices[face[1] as usize].position),
+        c: Vec3::from(vertices[face[2] as usize].position),
+    }).collect();
+    let bounds = Aabb::from_points(vertices.iter().map(|vertex| Vec3::from(vertex.position)));
+    let submeshes = vec![SubMesh { material: 0, indices: 0..indices.len() as u32 }];
+    let mesh = Mesh::from_vertices(device, &vertices, &indices, submeshes, name);
+    Ok(CsgMesh { model: Model::from_mesh(mesh, bounds, name), material, triangles })
+}
+
+// one vertex in every grid cell the surface passes through, at the
+// average of where it crosses the cell's edges, and a quad around every
+// grid edge it crosses joining the four cells sharing that edge
+fn surface_net(primitives: &[Primitive], resolution: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let bounds = bounds(primitives);
+    let cell = (bounds.max - bounds.min).max_element() / resolution as f32;
+    // a cell of room on every side, so the surface is closed
+    let origin = bounds.min - Vec3::splat(cell);
+    let size = ((bounds.max - bounds.min) / cell).ceil();
+    let dims = [size.x as usize + 3, size.y as usize + 3, size.z as usize + 3];
+    let index = |x: usize, y: usize, z: usize| x + dims[0] * (y + dims[1] * z);
+    let point = |x: usize, y: usize, z: usize| origin + Vec3::new(x as f32, y as f32, z as f32) * cell;
+
+    let mut samples = vec![0.0; dims[0] * dims[1] * dims[2]];
+    for z in 0..dims[2] {
+        for y in 0..dims[1] {
+            for x in 0..dims[0] {
+                samples[index(x, y, z)] = evaluate(primitives, point(x, y, z)).0;
+            }
+        }
+    }
+
+    // cells are indexed by their lowest corner
+    let mut cell_vertices = vec![u32::MAX; samples.len()];
+    let mut vertices = vec![];
+    let texel = 1.0 / primitives.len() as f32;
+    for z in 0..dims[2] - 1 {
+        for y in 0..dims[1] - 1 {
+            for x in 0..dims[0] - 1 {
+                let corners: Vec<(Vec3, f32)> = (0..8)
+                    .map(|corner| {
+                        let (cx, cy, cz) = (x + (corner & 1), y + ((corner >> 1) & 1), z + (corner >> 2));
+                        (point(cx, cy, cz), samples[index(cx, cy, cz)])
+                    })
+                    .collect();
+                let inside = corners.iter().filter(|(_, value)| *value < 0.0).count();
+                if inside == 0 || inside == 8 {
+                    continue;
+                }
+                let mut sum = Vec3::zero();
+                let mut crossings = 0.0;
+                for (a, b) in CELL_EDGES.iter() {
+                    let ((pa, va), (pb, vb)) = (corners[*a], corners[*b]);
+                    if (va < 0.0) != (vb < 0.0) {
+                        sum += pa + (pb - pa) * (va / (va - vb));
+                        crossings += 1.0;
+                    }
+                }
+                let position = sum / crossings;
+                let (_, color) = evaluate(primitives, position);
+                cell_vertices[index(x, y, z)] = vertices.len() as u32;
+                vertices.push(surface_vertex(primitives, position, cell * 0.1,
+                                             [(color as f32 + 0.5) * texel, 0.5]));
+            }
+        }
+    }
+
+    let mut indices = vec![];
+    for z in 1..dims[2] - 1 {
+        for y in 1..dims[1] - 1 {
+            for x in 1..dims[0] - 1 {
+                let inside = samples[index(x, y, z)] < 0.0;
+                // the edges leading up from this sample along x, y and z,
+                // each with the cells around it in counterclockwise order
+                // seen from the edge's direction
+                let quads = [
+                    (index(x + 1, y, z), [index(x, y - 1, z - 1), index(x, y, z - 1), index(x, y, z), index(x, y - 1, z)]),
+                    (index(x, y + 1, z), [index(x - 1, y, z - 1), index(x - 1, y, z), index(x, y, z), index(x, y, z - 1)]),
+                    (index(x, y, z + 1), [index(x - 1, y - 1, z), index(x, y - 1, z), index(x, y, z), index(x - 1, y, z)]),
+                ];
+                for (next, cells) in quads.iter() {
+                    if inside == (samples[*next] < 0.0) {
+                        continue;
+                    }
+                    let [a, b, c, d] = cells.map(|cell| cell_vertices[cell]);
+                    if [a, b, c, d].contains(&u32::MAX) {
+                        continue;
+                    }
+                    // facing out of the solid side
+                    if inside {
+                        indices.extend_from_slice(&[a, b, c, a, c, d]);
+                    } else {
+                        indices.extend_from_slice(&[a, c, b, a, d, c]);
+                    }
+                }
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+// the corner pairs of a cell's twelve edges, corners numbered by their x,
+// y and z bits
+const CELL_EDGES: [(usize, usize); 12] = [
+    (0, 1), (2, 3), (4, 5), (6, 7),
+    (0, 2), (1, 3), (4, 6), (5, 7),
+    (0, 4), (1, 5), (2, 6), (3, 7),
+];
+
+// the normal is the distance's gradient, and the palette has no detail to
+// map, so any tangent perpendicular to it will do
+fn surface_vertex(primitives: &[Primitive], position: Vec3, step: f32, tex_coord: [f32; 2]) -> Vertex {
+    let distance = |offset: Vec3| evaluate(primitives, position + offset).0;
+    let (dx, dy, dz) = (Vec3::new(step, 0.0, 0.0), Vec3::new(0.0, step, 0.0), Vec3::new(0.0, 0.0, step));
+    let gradient = Vec3::new(
+        distance(dx) - distance(-dx),
+        distance(dy) - distance(-dy),
+        distance(dz) - distance(-dz),
+    );
+    let normal = if gradient.length_squared() > 0.0 { gradient.normalize() } else { Vec3::unit_y() };
+    let axis = if normal.x.abs() < 0.9 { Vec3::unit_x() } else { Vec3::unit_z() };
+    let tangent = (axis - normal * normal.dot(axis)).normalize();
+    Vertex {
+        position: position.into(),
+        normal: normal.into(),
+        tex_coord,
+        tangent: tangent.extend(1.0).into(),
+    }
+}
@@ -0,0 +1,76 @@
+use glam::{Mat4, Vec3, Vec4};
+use crate::collision::Aabb;
+
+// the six planes bounding what a view-projection matrix sees, for testing
+// models' bounds before drawing them
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    view_proj: Mat4,
+    // normals point inwards, a point p is inside when dot(n, p) + d >= 0
+    planes: [(Vec3, f32); 6],
+}
+
+impl Frustum {
+    // extracts the planes from the matrix's rows (Gribb and Hartmann),
+    // for clip space depth from 0 to 1
+    pub fn from_matrix(view_proj: Mat4) -> Self {
+        let m = view_proj.to_cols_array_2d();
+        let row = |i: usize| Vec4::new(m[0][i], m[1][i], m[2][i], m[3][i]);
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        let plane = |p: Vec4| {
+            let normal = p.truncate();
+            let length = normal.length().max(f32::EPSILON);
+            (normal / length, p.w / length)
+        };
+        Self {
+            view_proj,
+            planes: [
+                plane(r3 + r0),
+                plane(r3 - r0),
+                plane(r3 + r1),
+                plane(r3 - r1),
+                plane(r2),
+                plane(r3 - r2),
+            ],
+        }
+    }
+
+    // the same frustum reaching back indefinitely, for depth clamped passes
+    // that keep what's in front of the near plane
+    pub fn without_near(mut self) -> Self {
+        self.planes[4] = (Vec3::zero(), 0.0);
+        self
+    }
+
+    // conservative: boxes crossing a corner outside the frustum can pass.
+    // empty bounds are always visible, since nothing is known about them
+    pub fn intersects(&self, bounds: &Aabb) -> bool {
+        if bounds.is_empty() {
+            return true;
+        }
+        self.planes.iter().all(|(normal, d)| {
+            // the corner furthest along the normal
+            let corner = Vec3::new(
+                if normal.x >= 0.0 { bounds.max.x } else { bounds.min.x },
+                if normal.y >= 0.0 { bounds.max.y } else { bounds.min.y },
+                if normal.z >= 0.0 { bounds.max.z } else { bounds.min.z },
+            );
+            normal.dot(corner) + d >= 0.0
+        })
+    }
+
+    // the near corners followed by the far ones, each counter-clockwise
+    // from the bottom left
+    pub fn corners(&self) -> [Vec3; 8] {
+        let inverse = self.view_proj.inverse();
+        let mut corners = [Vec3::zero(); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let x = if i & 1 != (i >> 1) & 1 { 1.0 } else { -1.0 };
+            let y = if i & 2 != 0 { 1.0 } else { -1.0 };
+            let z = if i & 4 != 0 { 1.0 } else { 0.0 };
+            let p = inverse * Vec4::new(x, y, z, 1.0);
+            *corner = p.truncate() / p.w;
+        }
+        corners
+    }
+}
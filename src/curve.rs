@@ -0,0 +1,327 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use wgpu;
+use glam::{Vec2, Vec3};
+use crate::camera::Camera;
+use crate::texture::DepthTexture;
+use crate::upscale;
+
+// a line through 3d points drawn a constant number of pixels wide, e.g.
+//
+// [[curve]]
+// kind = "catmull_rom"
+// points = [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [2.0, 0.0, 1.0]]
+// width = 3.0
+// color = [1.0, 0.5, 0.0]
+#[derive(Debug, Clone, Deserialize)]
+pub struct Curve {
+    #[serde(default)]
+    pub kind: CurveKind,
+    pub points: Vec<[f32; 3]>,
+    // in pixels of the window
+    #[serde(default = "default_width")]
+    pub width: f32,
+    #[serde(default = "default_color")]
+    pub color: [f32; 3],
+    // straight pieces each span of a bezier or catmull-rom curve is drawn
+    // with
+    #[serde(default = "default_segments")]
+    pub segments: u32,
+}
+
+fn default_width() -> f32 {
+    2.0
+}
+
+fn default_color() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn default_segments() -> u32 {
+    16
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveKind {
+    // straight between the points
+    #[default]
+    Polyline,
+    // cubic spans, each ending where the next starts: 4, 7, 10... points
+    Bezier,
+    // a smooth curve through every point
+    CatmullRom,
+}
+
+impl Curve {
+    pub fn new(kind: CurveKind, points: Vec<[f32; 3]>) -> Self {
+        Self { kind, points, width: default_width(), color: default_color(), segments: default_segments() }
+    }
+
+    // fewer points than the kind needs, or a bezier curve with some left
+    // over, is an error
+    pub fn validate(&self) -> Result<()> {
+        match self.kind {
+            CurveKind::Polyline | CurveKind::CatmullRom if self.points.len() < 2 =>
+                bail!("A {:?} curve needs at least 2 points, not {}", self.kind, self.points.len()),
+            CurveKind::Bezier if self.points.len() < 4 || !(self.points.len() - 1).is_multiple_of(3) =>
+                bail!("A bezier curve needs 4, 7, 10... points, not {}", self.points.len()),
+            _ => Ok(()),
+        }
+    }
+
+    // calls `f` with the points of the straight pieces the curve is drawn
+    // with, in order
+    pub fn tessellate<F: FnMut(Vec3)>(&self, mut f: F) {
+        if self.validate().is_err() {
+            return;
+        }
+        let points = |i: usize| Vec3::from(self.points[i]);
+        let segments = self.segments.max(1);
+        let steps = |f: &mut F, span: &dyn Fn(f32) -> Vec3| {
+            for step in 1..=segments {
+                f(span(step as f32 / segments as f32));
+            }
+        };
+        f(points(0));
+        match self.kind {
+            CurveKind::Polyline => for i in 1..self.points.len() {
+                f(points(i));
+            },
+            CurveKind::Bezier => for i in (0..self.points.len() - 1).step_by(3) {
+                let [a, b, c, d] = [points(i), points(i + 1), points(i + 2), points(i + 3)];
+                steps(&mut f, &|t| {
+                    let s = 1.0 - t;
+                    a * (s * s * s) + b * (3.0 * s * s * t) + c * (3.0 * s * t * t) + d * (t * t * t)
+                });
+            },
+            CurveKind::CatmullRom => {
+                let last = self.points.len() - 1;
+                for i in 0..last {
+                    // the ends are mirrored so the curve starts and stops
+                    // at them
+                    let b = points(i);
+                    let c = points(i + 1);
+                    let a = if i == 0 { b * 2.0 - c } else { points(i - 1) };
+                    let d = if i + 1 == last { c * 2.0 - b } else { points(i + 2) };
+                    steps(&mut f, &|t| {
+                        let (t2, t3) = (t * t, t * t * t);
+                        (b * 2.0 + (c - a) * t + (a * 2.0 - b * 5.0 + c * 4.0 - d) * t2
+                            + (b * 3.0 - a - c * 3.0 + d) * t3) * 0.5
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Description {
+    #[serde(default)]
+    curve: Vec<Curve>,
+}
+
+// reads a list of `[[curve]]` tables from a toml file
+pub fn load(path: &str) -> Result<Vec<Curve>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path))?;
+    let description: Description = toml::from_str(&text)
+        .with_context(|| format!("Failed to parse {}", path))?;
+    for (i, curve) in description.curve.iter().enumerate() {
+        curve.validate().with_context(|| format!("{}: curve {}", path, i))?;
+    }
+    Ok(description.curve)
+}
+
+// one straight piece, drawn as a quad facing the camera
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Segment {
+    start: [f32; 3],
+    end: [f32; 3],
+    color: [f32; 3],
+    width: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CurveUniforms {
+    view_proj: [[f32; 4]; 4],
+    // xy the window's size in pixels
+    viewport: [f32; 4],
+}
+
+// draws a scene's curves in the main pass, hidden by what's in front of
+// them. the quads are widened in the vertex shader, so they stay as wide
+// at any distance
+pub struct CurvePass {
+    format: wgpu::TextureFormat,
+    layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    segments: Vec<Segment>,
+    buffer: wgpu::Buffer,
+    // segments the buffer has room for
+    capacity: usize,
+    // segments uploaded by the last `upload`
+    count: u32,
+}
+
+fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("curve_segment_buffer"),
+        size: (capacity * std::mem::size_of::<Segment>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+impl CurvePass {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32) -> Self {
+        let uniform_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::VERTEX,
+                        ty: wgpu::BindingType::UniformBuffer {
+                            dynamic: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("curve_bind_group_layout"),
+            }
+        );
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("curve_uniform_buffer"),
+            size: std::mem::size_of::<CurveUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+            }],
+            label: Some("curve_bind_group"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("curve_pipeline_layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &layout, format, sample_count);
+        let capacity = 256;
+        Self {
+            format,
+            layout,
+            pipeline,
+            uniform_buffer,
+            uniform_bind_group,
+            segments: Vec::with_capacity(capacity),
+            buffer: create_buffer(device, capacity),
+            capacity,
+            count: 0,
+        }
+    }
+
+    // rebuilds the pipeline for a main pass with `sample_count` samples
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.pipeline = create_pipeline(device, &self.layout, self.format, sample_count);
+    }
+
+    // tessellates the curves and copies their pieces to the GPU, growing
+    // the buffer if needed
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, curves: &[Curve]) {
+        self.segments.clear();
+        for curve in curves {
+            let mut previous = None;
+            let segments = &mut self.segments;
+            curve.tessellate(|point| {
+                if let Some(start) = previous.replace(point) {
+                    segments.push(Segment {
+                        start: start.into(),
+                        end: point.into(),
+                        color: curve.color,
+                        width: curve.width,
+                    });
+                }
+            });
+        }
+        if self.segments.len() > self.capacity {
+            self.capacity = self.segments.len().next_power_of_two();
+            self.buffer = create_buffer(device, self.capacity);
+        }
+        if !self.segments.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.segments));
+        }
+        self.count = self.segments.len() as u32;
+    }
+
+    // the camera for this frame, `jitter` has to match the projection
+    // offset the meshes are drawn with. `width` and `height` are the
+    // window's, which the curves' widths are measured in
+    pub fn prepare(&self, queue: &wgpu::Queue, camera: &Camera, jitter: Vec2, width: u32, height: u32) {
+        if self.count == 0 {
+            return;
+        }
+        let uniforms = CurveUniforms {
+            view_proj: upscale::jittered(camera.build_view_projection_matrix(), jitter).to_cols_array_2d(),
+            viewport: [width as f32, height as f32, 0.0, 0.0],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        if self.count == 0 {
+            return;
+        }
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        pass.set_vertex_buffer(0, self.buffer.slice(..));
+        pass.draw(0..6, 0..self.count);
+    }
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, format: wgpu::TextureFormat,
+                   sample_count: u32) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("curve.vert.spv"));
+    let fs_module = device.create_shader_module(wgpu::include_spirv!("lines.frag.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("curve_pipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        // the quads face either way depending on the segment's direction
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        color_states: &[format.into()],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+            format: DepthTexture::FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilStateDescriptor::default(),
+        }),
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[wgpu::VertexBufferDescriptor {
+                stride: std::mem::size_of::<Segment>() as wgpu::BufferAddress,
+                step_mode: wgpu::InputStepMode::Instance,
+                attributes: &wgpu::vertex_attr_array![0 => Float3, 1 => Float3, 2 => Float3, 3 => Float],
+            }],
+        },
+        sample_count,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
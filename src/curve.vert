@@ -0,0 +1,53 @@
+#version 450
+
+// one segment per instance, see `Segment` in curve.rs
+layout(location=0) in vec3 a_start;
+layout(location=1) in vec3 a_end;
+layout(location=2) in vec3 a_color;
+layout(location=3) in float a_width;
+
+layout(set=0, binding=0)
+  uniform Curves {
+  mat4 u_view_proj;
+  vec4 u_viewport;
+};
+
+layout(location=0) out vec3 v_color;
+
+// how far in front of the camera a segment is cut off, in clip space w
+const float NEAR = 1e-4;
+
+// x along the segment, y across it
+const vec2 CORNERS[6] = vec2[6](
+  vec2(0.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+  vec2(0.0, -1.0), vec2(1.0, 1.0), vec2(0.0, 1.0)
+);
+
+void main() {
+  vec2 corner = CORNERS[gl_VertexIndex];
+  vec4 start = u_view_proj * vec4(a_start, 1.0);
+  vec4 end = u_view_proj * vec4(a_end, 1.0);
+  // cut what's behind the camera off, dropping segments entirely behind it
+  if (start.w < NEAR && end.w < NEAR) {
+    gl_Position = vec4(0.0, 0.0, 2.0, 1.0);
+    v_color = a_color;
+    return;
+  }
+  if (start.w < NEAR) {
+    start = mix(start, end, (NEAR - start.w) / (end.w - start.w));
+  } else if (end.w < NEAR) {
+    end = mix(end, start, (NEAR - end.w) / (start.w - end.w));
+  }
+
+  // widened in pixels, and lengthened by half the width at both ends so
+  // the pieces of a curve overlap at its bends
+  vec2 half_size = u_viewport.xy * 0.5;
+  vec2 delta = end.xy / end.w * half_size - start.xy / start.w * half_size;
+  vec2 along = length(delta) > 1e-6 ? normalize(delta) : vec2(1.0, 0.0);
+  vec2 across = vec2(-along.y, along.x);
+  vec2 offset = (across * corner.y + along * (corner.x * 2.0 - 1.0)) * a_width * 0.5 / half_size;
+
+  vec4 position = mix(start, end, corner.x);
+  gl_Position = position + vec4(offset * position.w, 0.0, 0.0);
+  v_color = a_color;
+}
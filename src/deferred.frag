@@ -0,0 +1,398 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform Deferred {
+  mat4 d_inv_view_proj;
+  vec4 d_eye;
+};
+// see gbuffer.frag
+layout(set=0, binding=1) uniform texture2D t_albedo;
+layout(set=0, binding=2) uniform texture2D t_normal;
+layout(set=0, binding=3) uniform texture2D t_material;
+layout(set=0, binding=4) uniform texture2D t_emissive;
+layout(set=0, binding=5) uniform texture2D t_depth;
+// see ssao.frag, a single white texel while it's off
+layout(set=0, binding=6) uniform texture2D t_ssao;
+
+layout(set=1, binding=0) uniform Light {
+  vec3 l_position;
+  float l_exposure;
+  vec3 l_color;
+  float l_fog_density;
+  vec3 l_ambient;
+  uint l_light_count;
+  vec3 l_fog_color;
+};
+
+// must match the LIGHT_* constants in lights.rs
+const uint LIGHT_DIRECTIONAL = 0;
+const uint LIGHT_POINT = 1;
+const uint LIGHT_SPOT = 2;
+
+struct LightRaw {
+  vec3 position;
+  float range;
+  vec3 color;
+  uint kind;
+  vec3 direction;
+  float cos_inner;
+  float cos_outer;
+  // the slot in the point shadow maps, negative without one
+  int shadow;
+};
+
+layout(set=1, binding=1) readonly buffer Lights {
+  LightRaw lights[];
+};
+
+// must match cluster.rs
+#define CLUSTERS_X 16
+#define CLUSTERS_Y 9
+#define CLUSTERS_Z 24
+#define CLUSTER_STRIDE 128
+const uint CLUSTER_OVERFLOW = 0xffffffffu;
+
+layout(set=1, binding=12) uniform Clusters {
+  mat4 c_view;
+  mat4 c_inv_proj;
+  float c_near;
+  float c_far;
+  uint c_light_count;
+  uint c_enabled;
+};
+
+// see cluster.comp
+layout(set=1, binding=13) readonly buffer ClusterLights {
+  uint c_lights[];
+};
+
+// must match MAX_CASCADES in shadow.rs
+#define MAX_CASCADES 4
+
+layout(set=1, binding=2) uniform Shadows {
+  mat4 sh_cascades[MAX_CASCADES];
+  vec4 sh_texel_sizes;
+  uint sh_cascade_count;
+  float sh_blend;
+};
+layout(set=1, binding=3) uniform texture2DArray t_shadow;
+layout(set=1, binding=4) uniform samplerShadow s_shadow;
+
+// must match MAX_POINT_SHADOWS in shadow.rs
+#define MAX_POINT_SHADOWS 8
+
+layout(set=1, binding=5) uniform PointShadows {
+  mat4 ps_faces[MAX_POINT_SHADOWS * 6];
+  // each face's corner and width in the atlas, and the width of its texels
+  // one unit from the light. 0 wide when the light didn't fit
+  vec4 ps_tiles[MAX_POINT_SHADOWS * 6];
+};
+// the atlas, a single layer
+layout(set=1, binding=6) uniform texture2DArray t_point_shadow;
+
+// image based ambient light, see ibl.rs. the maps are equirect like the
+// skybox
+layout(set=1, binding=7) uniform Environment {
+  float e_intensity;
+  uint e_enabled;
+  // mip levels of the specular map
+  float e_levels;
+  // added to the maps' u, turning them around the up axis
+  float e_offset;
+};
+layout(set=1, binding=8) uniform texture2D t_irradiance;
+layout(set=1, binding=9) uniform texture2D t_specular;
+layout(set=1, binding=10) uniform texture2D t_brdf_lut;
+layout(set=1, binding=11) uniform sampler s_environment;
+// the environment captured at a point in the scene, which surfaces within
+// its radius take their ambient light and reflections from
+layout(set=1, binding=14) uniform Probe {
+  vec3 p_position;
+  float p_radius;
+  // 0 until something is captured
+  uint p_enabled;
+};
+layout(set=1, binding=15) uniform texture2D t_probe_irradiance;
+layout(set=1, binding=16) uniform texture2D t_probe_specular;
+
+#define PI 3.14159265
+
+// the g-buffer's pixel, unpacked in `main`
+vec3 position;
+vec3 geometric;
+bool receives_shadows;
+
+// the cook-torrance specular term with the ggx distribution and the
+// schlick approximations of the smith geometry term and the fresnel
+// factor, `f0` being the reflectance head-on
+vec3 specular_brdf(vec3 n, vec3 v, vec3 l, vec3 f0, float roughness) {
+  vec3 h = normalize(l + v);
+  float n_dot_l = max(dot(n, l), 0.0);
+  float n_dot_v = max(dot(n, v), 1e-4);
+  float n_dot_h = max(dot(n, h), 0.0);
+  float a = roughness * roughness;
+  float a2 = a * a;
+  float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+  float distribution = a2 / (PI * d * d);
+  float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+  float geometry = n_dot_l / (n_dot_l * (1.0 - k) + k) * n_dot_v / (n_dot_v * (1.0 - k) + k);
+  vec3 fresnel = f0 + (1.0 - f0) * pow(1.0 - max(dot(h, v), 0.0), 5.0);
+  return distribution * geometry * fresnel / max(4.0 * n_dot_l * n_dot_v, 1e-4);
+}
+
+// what a light of `color` arriving from `l` reflects towards the eye. the
+// color is what a white lambertian surface facing the light shows, so it's
+// scaled by pi into radiance
+vec3 shade(vec3 n, vec3 v, vec3 l, vec3 color, vec3 base, float metallic, float roughness) {
+  vec3 f0 = mix(vec3(0.04), base, metallic);
+  vec3 diffuse = (1.0 - metallic) * base / PI;
+  vec3 specular = specular_brdf(n, v, l, f0, roughness);
+  return (diffuse + specular) * color * PI * max(dot(n, l), 0.0);
+}
+
+// where `p` lands in a cascade's map: uv, depth, and how close it is to
+// the map's edge, beyond 1 when it's outside
+vec4 cascade_coords(uint cascade, vec3 p) {
+  vec4 clip = sh_cascades[cascade] * vec4(p, 1.0);
+  vec2 uv = clip.xy * vec2(0.5, -0.5) + 0.5;
+  float edge = max(max(abs(clip.x), abs(clip.y)), clip.z > 1.0 ? 2.0 : 0.0);
+  return vec4(uv, clip.z, edge);
+}
+
+// the fraction of the sun reaching `position`, 3x3 filtered
+float cascade_shadow(uint cascade, vec4 coords) {
+  vec2 texel = 1.0 / vec2(textureSize(sampler2DArrayShadow(t_shadow, s_shadow), 0).xy);
+  float lit = 0.0;
+  for (int x = -1; x <= 1; x++) {
+    for (int y = -1; y <= 1; y++) {
+      vec4 lookup = vec4(coords.xy + vec2(x, y) * texel, float(cascade), coords.z);
+      lit += texture(sampler2DArrayShadow(t_shadow, s_shadow), lookup);
+    }
+  }
+  return lit / 9.0;
+}
+
+// picks the first cascade the fragment is in, pushed off the surface by
+// a few texels against acne, and fades into the next one near its edge.
+// the last cascade fades out to no shadow at all
+float sun_shadow(vec3 n) {
+  for (uint i = 0; i < sh_cascade_count; i++) {
+    vec3 p = position + n * sh_texel_sizes[i] * 1.5;
+    vec4 coords = cascade_coords(i, p);
+    if (coords.w > 1.0) {
+      continue;
+    }
+    float shadow = cascade_shadow(i, coords);
+    float fade = smoothstep(1.0 - sh_blend, 1.0, coords.w);
+    if (fade > 0.0) {
+      float next = 1.0;
+      if (i + 1 < sh_cascade_count) {
+        vec4 next_coords = cascade_coords(i + 1, position + n * sh_texel_sizes[i + 1] * 1.5);
+        next = cascade_shadow(i + 1, next_coords);
+      }
+      shadow = mix(shadow, next, fade);
+    }
+    return shadow;
+  }
+  return 1.0;
+}
+
+// the fraction of a point or spot light reaching `position`. the face of
+// the light's cube is the one facing the major axis of the direction, in
+// the order +X, -X, +Y, -Y, +Z, -Z, and the maps hold the distance to the
+// light over its range
+float point_shadow(LightRaw light, vec3 n) {
+  vec3 d = position - light.position;
+  vec3 a = abs(d);
+  int face;
+  if (a.x >= a.y && a.x >= a.z) {
+    face = d.x > 0.0 ? 0 : 1;
+  } else if (a.y >= a.z) {
+    face = d.y > 0.0 ? 2 : 3;
+  } else {
+    face = d.z > 0.0 ? 4 : 5;
+  }
+  int index = light.shadow * 6 + face;
+  vec4 tile = ps_tiles[index];
+  if (tile.z == 0.0) {
+    return 1.0;
+  }
+  // pushed off the surface by a few texels, which grow with the distance
+  vec3 p = position + n * tile.w * length(d) * 1.5;
+  d = p - light.position;
+  vec4 clip = ps_faces[index] * vec4(p, 1.0);
+  // kept half a texel inside the tile so filtering never reads its
+  // neighbours
+  vec2 uv = clamp(clip.xy / clip.w * vec2(0.5, -0.5) + 0.5, tile.w * 0.25, 1.0 - tile.w * 0.25);
+  float depth = length(d) / max(light.range, 1e-6) - 0.002;
+  return texture(sampler2DArrayShadow(t_point_shadow, s_shadow), vec4(tile.xy + uv * tile.z, 0.0, depth));
+}
+
+// where a direction lands in the environment's maps, as in background.frag
+vec2 panorama_uv(vec3 d) {
+  return vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+}
+
+// where the capture's maps are looked up for a ray from `p` within its
+// radius: the ray is followed out to the radius, as if everything captured
+// lay there, so nearby surfaces line up with what they reflect
+vec3 probe_direction(vec3 p, vec3 r) {
+  vec3 o = p - p_position;
+  float b = dot(o, r);
+  float c = dot(o, o) - p_radius * p_radius;
+  return o + r * (sqrt(max(b * b - c, 0.0)) - b);
+}
+
+// how much the capture replaces the environment at `p`, fading out over
+// the outer fifth of its radius
+float probe_weight(vec3 p) {
+  if (p_enabled == 0) {
+    return 0.0;
+  }
+  return clamp((p_radius - distance(p, p_position)) / max(p_radius * 0.2, 1e-4), 0.0, 1.0);
+}
+
+// the light from around the surface: the lighting's constant ambient, or
+// the environment's maps split into diffuse and specular, blended towards
+// the capture's near it
+vec3 ambient_light(vec3 n, vec3 v, vec3 base, float metallic, float roughness) {
+  float probe = probe_weight(position);
+  if (e_enabled == 0 && probe == 0.0) {
+    return l_ambient * base;
+  }
+  vec3 f0 = mix(vec3(0.04), base, metallic);
+  float n_dot_v = max(dot(n, v), 1e-4);
+  // rough surfaces get less of the fresnel brightening at grazing angles
+  vec3 fresnel = f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+  vec3 r = reflect(-v, n);
+  // the levels are explicit, the seam of the panorama breaks derivatives
+  float level = roughness * (e_levels - 1.0);
+  // without the environment's maps the constant ambient comes from everywhere
+  vec3 irradiance = l_ambient;
+  vec3 reflected = l_ambient;
+  if (e_enabled != 0) {
+    vec2 offset = vec2(e_offset, 0.0);
+    irradiance = textureLod(sampler2D(t_irradiance, s_environment), panorama_uv(n) + offset, 0.0).rgb * e_intensity;
+    reflected = textureLod(sampler2D(t_specular, s_environment), panorama_uv(r) + offset, level).rgb * e_intensity;
+  }
+  // the capture is the lit scene, it isn't scaled
+  if (probe > 0.0) {
+    irradiance = mix(irradiance, textureLod(sampler2D(t_probe_irradiance, s_environment),
+                                            panorama_uv(n), 0.0).rgb, probe);
+    reflected = mix(reflected, textureLod(sampler2D(t_probe_specular, s_environment),
+                                          panorama_uv(probe_direction(position, r)), level).rgb, probe);
+  }
+  vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * base * irradiance;
+  vec2 lut_size = vec2(textureSize(sampler2D(t_brdf_lut, s_environment), 0));
+  vec2 lut_uv = clamp(vec2(n_dot_v, roughness), 0.5 / lut_size, 1.0 - 0.5 / lut_size);
+  vec2 brdf = textureLod(sampler2D(t_brdf_lut, s_environment), lut_uv, 0.0).rg;
+  return diffuse + reflected * (f0 * brdf.x + brdf.y);
+}
+
+// point and spot lights fall off with the inverse square of the distance,
+// windowed to reach zero at the light's range. spot lights fade from the
+// inner to the outer cone
+vec3 evaluate_light(LightRaw light, vec3 n, vec3 v, vec3 base, float metallic, float roughness) {
+  if (light.kind == LIGHT_DIRECTIONAL) {
+    return shade(n, v, -light.direction, light.color, base, metallic, roughness);
+  }
+  vec3 to_light = light.position - position;
+  float d2 = dot(to_light, to_light);
+  vec3 l = to_light * inversesqrt(max(d2, 1e-12));
+  float window = clamp(1.0 - pow(d2 / max(light.range * light.range, 1e-6), 2.0), 0.0, 1.0);
+  float attenuation = window * window / max(d2, 1e-4);
+  if (light.kind == LIGHT_SPOT) {
+    attenuation *= smoothstep(light.cos_outer, max(light.cos_inner, light.cos_outer + 1e-4),
+                              dot(-l, light.direction));
+  }
+  if (attenuation <= 0.0) {
+    return vec3(0.0);
+  }
+  if (light.shadow >= 0 && receives_shadows) {
+    attenuation *= point_shadow(light, geometric);
+  }
+  return shade(n, v, l, light.color * attenuation, base, metallic, roughness);
+}
+
+// where the list of lights reaching `position` starts in `c_lights`, `ndc`
+// being where it's seen on screen
+uint cluster_of(vec3 position, vec2 ndc) {
+  float depth = -(c_view * vec4(position, 1.0)).z;
+  float slice = log(max(depth, c_near) / c_near) / log(c_far / c_near) * CLUSTERS_Z;
+  uvec2 tile = uvec2(clamp((ndc * 0.5 + 0.5) * vec2(CLUSTERS_X, CLUSTERS_Y), vec2(0.0),
+                           vec2(CLUSTERS_X - 1, CLUSTERS_Y - 1)));
+  uint z = uint(clamp(slice, 0.0, CLUSTERS_Z - 1));
+  return ((z * CLUSTERS_Y + tile.y) * CLUSTERS_X + tile.x) * CLUSTER_STRIDE;
+}
+
+// the point and spot lights at `position`, only those reaching its cluster
+// when the lights are clustered
+vec3 evaluate_lights(vec3 position, vec2 ndc, vec3 n, vec3 v, vec3 base, float metallic, float roughness) {
+  vec3 color = vec3(0.0);
+  uint cluster = c_enabled != 0 ? cluster_of(position, ndc) : 0;
+  if (c_enabled == 0 || c_lights[cluster] == CLUSTER_OVERFLOW) {
+    for (uint i = 0; i < l_light_count; i++) {
+      color += evaluate_light(lights[i], n, v, base, metallic, roughness);
+    }
+    return color;
+  }
+  uint count = c_lights[cluster];
+  for (uint i = 0; i < count; i++) {
+    color += evaluate_light(lights[c_lights[cluster + 1 + i]], n, v, base, metallic, roughness);
+  }
+  return color;
+}
+
+// applies the exposure and exponential distance fog
+vec4 environment(vec3 color, float alpha) {
+  float fog = exp(-l_fog_density * distance(d_eye.xyz, position));
+  return vec4(mix(l_fog_color, color * l_exposure, fog), alpha);
+}
+
+vec3 from_octahedral(vec2 e) {
+  vec3 n = vec3(e, 1.0 - abs(e.x) - abs(e.y));
+  if (n.z < 0.0) {
+    n.xy = (1.0 - abs(n.yx)) * vec2(n.x >= 0.0 ? 1.0 : -1.0, n.y >= 0.0 ? 1.0 : -1.0);
+  }
+  return normalize(n);
+}
+
+// lights every pixel of the g-buffer the way shader.frag lights a
+// fragment, passing its depth on so what's drawn after is hidden by it
+void main() {
+  ivec2 pixel = ivec2(gl_FragCoord.xy);
+  float depth = texelFetch(t_depth, pixel, 0).r;
+  if (depth >= 1.0) {
+    discard;
+  }
+  vec2 ndc = vec2(v_tex_coord.x * 2.0 - 1.0, 1.0 - v_tex_coord.y * 2.0);
+  vec4 world = d_inv_view_proj * vec4(ndc, depth, 1.0);
+  position = world.xyz / world.w;
+
+  vec4 albedo = texelFetch(t_albedo, pixel, 0);
+  vec4 normal = texelFetch(t_normal, pixel, 0);
+  vec4 material = texelFetch(t_material, pixel, 0);
+  vec4 emitted = texelFetch(t_emissive, pixel, 0);
+  vec3 emissive = emitted.rgb;
+  vec3 base = albedo.rgb;
+  gl_FragDepth = depth;
+  if (normal.w > 0.5) {
+    f_color = environment(base + emissive, 1.0);
+    return;
+  }
+  float metallic = material.r;
+  float roughness = material.g;
+  geometric = from_octahedral(material.ba);
+  receives_shadows = emitted.a > 0.5;
+
+  vec3 n = normalize(normal.xyz);
+  vec3 v = normalize(d_eye.xyz - position);
+  float shadow = receives_shadows ? sun_shadow(geometric) : 1.0;
+  vec3 direct = shade(n, v, normalize(l_position - position), l_color, base, metallic, roughness) * shadow;
+  direct += evaluate_lights(position, ndc, n, v, base, metallic, roughness);
+  float ssao = texelFetch(t_ssao, min(pixel, textureSize(t_ssao, 0) - 1), 0).r;
+  vec3 ambient = ambient_light(n, v, base, metallic, roughness) * albedo.a * ssao;
+  f_color = environment(direct + ambient + emissive, 1.0);
+}
@@ -0,0 +1,241 @@
+use wgpu;
+use glam::Vec2;
+use crate::camera::Camera;
+use crate::graph::Resource;
+use crate::lights::Lights;
+use crate::renderer::create_mesh_pipeline;
+use crate::texture::DepthTexture;
+use crate::upscale;
+use std::cell::{Ref, RefCell};
+
+// the g-buffer's color targets in the order gbuffer.frag writes them
+pub const TARGETS: [(Resource, wgpu::TextureFormat); 4] = [
+    ("gbuffer_albedo", wgpu::TextureFormat::Rgba8UnormSrgb),
+    ("gbuffer_normal", wgpu::TextureFormat::Rgba16Float),
+    ("gbuffer_material", wgpu::TextureFormat::Rgba16Float),
+    ("gbuffer_emissive", wgpu::TextureFormat::Rgba16Float),
+];
+pub const DEPTH: Resource = "gbuffer_depth";
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DeferredUniforms {
+    inv_view_proj: [[f32; 4]; 4],
+    eye: [f32; 4],
+}
+
+// the texture pool's generation and the addresses of the views a g-buffer
+// bind group was made from
+type BindKey = (u64, [usize; 6]);
+
+// the deferred path: the meshes write their material inputs into the
+// g-buffer, and a fullscreen pass inside the main pass lights every pixel
+// once, so each light costs a pixel rather than every fragment drawn. the
+// g-buffer is single sampled and sized like the main pass
+pub struct DeferredPass {
+    format: wgpu::TextureFormat,
+    gbuffer_fs_module: wgpu::ShaderModule,
+    gbuffer_pipeline: wgpu::RenderPipeline,
+    instanced_gbuffer_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    layout: wgpu::PipelineLayout,
+    lighting_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    // the last `bind` and what it was made from
+    bound: RefCell<Option<(BindKey, wgpu::BindGroup)>>,
+}
+
+impl DeferredPass {
+    // `layouts` and `vs_modules` are the mesh and instanced ones the main
+    // pass draws with
+    pub fn new(device: &wgpu::Device, layouts: (&wgpu::PipelineLayout, &wgpu::PipelineLayout),
+               vs_modules: (&wgpu::ShaderModule, &wgpu::ShaderModule), format: wgpu::TextureFormat,
+               sample_count: u32) -> Self {
+        let gbuffer_fs_module = device.create_shader_module(wgpu::include_spirv!("gbuffer.frag.spv"));
+        let (gbuffer_pipeline, instanced_gbuffer_pipeline) =
+            create_gbuffer_pipelines(device, layouts, vs_modules, &gbuffer_fs_module);
+
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::SampledTexture {
+                multisampled: false,
+                dimension: wgpu::TextureViewDimension::D2,
+                component_type: wgpu::TextureComponentType::Float,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture(1),
+                texture(2),
+                texture(3),
+                texture(4),
+                texture(5),
+                texture(6),
+            ],
+            label: Some("deferred_bind_group_layout"),
+        });
+        let light_bind_group_layout = Lights::create_bind_group_layout(device);
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("deferred_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout, &light_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let lighting_pipeline = create_lighting_pipeline(device, &layout, format, sample_count);
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("deferred_uniform_buffer"),
+            size: std::mem::size_of::<DeferredUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            format,
+            gbuffer_fs_module,
+            gbuffer_pipeline,
+            instanced_gbuffer_pipeline,
+            bind_group_layout,
+            layout,
+            lighting_pipeline,
+            uniform_buffer,
+            bound: RefCell::new(None),
+        }
+    }
+
+    // rebuilds the lighting pipeline for a main pass with `sample_count`
+    // samples. it reads the g-buffer per pixel and covers every sample
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.lighting_pipeline = create_lighting_pipeline(device, &self.layout, self.format, sample_count);
+    }
+
+    // rebuilds the g-buffer pipelines after the mesh vertex shaders changed
+    pub fn set_vertex_shaders(&mut self, device: &wgpu::Device,
+                              layouts: (&wgpu::PipelineLayout, &wgpu::PipelineLayout),
+                              vs_modules: (&wgpu::ShaderModule, &wgpu::ShaderModule)) {
+        let (pipeline, instanced_pipeline) =
+            create_gbuffer_pipelines(device, layouts, vs_modules, &self.gbuffer_fs_module);
+        self.gbuffer_pipeline = pipeline;
+        self.instanced_gbuffer_pipeline = instanced_pipeline;
+    }
+
+    // the mesh and instanced pipelines writing the g-buffer
+    pub fn gbuffer_pipelines(&self) -> (&wgpu::RenderPipeline, &wgpu::RenderPipeline) {
+        (&self.gbuffer_pipeline, &self.instanced_gbuffer_pipeline)
+    }
+
+    // `jitter` has to match the projection offset the g-buffer is drawn with
+    pub fn prepare(&self, queue: &wgpu::Queue, camera: &Camera, jitter: Vec2) {
+        let view_proj = upscale::jittered(camera.build_view_projection_matrix(), jitter);
+        let uniforms = DeferredUniforms {
+            inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+            eye: camera.eye.extend(1.0).into(),
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    // the g-buffer's views, in the order of `TARGETS` followed by the
+    // depth and the ambient occlusion, for `draw`. they're transient, so
+    // the bind group is only kept while `generation`, the texture pool's,
+    // and the views stay the same, e.g. until a resize
+    pub fn bind(&self, device: &wgpu::Device, generation: u64,
+                views: [&wgpu::TextureView; 6]) -> Ref<'_, wgpu::BindGroup> {
+        // the pool's textures don't move while its generation holds
+        let mut addresses = [0; 6];
+        for (address, view) in addresses.iter_mut().zip(views.iter()) {
+            *address = *view as *const wgpu::TextureView as usize;
+        }
+        let key = (generation, addresses);
+        let stale = match &*self.bound.borrow() {
+            Some((bound, _)) => *bound != key,
+            None => true,
+        };
+        if stale {
+            let texture = |binding: u32| wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(views[binding as usize - 1]),
+            };
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(self.uniform_buffer.slice(..)),
+                    },
+                    texture(1),
+                    texture(2),
+                    texture(3),
+                    texture(4),
+                    texture(5),
+                    texture(6),
+                ],
+                label: Some("deferred_bind_group"),
+            });
+            *self.bound.borrow_mut() = Some((key, bind_group));
+        }
+        Ref::map(self.bound.borrow(), |bound| &bound.as_ref().unwrap().1)
+    }
+
+    // lights the g-buffer, writing its depth into the main pass'
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, gbuffer: &'a wgpu::BindGroup,
+                    light_bind_group: &'a wgpu::BindGroup) {
+        pass.set_pipeline(&self.lighting_pipeline);
+        pass.set_bind_group(0, gbuffer, &[]);
+        pass.set_bind_group(1, light_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_gbuffer_pipelines(device: &wgpu::Device, (layout, instanced_layout): (&wgpu::PipelineLayout, &wgpu::PipelineLayout),
+                            (vs_module, instanced_vs_module): (&wgpu::ShaderModule, &wgpu::ShaderModule),
+                            fs_module: &wgpu::ShaderModule) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+    let color_states: Vec<wgpu::ColorStateDescriptor> = TARGETS.iter().map(|(_, format)| (*format).into()).collect();
+    (
+        create_mesh_pipeline(device, layout, (vs_module, fs_module), &color_states, false, 1, "gbuffer_pipeline"),
+        create_mesh_pipeline(device, instanced_layout, (instanced_vs_module, fs_module), &color_states, true, 1,
+                             "instanced_gbuffer_pipeline"),
+    )
+}
+
+fn create_lighting_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, format: wgpu::TextureFormat,
+                            sample_count: u32) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("fullscreen.vert.spv"));
+    let fs_module = device.create_shader_module(wgpu::include_spirv!("deferred.frag.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("deferred_pipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        color_states: &[format.into()],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        // the g-buffer's depth replaces whatever the background left
+        depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+            format: DepthTexture::FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilStateDescriptor::default(),
+        }),
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[],
+        },
+        sample_count,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
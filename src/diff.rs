@@ -0,0 +1,141 @@
+use crate::collision::Aabb;
+use crate::scene::Scene;
+use glam::Vec3;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+// bounds closer than this count as the same place
+const TOLERANCE: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Removed,
+    // in another place, or turned or resized
+    Moved,
+    // in the same place with other geometry
+    Edited,
+}
+
+impl Change {
+    // the color its bounds are outlined in
+    pub fn color(self) -> [f32; 3] {
+        match self {
+            Change::Added => [0.1, 0.9, 0.2],
+            Change::Removed => [1.0, 0.1, 0.1],
+            Change::Moved => [1.0, 0.8, 0.1],
+            Change::Edited => [0.2, 0.5, 1.0],
+        }
+    }
+}
+
+// one model that differs between the versions. moved models have their
+// bounds in both, the others only in the version that has them
+#[derive(Debug, Clone)]
+pub struct ModelChange {
+    pub name: String,
+    pub change: Change,
+    pub before: Option<Aabb>,
+    pub after: Option<Aabb>,
+}
+
+// what changed from one version of a scene to another. models are matched
+// by name, several of the same name in the order they come in
+#[derive(Debug, Clone, Default)]
+pub struct SceneDiff {
+    pub changes: Vec<ModelChange>,
+}
+
+impl SceneDiff {
+    pub fn new(before: &Scene, after: &Scene) -> Self {
+        let mut by_name: HashMap<&str, VecDeque<usize>> = HashMap::new();
+        for (i, model) in after.models.iter().enumerate() {
+            by_name.entry(model.name.as_str()).or_default().push_back(i);
+        }
+        let mut matched = vec![false; after.models.len()];
+        let mut changes = vec![];
+        for old in &before.models {
+            let new = match by_name.get_mut(old.name.as_str()).and_then(VecDeque::pop_front) {
+                Some(i) => {
+                    matched[i] = true;
+                    &after.models[i]
+                }
+                None => {
+                    changes.push(ModelChange {
+                        name: old.name.clone(),
+                        change: Change::Removed,
+                        before: Some(old.world_bounds()),
+                        after: None,
+                    });
+                    continue;
+                }
+            };
+            let (old_bounds, new_bounds) = (old.world_bounds(), new.world_bounds());
+            let change = if !same_place(&old_bounds, &new_bounds) {
+                Change::Moved
+            } else if old.mesh.num_vertices != new.mesh.num_vertices || old.mesh.num_indices != new.mesh.num_indices {
+                Change::Edited
+            } else {
+                continue;
+            };
+            changes.push(ModelChange {
+                name: old.name.clone(),
+                change,
+                before: Some(old_bounds).filter(|_| change == Change::Moved),
+                after: Some(new_bounds),
+            });
+        }
+        // whatever is left over only exists in the new version
+        for (new, _) in after.models.iter().zip(&matched).filter(|(_, matched)| !**matched) {
+            changes.push(ModelChange {
+                name: new.name.clone(),
+                change: Change::Added,
+                before: None,
+                after: Some(new.world_bounds()),
+            });
+        }
+        Self { changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    // the outlines to draw over either version: a moved model's old place
+    // is drawn dimmed
+    pub fn highlights(&self) -> Vec<(Aabb, [f32; 3])> {
+        let mut highlights = vec![];
+        for change in &self.changes {
+            let color = change.change.color();
+            if let Some(bounds) = change.after {
+                highlights.push((bounds, color));
+            }
+            if let Some(bounds) = change.before {
+                let color = if change.change == Change::Moved { color.map(|c| c * 0.4) } else { color };
+                highlights.push((bounds, color));
+            }
+        }
+        highlights
+    }
+
+    fn count(&self, change: Change) -> usize {
+        self.changes.iter().filter(|model| model.change == change).count()
+    }
+}
+
+fn same_place(a: &Aabb, b: &Aabb) -> bool {
+    let close = |a: Vec3, b: Vec3| (a - b).abs().max_element() <= TOLERANCE;
+    a.is_empty() == b.is_empty() && (a.is_empty() || (close(a.min, b.min) && close(a.max, b.max)))
+}
+
+impl fmt::Display for SceneDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} added, {} removed, {} moved, {} edited",
+                 self.count(Change::Added), self.count(Change::Removed),
+                 self.count(Change::Moved), self.count(Change::Edited))?;
+        for change in &self.changes {
+            writeln!(f, "  {:?} {}", change.change, change.name)?;
+        }
+        Ok(())
+    }
+}
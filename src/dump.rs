@@ -0,0 +1,88 @@
+use crate::readback::Readbacks;
+use crate::texture::DepthTexture;
+use anyhow::{anyhow, Context, Result};
+use std::path::{Path, PathBuf};
+use wgpu;
+
+// a texture, or one layer of a texture array, saved by a frame dump. it
+// needs COPY_SRC usage
+pub(crate) struct Target<'a> {
+    pub name: String,
+    pub texture: &'a wgpu::Texture,
+    pub layer: u32,
+    pub size: wgpu::Extent3d,
+    pub format: wgpu::TextureFormat,
+}
+
+impl<'a> Target<'a> {
+    pub fn new(name: &str, texture: &'a wgpu::Texture, size: wgpu::Extent3d, format: wgpu::TextureFormat) -> Self {
+        Self { name: name.to_string(), texture, layer: 0, size: wgpu::Extent3d { depth: 1, ..size }, format }
+    }
+
+    pub fn at_layer(self, layer: u32) -> Self {
+        Self { layer, ..self }
+    }
+}
+
+// reads the targets back and writes each to `dir` as a png numbered in
+// the order they're given, so they sort the way the frame drew them.
+// color targets are saved as they are. depth becomes 16 bit greyscale
+// stretched over the depths actually drawn, since perspective depth is
+// crowded next to 1, with the cleared far plane left white. targets in
+// other formats are skipped
+pub(crate) fn save(device: &wgpu::Device, queue: &wgpu::Queue, readbacks: &mut Readbacks, targets: &[Target],
+                   dir: &Path) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let pending: Vec<_> = targets.iter()
+        .filter(|target| is_color(target.format) || target.format == DepthTexture::FORMAT)
+        .map(|target| {
+            let receiver = readbacks.read_texture_bytes(device, queue, target.texture, target.layer, target.size, 4);
+            (target, receiver)
+        })
+        .collect();
+    readbacks.wait(device);
+
+    let mut paths = vec![];
+    for (i, (target, receiver)) in pending.into_iter().enumerate() {
+        let bytes = receiver.recv().map_err(|_| anyhow!("Lost the readback of {}", target.name))??;
+        let path = dir.join(format!("{:02}_{}.png", i, target.name));
+        let (width, height) = (target.size.width, target.size.height);
+        let saved = if target.format == DepthTexture::FORMAT {
+            depth_image(&bytes, width, height)?.save(&path)
+        } else {
+            let mut image = image::RgbaImage::from_raw(width, height, bytes)
+                .context("Readback doesn't match the target size")?;
+            if is_bgra(target.format) {
+                for pixel in image.pixels_mut() {
+                    pixel.0.swap(0, 2);
+                }
+            }
+            image.save(&path)
+        };
+        saved.with_context(|| format!("Failed to write {}", path.display()))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+pub(crate) fn is_bgra(format: wgpu::TextureFormat) -> bool {
+    matches!(format, wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb)
+}
+
+fn is_color(format: wgpu::TextureFormat) -> bool {
+    is_bgra(format) || matches!(format, wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb)
+}
+
+fn depth_image(bytes: &[u8], width: u32, height: u32) -> Result<image::ImageBuffer<image::Luma<u16>, Vec<u16>>> {
+    let depths: Vec<f32> = bytes.chunks_exact(4)
+        .map(|texel| f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]))
+        .collect();
+    let (near, far) = depths.iter()
+        .filter(|depth| **depth < 1.0)
+        .fold((1.0f32, 0.0f32), |(near, far), depth| (near.min(*depth), far.max(*depth)));
+    let range = (far - near).max(f32::EPSILON);
+    let pixels = depths.iter()
+        .map(|depth| if *depth < 1.0 { ((depth - near) / range * 65534.0) as u16 } else { u16::MAX })
+        .collect();
+    image::ImageBuffer::from_raw(width, height, pixels).context("Readback doesn't match the target size")
+}
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use crate::profiler::Budget;
+use crate::state::State;
+use crate::ui::UiBackend;
+use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
+use egui_winit_platform::{Platform, PlatformDescriptor};
+use std::time::Instant;
+use winit::event::Event;
+use winit::window::Window;
+
+// an egui overlay drawn over the finished frame, so panels for tweaking
+// the scene don't need a UI renderer of their own. hidden until it's made
+// visible, F2 in the viewer. the state's stats HUD is drawn in a corner
+// whenever it's on, panels or not, its message along the bottom and the
+// joints' names while skeletons are shown
+pub struct UiOverlay {
+    platform: Platform,
+    render_pass: RenderPass,
+    start: Instant,
+    visible: bool,
+}
+
+impl UiOverlay {
+    pub fn new(window: &Window, state: &State) -> Self {
+        let size = window.inner_size();
+        let platform = Platform::new(PlatformDescriptor {
+            physical_width: size.width,
+            physical_height: size.height,
+            scale_factor: window.scale_factor(),
+            font_definitions: egui::FontDefinitions::default(),
+            style: egui::Style::default(),
+        });
+        let render_pass = RenderPass::new(state.renderer().device(), state.renderer().format());
+        Self { platform, render_pass, start: Instant::now(), visible: false }
+    }
+
+    // renders the state's frame with the panels `build` adds on top, or
+    // just the frame while hidden
+    pub fn render_with<F>(&mut self, state: &mut State, window: &Window, build: F) -> Result<()>
+    where
+        F: FnOnce(&egui::CtxRef, &mut State),
+    {
+        if !self.is_drawing(state) {
+            return state.render();
+        }
+        self.platform.update_time(self.start.elapsed().as_secs_f64());
+        self.platform.begin_frame();
+        let context = self.platform.context();
+        if state.shows_stats() {
+            let lines = state.stats().lines();
+            egui::Area::new("stats").fixed_pos(egui::pos2(8.0, 8.0)).show(&context, |ui| {
+                for (line, budget) in lines {
+                    match budget {
+                        Budget::Within => ui.label(line),
+                        Budget::Near => ui.colored_label(egui::Color32::YELLOW, line),
+                        Budget::Over => ui.colored_label(egui::Color32::RED, line),
+                    };
+                }
+            });
+        }
+        // the skeleton overlay's joint names, next to the joints
+        let scale = window.scale_factor() as f32;
+        for (index, (name, [x, y])) in state.joint_labels().into_iter().enumerate() {
+            egui::Area::new(("joint", index)).fixed_pos(egui::pos2(x / scale + 4.0, y / scale)).show(&context, |ui| {
+                ui.label(name);
+            });
+        }
+        if let Some(message) = state.message() {
+            let height = window.inner_size().height as f32 / window.scale_factor() as f32;
+            egui::Area::new("message").fixed_pos(egui::pos2(8.0, height - 24.0)).show(&context, |ui| {
+                ui.colored_label(egui::Color32::RED, message);
+            });
+        }
+        if self.visible {
+            build(&context, state);
+        }
+        let (_output, shapes) = self.platform.end_frame();
+        let context = self.platform.context();
+        let paint_jobs = context.tessellate(shapes);
+        let texture = context.texture();
+        let size = window.inner_size();
+        let screen = ScreenDescriptor {
+            physical_width: size.width,
+            physical_height: size.height,
+            scale_factor: window.scale_factor() as f32,
+        };
+        let render_pass = &mut self.render_pass;
+        state.render_with(|device, queue, encoder, view| {
+            render_pass.update_texture(device, queue, &texture);
+            render_pass.update_user_textures(device, queue);
+            render_pass.update_buffers(device, queue, &paint_jobs, &screen);
+            // loads what's drawn instead of clearing it
+            render_pass.execute(encoder, view, &paint_jobs, &screen, None);
+        })
+    }
+}
+
+impl UiBackend for UiOverlay {
+    fn handle_event(&mut self, event: &Event<()>) -> bool {
+        self.platform.handle_event(event);
+        let context = self.platform.context();
+        self.visible && (context.wants_mouse_input() || context.wants_keyboard_input())
+    }
+
+    fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    fn is_drawing(&self, state: &State) -> bool {
+        self.visible || state.shows_stats() || state.message().is_some() || state.renderer().shows_skeletons()
+    }
+
+    // the state's tweak panel and outliner in windows, see `State::ui` and
+    // `State::outliner`, and the animation timeline in another when there's
+    // anything to animate
+    fn render(&mut self, state: &mut State, window: &Window) -> Result<()> {
+        self.render_with(state, window, |context, state| {
+            egui::Window::new("wr3d").show(context, |ui| state.ui(ui));
+            egui::Window::new("Outliner").show(context, |ui| state.outliner(ui));
+            if state.scene_mut().models.iter().any(|model| model.skin.is_some()) {
+                egui::Window::new("Timeline").show(context, |ui| state.timeline(ui));
+            }
+        })
+    }
+}
@@ -0,0 +1,10 @@
+#version 450
+
+layout(location=0) out vec2 v_tex_coord;
+
+// a single triangle covering the screen, for post-processing passes
+void main() {
+  vec2 uv = vec2(float((gl_VertexIndex << 1) & 2), float(gl_VertexIndex & 2));
+  gl_Position = vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+  v_tex_coord = uv;
+}
@@ -0,0 +1,130 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_color;
+layout(set=0, binding=1) uniform sampler s_color;
+
+// contrast below max(EDGE_MIN, EDGE_THRESHOLD * the brightest neighbor)
+// isn't treated as an edge
+const float EDGE_THRESHOLD = 0.125;
+const float EDGE_MIN = 0.0312;
+// how far along an edge its ends are looked for, in texels
+const int SEARCH_STEPS = 10;
+const float SEARCH_STRIDE[SEARCH_STEPS] = float[](1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0);
+// how much of the blur over single pixels is kept
+const float SUBPIXEL = 0.75;
+
+// sampling the target's sRGB format gives linear colors, the square root
+// brings the contrast closer to what the eye sees
+float luma(vec3 c) {
+  return sqrt(dot(c, vec3(0.299, 0.587, 0.114)));
+}
+
+float luma_at(vec2 uv) {
+  return luma(textureLod(sampler2D(t_color, s_color), uv, 0.0).rgb);
+}
+
+// Lottes' FXAA: finds the direction of the edge through the pixel, walks
+// along it to both ends and blends with the neighbor across it by how
+// close the pixel is to the nearer end
+void main() {
+  vec2 texel = 1.0 / vec2(textureSize(sampler2D(t_color, s_color), 0));
+  vec2 uv = v_tex_coord;
+  vec4 center = textureLod(sampler2D(t_color, s_color), uv, 0.0);
+  float l_m = luma(center.rgb);
+  float l_n = luma_at(uv + vec2(0.0, -texel.y));
+  float l_s = luma_at(uv + vec2(0.0, texel.y));
+  float l_e = luma_at(uv + vec2(texel.x, 0.0));
+  float l_w = luma_at(uv + vec2(-texel.x, 0.0));
+  float highest = max(max(max(l_n, l_s), max(l_e, l_w)), l_m);
+  float lowest = min(min(min(l_n, l_s), min(l_e, l_w)), l_m);
+  float contrast = highest - lowest;
+  if (contrast < max(EDGE_MIN, highest * EDGE_THRESHOLD)) {
+    f_color = center;
+    return;
+  }
+
+  float l_ne = luma_at(uv + vec2(texel.x, -texel.y));
+  float l_nw = luma_at(uv - texel);
+  float l_se = luma_at(uv + texel);
+  float l_sw = luma_at(uv + vec2(-texel.x, texel.y));
+
+  // a pixel standing out from all its neighbors gets blurred with them
+  float average = (2.0 * (l_n + l_s + l_e + l_w) + l_ne + l_nw + l_se + l_sw) / 12.0;
+  float subpixel = smoothstep(0.0, 1.0, clamp(abs(average - l_m) / contrast, 0.0, 1.0));
+  subpixel = subpixel * subpixel * SUBPIXEL;
+
+  float horizontal =
+    abs(l_n + l_s - 2.0 * l_m) * 2.0 + abs(l_ne + l_se - 2.0 * l_e) + abs(l_nw + l_sw - 2.0 * l_w);
+  float vertical =
+    abs(l_e + l_w - 2.0 * l_m) * 2.0 + abs(l_ne + l_nw - 2.0 * l_n) + abs(l_se + l_sw - 2.0 * l_s);
+  bool is_horizontal = horizontal >= vertical;
+
+  // the neighbor across the edge with the larger difference
+  float l_positive = is_horizontal ? l_s : l_e;
+  float l_negative = is_horizontal ? l_n : l_w;
+  float gradient_positive = abs(l_positive - l_m);
+  float gradient_negative = abs(l_negative - l_m);
+  float step_length = is_horizontal ? texel.y : texel.x;
+  float l_opposite;
+  float gradient;
+  if (gradient_positive >= gradient_negative) {
+    l_opposite = l_positive;
+    gradient = gradient_positive;
+  } else {
+    l_opposite = l_negative;
+    gradient = gradient_negative;
+    step_length = -step_length;
+  }
+
+  // walk along the edge from halfway between the two pixels until the
+  // luma there no longer matches
+  vec2 edge_uv = uv;
+  if (is_horizontal) {
+    edge_uv.y += step_length * 0.5;
+  } else {
+    edge_uv.x += step_length * 0.5;
+  }
+  vec2 edge_step = is_horizontal ? vec2(texel.x, 0.0) : vec2(0.0, texel.y);
+  float edge_luma = (l_m + l_opposite) * 0.5;
+  float threshold = gradient * 0.25;
+
+  vec2 uv_p = edge_uv + edge_step * SEARCH_STRIDE[0];
+  vec2 uv_n = edge_uv - edge_step * SEARCH_STRIDE[0];
+  float delta_p = luma_at(uv_p) - edge_luma;
+  float delta_n = luma_at(uv_n) - edge_luma;
+  bool end_p = abs(delta_p) >= threshold;
+  bool end_n = abs(delta_n) >= threshold;
+  for (int i = 1; i < SEARCH_STEPS && !(end_p && end_n); i++) {
+    if (!end_p) {
+      uv_p += edge_step * SEARCH_STRIDE[i];
+      delta_p = luma_at(uv_p) - edge_luma;
+      end_p = abs(delta_p) >= threshold;
+    }
+    if (!end_n) {
+      uv_n -= edge_step * SEARCH_STRIDE[i];
+      delta_n = luma_at(uv_n) - edge_luma;
+      end_n = abs(delta_n) >= threshold;
+    }
+  }
+
+  float distance_p = is_horizontal ? uv_p.x - uv.x : uv_p.y - uv.y;
+  float distance_n = is_horizontal ? uv.x - uv_n.x : uv.y - uv_n.y;
+  bool nearer_p = distance_p <= distance_n;
+  float nearest = min(distance_p, distance_n);
+  // only blend towards an end on the pixel's side of the edge
+  float delta = nearer_p ? delta_p : delta_n;
+  bool same_side = (delta < 0.0) == (l_m - edge_luma < 0.0);
+  float edge_blend = same_side ? 0.0 : 0.5 - nearest / (distance_p + distance_n);
+
+  float blend = max(edge_blend, subpixel);
+  vec2 blended_uv = uv;
+  if (is_horizontal) {
+    blended_uv.y += blend * step_length;
+  } else {
+    blended_uv.x += blend * step_length;
+  }
+  f_color = vec4(textureLod(sampler2D(t_color, s_color), blended_uv, 0.0).rgb, center.a);
+}
@@ -0,0 +1,127 @@
+#version 450
+
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+  vec4 u_eye;
+  vec4 u_debug;
+};
+
+// members after the flags are custom parameters, set per object and
+// zero when an object doesn't set them, see params.rs
+layout(set=0, binding=1)
+  uniform Model {
+  mat4 u_model;
+  mat4 u_normal;
+  // x the OBJECT_ bits
+  uvec4 u_flags;
+  // rgb blended over the base color by a
+  vec4 p_tint;
+};
+
+// must match the OBJECT_ constants in renderer.rs
+#define OBJECT_UNLIT 1u
+#define OBJECT_NO_SHADOWS 2u
+
+layout(set=1, binding=0) uniform texture2D t_albedo;
+layout(set=1, binding=1) uniform sampler s_albedo;
+layout(set=1, binding=2) uniform texture2D t_metallic_roughness;
+layout(set=1, binding=3) uniform sampler s_metallic_roughness;
+layout(set=1, binding=4) uniform texture2D t_bump;
+layout(set=1, binding=5) uniform sampler s_bump;
+layout(set=1, binding=6) uniform texture2D t_dissolve;
+layout(set=1, binding=7) uniform sampler s_dissolve;
+layout(set=1, binding=8) uniform texture2D t_normal;
+layout(set=1, binding=9) uniform sampler s_normal;
+layout(set=1, binding=10) uniform texture2D t_occlusion;
+layout(set=1, binding=11) uniform sampler s_occlusion;
+layout(set=1, binding=12) uniform texture2D t_emissive;
+layout(set=1, binding=13) uniform sampler s_emissive;
+
+layout(set=2, binding=0) uniform MaterialRaw {
+  vec3 u_albedo;
+  float u_metallic;
+  vec3 u_emissive;
+  float u_roughness;
+  float u_occlusion_strength;
+  float u_dissolve;
+  float u_bump_multiplier;
+  float u_normal_scale;
+  uint u_unlit;
+};
+
+layout(location=0) in vec3 v_position;
+layout(location=1) in vec3 v_normal;
+layout(location=2) in vec2 v_tex_coord;
+layout(location=3) in vec4 v_color;
+layout(location=4) in vec4 v_tangent;
+
+// see TARGETS in deferred.rs
+// rgb the base color, a the ambient occlusion
+layout(location=0) out vec4 g_albedo;
+// xyz the shaded normal, w 1 for unlit materials
+layout(location=1) out vec4 g_normal;
+// r metallic, g roughness, ba the geometric normal for the shadow offsets
+layout(location=2) out vec4 g_material;
+// rgb the emitted color, a 1 for objects receiving shadows
+layout(location=3) out vec4 g_emissive;
+
+// perturbs the normal by the screen-space gradient of the bump map, which
+// works without tangents (Mikkelsen, "Bump Mapping Unparametrized Surfaces
+// on the GPU"). a constant bump map leaves the normal unchanged
+vec3 bump(vec3 n) {
+  float height = texture(sampler2D(t_bump, s_bump), v_tex_coord).r * u_bump_multiplier;
+  vec3 dp_dx = dFdx(v_position);
+  vec3 dp_dy = dFdy(v_position);
+  vec3 r1 = cross(dp_dy, n);
+  vec3 r2 = cross(n, dp_dx);
+  float det = dot(dp_dx, r1);
+  vec3 grad = sign(det) * (dFdx(height) * r1 + dFdy(height) * r2);
+  return normalize(abs(det) * n - grad);
+}
+
+// bends the normal by the tangent space normal map. the flat map of
+// materials without one leaves it unchanged
+vec3 normal_map(vec3 n) {
+  vec3 t = v_tangent.xyz - n * dot(n, v_tangent.xyz);
+  if (dot(t, t) < 1e-12) {
+    return n;
+  }
+  t = normalize(t);
+  vec3 b = cross(n, t) * v_tangent.w;
+  vec3 m = texture(sampler2D(t_normal, s_normal), v_tex_coord).xyz * 2.0 - 1.0;
+  m.xy *= u_normal_scale;
+  return normalize(mat3(t, b, n) * m);
+}
+
+// folds a unit vector onto the octahedron and that into a square
+vec2 octahedral(vec3 n) {
+  n /= abs(n.x) + abs(n.y) + abs(n.z);
+  vec2 folded = (1.0 - abs(n.yx)) * vec2(n.x >= 0.0 ? 1.0 : -1.0, n.y >= 0.0 ? 1.0 : -1.0);
+  return n.z >= 0.0 ? n.xy : folded;
+}
+
+// the material inputs of shader.frag, written out for deferred.frag to
+// light. blending needs the colors behind, so translucent materials are
+// cut out at half coverage instead
+void main() {
+  vec4 albedo = texture(sampler2D(t_albedo, s_albedo), v_tex_coord) * v_color;
+  float alpha = albedo.a * u_dissolve
+    * texture(sampler2D(t_dissolve, s_dissolve), v_tex_coord).r;
+  if (alpha < 0.5) {
+    discard;
+  }
+  vec3 base = mix(u_albedo * albedo.rgb, p_tint.rgb, clamp(p_tint.a, 0.0, 1.0));
+  vec3 emissive = u_emissive * texture(sampler2D(t_emissive, s_emissive), v_tex_coord).rgb;
+  vec4 metallic_roughness = texture(sampler2D(t_metallic_roughness, s_metallic_roughness), v_tex_coord);
+  float metallic = u_metallic * metallic_roughness.b;
+  float roughness = clamp(u_roughness * metallic_roughness.g, 0.04, 1.0);
+  float occlusion = mix(1.0, texture(sampler2D(t_occlusion, s_occlusion), v_tex_coord).r, u_occlusion_strength);
+
+  vec3 geometric = normalize(v_normal);
+  g_albedo = vec4(base, occlusion);
+  bool unlit = u_unlit != 0 || (u_flags.x & OBJECT_UNLIT) != 0;
+  g_normal = vec4(bump(normal_map(geometric)), unlit ? 1.0 : 0.0);
+  g_material = vec4(metallic, roughness, octahedral(geometric));
+  g_emissive = vec4(emissive, (u_flags.x & OBJECT_NO_SHADOWS) == 0 ? 1.0 : 0.0);
+}
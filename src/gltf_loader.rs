@@ -0,0 +1,484 @@
+use anyhow::{bail, Context, Result};
+use glam::{Mat4, Quat, Vec3};
+use gltf::animation::Interpolation;
+use gltf::animation::util::ReadOutputs;
+use gltf::image::Format;
+use crate::assets::Assets;
+use crate::collision::{Aabb, Triangle};
+use crate::import::ImportOptions;
+use crate::material::{Material, PbrMaterial};
+use crate::mesh::{smooth_normals, tangents, Mesh, Model, SubMesh, Transform};
+use crate::skin::{Animation, Channel, Joint, Property, Skin};
+use crate::stats::{ModelStats, StageTimer};
+use crate::vertex::Vertex;
+
+// the models and materials of a gltf file, ready for a scene to draw
+pub(crate) struct Imported {
+    pub models: Vec<Model>,
+    pub model_stats: Vec<ModelStats>,
+    pub materials: Vec<Material>,
+    pub triangles: Vec<Triangle>,
+    // what had to be skipped or replaced
+    pub problems: Vec<String>,
+    // the options the vertices were loaded with, fitted to the file
+    pub import: ImportOptions,
+}
+
+// reads a .gltf or .glb file with its buffers and images. every node with
+// a mesh becomes a model, its transform and then `options` baked into the
+// vertices, hanging off the model of its closest ancestor with a mesh.
+// skinned meshes are posed by their joints instead, see `Skin`
+pub(crate) fn load(device: &wgpu::Device, queue: &wgpu::Queue, path: &str, assets: &mut Assets,
+                   options: &ImportOptions, timer: &mut StageTimer) -> Result<Imported> {
+    let (document, buffers, images) = gltf::import(path)
+        .with_context(|| format!("Failed to read {}", path))?;
+    timer.stage("parse");
+
+    let mut problems = vec![];
+    let mut materials: Vec<Material> = document.materials()
+        .map(|material| load_material(device, queue, path, &material, &images, assets, &mut problems))
+        .collect();
+    timer.stage("materials");
+
+    let scene = document.default_scene()
+        .or_else(|| document.scenes().next())
+        .with_context(|| format!("{} has no scenes", path))?;
+    // primitives without a material use the one gltf defines as default,
+    // added after the file's own materials
+    let default_material = materials.len();
+    let nodes = Nodes::new(&document, &scene);
+    let options = &options.fitted(&bounds(&nodes));
+    let mut imported = Imported {
+        models: vec![],
+        model_stats: vec![],
+        materials: vec![],
+        triangles: vec![],
+        problems,
+        import: *options,
+    };
+    // the model each node became
+    let mut models = vec![None; nodes.parents.len()];
+    for node in &nodes.order {
+        if let Some(mesh) = node.mesh() {
+            let name = node.name().or_else(|| mesh.name())
+                .map_or_else(|| format!("node {}", node.index()), str::to_string);
+            let skeleton = node.skin().map(|skin| load_skeleton(&document, &skin, &nodes, &buffers));
+            let count = imported.models.len();
+            load_model(device, &mesh, &name, (nodes.worlds[node.index()], options, skeleton), &buffers,
+                       default_material, &mut imported);
+            if imported.models.len() > count {
+                // parents come first, so theirs are already known
+                let parent = std::iter::successors(nodes.parents[node.index()], |parent| nodes.parents[*parent])
+                    .find_map(|ancestor| models[ancestor]);
+                imported.models[count].parent = parent;
+                models[node.index()] = Some(count);
+            }
+        }
+    }
+    let uses_default = imported.models.iter()
+        .flat_map(|model| &model.mesh.submeshes)
+        .any(|submesh| submesh.material == default_material);
+    if uses_default {
+        materials.push(Material::from_pbr(device, queue, "default", PbrMaterial::default()));
+    }
+    imported.materials = materials;
+    timer.stage("meshes");
+    Ok(imported)
+}
+
+// the nodes of a scene, parents before their children, and where each
+// one is. indexed by the nodes' indices in the file
+struct Nodes<'a> {
+    order: Vec<gltf::Node<'a>>,
+    // `order`'s index of each node, past the end for nodes the scene
+    // doesn't use
+    ranks: Vec<usize>,
+    parents: Vec<Option<usize>>,
+    locals: Vec<Mat4>,
+    worlds: Vec<Mat4>,
+}
+
+impl<'a> Nodes<'a> {
+    fn new(document: &gltf::Document, scene: &gltf::Scene<'a>) -> Self {
+        let count = document.nodes().len();
+        let mut nodes = Self {
+            order: vec![],
+            ranks: vec![usize::MAX; count],
+            parents: vec![None; count],
+            locals: vec![Mat4::identity(); count],
+            worlds: vec![Mat4::identity(); count],
+        };
+        let mut pending: Vec<(gltf::Node, Option<usize>)> = scene.nodes().map(|node| (node, None)).collect();
+        while let Some((node, parent)) = pending.pop() {
+            let index = node.index();
+            let local = Mat4::from_cols_array_2d(&node.transform().matrix());
+            nodes.ranks[index] = nodes.order.len();
+            nodes.parents[index] = parent;
+            nodes.locals[index] = local;
+            nodes.worlds[index] = parent.map_or_else(Mat4::identity, |parent| nodes.worlds[parent]) * local;
+            pending.extend(node.children().map(|child| (child, Some(index))));
+            nodes.order.push(node);
+        }
+        nodes
+    }
+}
+
+// the scene's extent in the file's units, from the corners of its
+// primitives' bounds, which gltf requires, through their nodes' transforms
+fn bounds(nodes: &Nodes) -> Aabb {
+    let mut corners = vec![];
+    for node in &nodes.order {
+        let transform = nodes.worlds[node.index()];
+        for primitive in node.mesh().iter().flat_map(|mesh| mesh.primitives()) {
+            let gltf::mesh::Bounds { min, max } = primitive.bounding_box();
+            for i in 0..8 {
+                let corner = Vec3::new(
+                    if i & 1 == 0 { min[0] } else { max[0] },
+                    if i & 2 == 0 { min[1] } else { max[1] },
+                    if i & 4 == 0 { min[2] } else { max[2] },
+                );
+                corners.push((transform * corner.extend(1.0)).truncate());
+            }
+        }
+    }
+    Aabb::from_points(corners)
+}
+
+// a skin's joints, parents first, what the file's joint indices are in
+// them and the file's animations that move them
+struct Skeleton {
+    joints: Vec<Joint>,
+    remap: Vec<u16>,
+    animations: Vec<Animation>,
+}
+
+// joints without an inverse bind matrix are bound where they are. channels
+// of other nodes or with broken keys are left out, cubic splines are
+// played linearly through their keys
+fn load_skeleton(document: &gltf::Document, skin: &gltf::Skin, nodes: &Nodes,
+                 buffers: &[gltf::buffer::Data]) -> Skeleton {
+    let inverse_binds: Vec<Mat4> = skin.reader(|buffer| Some(&buffers[buffer.index()]))
+        .read_inverse_bind_matrices()
+        .map_or_else(Vec::new, |matrices| matrices.map(|matrix| Mat4::from_cols_array_2d(&matrix)).collect());
+    let file_joints: Vec<gltf::Node> = skin.joints().collect();
+    let joint_of = |node: usize| file_joints.iter().position(|joint| joint.index() == node);
+    let mut sorted: Vec<usize> = (0..file_joints.len()).collect();
+    sorted.sort_by_key(|&joint| nodes.ranks[file_joints[joint].index()]);
+    let mut remap = vec![0; file_joints.len()];
+    for (index, &joint) in sorted.iter().enumerate() {
+        remap[joint] = index as u16;
+    }
+
+    let joints = sorted.iter().enumerate()
+        .map(|(index, &joint)| {
+            let node = &file_joints[joint];
+            let mut base = Mat4::identity();
+            let mut above = nodes.parents[node.index()];
+            let parent = loop {
+                match above {
+                    Some(parent) => match joint_of(parent) {
+                        Some(parent) => break Some(remap[parent] as usize).filter(|parent| *parent < index),
+                        None => {
+                            base = nodes.locals[parent] * base;
+                            above = nodes.parents[parent];
+                        }
+                    },
+                    None => break None,
+                }
+            };
+            let (translation, rotation, scale) = node.transform().decomposed();
+            let local = Transform { translation: translation.into(), rotation: Quat::from(rotation), scale: scale.into() };
+            let inverse_bind = inverse_binds.get(joint).copied().unwrap_or_else(Mat4::identity);
+            let name = node.name().map_or_else(|| format!("node {}", node.index()), str::to_string);
+            Joint::new(name, parent, local, base, inverse_bind)
+        })
+        .collect();
+
+    let animations = document.animations().filter_map(|animation| {
+        let channels: Vec<Channel> = animation.channels()
+            .filter_map(|channel| {
+                let joint = joint_of(channel.target().node().index())?;
+                let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+                let times: Vec<f32> = reader.read_inputs()?.collect();
+                let vector = |[x, y, z]: [f32; 3]| [x, y, z, 0.0];
+                let (property, values): (_, Vec<[f32; 4]>) = match reader.read_outputs()? {
+                    ReadOutputs::Translations(values) => (Property::Translation, values.map(vector).collect()),
+                    ReadOutputs::Rotations(values) => (Property::Rotation, values.into_f32().collect()),
+                    ReadOutputs::Scales(values) => (Property::Scale, values.map(vector).collect()),
+                    ReadOutputs::MorphTargetWeights(_) => return None,
+                };
+                let interpolation = channel.sampler().interpolation();
+                // a spline's keys come between their in and out tangents
+                let values: Vec<_> = match interpolation {
+                    Interpolation::CubicSpline => values.chunks_exact(3).map(|key| key[1]).collect(),
+                    _ => values,
+                };
+                if values.len() < times.len() {
+                    return None;
+                }
+                Some(Channel {
+                    joint: remap[joint] as usize,
+                    property,
+                    step: interpolation == Interpolation::Step,
+                    times,
+                    values,
+                })
+            })
+            .collect();
+        if channels.is_empty() {
+            return None;
+        }
+        let name = animation.name().map_or_else(|| format!("animation {}", animation.index()), str::to_string);
+        Some(Animation::new(name, channels))
+    }).collect();
+    Skeleton { joints, remap, animations }
+}
+
+// maps whose images can't be used are left out, as if the material didn't
+// have them. images used by several materials are uploaded once
+fn load_material(device: &wgpu::Device, queue: &wgpu::Queue, path: &str, material: &gltf::Material,
+                 images: &[gltf::image::Data], assets: &mut Assets, problems: &mut Vec<String>) -> Material {
+    let name = material.name()
+        .map_or_else(|| format!("material {}", material.index().unwrap_or(0)), str::to_string);
+    let pbr = material.pbr_metallic_roughness();
+    let mut map = |texture: gltf::texture::Texture, format, label: &str, kind: &str| {
+        let index = texture.source().index();
+        let key = format!("{}#image{}", path, index);
+        match assets.texture_with(device, queue, &key, format, Some(label), || rgba_image(&images[index])) {
+            Ok(texture) => Some(texture),
+            Err(error) => {
+                problems.push(format!("material {}: {} map: {:#}", name, kind, error));
+                None
+            }
+        }
+    };
+    let srgb = wgpu::TextureFormat::Rgba8UnormSrgb;
+    let linear = wgpu::TextureFormat::Rgba8Unorm;
+    let albedo_texture = pbr.base_color_texture()
+        .and_then(|info| map(info.texture(), srgb, "albedo_texture", "base color"));
+    let metallic_roughness_texture = pbr.metallic_roughness_texture()
+        .and_then(|info| map(info.texture(), linear, "metallic_roughness_texture", "metallic-roughness"));
+    let emissive_texture = material.emissive_texture()
+        .and_then(|info| map(info.texture(), srgb, "emissive_texture", "emissive"));
+    let normal_texture = material.normal_texture().and_then(|normal| {
+        map(normal.texture(), linear, "normal_texture", "normal").map(|texture| (texture, normal.scale()))
+    });
+    let occlusion = material.occlusion_texture().and_then(|occlusion| {
+        map(occlusion.texture(), linear, "occlusion_texture", "occlusion")
+            .map(|texture| (texture, occlusion.strength()))
+    });
+    let (occlusion_texture, occlusion_strength) = match occlusion {
+        Some((texture, strength)) => (Some(texture), strength),
+        None => (None, 1.0),
+    };
+    let params = PbrMaterial {
+        albedo: pbr.base_color_factor(),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        emissive: material.emissive_factor(),
+        occlusion_strength,
+        albedo_texture,
+        metallic_roughness_texture,
+        occlusion_texture,
+        emissive_texture,
+        normal_texture,
+        ..PbrMaterial::default()
+    };
+    Material::from_pbr(device, queue, &name, params)
+}
+
+// gltf decodes images to whatever channels the file had, the textures
+// are always 8 bit rgba
+fn rgba_image(data: &gltf::image::Data) -> Result<image::RgbaImage> {
+    let channels = match data.format {
+        Format::R8 => 1,
+        Format::R8G8 => 2,
+        Format::R8G8B8 => 3,
+        Format::R8G8B8A8 => 4,
+        format => bail!("Unsupported image format {:?}", format),
+    };
+    let mut pixels = Vec::with_capacity(data.pixels.len() / channels * 4);
+    for texel in data.pixels.chunks_exact(channels) {
+        pixels.extend_from_slice(&match texel {
+            [r] => [*r, *r, *r, 255],
+            [r, g] => [*r, *g, 0, 255],
+            [r, g, b] => [*r, *g, *b, 255],
+            [r, g, b, a] => [*r, *g, *b, *a],
+            _ => unreachable!(),
+        });
+    }
+    image::RgbaImage::from_raw(data.width, data.height, pixels)
+        .context("Image data doesn't match its size")
+}
+
+// one sub-mesh per triangle primitive, other primitive modes (points,
+// lines, strips) and primitives with broken indices are skipped. the
+// node's transform is applied before the import options, unless the mesh
+// is skinned. its vertices are then kept as the file has them, for the
+// skin to move and apply the options to
+fn load_model(device: &wgpu::Device, mesh: &gltf::Mesh, name: &str,
+              (transform, options, skeleton): (Mat4, &ImportOptions, Option<Skeleton>),
+              buffers: &[gltf::buffer::Data], default_material: usize, imported: &mut Imported) {
+    let skinned = skeleton.is_some();
+    let transform = if skinned { Mat4::identity() } else { transform };
+    let normal_matrix = transform.inverse().transpose();
+    let position = |p: Vec3| {
+        let p = (transform * p.extend(1.0)).truncate();
+        if skinned { p } else { options.position(p) }
+    };
+    let normal = |n: Vec3| {
+        let n = (normal_matrix * n.extend(0.0)).truncate().normalize();
+        if skinned { n } else { options.normal(n) }
+    };
+    let tangent = |[x, y, z, w]: [f32; 4]| {
+        let t = (transform * Vec3::new(x, y, z).extend(0.0)).truncate().normalize();
+        if skinned { [t.x, t.y, t.z, w] } else { options.tangent([t.x, t.y, t.z, w]) }
+    };
+    let mut vertices: Vec<Vertex> = vec![];
+    let mut indices: Vec<u32> = vec![];
+    let mut influences: Vec<([u16; 4], [f32; 4])> = vec![];
+    let mut submeshes = vec![];
+    let mut missing_normals = false;
+    let mut missing_tex_coords = false;
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            continue;
+        }
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let positions: Vec<Vec3> = match reader.read_positions() {
+            Some(positions) => positions.map(|p| position(Vec3::from(p))).collect(),
+            None => {
+                imported.problems.push(format!("model {}: primitive {} has no positions", name, primitive.index()));
+                continue;
+            }
+        };
+        let mut primitive_indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        options.triangles(&mut primitive_indices);
+        if let Some(index) = primitive_indices.iter().find(|index| **index as usize >= positions.len()) {
+            imported.problems.push(format!("model {}: primitive {}: index {} is past its {} vertices",
+                                           name, primitive.index(), index, positions.len()));
+            continue;
+        }
+        // normals and tangents made here are for the vertices as they're
+        // drawn, which skinning's options would flip again
+        let normals: Vec<Vec3> = match reader.read_normals() {
+            Some(normals) => normals.map(|n| normal(Vec3::from(n))).collect(),
+            None => {
+                missing_normals = true;
+                let normals = smooth_normals(&positions, &primitive_indices);
+                if skinned && options.flip_normals { normals.iter().map(|n| -*n).collect() } else { normals }
+            }
+        };
+        let tex_coords: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+            Some(tex_coords) => tex_coords.into_f32().map(|t| options.tex_coord(t)).collect(),
+            None => {
+                missing_tex_coords = true;
+                vec![[0.0; 2]; positions.len()]
+            }
+        };
+        // the file's tangents only go with its own normals
+        let primitive_tangents: Vec<[f32; 4]> = match reader.read_tangents().filter(|_| reader.read_normals().is_some()) {
+            Some(file_tangents) => file_tangents.map(tangent).collect(),
+            None => tangents(&positions, &normals, &tex_coords, &primitive_indices).into_iter()
+                .map(|[x, y, z, w]| if skinned && options.flip_v { [x, y, z, -w] } else { [x, y, z, w] })
+                .collect(),
+        };
+        if let Some(skeleton) = &skeleton {
+            // joints past the skin's are left for the skin to skip
+            let joints = reader.read_joints(0).map(|joints| joints.into_u16());
+            let weights = reader.read_weights(0).map(|weights| weights.into_f32());
+            match joints.zip(weights) {
+                Some((joints, weights)) => influences.extend(joints.zip(weights).map(|(joints, weights)| {
+                    (joints.map(|joint| skeleton.remap.get(joint as usize).copied().unwrap_or(u16::MAX)), weights)
+                })),
+                None => imported.problems.push(format!("model {}: primitive {} has no joints, it isn't animated",
+                                                       name, primitive.index())),
+            }
+            influences.resize(vertices.len() + positions.len(), ([0; 4], [0.0; 4]));
+        }
+
+        let base_vertex = vertices.len() as u32;
+        let start = indices.len() as u32;
+        let attributes = positions.iter().zip(&normals).zip(&tex_coords).zip(primitive_tangents);
+        vertices.extend(attributes.map(|(((position, normal), tex_coord), tangent)| {
+            Vertex {
+                position: [position.x, position.y, position.z],
+                normal: [normal.x, normal.y, normal.z],
+                tex_coord: *tex_coord,
+                tangent,
+            }
+        }));
+        indices.extend(primitive_indices.iter().map(|index| index + base_vertex));
+        submeshes.push(SubMesh {
+            material: primitive.material().index().unwrap_or(default_material),
+            indices: start..indices.len() as u32,
+        });
+    }
+    if submeshes.is_empty() {
+        return;
+    }
+    // skinned models start in the skeleton's rest pose, which is also
+    // where they collide
+    let skin = skeleton.map(|Skeleton { joints, animations, .. }| {
+        let mut skin = Skin::new(joints, std::mem::take(&mut vertices), influences, *options);
+        skin.animations = animations;
+        if !skin.animations.is_empty() {
+            skin.animator.play(0, 0.0);
+        }
+        vertices = skin.update().map(<[Vertex]>::to_vec).unwrap_or_default();
+        skin
+    });
+    let position = |index: u32| Vec3::from(vertices[index as usize].position);
+    imported.triangles.extend(indices.chunks_exact(3).map(|face| Triangle {
+        a: position(face[0]),
+        b: position(face[1]),
+        c: position(face[2]),
+    }));
+    let bounds = match &skin {
+        Some(skin) => skin.bounds(),
+        None => Aabb::from_points(vertices.iter().map(|vertex| Vec3::from(vertex.position))),
+    };
+    let mut model = Model::from_mesh(Mesh::from_vertices(device, &vertices, &indices, submeshes, name), bounds, name);
+    model.skin = skin;
+    imported.model_stats.push(ModelStats {
+        missing_normals,
+        missing_tex_coords,
+        ..ModelStats::new(&model)
+    });
+    imported.models.push(model);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_arm_skeleton() {
+        let (document, buffers, _) = gltf::import("data/arm.gltf").unwrap();
+        let scene = document.default_scene().unwrap();
+        let nodes = Nodes::new(&document, &scene);
+        let node = nodes.order.iter().find(|node| node.skin().is_some()).unwrap();
+        let Skeleton { joints, remap, animations } = load_skeleton(&document, &node.skin().unwrap(), &nodes, &buffers);
+        let names: Vec<_> = joints.iter().map(|joint| joint.name.as_str()).collect();
+        assert_eq!(names, ["shoulder", "elbow"]);
+        assert_eq!(joints[1].parent, Some(0));
+        assert_eq!(remap, [0, 1]);
+
+        // the elbow bends the top vertex over to x = -1 a second in
+        let vertex = |y: f32| Vertex { position: [0.0, y, 0.0], normal: [0.0, 0.0, 1.0], ..Vertex::default() };
+        let mut skin = Skin::new(joints, vec![vertex(0.0), vertex(2.0)],
+                                 vec![([0; 4], [1.0, 0.0, 0.0, 0.0]), ([1, 0, 0, 0], [1.0, 0.0, 0.0, 0.0])],
+                                 ImportOptions::default());
+        assert_eq!(animations.iter().map(|animation| animation.duration).collect::<Vec<_>>(), [2.0]);
+        skin.animations = animations;
+        skin.animator.play(0, 0.0);
+        skin.animate(0.0);
+        skin.animate(1.0);
+        let hand = Vec3::from(skin.update().unwrap()[1].position);
+        assert!((hand - Vec3::new(-1.0, 1.0, 0.0)).length() < 1e-4, "the hand is at {}", hand);
+        assert!(skin.bounds().min.x < -0.99);
+    }
+}
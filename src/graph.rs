@@ -0,0 +1,626 @@
+use wgpu;
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+// passes name the textures they read and write with static strings
+pub type Resource = &'static str;
+
+// the most resources one graph can name. the views of a frame are looked
+// up in an array of this size, so recording doesn't allocate
+pub const MAX_RESOURCES: usize = 32;
+
+// a texture that only lives for one frame, allocated by the graph and
+// handed to the next pass with the same description once the last pass
+// using it is done
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub usage: wgpu::TextureUsage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    // a view the graph doesn't own, e.g. the frame's swap chain image.
+    // passes writing one are what the graph is run for
+    Imported,
+    Transient(TextureDesc),
+    // state a pass keeps itself, e.g. the shadow maps, only named so the
+    // passes using it are ordered after the one updating it
+    External,
+}
+
+// what `add_pass` records a pass with
+pub type Record<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &Attachments) + 'a>;
+
+// the names a pass reads and writes are ranges of `RenderGraph::names`
+struct Pass {
+    name: &'static str,
+    reads: Range<usize>,
+    writes: Range<usize>,
+}
+
+// the views of the imported and transient textures a pass declared
+pub struct Attachments<'v> {
+    pass: &'static str,
+    generation: u64,
+    declared: &'v [Resource],
+    resources: &'v [(Resource, Kind)],
+    views: &'v [Option<&'v wgpu::TextureView>],
+}
+
+impl<'v> Attachments<'v> {
+    // panics when the pass didn't declare `name` or it has no texture, which
+    // is a mistake in how the graph was built rather than something to handle
+    pub fn view(&self, name: Resource) -> &'v wgpu::TextureView {
+        let view = if self.declared.contains(&name) {
+            self.resources.iter().position(|(other, _)| *other == name).and_then(|index| self.views[index])
+        } else {
+            None
+        };
+        match view {
+            Some(view) => view,
+            None => panic!("Pass {} has no attachment {}", self.pass, name),
+        }
+    }
+
+    // the pool's `TexturePool::generation` while recording
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+// the order passes run in and when each transient texture is used, worked
+// out by `compile` and kept until the graph changes
+struct Schedule {
+    order: Vec<usize>,
+    // indices into `RenderGraph::resources`, with the first and last step
+    // using them, by first use
+    transients: Vec<(usize, TextureDesc, (usize, usize))>,
+}
+
+// the passes of a frame and the textures they pass along. passes are
+// recorded in an order where everything a pass reads has been written by
+// every pass writing it, and passes writing the same resource keep the
+// order they were added in. passes that don't lead to an imported
+// resource are skipped. everything goes into one encoder.
+//
+// passes are recorded with closures by default, for a graph built and run
+// once. a graph run every frame can hold plain values instead, see `run`,
+// and keeps its schedule until a pass or resource is added
+pub struct RenderGraph<'a, P = Record<'a>> {
+    resources: Vec<(Resource, Kind)>,
+    // the views given to `import`
+    views: Vec<(Resource, &'a wgpu::TextureView)>,
+    passes: Vec<Pass>,
+    records: Vec<P>,
+    // the reads and then the writes of every pass
+    names: Vec<Resource>,
+    schedule: Option<Schedule>,
+}
+
+impl<'a, P> Default for RenderGraph<'a, P> {
+    fn default() -> Self {
+        Self {
+            resources: vec![],
+            views: vec![],
+            passes: vec![],
+            records: vec![],
+            names: vec![],
+            schedule: None,
+        }
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn add_pass<F>(&mut self, name: &'static str, reads: &[Resource], writes: &[Resource], record: F)
+    where
+        F: FnOnce(&mut wgpu::CommandEncoder, &Attachments) + 'a,
+    {
+        self.add(name, reads, writes, Box::new(record));
+    }
+
+    // records the passes into one encoder and submits it, taking the
+    // transient textures from `pool`. `timings` is refilled with how long
+    // each pass took to record, in the order they ran, and the submit last
+    pub fn execute(mut self, device: &wgpu::Device, queue: &wgpu::Queue, pool: &mut TexturePool,
+                   timings: &mut Vec<(&'static str, Duration)>) -> Result<()> {
+        self.compile()?;
+        let mut records: Vec<_> = self.records.drain(..).map(Some).collect();
+        self.record(device, queue, pool, timings, &self.views, |pass, encoder, attachments| {
+            (records[pass].take().unwrap())(encoder, attachments);
+        })
+    }
+}
+
+impl<'a, P> RenderGraph<'a, P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn import(&mut self, name: Resource, view: &'a wgpu::TextureView) {
+        self.declare(name, Kind::Imported);
+        self.views.retain(|(other, _)| *other != name);
+        self.views.push((name, view));
+    }
+
+    // an imported resource whose view is only given to `run`, so the graph
+    // can outlive it
+    pub fn imported(&mut self, name: Resource) {
+        self.declare(name, Kind::Imported);
+    }
+
+    pub fn transient(&mut self, name: Resource, desc: TextureDesc) {
+        self.declare(name, Kind::Transient(desc));
+    }
+
+    pub fn external(&mut self, name: Resource) {
+        self.declare(name, Kind::External);
+    }
+
+    fn declare(&mut self, name: Resource, kind: Kind) {
+        self.schedule = None;
+        match self.resources.iter_mut().find(|(other, _)| *other == name) {
+            Some(resource) => resource.1 = kind,
+            None => self.resources.push((name, kind)),
+        }
+    }
+
+    // a pass recorded with `record`, see `run`
+    pub fn add(&mut self, name: &'static str, reads: &[Resource], writes: &[Resource], record: P) {
+        self.schedule = None;
+        let start = self.names.len();
+        self.names.extend_from_slice(reads);
+        self.names.extend_from_slice(writes);
+        self.passes.push(Pass {
+            name,
+            reads: start..start + reads.len(),
+            writes: start + reads.len()..self.names.len(),
+        });
+        self.records.push(record);
+    }
+
+    fn reads(&self, pass: usize) -> &[Resource] {
+        &self.names[self.passes[pass].reads.clone()]
+    }
+
+    fn writes(&self, pass: usize) -> &[Resource] {
+        &self.names[self.passes[pass].writes.clone()]
+    }
+
+    // whether the graph has a resource named `name`
+    pub fn has(&self, name: Resource) -> bool {
+        self.kind(name).is_some()
+    }
+
+    // the same graph recording its passes with what `f` makes of their values
+    pub fn map<Q, F: FnMut(P) -> Q>(self, f: F) -> RenderGraph<'a, Q> {
+        RenderGraph {
+            resources: self.resources,
+            views: self.views,
+            passes: self.passes,
+            records: self.records.into_iter().map(f).collect(),
+            names: self.names,
+            schedule: self.schedule,
+        }
+    }
+
+    fn kind(&self, name: Resource) -> Option<Kind> {
+        self.resources.iter().find(|(other, _)| *other == name).map(|(_, kind)| *kind)
+    }
+
+    // the names of the passes that will run, in order
+    pub fn schedule(&self) -> Result<Vec<&'static str>> {
+        Ok(self.order()?.into_iter().map(|pass| self.passes[pass].name).collect())
+    }
+
+    // checks the graph and works out its schedule, which is kept until a
+    // pass or resource is added
+    pub fn compile(&mut self) -> Result<()> {
+        if self.schedule.is_some() {
+            return Ok(());
+        }
+        if self.resources.len() > MAX_RESOURCES {
+            bail!("The graph has {} resources, at most {} are supported", self.resources.len(), MAX_RESOURCES);
+        }
+        let order = self.order()?;
+        let transients = self.transients(&order);
+        self.schedule = Some(Schedule { order, transients });
+        Ok(())
+    }
+
+    fn order(&self) -> Result<Vec<usize>> {
+        for (index, pass) in self.passes.iter().enumerate() {
+            let mut names = self.reads(index).iter().chain(self.writes(index));
+            if let Some(name) = names.find(|name| self.kind(name).is_none()) {
+                bail!("Pass {} uses {}, which isn't in the graph", pass.name, name);
+            }
+        }
+
+        // walks back from the imported resources to the passes they need
+        let mut needed = vec![false; self.passes.len()];
+        let mut stack: Vec<usize> = (0..self.passes.len())
+            .filter(|pass| self.writes(*pass).iter().any(|name| self.kind(name) == Some(Kind::Imported)))
+            .collect();
+        while let Some(pass) = stack.pop() {
+            if needed[pass] {
+                continue;
+            }
+            needed[pass] = true;
+            let reads = self.reads(pass);
+            stack.extend((0..self.passes.len())
+                .filter(|other| self.writes(*other).iter().any(|name| reads.contains(name))));
+        }
+
+        // a pass waits for every pass writing what it only reads, and for
+        // the passes added before it writing the same resource
+        let before = |a: usize, b: usize| {
+            self.writes(a).iter().any(|name| self.reads(b).contains(name) && !self.writes(b).contains(name))
+        };
+        let mut waiting: Vec<usize> = (0..self.passes.len()).filter(|pass| needed[*pass]).collect();
+        let mut order = vec![];
+        while !waiting.is_empty() {
+            let ready = waiting.iter().position(|&pass| {
+                waiting.iter().all(|&other| {
+                    other == pass || !(before(other, pass)
+                        || (other < pass && self.writes(other).iter()
+                            .any(|name| self.writes(pass).contains(name))))
+                })
+            });
+            match ready {
+                Some(index) => order.push(waiting.remove(index)),
+                None => {
+                    let names: Vec<_> = waiting.iter().map(|pass| self.passes[*pass].name).collect();
+                    bail!("The passes {} depend on each other", names.join(", "));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    // the transient textures the passes in `order` use, see `Schedule`
+    fn transients(&self, order: &[usize]) -> Vec<(usize, TextureDesc, (usize, usize))> {
+        let mut uses: HashMap<Resource, (usize, usize)> = HashMap::new();
+        for (step, pass) in order.iter().enumerate() {
+            for name in self.reads(*pass).iter().chain(self.writes(*pass)) {
+                let (_, last) = uses.entry(name).or_insert((step, step));
+                *last = step;
+            }
+        }
+        let mut transients: Vec<_> = self.resources.iter().enumerate()
+            .filter_map(|(index, (name, kind))| match kind {
+                Kind::Transient(desc) => uses.get(name).map(|uses| (index, *desc, *uses)),
+                _ => None,
+            })
+            .collect();
+        transients.sort_by_key(|(index, _, (first, _))| (*first, self.resources[*index].0));
+        transients
+    }
+
+    // which texture each transient gets when the pool starts out empty,
+    // ones with the same number share it one after the other
+    fn aliases(&self, order: &[usize]) -> HashMap<Resource, usize> {
+        let mut textures: Vec<(TextureDesc, usize)> = vec![];
+        let mut shared = HashMap::new();
+        for (index, desc, (first, last)) in self.transients(order) {
+            let free = textures.iter().position(|(other, busy)| *other == desc && *busy < first);
+            let texture = match free {
+                Some(texture) => texture,
+                None => {
+                    textures.push((desc, last));
+                    textures.len() - 1
+                }
+            };
+            textures[texture].1 = last;
+            shared.insert(self.resources[index].0, texture);
+        }
+        shared
+    }
+
+    // the graph in Graphviz's dot language, e.g. for `dot -Tsvg`. passes
+    // are boxes numbered in the order they run, with the ones skipped
+    // dashed, and resources are ellipses with an arrow from each pass
+    // writing them and to each pass reading them. transient textures show
+    // their size and format, and which texture of the frame they get:
+    // ones with the same number share it, one after the other
+    pub fn to_dot(&self) -> Result<String> {
+        let order = self.order()?;
+        let shared = self.aliases(&order);
+
+        let mut dot = String::from("digraph frame {\n    rankdir=LR;\n");
+        let mut resources: Vec<_> = self.resources.iter().collect();
+        resources.sort_by_key(|(name, _)| *name);
+        for (name, kind) in resources {
+            let (label, style) = match kind {
+                Kind::Imported => (format!("{}\\nimported", name), "bold"),
+                Kind::Transient(desc) => {
+                    let texture = match shared.get(name) {
+                        Some(index) => format!("texture {}", index),
+                        None => String::from("unused"),
+                    };
+                    let samples = if desc.sample_count > 1 { format!(" x{}", desc.sample_count) } else { String::new() };
+                    (format!("{}\\n{}x{} {:?}{}\\n{}", name, desc.width, desc.height, desc.format, samples, texture),
+                     "solid")
+                }
+                Kind::External => (format!("{}\\nexternal", name), "dashed"),
+            };
+            dot += &format!("    \"{}\" [shape=ellipse, style={}, label=\"{}\"];\n", name, style, label);
+        }
+        for (index, pass) in self.passes.iter().enumerate() {
+            let node = format!("pass{}", index);
+            match order.iter().position(|&other| other == index) {
+                Some(step) => dot += &format!("    {} [shape=box, label=\"{}. {}\"];\n", node, step + 1, pass.name),
+                None => dot += &format!("    {} [shape=box, style=dashed, label=\"{}\"];\n", node, pass.name),
+            }
+            for name in self.reads(index) {
+                dot += &format!("    \"{}\" -> {};\n", name, node);
+            }
+            for name in self.writes(index) {
+                dot += &format!("    {} -> \"{}\";\n", node, name);
+            }
+        }
+        dot += "}\n";
+        Ok(dot)
+    }
+
+    // records the compiled graph the way `execute` does, handing each
+    // pass' value to `record`. `imported` has the views of the resources
+    // declared with `imported`. once the pool holds the frame's textures
+    // and `timings` has grown to fit, this doesn't allocate, so a graph
+    // built once can be run every frame
+    pub fn run<F>(&self, device: &wgpu::Device, queue: &wgpu::Queue, pool: &mut TexturePool,
+                  timings: &mut Vec<(&'static str, Duration)>, imported: &[(Resource, &wgpu::TextureView)],
+                  mut record: F) -> Result<()>
+    where
+        F: FnMut(&P, &mut wgpu::CommandEncoder, &Attachments),
+    {
+        self.record(device, queue, pool, timings, imported, |pass, encoder, attachments| {
+            record(&self.records[pass], encoder, attachments);
+        })
+    }
+
+    fn record<F>(&self, device: &wgpu::Device, queue: &wgpu::Queue, pool: &mut TexturePool,
+                 timings: &mut Vec<(&'static str, Duration)>, imported: &[(Resource, &wgpu::TextureView)],
+                 mut record: F) -> Result<()>
+    where
+        F: FnMut(usize, &mut wgpu::CommandEncoder, &Attachments),
+    {
+        let schedule = match &self.schedule {
+            Some(schedule) => schedule,
+            None => bail!("The graph has to be compiled before it's run"),
+        };
+        let mut slots = [None; MAX_RESOURCES];
+        for (index, desc, (first, last)) in &schedule.transients {
+            slots[*index] = Some(pool.take(device, *desc, *first, *last));
+        }
+        let mut views = [None; MAX_RESOURCES];
+        for (index, (name, kind)) in self.resources.iter().enumerate() {
+            views[index] = match kind {
+                Kind::Imported => match imported.iter().find(|(other, _)| other == name) {
+                    Some((_, view)) => Some(*view),
+                    None => bail!("There's no view for the imported {}", name),
+                },
+                Kind::Transient(_) => slots[index].map(|slot| &pool.textures[slot].view),
+                Kind::External => None,
+            };
+        }
+
+        let mut encoder = device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Render Graph Encoder") },
+        );
+        timings.clear();
+        for &index in &schedule.order {
+            let pass = &self.passes[index];
+            let attachments = Attachments {
+                pass: pass.name,
+                generation: pool.generation,
+                declared: &self.names[pass.reads.start..pass.writes.end],
+                resources: &self.resources,
+                views: &views[..self.resources.len()],
+            };
+            let start = Instant::now();
+            record(index, &mut encoder, &attachments);
+            timings.push((pass.name, start.elapsed()));
+        }
+        let start = Instant::now();
+        queue.submit(std::iter::once(encoder.finish()));
+        timings.push(("submit", start.elapsed()));
+        pool.finish_frame();
+        Ok(())
+    }
+}
+
+struct PooledTexture {
+    desc: TextureDesc,
+    // kept for the view
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    // the last step of this frame it's used in, none while unused
+    busy_until: Option<usize>,
+    // how many graphs in a row haven't used it
+    idle: u32,
+}
+
+// how many graphs in a row can leave a texture unused before it's dropped,
+// so graphs run in between frames, e.g. the minimap's, don't free them
+const IDLE_GRAPHS: u32 = 4;
+
+// the transient textures of past frames, kept around to be reused. ones
+// the last few graphs didn't use are dropped, e.g. after a resize
+#[derive(Default)]
+pub struct TexturePool {
+    textures: Vec<PooledTexture>,
+    // bumped whenever a texture is created or dropped, see `generation`
+    generation: u64,
+}
+
+impl TexturePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // changes whenever the textures do, so bind groups made from them can
+    // be kept until then
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // a texture matching `desc` that's free from step `first` to `last`
+    fn take(&mut self, device: &wgpu::Device, desc: TextureDesc, first: usize, last: usize) -> usize {
+        let free = self.textures.iter().position(|texture| {
+            texture.desc == desc && !matches!(texture.busy_until, Some(busy) if busy >= first)
+        });
+        if let Some(index) = free {
+            self.textures[index].busy_until = Some(last);
+            return index;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("transient_texture"),
+            size: wgpu::Extent3d { width: desc.width, height: desc.height, depth: 1 },
+            mip_level_count: 1,
+            sample_count: desc.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: desc.format,
+            usage: desc.usage,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.textures.push(PooledTexture { desc, _texture: texture, view, busy_until: Some(last), idle: 0 });
+        self.generation += 1;
+        self.textures.len() - 1
+    }
+
+    // drops what's been idle too long once a graph is submitted
+    fn finish_frame(&mut self) {
+        for texture in &mut self.textures {
+            texture.idle = if texture.busy_until.is_some() { 0 } else { texture.idle + 1 };
+            texture.busy_until = None;
+        }
+        let count = self.textures.len();
+        self.textures.retain(|texture| texture.idle < IDLE_GRAPHS);
+        if self.textures.len() != count {
+            self.generation += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desc(width: u32) -> TextureDesc {
+        TextureDesc {
+            width,
+            height: 16,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            sample_count: 1,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        }
+    }
+
+    fn graph(passes: &[(&'static str, &[Resource], &[Resource])]) -> RenderGraph<'static, ()> {
+        let mut graph = RenderGraph::new();
+        graph.imported("frame");
+        graph.external("shadows");
+        for (name, reads, writes) in passes {
+            graph.add(name, reads, writes, ());
+        }
+        graph
+    }
+
+    #[test]
+    fn runs_writers_before_readers() {
+        let mut graph = graph(&[
+            ("tonemap", &["hdr"], &["frame"]),
+            ("main", &["shadows"], &["hdr"]),
+            ("shadows", &[], &["shadows"]),
+        ]);
+        graph.transient("hdr", desc(16));
+        assert_eq!(graph.schedule().unwrap(), ["shadows", "main", "tonemap"]);
+    }
+
+    #[test]
+    fn keeps_writers_of_a_resource_in_order() {
+        let graph = graph(&[
+            ("main", &[], &["frame"]),
+            ("overlay", &["frame"], &["frame"]),
+            ("ui", &[], &["frame"]),
+        ]);
+        assert_eq!(graph.schedule().unwrap(), ["main", "overlay", "ui"]);
+    }
+
+    #[test]
+    fn culls_passes_with_unused_outputs() {
+        let mut graph = graph(&[
+            ("shadows", &[], &["shadows"]),
+            ("ssao", &[], &["occlusion"]),
+            ("main", &["shadows"], &["frame"]),
+        ]);
+        graph.transient("occlusion", desc(16));
+        assert_eq!(graph.schedule().unwrap(), ["shadows", "main"]);
+    }
+
+    #[test]
+    fn rejects_unknown_resources_and_cycles() {
+        let graph = graph(&[("main", &["missing"], &["frame"])]);
+        assert!(graph.schedule().is_err());
+
+        let mut graph = graph_with_cycle();
+        assert!(graph.compile().is_err());
+    }
+
+    fn graph_with_cycle() -> RenderGraph<'static, ()> {
+        let mut graph = graph(&[
+            ("a", &["y"], &["x"]),
+            ("b", &["x"], &["y"]),
+            ("main", &["x"], &["frame"]),
+        ]);
+        graph.transient("x", desc(16));
+        graph.transient("y", desc(16));
+        graph
+    }
+
+    #[test]
+    fn aliases_transients_that_dont_overlap() {
+        let mut graph = graph(&[
+            ("gbuffer", &[], &["albedo"]),
+            ("light", &["albedo"], &["hdr"]),
+            ("bloom", &["hdr"], &["bright"]),
+            ("tonemap", &["hdr", "bright"], &["frame"]),
+        ]);
+        graph.transient("albedo", desc(16));
+        graph.transient("hdr", desc(16));
+        graph.transient("bright", desc(16));
+        let order = graph.order().unwrap();
+        let aliases = graph.aliases(&order);
+        // albedo is done with by the time bloom writes, hdr is still read
+        assert_eq!(aliases["albedo"], aliases["bright"]);
+        assert_ne!(aliases["albedo"], aliases["hdr"]);
+    }
+
+    #[test]
+    fn only_aliases_matching_descriptions() {
+        let mut graph = graph(&[
+            ("a", &[], &["small"]),
+            ("b", &["small"], &["large"]),
+            ("c", &["large"], &["frame"]),
+        ]);
+        graph.transient("small", desc(16));
+        graph.transient("large", desc(32));
+        let order = graph.order().unwrap();
+        let aliases = graph.aliases(&order);
+        assert_ne!(aliases["small"], aliases["large"]);
+    }
+
+    #[test]
+    fn keeps_the_schedule_until_the_graph_changes() {
+        let mut graph = graph(&[("main", &[], &["frame"])]);
+        graph.compile().unwrap();
+        assert!(graph.schedule.is_some());
+        graph.add("ui", &[], &["frame"], ());
+        assert!(graph.schedule.is_none());
+    }
+}
@@ -0,0 +1,60 @@
+#version 450
+
+#define MAX_BLOBS 16
+
+struct Blob {
+  vec4 footprint;
+  vec4 height;
+};
+
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+  vec4 u_eye;
+};
+
+layout(set=1, binding=0)
+  uniform Ground {
+  vec4 g_plane;
+  vec4 g_color;
+  uvec4 g_count;
+  Blob g_blobs[MAX_BLOBS];
+};
+
+layout(set=2, binding=0) uniform Light {
+  vec3 l_position;
+  float l_exposure;
+  vec3 l_color;
+  float l_fog_density;
+  vec3 l_ambient;
+  vec3 l_fog_color;
+};
+
+layout(location=0) in vec3 v_position;
+layout(location=0) out vec4 f_color;
+
+// how much of the sky a model hides from this point, a rounded box around
+// its footprint blurred by its size and faded as it lifts off the ground
+float occlusion(Blob blob) {
+  vec2 q = abs(v_position.xz - blob.footprint.xy) - blob.footprint.zw;
+  float d = length(max(q, 0.0)) + min(max(q.x, q.y), 0.0);
+  float softness = 0.25 * max(blob.footprint.z, blob.footprint.w) + blob.height.x + 1e-4;
+  float lift = clamp(1.0 - blob.height.x / max(blob.height.y, 1e-4), 0.0, 1.0);
+  return (1.0 - smoothstep(-softness, softness, d)) * lift;
+}
+
+void main() {
+  float shadow = 0.0;
+  for (uint i = 0; i < g_count.x; i++) {
+    shadow = max(shadow, occlusion(g_blobs[i]));
+  }
+
+  vec3 light_dir = normalize(l_position - v_position);
+  float diffuse = max(light_dir.y, 0.0);
+  vec3 color = g_color.rgb * (l_ambient + diffuse) * l_color
+    * (1.0 - g_color.w * shadow) * l_exposure;
+  float fog = exp(-l_fog_density * distance(u_eye.xyz, v_position));
+  // fade out towards the edge instead of ending in a hard line
+  float edge = length(v_position.xz - g_plane.xz) / g_plane.w;
+  f_color = vec4(mix(l_fog_color, color, fog), 1.0 - smoothstep(0.5, 1.0, edge));
+}
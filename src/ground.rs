@@ -0,0 +1,209 @@
+use wgpu;
+use glam::Vec3;
+use crate::collision::Aabb;
+use crate::config::GroundConfig;
+use crate::lights::Lights;
+use crate::scene::Scene;
+use crate::texture::DepthTexture;
+
+// must match MAX_BLOBS in ground.frag
+pub const MAX_BLOBS: usize = 16;
+
+// the footprint of a model on the ground, shaded as a soft rounded box
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Blob {
+    // xz center, xz half extents
+    footprint: [f32; 4],
+    // x how far the model's bottom is above the ground, y its height
+    height: [f32; 4],
+}
+
+impl From<&Aabb> for Blob {
+    fn from(bounds: &Aabb) -> Self {
+        let center = bounds.center();
+        let extents = (bounds.max - bounds.min) * 0.5;
+        Self {
+            footprint: [center.x, center.z, extents.x, extents.z],
+            height: [0.0, bounds.max.y - bounds.min.y, 0.0, 0.0],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct GroundUniforms {
+    // xyz center of the plane, w its half size
+    plane: [f32; 4],
+    // rgb color, w shadow strength
+    color: [f32; 4],
+    count: [u32; 4],
+    blobs: [Blob; MAX_BLOBS],
+}
+
+// an analytic alternative to shadow mapping: a plane fitted under the
+// scene with a blurred blob below every model, drawn first in the main pass
+// so everything else is drawn over it
+pub struct Ground {
+    config: GroundConfig,
+    format: wgpu::TextureFormat,
+    layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+}
+
+impl Ground {
+    pub fn new(device: &wgpu::Device, config: &GroundConfig, format: wgpu::TextureFormat,
+               sample_count: u32) -> Self {
+        // same as the renderer's camera layout, so its bind group can be
+        // reused
+        let shared_layout = |label| device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label,
+            }
+        );
+        let camera_bind_group_layout = shared_layout(Some("ground_camera_bind_group_layout"));
+        let uniform_bind_group_layout = shared_layout(Some("ground_bind_group_layout"));
+        let light_bind_group_layout = Lights::create_bind_group_layout(device);
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ground_uniform_buffer"),
+            size: std::mem::size_of::<GroundUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+            }],
+            label: Some("ground_bind_group"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("ground_pipeline_layout"),
+            bind_group_layouts: &[
+                &camera_bind_group_layout, &uniform_bind_group_layout, &light_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &layout, format, sample_count);
+        Self { config: config.clone(), format, layout, pipeline, uniform_buffer, uniform_bind_group }
+    }
+
+    // rebuilds the pipeline for a main pass with `sample_count` samples
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.pipeline = create_pipeline(device, &self.layout, self.format, sample_count);
+    }
+
+    // fits the plane under the scene's models and uploads their footprints.
+    // scenes with more models than there are blobs get a single blob
+    // covering all of them
+    pub fn prepare(&self, queue: &wgpu::Queue, scene: &Scene) {
+        if !self.config.enabled {
+            return;
+        }
+        // instanced models cast no blobs
+        let models: Vec<Aabb> = scene.models.iter().enumerate()
+            .filter(|(index, _)| !scene.is_instanced(*index))
+            .map(|(_, model)| model.world_bounds())
+            .filter(|bounds| !bounds.is_empty())
+            .collect();
+        let mut bounds = Aabb::from_points(models.iter().flat_map(|bounds| {
+            std::iter::once(bounds.min).chain(std::iter::once(bounds.max))
+        }));
+        if bounds.is_empty() {
+            bounds = Aabb { min: Vec3::zero(), max: Vec3::zero() };
+        }
+        let center = bounds.center();
+        let size = bounds.max - bounds.min;
+        let half_size = (size.x.max(size.z) * 2.0).max(1.0);
+        let [r, g, b] = self.config.color;
+        let mut uniforms = GroundUniforms {
+            // just below the lowest point, so the models' bases don't z-fight
+            plane: [center.x, bounds.min.y - half_size * 1e-3, center.z, half_size],
+            color: [r, g, b, self.config.shadow.clamp(0.0, 1.0)],
+            count: [0; 4],
+            blobs: [bytemuck::Zeroable::zeroed(); MAX_BLOBS],
+        };
+        let count = models.len();
+        if count <= MAX_BLOBS {
+            for (blob, model) in uniforms.blobs.iter_mut().zip(&models) {
+                *blob = model.into();
+                blob.height[0] = model.min.y - bounds.min.y;
+            }
+            uniforms.count[0] = count as u32;
+        } else {
+            uniforms.blobs[0] = (&bounds).into();
+            uniforms.count[0] = 1;
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup,
+                    light_bind_group: &'a wgpu::BindGroup) {
+        if !self.config.enabled {
+            return;
+        }
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, camera_bind_group, &[]);
+        pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        pass.set_bind_group(2, light_bind_group, &[]);
+        pass.draw(0..4, 0..1);
+    }
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, format: wgpu::TextureFormat,
+                   sample_count: u32) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("ground.vert.spv"));
+    let fs_module = device.create_shader_module(wgpu::include_spirv!("ground.frag.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("ground_pipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        // the plane's edge fades into the background
+        color_states: &[wgpu::ColorStateDescriptor {
+            format,
+            color_blend: wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha_blend: wgpu::BlendDescriptor::REPLACE,
+            write_mask: wgpu::ColorWrite::ALL,
+        }],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleStrip,
+        // nothing of the scene is below the plane, and not writing depth
+        // keeps the faded edge from hiding what's behind it
+        depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+            format: DepthTexture::FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilStateDescriptor::default(),
+        }),
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[],
+        },
+        sample_count,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
@@ -0,0 +1,21 @@
+#version 450
+
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+  vec4 u_eye;
+};
+
+layout(set=1, binding=0)
+  uniform Ground {
+  vec4 g_plane;
+};
+
+layout(location=0) out vec3 v_position;
+
+// a triangle strip quad centered under the scene
+void main() {
+  vec2 corner = vec2(float(gl_VertexIndex & 1), float(gl_VertexIndex >> 1)) * 2.0 - 1.0;
+  v_position = g_plane.xyz + vec3(corner.x, 0.0, corner.y) * g_plane.w;
+  gl_Position = u_view_proj * vec4(v_position, 1.0);
+}
@@ -0,0 +1,337 @@
+use wgpu;
+use wgpu::util::DeviceExt;
+use glam::Vec3;
+use crate::collision::{Aabb, Triangle};
+use crate::config::DebugView;
+use crate::renderer::ModelTransforms;
+use crate::scene::Scene;
+use crate::texture::{DepthTexture, RenderTexture};
+use crate::vertex::Vertex;
+
+// one instance per triangle of the scene, expanded in the vertex shader
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TriangleInstance {
+    a: [f32; 3],
+    b: [f32; 3],
+    c: [f32; 3],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct HeatmapUniforms {
+    // the target's size in pixels
+    size: [f32; 4],
+}
+
+// the scene's triangles uploaded for the triangle density view
+struct Triangles {
+    scene_id: u64,
+    buffer: wgpu::Buffer,
+    count: u32,
+}
+
+// the textures sized after the target, recreated on resize
+struct Targets {
+    count: RenderTexture,
+    depth: DepthTexture,
+    bind_group: wgpu::BindGroup,
+}
+
+// diagnostic views of what the GPU spends its time on, drawn instead of
+// the shaded scene. the overdraw view adds up every fragment rasterized at
+// a pixel, hidden or not, then colors the count. the triangle density view
+// colors each visible triangle by its area on screen, where triangles of a
+// few pixels waste most of the 2x2 quads they're shaded in
+pub struct Heatmaps {
+    overdraw_pipeline: wgpu::RenderPipeline,
+    resolve_pipeline: wgpu::RenderPipeline,
+    triangle_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    targets: Targets,
+    triangles: Option<Triangles>,
+}
+
+// overdraw counts are accumulated in a single float channel
+const COUNT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
+impl Heatmaps {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        let uniform_layout = |label| device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label,
+            }
+        );
+        // same as the renderer's camera layout, so its bind group can be reused
+        let camera_bind_group_layout = uniform_layout(Some("heatmap_camera_bind_group_layout"));
+        let uniform_bind_group_layout = uniform_layout(Some("heatmap_uniform_bind_group_layout"));
+        let model_bind_group_layout = ModelTransforms::create_bind_group_layout(device);
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        multisampled: false,
+                        dimension: wgpu::TextureViewDimension::D2,
+                        component_type: wgpu::TextureComponentType::Float,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("heatmap_bind_group_layout"),
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("heatmap_uniform_buffer"),
+            contents: bytemuck::cast_slice(&[HeatmapUniforms { size: [width as f32, height as f32, 0.0, 0.0] }]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+            }],
+            label: Some("heatmap_uniform_bind_group"),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let pipeline = |label, layouts: &[&wgpu::BindGroupLayout], vs_module: &wgpu::ShaderModule,
+                        fs_module: &wgpu::ShaderModule, color_state: wgpu::ColorStateDescriptor,
+                        depth: bool, vertex_buffers: &[wgpu::VertexBufferDescriptor]| {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: layouts,
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex_stage: wgpu::ProgrammableStageDescriptor {
+                    module: vs_module,
+                    entry_point: "main",
+                },
+                fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                    module: fs_module,
+                    entry_point: "main",
+                }),
+                rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: if vertex_buffers.is_empty() { wgpu::CullMode::None } else { wgpu::CullMode::Back },
+                    ..Default::default()
+                }),
+                color_states: &[color_state],
+                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+                depth_stencil_state: if depth {
+                    Some(wgpu::DepthStencilStateDescriptor {
+                        format: DepthTexture::FORMAT,
+                        depth_write_enabled: true,
+                        depth_compare: wgpu::CompareFunction::Less,
+                        stencil: wgpu::StencilStateDescriptor::default(),
+                    })
+                } else {
+                    None
+                },
+                vertex_state: wgpu::VertexStateDescriptor {
+                    index_format: wgpu::IndexFormat::Uint32,
+                    vertex_buffers,
+                },
+                sample_count: 1,
+                sample_mask: !0,
+                alpha_to_coverage_enabled: false,
+            })
+        };
+
+        // only needs the model transforms, unlike `shader.vert` which reads
+        // the materials to displace vertices
+        let mesh_vs = device.create_shader_module(wgpu::include_spirv!("shadow.vert.spv"));
+        let overdraw_fs = device.create_shader_module(wgpu::include_spirv!("overdraw.frag.spv"));
+        let fullscreen_vs = device.create_shader_module(wgpu::include_spirv!("fullscreen.vert.spv"));
+        let resolve_fs = device.create_shader_module(wgpu::include_spirv!("overdraw_resolve.frag.spv"));
+        let triangle_vs = device.create_shader_module(wgpu::include_spirv!("triangle_density.vert.spv"));
+        let triangle_fs = device.create_shader_module(wgpu::include_spirv!("triangle_density.frag.spv"));
+        // every fragment adds one, without a depth test so hidden ones count
+        let additive = wgpu::BlendDescriptor {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let vertex_layout = Vertex::layout();
+        let overdraw_pipeline = pipeline(
+            "overdraw_pipeline", &[&model_bind_group_layout], &mesh_vs, &overdraw_fs,
+            wgpu::ColorStateDescriptor {
+                format: COUNT_FORMAT,
+                color_blend: additive.clone(),
+                alpha_blend: additive,
+                write_mask: wgpu::ColorWrite::ALL,
+            },
+            false,
+            &[vertex_layout.descriptor()],
+        );
+        let resolve_pipeline = pipeline(
+            "overdraw_resolve_pipeline", &[&bind_group_layout], &fullscreen_vs, &resolve_fs,
+            format.into(), false, &[],
+        );
+        let triangle_pipeline = pipeline(
+            "triangle_density_pipeline", &[&camera_bind_group_layout, &uniform_bind_group_layout],
+            &triangle_vs, &triangle_fs, format.into(), true,
+            &[wgpu::VertexBufferDescriptor {
+                stride: std::mem::size_of::<TriangleInstance>() as wgpu::BufferAddress,
+                step_mode: wgpu::InputStepMode::Instance,
+                attributes: &wgpu::vertex_attr_array![0 => Float3, 1 => Float3, 2 => Float3],
+            }],
+        );
+        let targets = create_targets(device, &bind_group_layout, &sampler, width, height);
+        Self {
+            overdraw_pipeline,
+            resolve_pipeline,
+            triangle_pipeline,
+            bind_group_layout,
+            sampler,
+            uniform_buffer,
+            uniform_bind_group,
+            targets,
+            triangles: None,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.targets = create_targets(device, &self.bind_group_layout, &self.sampler, width, height);
+        let uniforms = HeatmapUniforms { size: [width as f32, height as f32, 0.0, 0.0] };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    // uploads the scene's triangles the first time the triangle density
+    // view shows it
+    pub fn prepare(&mut self, device: &wgpu::Device, scene: &Scene, view: DebugView) {
+        if view != DebugView::TriangleDensity
+            || self.triangles.as_ref().map_or(false, |triangles| triangles.scene_id == scene.id) {
+            return;
+        }
+        let mut instances = vec![];
+        if let Some(bounds) = scene.collision.bounds() {
+            // grown a little so triangles lying on the bounds are included
+            let bounds = Aabb { min: bounds.min - Vec3::one(), max: bounds.max + Vec3::one() };
+            scene.collision.for_each_overlapping(&bounds, |triangle: &Triangle| {
+                instances.push(TriangleInstance {
+                    a: triangle.a.into(),
+                    b: triangle.b.into(),
+                    c: triangle.c.into(),
+                });
+            });
+        }
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("triangle_density_buffer"),
+            contents: bytemuck::cast_slice(&instances),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
+        self.triangles = Some(Triangles { scene_id: scene.id, buffer, count: instances.len() as u32 });
+    }
+
+    // draws `view` of the scene into `output`, which has to be the size
+    // given to `new` or `resize`
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, scene: &Scene, view: DebugView,
+                  output: &wgpu::TextureView, camera_bind_group: &wgpu::BindGroup,
+                  model_bind_group: &wgpu::BindGroup) {
+        let clear = |attachment| wgpu::RenderPassColorAttachmentDescriptor {
+            attachment,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: true,
+            },
+        };
+        if view == DebugView::Overdraw {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[clear(&self.targets.count.view)],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(&self.overdraw_pipeline);
+            // instanced models aren't counted, and shadow proxies aren't seen
+            for draw in &scene.draws {
+                if scene.is_instanced(draw.model) || !scene.models[draw.model].is_drawn() {
+                    continue;
+                }
+                let mesh = &scene.models[draw.model].mesh;
+                pass.set_bind_group(0, model_bind_group, &[ModelTransforms::offset(draw.model)]);
+                pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                pass.set_index_buffer(mesh.index_buffer.slice(..));
+                pass.draw_indexed(draw.indices.clone(), 0, 0..1);
+            }
+            drop(pass);
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[clear(output)],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(&self.resolve_pipeline);
+            pass.set_bind_group(0, &self.targets.bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        } else if let Some(triangles) = self.triangles.as_ref().filter(|_| view == DebugView::TriangleDensity) {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[clear(output)],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                    attachment: &self.targets.depth.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            pass.set_pipeline(&self.triangle_pipeline);
+            pass.set_bind_group(0, camera_bind_group, &[]);
+            pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+            pass.set_vertex_buffer(0, triangles.buffer.slice(..));
+            pass.draw(0..3, 0..triangles.count);
+        }
+    }
+}
+
+fn create_targets(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, sampler: &wgpu::Sampler,
+                  width: u32, height: u32) -> Targets {
+    let count = RenderTexture::with_size(device, width, height, COUNT_FORMAT, Some("overdraw_count"));
+    let depth = DepthTexture::with_size(device, width, height, Some("heatmap_depth"));
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&count.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+        label: Some("heatmap_bind_group"),
+    });
+    Targets { count, depth, bind_group }
+}
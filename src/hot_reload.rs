@@ -0,0 +1,187 @@
+use crate::config::Config;
+use crate::renderer::{self, Renderer};
+use crate::shader_cache::ShaderCache;
+use crate::state::State;
+use anyhow::{anyhow, Context, Result};
+use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+// stops watching when dropped. editors save in bursts, one reload per
+// burst is enough
+fn watch(dir: &Path) -> Result<(RecommendedWatcher, Receiver<DebouncedEvent>)> {
+    let (sender, events) = channel();
+    let mut watcher = watcher(sender, Duration::from_millis(200))?;
+    watcher.watch(dir, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", dir.display()))?;
+    Ok((watcher, events))
+}
+
+// queues the files created, written or renamed into place since the last
+// call, once each
+fn collect(events: &Receiver<DebouncedEvent>, pending: &mut VecDeque<PathBuf>) {
+    while let Ok(event) = events.try_recv() {
+        match event {
+            DebouncedEvent::Create(path) | DebouncedEvent::Write(path) | DebouncedEvent::Rename(_, path)
+                if !pending.contains(&path) => pending.push_back(path),
+            _ => {}
+        }
+    }
+}
+
+// watches a directory of glsl shaders and recompiles the ones that change,
+// the way build.rs does, so the mesh shaders can be edited while the
+// viewer runs. shaders of the other passes are only picked up by a rebuild
+pub struct ShaderWatcher {
+    dir: PathBuf,
+    // stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    pending: VecDeque<PathBuf>,
+    compiler: shaderc::Compiler,
+    cache: Option<ShaderCache>,
+}
+
+impl ShaderWatcher {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let (watcher, events) = watch(dir.as_ref())?;
+        let compiler = shaderc::Compiler::new().context("Unable to create shader compiler")?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            _watcher: watcher,
+            events,
+            pending: VecDeque::new(),
+            compiler,
+            cache: None,
+        })
+    }
+
+    // compiled shaders are looked up in and added to `cache`
+    pub fn set_cache(&mut self, cache: ShaderCache) {
+        self.cache = Some(cache);
+    }
+
+    pub fn cache(&self) -> Option<&ShaderCache> {
+        self.cache.as_ref()
+    }
+
+    // queues the mesh shaders as they are in the directory now for the next
+    // poll, so edits made while the viewer wasn't running are picked up
+    // without a rebuild. with a cache only the changed ones are compiled
+    pub fn queue_current(&mut self) {
+        for name in renderer::RELOADABLE_SHADERS {
+            let path = self.dir.join(name);
+            if path.is_file() && !self.pending.contains(&path) {
+                self.pending.push_back(path);
+            }
+        }
+    }
+
+    // recompiles the shaders written since the last call and hands them to
+    // the renderer, returning the ones it reloaded. a shader that fails to
+    // compile is reported and the renderer keeps the last working one, the
+    // rest are left for the next call
+    pub fn poll(&mut self, renderer: &mut Renderer) -> Result<Vec<PathBuf>> {
+        let mut reloaded = vec![];
+        collect(&self.events, &mut self.pending);
+        while let Some(path) = self.pending.pop_front() {
+            let kind = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("vert") => shaderc::ShaderKind::Vertex,
+                Some("frag") => shaderc::ShaderKind::Fragment,
+                Some("comp") => shaderc::ShaderKind::Compute,
+                _ => continue,
+            };
+            let name = path.file_name().and_then(|name| name.to_str())
+                .ok_or_else(|| anyhow!("Invalid shader path {}", path.display()))?
+                .to_string();
+            let source = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let compiler = &mut self.compiler;
+            let compile = || {
+                let compiled = compiler.compile_into_spirv(&source, kind, &name, "main", None)
+                    .with_context(|| format!("Failed to compile {}", name))?;
+                Ok(compiled.as_binary().to_vec())
+            };
+            let spirv = match &mut self.cache {
+                Some(cache) => cache.get_or_compile(&name, &source, compile)?,
+                None => compile()?,
+            };
+            if renderer.reload_shader(&name, &spirv)? {
+                reloaded.push(path);
+            }
+        }
+        Ok(reloaded)
+    }
+}
+
+// watches the directory models and textures are loaded from, so a scene
+// follows their edits without restarting the viewer
+pub struct AssetWatcher {
+    // stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    pending: VecDeque<PathBuf>,
+}
+
+impl AssetWatcher {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let (watcher, events) = watch(dir.as_ref())?;
+        Ok(Self { _watcher: watcher, events, pending: VecDeque::new() })
+    }
+
+    // hands the files written since the last call to the viewer's scene,
+    // returning the ones it reloaded. a file that fails to reload is
+    // reported and the scene keeps what it had, the rest are left for the
+    // next call. embedders can pass the paths to `Scene::reload_file`
+    pub fn poll(&mut self, state: &mut State) -> Result<Vec<PathBuf>> {
+        let mut reloaded = vec![];
+        collect(&self.events, &mut self.pending);
+        while let Some(path) = self.pending.pop_front() {
+            if state.reload_file(&path)
+                .with_context(|| format!("Failed to reload {}", path.display()))? {
+                reloaded.push(path);
+            }
+        }
+        Ok(reloaded)
+    }
+}
+
+// watches `wr3d.toml` and reads it again whenever it's saved, so the
+// viewer can be tuned without restarting, see `State::apply_config`.
+// editors often save by renaming a new file into place, so it's the
+// directory that's watched
+pub struct ConfigWatcher {
+    path: PathBuf,
+    // stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    pending: VecDeque<PathBuf>,
+}
+
+impl ConfigWatcher {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let (watcher, events) = watch(&dir)?;
+        Ok(Self { path, _watcher: watcher, events, pending: VecDeque::new() })
+    }
+
+    // applies the file to the viewer if it was written since the last call,
+    // returning whether it was. an invalid file is reported and nothing of
+    // it is applied
+    pub fn poll(&mut self, state: &mut State) -> Result<bool> {
+        collect(&self.events, &mut self.pending);
+        let name = self.path.file_name();
+        let changed = self.pending.iter().any(|path| path.file_name() == name);
+        self.pending.clear();
+        if changed {
+            state.apply_config(&Config::load(&self.path)?)?;
+        }
+        Ok(changed)
+    }
+}
@@ -0,0 +1,646 @@
+use wgpu;
+use wgpu::util::DeviceExt;
+use anyhow::{Context, Result};
+use bytemuck::Zeroable;
+use glam::Vec3;
+use crate::caps::Capabilities;
+use crate::config::EnvironmentConfig;
+use crate::texture::Texture;
+
+// half floats, the panorama is brighter than 1 once blurred highlights add up
+pub(crate) const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// diffuse light changes slowly with the direction, a small map is enough
+const IRRADIANCE_WIDTH: u32 = 64;
+const LUT_SIZE: u32 = 128;
+// one level per roughness step, 0 at the top and 1 at the bottom
+const SPECULAR_LEVELS: u32 = 6;
+
+// bound with the lights at set 3 binding 7
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentUniforms {
+    intensity: f32,
+    // 0 falls back to the lighting's constant ambient
+    enabled: u32,
+    // in the specular map, the last is fully rough
+    levels: f32,
+    // added to the maps' horizontal coordinate, see `panorama_offset`
+    offset: f32,
+}
+
+// where the captured environment applies, bound with the lights at set 3
+// binding 14
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ProbeUniforms {
+    position: [f32; 3],
+    radius: f32,
+    // 0 until something is captured
+    enabled: u32,
+    _padding: [u32; 3],
+}
+
+// the camera of each face a capture is drawn with and the point they
+// share, for unwrapping the faces into a panorama
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct UnwrapUniforms {
+    faces: [[[f32; 4]; 4]; 6],
+    position: [f32; 4],
+}
+
+// the roughness one level of the specular map is blurred for
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PrefilterUniforms {
+    roughness: f32,
+    _padding: [f32; 3],
+}
+
+// the ambient light of a panorama, split the usual way: an irradiance map
+// for diffuse, the panorama blurred for increasing roughness down a mip
+// chain for specular, and a lookup table of how much of the specular
+// color each view angle and roughness reflects. all three are equirect
+// like the skybox and computed once, on the GPU, when the renderer starts
+pub(crate) struct Environment {
+    uniform_buffer: wgpu::Buffer,
+    enabled: bool,
+    intensity: f32,
+    // degrees around the up axis
+    rotation: f32,
+    // kept alive for the views
+    _irradiance: wgpu::Texture,
+    irradiance_view: wgpu::TextureView,
+    _specular: wgpu::Texture,
+    specular_view: wgpu::TextureView,
+    _lut: wgpu::Texture,
+    lut_view: wgpu::TextureView,
+    // whether the lookup table was filled, it's a single texel without a
+    // panorama until the first capture
+    lut_ready: bool,
+    sampler: wgpu::Sampler,
+    probe: Probe,
+    probe_buffer: wgpu::Buffer,
+    // cube faces drawn this wide, and how far from the point of the capture
+    // surfaces reflect it
+    probe_resolution: u32,
+    probe_radius: f32,
+    width: u32,
+    supported: bool,
+}
+
+// the maps of a capture, the same kind as the panorama's. single texels
+// until there is one
+struct Probe {
+    _irradiance: wgpu::Texture,
+    irradiance_view: wgpu::TextureView,
+    _specular: wgpu::Texture,
+    specular_view: wgpu::TextureView,
+}
+
+impl Probe {
+    fn new(irradiance: wgpu::Texture, specular: wgpu::Texture) -> Self {
+        Self {
+            irradiance_view: irradiance.create_view(&wgpu::TextureViewDescriptor::default()),
+            _irradiance: irradiance,
+            specular_view: specular.create_view(&wgpu::TextureViewDescriptor::default()),
+            _specular: specular,
+        }
+    }
+}
+
+impl Environment {
+    // without a panorama, when it's off or when the device can't run the
+    // compute shaders building the maps, the maps are single texels the
+    // shader never reads and the lighting's constant ambient is used
+    pub(crate) fn new(device: &wgpu::Device, queue: &wgpu::Queue, config: &EnvironmentConfig,
+                      skybox: Option<&str>, caps: &Capabilities) -> Result<Self> {
+        let path = config.panorama.as_deref().or(skybox)
+            .filter(|_| config.enabled && caps.storage_textures());
+        // small enough a panorama and the blurriest levels would be gone
+        let width = config.resolution.max(64);
+        let (irradiance, specular, lut) = match path {
+            Some(_) => (
+                create_texture(device, "irradiance_texture", IRRADIANCE_WIDTH, IRRADIANCE_WIDTH / 2, 1),
+                create_texture(device, "specular_texture", width, width / 2, SPECULAR_LEVELS),
+                create_texture(device, "brdf_lut_texture", LUT_SIZE, LUT_SIZE, 1),
+            ),
+            None => (
+                create_texture(device, "irradiance_texture", 1, 1, 1),
+                create_texture(device, "specular_texture", 1, 1, 1),
+                create_texture(device, "brdf_lut_texture", 1, 1, 1),
+            ),
+        };
+        if let Some(path) = path {
+            let panorama = Texture::new(
+                device, queue, path, wgpu::TextureFormat::Rgba8UnormSrgb, Some("environment_panorama"))
+                .with_context(|| format!("Failed to load the environment {}", path))?;
+            convolve(device, queue, &panorama.view, &irradiance, (&specular, width), Some(&lut));
+        }
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("environment_uniform_buffer"),
+            size: std::mem::size_of::<EnvironmentUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let probe_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("probe_uniform_buffer"),
+            contents: bytemuck::bytes_of(&ProbeUniforms::zeroed()),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let probe = Probe::new(create_texture(device, "probe_irradiance_texture", 1, 1, 1),
+                               create_texture(device, "probe_specular_texture", 1, 1, 1));
+        let environment = Self {
+            uniform_buffer,
+            enabled: path.is_some(),
+            intensity: config.intensity.max(0.0),
+            rotation: config.rotation,
+            irradiance_view: irradiance.create_view(&wgpu::TextureViewDescriptor::default()),
+            _irradiance: irradiance,
+            specular_view: specular.create_view(&wgpu::TextureViewDescriptor::default()),
+            _specular: specular,
+            lut_view: lut.create_view(&wgpu::TextureViewDescriptor::default()),
+            _lut: lut,
+            lut_ready: path.is_some(),
+            sampler: create_panorama_sampler(device, "environment_sampler"),
+            probe,
+            probe_buffer,
+            probe_resolution: config.probe_resolution.max(16),
+            probe_radius: config.probe_radius.max(0.0),
+            width,
+            supported: caps.storage_textures(),
+        };
+        environment.write_uniforms(queue);
+        Ok(environment)
+    }
+
+    pub(crate) fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub(crate) fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    // scales the light from the panorama, captures aren't
+    pub(crate) fn set_intensity(&mut self, queue: &wgpu::Queue, intensity: f32) {
+        self.intensity = intensity.max(0.0);
+        self.write_uniforms(queue);
+    }
+
+    // turns the panorama `degrees` around the up axis
+    pub(crate) fn set_rotation(&mut self, queue: &wgpu::Queue, degrees: f32) {
+        self.rotation = degrees;
+        self.write_uniforms(queue);
+    }
+
+    fn write_uniforms(&self, queue: &wgpu::Queue) {
+        let uniforms = EnvironmentUniforms {
+            intensity: self.intensity,
+            enabled: self.enabled as u32,
+            levels: SPECULAR_LEVELS as f32,
+            offset: panorama_offset(self.rotation),
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    // whether the device can build maps from a capture
+    pub(crate) fn can_capture(&self) -> bool {
+        self.supported
+    }
+
+    // how wide the cube faces of a capture are drawn
+    pub(crate) fn probe_resolution(&self) -> u32 {
+        self.probe_resolution
+    }
+
+    // unwraps the six faces drawn at `position`, layers of `faces` seen
+    // through the `matrices` in the order of the point shadows' faces, into
+    // a panorama and blurs it into the probe's maps, replacing the last
+    // capture. the views change, so the light bind groups need rebinding
+    pub(crate) fn capture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, faces: &wgpu::Texture,
+                          matrices: [[[f32; 4]; 4]; 6], position: Vec3) {
+        let width = self.width;
+        let panorama = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("probe_panorama"),
+            size: wgpu::Extent3d { width: self.probe_resolution * 4, height: self.probe_resolution * 2, depth: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        let panorama_view = panorama.create_view(&wgpu::TextureViewDescriptor::default());
+        let uniforms = UnwrapUniforms {
+            faces: matrices,
+            position: position.extend(1.0).into(),
+        };
+        unwrap(device, queue, faces, &panorama_view, &uniforms);
+
+        let irradiance = create_texture(device, "probe_irradiance_texture", IRRADIANCE_WIDTH, IRRADIANCE_WIDTH / 2, 1);
+        let specular = create_texture(device, "probe_specular_texture", width, width / 2, SPECULAR_LEVELS);
+        let lut = if self.lut_ready {
+            None
+        } else {
+            Some(create_texture(device, "brdf_lut_texture", LUT_SIZE, LUT_SIZE, 1))
+        };
+        convolve(device, queue, &panorama_view, &irradiance, (&specular, width), lut.as_ref());
+        if let Some(lut) = lut {
+            self.lut_view = lut.create_view(&wgpu::TextureViewDescriptor::default());
+            self._lut = lut;
+            self.lut_ready = true;
+        }
+        self.probe = Probe::new(irradiance, specular);
+        let uniforms = ProbeUniforms {
+            position: position.into(),
+            radius: self.probe_radius,
+            enabled: 1,
+            ..ProbeUniforms::zeroed()
+        };
+        queue.write_buffer(&self.probe_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    // the uniforms, the three maps and their sampler, in the order of the
+    // light bind group's bindings 7 to 11, then the capture's uniforms and
+    // maps at 14 to 16
+    pub(crate) fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 8] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: wgpu::BindingResource::Buffer(self.uniform_buffer.slice(..)),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::TextureView(&self.irradiance_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: wgpu::BindingResource::TextureView(&self.specular_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: wgpu::BindingResource::TextureView(&self.lut_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 11,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 14,
+                resource: wgpu::BindingResource::Buffer(self.probe_buffer.slice(..)),
+            },
+            wgpu::BindGroupEntry {
+                binding: 15,
+                resource: wgpu::BindingResource::TextureView(&self.probe.irradiance_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 16,
+                resource: wgpu::BindingResource::TextureView(&self.probe.specular_view),
+            },
+        ]
+    }
+}
+
+// what turning a panorama `degrees` counterclockwise around the up axis
+// adds to the horizontal coordinate its directions are looked up at. the
+// samplers repeat horizontally, so it doesn't need wrapping
+pub(crate) fn panorama_offset(degrees: f32) -> f32 {
+    degrees / 360.0
+}
+
+fn create_texture(device: &wgpu::Device, label: &str, width: u32, height: u32, levels: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d { width, height, depth: 1 },
+        mip_level_count: levels,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsage::STORAGE | wgpu::TextureUsage::SAMPLED,
+    })
+}
+
+// wraps around horizontally like the skybox's
+fn create_panorama_sampler(device: &wgpu::Device, label: &str) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some(label),
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+// one mip level of a map, for writing from a compute shader
+fn storage_view(texture: &wgpu::Texture, level: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("environment_storage_view"),
+        base_mip_level: level,
+        level_count: std::num::NonZeroU32::new(1),
+        ..Default::default()
+    })
+}
+
+fn create_bind_group_layout(device: &wgpu::Device, label: &str, panorama: bool,
+                            prefilter: bool) -> wgpu::BindGroupLayout {
+    let mut entries = vec![];
+    if panorama {
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStage::COMPUTE,
+            ty: wgpu::BindingType::SampledTexture {
+                multisampled: false,
+                dimension: wgpu::TextureViewDimension::D2,
+                component_type: wgpu::TextureComponentType::Float,
+            },
+            count: None,
+        });
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStage::COMPUTE,
+            ty: wgpu::BindingType::Sampler {
+                comparison: false,
+            },
+            count: None,
+        });
+    }
+    entries.push(wgpu::BindGroupLayoutEntry {
+        binding: if panorama { 2 } else { 0 },
+        visibility: wgpu::ShaderStage::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            dimension: wgpu::TextureViewDimension::D2,
+            format: FORMAT,
+            readonly: false,
+        },
+        count: None,
+    });
+    if prefilter {
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: 3,
+            visibility: wgpu::ShaderStage::COMPUTE,
+            ty: wgpu::BindingType::UniformBuffer {
+                dynamic: false,
+                min_binding_size: None,
+            },
+            count: None,
+        });
+    }
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &entries,
+        label: Some(label),
+    })
+}
+
+fn create_pipeline(device: &wgpu::Device, label: &str, bind_group_layout: &wgpu::BindGroupLayout,
+                   module: &wgpu::ShaderModule) -> wgpu::ComputePipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(label),
+        layout: Some(&layout),
+        compute_stage: wgpu::ProgrammableStageDescriptor {
+            module,
+            entry_point: "main",
+        },
+    })
+}
+
+// the compute shaders run in 8x8 groups
+fn dispatch(pass: &mut wgpu::ComputePass, width: u32, height: u32) {
+    pass.dispatch(width.div_ceil(8), height.div_ceil(8), 1);
+}
+
+// fills the irradiance map, every level of the specular map and the lookup
+// table, unless it's already filled, from the panorama, in one submission
+fn convolve(device: &wgpu::Device, queue: &wgpu::Queue, panorama: &wgpu::TextureView, irradiance: &wgpu::Texture,
+            (specular, width): (&wgpu::Texture, u32), lut: Option<&wgpu::Texture>) {
+    let irradiance_layout = create_bind_group_layout(device, "irradiance_bind_group_layout", true, false);
+    let prefilter_layout = create_bind_group_layout(device, "prefilter_bind_group_layout", true, true);
+    let lut_layout = create_bind_group_layout(device, "brdf_lut_bind_group_layout", false, false);
+    let irradiance_pipeline = create_pipeline(
+        device, "irradiance_pipeline", &irradiance_layout,
+        &device.create_shader_module(wgpu::include_spirv!("irradiance.comp.spv")));
+    let prefilter_pipeline = create_pipeline(
+        device, "prefilter_pipeline", &prefilter_layout,
+        &device.create_shader_module(wgpu::include_spirv!("prefilter.comp.spv")));
+    let lut_pipeline = create_pipeline(
+        device, "brdf_lut_pipeline", &lut_layout,
+        &device.create_shader_module(wgpu::include_spirv!("brdf_lut.comp.spv")));
+    let sampler = create_panorama_sampler(device, "panorama_sampler");
+
+    let irradiance_view = storage_view(irradiance, 0);
+    let irradiance_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &irradiance_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(panorama),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&irradiance_view),
+            },
+        ],
+        label: Some("irradiance_bind_group"),
+    });
+    // the buffers and views have to outlive the bind groups' use below
+    let levels: Vec<(wgpu::TextureView, wgpu::Buffer)> = (0..SPECULAR_LEVELS).map(|level| {
+        let uniforms = PrefilterUniforms {
+            roughness: level as f32 / (SPECULAR_LEVELS - 1) as f32,
+            ..PrefilterUniforms::zeroed()
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("prefilter_uniform_buffer"),
+            contents: bytemuck::bytes_of(&uniforms),
+            usage: wgpu::BufferUsage::UNIFORM,
+        });
+        (storage_view(specular, level), buffer)
+    }).collect();
+    let prefilter_bind_groups: Vec<wgpu::BindGroup> = levels.iter().map(|(view, buffer)| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &prefilter_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(panorama),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Buffer(buffer.slice(..)),
+                },
+            ],
+            label: Some("prefilter_bind_group"),
+        })
+    }).collect();
+    let lut_view = lut.map(|lut| storage_view(lut, 0));
+    let lut_bind_group = lut_view.as_ref().map(|view| device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &lut_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(view),
+        }],
+        label: Some("brdf_lut_bind_group"),
+    }));
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("environment_encoder"),
+    });
+    {
+        let mut pass = encoder.begin_compute_pass();
+        pass.set_pipeline(&irradiance_pipeline);
+        pass.set_bind_group(0, &irradiance_bind_group, &[]);
+        dispatch(&mut pass, IRRADIANCE_WIDTH, IRRADIANCE_WIDTH / 2);
+        pass.set_pipeline(&prefilter_pipeline);
+        for (level, bind_group) in prefilter_bind_groups.iter().enumerate() {
+            pass.set_bind_group(0, bind_group, &[]);
+            dispatch(&mut pass, width >> level, (width / 2) >> level);
+        }
+        if let Some(lut_bind_group) = &lut_bind_group {
+            pass.set_pipeline(&lut_pipeline);
+            pass.set_bind_group(0, lut_bind_group, &[]);
+            dispatch(&mut pass, LUT_SIZE, LUT_SIZE);
+        }
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+}
+
+// draws the panorama around a capture's point from its cube faces
+fn unwrap(device: &wgpu::Device, queue: &wgpu::Queue, faces: &wgpu::Texture, panorama: &wgpu::TextureView,
+          uniforms: &UnwrapUniforms) {
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::SampledTexture {
+                    multisampled: false,
+                    dimension: wgpu::TextureViewDimension::D2Array,
+                    component_type: wgpu::TextureComponentType::Float,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Sampler {
+                    comparison: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::UniformBuffer {
+                    dynamic: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+        label: Some("unwrap_bind_group_layout"),
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("unwrap_pipeline_layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("fullscreen.vert.spv"));
+    let fs_module = device.create_shader_module(wgpu::include_spirv!("unwrap.frag.spv"));
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("unwrap_pipeline"),
+        layout: Some(&layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        color_states: &[FORMAT.into()],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        depth_stencil_state: None,
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[],
+        },
+        sample_count: 1,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    });
+    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("unwrap_uniform_buffer"),
+        contents: bytemuck::bytes_of(uniforms),
+        usage: wgpu::BufferUsage::UNIFORM,
+    });
+    let faces_view = faces.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+    // the faces' edges meet, clamping keeps them from wrapping around
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("unwrap_sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&faces_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+            },
+        ],
+        label: Some("unwrap_bind_group"),
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("unwrap_encoder"),
+    });
+    {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: panorama,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+}
@@ -0,0 +1,126 @@
+use glam::Vec3;
+use serde::Deserialize;
+use crate::collision::Aabb;
+
+// the axis pointing up in a model's file, turned onto the viewer's y
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+// fixes for files whose tools disagree with the viewer's conventions,
+// applied to the vertices as they're loaded. the defaults leave them as
+// they are
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ImportOptions {
+    // turns every triangle around, for faces culled from the front
+    pub flip_winding: bool,
+    // points the file's normals the other way, for surfaces lit from behind
+    pub flip_normals: bool,
+    // mirrors the texture coordinates vertically, for upside down textures
+    pub flip_v: bool,
+    // world units per unit of the file, e.g. 0.01 for centimeters
+    pub scale: f32,
+    // scales models far too small or large by a power of ten on top of
+    // `scale`, see `fitted`
+    pub auto_scale: bool,
+    pub up_axis: UpAxis,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            flip_winding: false,
+            flip_normals: false,
+            flip_v: false,
+            scale: 1.0,
+            auto_scale: false,
+            up_axis: UpAxis::Y,
+        }
+    }
+}
+
+impl ImportOptions {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // the options a model with `bounds`, in the file's units, is loaded
+    // with. with `auto_scale`, one whose largest side comes out below 0.1
+    // or above 100 units is scaled by the power of ten that brings it
+    // between 1 and 10, so millimeter CAD parts and kilometer terrain both
+    // fit the default camera and stay a round unit conversion away from
+    // the file
+    pub fn fitted(&self, bounds: &Aabb) -> Self {
+        let mut fitted = Self { auto_scale: false, ..*self };
+        if !self.auto_scale || bounds.is_empty() {
+            return fitted;
+        }
+        let size = (bounds.max - bounds.min).max_element() * self.scale;
+        if size > 0.0 && !(0.1..=100.0).contains(&size) {
+            fitted.scale *= 10.0f32.powi(-(size.log10().floor() as i32));
+        }
+        fitted
+    }
+
+    // a direction from the file's axes onto the viewer's, a rotation so
+    // windings stay as they are
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        match self.up_axis {
+            UpAxis::Y => v,
+            UpAxis::Z => Vec3::new(v.x, v.z, -v.y),
+        }
+    }
+
+    pub fn position(&self, p: Vec3) -> Vec3 {
+        self.rotate(p) * self.scale
+    }
+
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        if self.flip_normals { -self.rotate(n) } else { self.rotate(n) }
+    }
+
+    pub fn tex_coord(&self, [u, v]: [f32; 2]) -> [f32; 2] {
+        if self.flip_v { [u, 1.0 - v] } else { [u, v] }
+    }
+
+    // flipping v or the normal mirrors the bitangent, which w's sign
+    // turns back
+    pub fn tangent(&self, [x, y, z, w]: [f32; 4]) -> [f32; 4] {
+        let t = self.rotate(Vec3::new(x, y, z));
+        let w = if self.flip_v != self.flip_normals { -w } else { w };
+        [t.x, t.y, t.z, w]
+    }
+
+    // swaps the last two corners of every triangle when flipping windings
+    pub fn triangles(&self, indices: &mut [u32]) {
+        if self.flip_winding {
+            for face in indices.chunks_exact_mut(3) {
+                face.swap(1, 2);
+            }
+        }
+    }
+
+    // applies the options to a part of an obj file before it's built into
+    // a mesh. normals generated for it follow the flipped windings
+    pub(crate) fn apply_to_obj(&self, mesh: &mut tobj::Mesh) {
+        if self.is_identity() {
+            return;
+        }
+        for p in mesh.positions.chunks_exact_mut(3) {
+            let position = self.position(Vec3::new(p[0], p[1], p[2]));
+            p.copy_from_slice(&[position.x, position.y, position.z]);
+        }
+        for n in mesh.normals.chunks_exact_mut(3) {
+            let normal = self.normal(Vec3::new(n[0], n[1], n[2]));
+            n.copy_from_slice(&[normal.x, normal.y, normal.z]);
+        }
+        for t in mesh.texcoords.chunks_exact_mut(2) {
+            t.copy_from_slice(&self.tex_coord([t[0], t[1]]));
+        }
+        self.triangles(&mut mesh.indices);
+    }
+}
@@ -0,0 +1,17 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=1) flat in uint v_layer;
+layout(location=0) out vec4 f_color;
+
+layout(set=1, binding=0) uniform texture2DArray t_atlas;
+layout(set=1, binding=1) uniform sampler s_atlas;
+
+void main() {
+  vec4 color = texture(sampler2DArray(t_atlas, s_atlas), vec3(v_tex_coord, float(v_layer)));
+  // sprites write depth, so cut them out instead of blending
+  if (color.a < 0.5) {
+    discard;
+  }
+  f_color = vec4(color.rgb, 1.0);
+}
@@ -0,0 +1,377 @@
+use wgpu;
+use wgpu::util::DeviceExt;
+use glam::{Mat4, Vec3};
+use crate::camera::Camera;
+use crate::config::ImposterConfig;
+use crate::renderer::{ModelTransforms, Uniforms};
+use crate::scene::Scene;
+use crate::texture::DepthTexture;
+
+// one sprite per model, instanced from a buffer rewritten every frame
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Instance {
+    center: [f32; 3],
+    right: [f32; 3],
+    up: [f32; 3],
+    layer: u32,
+}
+
+// a model's layer of the atlas and the camera used to bake it
+struct Slot {
+    view: wgpu::TextureView,
+    uniform_buffer: wgpu::Buffer,
+    // the view direction the sprite was baked from
+    baked: Option<Vec3>,
+}
+
+// what baking borrows from the renderer's main pass
+pub(crate) struct MainPass<'a> {
+    pub pipeline: &'a wgpu::RenderPipeline,
+    pub transforms: &'a ModelTransforms,
+    pub light_bind_group: &'a wgpu::BindGroup,
+}
+
+// the atlas is an array texture with a layer per model of the scene
+struct Atlas {
+    _texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+    slots: Vec<Slot>,
+    instance_buffer: wgpu::Buffer,
+}
+
+// draws distant models as camera-facing sprites, for scenes with many
+// scattered objects. sprites are baked with the main pipeline into an
+// atlas and rebaked when the view direction has changed enough
+pub struct Imposters {
+    config: ImposterConfig,
+    format: wgpu::TextureFormat,
+    layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    // the main pipeline's set 0, the bake camera next to the model matrices
+    model_bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    depth_texture: DepthTexture,
+    atlas: Option<Atlas>,
+    scene_id: Option<u64>,
+    // whether each model is drawn as a sprite this frame
+    active: Vec<bool>,
+    instances: Vec<Instance>,
+}
+
+// the sprite's axes for a model seen along `direction`, matching the
+// orientation `Mat4::look_at_rh` gives the baked image
+fn sprite_axes(direction: Vec3) -> (Vec3, Vec3) {
+    let up = if direction.y.abs() > 0.99 { Vec3::unit_z() } else { Vec3::unit_y() };
+    let right = direction.cross(up).normalize();
+    (right, right.cross(direction))
+}
+
+impl Imposters {
+    pub fn new(device: &wgpu::Device, config: &ImposterConfig, format: wgpu::TextureFormat,
+               sample_count: u32) -> Self {
+        let uniform_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer {
+                            dynamic: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("imposter_uniform_bind_group_layout"),
+            }
+        );
+        let bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::SampledTexture {
+                            multisampled: false,
+                            dimension: wgpu::TextureViewDimension::D2Array,
+                            component_type: wgpu::TextureComponentType::Float,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("imposter_bind_group_layout"),
+            }
+        );
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("imposter_pipeline_layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &layout, format, sample_count);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let depth_texture = DepthTexture::with_size(
+            device, config.resolution, config.resolution, Some("imposter_depth_texture"));
+        Self {
+            config: config.clone(),
+            format,
+            layout,
+            pipeline,
+            bind_group_layout,
+            model_bind_group_layout: ModelTransforms::create_bind_group_layout(device),
+            sampler,
+            depth_texture,
+            atlas: None,
+            scene_id: None,
+            active: vec![],
+            instances: vec![],
+        }
+    }
+
+    // rebuilds the pipeline for a main pass with `sample_count` samples
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.pipeline = create_pipeline(device, &self.layout, self.format, sample_count);
+    }
+
+    pub fn is_active(&self, model: usize) -> bool {
+        self.active.get(model).copied().unwrap_or(false)
+    }
+
+    // picks the models to draw as sprites and bakes the ones that are
+    // missing or stale, using the main pipeline and light
+    pub(crate) fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        camera: &Camera,
+        main: MainPass,
+    ) {
+        if self.scene_id != Some(scene.id) {
+            self.scene_id = Some(scene.id);
+            self.atlas = self.create_atlas(device, scene.models.len());
+            self.active = vec![false; scene.models.len()];
+            self.instances = Vec::with_capacity(scene.models.len());
+        }
+        let atlas = match &mut self.atlas {
+            Some(atlas) => atlas,
+            None => return,
+        };
+
+        self.instances.clear();
+        let max_angle = self.config.angle;
+        let mut encoder = None;
+        for (index, model) in scene.models.iter().enumerate() {
+            let bounds = model.world_bounds();
+            let center = bounds.center();
+            let radius = bounds.radius();
+            let offset = center - camera.eye;
+            let distance = offset.length();
+            self.active[index] = self.config.enabled && !bounds.is_empty() && !scene.is_instanced(index)
+                && model.is_drawn() && distance > self.config.distance.max(radius);
+            if !self.active[index] {
+                continue;
+            }
+
+            let direction = offset / distance;
+            let slot = &mut atlas.slots[index];
+            let stale = slot.baked.map_or(true, |baked| {
+                baked.dot(direction).min(1.0).acos() > max_angle
+            });
+            if stale {
+                let (_, up) = sprite_axes(direction);
+                let eye = center - direction * radius * 2.0;
+                let view = Mat4::look_at_rh(eye, center, up);
+                let proj = Mat4::orthographic_rh(-radius, radius, -radius, radius, radius * 0.5, radius * 3.5);
+                let uniforms = Uniforms::from_matrix(proj * view, eye);
+                queue.write_buffer(&slot.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+                // created per bake, the transform buffer is replaced when it grows
+                let bind_group = main.transforms.create_bind_group(
+                    device, &self.model_bind_group_layout, &slot.uniform_buffer);
+                let encoder = encoder.get_or_insert_with(|| device.create_command_encoder(
+                    &wgpu::CommandEncoderDescriptor { label: Some("imposter_bake_encoder") }));
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                        attachment: &slot.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                        attachment: &self.depth_texture.view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    }),
+                });
+                pass.set_pipeline(main.pipeline);
+                pass.set_bind_group(0, &bind_group, &[ModelTransforms::offset(index)]);
+                pass.set_bind_group(3, main.light_bind_group, &[]);
+                for draw in scene.draws.iter().filter(|draw| draw.model == index) {
+                    let material = &scene.materials[draw.material];
+                    pass.set_bind_group(1, &material.texture_bind_group, &[]);
+                    pass.set_bind_group(2, &material.bind_group, &[]);
+                    pass.set_vertex_buffer(0, model.mesh.vertex_buffer.slice(..));
+                    pass.set_index_buffer(model.mesh.index_buffer.slice(..));
+                    pass.draw_indexed(draw.indices.clone(), 0, 0..1);
+                }
+                slot.baked = Some(direction);
+            }
+
+            // face the camera, but keep the orientation the sprite was baked with
+            let (right, up) = sprite_axes(direction);
+            self.instances.push(Instance {
+                center: center.into(),
+                right: (right * radius).into(),
+                up: (up * radius).into(),
+                layer: index as u32,
+            });
+        }
+        if let Some(encoder) = encoder {
+            queue.submit(std::iter::once(encoder.finish()));
+        }
+        if !self.instances.is_empty() {
+            queue.write_buffer(&atlas.instance_buffer, 0, bytemuck::cast_slice(&self.instances));
+        }
+    }
+
+    // draws this frame's sprites, the main pass' camera bind group is
+    // expected at set 0
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, uniform_bind_group: &'a wgpu::BindGroup) {
+        let atlas = match &self.atlas {
+            Some(atlas) if !self.instances.is_empty() => atlas,
+            _ => return,
+        };
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, uniform_bind_group, &[]);
+        pass.set_bind_group(1, &atlas.bind_group, &[]);
+        pass.set_vertex_buffer(0, atlas.instance_buffer.slice(..));
+        pass.draw(0..4, 0..self.instances.len() as u32);
+    }
+
+    fn create_atlas(&self, device: &wgpu::Device, models: usize) -> Option<Atlas> {
+        if !self.config.enabled || models == 0 {
+            return None;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("imposter_atlas"),
+            size: wgpu::Extent3d {
+                width: self.config.resolution,
+                height: self.config.resolution,
+                depth: models as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&array_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("imposter_bind_group"),
+        });
+        let slots = (0..models as u32).map(|layer| {
+            let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            });
+            let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("imposter_uniform_buffer"),
+                contents: bytemuck::cast_slice(&[Uniforms::from_matrix(Mat4::identity(), Vec3::zero())]),
+                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            });
+            Slot { view, uniform_buffer, baked: None }
+        }).collect();
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("imposter_instance_buffer"),
+            size: (models * std::mem::size_of::<Instance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Some(Atlas { _texture: texture, bind_group, slots, instance_buffer })
+    }
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, format: wgpu::TextureFormat,
+                   sample_count: u32) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("imposter.vert.spv"));
+    let fs_module = device.create_shader_module(wgpu::include_spirv!("imposter.frag.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("imposter_pipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::None,
+            ..Default::default()
+        }),
+        color_states: &[format.into()],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleStrip,
+        depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+            format: DepthTexture::FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilStateDescriptor::default(),
+        }),
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[
+                wgpu::VertexBufferDescriptor {
+                    stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![0 => Float3,
+                                                          1 => Float3,
+                                                          2 => Float3,
+                                                          3 => Uint],
+                },
+            ],
+        },
+        sample_count,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
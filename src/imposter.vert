@@ -0,0 +1,23 @@
+#version 450
+
+layout(location=0) in vec3 a_center;
+layout(location=1) in vec3 a_right;
+layout(location=2) in vec3 a_up;
+layout(location=3) in uint a_layer;
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+};
+
+layout(location=0) out vec2 v_tex_coord;
+layout(location=1) flat out uint v_layer;
+
+// a triangle strip quad around the model's center, `a_right` and `a_up`
+// are already scaled to the model's radius
+void main() {
+  vec2 corner = vec2(float(gl_VertexIndex & 1), float(gl_VertexIndex >> 1)) * 2.0 - 1.0;
+  vec3 position = a_center + a_right * corner.x + a_up * corner.y;
+  gl_Position = u_view_proj * vec4(position, 1.0);
+  v_tex_coord = vec2(corner.x, -corner.y) * 0.5 + 0.5;
+  v_layer = a_layer;
+}
@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use winit::event::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+// the input the viewer reacts to, boiled down from winit's events so it
+// can be written to a file and played back
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputEvent {
+    Key { key: VirtualKeyCode, state: ElementState },
+    Button { button: MouseButton, state: ElementState },
+    // in physical pixels from the window's top left
+    CursorMoved { x: f64, y: f64 },
+    // in lines, touchpads scroll roughly one per 50 pixels
+    Scroll { lines: f32 },
+    // raw mouse movement, for looking around in the first-person modes
+    MouseMotion { dx: f64, dy: f64 },
+}
+
+impl InputEvent {
+    // None for window events the viewer doesn't use
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => Some(InputEvent::Key { key: *key, state: *state }),
+            // the viewer has no use for extra buttons, which toml can't
+            // write either
+            WindowEvent::MouseInput { button: MouseButton::Other(_), .. } => None,
+            WindowEvent::MouseInput { state, button, .. } =>
+                Some(InputEvent::Button { button: *button, state: *state }),
+            WindowEvent::CursorMoved { position, .. } =>
+                Some(InputEvent::CursorMoved { x: position.x, y: position.y }),
+            WindowEvent::MouseWheel { delta, .. } => Some(InputEvent::Scroll {
+                lines: match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 50.0,
+                },
+            }),
+            _ => None,
+        }
+    }
+}
+
+// an event and the update it came before, counting from the first one
+// recorded
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RecordedInput {
+    pub frame: u64,
+    #[serde(flatten)]
+    pub event: InputEvent,
+}
+
+// a session's input and the seconds every update stepped by, so playing
+// it back moves the camera exactly like the session did, however fast the
+// machine replaying it is. see `State::start_recording`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputRecording {
+    #[serde(default)]
+    pub steps: Vec<f32>,
+    // in the order they happened
+    #[serde(default, rename = "event")]
+    pub events: Vec<RecordedInput>,
+}
+
+impl InputRecording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // reads a recording written by `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    // writes the steps and then an `[[event]]` table per event
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, toml::to_string(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
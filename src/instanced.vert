@@ -0,0 +1,125 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+layout(location=1) in vec3 a_normal;
+layout(location=2) in vec2 a_tex_coord;
+layout(location=3) in vec4 a_tangent;
+// per instance
+layout(location=4) in vec4 a_model_0;
+layout(location=5) in vec4 a_model_1;
+layout(location=6) in vec4 a_model_2;
+layout(location=7) in vec4 a_model_3;
+layout(location=8) in vec3 a_normal_0;
+layout(location=9) in vec3 a_normal_1;
+layout(location=10) in vec3 a_normal_2;
+layout(location=11) in vec4 a_color;
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+  vec4 u_eye;
+  vec4 u_debug;
+  // x seconds of animation
+  vec4 u_time;
+};
+layout(set=1, binding=14) uniform texture2D t_displacement;
+layout(set=1, binding=15) uniform sampler s_displacement;
+layout(set=2, binding=0) uniform MaterialRaw {
+  vec3 u_albedo;
+  float u_metallic;
+  vec3 u_emissive;
+  float u_roughness;
+  float u_occlusion_strength;
+  float u_dissolve;
+  float u_bump_multiplier;
+  float u_normal_scale;
+  uint u_unlit;
+  uint u_displacement;
+  float u_displacement_scale;
+  float u_displacement_frequency;
+  float u_displacement_speed;
+};
+
+layout(location=0) out vec3 v_position;
+layout(location=1) out vec3 v_normal;
+layout(location=2) out vec2 v_tex_coord;
+layout(location=3) out vec4 v_color;
+layout(location=4) out vec4 v_tangent;
+
+#define DISPLACEMENT_MAP 1u
+#define DISPLACEMENT_NOISE 2u
+
+float hash(vec3 p) {
+  p = fract(p * 0.3183099 + 0.1) * 17.0;
+  return fract(p.x * p.y * p.z * (p.x + p.y + p.z));
+}
+
+// value noise between -1 and 1 in w, its gradient in xyz (Quilez,
+// "Value Noise Derivatives")
+vec4 noise(vec3 x) {
+  vec3 i = floor(x);
+  vec3 f = fract(x);
+  vec3 u = f * f * (3.0 - 2.0 * f);
+  vec3 du = 6.0 * f * (1.0 - f);
+  float a = hash(i);
+  float b = hash(i + vec3(1.0, 0.0, 0.0));
+  float c = hash(i + vec3(0.0, 1.0, 0.0));
+  float d = hash(i + vec3(1.0, 1.0, 0.0));
+  float e = hash(i + vec3(0.0, 0.0, 1.0));
+  float g = hash(i + vec3(1.0, 0.0, 1.0));
+  float h = hash(i + vec3(0.0, 1.0, 1.0));
+  float k = hash(i + vec3(1.0, 1.0, 1.0));
+  float k1 = b - a;
+  float k2 = c - a;
+  float k3 = e - a;
+  float k4 = a - b - c + d;
+  float k5 = a - c - e + h;
+  float k6 = a - b - e + g;
+  float k7 = -a + b + c - d + e - g - h + k;
+  float value = a + k1 * u.x + k2 * u.y + k3 * u.z + k4 * u.x * u.y + k5 * u.y * u.z + k6 * u.z * u.x
+    + k7 * u.x * u.y * u.z;
+  vec3 gradient = du * vec3(k1 + k4 * u.y + k6 * u.z + k7 * u.y * u.z,
+                            k2 + k5 * u.z + k4 * u.x + k7 * u.z * u.x,
+                            k3 + k6 * u.x + k5 * u.y + k7 * u.x * u.y);
+  return vec4(2.0 * gradient, 2.0 * value - 1.0);
+}
+
+// the material's height at a vertex in w and its gradient along the
+// surface in xyz, both in the mesh's own units. a map's slope is taken
+// per texture repeat, turned along the surface by the tangents, which
+// meshes without them don't get
+vec4 displacement(vec3 position, vec3 n, vec2 tex_coord, vec4 tangent) {
+  float shift = u_time.x * u_displacement_speed;
+  if (u_displacement == DISPLACEMENT_MAP) {
+    vec2 uv = tex_coord * u_displacement_frequency + vec2(shift, 0.0);
+    vec2 texel = 1.0 / vec2(textureSize(sampler2D(t_displacement, s_displacement), 0));
+    float height = textureLod(sampler2D(t_displacement, s_displacement), uv, 0.0).r;
+    float du = textureLod(sampler2D(t_displacement, s_displacement), uv + vec2(texel.x, 0.0), 0.0).r
+      - textureLod(sampler2D(t_displacement, s_displacement), uv - vec2(texel.x, 0.0), 0.0).r;
+    float dv = textureLod(sampler2D(t_displacement, s_displacement), uv + vec2(0.0, texel.y), 0.0).r
+      - textureLod(sampler2D(t_displacement, s_displacement), uv - vec2(0.0, texel.y), 0.0).r;
+    vec3 t = tangent.xyz - n * dot(n, tangent.xyz);
+    vec3 b = cross(n, t) * tangent.w;
+    vec2 slope = vec2(du, dv) / (2.0 * texel) * u_displacement_frequency;
+    return vec4((slope.x * t + slope.y * b) * u_displacement_scale, height * u_displacement_scale);
+  }
+  if (u_displacement == DISPLACEMENT_NOISE) {
+    vec4 value = noise(position * u_displacement_frequency + vec3(shift));
+    vec3 gradient = value.xyz * u_displacement_frequency;
+    return vec4(gradient - n * dot(n, gradient), value.w) * u_displacement_scale;
+  }
+  return vec4(0.0);
+}
+
+void main() {
+  mat4 model = mat4(a_model_0, a_model_1, a_model_2, a_model_3);
+  // displaced like in shader.vert, the same way for every instance
+  vec4 displaced = displacement(a_position, a_normal, a_tex_coord, a_tangent);
+  vec3 position = a_position + a_normal * displaced.w;
+  vec4 world = model * vec4(position, 1.0);
+  gl_Position = u_view_proj * world;
+  v_position = world.xyz;
+  v_normal = mat3(a_normal_0, a_normal_1, a_normal_2) * (a_normal - displaced.xyz);
+  v_tex_coord = a_tex_coord;
+  v_color = a_color;
+  v_tangent = vec4(mat3(model) * a_tangent.xyz, a_tangent.w);
+}
@@ -0,0 +1,73 @@
+pub mod state;
+pub mod renderer;
+pub mod graph;
+pub mod caps;
+pub mod camera;
+pub mod collision;
+pub mod culling;
+pub mod assets;
+pub mod texture;
+pub mod streaming;
+pub mod readback;
+pub mod picking;
+pub mod dump;
+pub mod upscale;
+pub mod tonemap;
+pub mod imposter;
+pub mod deferred;
+pub mod ssao;
+pub mod ground;
+pub mod background;
+pub mod bloom;
+pub mod motion_blur;
+pub mod fxaa;
+pub mod heatmap;
+pub mod lines;
+pub mod wireframe;
+pub mod navcube;
+pub mod minimap;
+pub mod vertex;
+pub mod mesh;
+pub mod skin;
+pub mod animator;
+pub mod material;
+pub mod params;
+pub mod shader_cache;
+pub mod lighting;
+pub mod quality;
+pub mod lights;
+pub mod cluster;
+pub mod ibl;
+pub mod scene;
+pub mod selection;
+pub mod batching;
+pub mod loading;
+pub mod pack;
+pub mod tiles;
+pub mod import;
+pub mod input;
+pub mod diff;
+pub mod sdf;
+pub mod csg;
+pub mod curve;
+pub mod shadow;
+pub mod stats;
+pub mod profiler;
+pub mod voxel;
+pub mod procedural;
+pub mod gltf_loader;
+pub mod alloc;
+pub mod config;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "hot-reload")]
+pub mod hot_reload;
+pub mod ui;
+pub mod viewer;
+#[cfg(feature = "ui")]
+pub mod egui_overlay;
+
+// the renderer on its own for embedding into other winit applications, and
+// the bundled viewer's state, which adds a scene, a camera and input handling
+pub use renderer::Renderer;
+pub use state::State as App;
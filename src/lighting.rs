@@ -0,0 +1,99 @@
+use serde::Deserialize;
+
+// everything about the environment's light: the sun, the sky behind the
+// scene, exposure and distance fog. colors are linear
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lighting {
+    // the sun is a point light, far away enough to act like a directional one
+    pub sun_position: [f32; 3],
+    pub sun_color: [f32; 3],
+    // light from the sky, scaled by each material's albedo and occlusion
+    pub ambient: [f32; 3],
+    // the background
+    pub sky_color: [f32; 3],
+    pub exposure: f32,
+    pub fog_color: [f32; 3],
+    // 0 disables fog
+    pub fog_density: f32,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Preset::Studio.lighting()
+    }
+}
+
+// named lighting setups for look development, cycled with L in the viewer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Studio,
+    Noon,
+    Sunset,
+    Overcast,
+    Night,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 5] = [
+        Preset::Studio, Preset::Noon, Preset::Sunset, Preset::Overcast, Preset::Night,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|preset| *preset == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn lighting(self) -> Lighting {
+        match self {
+            // a white key light in front of a neutral backdrop, no fog
+            Preset::Studio => Lighting {
+                sun_position: [0.0, 2.0, -3.0],
+                sun_color: [1.0, 1.0, 1.0],
+                ambient: [0.05, 0.05, 0.05],
+                sky_color: [0.1, 0.2, 0.3],
+                exposure: 1.0,
+                fog_color: [0.1, 0.2, 0.3],
+                fog_density: 0.0,
+            },
+            Preset::Noon => Lighting {
+                sun_position: [20.0, 100.0, 10.0],
+                sun_color: [1.0, 0.97, 0.9],
+                ambient: [0.25, 0.3, 0.4],
+                sky_color: [0.35, 0.55, 0.9],
+                exposure: 1.0,
+                fog_color: [0.55, 0.7, 0.9],
+                fog_density: 0.005,
+            },
+            Preset::Sunset => Lighting {
+                sun_position: [100.0, 8.0, -40.0],
+                sun_color: [1.0, 0.5, 0.25],
+                ambient: [0.15, 0.1, 0.15],
+                sky_color: [0.8, 0.35, 0.2],
+                exposure: 1.3,
+                fog_color: [0.8, 0.45, 0.3],
+                fog_density: 0.02,
+            },
+            Preset::Overcast => Lighting {
+                sun_position: [0.0, 100.0, 0.0],
+                sun_color: [0.45, 0.47, 0.5],
+                ambient: [0.35, 0.36, 0.38],
+                sky_color: [0.55, 0.57, 0.6],
+                exposure: 1.1,
+                fog_color: [0.55, 0.57, 0.6],
+                fog_density: 0.03,
+            },
+            // moonlight
+            Preset::Night => Lighting {
+                sun_position: [-30.0, 60.0, 40.0],
+                sun_color: [0.15, 0.2, 0.35],
+                ambient: [0.02, 0.02, 0.04],
+                sky_color: [0.005, 0.008, 0.02],
+                exposure: 1.6,
+                fog_color: [0.01, 0.015, 0.03],
+                fog_density: 0.01,
+            },
+        }
+    }
+}
@@ -0,0 +1,500 @@
+use wgpu;
+use wgpu::util::DeviceExt;
+use anyhow::{bail, Result};
+use crate::camera::Camera;
+use crate::cluster::LightClusters;
+use crate::lighting::Lighting;
+use crate::ibl::Environment;
+use crate::shadow::{ShadowMaps, MAX_POINT_SHADOWS};
+use glam::{Vec2, Vec3};
+
+// a light added to the sun. point and spot lights fade out with the
+// inverse square of the distance and reach zero at `range`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    // linear, what a white surface facing the light shows at distance 1,
+    // or anywhere for directional lights
+    pub color: [f32; 3],
+    // point and spot lights only, and only the first few that ask get a
+    // shadow map, see `MAX_POINT_SHADOWS`
+    pub casts_shadows: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    // infinitely far away, shining along `direction`
+    Directional { direction: [f32; 3] },
+    Point { position: [f32; 3], range: f32 },
+    // a point light limited to a cone around `direction`. the angles are
+    // in radians from the axis, full strength inside `inner_angle` and
+    // fading out to nothing at `outer_angle`
+    Spot { position: [f32; 3], direction: [f32; 3], range: f32, inner_angle: f32, outer_angle: f32 },
+}
+
+impl Light {
+    pub fn directional(direction: [f32; 3], color: [f32; 3]) -> Self {
+        Self { kind: LightKind::Directional { direction }, color, casts_shadows: false }
+    }
+
+    pub fn point(position: [f32; 3], color: [f32; 3], range: f32) -> Self {
+        Self { kind: LightKind::Point { position, range }, color, casts_shadows: false }
+    }
+
+    pub fn spot(position: [f32; 3], direction: [f32; 3], color: [f32; 3], range: f32,
+                inner_angle: f32, outer_angle: f32) -> Self {
+        Self {
+            kind: LightKind::Spot { position, direction, range, inner_angle, outer_angle },
+            color,
+            casts_shadows: false,
+        }
+    }
+
+    // where the light shines from and how far, for lights that have a
+    // position
+    pub(crate) fn origin(&self) -> Option<(Vec3, f32)> {
+        match self.kind {
+            LightKind::Directional { .. } => None,
+            LightKind::Point { position, range } | LightKind::Spot { position, range, .. } => {
+                Some((Vec3::from(position), range.max(0.0)))
+            }
+        }
+    }
+
+    // whether the light would get a shadow map if one is left
+    pub(crate) fn wants_shadow(&self) -> bool {
+        self.casts_shadows && matches!(self.origin(), Some((_, range)) if range > 0.0)
+    }
+}
+
+// the sun and the rest of the environment's lighting, bound at set 3
+// binding 0
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightingUniforms {
+    position: [f32; 3],
+    exposure: f32,
+    color: [f32; 3],
+    fog_density: f32,
+    ambient: [f32; 3],
+    // how many of the storage buffer's lights are in use
+    light_count: u32,
+    fog_color: [f32; 3],
+    _padding: f32,
+}
+
+impl LightingUniforms {
+    fn new(lighting: &Lighting, light_count: usize) -> Self {
+        Self {
+            position: lighting.sun_position,
+            exposure: lighting.exposure,
+            color: lighting.sun_color,
+            fog_density: lighting.fog_density,
+            ambient: lighting.ambient,
+            light_count: light_count as u32,
+            fog_color: lighting.fog_color,
+            _padding: 0.0,
+        }
+    }
+}
+
+// must match the LIGHT_* constants in shader.frag
+const LIGHT_DIRECTIONAL: u32 = 0;
+const LIGHT_POINT: u32 = 1;
+const LIGHT_SPOT: u32 = 2;
+
+// one entry of the storage buffer at set 3 binding 1, the fields a kind
+// doesn't have are zero
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightRaw {
+    position: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    kind: u32,
+    // normalized
+    direction: [f32; 3],
+    // the cosines of the cone's angles, so the shader compares dot products
+    cos_inner: f32,
+    cos_outer: f32,
+    // the light's slot in the point shadow maps, -1 without one
+    shadow: i32,
+    _padding: [f32; 2],
+}
+
+impl LightRaw {
+    fn new(light: &Light, shadow: Option<usize>) -> Self {
+        let raw = Self {
+            position: [0.0; 3],
+            range: 0.0,
+            color: light.color,
+            kind: LIGHT_DIRECTIONAL,
+            direction: [0.0; 3],
+            cos_inner: 0.0,
+            cos_outer: 0.0,
+            shadow: shadow.map_or(-1, |slot| slot as i32),
+            _padding: [0.0; 2],
+        };
+        // without a direction the light points straight down
+        let normalize = |direction: [f32; 3]| {
+            let direction = Vec3::from(direction);
+            let direction = if direction.length_squared() > 0.0 { direction.normalize() } else { -Vec3::unit_y() };
+            direction.into()
+        };
+        match light.kind {
+            LightKind::Directional { direction } => Self { direction: normalize(direction), ..raw },
+            LightKind::Point { position, range } => Self {
+                position,
+                range: range.max(0.0),
+                kind: LIGHT_POINT,
+                ..raw
+            },
+            LightKind::Spot { position, direction, range, inner_angle, outer_angle } => {
+                // a cone can't be wider than a hemisphere, and the inner
+                // angle never passes the outer one
+                let outer_angle = outer_angle.clamp(0.0, std::f32::consts::FRAC_PI_2);
+                Self {
+                    position,
+                    range: range.max(0.0),
+                    kind: LIGHT_SPOT,
+                    direction: normalize(direction),
+                    cos_inner: inner_angle.clamp(0.0, outer_angle).cos(),
+                    cos_outer: outer_angle.cos(),
+                    ..raw
+                }
+            }
+        }
+    }
+}
+
+fn environment_map(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::SampledTexture {
+            multisampled: false,
+            dimension: wgpu::TextureViewDimension::D2,
+            component_type: wgpu::TextureComponentType::Float,
+        },
+        count: None,
+    }
+}
+
+// the environment's lighting and the list of lights, uploaded
+// whenever either changes
+pub(crate) struct Lights {
+    lighting: Lighting,
+    lights: Vec<Light>,
+    uniform_buffer: wgpu::Buffer,
+    storage_buffer: wgpu::Buffer,
+    // in lights, the buffer is never empty
+    capacity: usize,
+    clusters: LightClusters,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    // the same with the clusters turned off, for views other than the
+    // frame's the clusters aren't built for
+    unclustered_bind_group: wgpu::BindGroup,
+}
+
+impl Lights {
+    pub(crate) fn new(device: &wgpu::Device, lighting: Lighting, shadows: &ShadowMaps,
+                      environment: &Environment, clustered: bool) -> Self {
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("light_uniform_buffer"),
+                contents: bytemuck::bytes_of(&LightingUniforms::new(&lighting, 0)),
+                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            });
+        let capacity = 4;
+        let storage_buffer = Self::create_storage_buffer(device, capacity);
+        let clusters = LightClusters::new(device, &storage_buffer, clustered);
+        let bind_group_layout = Self::create_bind_group_layout(device);
+        let (bind_group, unclustered_bind_group) = Self::create_bind_groups(
+            device, &bind_group_layout, &uniform_buffer, &storage_buffer, &clusters, shadows, environment);
+        Self {
+            lighting,
+            lights: vec![],
+            uniform_buffer,
+            storage_buffer,
+            capacity,
+            clusters,
+            bind_group_layout,
+            bind_group,
+            unclustered_bind_group,
+        }
+    }
+
+    fn create_storage_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light_storage_buffer"),
+            size: (capacity * std::mem::size_of::<LightRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // set 3 of every pipeline that's lit, identical wherever it's created
+    // so the bind group can be shared. bindings 2 to 6 are the shadow maps,
+    // 7 to 11 the environment's maps, 12 and 13 the light clusters and 14
+    // to 16 the environment captured in the scene
+    pub(crate) fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::StorageBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                        readonly: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        multisampled: false,
+                        dimension: wgpu::TextureViewDimension::D2Array,
+                        component_type: wgpu::TextureComponentType::Float,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        multisampled: false,
+                        dimension: wgpu::TextureViewDimension::D2Array,
+                        component_type: wgpu::TextureComponentType::Float,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                environment_map(8),
+                environment_map(9),
+                environment_map(10),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 12,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::StorageBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                        readonly: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 14,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                environment_map(15),
+                environment_map(16),
+            ],
+            label: Some("light_bind_group_layout"),
+        })
+    }
+
+    // the bind group with the clusters and the one without
+    fn create_bind_groups(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, uniform_buffer: &wgpu::Buffer,
+                          storage_buffer: &wgpu::Buffer, clusters: &LightClusters, shadows: &ShadowMaps,
+                          environment: &Environment) -> (wgpu::BindGroup, wgpu::BindGroup) {
+        let create = |clustered| {
+            let mut entries = vec![
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(storage_buffer.slice(..)),
+                },
+            ];
+            entries.extend(shadows.bind_group_entries().iter().cloned());
+            entries.extend(environment.bind_group_entries().iter().cloned());
+            entries.extend(clusters.bind_group_entries(clustered).iter().cloned());
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout,
+                entries: &entries,
+                label: Some("light_bind_group"),
+            })
+        };
+        (create(true), create(false))
+    }
+
+    pub(crate) fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub(crate) fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub(crate) fn unclustered_bind_group(&self) -> &wgpu::BindGroup {
+        &self.unclustered_bind_group
+    }
+
+    pub(crate) fn lighting(&self) -> &Lighting {
+        &self.lighting
+    }
+
+    pub(crate) fn set_lighting(&mut self, queue: &wgpu::Queue, lighting: Lighting) {
+        self.lighting = lighting;
+        self.write_uniforms(queue);
+    }
+
+    // binds new shadow maps or environment maps in place of the old ones
+    pub(crate) fn rebind(&mut self, device: &wgpu::Device, shadows: &ShadowMaps, environment: &Environment) {
+        let (bind_group, unclustered_bind_group) = Self::create_bind_groups(
+            device, &self.bind_group_layout, &self.uniform_buffer, &self.storage_buffer, &self.clusters, shadows,
+            environment);
+        self.bind_group = bind_group;
+        self.unclustered_bind_group = unclustered_bind_group;
+    }
+
+    pub(crate) fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    pub(crate) fn is_clustered(&self) -> bool {
+        self.clusters.is_enabled()
+    }
+
+    pub(crate) fn set_clustered(&mut self, clustered: bool) {
+        self.clusters.set_enabled(clustered);
+    }
+
+    // bins the lights into the clusters of a frame seen by `camera`, see
+    // `LightClusters`
+    pub(crate) fn cluster(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, camera: &Camera,
+                          jitter: Vec2) {
+        self.clusters.dispatch(encoder, queue, camera, jitter, self.lights.len());
+    }
+
+    pub(crate) fn add(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, shadows: &ShadowMaps,
+                      environment: &Environment, light: Light) -> usize {
+        self.lights.push(light);
+        if self.lights.len() > self.capacity {
+            self.capacity = self.lights.len().next_power_of_two();
+            self.storage_buffer = Self::create_storage_buffer(device, self.capacity);
+            self.clusters.rebind(device, &self.storage_buffer);
+            self.rebind(device, shadows, environment);
+            self.write_lights(queue, 0);
+        } else {
+            self.write_lights(queue, self.lights.len() - 1);
+        }
+        self.write_uniforms(queue);
+        self.lights.len() - 1
+    }
+
+    pub(crate) fn set(&mut self, queue: &wgpu::Queue, index: usize, light: Light) -> Result<()> {
+        match self.lights.get_mut(index) {
+            Some(slot) => *slot = light,
+            None => bail!("No light {}, there are {}", index, self.lights.len()),
+        }
+        self.write_lights(queue, index);
+        Ok(())
+    }
+
+    // the lights after `index` move down by one
+    pub(crate) fn remove(&mut self, queue: &wgpu::Queue, index: usize) -> Result<Light> {
+        if index >= self.lights.len() {
+            bail!("No light {}, there are {}", index, self.lights.len());
+        }
+        let light = self.lights.remove(index);
+        self.write_lights(queue, index);
+        self.write_uniforms(queue);
+        Ok(light)
+    }
+
+    fn write_uniforms(&self, queue: &wgpu::Queue) {
+        let uniforms = LightingUniforms::new(&self.lighting, self.lights.len());
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    // rewrites the lights from `first` on. shadow maps go to the first
+    // lights asking for them, so a change can move the later lights' slots
+    fn write_lights(&self, queue: &wgpu::Queue, first: usize) {
+        if first >= self.lights.len() {
+            return;
+        }
+        let mut slots = self.lights[..first].iter().filter(|light| light.wants_shadow()).count();
+        let raw: Vec<LightRaw> = self.lights[first..].iter().map(|light| {
+            let shadow = if light.wants_shadow() && slots < MAX_POINT_SHADOWS {
+                slots += 1;
+                Some(slots - 1)
+            } else {
+                None
+            };
+            LightRaw::new(light, shadow)
+        }).collect();
+        let offset = (first * std::mem::size_of::<LightRaw>()) as wgpu::BufferAddress;
+        queue.write_buffer(&self.storage_buffer, offset, bytemuck::cast_slice(&raw));
+    }
+}
@@ -0,0 +1,196 @@
+use wgpu;
+use glam::Vec3;
+use crate::collision::Aabb;
+use crate::texture::DepthTexture;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 3],
+}
+
+// colored debug lines, collected on the CPU every frame and drawn on top
+// of the scene in the main pass
+pub struct DebugLines {
+    format: wgpu::TextureFormat,
+    layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    vertices: Vec<LineVertex>,
+    buffer: wgpu::Buffer,
+    // vertices the buffer has room for
+    capacity: usize,
+    // vertices uploaded by the last `upload`
+    count: u32,
+}
+
+fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("debug_line_buffer"),
+        size: (capacity * std::mem::size_of::<LineVertex>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+impl DebugLines {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32) -> Self {
+        // same as the renderer's camera layout, so its bind group can be reused
+        let camera_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("debug_line_camera_bind_group_layout"),
+            }
+        );
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("debug_line_pipeline_layout"),
+            bind_group_layouts: &[&camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &layout, format, sample_count);
+        let capacity = 1024;
+        Self {
+            format,
+            layout,
+            pipeline,
+            vertices: Vec::with_capacity(capacity),
+            buffer: create_buffer(device, capacity),
+            capacity,
+            count: 0,
+        }
+    }
+
+    // rebuilds the pipeline for a main pass with `sample_count` samples
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.pipeline = create_pipeline(device, &self.layout, self.format, sample_count);
+    }
+
+    // starts collecting the next frame's lines
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    pub fn line(&mut self, a: Vec3, b: Vec3, color: [f32; 3]) {
+        self.vertices.push(LineVertex { position: a.into(), color });
+        self.vertices.push(LineVertex { position: b.into(), color });
+    }
+
+    // the twelve edges of a box given as its near and far faces, each four
+    // corners in order around the face
+    pub fn cuboid(&mut self, corners: &[Vec3; 8], color: [f32; 3]) {
+        for i in 0..4 {
+            let j = (i + 1) % 4;
+            self.line(corners[i], corners[j], color);
+            self.line(corners[i + 4], corners[j + 4], color);
+            self.line(corners[i], corners[i + 4], color);
+        }
+    }
+
+    pub fn aabb(&mut self, bounds: &Aabb, color: [f32; 3]) {
+        let (min, max) = (bounds.min, bounds.max);
+        self.cuboid(&[
+            Vec3::new(min.x, min.y, min.z), Vec3::new(max.x, min.y, min.z),
+            Vec3::new(max.x, max.y, min.z), Vec3::new(min.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z), Vec3::new(max.x, min.y, max.z),
+            Vec3::new(max.x, max.y, max.z), Vec3::new(min.x, max.y, max.z),
+        ], color);
+    }
+
+    // an octahedron pointing from `head` to `tail`, the way bones are
+    // usually drawn, widest a fifth of the way along
+    pub fn bone(&mut self, head: Vec3, tail: Vec3, color: [f32; 3]) {
+        let along = tail - head;
+        let length = along.length();
+        if length <= f32::EPSILON {
+            return;
+        }
+        let direction = along / length;
+        let other = if direction.y.abs() < 0.9 { Vec3::unit_y() } else { Vec3::unit_x() };
+        let u = direction.cross(other).normalize() * length * 0.1;
+        let v = direction.cross(u);
+        let center = head + along * 0.2;
+        let ring = [center + u, center + v, center - u, center - v];
+        for i in 0..4 {
+            self.line(head, ring[i], color);
+            self.line(ring[i], tail, color);
+            self.line(ring[i], ring[(i + 1) % 4], color);
+        }
+    }
+
+    // three crossing lines `size` long
+    pub fn cross(&mut self, center: Vec3, size: f32, color: [f32; 3]) {
+        let half = size * 0.5;
+        for axis in [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()].iter() {
+            self.line(center - *axis * half, center + *axis * half, color);
+        }
+    }
+
+    // copies the collected lines to the GPU, growing the buffer if needed
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.vertices.len() > self.capacity {
+            self.capacity = self.vertices.len().next_power_of_two();
+            self.buffer = create_buffer(device, self.capacity);
+        }
+        if !self.vertices.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.vertices));
+        }
+        self.count = self.vertices.len() as u32;
+    }
+
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) {
+        if self.count == 0 {
+            return;
+        }
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, camera_bind_group, &[]);
+        pass.set_vertex_buffer(0, self.buffer.slice(..));
+        pass.draw(0..self.count, 0..1);
+    }
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, format: wgpu::TextureFormat,
+                   sample_count: u32) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("lines.vert.spv"));
+    let fs_module = device.create_shader_module(wgpu::include_spirv!("lines.frag.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("debug_line_pipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        color_states: &[format.into()],
+        primitive_topology: wgpu::PrimitiveTopology::LineList,
+        // drawn through everything, debug lines are no use hidden
+        depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+            format: DepthTexture::FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilStateDescriptor::default(),
+        }),
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[wgpu::VertexBufferDescriptor {
+                stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::InputStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float3, 1 => Float3],
+            }],
+        },
+        sample_count,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
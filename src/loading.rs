@@ -0,0 +1,84 @@
+use crate::assets::Assets;
+use crate::config::SceneConfig;
+use crate::curve;
+use crate::scene::Scene;
+use crate::sdf;
+use crate::voxel;
+use anyhow::Result;
+use async_std::task::{self, JoinHandle};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use wgpu;
+
+// a scene loading on an async-std task, so the window keeps responding
+// while the files are parsed and decoded. the uploads happen on the task
+// too, wgpu's device and queue can be used from any thread
+pub struct SceneLoad {
+    task: JoinHandle<(Result<Scene>, Assets)>,
+}
+
+impl SceneLoad {
+    // loads what `config` lists, sharing textures through `assets`, which
+    // are handed back along with the scene
+    pub fn start(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>, config: SceneConfig,
+                 mut assets: Assets) -> Self {
+        let task = task::spawn_blocking(move || {
+            let scene = load(&device, &queue, &config, &mut assets);
+            (scene, assets)
+        });
+        Self { task }
+    }
+
+    // the loaded scene if it's done, without waiting. it isn't handed out
+    // twice, drop the load once this returns something
+    pub fn poll(&mut self) -> Option<(Result<Scene>, Assets)> {
+        let mut context = Context::from_waker(Waker::noop());
+        match Pin::new(&mut self.task).poll(&mut context) {
+            Poll::Ready(loaded) => Some(loaded),
+            Poll::Pending => None,
+        }
+    }
+
+    pub async fn finish(self) -> (Result<Scene>, Assets) {
+        self.task.await
+    }
+}
+
+// the models in `config` along with their raymarched primitives, curves
+// and voxels, with the static models batched if `config` says so.
+// the extras are optional, a broken one is reported and left out
+pub fn load(device: &wgpu::Device, queue: &wgpu::Queue, config: &SceneConfig, assets: &mut Assets) -> Result<Scene> {
+    let mut scene = Scene::load_with_options(device, queue, &config.model, assets, config.import)?;
+    for path in &config.extra_models {
+        match Scene::load_with_options(device, queue, path, assets, config.import) {
+            Ok(other) => {
+                scene.append(other);
+            }
+            Err(error) => scene.stats.problems.push(format!("{}: {:#}", path, error)),
+        }
+    }
+    if let Some(path) = &config.sdf {
+        match sdf::load(path) {
+            Ok(sdf) => scene.sdf = sdf,
+            Err(error) => scene.stats.problems.push(format!("{}: {:#}", path, error)),
+        }
+    }
+    if let Some(path) = &config.curves {
+        match curve::load(path) {
+            Ok(curves) => scene.curves = curves,
+            Err(error) => scene.stats.problems.push(format!("{}: {:#}", path, error)),
+        }
+    }
+    if let Some(path) = &config.voxels {
+        match voxel::load(device, queue, path, config.voxel_size) {
+            Ok(voxels) => scene.add_models(voxels.models, voxels.material, voxels.triangles),
+            Err(error) => scene.stats.problems.push(format!("{}: {:#}", path, error)),
+        }
+    }
+    if config.static_batching {
+        scene.batch_static(device, queue)?;
+    }
+    Ok(scene)
+}
@@ -1,68 +1,19 @@
-pub mod state;
-pub mod camera;
-pub mod texture;
-pub mod vertex;
-pub mod mesh;
-pub mod material;
-
-use winit::{
-    event::*,
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
-};
 use anyhow::Result;
+use structopt::StructOpt;
+use wr3d::config::{self, Config};
+use wr3d::viewer::Opt;
+#[cfg(debug_assertions)]
+use wr3d::alloc;
+
+// counts allocations for the viewer's frame loop audit, see `alloc::FrameAudit`
+#[cfg(debug_assertions)]
+#[global_allocator]
+static ALLOCATOR: alloc::CountingAllocator = alloc::CountingAllocator;
 
 #[async_std::main]
 async fn main() -> Result<()> {
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop)?;
-    let mut state = state::State::new(&window).await?;
-    event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Poll;        
-        match event {
-            Event::WindowEvent {
-                ref event,
-                window_id,
-            } if window_id == window.id() => {
-                match event {
-                    WindowEvent::CloseRequested =>
-                        *control_flow = ControlFlow::Exit,
-                    WindowEvent::KeyboardInput {
-                        input,
-                        ..
-                    } => {
-                        match input {
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            } => *control_flow = ControlFlow::Exit,
-                            _ => {}
-                        }
-                    },
-                    WindowEvent::Resized(physical_size) => {
-                        state.resize(*physical_size);
-                    }
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        state.resize(**new_inner_size);
-                    }
-                    _ => (),
-                }
-            },
-            Event::MainEventsCleared => {
-                window.request_redraw();
-            },
-            Event::RedrawRequested(_) => {
-                state.update();
-                match state.render() {
-                    Ok(_) => {},
-                    Err(wgpu::SwapChainError::Lost) => state.resize(state.size),
-                    Err(wgpu::SwapChainError::OutOfMemory) =>
-                        *control_flow = ControlFlow::Exit,
-                    Err(e) => eprintln!("{:?}", e),
-                }
-            },
-            _ => (),
-        }
-    });
+    // the viewer's own messages show by default, RUST_LOG picks others
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("wr3d=info")).init();
+    let config = Config::load(config::PATH)?;
+    Opt::from_args().run(config).await
 }
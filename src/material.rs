@@ -1,41 +1,454 @@
-use crate::texture::Texture;
+use crate::assets::{Assets, Handle};
+use crate::texture::{SamplerDesc, Texture};
 use wgpu;
 use tobj;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use wgpu::util::DeviceExt;
 use mint::Vector3;
 use crevice::std140::{AsStd140, Std140};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 pub struct Material {
-    pub diffuse_texture: Texture,
+    pub albedo_texture: Handle<Texture>,
+    // roughness in green and metalness in blue, as in gltf
+    pub metallic_roughness_texture: Handle<Texture>,
+    pub bump_texture: Handle<Texture>,
+    pub dissolve_texture: Handle<Texture>,
+    // tangent space, flat when the material has none
+    pub normal_texture: Handle<Texture>,
+    // ambient occlusion in red
+    pub occlusion_texture: Handle<Texture>,
+    pub emissive_texture: Handle<Texture>,
+    // heights in red, read by the vertex shaders
+    pub displacement_texture: Handle<Texture>,
     pub name: String,
+    // what the parameter buffer was filled from
+    pub factors: Factors,
+    // what the material editor changed since it was loaded
+    pub edits: MaterialEdits,
+    // the parameters, rewritten by `set_factors`
+    buffer: wgpu::Buffer,
+    // set 1, the texture maps
+    pub texture_bind_group_layout: wgpu::BindGroupLayout,
+    pub texture_bind_group: wgpu::BindGroup,
+    // set 2, the material parameters
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
 }
 
+// a metallic-roughness material as the shader sees it, built from gltf or
+// mtl data. the factors multiply their maps, and maps left out act as if
+// they were white (flat for the normal map, black for the bump map)
+pub struct PbrMaterial {
+    // rgb is the base color, a the opacity
+    pub albedo: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    // how much of the occlusion map is applied
+    pub occlusion_strength: f32,
+    // shown in its albedo and emissive colors, without lighting
+    pub unlit: bool,
+    pub albedo_texture: Option<Handle<Texture>>,
+    pub metallic_roughness_texture: Option<Handle<Texture>>,
+    pub occlusion_texture: Option<Handle<Texture>>,
+    pub emissive_texture: Option<Handle<Texture>>,
+    // the tangent space normal map and its scale
+    pub normal_texture: Option<(Handle<Texture>, f32)>,
+    // the height map and its multiplier
+    pub bump_texture: Option<(Handle<Texture>, f32)>,
+    pub dissolve_texture: Option<Handle<Texture>>,
+    // moves the vertices along their normals, e.g. for water or flags
+    pub displacement: Displacement,
+    pub displacement_texture: Option<Handle<Texture>>,
+}
+
+// the parameters of a material without its maps, as `PbrMaterial` sets
+// them up, kept so they can be baked into a pack
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Factors {
+    pub albedo: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    pub occlusion_strength: f32,
+    pub unlit: bool,
+    pub bump_multiplier: f32,
+    pub normal_scale: f32,
+    pub displacement: Displacement,
+}
+
+// the names of the maps in the order of `Material::textures`, as the
+// material files write them
+pub const MAP_NAMES: [&str; 8] = [
+    "albedo", "metallic_roughness", "bump", "dissolve", "normal", "occlusion", "emissive", "displacement",
+];
+
+// the maps holding colors, sampled with the srgb conversion
+pub(crate) const SRGB_MAPS: [usize; 2] = [0, 6];
+
+// changes made to a loaded material, kept so they can be saved next to the
+// scene and applied again when it's loaded, see `params::save_materials`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialEdits {
+    // all of the parameters once any of them changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub factors: Option<Factors>,
+    // the image files assigned to maps, by their `MAP_NAMES`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub maps: BTreeMap<String, String>,
+}
+
+// where the heights vertices are displaced by come from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DisplacementMode {
+    None,
+    // red of `displacement_texture` at the vertex's texture coordinates
+    Map,
+    // value noise over the mesh's own positions
+    Noise,
+}
+
+// a height between 0 and `scale` (-`scale` and `scale` for noise) added
+// along the normal, with the normal tilted to match. `frequency` repeats
+// the map or noise more often and `speed` scrolls it, in repeats per
+// second, so the surface moves
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Displacement {
+    pub mode: DisplacementMode,
+    pub scale: f32,
+    pub frequency: f32,
+    pub speed: f32,
+}
+
+impl Default for Displacement {
+    fn default() -> Self {
+        Self {
+            mode: DisplacementMode::None,
+            scale: 1.0,
+            frequency: 1.0,
+            speed: 0.0,
+        }
+    }
+}
+
+// the gltf defaults for a primitive without a material
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        Self {
+            albedo: [1.0; 4],
+            metallic: 1.0,
+            roughness: 1.0,
+            emissive: [0.0; 3],
+            occlusion_strength: 1.0,
+            unlit: false,
+            albedo_texture: None,
+            metallic_roughness_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+            normal_texture: None,
+            bump_texture: None,
+            dissolve_texture: None,
+            displacement: Displacement::default(),
+            displacement_texture: None,
+        }
+    }
+}
+
+// the mtl `illum` models that matter for the conversion, above ambient
+// everything has highlights
+const ILLUM_COLOR: u8 = 0;
+const ILLUM_AMBIENT: u8 = 1;
+
+// a normal map texel pointing straight out of the surface
+const FLAT_NORMAL: [u8; 4] = [128, 128, 255, 255];
+
+impl PbrMaterial {
+    // converts a classic mtl material. the pbr extension (`Pr`, `Pm`,
+    // `map_Pr`, `map_Pm`, `Ke`, `map_Ke`) is used where present, otherwise
+    // the roughness follows the specular exponent `Ns`. `map_ao` is read as
+    // an occlusion map. maps that fail to load are reported in `problems`
+    // and left out. maps used as they are come from `assets`, so materials
+    // sharing one upload it once
+    pub fn from_mtl(device: &wgpu::Device, queue: &wgpu::Queue, material: &tobj::Material,
+                    assets: &mut Assets, problems: &mut Vec<String>) -> Self {
+        let param = |key: &str| material.unknown_param.get(key).map_or("", String::as_str);
+        let report = |problems: &mut Vec<String>, label: &str, error: anyhow::Error| {
+            problems.push(format!("material {}: {}: {:#}", material.name, label, error));
+        };
+        let mut map = |statement: &str, format, label: &str| {
+            let (file, _) = parse_texture_map(statement)?;
+            match assets.texture(device, queue, &format!("data/{}", file), format, Some(label)) {
+                Ok(texture) => Some(texture),
+                Err(error) => {
+                    report(problems, label, error);
+                    None
+                }
+            }
+        };
+        let albedo_texture = map(&material.diffuse_texture, wgpu::TextureFormat::Rgba8UnormSrgb, "albedo_texture");
+        let emissive_texture = map(param("map_Ke"), wgpu::TextureFormat::Rgba8UnormSrgb, "emissive_texture");
+        let occlusion_texture = map(param("map_ao"), wgpu::TextureFormat::Rgba8Unorm, "occlusion_texture");
+        let dissolve_texture = map(&material.dissolve_texture, wgpu::TextureFormat::Rgba8Unorm, "dissolve_texture");
+        let displacement_texture = map(param("disp"), wgpu::TextureFormat::Rgba8Unorm, "displacement_texture");
+        let mut image = |statement: &str, label: &str| match load_map_image(statement) {
+            Ok(image) => image.map(|(image, _)| image),
+            Err(error) => {
+                report(problems, label, error);
+                None
+            }
+        };
+        let metallic_roughness_image = metallic_roughness_image(
+            image(param("map_Pr"), "roughness_texture"), image(param("map_Pm"), "metallic_texture"));
+        let (bump_texture, normal_texture) = load_bump_maps(device, queue, material, problems);
+
+        // tobj only knows about `d`, `Tr` (its inverse) and `Ke` end up as
+        // unknown parameters
+        let mut dissolve = material.dissolve;
+        if let Ok(transparency) = param("Tr").trim().parse::<f32>() {
+            if dissolve == 1.0 {
+                dissolve = 1.0 - transparency;
+            }
+        }
+        let illum = material.illumination_model.unwrap_or(2);
+        // the roughness whose highlight roughly matches the blinn-phong
+        // exponent, fully rough without highlights
+        let roughness = match param("Pr").trim().parse::<f32>() {
+            Ok(roughness) => roughness,
+            Err(_) if illum <= ILLUM_AMBIENT => 1.0,
+            Err(_) => (2.0 / (material.shininess.max(0.0) + 2.0)).powf(0.25),
+        };
+        let mut displacement = Displacement::default();
+        if displacement_texture.is_some() {
+            displacement.mode = DisplacementMode::Map;
+            displacement.scale = parse_texture_map(param("disp")).map_or(1.0, |(_, scale)| scale);
+        } else if let Ok(scale) = param("disp_noise").trim().parse() {
+            displacement.mode = DisplacementMode::Noise;
+            displacement.scale = scale;
+        }
+        displacement.frequency = param("disp_frequency").trim().parse().unwrap_or(displacement.frequency);
+        displacement.speed = param("disp_speed").trim().parse().unwrap_or(displacement.speed);
+        let [r, g, b] = material.diffuse;
+        Self {
+            albedo: [r, g, b, dissolve],
+            metallic: param("Pm").trim().parse().unwrap_or(0.0),
+            roughness,
+            emissive: parse_vec3(param("Ke")).unwrap_or([0.0; 3]),
+            occlusion_strength: 1.0,
+            unlit: illum == ILLUM_COLOR,
+            albedo_texture,
+            metallic_roughness_texture: metallic_roughness_image.map(|image| Handle::new(Texture::from_image(
+                device, queue, &image, wgpu::TextureFormat::Rgba8Unorm, Some("metallic_roughness_texture")))),
+            occlusion_texture,
+            emissive_texture,
+            normal_texture,
+            bump_texture,
+            dissolve_texture,
+            displacement,
+            displacement_texture,
+        }
+    }
+}
+
 impl Material {
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, material: &tobj::Material) -> Result<Self> {
-        let path = format!("data/{}", material.diffuse_texture);
-        let diffuse_texture = Texture::new(device, queue, &path,
-                                           Some("diffuse_texture"))?;
-        let name = material.name.as_str().to_string();
-        let material_raw = MaterialRaw {
-            ambient: Vector3::from_slice(&material.ambient),
-            diffuse: Vector3::from_slice(&material.diffuse),
-            specular: Vector3::from_slice(&material.specular),
-            shininess: material.shininess,
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, material: &tobj::Material,
+               assets: &mut Assets, problems: &mut Vec<String>) -> Self {
+        let pbr = PbrMaterial::from_mtl(device, queue, material, assets, problems);
+        Self::from_pbr(device, queue, &material.name, pbr)
+    }
+
+    // a rough dielectric colored only by `albedo_texture`, e.g. a palette
+    pub fn from_diffuse_texture(device: &wgpu::Device, queue: &wgpu::Queue, name: &str,
+                                albedo_texture: Handle<Texture>) -> Self {
+        Self::from_pbr(device, queue, name, PbrMaterial {
+            metallic: 0.0,
+            albedo_texture: Some(albedo_texture),
+            ..PbrMaterial::default()
+        })
+    }
+
+    // uploads the parameters and binds the maps, with single texels
+    // standing in for the ones `pbr` doesn't have
+    pub fn from_pbr(device: &wgpu::Device, queue: &wgpu::Queue, name: &str, pbr: PbrMaterial) -> Self {
+        let texel = |color, format, label| Handle::new(Texture::from_color(device, queue, color, format, Some(label)));
+        let albedo_texture = pbr.albedo_texture.unwrap_or_else(
+            || texel([255; 4], wgpu::TextureFormat::Rgba8UnormSrgb, "albedo_texture"));
+        let metallic_roughness_texture = pbr.metallic_roughness_texture.unwrap_or_else(
+            || texel([255; 4], wgpu::TextureFormat::Rgba8Unorm, "metallic_roughness_texture"));
+        let (bump_texture, bump_multiplier) = pbr.bump_texture.unwrap_or_else(
+            || (texel([0, 0, 0, 255], wgpu::TextureFormat::Rgba8Unorm, "bump_texture"), 1.0));
+        let dissolve_texture = pbr.dissolve_texture.unwrap_or_else(
+            || texel([255; 4], wgpu::TextureFormat::Rgba8Unorm, "dissolve_texture"));
+        let (normal_texture, normal_scale) = pbr.normal_texture.unwrap_or_else(
+            || (texel(FLAT_NORMAL, wgpu::TextureFormat::Rgba8Unorm, "normal_texture"), 1.0));
+        let occlusion_texture = pbr.occlusion_texture.unwrap_or_else(
+            || texel([255; 4], wgpu::TextureFormat::Rgba8Unorm, "occlusion_texture"));
+        let emissive_texture = pbr.emissive_texture.unwrap_or_else(
+            || texel([255; 4], wgpu::TextureFormat::Rgba8UnormSrgb, "emissive_texture"));
+        let displacement_texture = pbr.displacement_texture.unwrap_or_else(
+            || texel([0, 0, 0, 255], wgpu::TextureFormat::Rgba8Unorm, "displacement_texture"));
+        let factors = Factors {
+            albedo: pbr.albedo,
+            metallic: pbr.metallic.clamp(0.0, 1.0),
+            roughness: pbr.roughness.clamp(0.0, 1.0),
+            emissive: pbr.emissive,
+            occlusion_strength: pbr.occlusion_strength,
+            unlit: pbr.unlit,
+            bump_multiplier,
+            normal_scale,
+            displacement: pbr.displacement,
         };
+        Self::from_parts(device, name.to_string(), factors, [
+            albedo_texture,
+            metallic_roughness_texture,
+            bump_texture,
+            dissolve_texture,
+            normal_texture,
+            occlusion_texture,
+            emissive_texture,
+            displacement_texture,
+        ])
+    }
+
+    // binds the maps in the order of `create_texture_bind_group_layout`
+    pub(crate) fn from_parts(device: &wgpu::Device, name: String, factors: Factors,
+                             textures: [Handle<Texture>; 8]) -> Self {
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some(format!("{} uniform buffer", name).as_str()),
-            contents: material_raw.as_std140().as_bytes(),
+            contents: factors.raw().as_std140().as_bytes(),
             usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
         });
-        let bind_group_layout = device.create_bind_group_layout(
+        let bind_group_layout = Self::create_bind_group_layout(
+            device, Some(format!("{} bind group layout", name).as_str()));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(buffer.slice(..)),
+            },],
+            label: Some(format!("{} bind group", name).as_str()),
+        });
+
+        let texture_bind_group_layout = Self::create_texture_bind_group_layout(device);
+        let texture_bind_group = create_texture_bind_group(
+            device, &texture_bind_group_layout, &name, textures.iter().map(|texture| &**texture));
+
+        let [albedo_texture, metallic_roughness_texture, bump_texture, dissolve_texture,
+             normal_texture, occlusion_texture, emissive_texture, displacement_texture] = textures;
+        Self {
+            albedo_texture,
+            metallic_roughness_texture,
+            bump_texture,
+            dissolve_texture,
+            normal_texture,
+            occlusion_texture,
+            emissive_texture,
+            displacement_texture,
+            name,
+            factors,
+            edits: MaterialEdits::default(),
+            buffer,
+            texture_bind_group_layout,
+            texture_bind_group,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    // writes new parameters to the GPU, drawn with from the next frame
+    pub fn set_factors(&mut self, queue: &wgpu::Queue, factors: Factors) {
+        queue.write_buffer(&self.buffer, 0, factors.raw().as_std140().as_bytes());
+        self.factors = factors;
+        self.edits.factors = Some(factors);
+    }
+
+    // replaces map `slot`, see `MAP_NAMES`, with `texture` loaded from
+    // `path`. it's sampled the way the map it replaces was
+    pub fn set_map(&mut self, device: &wgpu::Device, slot: usize, texture: Handle<Texture>, path: &str) {
+        let maps = self.textures_mut();
+        let map = &mut *maps[slot];
+        *map = if texture.sampler_desc == map.sampler_desc {
+            texture
+        } else {
+            Handle::new(texture.with_sampler(device, map.sampler_desc))
+        };
+        self.rebind_textures(device);
+        self.edits.maps.insert(MAP_NAMES[slot].to_string(), path.to_string());
+    }
+
+    // samples every map with `desc`, e.g. repeating them or filtering them
+    // anisotropically. maps shared with other materials keep their images
+    // but get samplers of their own
+    pub fn set_sampler(&mut self, device: &wgpu::Device, desc: SamplerDesc) {
+        self.map_samplers(device, |_| desc);
+    }
+
+    // filters every map with `anisotropy` samples, keeping the rest of its
+    // sampler. anything above 1 turns on linear filtering, which
+    // anisotropic filtering needs
+    pub fn set_anisotropy(&mut self, device: &wgpu::Device, anisotropy: u8) {
+        self.map_samplers(device, |desc| match anisotropy {
+            0 | 1 => SamplerDesc { anisotropy: 1, ..desc },
+            _ => SamplerDesc {
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+                anisotropy,
+                ..desc
+            },
+        });
+    }
+
+    fn map_samplers<F: Fn(SamplerDesc) -> SamplerDesc>(&mut self, device: &wgpu::Device, f: F) {
+        for texture in self.textures_mut() {
+            *texture = Handle::new(texture.with_sampler(device, f(texture.sampler_desc)));
+        }
+        self.rebind_textures(device);
+    }
+
+    // after maps were replaced through `textures_mut`
+    pub(crate) fn rebind_textures(&mut self, device: &wgpu::Device) {
+        self.texture_bind_group = create_texture_bind_group(
+            device, &self.texture_bind_group_layout, &self.name, self.textures().iter().copied());
+    }
+
+    // in the order of `create_texture_bind_group_layout`
+    pub(crate) fn textures(&self) -> [&Texture; 8] {
+        [
+            &self.albedo_texture,
+            &self.metallic_roughness_texture,
+            &self.bump_texture,
+            &self.dissolve_texture,
+            &self.normal_texture,
+            &self.occlusion_texture,
+            &self.emissive_texture,
+            &self.displacement_texture,
+        ]
+    }
+
+    pub(crate) fn textures_mut(&mut self) -> [&mut Handle<Texture>; 8] {
+        [
+            &mut self.albedo_texture,
+            &mut self.metallic_roughness_texture,
+            &mut self.bump_texture,
+            &mut self.dissolve_texture,
+            &mut self.normal_texture,
+            &mut self.occlusion_texture,
+            &mut self.emissive_texture,
+            &mut self.displacement_texture,
+        ]
+    }
+
+    pub fn create_bind_group_layout(device: &wgpu::Device, label: Option<&str>) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(
             &wgpu::BindGroupLayoutDescriptor {
                 entries: &[
+                    // the vertex shaders read the displacement
                     wgpu::BindGroupLayoutEntry {
                         binding: 0,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
                         ty: wgpu::BindingType::UniformBuffer {
                             dynamic: false,
                             min_binding_size: None,
@@ -43,30 +456,241 @@ impl Material {
                         count: None,
                     },
                 ],
-                label: Some(format!("{} bind group layout", name).as_str()),
+                label,
             }
-        );
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::Buffer(buffer.slice(..)),
-            },],
-            label: Some(format!("{} bind group", name).as_str()),
-        });
-        Ok(Self {
-            diffuse_texture,
-            name,
-            bind_group_layout,
-            bind_group,
-        })
+        )
+    }
+
+    // albedo, metallic-roughness, bump, dissolve, normal, occlusion,
+    // emissive and displacement maps, each a texture and sampler. the last
+    // is sampled by the vertex shaders
+    pub fn create_texture_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let visibility = |binding| match binding {
+            14 | 15 => wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+            _ => wgpu::ShaderStage::FRAGMENT,
+        };
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: visibility(binding),
+            ty: wgpu::BindingType::SampledTexture {
+                multisampled: false,
+                dimension: wgpu::TextureViewDimension::D2,
+                component_type: wgpu::TextureComponentType::Float,
+            },
+            count: None,
+        };
+        let sampler = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: visibility(binding),
+            ty: wgpu::BindingType::Sampler {
+                comparison: false,
+            },
+            count: None,
+        };
+        device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    texture(0), sampler(1),
+                    texture(2), sampler(3),
+                    texture(4), sampler(5),
+                    texture(6), sampler(7),
+                    texture(8), sampler(9),
+                    texture(10), sampler(11),
+                    texture(12), sampler(13),
+                    texture(14), sampler(15),
+                ],
+                label: Some("material_texture_bind_group_layout"),
+            }
+        )
+    }
+}
+
+// a texture and its sampler per map
+fn create_texture_bind_group<'a>(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, name: &str,
+                                 textures: impl Iterator<Item = &'a Texture>) -> wgpu::BindGroup {
+    let entries: Vec<wgpu::BindGroupEntry> = textures.enumerate().flat_map(|(i, texture)| {
+        vec![
+            wgpu::BindGroupEntry {
+                binding: i as u32 * 2,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: i as u32 * 2 + 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            },
+        ]
+    }).collect();
+    device.create_bind_group(
+        &wgpu::BindGroupDescriptor {
+            layout,
+            entries: &entries,
+            label: Some(format!("{} texture bind group", name).as_str()),
+        }
+    )
+}
+
+// the image files an mtl material's maps are loaded from
+pub(crate) fn map_paths(material: &tobj::Material) -> Vec<String> {
+    let param = |key: &str| material.unknown_param.get(key).map_or("", String::as_str);
+    let statements = [
+        material.diffuse_texture.as_str(),
+        material.dissolve_texture.as_str(),
+        material.normal_texture.as_str(),
+        param("norm"),
+        param("map_Ke"),
+        param("map_ao"),
+        param("map_Pr"),
+        param("map_Pm"),
+        param("disp"),
+    ];
+    statements.iter()
+        .filter_map(|statement| parse_texture_map(statement))
+        .map(|(file, _)| format!("data/{}", file))
+        .collect()
+}
+
+// the decoded image an mtl statement refers to, if there is one, and its
+// `-bm` multiplier
+fn load_map_image(statement: &str) -> Result<Option<(image::RgbaImage, f32)>> {
+    match parse_texture_map(statement) {
+        Some((file, bump_multiplier)) => {
+            let path = format!("data/{}", file);
+            let image = Texture::load_image(&path).with_context(|| format!("Failed to load {}", path))?;
+            Ok(Some((image, bump_multiplier)))
+        }
+        None => Ok(None),
+    }
+}
+
+// combines separate roughness and metalness maps, both read from red, into
+// the gltf layout. a missing one is white so only its factor counts
+fn metallic_roughness_image(roughness: Option<image::RgbaImage>,
+                            metallic: Option<image::RgbaImage>) -> Option<image::RgbaImage> {
+    let (width, height) = match (&roughness, &metallic) {
+        (None, None) => return None,
+        (Some(image), _) | (None, Some(image)) => image.dimensions(),
+    };
+    // sampled at the resolution of the first map
+    let fit = |image: Option<image::RgbaImage>| image.map(|image| {
+        if image.dimensions() == (width, height) {
+            image
+        } else {
+            image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle)
+        }
+    });
+    let roughness = fit(roughness);
+    let metallic = fit(metallic);
+    let red = |image: &Option<image::RgbaImage>, x, y| image.as_ref().map_or(255, |image| image.get_pixel(x, y)[0]);
+    Some(image::RgbaImage::from_fn(width, height, |x, y| {
+        image::Rgba([0, red(&roughness, x, y), red(&metallic, x, y), 255])
+    }))
+}
+
+// a map and its multiplier or scale
+type ScaledMap = Option<(Handle<Texture>, f32)>;
+
+// tobj reads `bump` and `map_Bump` into `normal_texture`, and exporters
+// put both height maps and tangent space normal maps there. `norm` is
+// always a normal map, otherwise the image tells them apart. `-bm` scales
+// either. returns the bump and the normal map
+fn load_bump_maps(device: &wgpu::Device, queue: &wgpu::Queue, material: &tobj::Material,
+                  problems: &mut Vec<String>) -> (ScaledMap, ScaledMap) {
+    let norm = material.unknown_param.get("norm").map_or("", String::as_str);
+    let mut bump = None;
+    let mut normal = None;
+    for (statement, is_norm) in [(norm, true), (material.normal_texture.as_str(), false)].iter() {
+        match load_map_image(statement) {
+            Ok(Some(map)) if *is_norm || (normal.is_none() && looks_like_normal_map(&map.0)) => normal = Some(map),
+            Ok(Some(map)) => bump = Some(map),
+            Ok(None) => {}
+            Err(error) => problems.push(format!("material {}: {}: {:#}", material.name,
+                                                if *is_norm { "normal_texture" } else { "bump_texture" }, error)),
+        }
+    }
+    let upload = |map: Option<(image::RgbaImage, f32)>, label| map.map(|(image, multiplier)| {
+        let texture = Texture::from_image(device, queue, &image, wgpu::TextureFormat::Rgba8Unorm, Some(label));
+        (Handle::new(texture), multiplier)
+    });
+    (upload(bump, "bump_texture"), upload(normal, "normal_texture"))
+}
+
+// tangent space normal maps are mostly blue, their red and green around
+// the middle. height maps are grey
+fn looks_like_normal_map(image: &image::RgbaImage) -> bool {
+    // a few thousand texels are plenty to tell
+    let step = (image.width() as usize * image.height() as usize / 4096).max(1);
+    let mut sum = [0.0f64; 3];
+    let mut count = 0.0;
+    for texel in image.pixels().step_by(step) {
+        for (channel, value) in sum.iter_mut().zip(texel.0.iter()) {
+            *channel += *value as f64 / 255.0;
+        }
+        count += 1.0;
+    }
+    let [r, g, b] = [sum[0] / count, sum[1] / count, sum[2] / count];
+    b > 0.75 && (0.35..0.65).contains(&r) && (0.35..0.65).contains(&g)
+}
+
+// splits a statement like `-bm 0.5 -clamp on bump.png` into the file name,
+// which always comes last, and the bump multiplier
+fn parse_texture_map(statement: &str) -> Option<(&str, f32)> {
+    let tokens: Vec<&str> = statement.split_whitespace().collect();
+    let (file, options) = tokens.split_last()?;
+    let bump_multiplier = options.iter()
+        .position(|option| *option == "-bm")
+        .and_then(|i| options.get(i + 1))
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(1.0);
+    Some((file, bump_multiplier))
+}
+
+fn parse_vec3(value: &str) -> Option<[f32; 3]> {
+    let mut components = value.split_whitespace().map(|c| c.parse::<f32>());
+    let x = components.next()?.ok()?;
+    let y = components.next()?.ok()?;
+    let z = components.next()?.ok()?;
+    Some([x, y, z])
+}
+
+impl Factors {
+    fn raw(&self) -> MaterialRaw {
+        let [r, g, b, dissolve] = self.albedo;
+        MaterialRaw {
+            albedo: Vector3::from_slice(&[r, g, b]),
+            metallic: self.metallic,
+            emissive: Vector3::from_slice(&self.emissive),
+            roughness: self.roughness,
+            occlusion_strength: self.occlusion_strength,
+            dissolve,
+            bump_multiplier: self.bump_multiplier,
+            normal_scale: self.normal_scale,
+            unlit: self.unlit as u32,
+            displacement: match self.displacement.mode {
+                DisplacementMode::None => 0,
+                DisplacementMode::Map => 1,
+                DisplacementMode::Noise => 2,
+            },
+            displacement_scale: self.displacement.scale,
+            displacement_frequency: self.displacement.frequency,
+            displacement_speed: self.displacement.speed,
+        }
     }
 }
 
 #[derive(AsStd140)]
 struct MaterialRaw {
-    ambient: Vector3<f32>,
-    diffuse: Vector3<f32>,
-    specular: Vector3<f32>,
-    shininess: f32,
+    albedo: Vector3<f32>,
+    metallic: f32,
+    emissive: Vector3<f32>,
+    roughness: f32,
+    occlusion_strength: f32,
+    dissolve: f32,
+    bump_multiplier: f32,
+    normal_scale: f32,
+    unlit: u32,
+    // 0 none, 1 map, 2 noise, see `DisplacementMode`
+    displacement: u32,
+    displacement_scale: f32,
+    displacement_frequency: f32,
+    displacement_speed: f32,
 }
@@ -1,4 +1,12 @@
-use crate::vertex::Vertex;
+use crate::collision::Aabb;
+use crate::skin::Skin;
+use crate::vertex::{Vertex, VertexLayout};
+use anyhow::{bail, Result};
+use bytemuck::Zeroable;
+use glam::{Mat4, Quat, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Range;
 use tobj;
 use wgpu;
 use wgpu::util::DeviceExt;
@@ -6,62 +14,551 @@ use wgpu::util::DeviceExt;
 pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
-    pub material: usize,
+    pub submeshes: Vec<SubMesh>,
+    pub num_vertices: u32,
     pub num_indices: u32,
+    // what the vertex buffer holds, for picking pipelines that can draw it
+    pub layout: VertexLayout,
+    // uv units per world unit across its triangles, how much of a texture
+    // one unit of its surface shows. 0 when unknown, see `streaming`
+    pub uv_density: f32,
+}
+
+// a range of the index buffer drawn with a single material
+pub struct SubMesh {
+    pub material: usize,
+    pub indices: Range<u32>,
 }
 
 impl Mesh {
-    pub fn new(device: &wgpu::Device, mesh: &tobj::Mesh, name: &str) -> Self {
+    // merges the parts of an object into a single vertex and index buffer,
+    // keeping one sub-mesh per part so each can use its own material
+    pub fn new(device: &wgpu::Device, parts: &[&tobj::Mesh], name: &str) -> Self {
         let mut vertices: Vec<Vertex> = vec![];
-        for i in 0..mesh.positions.len() / 3 {
-            vertices.push(Vertex {
-                position: [
-                    mesh.positions[i * 3],
-                    mesh.positions[i * 3 + 1],
-                    mesh.positions[i * 3 + 2]
-                ],
-                normal: [
-                    mesh.normals[i * 3],
-                    mesh.normals[i * 3 + 1],
-                    mesh.normals[i * 3 + 2]
-                ],
-                tex_coord: [
-                    mesh.texcoords[i * 2],
-                    mesh.texcoords[i * 2 + 1]
-                ],
+        let mut indices: Vec<u32> = vec![];
+        let mut submeshes: Vec<SubMesh> = vec![];
+        for mesh in parts {
+            let base_vertex = vertices.len() as u32;
+            let start = indices.len() as u32;
+            push_vertices(&mut vertices, mesh);
+            indices.extend(mesh.indices.iter().map(|index| index + base_vertex));
+            submeshes.push(SubMesh {
+                material: mesh.material_id.unwrap_or(0),
+                indices: start..indices.len() as u32,
             });
         }
+        Self::from_vertices(device, &vertices, &indices, submeshes, name)
+    }
+
+    // uploads already built geometry, e.g. generated from voxels
+    pub fn from_vertices(device: &wgpu::Device, vertices: &[Vertex], indices: &[u32],
+                         submeshes: Vec<SubMesh>, name: &str) -> Self {
         let vertex_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some(format!("{} vertex buffer", name).as_str()),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsage::VERTEX,
+                contents: bytemuck::cast_slice(vertices),
+                // read back to bake static models into batches, read by
+                // the picking pass and rewritten by skinning
+                usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_SRC | wgpu::BufferUsage::STORAGE
+                    | wgpu::BufferUsage::COPY_DST,
             });
         let index_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some(format!("{} index buffer", name).as_str()),
-                contents: bytemuck::cast_slice(&mesh.indices),
-                usage: wgpu::BufferUsage::INDEX,
+                contents: bytemuck::cast_slice(indices),
+                usage: wgpu::BufferUsage::INDEX | wgpu::BufferUsage::COPY_SRC | wgpu::BufferUsage::STORAGE,
             });
-        let num_indices = mesh.indices.len() as u32;
         Self {
             vertex_buffer,
             index_buffer,
-            material: mesh.material_id.unwrap_or(0),
-            num_indices,
+            submeshes,
+            num_vertices: vertices.len() as u32,
+            num_indices: indices.len() as u32,
+            layout: Vertex::layout(),
+            uv_density: uv_density(vertices, indices),
+        }
+    }
+}
+
+// the square root of the triangles' uv area over their area in space
+fn uv_density(vertices: &[Vertex], indices: &[u32]) -> f32 {
+    let (mut uv_area, mut area) = (0.0, 0.0);
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+        let (p0, p1, p2) = (Vec3::from(a.position), Vec3::from(b.position), Vec3::from(c.position));
+        area += (p1 - p0).cross(p2 - p0).length();
+        let (u0, u1, u2) = (Vec2::from(a.tex_coord), Vec2::from(b.tex_coord), Vec2::from(c.tex_coord));
+        let (e1, e2) = (u1 - u0, u2 - u0);
+        uv_area += (e1.x * e2.y - e1.y * e2.x).abs();
+    }
+    if area > 0.0 { (uv_area / area).sqrt() } else { 0.0 }
+}
+
+// geometry generated or edited at runtime, drawn with a single material.
+// the vertices and indices stay on the CPU, and `upload` copies only what
+// changed since the last upload into buffers with room to grow, so a mesh
+// can be rewritten every frame without reallocating
+#[derive(Debug, Clone, Default)]
+pub struct DynamicMesh {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    // what has to be written on the next upload
+    dirty_vertices: Option<Range<usize>>,
+    dirty_indices: Option<Range<usize>>,
+    // what the GPU buffers have room for
+    vertex_capacity: usize,
+    index_capacity: usize,
+}
+
+impl DynamicMesh {
+    // the least the buffers are created with, so small meshes can grow a
+    // while before they're reallocated
+    const MIN_CAPACITY: usize = 64;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_vertices(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        let mut mesh = Self::new();
+        mesh.set(vertices, indices);
+        mesh
+    }
+
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_points(self.vertices.iter().map(|vertex| Vec3::from(vertex.position)))
+    }
+
+    // replaces all of the geometry
+    pub fn set(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>) {
+        self.dirty_vertices = if vertices.is_empty() { None } else { Some(0..vertices.len()) };
+        self.dirty_indices = if indices.is_empty() { None } else { Some(0..indices.len()) };
+        self.vertices = vertices;
+        self.indices = indices;
+    }
+
+    pub fn clear(&mut self) {
+        self.set(vec![], vec![]);
+    }
+
+    // appends a piece of geometry, its indices counting from its own first
+    // vertex
+    pub fn push(&mut self, vertices: &[Vertex], indices: &[u32]) {
+        let base = self.vertices.len() as u32;
+        self.write_vertices(self.vertices.len(), vertices);
+        let start = self.indices.len();
+        self.indices.extend(indices.iter().map(|index| index + base));
+        mark(&mut self.dirty_indices, start..self.indices.len());
+    }
+
+    // overwrites the vertices from `start` on, extending the mesh if they
+    // run past its end
+    pub fn write_vertices(&mut self, start: usize, vertices: &[Vertex]) {
+        write(&mut self.vertices, &mut self.dirty_vertices, start, vertices);
+    }
+
+    pub fn write_indices(&mut self, start: usize, indices: &[u32]) {
+        write(&mut self.indices, &mut self.dirty_indices, start, indices);
+    }
+
+    // empty buffers for the mesh to be uploaded into, sized for what it has
+    // now. the first upload writes everything
+    pub fn create_mesh(&mut self, device: &wgpu::Device, name: &str) -> Mesh {
+        self.vertex_capacity = self.vertices.len().max(Self::MIN_CAPACITY).next_power_of_two();
+        self.index_capacity = self.indices.len().max(Self::MIN_CAPACITY).next_power_of_two();
+        self.dirty_vertices = Some(0..self.vertices.len());
+        self.dirty_indices = Some(0..self.indices.len());
+        Mesh {
+            vertex_buffer: create_dynamic_buffer::<Vertex>(
+                device, &format!("{} vertex buffer", name), self.vertex_capacity, wgpu::BufferUsage::VERTEX),
+            index_buffer: create_dynamic_buffer::<u32>(
+                device, &format!("{} index buffer", name), self.index_capacity, wgpu::BufferUsage::INDEX),
+            submeshes: vec![SubMesh { material: 0, indices: 0..0 }],
+            num_vertices: 0,
+            num_indices: 0,
+            layout: Vertex::layout(),
+            uv_density: 0.0,
+        }
+    }
+
+    // writes the changes since the last upload into `mesh`, which must come
+    // from `create_mesh`, reallocating its buffers when the geometry has
+    // outgrown them. returns whether anything changed
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, mesh: &mut Mesh) -> bool {
+        let resized = mesh.num_vertices as usize != self.vertices.len()
+            || mesh.num_indices as usize != self.indices.len();
+        if self.dirty_vertices.is_none() && self.dirty_indices.is_none() && !resized {
+            return false;
+        }
+        if self.vertices.len() > self.vertex_capacity {
+            self.vertex_capacity = self.vertices.len().next_power_of_two();
+            mesh.vertex_buffer = create_dynamic_buffer::<Vertex>(
+                device, "dynamic vertex buffer", self.vertex_capacity, wgpu::BufferUsage::VERTEX);
+            self.dirty_vertices = Some(0..self.vertices.len());
+        }
+        if self.indices.len() > self.index_capacity {
+            self.index_capacity = self.indices.len().next_power_of_two();
+            mesh.index_buffer = create_dynamic_buffer::<u32>(
+                device, "dynamic index buffer", self.index_capacity, wgpu::BufferUsage::INDEX);
+            self.dirty_indices = Some(0..self.indices.len());
+        }
+        if let Some(range) = self.dirty_vertices.take() {
+            let offset = (range.start * std::mem::size_of::<Vertex>()) as wgpu::BufferAddress;
+            queue.write_buffer(&mesh.vertex_buffer, offset, bytemuck::cast_slice(&self.vertices[range]));
+        }
+        if let Some(range) = self.dirty_indices.take() {
+            let offset = (range.start * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+            queue.write_buffer(&mesh.index_buffer, offset, bytemuck::cast_slice(&self.indices[range]));
+        }
+        mesh.num_vertices = self.vertices.len() as u32;
+        mesh.num_indices = self.indices.len() as u32;
+        mesh.submeshes = vec![SubMesh { material: 0, indices: 0..mesh.num_indices }];
+        true
+    }
+}
+
+fn create_dynamic_buffer<T>(device: &wgpu::Device, label: &str, capacity: usize,
+                            usage: wgpu::BufferUsage) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: (capacity * std::mem::size_of::<T>()) as wgpu::BufferAddress,
+        // storage for the picking pass, which reads the geometry itself
+        usage: usage | wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+// copies `items` over `target` from `start`, growing it as needed, and
+// marks what changed
+fn write<T: Copy + Default>(target: &mut Vec<T>, dirty: &mut Option<Range<usize>>, start: usize, items: &[T]) {
+    if items.is_empty() {
+        return;
+    }
+    // a gap left by starting past the end is written too
+    let first = start.min(target.len());
+    let end = start + items.len();
+    if target.len() < end {
+        target.resize(end, T::default());
+    }
+    target[start..end].copy_from_slice(items);
+    mark(dirty, first..end);
+}
+
+fn mark(dirty: &mut Option<Range<usize>>, range: Range<usize>) {
+    if range.is_empty() {
+        return;
+    }
+    *dirty = Some(match dirty.take() {
+        Some(dirty) => dirty.start.min(range.start)..dirty.end.max(range.end),
+        None => range,
+    });
+}
+
+// files without normals get smooth ones averaged from their faces, and
+// without uvs every vertex samples the texture's corner
+fn push_vertices(vertices: &mut Vec<Vertex>, mesh: &tobj::Mesh) {
+    let positions: Vec<Vec3> = mesh.positions.chunks_exact(3).map(|p| Vec3::new(p[0], p[1], p[2])).collect();
+    let normals: Vec<Vec3> = if mesh.normals.len() == positions.len() * 3 {
+        mesh.normals.chunks_exact(3).map(|n| Vec3::new(n[0], n[1], n[2])).collect()
+    } else {
+        smooth_normals(&positions, &mesh.indices)
+    };
+    let tex_coords: Vec<[f32; 2]> = if mesh.texcoords.len() == positions.len() * 2 {
+        mesh.texcoords.chunks_exact(2).map(|t| [t[0], t[1]]).collect()
+    } else {
+        vec![[0.0; 2]; positions.len()]
+    };
+    let tangents = tangents(&positions, &normals, &tex_coords, &mesh.indices);
+    for (((position, normal), tex_coord), tangent) in positions.iter().zip(&normals).zip(&tex_coords).zip(tangents) {
+        vertices.push(Vertex {
+            position: [position.x, position.y, position.z],
+            normal: [normal.x, normal.y, normal.z],
+            tex_coord: *tex_coord,
+            tangent,
+        });
+    }
+}
+
+// what keeps a part of an obj file from being drawn at all
+pub(crate) fn validate(mesh: &tobj::Mesh) -> Result<()> {
+    let count = mesh.positions.len() / 3;
+    if !mesh.indices.chunks_exact(3).remainder().is_empty() {
+        bail!("{} indices don't make whole triangles", mesh.indices.len());
+    }
+    if let Some(index) = mesh.indices.iter().find(|index| **index as usize >= count) {
+        bail!("index {} is past its {} vertices", index, count);
+    }
+    Ok(())
+}
+
+// averages the normals of the faces around each vertex
+pub(crate) fn smooth_normals(positions: &[Vec3], indices: &[u32]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::zero(); positions.len()];
+    for face in indices.chunks_exact(3) {
+        let [a, b, c] = [face[0] as usize, face[1] as usize, face[2] as usize];
+        // not normalized, so larger faces count for more
+        let normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        normals[a] += normal;
+        normals[b] += normal;
+        normals[c] += normal;
+    }
+    normals.into_iter()
+        .map(|normal| if normal.length_squared() > 0.0 { normal.normalize() } else { Vec3::unit_y() })
+        .collect()
+}
+
+// per-vertex tangents for normal mapping, accumulated from how each face's
+// uvs run across it (Lengyel, "Computing Tangent Space Basis Vectors for an
+// Arbitrary Mesh"). vertices whose faces have no usable uvs get any
+// tangent perpendicular to their normal
+pub(crate) fn tangents(positions: &[Vec3], normals: &[Vec3], tex_coords: &[[f32; 2]],
+                       indices: &[u32]) -> Vec<[f32; 4]> {
+    let mut tangents = vec![Vec3::zero(); positions.len()];
+    let mut bitangents = vec![Vec3::zero(); positions.len()];
+    for face in indices.chunks_exact(3) {
+        let [a, b, c] = [face[0] as usize, face[1] as usize, face[2] as usize];
+        let edge1 = positions[b] - positions[a];
+        let edge2 = positions[c] - positions[a];
+        let [du1, dv1] = [tex_coords[b][0] - tex_coords[a][0], tex_coords[b][1] - tex_coords[a][1]];
+        let [du2, dv2] = [tex_coords[c][0] - tex_coords[a][0], tex_coords[c][1] - tex_coords[a][1]];
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < 1e-12 {
+            continue;
+        }
+        let tangent = (edge1 * dv2 - edge2 * dv1) / det;
+        let bitangent = (edge2 * du1 - edge1 * du2) / det;
+        for &vertex in &[a, b, c] {
+            tangents[vertex] += tangent;
+            bitangents[vertex] += bitangent;
         }
     }
+    normals.iter().zip(tangents.iter().zip(&bitangents)).map(|(normal, (tangent, bitangent))| {
+        // made perpendicular to the normal
+        let mut t = *tangent - *normal * normal.dot(*tangent);
+        if t.length_squared() < 1e-12 {
+            let axis = if normal.x.abs() < 0.9 { Vec3::unit_x() } else { Vec3::unit_y() };
+            t = normal.cross(axis);
+        }
+        // only degenerate normals are left without one
+        let t = if t.length_squared() > 0.0 { t.normalize() } else { Vec3::unit_x() };
+        let handedness = if normal.cross(t).dot(*bitangent) < 0.0 { -1.0 } else { 1.0 };
+        [t.x, t.y, t.z, handedness]
+    }).collect()
 }
 
 pub struct Model {
     pub mesh: Mesh,
-    name: String,
+    // in the model's own space, see `world_bounds`
+    pub bounds: Aabb,
+    pub name: String,
+    pub transform: Transform,
+    // values for the mesh shaders' custom parameters by name, see
+    // `params::ShaderParam`
+    pub params: BTreeMap<String, Vec<f32>>,
+    pub flags: ObjectFlags,
+    // moves the vertices with a skeleton, see `Scene::animate`
+    pub skin: Option<Skin>,
+    // the model it hangs off in the outliner, which moves it along, see
+    // `Scene::set_parent`. `transform` stays where it is in the scene
+    pub parent: Option<usize>,
+    // left out of every pass, shadows included
+    pub hidden: bool,
+    // can't be picked or moved
+    pub locked: bool,
+}
+
+// how a model takes part in drawing the frame, kept with its parameters.
+// instanced copies follow their model's flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ObjectFlags {
+    // drawn in its base and emissive colors only, whatever its material
+    pub unlit: bool,
+    // drawn into the shadow maps
+    pub cast_shadows: bool,
+    // darkened by the sun's and the point lights' shadows
+    pub receive_shadows: bool,
+    // left out of the main view, e.g. a simplified proxy casting the
+    // shadow of a detailed model
+    pub shadow_only: bool,
+    // never moves, so it can be merged with others, see
+    // `Scene::batch_static`
+    pub is_static: bool,
+}
+
+impl Default for ObjectFlags {
+    fn default() -> Self {
+        Self { unlit: false, cast_shadows: true, receive_shadows: true, shadow_only: false, is_static: false }
+    }
+}
+
+// where a model is placed in the scene. applied when drawing, so it can
+// change every frame without touching the vertices; walk mode collision
+// still uses the vertices as loaded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::zero(),
+            rotation: Quat::identity(),
+            scale: Vec3::one(),
+        }
+    }
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
+    // shears are lost, which transforms built from these never have
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+        Self { translation, rotation, scale }
+    }
 }
 
 impl Model {
-    pub fn new(device: &wgpu::Device, model: &tobj::Model) -> Self {
-        let name = model.name.as_str();
-        let mesh = Mesh::new(device, &model.mesh, name);
-        Self { mesh, name: name.to_string() }
+    // tobj starts a new model whenever the material changes, `parts` are
+    // the consecutive models sharing a name
+    pub fn new(device: &wgpu::Device, parts: &[&tobj::Model]) -> Self {
+        let name = parts[0].name.as_str();
+        let meshes: Vec<&tobj::Mesh> = parts.iter().map(|part| &part.mesh).collect();
+        let mesh = Mesh::new(device, &meshes, name);
+        let bounds = Aabb::from_points(meshes.iter().flat_map(|mesh| {
+            mesh.positions.chunks_exact(3).map(|p| Vec3::new(p[0], p[1], p[2]))
+        }));
+        Self::from_mesh(mesh, bounds, name)
+    }
+
+    pub fn from_mesh(mesh: Mesh, bounds: Aabb, name: &str) -> Self {
+        Self { mesh, bounds, name: name.to_string(), transform: Transform::default(), params: BTreeMap::new(),
+               flags: ObjectFlags::default(), skin: None, parent: None, hidden: false, locked: false }
+    }
+
+    // seen in the main view, and so by picking and the debug views
+    pub fn is_drawn(&self) -> bool {
+        !self.hidden && !self.flags.shadow_only
+    }
+
+    pub fn casts_shadows(&self) -> bool {
+        !self.hidden && self.flags.cast_shadows
+    }
+
+    // the bounds moved by the transform, grown to stay axis aligned
+    pub fn world_bounds(&self) -> Aabb {
+        if self.bounds.is_empty() || self.transform == Transform::default() {
+            return self.bounds;
+        }
+        let matrix = self.transform.matrix();
+        let Aabb { min, max } = self.bounds;
+        Aabb::from_points((0..8).map(|corner| {
+            let x = if corner & 1 == 0 { min.x } else { max.x };
+            let y = if corner & 2 == 0 { min.y } else { max.y };
+            let z = if corner & 4 == 0 { min.z } else { max.z };
+            matrix.transform_point3(Vec3::new(x, y, z))
+        }))
+    }
+}
+
+// one copy of a model drawn through an `InstanceBuffer`, placed in the
+// scene by its own transform and tinted by `color`
+#[derive(Debug, Clone, Copy)]
+pub struct Instance {
+    pub transform: Transform,
+    pub color: [f32; 4],
+}
+
+impl Instance {
+    pub fn new(transform: Transform) -> Self {
+        Self { transform, color: [1.0; 4] }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct InstanceRaw {
+    model: [[f32; 4]; 4],
+    // the inverse transpose of the model matrix's upper 3x3
+    normal: [[f32; 3]; 3],
+    color: [f32; 4],
+}
+
+impl From<&Instance> for InstanceRaw {
+    fn from(instance: &Instance) -> Self {
+        let model = instance.transform.matrix();
+        let normal = model.inverse().transpose();
+        Self {
+            model: model.to_cols_array_2d(),
+            normal: [normal.x_axis.truncate().into(), normal.y_axis.truncate().into(), normal.z_axis.truncate().into()],
+            color: instance.color,
+        }
+    }
+}
+
+// per-instance transforms and colors in a vertex buffer stepped once per
+// instance, so any number of copies of a mesh take a single draw call
+pub struct InstanceBuffer {
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    count: u32,
+}
+
+impl InstanceBuffer {
+    pub(crate) const STRIDE: wgpu::BufferAddress = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+
+    pub fn new(device: &wgpu::Device, instances: &[Instance]) -> Self {
+        let raw: Vec<InstanceRaw> = instances.iter().map(InstanceRaw::from).collect();
+        // never empty, wgpu doesn't allow zero sized buffers
+        let contents = if raw.is_empty() {
+            vec![InstanceRaw::zeroed()]
+        } else {
+            raw
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("instance_buffer"),
+            contents: bytemuck::cast_slice(&contents),
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        });
+        Self { buffer, capacity: contents.len(), count: instances.len() as u32 }
+    }
+
+    // replaces every instance, growing the buffer if there are more than
+    // it was created with
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[Instance]) {
+        if instances.len() > self.capacity {
+            *self = Self::new(device, instances);
+            return;
+        }
+        let raw: Vec<InstanceRaw> = instances.iter().map(InstanceRaw::from).collect();
+        if !raw.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&raw));
+        }
+        self.count = instances.len() as u32;
+    }
+
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub(crate) fn slice(&self) -> wgpu::BufferSlice<'_> {
+        self.buffer.slice(..self.count as wgpu::BufferAddress * Self::STRIDE)
     }
 }
@@ -0,0 +1,31 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_map;
+layout(set=0, binding=1) uniform sampler s_map;
+layout(set=0, binding=2) uniform Minimap {
+  // xy where the main camera is on the map and zw the way it looks, in
+  // texture coordinates. zw is zero when it looks straight down
+  vec4 m_marker;
+};
+
+const vec3 FRAME_COLOR = vec3(0.9);
+const vec3 MARKER_COLOR = vec3(1.0, 0.3, 0.2);
+
+// the secondary view with a frame around it and the main camera marked
+void main() {
+  vec3 color = texture(sampler2D(t_map, s_map), v_tex_coord).rgb;
+  vec2 edge = min(v_tex_coord, 1.0 - v_tex_coord);
+  if (min(edge.x, edge.y) < 0.01) {
+    color = FRAME_COLOR;
+  }
+  vec2 eye = m_marker.xy;
+  vec2 heading = m_marker.zw;
+  float along = clamp(dot(v_tex_coord - eye, heading), 0.0, 0.08);
+  if (distance(v_tex_coord, eye) < 0.02 || distance(v_tex_coord, eye + heading * along) < 0.006) {
+    color = MARKER_COLOR;
+  }
+  f_color = vec4(color, 1.0);
+}
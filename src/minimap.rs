@@ -0,0 +1,265 @@
+use wgpu;
+use glam::{Mat4, Vec2, Vec3};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use crate::camera::Camera;
+use crate::collision::Aabb;
+use crate::mesh::Model;
+use crate::scene::Scene;
+use crate::texture::{RenderTexture, SamplerDesc};
+
+// the map's side in pixels, and its distance from the window's bottom left
+// corner
+pub const SIZE: u32 = 200;
+const MARGIN: u32 = 16;
+const FOV: f32 = std::f32::consts::FRAC_PI_4;
+
+// what the secondary view looks at
+#[derive(Debug, Clone, Copy)]
+pub enum MinimapView {
+    // straight down at the whole scene, north up
+    TopDown,
+    // from a camera of the application's choosing, its aspect ratio
+    // ignored since the map is square
+    Camera { eye: [f32; 3], center: [f32; 3], up: [f32; 3] },
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MinimapUniforms {
+    // the main camera's position and heading on the map, see minimap.frag
+    marker: [f32; 4],
+}
+
+// a second camera's view of the scene, rendered into a small texture every
+// frame by the renderer and composited into the frame's bottom left corner
+// with the main camera marked on it. `point_at` finds what's under the
+// cursor there, to move the main camera to
+pub struct Minimap {
+    view: MinimapView,
+    texture: RenderTexture,
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Minimap {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, view: MinimapView) -> Self {
+        let texture = RenderTexture::with_size(device, SIZE, SIZE, format, Some("minimap_texture"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        multisampled: false,
+                        dimension: wgpu::TextureViewDimension::D2,
+                        component_type: wgpu::TextureComponentType::Float,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("minimap_bind_group_layout"),
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("minimap_uniform_buffer"),
+            size: std::mem::size_of::<MinimapUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sampler = SamplerDesc::linear().create(device, Some("minimap_sampler"));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+                },
+            ],
+            label: Some("minimap_bind_group"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("minimap_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &layout, format);
+        Self {
+            view,
+            texture,
+            pipeline,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    pub fn view(&self) -> MinimapView {
+        self.view
+    }
+
+    pub fn set_view(&mut self, view: MinimapView) {
+        self.view = view;
+    }
+
+    // what the map is rendered into, `SIZE` pixels square
+    pub fn target(&self) -> &wgpu::TextureView {
+        &self.texture.view
+    }
+
+    // the camera the map is seen from. the top-down one frames the scene's
+    // models, or the main camera's surroundings without any
+    pub fn camera(&self, scene: &Scene, main: &Camera) -> Camera {
+        let (eye, center, up) = match self.view {
+            MinimapView::TopDown => {
+                let corners = scene.models.iter().map(Model::world_bounds).flat_map(|bounds| vec![bounds.min, bounds.max]);
+                let bounds = Aabb::from_points(corners);
+                let (center, radius) = if bounds.is_empty() {
+                    (main.center, 10.0)
+                } else {
+                    (bounds.center(), bounds.radius().max(0.1))
+                };
+                let distance = radius / (FOV * 0.5).tan() + radius;
+                (center + Vec3::unit_y() * distance, center, -Vec3::unit_z())
+            }
+            MinimapView::Camera { eye, center, up } => (Vec3::from(eye), Vec3::from(center), Vec3::from(up)),
+        };
+        let distance = (eye - center).length().max(0.1);
+        Camera {
+            eye,
+            center,
+            up,
+            aspect_ratio: 1.0,
+            fov: FOV,
+            z_near: distance * 0.01,
+            z_far: distance * 4.0,
+        }
+    }
+
+    // marks the main camera on the map seen from `camera`
+    pub fn prepare(&self, queue: &wgpu::Queue, camera: &Camera, main: &Camera) {
+        let view_proj = camera.build_view_projection_matrix();
+        let to_map = |p: Vec3| {
+            let ndc = project(view_proj, p);
+            Vec2::new(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5)
+        };
+        let eye = to_map(main.eye);
+        let heading = to_map(main.eye + (main.center - main.eye).normalize() * 0.01) - eye;
+        let heading = if heading.length_squared() > 0.0 { heading.normalize() } else { Vec2::zero() };
+        let uniforms = MinimapUniforms { marker: [eye.x, eye.y, heading.x, heading.y] };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    // draws the map over the bottom left corner of `target`
+    pub fn composite(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView, size: PhysicalSize<u32>) {
+        let (x, y) = match viewport(size) {
+            Some(corner) => corner,
+            None => return,
+        };
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_viewport(x, y, SIZE as f32, SIZE as f32, 0.0, 1.0);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    // the point of the scene under `cursor` on the map seen from `camera`,
+    // the closest triangle hit or where the view ray crosses y = 0. none when the cursor is off the map or the ray hits neither
+    pub fn point_at(&self, scene: &Scene, camera: &Camera, size: PhysicalSize<u32>,
+                    cursor: PhysicalPosition<f64>) -> Option<Vec3> {
+        let (x, y) = viewport(size)?;
+        let ndc_x = (cursor.x as f32 - x) / SIZE as f32 * 2.0 - 1.0;
+        let ndc_y = 1.0 - (cursor.y as f32 - y) / SIZE as f32 * 2.0;
+        if ndc_x.abs() > 1.0 || ndc_y.abs() > 1.0 {
+            return None;
+        }
+        let inverse = camera.build_view_projection_matrix().inverse();
+        let near = project(inverse, Vec3::new(ndc_x, ndc_y, 0.0));
+        let far = project(inverse, Vec3::new(ndc_x, ndc_y, 1.0));
+        let direction = far - near;
+        if let Some(t) = scene.collision.raycast(near, direction, 1.0) {
+            return Some(near + direction * t);
+        }
+        let t = -near.y / direction.y;
+        if direction.y != 0.0 && (0.0..=1.0).contains(&t) {
+            Some(near + direction * t)
+        } else {
+            None
+        }
+    }
+}
+
+// `p` through a projective transform, divided by w
+fn project(matrix: Mat4, p: Vec3) -> Vec3 {
+    let p = matrix * p.extend(1.0);
+    p.truncate() / p.w
+}
+
+// the top left of the map's square, none when the window is too small
+fn viewport(size: PhysicalSize<u32>) -> Option<(f32, f32)> {
+    if size.width < SIZE + 2 * MARGIN || size.height < SIZE + 2 * MARGIN {
+        return None;
+    }
+    Some((MARGIN as f32, (size.height - SIZE - MARGIN) as f32))
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("fullscreen.vert.spv"));
+    let fs_module = device.create_shader_module(wgpu::include_spirv!("minimap.frag.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("minimap_pipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        color_states: &[format.into()],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        depth_stencil_state: None,
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[],
+        },
+        sample_count: 1,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
@@ -0,0 +1,49 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_color;
+layout(set=0, binding=1) uniform sampler s_color;
+layout(set=0, binding=2) uniform texture2D t_depth;
+layout(set=0, binding=3)
+  uniform MotionBlur {
+  // from this frame's clip space to the previous frame's
+  mat4 u_reproject;
+  // the share of the frame's motion the shutter is open for
+  float u_shutter;
+  uint u_samples;
+};
+
+// the longest streak, in uv units, so a camera cut doesn't smear the
+// whole screen
+const float MAX_LENGTH = 0.05;
+
+// each pixel's velocity is where its point was on screen last frame, found
+// through the depth buffer like the temporal upscaler's reprojection, and
+// the image is averaged along it centered on the pixel
+void main() {
+  vec2 uv = v_tex_coord;
+  ivec2 depth_size = textureSize(sampler2D(t_depth, s_color), 0);
+  ivec2 texel = clamp(ivec2(uv * vec2(depth_size)), ivec2(0), depth_size - 1);
+  float depth = texelFetch(sampler2D(t_depth, s_color), texel, 0).r;
+  vec4 previous = u_reproject * vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+  vec2 previous_uv = previous.xy / previous.w * vec2(0.5, -0.5) + 0.5;
+  vec2 velocity = (uv - previous_uv) * u_shutter;
+  float streak = length(velocity);
+  if (streak > MAX_LENGTH) {
+    velocity *= MAX_LENGTH / streak;
+  }
+
+  vec4 center = texture(sampler2D(t_color, s_color), uv);
+  if (u_samples < 2 || previous.w <= 0.0) {
+    f_color = center;
+    return;
+  }
+  vec3 sum = vec3(0.0);
+  for (uint i = 0; i < u_samples; i++) {
+    float t = float(i) / float(u_samples - 1) - 0.5;
+    sum += texture(sampler2D(t_color, s_color), clamp(uv + velocity * t, 0.0, 1.0)).rgb;
+  }
+  f_color = vec4(sum / float(u_samples), center.a);
+}
@@ -0,0 +1,211 @@
+use wgpu;
+use glam::Mat4;
+use crate::camera::Camera;
+use crate::config::MotionBlurConfig;
+use crate::graph::Resource;
+use crate::texture::SamplerDesc;
+
+// the main pass' image before it's blurred, sized like the frame
+pub const INPUT: Resource = "motion_blur_input";
+
+// the most samples along a pixel's velocity
+const MAX_SAMPLES: u32 = 32;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MotionBlurUniforms {
+    reproject: [[f32; 4]; 4],
+    shutter: f32,
+    samples: u32,
+    _padding: [f32; 2],
+}
+
+// smears the image along how far each pixel moved since the last frame.
+// like the temporal upscaler, the motion comes from reprojecting the depth
+// buffer with the last frame's camera, so only camera motion blurs
+pub struct MotionBlur {
+    config: MotionBlurConfig,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    previous_view_proj: Option<Mat4>,
+}
+
+impl MotionBlur {
+    // `format` is the main pass' color format
+    pub fn new(device: &wgpu::Device, config: &MotionBlurConfig, format: wgpu::TextureFormat) -> Self {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::SampledTexture {
+                multisampled: false,
+                dimension: wgpu::TextureViewDimension::D2,
+                component_type: wgpu::TextureComponentType::Float,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                texture(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("motion_blur_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("motion_blur_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &layout, format);
+        let sampler = SamplerDesc::linear().create(device, Some("motion_blur_sampler"));
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("motion_blur_uniform_buffer"),
+            size: std::mem::size_of::<MotionBlurUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            config: config.clone(),
+            pipeline,
+            bind_group_layout,
+            sampler,
+            uniform_buffer,
+            previous_view_proj: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    // the first frame after turning it on has nothing to blur towards
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.config.enabled = enabled;
+        self.previous_view_proj = None;
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.config.samples
+    }
+
+    pub fn set_samples(&mut self, samples: u32) {
+        self.config.samples = samples.clamp(1, MAX_SAMPLES);
+    }
+
+    pub fn shutter(&self) -> f32 {
+        self.config.shutter
+    }
+
+    pub fn set_shutter(&mut self, shutter: f32) {
+        self.config.shutter = shutter.max(0.0);
+    }
+
+    // a resize or a new camera, e.g. after loading a scene, isn't motion
+    pub fn reset(&mut self) {
+        self.previous_view_proj = None;
+    }
+
+    // the reprojection from this frame's camera to the last one's
+    pub fn prepare(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let view_proj = camera.build_view_projection_matrix();
+        let previous = self.previous_view_proj.unwrap_or(view_proj);
+        let uniforms = MotionBlurUniforms {
+            reproject: (previous * view_proj.inverse()).to_cols_array_2d(),
+            shutter: self.config.shutter,
+            samples: self.config.samples.clamp(1, MAX_SAMPLES),
+            _padding: [0.0; 2],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+        self.previous_view_proj = Some(view_proj);
+    }
+
+    // writes `input` blurred along the motion found through `depth` over
+    // all of `target`. the depth may be smaller, e.g. while upscaling
+    pub fn render(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder,
+                  (input, depth): (&wgpu::TextureView, &wgpu::TextureView), target: &wgpu::TextureView) {
+        // the input is a transient texture, which may be another one next
+        // frame
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(depth),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Buffer(self.uniform_buffer.slice(..)),
+                },
+            ],
+            label: Some("motion_blur_bind_group"),
+        });
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("fullscreen.vert.spv"));
+    let fs_module = device.create_shader_module(wgpu::include_spirv!("motion_blur.frag.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("motion_blur_pipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        color_states: &[format.into()],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        depth_stencil_state: None,
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[],
+        },
+        sample_count: 1,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
@@ -0,0 +1,268 @@
+use wgpu;
+use glam::{Mat4, Vec3};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use crate::camera::Camera;
+
+// the cube's side in pixels, and its distance from the window's top right
+// corner
+const SIZE: u32 = 120;
+const MARGIN: u32 = 16;
+// what the orthographic view around the cube spans, room for its corners
+// at any rotation
+const EXTENT: f32 = 1.8;
+
+// faces are split in thirds along both sides, the middle cell standing for
+// the face and the outer ones for the edges and corners they touch
+const THIRD: f32 = 1.0 / 3.0;
+
+const HOVER_COLOR: [f32; 3] = [1.0, 0.8, 0.2];
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CubeVertex {
+    position: [f32; 3],
+    color: [f32; 3],
+}
+
+// a cube in the window's corner turned like the scene, showing which way
+// the camera looks. its faces, edges and corners are the directions
+// `region_at` finds under the cursor, to snap the orbit camera to. drawn
+// by the renderer after everything else with the debug line shaders
+pub struct NavCube {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    vertex_count: u32,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    // the region drawn highlighted
+    hovered: Option<Vec3>,
+}
+
+impl NavCube {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX,
+                ty: wgpu::BindingType::UniformBuffer {
+                    dynamic: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("nav_cube_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("nav_cube_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &layout, format);
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("nav_cube_uniform_buffer"),
+            size: std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+            }],
+            label: Some("nav_cube_bind_group"),
+        });
+        let vertices = cube_vertices(None);
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("nav_cube_vertex_buffer"),
+            size: (vertices.len() * std::mem::size_of::<CubeVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+        Self {
+            pipeline,
+            vertex_buffer,
+            vertex_count: vertices.len() as u32,
+            uniform_buffer,
+            bind_group,
+            hovered: None,
+        }
+    }
+
+    // highlights the face, edge or corner `region_at` returned
+    pub fn set_hovered(&mut self, queue: &wgpu::Queue, region: Option<Vec3>) {
+        if region != self.hovered {
+            self.hovered = region;
+            queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&cube_vertices(region)));
+        }
+    }
+
+    // the direction from the cube's center to the face, edge or corner
+    // under `cursor`, with components of -1, 0 or 1, or none when the cursor
+    // isn't over the cube
+    pub fn region_at(&self, camera: &Camera, size: PhysicalSize<u32>, cursor: PhysicalPosition<f64>) -> Option<Vec3> {
+        let (x, y) = viewport(size)?;
+        let ndc_x = (cursor.x as f32 - x) / SIZE as f32 * 2.0 - 1.0;
+        let ndc_y = 1.0 - (cursor.y as f32 - y) / SIZE as f32 * 2.0;
+        if ndc_x.abs() > 1.0 || ndc_y.abs() > 1.0 {
+            return None;
+        }
+        let inverse = view_projection(camera).inverse();
+        let near = inverse.transform_point3(Vec3::new(ndc_x, ndc_y, 0.0));
+        let far = inverse.transform_point3(Vec3::new(ndc_x, ndc_y, 1.0));
+        let hit = enter_cube(near, far - near)?;
+
+        let axis = (0..3).fold(0, |best, axis| if hit[axis].abs() > hit[best].abs() { axis } else { best });
+        let mut region = Vec3::zero();
+        for i in 0..3 {
+            if i == axis || hit[i].abs() > THIRD {
+                region[i] = hit[i].signum();
+            }
+        }
+        Some(region)
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue, camera: &Camera) {
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&view_projection(camera).to_cols_array_2d()));
+    }
+
+    // draws over whatever `target` holds
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView, size: PhysicalSize<u32>) {
+        let (x, y) = match viewport(size) {
+            Some(corner) => corner,
+            None => return,
+        };
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_viewport(x, y, SIZE as f32, SIZE as f32, 0.0, 1.0);
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+// the top left of the cube's square, none when the window is too small
+fn viewport(size: PhysicalSize<u32>) -> Option<(f32, f32)> {
+    if size.width < SIZE + 2 * MARGIN || size.height < SIZE + 2 * MARGIN {
+        return None;
+    }
+    Some(((size.width - SIZE - MARGIN) as f32, MARGIN as f32))
+}
+
+// looks at the cube from the camera's direction, without its position or
+// perspective
+fn view_projection(camera: &Camera) -> Mat4 {
+    let offset = camera.eye - camera.center;
+    let direction = if offset.length_squared() > 0.0 { offset.normalize() } else { Vec3::unit_z() };
+    let view = Mat4::look_at_rh(direction * EXTENT * 2.0, Vec3::zero(), camera.up);
+    let proj = Mat4::orthographic_rh(-EXTENT, EXTENT, -EXTENT, EXTENT, 0.1, EXTENT * 4.0);
+    proj * view
+}
+
+// where the ray first enters the cube from -1 to 1, if it hits it
+fn enter_cube(origin: Vec3, direction: Vec3) -> Option<Vec3> {
+    let (mut enter, mut exit) = (f32::NEG_INFINITY, f32::INFINITY);
+    for i in 0..3 {
+        if direction[i].abs() < f32::EPSILON {
+            if origin[i].abs() > 1.0 {
+                return None;
+            }
+            continue;
+        }
+        let (a, b) = ((-1.0 - origin[i]) / direction[i], (1.0 - origin[i]) / direction[i]);
+        enter = enter.max(a.min(b));
+        exit = exit.min(a.max(b));
+    }
+    if enter > exit || exit < 0.0 {
+        return None;
+    }
+    Some(origin + direction * enter.max(0.0))
+}
+
+// every face as three by three cells, colored by the axis it faces with
+// the edges and corners lighter
+fn cube_vertices(hovered: Option<Vec3>) -> Vec<CubeVertex> {
+    let axes = [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()];
+    let bounds = [(-1.0, -THIRD), (-THIRD, THIRD), (THIRD, 1.0)];
+    let mut vertices = vec![];
+    for axis in 0..3 {
+        for &sign in &[1.0f32, -1.0] {
+            let normal = axes[axis] * sign;
+            // counterclockwise seen from outside
+            let (u, v) = if sign > 0.0 {
+                (axes[(axis + 1) % 3], axes[(axis + 2) % 3])
+            } else {
+                (axes[(axis + 2) % 3], axes[(axis + 1) % 3])
+            };
+            let mut base = [0.25; 3];
+            base[axis] = if sign > 0.0 { 0.8 } else { 0.45 };
+            for (i, (u0, u1)) in bounds.iter().enumerate() {
+                for (j, (v0, v1)) in bounds.iter().enumerate() {
+                    let region = normal + u * (i as f32 - 1.0) + v * (j as f32 - 1.0);
+                    let color = if Some(region) == hovered {
+                        HOVER_COLOR
+                    } else if i == 1 && j == 1 {
+                        base
+                    } else {
+                        base.map(|c| c + (1.0 - c) * 0.3)
+                    };
+                    let corner = |a: f32, b: f32| CubeVertex { position: (normal + u * a + v * b).into(), color };
+                    vertices.extend_from_slice(&[
+                        corner(*u0, *v0), corner(*u1, *v0), corner(*u1, *v1),
+                        corner(*u0, *v0), corner(*u1, *v1), corner(*u0, *v1),
+                    ]);
+                }
+            }
+        }
+    }
+    vertices
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("lines.vert.spv"));
+    let fs_module = device.create_shader_module(wgpu::include_spirv!("lines.frag.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("nav_cube_pipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        // the cube is convex, so culling its back faces leaves no need for
+        // a depth buffer
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::Back,
+            ..Default::default()
+        }),
+        color_states: &[format.into()],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        depth_stencil_state: None,
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[wgpu::VertexBufferDescriptor {
+                stride: std::mem::size_of::<CubeVertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::InputStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float3, 1 => Float3],
+            }],
+        },
+        sample_count: 1,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
@@ -0,0 +1,8 @@
+#version 450
+
+layout(location=0) out float f_count;
+
+// added up by the blend state, one per fragment
+void main() {
+  f_count = 1.0;
+}
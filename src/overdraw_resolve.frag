@@ -0,0 +1,25 @@
+#version 450
+
+// the count shown as the last color of the ramp
+#define MAX_OVERDRAW 8.0
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_count;
+layout(set=0, binding=1) uniform sampler s_count;
+
+// black where nothing was drawn, then blue, green, yellow and red as more
+// fragments land on the same pixel
+vec3 ramp(float t) {
+  const vec3 colors[5] = vec3[](
+    vec3(0.0), vec3(0.1, 0.2, 1.0), vec3(0.1, 0.9, 0.2), vec3(1.0, 0.9, 0.1), vec3(1.0, 0.1, 0.1));
+  float x = clamp(t, 0.0, 1.0) * 4.0;
+  int i = min(int(x), 3);
+  return mix(colors[i], colors[i + 1], x - float(i));
+}
+
+void main() {
+  float count = texture(sampler2D(t_count, s_count), v_tex_coord).r;
+  f_color = vec4(ramp(count / MAX_OVERDRAW), 1.0);
+}
@@ -0,0 +1,263 @@
+use crate::assets::{Assets, Handle};
+use crate::batching::cast;
+use crate::collision::{Aabb, Triangle};
+use crate::config::SceneConfig;
+use crate::loading;
+use crate::material::{Factors, Material, SRGB_MAPS};
+use crate::mesh::{Mesh, Model, ObjectFlags, SubMesh, Transform};
+use crate::readback::Readbacks;
+use crate::scene::Scene;
+use crate::texture::Texture;
+use crate::vertex::Vertex;
+use anyhow::{bail, Context, Result};
+use glam::{Quat, Vec3};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use wgpu;
+
+// written ahead of the pack and bumped whenever what's below changes, so
+// packs baked by another version are refused instead of misread
+const VERSION: u32 = 2;
+
+// a scene's meshes, materials and maps once they're loaded, fixed up by
+// the import options and batched, so loading it again is only uploads
+#[derive(Serialize, Deserialize)]
+struct Pack {
+    models: Vec<PackedModel>,
+    materials: Vec<PackedMaterial>,
+    // shared by the materials using them
+    textures: Vec<PackedTexture>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PackedModel {
+    name: String,
+    // `Vertex`es and u32 indices, as they are in the GPU buffers
+    vertices: Vec<u8>,
+    indices: Vec<u8>,
+    // the material and index range of each sub-mesh
+    submeshes: Vec<(usize, u32, u32)>,
+    translation: [f32; 3],
+    rotation: [f32; 4],
+    scale: [f32; 3],
+    params: BTreeMap<String, Vec<f32>>,
+    flags: ObjectFlags,
+    // see `Model::parent`
+    parent: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PackedMaterial {
+    name: String,
+    factors: Factors,
+    // indices into `Pack::textures`, in the order of `Material::textures`
+    textures: [usize; 8],
+}
+
+// the largest level of a map, in 8 bit rgba
+#[derive(Serialize, Deserialize)]
+struct PackedTexture {
+    width: u32,
+    height: u32,
+    srgb: bool,
+    pixels: Vec<u8>,
+}
+
+// what a pack uploads to, before it's made into a scene
+pub(crate) struct Unpacked {
+    pub models: Vec<Model>,
+    pub materials: Vec<Material>,
+    pub triangles: Vec<Triangle>,
+}
+
+// reads a scene description in ron, the same fields as the `[scene]`
+// table of wr3d.toml
+pub fn read_scene(path: &Path) -> Result<SceneConfig> {
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    ron::de::from_str(&source).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+// loads the scene `path` describes and writes what it loaded to `output`.
+// raymarched shapes and curves aren't baked, they stay in their own files
+pub fn bake(device: &wgpu::Device, queue: &wgpu::Queue, path: &Path, output: &Path) -> Result<()> {
+    let config = read_scene(path)?;
+    let scene = loading::load(device, queue, &config, &mut Assets::new())?;
+    for problem in &scene.stats.problems {
+        warn!("{}", problem);
+    }
+    write(device, queue, &scene, output)
+}
+
+// reads the scene's buffers and maps back from the GPU into a pack at `path`
+pub fn write(device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene, path: &Path) -> Result<()> {
+    if !scene.instances.is_empty() {
+        bail!("Instanced models can't be baked");
+    }
+    if let Some(model) = scene.models.iter().find(|model| model.skin.is_some()) {
+        bail!("Skinned models can't be baked, {} is", model.name);
+    }
+    let mut readbacks = Readbacks::new();
+    let buffers: Vec<_> = scene.models.iter()
+        .map(|model| {
+            let mesh = &model.mesh;
+            let vertex_size = (mesh.num_vertices as usize * std::mem::size_of::<Vertex>()) as wgpu::BufferAddress;
+            let index_size = (mesh.num_indices as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress;
+            (readbacks.read_buffer(device, queue, &mesh.vertex_buffer, 0, vertex_size),
+             readbacks.read_buffer(device, queue, &mesh.index_buffer, 0, index_size))
+        })
+        .collect();
+    // maps shared between materials are stored once
+    let mut textures: Vec<(&Texture, bool)> = vec![];
+    let mut materials = vec![];
+    for material in &scene.materials {
+        let mut indices = [0; 8];
+        for (slot, texture) in material.textures().iter().enumerate() {
+            indices[slot] = match textures.iter().position(|(other, _)| other.shares_image(texture)) {
+                Some(index) => index,
+                None => {
+                    textures.push((texture, SRGB_MAPS.contains(&slot)));
+                    textures.len() - 1
+                }
+            };
+        }
+        materials.push(PackedMaterial { name: material.name.clone(), factors: material.factors, textures: indices });
+    }
+    // streamed maps have their whole image on the CPU already
+    let images: Vec<_> = textures.iter()
+        .map(|(texture, _)| match &texture.mips {
+            Some((mips, _)) => Err(mips.level(0).clone()),
+            None => Ok(texture.read(device, queue, &mut readbacks)),
+        })
+        .collect();
+    readbacks.wait(device);
+
+    let mut models = vec![];
+    for (model, (vertices, indices)) in scene.models.iter().zip(buffers) {
+        models.push(PackedModel {
+            name: model.name.clone(),
+            vertices: vertices.recv()??,
+            indices: indices.recv()??,
+            submeshes: model.mesh.submeshes.iter()
+                .map(|submesh| (submesh.material, submesh.indices.start, submesh.indices.end))
+                .collect(),
+            translation: model.transform.translation.into(),
+            rotation: model.transform.rotation.into(),
+            scale: model.transform.scale.into(),
+            params: model.params.clone(),
+            flags: model.flags,
+            parent: model.parent,
+        });
+    }
+    let mut packed_textures = vec![];
+    for ((_, srgb), image) in textures.iter().zip(images) {
+        let image = match image {
+            Ok(receiver) => receiver.recv()??,
+            Err(image) => image,
+        };
+        packed_textures.push(PackedTexture {
+            width: image.width(),
+            height: image.height(),
+            srgb: *srgb,
+            pixels: image.into_raw(),
+        });
+    }
+    let pack = Pack { models, materials, textures: packed_textures };
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    bincode::serialize_into(&mut writer, &VERSION)
+        .and_then(|()| bincode::serialize_into(&mut writer, &pack))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    writer.flush().with_context(|| format!("Failed to write {}", path.display()))
+}
+
+// uploads a pack written by `write`, sharing its maps through `assets`
+pub(crate) fn load(device: &wgpu::Device, queue: &wgpu::Queue, path: &str, assets: &mut Assets) -> Result<Unpacked> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let mut reader = BufReader::new(file);
+    let version: u32 = bincode::deserialize_from(&mut reader).with_context(|| format!("Failed to read {}", path))?;
+    if version != VERSION {
+        bail!("{} was baked by another version (format {}, expected {}), bake it again", path, version, VERSION);
+    }
+    let pack: Pack = bincode::deserialize_from(reader).with_context(|| format!("Failed to read {}", path))?;
+
+    let mut textures = vec![];
+    for (index, texture) in pack.textures.into_iter().enumerate() {
+        let PackedTexture { width, height, srgb, pixels } = texture;
+        let format = if srgb { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm };
+        textures.push(assets.texture_with(device, queue, &format!("{}#{}", path, index), format,
+                                          Some("pack_texture"), || {
+            image::RgbaImage::from_raw(width, height, pixels)
+                .with_context(|| format!("texture {} is smaller than {}x{}", index, width, height))
+        })?);
+    }
+    let mut materials = vec![];
+    for material in pack.materials {
+        let texture = |slot: usize| -> Result<Handle<Texture>> {
+            let index = material.textures[slot];
+            match textures.get(index) {
+                Some(texture) => Ok(texture.clone()),
+                None => bail!("material {} uses texture {}, the pack has {}", material.name, index, textures.len()),
+            }
+        };
+        let maps = [texture(0)?, texture(1)?, texture(2)?, texture(3)?,
+                    texture(4)?, texture(5)?, texture(6)?, texture(7)?];
+        materials.push(Material::from_parts(device, material.name, material.factors, maps));
+    }
+
+    let mut models = vec![];
+    let mut triangles = vec![];
+    for model in pack.models {
+        let vertices = cast::<Vertex>(&model.vertices);
+        let indices = cast::<u32>(&model.indices);
+        if let Some(index) = indices.iter().find(|&&index| index as usize >= vertices.len()) {
+            bail!("model {}: index {} is past its {} vertices", model.name, index, vertices.len());
+        }
+        let mut submeshes = vec![];
+        for (material, start, end) in model.submeshes {
+            if material >= materials.len() || start > end || end as usize > indices.len() {
+                bail!("model {}: a part uses indices {}..{} and material {}, out of range",
+                      model.name, start, end, material);
+            }
+            submeshes.push(SubMesh { material, indices: start..end });
+        }
+        let position = |index: u32| Vec3::from(vertices[index as usize].position);
+        triangles.extend(indices.chunks_exact(3).map(|face| Triangle {
+            a: position(face[0]),
+            b: position(face[1]),
+            c: position(face[2]),
+        }));
+        let mesh = Mesh::from_vertices(device, &vertices, &indices, submeshes, &model.name);
+        let bounds = Aabb::from_points(vertices.iter().map(|vertex| Vec3::from(vertex.position)));
+        let mut loaded = Model::from_mesh(mesh, bounds, &model.name);
+        loaded.transform = Transform {
+            translation: model.translation.into(),
+            rotation: Quat::from(model.rotation),
+            scale: model.scale.into(),
+        };
+        loaded.params = model.params;
+        loaded.flags = model.flags;
+        loaded.parent = model.parent;
+        models.push(loaded);
+    }
+    Ok(Unpacked { models, materials, triangles })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_example_scene() {
+        let config = read_scene(Path::new("data/scene.ron")).unwrap();
+        assert_eq!(config.model, "data/cube.obj");
+        assert_eq!(config.extra_models, vec!["data/cube.gltf".to_string()]);
+        assert!(config.import.auto_scale);
+        // left out fields keep their defaults
+        assert_eq!(config.voxel_size, SceneConfig::default().voxel_size);
+    }
+}
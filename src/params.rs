@@ -0,0 +1,365 @@
+use crate::assets::{Assets, Handle};
+use crate::material::{MaterialEdits, MAP_NAMES, SRGB_MAPS};
+use crate::mesh::ObjectFlags;
+use crate::scene::Scene;
+use crate::texture::Texture;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+// the model block's matrices come first, then the object flags as a
+// `uvec4 u_flags`, and custom parameters go after them up to the end of
+// the model's slot, see `ModelUniforms` in renderer.rs
+const FLAGS_OFFSET: u32 = 128;
+pub(crate) const FIRST_OFFSET: u32 = 144;
+pub(crate) const SLOT_SIZE: u32 = 256;
+
+// spir-v opcodes and decorations the reflection looks at
+const MAGIC: u32 = 0x0723_0203;
+const OP_MEMBER_NAME: u32 = 6;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// a value a mesh shader reads per object, declared in its `Model` block at
+// set 0 binding 1 after the flags, e.g. `vec4 p_tint;`. objects that
+// don't set it read zeros
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderParam {
+    pub name: String,
+    // bytes from the start of the model's slot
+    pub offset: u32,
+    // 1 for a float, up to 4 for a vec4
+    pub components: usize,
+}
+
+enum Type {
+    Float,
+    Vector(u32, u32),
+    Struct(Vec<u32>),
+    Pointer(u32),
+    Other,
+}
+
+// the custom parameters a compiled shader declares. a shader without a
+// model block has none
+pub fn reflect(spirv: &[u32]) -> Result<Vec<ShaderParam>> {
+    if spirv.len() < 5 || spirv[0] != MAGIC {
+        bail!("Not a SPIR-V module");
+    }
+    let mut types = HashMap::new();
+    let mut names = HashMap::new();
+    let mut offsets = HashMap::new();
+    let mut sets = HashMap::new();
+    let mut bindings = HashMap::new();
+    let mut variables = vec![];
+    let mut words = &spirv[5..];
+    while let Some(&first) = words.first() {
+        let (count, opcode) = ((first >> 16) as usize, first & 0xffff);
+        if count == 0 || count > words.len() {
+            bail!("Truncated SPIR-V instruction");
+        }
+        let operands = &words[1..count];
+        words = &words[count..];
+        match (opcode, operands) {
+            (OP_MEMBER_NAME, [id, member, name @ ..]) => {
+                names.insert((*id, *member), string(name));
+            }
+            (OP_TYPE_FLOAT, [id, 32]) => {
+                types.insert(*id, Type::Float);
+            }
+            (OP_TYPE_VECTOR, [id, component, count]) => {
+                types.insert(*id, Type::Vector(*component, *count));
+            }
+            (OP_TYPE_STRUCT, [id, members @ ..]) => {
+                types.insert(*id, Type::Struct(members.to_vec()));
+            }
+            (OP_TYPE_POINTER, [id, _, pointee]) => {
+                types.insert(*id, Type::Pointer(*pointee));
+            }
+            (OP_TYPE_FLOAT, [id, ..]) => {
+                types.insert(*id, Type::Other);
+            }
+            (OP_VARIABLE, [pointer, id, ..]) => variables.push((*pointer, *id)),
+            (OP_DECORATE, [id, DECORATION_DESCRIPTOR_SET, set]) => {
+                sets.insert(*id, *set);
+            }
+            (OP_DECORATE, [id, DECORATION_BINDING, binding]) => {
+                bindings.insert(*id, *binding);
+            }
+            (OP_MEMBER_DECORATE, [id, member, DECORATION_OFFSET, offset]) => {
+                offsets.insert((*id, *member), *offset);
+            }
+            _ => {}
+        }
+    }
+    let block = variables.iter()
+        .find(|(_, id)| sets.get(id) == Some(&0) && bindings.get(id) == Some(&1))
+        .and_then(|(pointer, _)| match types.get(pointer) {
+            Some(Type::Pointer(pointee)) => Some(*pointee),
+            _ => None,
+        });
+    let (block, members) = match block.map(|block| (block, types.get(&block))) {
+        Some((block, Some(Type::Struct(members)))) => (block, members),
+        _ => return Ok(vec![]),
+    };
+    let mut params = vec![];
+    for (member, member_type) in (0u32..).zip(members) {
+        let offset = *offsets.get(&(block, member)).context("Model block member without an offset")?;
+        if offset < FLAGS_OFFSET {
+            continue;
+        }
+        let name = names.get(&(block, member)).cloned()
+            .with_context(|| format!("Model block member at offset {} has no name", offset))?;
+        // shaders that don't read the flags still have to leave room for them
+        if offset < FIRST_OFFSET {
+            if name != "u_flags" {
+                bail!("Parameter {} is where the flags go, declare `uvec4 u_flags;` before it", name);
+            }
+            continue;
+        }
+        let components = match types.get(member_type) {
+            Some(Type::Float) => 1,
+            Some(Type::Vector(component, count)) if matches!(types.get(component), Some(Type::Float)) => {
+                *count as usize
+            }
+            _ => bail!("Parameter {} isn't a float or float vector", name),
+        };
+        if offset + components as u32 * 4 > SLOT_SIZE {
+            bail!("Parameter {} doesn't fit into the {} bytes after the flags", name, SLOT_SIZE - FIRST_OFFSET);
+        }
+        params.push(ShaderParam { name, offset, components });
+    }
+    Ok(params)
+}
+
+// a literal string operand, nul terminated and padded to whole words
+fn string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words.iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// a spir-v file's bytes as words
+pub(crate) fn words(bytes: &[u8]) -> Vec<u32> {
+    bytes.chunks_exact(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect()
+}
+
+// the parameters are kept next to the scene's file, e.g. `data/cube.obj`
+// has `data/cube.params.toml`, a table per model name:
+//
+//     [Cube]
+//     p_tint = [1.0, 0.2, 0.2, 0.5]
+//     p_pulse = 2.0
+//     cast_shadows = false
+//
+// with the model's `ObjectFlags` that differ from the defaults
+pub fn path_for(source: &str) -> PathBuf {
+    Path::new(source).with_extension("params.toml")
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Value {
+    Scalar(f32),
+    Vector(Vec<f32>),
+    Flag(bool),
+}
+
+// the names of the `ObjectFlags` in the parameter files
+const FLAG_NAMES: [&str; 5] = ["unlit", "cast_shadows", "receive_shadows", "shadow_only", "static"];
+
+fn flag<'a>(flags: &'a mut ObjectFlags, name: &str) -> Option<&'a mut bool> {
+    match name {
+        "unlit" => Some(&mut flags.unlit),
+        "cast_shadows" => Some(&mut flags.cast_shadows),
+        "receive_shadows" => Some(&mut flags.receive_shadows),
+        "shadow_only" => Some(&mut flags.shadow_only),
+        "static" => Some(&mut flags.is_static),
+        _ => None,
+    }
+}
+
+// reads the scene's parameter file into its models, if it has one. a name
+// shared by several models sets the first. models the file names that
+// aren't in the scene are reported in its problems
+pub(crate) fn load_into(scene: &mut Scene) -> Result<()> {
+    let path = path_for(&scene.stats.source);
+    if !path.exists() {
+        return Ok(());
+    }
+    let text = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let file: BTreeMap<String, BTreeMap<String, Value>> = toml::from_str(&text)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    for (name, values) in file {
+        let model = match scene.models.iter_mut().find(|model| model.name == name) {
+            Some(model) => model,
+            None => {
+                scene.stats.problems.push(format!("{}: no model {}", path.display(), name));
+                continue;
+            }
+        };
+        for (param, value) in values {
+            let value = match value {
+                Value::Scalar(value) => vec![value],
+                Value::Vector(values) => values,
+                Value::Flag(value) => {
+                    match flag(&mut model.flags, &param) {
+                        Some(flag) => *flag = value,
+                        None => scene.stats.problems.push(format!("{}: no flag {}", path.display(), param)),
+                    }
+                    continue;
+                }
+            };
+            model.params.insert(param, value);
+        }
+    }
+    Ok(())
+}
+
+// writes every model's parameters and the flags it changed to the scene's
+// parameter file, returning its path
+pub(crate) fn save(scene: &Scene) -> Result<PathBuf> {
+    let file: BTreeMap<&str, BTreeMap<&str, Value>> = scene.models.iter()
+        .filter(|model| !model.params.is_empty() || model.flags != ObjectFlags::default())
+        .map(|model| {
+            let mut values: BTreeMap<&str, Value> = model.params.iter()
+                .map(|(param, value)| (param.as_str(), match value.as_slice() {
+                    [value] => Value::Scalar(*value),
+                    values => Value::Vector(values.to_vec()),
+                }))
+                .collect();
+            let (mut flags, mut defaults) = (model.flags, ObjectFlags::default());
+            for name in FLAG_NAMES.iter() {
+                let value = *flag(&mut flags, name).unwrap();
+                if value != *flag(&mut defaults, name).unwrap() {
+                    values.insert(name, Value::Flag(value));
+                }
+            }
+            (model.name.as_str(), values)
+        })
+        .collect();
+    let path = path_for(&scene.stats.source);
+    std::fs::write(&path, toml::to_string(&file)?).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+// the material editor's changes are kept next to the scene's file too,
+// e.g. `data/cube.materials.toml`, a table per material name:
+//
+//     [Brick.factors]
+//     albedo = [1.0, 0.5, 0.5, 1.0]
+//     roughness = 0.4
+//     ...
+//
+//     [Brick.maps]
+//     albedo = "data/brick.png"
+//
+// with all of the parameters once any of them was changed
+pub fn materials_path_for(source: &str) -> PathBuf {
+    Path::new(source).with_extension("materials.toml")
+}
+
+// applies the scene's material file to its materials, if it has one. maps
+// that fail to load and materials the file names that aren't in the scene
+// are reported in its problems
+pub(crate) fn load_materials_into(device: &wgpu::Device, queue: &wgpu::Queue, scene: &mut Scene,
+                                  assets: &mut Assets) -> Result<()> {
+    let path = materials_path_for(&scene.stats.source);
+    if !path.exists() {
+        return Ok(());
+    }
+    let text = std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let file: BTreeMap<String, MaterialEdits> = toml::from_str(&text)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    for (name, edits) in file {
+        let material = match scene.materials.iter_mut().find(|material| material.name == name) {
+            Some(material) => material,
+            None => {
+                scene.stats.problems.push(format!("{}: no material {}", path.display(), name));
+                continue;
+            }
+        };
+        if let Some(factors) = edits.factors {
+            material.set_factors(queue, factors);
+        }
+        for (map, file) in &edits.maps {
+            match load_map(device, queue, assets, map, file) {
+                Ok((slot, texture)) => material.set_map(device, slot, texture, file),
+                Err(error) => scene.stats.problems.push(format!("{}: material {}: {:#}", path.display(), name, error)),
+            }
+        }
+    }
+    Ok(())
+}
+
+// loads `file` through `assets` for the map called `name`, see
+// `MAP_NAMES`, returning the map's slot along with it
+pub(crate) fn load_map(device: &wgpu::Device, queue: &wgpu::Queue, assets: &mut Assets, name: &str,
+                       file: &str) -> Result<(usize, Handle<Texture>)> {
+    let slot = MAP_NAMES.iter().position(|map| *map == name)
+        .with_context(|| format!("No map {}, materials have {}", name, MAP_NAMES.join(", ")))?;
+    let format = if SRGB_MAPS.contains(&slot) {
+        wgpu::TextureFormat::Rgba8UnormSrgb
+    } else {
+        wgpu::TextureFormat::Rgba8Unorm
+    };
+    let texture = assets.texture(device, queue, file, format, Some(&format!("{}_texture", name)))?;
+    Ok((slot, texture))
+}
+
+// writes the changes made to the scene's materials to its material file,
+// returning its path
+pub(crate) fn save_materials(scene: &Scene) -> Result<PathBuf> {
+    let file: BTreeMap<&str, &MaterialEdits> = scene.materials.iter()
+        .filter(|material| material.edits != MaterialEdits::default())
+        .map(|material| (material.name.as_str(), &material.edits))
+        .collect();
+    let path = materials_path_for(&scene.stats.source);
+    std::fs::write(&path, toml::to_string(&file)?).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Displacement, Factors};
+
+    #[test]
+    fn material_edits_round_trip() {
+        let factors = Factors {
+            albedo: [1.0, 0.5, 0.5, 1.0],
+            metallic: 0.0,
+            roughness: 0.4,
+            emissive: [0.0; 3],
+            occlusion_strength: 1.0,
+            unlit: false,
+            bump_multiplier: 1.0,
+            normal_scale: 1.0,
+            displacement: Displacement::default(),
+        };
+        let edits = MaterialEdits {
+            factors: Some(factors),
+            maps: std::iter::once(("albedo".to_string(), "data/brick.png".to_string())).collect(),
+        };
+        let file: BTreeMap<&str, &MaterialEdits> = std::iter::once(("Brick", &edits)).collect();
+        let text = toml::to_string(&file).unwrap();
+        let read: BTreeMap<String, MaterialEdits> = toml::from_str(&text).unwrap();
+        assert_eq!(read["Brick"], edits);
+
+        // a file only assigning maps leaves the parameters as loaded
+        let read: BTreeMap<String, MaterialEdits> = toml::from_str("[Brick.maps]\nnormal = \"n.png\"\n").unwrap();
+        assert_eq!(read["Brick"].factors, None);
+        assert_eq!(read["Brick"].maps["normal"], "n.png");
+    }
+}
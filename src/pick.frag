@@ -0,0 +1,10 @@
+#version 450
+
+layout(location=0) flat in uvec4 v_id;
+
+// the model plus one, the draw, the triangle and the instance
+layout(location=0) out uvec4 f_id;
+
+void main() {
+  f_id = v_id;
+}
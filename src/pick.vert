@@ -0,0 +1,36 @@
+#version 450
+
+// no vertex buffers, the position is pulled from the mesh's buffers so
+// every vertex knows which triangle of the index buffer it's drawn for
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+};
+layout(set=0, binding=1)
+  uniform Model {
+  mat4 u_model;
+  mat4 u_normal;
+};
+layout(set=1, binding=0)
+  uniform Draw {
+  // x the model plus one, y the draw, z its first index
+  uvec4 u_draw;
+  // x the vertex stride, y the position's offset in it, in floats
+  uvec4 u_vertex;
+};
+layout(set=2, binding=0) readonly buffer Vertices {
+  float b_vertices[];
+};
+layout(set=2, binding=1) readonly buffer Indices {
+  uint b_indices[];
+};
+
+layout(location=0) flat out uvec4 v_id;
+
+void main() {
+  uint index = u_draw.z + uint(gl_VertexIndex);
+  uint base = b_indices[index] * u_vertex.x + u_vertex.y;
+  vec3 position = vec3(b_vertices[base], b_vertices[base + 1u], b_vertices[base + 2u]);
+  gl_Position = u_view_proj * u_model * vec4(position, 1.0);
+  v_id = uvec4(u_draw.x, u_draw.y, index / 3u, 0u);
+}
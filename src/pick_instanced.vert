@@ -0,0 +1,33 @@
+#version 450
+
+// pick.vert for instanced models, placed by the instances' matrices
+layout(location=4) in vec4 a_model_0;
+layout(location=5) in vec4 a_model_1;
+layout(location=6) in vec4 a_model_2;
+layout(location=7) in vec4 a_model_3;
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+};
+layout(set=1, binding=0)
+  uniform Draw {
+  uvec4 u_draw;
+  uvec4 u_vertex;
+};
+layout(set=2, binding=0) readonly buffer Vertices {
+  float b_vertices[];
+};
+layout(set=2, binding=1) readonly buffer Indices {
+  uint b_indices[];
+};
+
+layout(location=0) flat out uvec4 v_id;
+
+void main() {
+  uint index = u_draw.z + uint(gl_VertexIndex);
+  uint base = b_indices[index] * u_vertex.x + u_vertex.y;
+  vec3 position = vec3(b_vertices[base], b_vertices[base + 1u], b_vertices[base + 2u]);
+  mat4 model = mat4(a_model_0, a_model_1, a_model_2, a_model_3);
+  gl_Position = u_view_proj * model * vec4(position, 1.0);
+  v_id = uvec4(u_draw.x, u_draw.y, index / 3u, uint(gl_InstanceIndex));
+}
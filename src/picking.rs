@@ -0,0 +1,403 @@
+use crate::culling::Frustum;
+use crate::mesh::InstanceBuffer;
+use crate::readback::Readbacks;
+use crate::renderer::ModelTransforms;
+use crate::scene::Scene;
+use crate::texture::DepthTexture;
+use crate::vertex::VertexAttribute;
+use anyhow::{Context, Result};
+use glam::Mat4;
+use std::sync::mpsc::Receiver;
+use wgpu;
+use wgpu::util::DeviceExt;
+
+// the ids are written as four unsigned integers
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Uint;
+const BYTES_PER_PIXEL: u32 = 16;
+
+// what's drawn at a pixel, down to the triangle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pick {
+    pub model: usize,
+    // the sub-mesh by its position in the model's mesh, and the material
+    // it's drawn with
+    pub submesh: usize,
+    pub material: usize,
+    // in the model's index buffer, its indices start at 3 times this
+    pub triangle: u32,
+    // the copy of an instanced model, 0 for other models
+    pub instance: u32,
+}
+
+// a draw's slot in the ids buffer, see pick.vert
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawIds {
+    // the model plus one, the draw and its first index
+    draw: [u32; 4],
+    // the vertex stride and the position's offset in it, in floats
+    vertex: [u32; 4],
+}
+
+// an id buffer only as large as the picked pixel: the scene is drawn with
+// a projection that blows the pixel up to fill it, and the mesh shaders
+// pull the vertices from the meshes' buffers themselves so each knows the
+// triangle it belongs to
+pub(crate) struct Picker {
+    pipeline: wgpu::RenderPipeline,
+    instanced_pipeline: wgpu::RenderPipeline,
+    model_bind_group_layout: wgpu::BindGroupLayout,
+    ids_bind_group_layout: wgpu::BindGroupLayout,
+    mesh_bind_group_layout: wgpu::BindGroupLayout,
+    camera_buffer: wgpu::Buffer,
+    target: wgpu::Texture,
+    target_view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+}
+
+impl Picker {
+    // the dynamic offsets of the ids buffer are aligned to this
+    const SLOT_SIZE: wgpu::BufferAddress = wgpu::BIND_BUFFER_ALIGNMENT;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let model_bind_group_layout = ModelTransforms::create_bind_group_layout(device);
+        let ids_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX,
+                ty: wgpu::BindingType::UniformBuffer {
+                    dynamic: true,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("pick_ids_bind_group_layout"),
+        });
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::VERTEX,
+            ty: wgpu::BindingType::StorageBuffer {
+                dynamic: false,
+                min_binding_size: None,
+                readonly: true,
+            },
+            count: None,
+        };
+        let mesh_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[storage(0), storage(1)],
+            label: Some("pick_mesh_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("pick_pipeline_layout"),
+            bind_group_layouts: &[&model_bind_group_layout, &ids_bind_group_layout, &mesh_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let vs_module = device.create_shader_module(wgpu::include_spirv!("pick.vert.spv"));
+        let instanced_vs_module = device.create_shader_module(wgpu::include_spirv!("pick_instanced.vert.spv"));
+        let fs_module = device.create_shader_module(wgpu::include_spirv!("pick.frag.spv"));
+        let pipeline = create_pipeline(device, &layout, (&vs_module, &fs_module), false);
+        let instanced_pipeline = create_pipeline(device, &layout, (&instanced_vs_module, &fs_module), true);
+
+        let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pick_camera_buffer"),
+            size: std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let texture = |format, usage, label| device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: 1, height: 1, depth: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+        });
+        let target = texture(FORMAT, wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+                             "pick_target");
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth = texture(DepthTexture::FORMAT, wgpu::TextureUsage::OUTPUT_ATTACHMENT, "pick_depth");
+        let depth_view = depth.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            pipeline,
+            instanced_pipeline,
+            model_bind_group_layout,
+            ids_bind_group_layout,
+            mesh_bind_group_layout,
+            camera_buffer,
+            target,
+            target_view,
+            depth_view,
+        }
+    }
+
+    // draws the scene's ids at pixel `x`, `y` of a `width` by `height`
+    // view through `view_proj` and reads them back. the result arrives
+    // once `readbacks` is polled, None where no model was drawn.
+    // `transforms` has to hold the scene's models
+    pub fn pick(&self, device: &wgpu::Device, queue: &wgpu::Queue, readbacks: &mut Readbacks,
+                (scene, transforms): (&Scene, &ModelTransforms), view_proj: Mat4,
+                (x, y, width, height): (u32, u32, u32, u32)) -> Receiver<Result<Option<Pick>>> {
+        let view_proj = region_projection((x, y, 1, 1), width, height) * view_proj;
+        let submeshes = self.draw_ids(device, queue, (scene, transforms), view_proj,
+                                      (&self.target_view, &self.depth_view));
+
+        let size = wgpu::Extent3d { width: 1, height: 1, depth: 1 };
+        readbacks.read_texture_with(device, queue, (&self.target, 0, size, BYTES_PER_PIXEL), move |bytes| {
+            let ids: Vec<u32> = bytes.chunks_exact(4)
+                .map(|id| u32::from_le_bytes([id[0], id[1], id[2], id[3]]))
+                .collect();
+            let (model, draw, triangle, instance) = match ids[..] {
+                [model, draw, triangle, instance] => (model, draw, triangle, instance),
+                _ => anyhow::bail!("Failed to read the picked ids"),
+            };
+            if model == 0 {
+                return Ok(None);
+            }
+            let (submesh, material) = *submeshes.get(draw as usize).context("Picked a draw that isn't there")?;
+            Ok(Some(Pick { model: model as usize - 1, submesh, material, triangle, instance }))
+        })
+    }
+
+    // like `pick` for every pixel of the `region` (x, y, width and height)
+    // of a `width` by `height` view at once: the result is the models
+    // seen in it, sorted, without the ones covered by others. the targets
+    // are made for the region's size each time
+    pub fn select(&self, device: &wgpu::Device, queue: &wgpu::Queue, readbacks: &mut Readbacks,
+                  (scene, transforms): (&Scene, &ModelTransforms), view_proj: Mat4,
+                  (region, width, height): ((u32, u32, u32, u32), u32, u32)) -> Receiver<Result<Vec<usize>>> {
+        let size = wgpu::Extent3d { width: region.2.max(1), height: region.3.max(1), depth: 1 };
+        let texture = |format, usage, label| device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+        });
+        let target = texture(FORMAT, wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+                             "select_target");
+        let depth = texture(DepthTexture::FORMAT, wgpu::TextureUsage::OUTPUT_ATTACHMENT, "select_depth");
+        let view_proj = region_projection(region, width, height) * view_proj;
+        self.draw_ids(device, queue, (scene, transforms), view_proj,
+                      (&target.create_view(&wgpu::TextureViewDescriptor::default()),
+                       &depth.create_view(&wgpu::TextureViewDescriptor::default())));
+
+        readbacks.read_texture_with(device, queue, (&target, 0, size, BYTES_PER_PIXEL), |bytes| {
+            let mut models: Vec<usize> = bytes.chunks_exact(BYTES_PER_PIXEL as usize)
+                .map(|ids| u32::from_le_bytes([ids[0], ids[1], ids[2], ids[3]]))
+                .filter(|&model| model > 0)
+                .map(|model| model as usize - 1)
+                .collect();
+            models.sort_unstable();
+            models.dedup();
+            Ok(models)
+        })
+    }
+
+    // draws the ids of the scene as seen through `view_proj` into
+    // `target` and returns each draw's sub-mesh and material
+    fn draw_ids(&self, device: &wgpu::Device, queue: &wgpu::Queue,
+                (scene, transforms): (&Scene, &ModelTransforms), view_proj: Mat4,
+                (target, depth): (&wgpu::TextureView, &wgpu::TextureView)) -> Vec<(usize, usize)> {
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&view_proj.to_cols_array_2d()));
+        let frustum = Frustum::from_matrix(view_proj);
+        // every draw has a slot, whether it's drawn or not
+        let mut ids = vec![0u8; scene.draws.len().max(1) * Self::SLOT_SIZE as usize];
+        let mut submeshes = Vec::with_capacity(scene.draws.len());
+        for (i, draw) in scene.draws.iter().enumerate() {
+            let layout = &scene.models[draw.model].mesh.layout;
+            let float = std::mem::size_of::<f32>() as wgpu::BufferAddress;
+            let slot = DrawIds {
+                draw: [draw.model as u32 + 1, i as u32, draw.indices.start, 0],
+                vertex: [(layout.stride() / float) as u32,
+                         (layout.offset(VertexAttribute::Position).unwrap_or(0) / float) as u32, 0, 0],
+            };
+            let offset = i * Self::SLOT_SIZE as usize;
+            ids[offset..offset + std::mem::size_of::<DrawIds>()].copy_from_slice(bytemuck::bytes_of(&slot));
+            submeshes.push((scene.draws[..i].iter().filter(|other| other.model == draw.model).count(),
+                            draw.material));
+        }
+        let ids_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("pick_ids_buffer"),
+            contents: &ids,
+            usage: wgpu::BufferUsage::UNIFORM,
+        });
+        let ids_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.ids_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(ids_buffer.slice(..Self::SLOT_SIZE)),
+            }],
+            label: Some("pick_ids_bind_group"),
+        });
+        let model_bind_group = transforms.create_bind_group(device, &self.model_bind_group_layout, &self.camera_buffer);
+        // only the models whose bounds cover the target, instances can be
+        // anywhere
+        let drawn: Vec<usize> = (0..scene.models.len())
+            .filter(|&model| {
+                let instanced = scene.is_instanced(model);
+                let model = &scene.models[model];
+                model.mesh.num_indices > 0 && model.is_drawn()
+                    && (instanced || frustum.intersects(&model.world_bounds()))
+            })
+            .collect();
+        let mesh_bind_groups: Vec<_> = drawn.iter()
+            .map(|&model| {
+                let mesh = &scene.models[model].mesh;
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.mesh_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::Buffer(mesh.vertex_buffer.slice(..)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Buffer(mesh.index_buffer.slice(..)),
+                        },
+                    ],
+                    label: Some("pick_mesh_bind_group"),
+                })
+            })
+            .collect();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("pick_encoder"),
+        });
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                    attachment: depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            for (&model, mesh_bind_group) in drawn.iter().zip(&mesh_bind_groups) {
+                let group = scene.instances.iter().find(|group| group.model == model);
+                match group {
+                    Some(group) if group.buffer.is_empty() => continue,
+                    Some(group) => {
+                        pass.set_pipeline(&self.instanced_pipeline);
+                        pass.set_vertex_buffer(0, group.buffer.slice());
+                    }
+                    None => pass.set_pipeline(&self.pipeline),
+                }
+                let instances = group.map_or(1, |group| group.buffer.len());
+                pass.set_bind_group(0, &model_bind_group, &[ModelTransforms::offset(model)]);
+                pass.set_bind_group(2, mesh_bind_group, &[]);
+                for (i, draw) in scene.draws.iter().enumerate().filter(|(_, draw)| draw.model == model) {
+                    let offset = (i as wgpu::BufferAddress * Self::SLOT_SIZE) as wgpu::DynamicOffset;
+                    pass.set_bind_group(1, &ids_bind_group, &[offset]);
+                    pass.draw(0..draw.indices.end - draw.indices.start, 0..instances);
+                }
+            }
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        submeshes
+    }
+}
+
+// scales and moves clip space so the `region` (x, y, width and height)
+// of a `width` by `height` view covers all of it
+fn region_projection((x, y, region_width, region_height): (u32, u32, u32, u32), width: u32, height: u32) -> Mat4 {
+    let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+    let (region_width, region_height) = (region_width.max(1) as f32, region_height.max(1) as f32);
+    let center_x = (x as f32 + region_width / 2.0) / width * 2.0 - 1.0;
+    let center_y = 1.0 - (y as f32 + region_height / 2.0) / height * 2.0;
+    let (scale_x, scale_y) = (width / region_width, height / region_height);
+    Mat4::from_cols_array(&[
+        scale_x, 0.0, 0.0, 0.0,
+        0.0, scale_y, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        -center_x * scale_x, -center_y * scale_y, 0.0, 1.0,
+    ])
+}
+
+// draws without an index or vertex buffer, the instanced pipeline takes
+// the instances' model matrices. back faces are culled like in the main
+// pass, so the ids match what's seen
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout,
+                   (vs_module, fs_module): (&wgpu::ShaderModule, &wgpu::ShaderModule),
+                   instanced: bool) -> wgpu::RenderPipeline {
+    let buffers = [wgpu::VertexBufferDescriptor {
+        stride: InstanceBuffer::STRIDE,
+        step_mode: wgpu::InputStepMode::Instance,
+        attributes: &wgpu::vertex_attr_array![4 => Float4, 5 => Float4, 6 => Float4, 7 => Float4],
+    }];
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(if instanced { "pick_instanced_pipeline" } else { "pick_pipeline" }),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::Back,
+            depth_bias: 0,
+            depth_bias_slope_scale: 0.0,
+            depth_bias_clamp: 0.0,
+            clamp_depth: false,
+        }),
+        color_states: &[wgpu::ColorStateDescriptor {
+            format: FORMAT,
+            color_blend: wgpu::BlendDescriptor::REPLACE,
+            alpha_blend: wgpu::BlendDescriptor::REPLACE,
+            write_mask: wgpu::ColorWrite::ALL,
+        }],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+            format: DepthTexture::FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilStateDescriptor::default(),
+        }),
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: if instanced { &buffers } else { &[] },
+        },
+        sample_count: 1,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec4;
+
+    #[test]
+    fn regions_fill_clip_space() {
+        // the region from 20, 10 to 60, 30 of a 100 by 50 view
+        let projection = region_projection((20, 10, 40, 20), 100, 50);
+        let corner = |x: f32, y: f32| {
+            let clip = projection * Vec4::new(x / 100.0 * 2.0 - 1.0, 1.0 - y / 50.0 * 2.0, 0.5, 1.0);
+            (clip.x, clip.y)
+        };
+        let close = |(x, y): (f32, f32), (ex, ey): (f32, f32)| (x - ex).abs() < 1e-5 && (y - ey).abs() < 1e-5;
+        assert!(close(corner(20.0, 10.0), (-1.0, 1.0)));
+        assert!(close(corner(60.0, 30.0), (1.0, -1.0)));
+        assert!(close(corner(40.0, 20.0), (0.0, 0.0)));
+        // a single pixel is blown up around its center
+        let pixel = region_projection((3, 4, 1, 1), 10, 10) * Vec4::new(-0.3, 0.1, 0.5, 1.0);
+        assert!(close((pixel.x, pixel.y), (0.0, 0.0)));
+    }
+}
@@ -0,0 +1,14 @@
+#version 450
+
+layout(location=0) in vec3 v_position;
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+  vec4 u_light;
+};
+
+// the distance to the light over its range rather than the projected
+// depth, so the six faces of a light agree along their edges
+void main() {
+  gl_FragDepth = clamp(distance(v_position, u_light.xyz) / u_light.w, 0.0, 1.0);
+}
@@ -0,0 +1,152 @@
+use wgpu;
+use wgpu::util::DeviceExt;
+use glam::Vec3;
+use crate::collision::Aabb;
+use crate::mesh::{Mesh, Model, SubMesh};
+use crate::vertex::Vertex;
+
+// geometry a compute shader writes straight into the vertex buffer it's
+// drawn from, so animating it never goes through the CPU. the shader runs
+// in 64 wide workgroups, one invocation per vertex, writing `Vertex`es to
+// the storage buffer at set 0 binding 0 from the parameters at binding 1.
+// `dispatch` is meant for a pre-pass hook, so the vertices are written
+// before the frame's passes read them
+pub struct ComputeMesh {
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    params_buffer: wgpu::Buffer,
+    vertex_count: u32,
+}
+
+// the parameters of `waves.comp`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct WaveParams {
+    // quads along each side of the grid
+    pub resolution: u32,
+    pub size: f32,
+    pub amplitude: f32,
+    // in seconds, moves the waves along
+    pub time: f32,
+}
+
+impl ComputeMesh {
+    // the vertex buffer starts out zeroed, the indices never change.
+    // returns the mesh to be drawn along with it
+    pub fn new(device: &wgpu::Device, module: &wgpu::ShaderModule, vertex_count: u32, indices: &[u32],
+               params: &[u8], name: &str) -> (Self, Mesh) {
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(format!("{} vertex buffer", name).as_str()),
+            size: (vertex_count as usize * std::mem::size_of::<Vertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::STORAGE,
+            mapped_at_creation: false,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(format!("{} index buffer", name).as_str()),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsage::INDEX,
+        });
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(format!("{} params buffer", name).as_str()),
+            contents: params,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::COMPUTE,
+                    ty: wgpu::BindingType::StorageBuffer {
+                        dynamic: false,
+                        readonly: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::COMPUTE,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("compute_mesh_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(vertex_buffer.slice(..)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(params_buffer.slice(..)),
+                },
+            ],
+            label: Some("compute_mesh_bind_group"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("compute_mesh_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("compute_mesh_pipeline"),
+            layout: Some(&layout),
+            compute_stage: wgpu::ProgrammableStageDescriptor {
+                module,
+                entry_point: "main",
+            },
+        });
+
+        let mesh = Mesh {
+            vertex_buffer,
+            index_buffer,
+            submeshes: vec![SubMesh { material: 0, indices: 0..indices.len() as u32 }],
+            num_vertices: vertex_count,
+            num_indices: indices.len() as u32,
+            layout: Vertex::layout(),
+            // the vertices only exist on the GPU
+            uv_density: 0.0,
+        };
+        (Self { pipeline, bind_group, params_buffer, vertex_count }, mesh)
+    }
+
+    // a `size` wide square grid in the xz plane rippled by waves up to
+    // `amplitude` high, as a model to add to the scene
+    pub fn waves(device: &wgpu::Device, resolution: u32, size: f32, amplitude: f32) -> (Self, Model) {
+        let resolution = resolution.max(1);
+        let side = resolution + 1;
+        let mut indices = Vec::with_capacity((resolution * resolution * 6) as usize);
+        for j in 0..resolution {
+            for i in 0..resolution {
+                let a = j * side + i;
+                let (b, c, d) = (a + side, a + 1, a + side + 1);
+                indices.extend_from_slice(&[a, b, c, c, b, d]);
+            }
+        }
+        let params = WaveParams { resolution, size, amplitude, time: 0.0 };
+        let module = device.create_shader_module(wgpu::include_spirv!("waves.comp.spv"));
+        let (compute, mesh) = Self::new(
+            device, &module, side * side, &indices, bytemuck::bytes_of(&params), "waves");
+        let half = Vec3::new(size * 0.5, amplitude.abs(), size * 0.5);
+        (compute, Model::from_mesh(mesh, Aabb { min: -half, max: half }, "waves"))
+    }
+
+    pub fn set_params(&self, queue: &wgpu::Queue, params: &[u8]) {
+        queue.write_buffer(&self.params_buffer, 0, params);
+    }
+
+    // rewrites every vertex
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut pass = encoder.begin_compute_pass();
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch(self.vertex_count.div_ceil(64), 1, 1);
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+// frames a time is smoothed over, so the breakdown doesn't flicker
+const SMOOTHING_FRAMES: f32 = 60.0;
+// a share of the budget past which a time is shown as close to it
+const NEAR_BUDGET: f32 = 0.8;
+
+// one timed part of a frame, part of the closest entry before it with a
+// smaller depth
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileEntry {
+    pub name: &'static str,
+    pub depth: usize,
+    // smoothed over the last frames
+    pub seconds: f32,
+}
+
+// how a time compares to the frame budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Within,
+    // past NEAR_BUDGET of it
+    Near,
+    Over,
+}
+
+impl Budget {
+    pub fn of(seconds: f32, budget: f32) -> Self {
+        if seconds > budget {
+            Budget::Over
+        } else if seconds > budget * NEAR_BUDGET {
+            Budget::Near
+        } else {
+            Budget::Within
+        }
+    }
+}
+
+// where the frames' time goes, system by system. every frame adds its
+// timings between `begin_frame` and `end_frame` in the same order, and
+// entries that stop being timed, e.g. a pass that was switched off, are
+// dropped. only allocates when the breakdown changes
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    entries: Vec<ProfileEntry>,
+    // the next entry this frame's timings are matched against
+    cursor: usize,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.cursor = 0;
+    }
+
+    // times `name` inside the last entry added with a smaller depth
+    pub fn add(&mut self, name: &'static str, depth: usize, time: Duration) {
+        let seconds = time.as_secs_f32();
+        let found = self.entries[self.cursor..].iter()
+            .position(|entry| entry.name == name && entry.depth == depth);
+        match found {
+            Some(offset) => {
+                self.entries[self.cursor..=self.cursor + offset].rotate_right(1);
+                let entry = &mut self.entries[self.cursor];
+                entry.seconds += (seconds - entry.seconds) / SMOOTHING_FRAMES;
+            }
+            None => self.entries.insert(self.cursor, ProfileEntry { name, depth, seconds }),
+        }
+        self.cursor += 1;
+    }
+
+    pub fn end_frame(&mut self) {
+        self.entries.truncate(self.cursor);
+    }
+
+    // in the order they were timed, every entry after the one it's part of
+    pub fn entries(&self) -> &[ProfileEntry] {
+        &self.entries
+    }
+}
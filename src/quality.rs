@@ -0,0 +1,111 @@
+use crate::config::RendererConfig;
+use serde::Deserialize;
+
+// graphics settings bundled from cheapest to best looking, cycled with G
+// in the viewer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quality {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+// what a quality preset sets, overriding the same values in the config
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualitySettings {
+    // width and height of each cascade's shadow map
+    pub shadow_resolution: u32,
+    pub shadow_cascades: u32,
+    // the largest face of a point light's shadow cube
+    pub point_shadow_resolution: u32,
+    pub msaa: u32,
+    // smooths edges in the finished image, for the presets without msaa
+    pub fxaa: bool,
+    // ambient occlusion, shown with deferred shading, and its depth
+    // lookups per pixel
+    pub ssao: bool,
+    pub ssao_samples: u32,
+    // the upscaler's render scale, below 1 renders fewer pixels
+    pub render_scale: f32,
+    // samples of the materials' anisotropic filtering, 1 leaves their
+    // samplers as they were loaded
+    pub anisotropy: u8,
+}
+
+impl Quality {
+    pub const ALL: [Quality; 4] = [Quality::Low, Quality::Medium, Quality::High, Quality::Ultra];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|quality| *quality == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // msaa stays at 1 or 4, which every adapter supports
+    pub fn settings(self) -> QualitySettings {
+        match self {
+            Quality::Low => QualitySettings {
+                shadow_resolution: 1024,
+                shadow_cascades: 2,
+                point_shadow_resolution: 256,
+                msaa: 1,
+                fxaa: true,
+                ssao: false,
+                ssao_samples: 8,
+                render_scale: 0.75,
+                anisotropy: 1,
+            },
+            Quality::Medium => QualitySettings {
+                shadow_resolution: 2048,
+                shadow_cascades: 3,
+                point_shadow_resolution: 512,
+                msaa: 1,
+                fxaa: true,
+                ssao: true,
+                ssao_samples: 8,
+                render_scale: 1.0,
+                anisotropy: 4,
+            },
+            Quality::High => QualitySettings {
+                shadow_resolution: 2048,
+                shadow_cascades: 4,
+                point_shadow_resolution: 512,
+                msaa: 4,
+                fxaa: false,
+                ssao: true,
+                ssao_samples: 16,
+                render_scale: 1.0,
+                anisotropy: 8,
+            },
+            Quality::Ultra => QualitySettings {
+                shadow_resolution: 4096,
+                shadow_cascades: 4,
+                point_shadow_resolution: 1024,
+                msaa: 4,
+                fxaa: false,
+                ssao: true,
+                ssao_samples: 32,
+                render_scale: 1.0,
+                anisotropy: 16,
+            },
+        }
+    }
+}
+
+impl QualitySettings {
+    // `config` with these settings in place of its own. anisotropy isn't
+    // part of it, it's applied to the scene's materials
+    pub fn apply(&self, config: &RendererConfig) -> RendererConfig {
+        let mut config = config.clone();
+        config.shadows.resolution = self.shadow_resolution;
+        config.shadows.cascades = self.shadow_cascades;
+        config.shadows.point_resolution = self.point_shadow_resolution;
+        config.msaa = self.msaa;
+        config.fxaa = self.fxaa;
+        config.ssao.enabled = self.ssao;
+        config.ssao.samples = self.ssao_samples;
+        config.upscale.scale = self.render_scale;
+        config
+    }
+}
@@ -0,0 +1,199 @@
+use wgpu;
+use anyhow::{Context, Result};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Receiver};
+use std::task::{Context as TaskContext, Poll, Waker};
+
+type Mapping = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+type Finish = Box<dyn FnOnce(Result<&[u8]>)>;
+
+// a copy waiting for its buffer to be mapped, and what to do with the bytes
+struct Pending {
+    buffer: wgpu::Buffer,
+    mapping: Mapping,
+    finish: Finish,
+}
+
+// GPU to CPU copies that finish in the background while frames keep
+// rendering. each read returns a receiver the result arrives on, which
+// can be checked with `try_recv` every frame. nothing arrives until `poll`
+// runs, which the renderer does at the start of every frame
+#[derive(Default)]
+pub struct Readbacks {
+    pending: Vec<Pending>,
+}
+
+impl Readbacks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // copies `size` bytes of `source` from `offset`, which needs COPY_SRC usage
+    pub fn read_buffer(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, source: &wgpu::Buffer,
+                       offset: wgpu::BufferAddress, size: wgpu::BufferAddress) -> Receiver<Result<Vec<u8>>> {
+        let buffer = create_buffer(device, size);
+        let mut encoder = device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("readback_encoder") },
+        );
+        encoder.copy_buffer_to_buffer(source, offset, &buffer, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+        let (sender, receiver) = channel();
+        self.push(buffer, move |data| {
+            let _ = sender.send(data.map(<[u8]>::to_vec));
+        });
+        receiver
+    }
+
+    // copies the first level of a texture with four bytes per pixel, which
+    // needs COPY_SRC usage
+    pub fn read_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture,
+                        size: wgpu::Extent3d) -> Receiver<Result<image::RgbaImage>> {
+        self.read_image(device, queue, texture, size, false)
+    }
+
+    // the same, swapping red and blue back for `bgra` textures, e.g. in a
+    // swap chain's format
+    pub(crate) fn read_image(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture,
+                             size: wgpu::Extent3d, bgra: bool) -> Receiver<Result<image::RgbaImage>> {
+        let (buffer, padded_bytes_per_row) = copy_texture(device, queue, texture, 0, size, 4);
+        let (sender, receiver) = channel();
+        self.push(buffer, move |data| {
+            let image = data.and_then(|data| unpad(data, size, padded_bytes_per_row)).map(|mut image| {
+                if bgra {
+                    for pixel in image.pixels_mut() {
+                        pixel.0.swap(0, 2);
+                    }
+                }
+                image
+            });
+            let _ = sender.send(image);
+        });
+        receiver
+    }
+
+    // copies the first level of one layer of a texture in any format with
+    // `bytes_per_pixel`, e.g. a depth map, as tightly packed rows
+    pub fn read_texture_bytes(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture,
+                              layer: u32, size: wgpu::Extent3d, bytes_per_pixel: u32) -> Receiver<Result<Vec<u8>>> {
+        self.read_texture_with(device, queue, (texture, layer, size, bytes_per_pixel), Ok)
+    }
+
+    // the same, turning the bytes into something else once they're back
+    pub(crate) fn read_texture_with<T, F>(&mut self, device: &wgpu::Device, queue: &wgpu::Queue,
+                                          (texture, layer, size, bytes_per_pixel): (&wgpu::Texture, u32, wgpu::Extent3d, u32),
+                                          map: F) -> Receiver<Result<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce(Vec<u8>) -> Result<T> + 'static,
+    {
+        let (buffer, padded_bytes_per_row) = copy_texture(device, queue, texture, layer, size, bytes_per_pixel);
+        let (sender, receiver) = channel();
+        self.push(buffer, move |data| {
+            let _ = sender.send(data.and_then(|data| {
+                map(unpad_rows(data, (size.width * bytes_per_pixel) as usize, padded_bytes_per_row as usize))
+            }));
+        });
+        receiver
+    }
+
+    // hands over every copy that's done without waiting for the others
+    pub fn poll(&mut self, device: &wgpu::Device) {
+        if self.pending.is_empty() {
+            return;
+        }
+        device.poll(wgpu::Maintain::Poll);
+        let mut context = TaskContext::from_waker(Waker::noop());
+        let mut i = 0;
+        while i < self.pending.len() {
+            let result = match self.pending[i].mapping.as_mut().poll(&mut context) {
+                Poll::Ready(result) => result,
+                Poll::Pending => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let Pending { buffer, finish, .. } = self.pending.swap_remove(i);
+            match result {
+                Ok(()) => {
+                    finish(Ok(&buffer.slice(..).get_mapped_range()));
+                    buffer.unmap();
+                }
+                Err(_) => finish(Err(anyhow::anyhow!("Failed to map the readback buffer"))),
+            }
+        }
+    }
+
+    // blocks until every copy is done
+    pub fn wait(&mut self, device: &wgpu::Device) {
+        while !self.pending.is_empty() {
+            device.poll(wgpu::Maintain::Wait);
+            self.poll(device);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    fn push<F: FnOnce(Result<&[u8]>) + 'static>(&mut self, buffer: wgpu::Buffer, finish: F) {
+        let mapping = Box::pin(buffer.slice(..).map_async(wgpu::MapMode::Read));
+        self.pending.push(Pending { buffer, mapping, finish: Box::new(finish) });
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback_buffer"),
+        size,
+        usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// copies a layer of a texture into a new mappable buffer, with its rows
+// padded to the copy alignment. returns the padded row size
+pub(crate) fn copy_texture(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture, layer: u32,
+                           size: wgpu::Extent3d, bytes_per_pixel: u32) -> (wgpu::Buffer, u32) {
+    let padded_bytes_per_row = (bytes_per_pixel * size.width).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let buffer = create_buffer(device, (padded_bytes_per_row * size.height) as wgpu::BufferAddress);
+    let mut encoder = device.create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("readback_encoder") },
+    );
+    encoder.copy_texture_to_buffer(
+        wgpu::TextureCopyView {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d { x: 0, y: 0, z: layer },
+        },
+        wgpu::BufferCopyView {
+            buffer: &buffer,
+            layout: wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: padded_bytes_per_row,
+                rows_per_image: size.height,
+            },
+        },
+        size,
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+    (buffer, padded_bytes_per_row)
+}
+
+// drops the padding `copy_texture` added to each row
+fn unpad_rows(data: &[u8], bytes_per_row: usize, padded_bytes_per_row: usize) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(data.len() / padded_bytes_per_row * bytes_per_row);
+    for row in data.chunks(padded_bytes_per_row) {
+        pixels.extend_from_slice(&row[..bytes_per_row]);
+    }
+    pixels
+}
+
+// the image in a buffer `copy_texture` filled from a four-byte-per-pixel
+// texture
+pub(crate) fn unpad(data: &[u8], size: wgpu::Extent3d, padded_bytes_per_row: u32) -> Result<image::RgbaImage> {
+    let pixels = unpad_rows(data, 4 * size.width as usize, padded_bytes_per_row as usize);
+    image::RgbaImage::from_raw(size.width, size.height, pixels)
+        .context("Readback buffer doesn't match the texture size")
+}
@@ -0,0 +1,374 @@
+use crate::config::BackgroundMode;
+use crate::animator::{AnimationState, Transition};
+use crate::lighting::Preset;
+use crate::mesh::Transform;
+use crate::skin::Skin;
+use crate::state::State;
+use anyhow::{anyhow, bail, Result};
+use glam::{Quat, Vec3};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use tungstenite::Message;
+
+// commands arrive as json text messages, e.g.
+// `{"command": "set_camera", "eye": [0, 1, 2]}`, and are answered with
+// `{"ok": true}` or `{"ok": false, "error": "..."}`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    LoadModel {
+        path: String,
+    },
+    SetCamera {
+        eye: Option<[f32; 3]>,
+        center: Option<[f32; 3]>,
+        fov: Option<f32>,
+    },
+    SetLight {
+        position: [f32; 3],
+        color: [f32; 3],
+    },
+    SetLighting {
+        preset: Preset,
+    },
+    SetBackground {
+        mode: BackgroundMode,
+    },
+    // turns the skybox and the light from the environment around the up
+    // axis and scales them, values left out stay as they are
+    SetEnvironment {
+        rotation: Option<f32>,
+        intensity: Option<f32>,
+    },
+    // draws a model with another of the scene's materials, or with its own
+    // again when `material` is left out
+    SetMaterial {
+        model: String,
+        material: Option<String>,
+    },
+    // sets one of the mesh shaders' custom parameters for a model, or
+    // unsets it when `value` is left out
+    SetParam {
+        model: String,
+        name: String,
+        value: Option<Vec<f32>>,
+    },
+    // changes how a model is drawn, flags left out stay as they are
+    SetFlags {
+        model: String,
+        unlit: Option<bool>,
+        cast_shadows: Option<bool>,
+        receive_shadows: Option<bool>,
+        shadow_only: Option<bool>,
+    },
+    // writes the parameters and flags next to the scene's file
+    SaveParams,
+    // at the camera's eye when `position` is left out
+    CaptureEnvironment {
+        position: Option<[f32; 3]>,
+    },
+    // saved on the machine running the viewer
+    Screenshot {
+        path: String,
+    },
+    // plays one of a skinned model's animations, crossfading from the one
+    // playing over `fade` seconds
+    PlayAnimation {
+        model: String,
+        animation: String,
+        fade: Option<f32>,
+    },
+    // adds an animation on top of the one playing, removed at weight 0
+    SetAnimationLayer {
+        model: String,
+        animation: String,
+        weight: f32,
+    },
+    AddAnimationState {
+        model: String,
+        name: String,
+        animation: String,
+        speed: Option<f32>,
+        looping: Option<bool>,
+    },
+    // from any state when `from` is left out, once `parameter` goes above
+    // `threshold`, or below it with `above` false
+    AddAnimationTransition {
+        model: String,
+        from: Option<String>,
+        to: String,
+        parameter: String,
+        threshold: f32,
+        above: Option<bool>,
+        fade: Option<f32>,
+    },
+    SetAnimationState {
+        model: String,
+        state: String,
+        fade: Option<f32>,
+    },
+    SetAnimationParameter {
+        model: String,
+        name: String,
+        value: f32,
+    },
+    // picks the models transformed together, leaving out locked ones
+    Select {
+        models: Vec<String>,
+    },
+    // moves, turns around the up axis by degrees and scales the selected
+    // models around their center, as one step to undo
+    TransformSelection {
+        translation: Option<[f32; 3]>,
+        turn: Option<f32>,
+        scale: Option<f32>,
+    },
+    Undo,
+    Redo,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Response {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl From<Result<()>> for Response {
+    fn from(result: Result<()>) -> Self {
+        match result {
+            Ok(()) => Self { ok: true, error: None },
+            Err(e) => Self { ok: false, error: Some(format!("{:#}", e)) },
+        }
+    }
+}
+
+struct Request {
+    command: Command,
+    reply: Sender<Response>,
+}
+
+// accepts websocket connections on a background thread and hands their
+// commands to the event loop, which applies them through `poll`
+pub struct RemoteServer {
+    requests: Receiver<Request>,
+}
+
+impl RemoteServer {
+    pub fn start(address: &str) -> Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let (sender, requests) = channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().filter_map(|stream| stream.ok()) {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    if let Err(e) = serve(stream, sender) {
+                        warn!("remote connection closed: {}", e);
+                    }
+                });
+            }
+        });
+        Ok(Self { requests })
+    }
+
+    // applies every command received since the last call
+    pub fn poll(&self, state: &mut State) {
+        while let Ok(request) = self.requests.try_recv() {
+            let response = apply(state, request.command).into();
+            let _ = request.reply.send(response);
+        }
+    }
+}
+
+fn apply(state: &mut State, command: Command) -> Result<()> {
+    match command {
+        Command::LoadModel { path } => state.load_scene(&path),
+        Command::SetCamera { eye, center, fov } => {
+            let camera = state.camera_mut();
+            if let Some(eye) = eye {
+                camera.eye = eye.into();
+            }
+            if let Some(center) = center {
+                camera.center = center.into();
+            }
+            if let Some(fov) = fov {
+                camera.fov = fov;
+            }
+            Ok(())
+        }
+        Command::SetLight { position, color } => {
+            state.renderer_mut().set_light(position, color);
+            Ok(())
+        }
+        Command::SetLighting { preset } => {
+            state.set_lighting(preset);
+            Ok(())
+        }
+        Command::SetBackground { mode } => {
+            state.renderer_mut().set_background_mode(mode);
+            Ok(())
+        }
+        Command::SetEnvironment { rotation, intensity } => {
+            let renderer = state.renderer_mut();
+            if let Some(rotation) = rotation {
+                renderer.set_environment_rotation(rotation);
+            }
+            if let Some(intensity) = intensity {
+                renderer.set_environment_intensity(intensity);
+            }
+            Ok(())
+        }
+        Command::SetMaterial { model, material } => {
+            let scene = state.scene_mut();
+            let model = scene.model_index(&model).ok_or_else(|| anyhow!("No model named {}", model))?;
+            match material {
+                Some(material) => {
+                    let material = scene.material_index(&material)
+                        .ok_or_else(|| anyhow!("No material named {}", material))?;
+                    scene.override_material(model, material)
+                }
+                None => {
+                    scene.reset_material(model);
+                    Ok(())
+                }
+            }
+        }
+        Command::SetParam { model, name, value } => {
+            let model = state.scene_mut().model_index(&model).ok_or_else(|| anyhow!("No model named {}", model))?;
+            match value {
+                Some(value) => state.set_param(model, &name, &value),
+                None => {
+                    state.reset_param(model, &name);
+                    Ok(())
+                }
+            }
+        }
+        Command::SetFlags { model, unlit, cast_shadows, receive_shadows, shadow_only } => {
+            let model = state.scene_mut().model_index(&model).ok_or_else(|| anyhow!("No model named {}", model))?;
+            let mut flags = state.scene_mut().models[model].flags;
+            flags.unlit = unlit.unwrap_or(flags.unlit);
+            flags.cast_shadows = cast_shadows.unwrap_or(flags.cast_shadows);
+            flags.receive_shadows = receive_shadows.unwrap_or(flags.receive_shadows);
+            flags.shadow_only = shadow_only.unwrap_or(flags.shadow_only);
+            state.set_flags(model, flags)
+        }
+        Command::SaveParams => state.save_params().map(|_| ()),
+        Command::CaptureEnvironment { position } => {
+            let position = position.map_or(state.camera_mut().eye, |position| position.into());
+            state.capture_environment(position)
+        }
+        Command::Screenshot { path } => {
+            let image = async_std::task::block_on(state.screenshot())?;
+            image.save(path)?;
+            Ok(())
+        }
+        Command::PlayAnimation { model, animation, fade } => {
+            let skin = skin(state, &model)?;
+            let clip = clip(skin, &animation)?;
+            skin.animator.play(clip, fade.unwrap_or(0.0));
+            Ok(())
+        }
+        Command::SetAnimationLayer { model, animation, weight } => {
+            let skin = skin(state, &model)?;
+            let clip = clip(skin, &animation)?;
+            skin.animator.set_layer(clip, weight);
+            Ok(())
+        }
+        Command::AddAnimationState { model, name, animation, speed, looping } => {
+            let skin = skin(state, &model)?;
+            let clip = clip(skin, &animation)?;
+            skin.animator.add_state(AnimationState {
+                name,
+                clip,
+                speed: speed.unwrap_or(1.0),
+                looping: looping.unwrap_or(true),
+            });
+            Ok(())
+        }
+        Command::AddAnimationTransition { model, from, to, parameter, threshold, above, fade } => {
+            skin(state, &model)?.animator.add_transition(Transition {
+                from,
+                to,
+                parameter,
+                threshold,
+                above: above.unwrap_or(true),
+                fade: fade.unwrap_or(0.0),
+            });
+            Ok(())
+        }
+        Command::SetAnimationState { model, state: name, fade } => {
+            if skin(state, &model)?.animator.set_state(&name, fade.unwrap_or(0.0)) {
+                Ok(())
+            } else {
+                Err(anyhow!("No animation state named {}", name))
+            }
+        }
+        Command::SetAnimationParameter { model, name, value } => {
+            skin(state, &model)?.animator.set_parameter(&name, value);
+            Ok(())
+        }
+        Command::Select { models } => {
+            let scene = state.scene_mut();
+            let models = models.iter()
+                .map(|model| scene.model_index(model).ok_or_else(|| anyhow!("No model named {}", model)))
+                .collect::<Result<Vec<_>>>()?;
+            state.select(models)
+        }
+        Command::TransformSelection { translation, turn, scale } => {
+            let delta = Transform {
+                translation: translation.map_or(Vec3::zero(), Vec3::from),
+                rotation: Quat::from_rotation_y(turn.unwrap_or(0.0).to_radians()),
+                scale: Vec3::splat(scale.unwrap_or(1.0)),
+            };
+            if !state.transform_selection(&delta) {
+                bail!("Nothing selected can be moved");
+            }
+            Ok(())
+        }
+        Command::Undo => {
+            if !state.undo() {
+                bail!("Nothing to undo");
+            }
+            Ok(())
+        }
+        Command::Redo => {
+            if !state.redo() {
+                bail!("Nothing to redo");
+            }
+            Ok(())
+        }
+    }
+}
+
+fn skin<'a>(state: &'a mut State, model: &str) -> Result<&'a mut Skin> {
+    let scene = state.scene_mut();
+    let index = scene.model_index(model).ok_or_else(|| anyhow!("No model named {}", model))?;
+    scene.models[index].skin.as_mut().ok_or_else(|| anyhow!("{} isn't skinned", model))
+}
+
+fn clip(skin: &Skin, animation: &str) -> Result<usize> {
+    skin.animation_index(animation).ok_or_else(|| anyhow!("No animation named {}", animation))
+}
+
+fn serve(stream: TcpStream, requests: Sender<Request>) -> Result<()> {
+    let mut socket = tungstenite::accept(stream).map_err(|e| anyhow!("{}", e))?;
+    loop {
+        let text = match socket.read_message()? {
+            Message::Text(text) => text,
+            Message::Close(_) => return Ok(()),
+            _ => continue,
+        };
+        let response = match serde_json::from_str::<Command>(&text) {
+            Ok(command) => {
+                let (reply, response) = channel();
+                requests.send(Request { command, reply })?;
+                response.recv()?
+            }
+            Err(e) => Err(anyhow!("Invalid command: {}", e)).into(),
+        };
+        socket.write_message(Message::Text(serde_json::to_string(&response)?))?;
+    }
+}
@@ -0,0 +1,2261 @@
+use wgpu;
+use wgpu::util::DeviceExt;
+use winit::window::Window;
+use anyhow::{bail, Context, Result};
+use bytemuck;
+use log::warn;
+use crate::background::Background;
+use crate::bloom::Bloom;
+use crate::camera::Camera;
+use crate::caps::Capabilities;
+use crate::collision::Aabb;
+use crate::config::{BackgroundMode, DebugConfig, DebugView, RendererConfig, ShadowConfig, Tonemap, UpscaleConfig};
+use crate::culling::Frustum;
+use crate::deferred::{self, DeferredPass};
+use crate::curve::CurvePass;
+use crate::dump;
+use crate::fxaa::Fxaa;
+use crate::graph::{Attachments, Record, RenderGraph, Resource, TextureDesc, TexturePool};
+use crate::ground::Ground;
+use crate::heatmap::Heatmaps;
+use crate::ibl::{self, Environment};
+use crate::imposter::{Imposters, MainPass};
+use crate::lines::DebugLines;
+use crate::wireframe::Wireframes;
+use crate::minimap::{self, Minimap, MinimapView};
+use crate::motion_blur::{self, MotionBlur};
+use crate::navcube::NavCube;
+use crate::material::Material;
+use crate::mesh::InstanceBuffer;
+use crate::skin::Joint;
+use crate::params::{self, ShaderParam};
+use crate::picking::{Pick, Picker};
+use crate::profiler::Profile;
+use crate::vertex::Vertex;
+use crate::scene::{InstanceGroup, Scene};
+use crate::sdf::SdfPass;
+use crate::shadow::{self, ShadowMaps};
+use crate::ssao::{self, Ssao};
+use crate::tonemap::{self, Tonemapper};
+use crate::texture::{DepthTexture, RenderTexture};
+use crate::upscale::{self, Upscaler};
+use crate::lighting::Lighting;
+use crate::lights::{Light, Lights};
+use crate::quality::Quality;
+use crate::readback::Readbacks;
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+// owns the GPU device and everything needed to draw a scene, but not the
+// event loop, the scene or the camera, so it can be embedded into an
+// application's own winit loop
+pub struct Renderer {
+    target: Target,
+    // shared with background loads, see `shared_device`
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    caps: Capabilities,
+    sc_desc: wgpu::SwapChainDescriptor,
+    // what the main pass draws into, `tonemap::FORMAT` with HDR on and the
+    // render target's format otherwise
+    color_format: wgpu::TextureFormat,
+    // with HDR on, maps the main pass' image onto the render target
+    tonemapper: Option<Tonemapper>,
+    // with HDR on, added onto the main pass' image before tonemapping
+    bloom: Option<Bloom>,
+    // smooths the edges of the finished image, before it's drawn over
+    fxaa: Fxaa,
+    // smears the main pass' image along the camera's motion
+    motion_blur: MotionBlur,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    vs_module: wgpu::ShaderModule,
+    fs_module: wgpu::ShaderModule,
+    // the custom parameters the mesh shaders declare, see `shader_params`
+    vs_params: Vec<ShaderParam>,
+    fs_params: Vec<ShaderParam>,
+    render_pipeline: wgpu::RenderPipeline,
+    // draws `scene.instances`, with the camera and their model's slot at
+    // set 0
+    instanced_pipeline_layout: wgpu::PipelineLayout,
+    instanced_vs_module: wgpu::ShaderModule,
+    instanced_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    // the camera on its own, for passes that don't draw the meshes
+    uniform_bind_group: wgpu::BindGroup,
+    transforms: ModelTransforms,
+    model_bind_group_layout: wgpu::BindGroupLayout,
+    model_bind_group: wgpu::BindGroup,
+    // the sun, sky and the added lights at set 3
+    lights: Lights,
+    shadows: ShadowMaps,
+    environment: Environment,
+    depth_texture: DepthTexture,
+    // what the scene is drawn with, 1 when the upscaler is on
+    sample_count: u32,
+    // the sample count asked for, used once the upscaler is off
+    msaa: u32,
+    quality: Option<Quality>,
+    imposters: Imposters,
+    deferred: DeferredPass,
+    // whether the meshes are lit through the g-buffer, see `set_deferred`
+    deferred_shading: bool,
+    // darkens the g-buffer's ambient light
+    ssao: Ssao,
+    upscaler: Upscaler,
+    sdf: SdfPass,
+    curves: CurvePass,
+    ground: Ground,
+    background: Background,
+    heatmaps: Heatmaps,
+    lines: DebugLines,
+    wireframes: Wireframes,
+    nav_cube: NavCube,
+    // off until `set_nav_cube`, so headless frames don't show it
+    show_nav_cube: bool,
+    minimap: Option<Minimap>,
+    debug: DebugConfig,
+    // seconds of animation, scrolling the displaced materials
+    time: f32,
+    // culling keeps using this frustum while set, whatever the camera does
+    frozen_frustum: Option<Frustum>,
+    // boxes outlined over the scene every frame, e.g. a scene diff
+    highlights: Vec<(Aabb, [f32; 3])>,
+    // skinned models' joints drawn over the scene, one of them, as model
+    // and joint, highlighted
+    show_skeletons: bool,
+    selected_joint: Option<(usize, usize)>,
+    // the models selected in the viewer, outlined every frame, and the
+    // box being dragged out to select more, in pixels
+    selected: Vec<usize>,
+    selection_box: Option<(u32, u32, u32, u32)>,
+    // the mesh and instanced pipelines and depth buffer for views other
+    // than the render target, created on first use
+    external_pipelines: HashMap<wgpu::TextureFormat, (wgpu::RenderPipeline, wgpu::RenderPipeline)>,
+    external_depth_texture: Option<DepthTexture>,
+    // the id buffer `pick` draws into, created on first use
+    picker: Option<Picker>,
+    pre_pass_hooks: Vec<EncoderHook>,
+    post_pass_hooks: Vec<EncoderHook>,
+    overlays: Vec<Box<dyn Overlay>>,
+    // copies back to the CPU still in flight, polled every frame
+    readbacks: Readbacks,
+    // counted while encoding, which only borrows the renderer
+    draw_stats: Cell<DrawStats>,
+    // the time the last frame's meshes spent on frustum tests
+    culling: Cell<Duration>,
+    // what `render_frame` did before encoding the frame and how long it took
+    present_timings: Vec<(&'static str, Duration)>,
+    // how long the last graph's passes took to record, see
+    // `RenderGraph::execute`
+    pass_timings: RefCell<Vec<(&'static str, Duration)>>,
+    // the frame graph's transient textures, e.g. the multisampled targets
+    transients: RefCell<TexturePool>,
+    // the built-in passes of the last frames, see `draw_frame`
+    frame_graphs: RefCell<Vec<(GraphKey, RenderGraph<'static, FramePass>)>>,
+}
+
+// the frame's color target in `Renderer::frame_graph`, the swap chain image
+// or the view passed to `render`
+pub const FRAME: Resource = "frame";
+// written by the shadow pass, read by the main pass
+pub const SHADOW_MAPS: Resource = "shadow_maps";
+// the lights binned into clusters, read by the main pass
+pub const LIGHT_CLUSTERS: Resource = "light_clusters";
+// the main pass' HDR image, tonemapped into `FRAME`. only in the graph
+// with HDR on
+pub const HDR_COLOR: Resource = "hdr_color";
+// the finished image in the render target's format, antialiased into
+// `FRAME`. only in the graph with fxaa on
+pub const LDR_COLOR: Resource = "ldr_color";
+
+// the scene's meshes drawn in the last frame's main pass, instanced ones
+// counting every copy's triangles
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrawStats {
+    pub draw_calls: u32,
+    pub triangles: u64,
+}
+
+impl DrawStats {
+    fn add(&mut self, indices: &std::ops::Range<u32>, instances: u32) {
+        self.draw_calls += 1;
+        self.triangles += u64::from(indices.end - indices.start) / 3 * u64::from(instances);
+    }
+}
+
+// custom work recorded into the frame's encoder before or after the main
+// pass, e.g. compute dispatches, extra render passes or post-processing;
+// the view is the frame's color target
+pub type EncoderHook = Box<dyn Fn(&wgpu::Device, &mut wgpu::CommandEncoder, &wgpu::TextureView)>;
+
+// see `Renderer::render_frame_with`
+type AfterFrame<'a> = dyn FnMut(&wgpu::Device, &wgpu::Queue, &mut wgpu::CommandEncoder, &wgpu::TextureView) + 'a;
+
+// everything the built-in passes of a frame depend on besides what they
+// draw. the frame graph is built from it, and rebuilt when it changes
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GraphKey {
+    // drawing into a view that isn't the render target, which skips the
+    // upscaler, tonemapping, raymarched shapes, the ground, drawn
+    // backgrounds, imposters, heatmaps, hooks and overlays
+    external: bool,
+    // whether `render_frame_with` adds a last pass
+    after: bool,
+    heatmaps: bool,
+    upscale: bool,
+    // of the main pass, smaller than `size` while upscaling
+    input_size: (u32, u32),
+    size: (u32, u32),
+    format: wgpu::TextureFormat,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+    fxaa: bool,
+    hdr: bool,
+    bloom: bool,
+    motion_blur: bool,
+    gbuffer: bool,
+    ssao: bool,
+    pre_pass_hooks: bool,
+    post_pass_hooks: bool,
+    minimap: bool,
+    nav_cube: bool,
+}
+
+// the built-in passes, recorded by `Renderer::record_pass` with the
+// resources `build_graph` picked for them
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FramePass {
+    Heatmaps,
+    PrePassHooks,
+    Shadows,
+    Gbuffer,
+    Ssao,
+    LightClusters,
+    Main { color: Resource, resolve: Option<Resource>, depth: Resource, gbuffer: bool, ssao: bool, run_hooks: bool },
+    Upscale { output: Resource },
+    MotionBlur { depth: Resource, output: Resource },
+    Bloom,
+    Tonemap { output: Resource },
+    Fxaa,
+    PostPassHooks,
+    Minimap,
+    NavCube,
+    AfterFrame,
+}
+
+// what a frame's passes draw and how
+#[derive(Clone, Copy)]
+struct Frame<'a> {
+    scene: &'a Scene,
+    camera: &'a Camera,
+    // the main pass' mesh and instanced pipelines
+    pipelines: (&'a wgpu::RenderPipeline, &'a wgpu::RenderPipeline),
+    jitter: Vec2,
+}
+
+// draws user content into the main pass after the scene. pipelines have to
+// target `Renderer::color_format` and `DepthTexture::FORMAT`. this is a trait
+// rather than a closure because anything bound to the pass has to be
+// borrowed for the pass' lifetime, which a closure's captures can't express
+pub trait Overlay {
+    fn draw<'a>(&'a self, device: &wgpu::Device, pass: &mut wgpu::RenderPass<'a>);
+}
+
+// where frames end up: a window's swap chain, or an offscreen texture
+// when running headless
+enum Target {
+    Window {
+        surface: wgpu::Surface,
+        swap_chain: wgpu::SwapChain,
+    },
+    Headless(RenderTexture),
+}
+
+// the camera, bound at set 0
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct Uniforms {
+    view_proj: [[f32; 4]; 4],
+    eye: [f32; 4],
+    // x the debug view, y checker density, z target texel density
+    debug: [f32; 4],
+    // x seconds of animation, padded to a vec4
+    time: [f32; 4],
+}
+
+impl Uniforms {
+    fn identity() -> Self {
+        Self::from_matrix(Mat4::identity(), Vec3::zero())
+    }
+
+    // `jitter` offsets the projection for the temporal upscale
+    fn new(camera: &Camera, jitter: Vec2, debug: &DebugConfig, time: f32) -> Self {
+        let view_proj = upscale::jittered(camera.build_view_projection_matrix(), jitter);
+        Uniforms {
+            debug: [debug.view as u32 as f32, debug.checker_density, debug.texel_density, 0.0],
+            time: [time, 0.0, 0.0, 0.0],
+            ..Self::from_matrix(view_proj, camera.eye)
+        }
+    }
+
+    pub(crate) fn from_matrix(view_proj: Mat4, eye: Vec3) -> Self {
+        Uniforms {
+            view_proj: view_proj.to_cols_array_2d(),
+            eye: eye.extend(1.0).into(),
+            debug: [0.0; 4],
+            time: [0.0; 4],
+        }
+    }
+}
+
+// a model's placement, one slot per model in `ModelTransforms`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ModelUniforms {
+    model: [[f32; 4]; 4],
+    // the inverse transpose, keeping normals perpendicular under
+    // non-uniform scaling
+    normal: [[f32; 4]; 4],
+    // x the `OBJECT_` bits of the model's flags, padded to keep the
+    // parameters vec4 aligned
+    flags: [u32; 4],
+    // the custom shader parameters, filling the slot up to the dynamic
+    // offset alignment, see `params::ShaderParam`
+    params: [f32; 28],
+}
+
+// the bits of `ModelUniforms::flags`, set for what differs from a default
+// object so a zeroed slot draws normally. must match shader.frag and
+// gbuffer.frag
+const OBJECT_UNLIT: u32 = 1;
+const OBJECT_NO_SHADOWS: u32 = 2;
+
+// every model's matrices in one uniform buffer, bound next to the camera
+// at set 0 binding 1 with a dynamic offset per draw
+pub(crate) struct ModelTransforms {
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    // reused every frame to upload all slots at once
+    slots: Vec<ModelUniforms>,
+}
+
+impl ModelTransforms {
+    // the offsets of dynamic bindings have to be aligned to this
+    const SLOT_SIZE: wgpu::BufferAddress = wgpu::BIND_BUFFER_ALIGNMENT;
+
+    fn new(device: &wgpu::Device) -> Self {
+        Self { buffer: Self::create_buffer(device, 16), capacity: 16, slots: vec![] }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("model_transform_buffer"),
+            size: capacity as wgpu::BufferAddress * Self::SLOT_SIZE,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // the camera and model bindings of set 0 for pipelines drawing the
+    // scene's meshes, identical wherever it's created
+    pub(crate) fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer {
+                            dynamic: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer {
+                            dynamic: true,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("model_bind_group_layout"),
+            }
+        )
+    }
+
+    pub(crate) fn create_bind_group(&self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout,
+                                    camera_buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+        // one slot, with the parameters after the matrices
+        let size = Self::SLOT_SIZE;
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(camera_buffer.slice(..)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(self.buffer.slice(..size)),
+                },
+            ],
+            label: Some("model_bind_group"),
+        })
+    }
+
+    // writes every model's matrices, flags and the values it has for `params`,
+    // returning whether the buffer had to grow, which leaves the bind groups
+    // created from it stale
+    fn upload<'a>(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene,
+                  params: impl Iterator<Item = &'a ShaderParam> + Clone) -> bool {
+        let grown = scene.models.len() > self.capacity;
+        if grown {
+            self.capacity = scene.models.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+        self.slots.clear();
+        self.slots.extend(scene.models.iter().map(|model| {
+            let matrix = model.transform.matrix();
+            let mut uniforms = ModelUniforms {
+                model: matrix.to_cols_array_2d(),
+                normal: matrix.inverse().transpose().to_cols_array_2d(),
+                flags: [0; 4],
+                params: [0.0; 28],
+            };
+            if model.flags.unlit {
+                uniforms.flags[0] |= OBJECT_UNLIT;
+            }
+            if !model.flags.receive_shadows {
+                uniforms.flags[0] |= OBJECT_NO_SHADOWS;
+            }
+            for param in params.clone() {
+                if let Some(value) = model.params.get(&param.name) {
+                    let start = ((param.offset - params::FIRST_OFFSET) / 4) as usize;
+                    let len = param.components.min(value.len());
+                    uniforms.params[start..start + len].copy_from_slice(&value[..len]);
+                }
+            }
+            uniforms
+        }));
+        if !self.slots.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.slots));
+        }
+        grown
+    }
+
+    // the dynamic offset selecting a model's slot
+    pub(crate) fn offset(model: usize) -> u32 {
+        (model as wgpu::BufferAddress * Self::SLOT_SIZE) as u32
+    }
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vs_module: &wgpu::ShaderModule,
+    fs_module: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    instanced: bool,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let color_states = [
+        wgpu::ColorStateDescriptor {
+            format,
+            // materials can be translucent through `d`/`Tr` and `map_d`
+            color_blend: wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha_blend: wgpu::BlendDescriptor {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            write_mask: wgpu::ColorWrite::ALL,
+        }
+    ];
+    let label = if instanced { "instanced_render_pipeline" } else { "render_pipeline" };
+    create_mesh_pipeline(device, layout, (vs_module, fs_module), &color_states, instanced, sample_count, label)
+}
+
+// a pipeline drawing the scene's meshes, or their instances, with a vertex
+// and fragment shader into `color_states`
+pub(crate) fn create_mesh_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    (vs_module, fs_module): (&wgpu::ShaderModule, &wgpu::ShaderModule),
+    color_states: &[wgpu::ColorStateDescriptor],
+    instanced: bool,
+    sample_count: u32,
+    label: &str,
+) -> wgpu::RenderPipeline {
+    let vertex_layout = Vertex::layout();
+    let buffers = [
+        vertex_layout.descriptor(),
+        // the model matrix's columns, the normal matrix's columns and the color
+        wgpu::VertexBufferDescriptor {
+            stride: InstanceBuffer::STRIDE,
+            step_mode: wgpu::InputStepMode::Instance,
+            attributes: &wgpu::vertex_attr_array![4 => Float4, 5 => Float4, 6 => Float4, 7 => Float4,
+                                                  8 => Float3, 9 => Float3, 10 => Float3,
+                                                  11 => Float4],
+        },
+    ];
+    device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: vs_module,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: fs_module,
+                entry_point: "main",
+            }),
+            rasterization_state: Some(
+                wgpu::RasterizationStateDescriptor {
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: wgpu::CullMode::Back,
+                    depth_bias: 0,
+                    depth_bias_slope_scale: 0.0,
+                    depth_bias_clamp: 0.0,
+                    clamp_depth: false,
+                }
+            ),
+            color_states,
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+                format: DepthTexture::FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilStateDescriptor::default(),
+            }),
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint32,
+                vertex_buffers: if instanced { &buffers } else { &buffers[..1] },
+            },
+            sample_count,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        }
+    )
+}
+
+// both shaders' parameters, each once, since they usually declare the same
+// model block
+fn merged_params<'a>(vs_params: &'a [ShaderParam],
+                     fs_params: &'a [ShaderParam]) -> impl Iterator<Item = &'a ShaderParam> + Clone {
+    fs_params.iter().chain(vs_params.iter().filter(move |param| fs_params.iter().all(|other| other.name != param.name)))
+}
+
+// the shaders `Renderer::reload_shader` swaps in, by file name
+pub const RELOADABLE_SHADERS: [&str; 3] = ["shader.vert", "shader.frag", "instanced.vert"];
+
+// 4 samples work everywhere, 2 and 8 only on some adapters
+fn check_sample_count(sample_count: u32) -> Result<()> {
+    if !matches!(sample_count, 1 | 2 | 4 | 8) {
+        bail!("msaa has to be 1, 2, 4 or 8 samples, not {}", sample_count);
+    }
+    Ok(())
+}
+
+// the built-in passes for frames like `key`, see `Renderer::frame_graph`.
+// the views of the imported resources come from `Renderer::imports`
+fn build_graph(key: &GraphKey) -> Result<RenderGraph<'static, FramePass>> {
+    let mut graph = RenderGraph::new();
+    graph.imported(FRAME);
+    if key.heatmaps {
+        graph.add("heatmaps", &[], &[FRAME], FramePass::Heatmaps);
+    } else {
+        add_scene_passes(&mut graph, key);
+    }
+    if key.after {
+        graph.add("after_frame", &[FRAME], &[FRAME], FramePass::AfterFrame);
+    }
+    graph.compile().context("Invalid frame graph")?;
+    Ok(graph)
+}
+
+fn add_scene_passes(graph: &mut RenderGraph<'static, FramePass>, key: &GraphKey) {
+    let run_hooks = !key.external;
+    let (width, height) = key.size;
+    let usage = wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED;
+    // the finished image, antialiased into the frame with fxaa on
+    let output = if key.fxaa {
+        graph.transient(LDR_COLOR, TextureDesc { width, height, format: key.format, sample_count: 1, usage });
+        LDR_COLOR
+    } else {
+        FRAME
+    };
+    // where the scene ends up before the post-pass hooks, tonemapped
+    // into the output afterwards with HDR on
+    let scene_color = if key.hdr {
+        graph.transient(HDR_COLOR, TextureDesc { width, height, format: tonemap::FORMAT, sample_count: 1, usage });
+        HDR_COLOR
+    } else {
+        output
+    };
+    // the main pass and upscaler draw into the blur's input instead, and
+    // it writes what they would have
+    let blurred = scene_color;
+    let scene_color = if key.motion_blur {
+        graph.transient(motion_blur::INPUT,
+                        TextureDesc { width, height, format: key.color_format, sample_count: 1, usage });
+        motion_blur::INPUT
+    } else {
+        scene_color
+    };
+    // the main pass' color target, the target it's resolved into and its depth buffer
+    let (color, resolve, depth) = if key.external {
+        graph.imported("depth");
+        (FRAME, None, "depth")
+    } else if key.upscale {
+        graph.imported("upscale_color");
+        graph.imported("upscale_depth");
+        ("upscale_color", None, "upscale_depth")
+    } else if key.sample_count > 1 {
+        let target = |format| TextureDesc {
+            width,
+            height,
+            format,
+            sample_count: key.sample_count,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+        };
+        graph.transient("multisampled_color", target(key.color_format));
+        graph.transient("multisampled_depth", target(DepthTexture::FORMAT));
+        ("multisampled_color", Some(scene_color), "multisampled_depth")
+    } else {
+        graph.imported("depth");
+        (scene_color, None, "depth")
+    };
+
+    if key.pre_pass_hooks {
+        graph.add("pre_pass_hooks", &[], &[FRAME], FramePass::PrePassHooks);
+    }
+    graph.external(SHADOW_MAPS);
+    graph.add("shadows", &[], &[SHADOW_MAPS], FramePass::Shadows);
+
+    let gbuffer = [deferred::TARGETS[0].0, deferred::TARGETS[1].0, deferred::TARGETS[2].0, deferred::TARGETS[3].0,
+                   deferred::DEPTH];
+    let (width, height) = key.input_size;
+    if key.gbuffer {
+        for (name, format) in deferred::TARGETS.iter().chain(&[(deferred::DEPTH, DepthTexture::FORMAT)]) {
+            graph.transient(name, TextureDesc { width, height, format: *format, sample_count: 1, usage });
+        }
+        graph.add("gbuffer", &[], &gbuffer, FramePass::Gbuffer);
+    }
+    if key.ssao {
+        for name in &[ssao::RAW, ssao::OCCLUSION] {
+            graph.transient(name, TextureDesc { width, height, format: ssao::FORMAT, sample_count: 1, usage });
+        }
+        graph.add("ssao", &[deferred::TARGETS[1].0, deferred::DEPTH], &[ssao::RAW, ssao::OCCLUSION], FramePass::Ssao);
+    }
+
+    // the clusters are built for the projection the main pass is drawn
+    // with, which the g-buffer shares
+    graph.external(LIGHT_CLUSTERS);
+    graph.add("light_clusters", &[], &[LIGHT_CLUSTERS], FramePass::LightClusters);
+
+    let mut reads = vec![SHADOW_MAPS, LIGHT_CLUSTERS];
+    if key.gbuffer {
+        reads.extend(&gbuffer);
+    }
+    if key.ssao {
+        reads.push(ssao::OCCLUSION);
+    }
+    let mut writes = vec![color, depth];
+    writes.extend(resolve);
+    graph.add("main", &reads, &writes, FramePass::Main {
+        color,
+        resolve,
+        depth,
+        gbuffer: key.gbuffer,
+        ssao: key.ssao,
+        run_hooks,
+    });
+    if key.upscale {
+        graph.add("upscale", &["upscale_color", "upscale_depth"], &[scene_color],
+                  FramePass::Upscale { output: scene_color });
+    }
+    if key.motion_blur {
+        graph.add("motion_blur", &[motion_blur::INPUT, depth], &[blurred],
+                  FramePass::MotionBlur { depth, output: blurred });
+    }
+    if key.bloom {
+        graph.add("bloom", &[HDR_COLOR], &[HDR_COLOR], FramePass::Bloom);
+    }
+    if key.hdr {
+        graph.add("tonemap", &[HDR_COLOR], &[output], FramePass::Tonemap { output });
+    }
+    if key.fxaa {
+        graph.add("fxaa", &[LDR_COLOR], &[FRAME], FramePass::Fxaa);
+    }
+    if key.post_pass_hooks {
+        graph.add("post_pass_hooks", &[FRAME], &[FRAME], FramePass::PostPassHooks);
+    }
+    if key.minimap {
+        graph.add("minimap", &[], &[FRAME], FramePass::Minimap);
+    }
+    if key.nav_cube {
+        graph.add("nav_cube", &[], &[FRAME], FramePass::NavCube);
+    }
+}
+
+impl Renderer {
+    pub async fn new(window: &Window, config: &RendererConfig) -> Result<Self> {
+        let size = window.inner_size();
+
+        // initializing GPU
+        let instance = wgpu::Instance::new(config.backend.to_wgpu());
+        let surface = unsafe { instance.create_surface(window) };
+        let (device, queue, caps) = Capabilities::request(&instance, Some(&surface)).await?;
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            width: size.width,
+            height: size.height,
+            present_mode: config.present_mode(),
+        };
+        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
+        Self::with_target(device, queue, caps, sc_desc, Target::Window { surface, swap_chain }, config)
+    }
+
+    // renders into an offscreen texture instead of a window, for tests and
+    // tools that only need the pixels
+    pub async fn new_headless(width: u32, height: u32, config: &RendererConfig) -> Result<Self> {
+        let instance = wgpu::Instance::new(config.backend.to_wgpu());
+        let (device, queue, caps) = Capabilities::request(&instance, None).await?;
+        let sc_desc = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let texture = RenderTexture::new(&device, &sc_desc, Some("headless_target"));
+        Self::with_target(device, queue, caps, sc_desc, Target::Headless(texture), config)
+    }
+
+    fn with_target(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        caps: Capabilities,
+        sc_desc: wgpu::SwapChainDescriptor,
+        target: Target,
+        config: &RendererConfig,
+    ) -> Result<Self> {
+        // a quality preset overrides the settings it covers
+        let config = &match config.quality {
+            Some(quality) => quality.settings().apply(config),
+            None => config.clone(),
+        };
+        // setting up uniform buffer, filled in with the camera on every render
+        let uniforms = Uniforms::identity();
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("uniform_buffer"),
+                contents: bytemuck::cast_slice(&[uniforms]),
+                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            }
+        );
+        let uniform_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer {
+                            dynamic: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("uniform_bind_group_layout")
+            }
+        );
+        let uniform_bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout: &uniform_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+                    },
+                ],
+                label: Some("uniform_bind_group"),
+            }
+        );
+
+        // identical layouts are deduplicated by wgpu, so these are
+        // compatible with the bind groups every texture and material creates
+        let texture_bind_group_layout = Material::create_texture_bind_group_layout(&device);
+        let material_bind_group_layout = Material::create_bind_group_layout(&device, None);
+        // the meshes are drawn with the camera and their model's transform
+        // at set 0
+        let transforms = ModelTransforms::new(&device);
+        let model_bind_group_layout = ModelTransforms::create_bind_group_layout(&device);
+        let model_bind_group = transforms.create_bind_group(&device, &model_bind_group_layout, &uniform_buffer);
+        let shadows = ShadowMaps::new(&device, &config.shadows, &transforms, &caps);
+        let environment = Environment::new(
+            &device, &queue, &config.environment, config.background.skybox.as_deref(), &caps)?;
+        let lights = Lights::new(&device, config.lighting.lighting(), &shadows, &environment, config.clustered);
+        let bind_group_layouts = [
+            &model_bind_group_layout,
+            &texture_bind_group_layout,
+            &material_bind_group_layout,
+            lights.bind_group_layout(),
+        ];
+
+        // load shaders
+        let vs_module = device.create_shader_module(
+            wgpu::include_spirv!("shader.vert.spv"));
+        let fs_module = device.create_shader_module(
+            wgpu::include_spirv!("shader.frag.spv"));
+        let vs_params = params::reflect(&params::words(include_bytes!("shader.vert.spv")))?;
+        let fs_params = params::reflect(&params::words(include_bytes!("shader.frag.spv")))?;
+
+        let tonemapper = if config.hdr.enabled {
+            Some(Tonemapper::new(&device, &config.hdr, sc_desc.format))
+        } else {
+            None
+        };
+        let color_format = if tonemapper.is_some() { tonemap::FORMAT } else { sc_desc.format };
+        let bloom = tonemapper.as_ref().map(|_| Bloom::new(&device, &config.bloom, sc_desc.width, sc_desc.height));
+        let fxaa = Fxaa::new(&device, config.fxaa, sc_desc.format);
+        let motion_blur = MotionBlur::new(&device, &config.motion_blur, color_format);
+
+        let depth_texture = DepthTexture::new(&device, &sc_desc, Some("depth_texture"));
+        let upscaler = Upscaler::new(
+            &device, &config.upscale, color_format, sc_desc.width, sc_desc.height);
+        check_sample_count(config.msaa)?;
+        // the upscaler draws into its own single sampled targets
+        let sample_count = if upscaler.is_enabled() { 1 } else { config.msaa };
+
+        // render pipeline
+        let render_pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("render_pipeline_layout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &[],
+            }
+        );
+
+        let render_pipeline = create_render_pipeline(
+            &device, &render_pipeline_layout, &vs_module, &fs_module, color_format, false, sample_count);
+
+        let instanced_vs_module = device.create_shader_module(
+            wgpu::include_spirv!("instanced.vert.spv"));
+        let instanced_pipeline_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("instanced_pipeline_layout"),
+                bind_group_layouts: &[
+                    &model_bind_group_layout,
+                    &texture_bind_group_layout,
+                    &material_bind_group_layout,
+                    lights.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            }
+        );
+        let instanced_pipeline = create_render_pipeline(
+            &device, &instanced_pipeline_layout, &instanced_vs_module, &fs_module, color_format, true,
+            sample_count);
+
+        let imposters = Imposters::new(&device, &config.imposters, color_format, sample_count);
+        let deferred = DeferredPass::new(
+            &device, (&render_pipeline_layout, &instanced_pipeline_layout), (&vs_module, &instanced_vs_module),
+            color_format, sample_count);
+        let ssao = Ssao::new(&device, &queue, &config.ssao);
+        let sdf = SdfPass::new(&device, color_format, sample_count);
+        let curves = CurvePass::new(&device, color_format, sample_count);
+        let ground = Ground::new(&device, &config.ground, color_format, sample_count);
+        let mut background = Background::new(
+            &device, &queue, &config.background, color_format, sample_count)?;
+        background.set_skybox_view(environment.rotation(), environment.intensity());
+        // the heatmaps and nav cube draw straight into the render target
+        let heatmaps = Heatmaps::new(&device, sc_desc.format, sc_desc.width, sc_desc.height);
+        let lines = DebugLines::new(&device, color_format, sample_count);
+        let wireframes = Wireframes::new(&device, color_format, sample_count);
+        let nav_cube = NavCube::new(&device, &queue, sc_desc.format);
+
+        // creating buffers;
+        Ok(Self {
+            target,
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+            caps,
+            sc_desc,
+            color_format,
+            tonemapper,
+            bloom,
+            fxaa,
+            motion_blur,
+            render_pipeline_layout,
+            vs_module,
+            fs_module,
+            vs_params,
+            fs_params,
+            render_pipeline,
+            instanced_pipeline_layout,
+            instanced_vs_module,
+            instanced_pipeline,
+            uniform_buffer,
+            uniform_bind_group,
+            transforms,
+            model_bind_group_layout,
+            model_bind_group,
+            lights,
+            shadows,
+            environment,
+            depth_texture,
+            sample_count,
+            msaa: config.msaa,
+            quality: config.quality,
+            imposters,
+            deferred,
+            deferred_shading: config.deferred,
+            ssao,
+            upscaler,
+            sdf,
+            curves,
+            ground,
+            background,
+            heatmaps,
+            lines,
+            wireframes,
+            nav_cube,
+            show_nav_cube: false,
+            minimap: None,
+            debug: config.debug.clone(),
+            time: 0.0,
+            frozen_frustum: None,
+            highlights: vec![],
+            show_skeletons: false,
+            selected_joint: None,
+            selected: vec![],
+            selection_box: None,
+            external_pipelines: HashMap::new(),
+            external_depth_texture: None,
+            picker: None,
+            pre_pass_hooks: vec![],
+            post_pass_hooks: vec![],
+            overlays: vec![],
+            readbacks: Readbacks::new(),
+            draw_stats: Cell::new(DrawStats::default()),
+            culling: Cell::new(Duration::default()),
+            present_timings: vec![],
+            pass_timings: RefCell::new(vec![]),
+            transients: RefCell::new(TexturePool::new()),
+            frame_graphs: RefCell::new(vec![]),
+        })
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    // the device and queue for uploading from other threads, e.g. while a
+    // scene loads in the background
+    pub fn shared_device(&self) -> (Arc<wgpu::Device>, Arc<wgpu::Queue>) {
+        (self.device.clone(), self.queue.clone())
+    }
+
+    // the optional features and limits the device was granted
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.caps
+    }
+
+    // the color format of the render target
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.sc_desc.format
+    }
+
+    // the color format of the main pass, which overlays' pipelines have to
+    // be created with. differs from `format` with HDR on
+    pub fn color_format(&self) -> wgpu::TextureFormat {
+        self.color_format
+    }
+
+    pub fn add_pre_pass<F>(&mut self, hook: F)
+    where
+        F: Fn(&wgpu::Device, &mut wgpu::CommandEncoder, &wgpu::TextureView) + 'static,
+    {
+        self.pre_pass_hooks.push(Box::new(hook));
+    }
+
+    pub fn add_post_pass<F>(&mut self, hook: F)
+    where
+        F: Fn(&wgpu::Device, &mut wgpu::CommandEncoder, &wgpu::TextureView) + 'static,
+    {
+        self.post_pass_hooks.push(Box::new(hook));
+    }
+
+    pub fn add_overlay<O: Overlay + 'static>(&mut self, overlay: O) {
+        self.overlays.push(Box::new(overlay));
+    }
+
+    // swaps in a recompiled `shader.vert`, `shader.frag` or `instanced.vert`,
+    // named by its file, and rebuilds the mesh pipelines, picking up the
+    // parameters the shader declares now. returns false for shaders the
+    // renderer doesn't draw the meshes with, and an error for parameters
+    // that don't fit, keeping the old shader
+    pub fn reload_shader(&mut self, name: &str, spirv: &[u32]) -> Result<bool> {
+        let device = &self.device;
+        let module = || device.create_shader_module(wgpu::ShaderModuleSource::SpirV(spirv.into()));
+        match name {
+            "shader.vert" => {
+                self.vs_params = params::reflect(spirv).with_context(|| format!("Failed to reflect {}", name))?;
+                self.vs_module = module();
+            }
+            "shader.frag" => {
+                self.fs_params = params::reflect(spirv).with_context(|| format!("Failed to reflect {}", name))?;
+                self.fs_module = module();
+            }
+            "instanced.vert" => self.instanced_vs_module = module(),
+            _ => return Ok(false),
+        }
+        self.render_pipeline = create_render_pipeline(
+            &self.device, &self.render_pipeline_layout, &self.vs_module, &self.fs_module,
+            self.color_format, false, self.sample_count);
+        self.instanced_pipeline = create_render_pipeline(
+            &self.device, &self.instanced_pipeline_layout, &self.instanced_vs_module, &self.fs_module,
+            self.color_format, true, self.sample_count);
+        self.deferred.set_vertex_shaders(&self.device, (&self.render_pipeline_layout, &self.instanced_pipeline_layout),
+                                         (&self.vs_module, &self.instanced_vs_module));
+        // rebuilt from the new modules when next needed
+        self.external_pipelines.clear();
+        Ok(true)
+    }
+
+    // the custom parameters the mesh shaders read per object, see
+    // `params::ShaderParam`
+    pub fn shader_params(&self) -> impl Iterator<Item = &ShaderParam> + Clone {
+        merged_params(&self.vs_params, &self.fs_params)
+    }
+
+    // samples per pixel of the main pass, which overlays' pipelines have to
+    // be created with
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    // switches multisampling, rebuilding the pipelines drawn in the main
+    // pass. while upscaling it only takes effect once the upscaler is off,
+    // and overlays added before have to be recreated
+    pub fn set_sample_count(&mut self, sample_count: u32) -> Result<()> {
+        check_sample_count(sample_count)?;
+        self.msaa = sample_count;
+        // the upscaler draws into its own single sampled targets
+        let sample_count = if self.upscaler.is_enabled() { 1 } else { sample_count };
+        if sample_count == self.sample_count {
+            return Ok(());
+        }
+        self.sample_count = sample_count;
+        self.render_pipeline = create_render_pipeline(
+            &self.device, &self.render_pipeline_layout, &self.vs_module, &self.fs_module,
+            self.color_format, false, sample_count);
+        self.instanced_pipeline = create_render_pipeline(
+            &self.device, &self.instanced_pipeline_layout, &self.instanced_vs_module, &self.fs_module,
+            self.color_format, true, sample_count);
+        self.imposters.set_sample_count(&self.device, sample_count);
+        self.deferred.set_sample_count(&self.device, sample_count);
+        self.sdf.set_sample_count(&self.device, sample_count);
+        self.curves.set_sample_count(&self.device, sample_count);
+        self.ground.set_sample_count(&self.device, sample_count);
+        self.background.set_sample_count(&self.device, sample_count);
+        self.lines.set_sample_count(&self.device, sample_count);
+        self.wireframes.set_sample_count(&self.device, sample_count);
+        Ok(())
+    }
+
+    pub fn is_deferred(&self) -> bool {
+        self.deferred_shading
+    }
+
+    // switches between lighting every mesh fragment as it's drawn and
+    // lighting the g-buffer once per pixel, which pays off with many
+    // lights. translucent materials are cut out at half coverage in the
+    // g-buffer, and the debug views and `render_to_texture` always draw
+    // forward
+    pub fn set_deferred(&mut self, deferred: bool) {
+        self.deferred_shading = deferred;
+    }
+
+    pub fn is_ssao_enabled(&self) -> bool {
+        self.ssao.is_enabled()
+    }
+
+    // switches the ambient occlusion, which needs the g-buffer's depth and
+    // normals, so it only shows with deferred shading
+    pub fn set_ssao(&mut self, enabled: bool) {
+        self.ssao.set_enabled(enabled);
+    }
+
+    pub fn ssao_radius(&self) -> f32 {
+        self.ssao.radius()
+    }
+
+    pub fn set_ssao_radius(&mut self, radius: f32) {
+        self.ssao.set_radius(radius);
+    }
+
+    pub fn ssao_intensity(&self) -> f32 {
+        self.ssao.intensity()
+    }
+
+    pub fn set_ssao_intensity(&mut self, intensity: f32) {
+        self.ssao.set_intensity(intensity);
+    }
+
+    pub fn ssao_samples(&self) -> u32 {
+        self.ssao.samples()
+    }
+
+    // depth lookups per pixel, up to 64
+    pub fn set_ssao_samples(&mut self, samples: u32) {
+        self.ssao.set_samples(samples);
+    }
+
+    pub fn is_clustered(&self) -> bool {
+        self.lights.is_clustered()
+    }
+
+    // switches between binning the lights into clusters every frame, so
+    // each fragment only evaluates the lights reaching it, and evaluating
+    // every light everywhere. both light the same, clustering is what
+    // keeps hundreds of lights affordable
+    pub fn set_clustered(&mut self, clustered: bool) {
+        self.lights.set_clustered(clustered);
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        self.tonemapper.is_some()
+    }
+
+    // the curve mapping the HDR image onto the render target, none with HDR
+    // off
+    pub fn tonemap(&self) -> Option<Tonemap> {
+        self.tonemapper.as_ref().map(Tonemapper::operator)
+    }
+
+    // does nothing with HDR off, which is only chosen at startup
+    pub fn set_tonemap(&mut self, tonemap: Tonemap) {
+        if let Some(tonemapper) = &mut self.tonemapper {
+            tonemapper.set_operator(tonemap);
+        }
+    }
+
+    // the scale applied before the tonemapping curve, 1 with HDR off
+    pub fn tonemap_exposure(&self) -> f32 {
+        self.tonemapper.as_ref().map_or(1.0, Tonemapper::exposure)
+    }
+
+    pub fn set_tonemap_exposure(&mut self, exposure: f32) {
+        if let Some(tonemapper) = &mut self.tonemapper {
+            tonemapper.set_exposure(exposure);
+        }
+    }
+
+    pub fn is_bloom_enabled(&self) -> bool {
+        self.bloom.as_ref().is_some_and(Bloom::is_enabled)
+    }
+
+    // the bloom settings below do nothing with HDR off
+    pub fn set_bloom(&mut self, enabled: bool) {
+        if let Some(bloom) = &mut self.bloom {
+            bloom.set_enabled(enabled);
+        }
+    }
+
+    pub fn bloom_threshold(&self) -> f32 {
+        self.bloom.as_ref().map_or(0.0, Bloom::threshold)
+    }
+
+    pub fn set_bloom_threshold(&mut self, threshold: f32) {
+        if let Some(bloom) = &mut self.bloom {
+            bloom.set_threshold(threshold);
+        }
+    }
+
+    pub fn bloom_intensity(&self) -> f32 {
+        self.bloom.as_ref().map_or(0.0, Bloom::intensity)
+    }
+
+    pub fn set_bloom_intensity(&mut self, intensity: f32) {
+        if let Some(bloom) = &mut self.bloom {
+            bloom.set_intensity(intensity);
+        }
+    }
+
+    pub fn is_motion_blur_enabled(&self) -> bool {
+        self.motion_blur.is_enabled()
+    }
+
+    // switches the blur along the camera's motion. it reads the main pass'
+    // depth, so it's skipped while multisampling without upscaling
+    pub fn set_motion_blur(&mut self, enabled: bool) {
+        self.motion_blur.set_enabled(enabled);
+    }
+
+    pub fn motion_blur_samples(&self) -> u32 {
+        self.motion_blur.samples()
+    }
+
+    pub fn set_motion_blur_samples(&mut self, samples: u32) {
+        self.motion_blur.set_samples(samples);
+    }
+
+    pub fn motion_blur_shutter(&self) -> f32 {
+        self.motion_blur.shutter()
+    }
+
+    pub fn set_motion_blur_shutter(&mut self, shutter: f32) {
+        self.motion_blur.set_shutter(shutter);
+    }
+
+    pub fn is_fxaa_enabled(&self) -> bool {
+        self.fxaa.is_enabled()
+    }
+
+    // switches the antialiasing of the finished image, which works with or
+    // without HDR and msaa. `render_to_texture` skips it
+    pub fn set_fxaa(&mut self, enabled: bool) {
+        self.fxaa.set_enabled(enabled);
+    }
+
+    pub fn quality(&self) -> Option<Quality> {
+        self.quality
+    }
+
+    // switches to a quality preset, rebuilding the shadow maps, the
+    // upscaler and, when the sample count changes, the main pass'
+    // pipelines. the preset's anisotropy is left to the scene's materials,
+    // see `Scene::set_anisotropy`
+    pub fn set_quality(&mut self, quality: Quality) -> Result<()> {
+        let settings = quality.settings();
+        let shadows = ShadowConfig {
+            resolution: settings.shadow_resolution,
+            cascades: settings.shadow_cascades,
+            point_resolution: settings.point_shadow_resolution,
+            ..self.shadows.config().clone()
+        };
+        self.shadows = ShadowMaps::new(&self.device, &shadows, &self.transforms, &self.caps);
+        self.lights.rebind(&self.device, &self.shadows, &self.environment);
+        let upscale = UpscaleConfig { scale: settings.render_scale, ..self.upscaler.config().clone() };
+        self.upscaler = Upscaler::new(
+            &self.device, &upscale, self.color_format, self.sc_desc.width, self.sc_desc.height);
+        self.set_fxaa(settings.fxaa);
+        self.set_ssao(settings.ssao);
+        self.set_ssao_samples(settings.ssao_samples);
+        self.quality = Some(quality);
+        self.set_sample_count(settings.msaa)
+    }
+
+    pub fn shadow_resolution(&self) -> u32 {
+        self.shadows.config().resolution
+    }
+
+    // rebuilds the shadow maps with cascades this wide, keeping the rest of
+    // the current shadow settings. wider than the device takes is clamped
+    pub fn set_shadow_resolution(&mut self, resolution: u32) -> Result<()> {
+        if resolution == 0 {
+            bail!("Shadow resolution must be at least 1");
+        }
+        let max = self.caps.max_texture_size();
+        if resolution > max {
+            warn!("Shadow resolution {} is more than the device supports, using {}", resolution, max);
+        }
+        let resolution = resolution.min(max);
+        if resolution == self.shadow_resolution() {
+            return Ok(());
+        }
+        let shadows = ShadowConfig { resolution, ..self.shadows.config().clone() };
+        self.shadows = ShadowMaps::new(&self.device, &shadows, &self.transforms, &self.caps);
+        self.lights.rebind(&self.device, &self.shadows, &self.environment);
+        Ok(())
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.sc_desc.width = new_size.width;
+        self.sc_desc.height = new_size.height;
+        match &mut self.target {
+            Target::Window { surface, swap_chain } => {
+                *swap_chain = self.device.create_swap_chain(surface, &self.sc_desc);
+            }
+            Target::Headless(texture) => {
+                *texture = RenderTexture::new(&self.device, &self.sc_desc, Some("headless_target"));
+            }
+        }
+        self.depth_texture = DepthTexture::new(&self.device, &self.sc_desc, Some("depth_texture"));
+        self.upscaler.resize(&self.device, new_size.width, new_size.height);
+        self.motion_blur.reset();
+        self.heatmaps.resize(&self.device, &self.queue, new_size.width, new_size.height);
+        if let Some(bloom) = &mut self.bloom {
+            bloom.resize(&self.device, new_size.width, new_size.height);
+        }
+    }
+
+    // renders into the renderer's own target, the window's swap chain or
+    // the offscreen texture of a headless renderer
+    pub fn render_frame(&mut self, scene: &Scene, camera: &Camera) -> Result<()> {
+        self.present(scene, camera, None)
+    }
+
+    // the same, with `after` as the frame graph's last pass, e.g. to put a
+    // UI on top of the frame
+    pub fn render_frame_with<F>(&mut self, scene: &Scene, camera: &Camera,
+                                mut after: F) -> Result<()>
+    where
+        F: FnMut(&wgpu::Device, &wgpu::Queue, &mut wgpu::CommandEncoder, &wgpu::TextureView),
+    {
+        self.present(scene, camera, Some(&mut after))
+    }
+
+    fn present(&mut self, scene: &Scene, camera: &Camera, after: Option<&mut AfterFrame>) -> Result<()> {
+        self.present_timings.clear();
+        let start = Instant::now();
+        self.prepare(scene, camera);
+        self.present_timings.push(("prepare", start.elapsed()));
+        if self.minimap.is_some() {
+            let start = Instant::now();
+            self.render_minimap(scene, camera)?;
+            self.present_timings.push(("minimap", start.elapsed()));
+        }
+        if let Target::Window { swap_chain, .. } = &mut self.target {
+            // waits while the GPU is behind or for vsync
+            let start = Instant::now();
+            let frame = swap_chain.get_current_frame()?.output;
+            self.present_timings.push(("acquire", start.elapsed()));
+            self.draw_frame(scene, camera, &frame.view, None, after)?;
+        } else if let Target::Headless(texture) = &self.target {
+            self.draw_frame(scene, camera, &texture.view, None, after)?;
+        }
+        Ok(())
+    }
+
+    // what the last frame drew, see `DrawStats`
+    pub fn draw_stats(&self) -> DrawStats {
+        self.draw_stats.get()
+    }
+
+    // how long the last graph's passes took to record, without the submit
+    pub fn encode_time(&self) -> Duration {
+        let pass_timings = self.pass_timings.borrow();
+        match pass_timings.split_last() {
+            Some((_, passes)) => passes.iter().map(|(_, time)| *time).sum(),
+            None => Duration::default(),
+        }
+    }
+
+    // adds where the last `render_frame` spent its time to `profile`, with
+    // the encoding broken down by pass at `depth + 1`. these are CPU
+    // times, wgpu can't time the GPU's passes, so its work shows up as
+    // waiting in acquire
+    pub fn profile(&self, profile: &mut Profile, depth: usize) {
+        for (name, time) in &self.present_timings {
+            profile.add(name, depth, *time);
+        }
+        let pass_timings = self.pass_timings.borrow();
+        let (submit, passes) = match pass_timings.split_last() {
+            Some((submit, passes)) => (submit, passes),
+            None => return,
+        };
+        profile.add("encode", depth, self.encode_time());
+        // the meshes are drawn into the g-buffer when there is one,
+        // otherwise in the main pass
+        let meshes = passes.iter().position(|(name, _)| *name == "gbuffer")
+            .or_else(|| passes.iter().position(|(name, _)| *name == "main"));
+        for (i, (name, time)) in passes.iter().enumerate() {
+            profile.add(name, depth + 1, *time);
+            if meshes == Some(i) {
+                profile.add("culling", depth + 2, self.culling.get());
+            }
+        }
+        profile.add(submit.0, depth, submit.1);
+    }
+
+    // moves and recolors the sun, keeping the rest of the lighting
+    pub fn set_light(&mut self, position: [f32; 3], color: [f32; 3]) {
+        self.set_lighting(Lighting {
+            sun_position: position,
+            sun_color: color,
+            ..*self.lights.lighting()
+        });
+    }
+
+    pub fn lighting(&self) -> &Lighting {
+        self.lights.lighting()
+    }
+
+    pub fn set_lighting(&mut self, lighting: Lighting) {
+        self.lights.set_lighting(&self.queue, lighting);
+    }
+
+    // the lights added to the sun, in the order they were added
+    pub fn lights(&self) -> &[Light] {
+        self.lights.lights()
+    }
+
+    // returns the light's index in `lights`
+    pub fn add_light(&mut self, light: Light) -> usize {
+        self.lights.add(&self.device, &self.queue, &self.shadows, &self.environment, light)
+    }
+
+    pub fn update_light(&mut self, index: usize, light: Light) -> Result<()> {
+        self.lights.set(&self.queue, index, light)
+    }
+
+    // the lights after it move down by one index
+    pub fn remove_light(&mut self, index: usize) -> Result<Light> {
+        self.lights.remove(&self.queue, index)
+    }
+
+    pub fn background_mode(&self) -> BackgroundMode {
+        self.background.mode()
+    }
+
+    pub fn set_background_mode(&mut self, mode: BackgroundMode) {
+        self.background.set_mode(mode);
+    }
+
+    // what the `Solid` background mode clears to
+    pub fn set_background_color(&mut self, color: [f32; 3]) {
+        self.background.set_color(color);
+    }
+
+    // degrees the skybox and the environment's light are turned around the
+    // up axis, counterclockwise seen from above
+    pub fn environment_rotation(&self) -> f32 {
+        self.environment.rotation()
+    }
+
+    pub fn set_environment_rotation(&mut self, degrees: f32) {
+        self.environment.set_rotation(&self.queue, degrees);
+        self.background.set_skybox_view(degrees, self.environment.intensity());
+    }
+
+    // what the skybox and the light from the environment are scaled by
+    pub fn environment_intensity(&self) -> f32 {
+        self.environment.intensity()
+    }
+
+    pub fn set_environment_intensity(&mut self, intensity: f32) {
+        self.environment.set_intensity(&self.queue, intensity);
+        self.background.set_skybox_view(self.environment.rotation(), self.environment.intensity());
+    }
+
+    pub fn debug_view(&self) -> DebugView {
+        self.debug.view
+    }
+
+    pub fn set_debug_view(&mut self, view: DebugView) {
+        self.debug.view = view;
+    }
+
+    // advances the animation time the displaced materials move by `dt`
+    // seconds, once per frame before rendering. doesn't allocate
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    // jumps to `seconds`, e.g. to restart animations or match a recording
+    pub fn set_time(&mut self, seconds: f32) {
+        self.time = seconds;
+    }
+
+    // keeps culling against the camera's current frustum while the camera
+    // moves on. the frozen frustum is drawn in yellow and the bounds of the
+    // models it culls in red
+    pub fn freeze_culling(&mut self, camera: &Camera) {
+        self.frozen_frustum = Some(Frustum::from_matrix(camera.build_view_projection_matrix()));
+    }
+
+    pub fn unfreeze_culling(&mut self) {
+        self.frozen_frustum = None;
+    }
+
+    pub fn is_culling_frozen(&self) -> bool {
+        self.frozen_frustum.is_some()
+    }
+
+    // outlines the boxes in their colors on top of every frame until
+    // they're replaced, an empty list removes them
+    pub fn set_highlights(&mut self, highlights: Vec<(Aabb, [f32; 3])>) {
+        self.highlights = highlights;
+    }
+
+    pub fn shows_skeletons(&self) -> bool {
+        self.show_skeletons
+    }
+
+    // draws the skinned models' joints as bones from their parents on top
+    // of every frame
+    pub fn set_skeletons(&mut self, show: bool) {
+        self.show_skeletons = show;
+    }
+
+    pub fn selected_joint(&self) -> Option<(usize, usize)> {
+        self.selected_joint
+    }
+
+    // highlights a joint of the skeletons, given as model and joint index
+    pub fn set_selected_joint(&mut self, joint: Option<(usize, usize)>) {
+        self.selected_joint = joint;
+    }
+
+    // outlines the models' bounds on top of every frame, wherever they
+    // move to
+    pub fn set_selected(&mut self, models: Vec<usize>) {
+        self.selected = models;
+    }
+
+    // draws the `x`, `y`, width and height of a box over the output, none
+    // removes it
+    pub fn set_selection_box(&mut self, region: Option<(u32, u32, u32, u32)>) {
+        self.selection_box = region;
+    }
+
+    pub fn shows_nav_cube(&self) -> bool {
+        self.show_nav_cube
+    }
+
+    // draws the navigation cube over every frame, after the post-pass
+    // hooks
+    pub fn set_nav_cube(&mut self, show: bool) {
+        self.show_nav_cube = show;
+    }
+
+    // shows a second view of the scene in the bottom left corner of every
+    // frame, none removes it. it's drawn like `render_to_texture`
+    pub fn set_minimap(&mut self, view: Option<MinimapView>) {
+        self.minimap = match (self.minimap.take(), view) {
+            (Some(mut minimap), Some(view)) => {
+                minimap.set_view(view);
+                Some(minimap)
+            }
+            (None, Some(view)) => Some(Minimap::new(&self.device, self.sc_desc.format, view)),
+            (_, None) => None,
+        };
+    }
+
+    pub fn minimap_view(&self) -> Option<MinimapView> {
+        self.minimap.as_ref().map(Minimap::view)
+    }
+
+    // the point of the scene under `cursor` on the minimap, see
+    // `Minimap::point_at`
+    pub fn minimap_point(&self, scene: &Scene, camera: &Camera,
+                         cursor: winit::dpi::PhysicalPosition<f64>) -> Option<Vec3> {
+        let minimap = self.minimap.as_ref()?;
+        let size = winit::dpi::PhysicalSize::new(self.sc_desc.width, self.sc_desc.height);
+        minimap.point_at(scene, &minimap.camera(scene, camera), size, cursor)
+    }
+
+    // the minimap's view, submitted before the frame compositing it
+    fn render_minimap(&mut self, scene: &Scene, camera: &Camera) -> Result<()> {
+        if let Some(minimap) = self.minimap.take() {
+            let map_camera = minimap.camera(scene, camera);
+            let result = self.render_to_texture(scene, &map_camera, minimap.target(), minimap::SIZE,
+                                                minimap::SIZE, self.sc_desc.format);
+            minimap.prepare(&self.queue, &map_camera, camera);
+            self.minimap = Some(minimap);
+            result?;
+        }
+        Ok(())
+    }
+
+    // the face, edge or corner of the navigation cube under `cursor`, as a
+    // direction from its center, see `NavCube::region_at`
+    pub fn nav_cube_region(&self, camera: &Camera, cursor: winit::dpi::PhysicalPosition<f64>) -> Option<Vec3> {
+        if !self.show_nav_cube {
+            return None;
+        }
+        let size = winit::dpi::PhysicalSize::new(self.sc_desc.width, self.sc_desc.height);
+        self.nav_cube.region_at(camera, size, cursor)
+    }
+
+    // highlights a region `nav_cube_region` returned, none clears it
+    pub fn hover_nav_cube(&mut self, region: Option<Vec3>) {
+        self.nav_cube.set_hovered(&self.queue, region);
+    }
+
+    // renders the scene into a temporary texture and reads it back, works
+    // for window and headless renderers alike
+    pub async fn screenshot(&mut self, scene: &Scene, camera: &Camera) -> Result<image::RgbaImage> {
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let (width, height) = (self.sc_desc.width, self.sc_desc.height);
+        let texture = RenderTexture::with_size(&self.device, width, height, format, Some("screenshot"));
+        self.render_to_texture(scene, camera, &texture.view, width, height, format)?;
+        texture.read(&self.device, &self.queue).await
+    }
+
+    // reads back the last rendered frame of a headless renderer
+    pub async fn capture(&self) -> Result<image::RgbaImage> {
+        match &self.target {
+            Target::Headless(texture) => texture.read(&self.device, &self.queue).await,
+            Target::Window { .. } => bail!("Only headless renderers can be captured"),
+        }
+    }
+
+    // renders a frame the way `render_frame` does, but into an offscreen
+    // target, and saves what it drew along the way into `dir`: the shadow
+    // maps, the depth buffer, the upscaler's input and history and the
+    // final image, along with the frame graph as frame_graph.dot, see
+    // `RenderGraph::to_dot`. the depth buffer is left out while
+    // multisampling, since multisampled textures can't be copied. waits for
+    // the GPU
+    pub fn dump_frame(&mut self, scene: &Scene, camera: &Camera, dir: &Path) -> Result<Vec<PathBuf>> {
+        self.prepare(scene, camera);
+        let color = RenderTexture::new(&self.device, &self.sc_desc, Some("dump_color"));
+        let graph = self.frame_graph(scene, camera, &color.view)?.to_dot()?;
+        self.render(scene, camera, &color.view)?;
+        let mut targets = self.shadows.dump_targets();
+        if self.upscaler.is_enabled() {
+            targets.extend(self.upscaler.dump_targets());
+        } else if self.sample_count == 1 {
+            targets.push(self.depth_texture.dump_target("depth"));
+        }
+        targets.push(color.dump_target("color"));
+        let mut paths = dump::save(&self.device, &self.queue, &mut self.readbacks, &targets, dir)?;
+        let path = dir.join("frame_graph.dot");
+        std::fs::write(&path, graph).with_context(|| format!("Failed to write {}", path.display()))?;
+        paths.push(path);
+        Ok(paths)
+    }
+
+    // like `screenshot` but without waiting for the GPU, the image arrives
+    // on the receiver a frame or two later
+    pub fn screenshot_async(&mut self, scene: &Scene, camera: &Camera)
+                            -> Result<Receiver<Result<image::RgbaImage>>> {
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let (width, height) = (self.sc_desc.width, self.sc_desc.height);
+        let texture = RenderTexture::with_size(&self.device, width, height, format, Some("screenshot"));
+        self.render_to_texture(scene, camera, &texture.view, width, height, format)?;
+        Ok(texture.read_async(&self.device, &self.queue, &mut self.readbacks))
+    }
+
+    // renders a frame the way `render_frame` does, every pass included,
+    // into an offscreen target in the swap chain's format and copies it
+    // back without waiting for the GPU. the swap chain's images can't be
+    // copied from themselves
+    pub fn capture_frame_async(&mut self, scene: &Scene, camera: &Camera)
+                               -> Result<Receiver<Result<image::RgbaImage>>> {
+        self.prepare(scene, camera);
+        let color = RenderTexture::new(&self.device, &self.sc_desc, Some("capture_color"));
+        self.render(scene, camera, &color.view)?;
+        Ok(color.read_async(&self.device, &self.queue, &mut self.readbacks))
+    }
+
+    // like `capture` but without waiting for the GPU
+    pub fn capture_async(&mut self) -> Result<Receiver<Result<image::RgbaImage>>> {
+        match &self.target {
+            Target::Headless(texture) => Ok(texture.read_async(&self.device, &self.queue, &mut self.readbacks)),
+            Target::Window { .. } => bail!("Only headless renderers can be captured"),
+        }
+    }
+
+    // copies part of a buffer with COPY_SRC usage back without waiting, for
+    // results of passes the renderer doesn't know about
+    pub fn read_buffer(&mut self, buffer: &wgpu::Buffer, offset: wgpu::BufferAddress,
+                       size: wgpu::BufferAddress) -> Receiver<Result<Vec<u8>>> {
+        self.readbacks.read_buffer(&self.device, &self.queue, buffer, offset, size)
+    }
+
+    // same for the first level of a four-byte-per-pixel texture with
+    // COPY_SRC usage
+    pub fn read_texture(&mut self, texture: &wgpu::Texture,
+                        size: wgpu::Extent3d) -> Receiver<Result<image::RgbaImage>> {
+        self.readbacks.read_texture(&self.device, &self.queue, texture, size)
+    }
+
+    // what the scene shows at pixel `x`, `y` of the output from `camera`,
+    // down to the sub-mesh and triangle. it's read back without waiting
+    // like `read_buffer`, and is None where no model was drawn
+    pub fn pick(&mut self, scene: &Scene, camera: &Camera, x: u32, y: u32) -> Receiver<Result<Option<Pick>>> {
+        self.upload_transforms(scene);
+        let device = &self.device;
+        let picker = self.picker.get_or_insert_with(|| Picker::new(device));
+        picker.pick(&self.device, &self.queue, &mut self.readbacks, (scene, &self.transforms),
+                    camera.build_view_projection_matrix(), (x, y, self.sc_desc.width, self.sc_desc.height))
+    }
+
+    // the models the scene shows anywhere in the `region` (x, y, width and
+    // height) of the output from `camera`, read back like `pick`
+    pub fn select_region(&mut self, scene: &Scene, camera: &Camera,
+                         region: (u32, u32, u32, u32)) -> Receiver<Result<Vec<usize>>> {
+        self.upload_transforms(scene);
+        let device = &self.device;
+        let picker = self.picker.get_or_insert_with(|| Picker::new(device));
+        picker.select(&self.device, &self.queue, &mut self.readbacks, (scene, &self.transforms),
+                      camera.build_view_projection_matrix(), (region, self.sc_desc.width, self.sc_desc.height))
+    }
+
+    // draws the scene around `position` into six cube faces, clipped like
+    // `camera`, and blurs them into maps that surfaces within the
+    // environment's `probe_radius` of it reflect instead of the panorama,
+    // replacing the last capture. the faces go through `render_to_texture`,
+    // so what that skips doesn't show up in the reflections
+    pub fn capture_environment(&mut self, scene: &Scene, camera: &Camera, position: Vec3) -> Result<()> {
+        if !self.environment.can_capture() {
+            bail!("Capturing the environment needs storage textures, which the device doesn't have");
+        }
+        let size = self.environment.probe_resolution();
+        let faces = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("probe_faces"),
+            size: wgpu::Extent3d { width: size, height: size, depth: 6 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: ibl::FORMAT,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        let mut matrices = [[[0.0; 4]; 4]; 6];
+        for (face, (direction, up)) in shadow::FACES.iter().enumerate() {
+            let face_camera = Camera {
+                eye: position,
+                center: position + Vec3::from(*direction),
+                up: Vec3::from(*up),
+                aspect_ratio: 1.0,
+                fov: std::f32::consts::FRAC_PI_2,
+                z_near: camera.z_near,
+                z_far: camera.z_far,
+            };
+            let view = faces.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: face as u32,
+                array_layer_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            });
+            self.render_to_texture(scene, &face_camera, &view, size, size, ibl::FORMAT)?;
+            matrices[face] = face_camera.build_view_projection_matrix().to_cols_array_2d();
+        }
+        self.environment.capture(&self.device, &self.queue, &faces, matrices, position);
+        self.lights.rebind(&self.device, &self.shadows, &self.environment);
+        Ok(())
+    }
+
+    // hands over the readbacks the GPU is done with. `prepare` does this
+    // every frame, call it directly while no frames are rendered
+    pub fn poll_readbacks(&mut self) {
+        self.readbacks.poll(&self.device);
+    }
+
+    // per-frame work that needs to mutate the renderer, done by
+    // `render_frame`. call it before `render` when drawing into your own views
+    pub fn prepare(&mut self, scene: &Scene, camera: &Camera) {
+        self.readbacks.poll(&self.device);
+        self.upload_transforms(scene);
+        // the atlas is single sampled
+        self.create_external_pipelines(self.color_format);
+        let main = MainPass {
+            pipeline: self.external_pipelines.get(&self.color_format)
+                .map_or(&self.render_pipeline, |(pipeline, _)| pipeline),
+            transforms: &self.transforms,
+            // the clusters are built for the camera, not the atlas' views
+            light_bind_group: self.lights.unclustered_bind_group(),
+        };
+        self.imposters.prepare(&self.device, &self.queue, scene, camera, main);
+        if self.upscaler.is_enabled() {
+            self.upscaler.prepare(&self.queue, camera);
+        }
+        if self.motion_blur.is_enabled() {
+            self.motion_blur.prepare(&self.queue, camera);
+        }
+        self.heatmaps.prepare(&self.device, scene, self.debug.view);
+
+        // with the frustum frozen, show it and the meshes it culls, the
+        // same models `draw_meshes` leaves out
+        self.lines.clear();
+        if let Some(frustum) = &self.frozen_frustum {
+            self.lines.cuboid(&frustum.corners(), [1.0, 0.9, 0.1]);
+            self.wireframes.prepare(&self.device, scene, (0..scene.models.len()).filter(|&model| {
+                !scene.is_instanced(model) && scene.models[model].is_drawn()
+                    && !frustum.intersects(&scene.models[model].world_bounds())
+            }));
+        } else {
+            self.wireframes.prepare(&self.device, scene, std::iter::empty());
+        }
+        for (bounds, color) in &self.highlights {
+            self.lines.aabb(bounds, *color);
+        }
+        for &model in &self.selected {
+            if let Some(model) = scene.models.get(model) {
+                self.lines.aabb(&model.world_bounds(), [1.0, 0.6, 0.1]);
+            }
+        }
+        if let Some(region) = self.selection_box {
+            self.selection_box_lines(camera, region);
+        }
+        if self.show_skeletons {
+            self.skeleton_lines(scene);
+        }
+        self.lines.upload(&self.device, &self.queue);
+        self.curves.upload(&self.device, &self.queue, &scene.curves);
+    }
+
+    // the box's corners put just in front of the camera, so it's drawn
+    // over everything
+    fn selection_box_lines(&mut self, camera: &Camera, (x, y, width, height): (u32, u32, u32, u32)) {
+        let inverse = camera.build_view_projection_matrix().inverse();
+        let (output_width, output_height) = (self.sc_desc.width.max(1) as f32, self.sc_desc.height.max(1) as f32);
+        let corner = |x: u32, y: u32| {
+            let ndc = Vec4::new(x as f32 / output_width * 2.0 - 1.0, 1.0 - y as f32 / output_height * 2.0, 0.5, 1.0);
+            let world = inverse * ndc;
+            world.truncate() / world.w
+        };
+        let corners = [corner(x, y), corner(x + width, y), corner(x + width, y + height), corner(x, y + height)];
+        for i in 0..4 {
+            self.lines.line(corners[i], corners[(i + 1) % 4], [1.0, 1.0, 1.0]);
+        }
+    }
+
+    // each joint as a bone from its parent, and roots as crosses sized by
+    // their model
+    fn skeleton_lines(&mut self, scene: &Scene) {
+        for (index, model) in scene.models.iter().enumerate() {
+            let skin = match model.skin.as_ref().filter(|_| !model.hidden) {
+                Some(skin) => skin,
+                None => continue,
+            };
+            let matrix = model.transform.matrix();
+            let size = (model.world_bounds().max - model.world_bounds().min).length() * 0.05;
+            for (joint, Joint { parent, .. }) in skin.joints().iter().enumerate() {
+                let color = if self.selected_joint == Some((index, joint)) {
+                    [1.0, 0.6, 0.1]
+                } else {
+                    [0.3, 0.8, 1.0]
+                };
+                let position = matrix.transform_point3(skin.joint_position(joint));
+                match parent {
+                    Some(parent) => {
+                        let head = matrix.transform_point3(skin.joint_position(*parent));
+                        self.lines.bone(head, position, color);
+                    }
+                    None => self.lines.cross(position, size, color),
+                }
+            }
+        }
+    }
+
+    // uploads the models' transforms, rebuilding the bind groups when the
+    // buffer had to grow
+    fn upload_transforms(&mut self, scene: &Scene) {
+        let params = merged_params(&self.vs_params, &self.fs_params);
+        if self.transforms.upload(&self.device, &self.queue, scene, params) {
+            self.model_bind_group = self.transforms.create_bind_group(
+                &self.device, &self.model_bind_group_layout, &self.uniform_buffer);
+            self.shadows.rebind(&self.device, &self.transforms);
+        }
+    }
+
+    // draws the scene into any view matching the target format and size
+    pub fn render(&self, scene: &Scene, camera: &Camera, view: &wgpu::TextureView) -> Result<()> {
+        self.draw_frame(scene, camera, view, None, None)
+    }
+
+    // the passes `render` records to draw the scene into `view`: the
+    // pre-pass hooks, the shadow maps, the g-buffer and its ambient
+    // occlusion, the main pass and the upscaler, motion blur, bloom,
+    // tonemapping, fxaa and post-pass hooks when they're on. the frame's color target is
+    // imported as `FRAME`, so passes added to the graph can read and write it
+    pub fn frame_graph<'a>(&'a self, scene: &'a Scene, camera: &'a Camera,
+                           view: &'a wgpu::TextureView) -> Result<RenderGraph<'a>> {
+        let key = self.graph_key(false, false);
+        let frame = self.frame(scene, camera, &key, None);
+        let mut graph = build_graph(&key)?
+            .map(|pass| -> Record<'a> {
+                Box::new(move |encoder, attachments| self.record_pass(pass, &frame, encoder, attachments))
+            });
+        for (name, view) in &self.imports(view, None) {
+            if graph.has(name) {
+                graph.import(name, view);
+            }
+        }
+        Ok(graph)
+    }
+
+    // records a graph's passes into one encoder and submits it, keeping its
+    // transient textures around for the next frames
+    pub fn execute(&self, graph: RenderGraph) -> Result<()> {
+        graph.execute(&self.device, &self.queue, &mut self.transients.borrow_mut(),
+                      &mut self.pass_timings.borrow_mut())
+    }
+
+    // runs the built-in passes, from a graph that's only rebuilt when
+    // something it depends on changes. `external` is the mesh and instanced
+    // pipelines and depth buffer for a view that isn't the render target,
+    // see `GraphKey::external`
+    fn draw_frame(&self, scene: &Scene, camera: &Camera, view: &wgpu::TextureView,
+                  external: Option<((&wgpu::RenderPipeline, &wgpu::RenderPipeline), &wgpu::TextureView)>,
+                  mut after: Option<&mut AfterFrame>) -> Result<()> {
+        let key = self.graph_key(external.is_some(), after.is_some());
+        let mut graphs = self.frame_graphs.borrow_mut();
+        let index = match graphs.iter().position(|(other, _)| *other == key) {
+            Some(index) => index,
+            None => {
+                // one graph is kept for each kind of frame
+                graphs.retain(|(other, _)| (other.external, other.after) != (key.external, key.after));
+                graphs.push((key, build_graph(&key)?));
+                graphs.len() - 1
+            }
+        };
+        let frame = self.frame(scene, camera, &key, external.map(|(pipelines, _)| pipelines));
+        let imports = self.imports(view, external.map(|(_, depth)| depth));
+        graphs[index].1.run(&self.device, &self.queue, &mut self.transients.borrow_mut(),
+                            &mut self.pass_timings.borrow_mut(), &imports, |pass, encoder, attachments| {
+            match (pass, &mut after) {
+                (FramePass::AfterFrame, Some(after)) => {
+                    after(&self.device, &self.queue, encoder, attachments.view(FRAME));
+                }
+                _ => self.record_pass(*pass, &frame, encoder, attachments),
+            }
+        })
+    }
+
+    // draws the scene into a caller-provided view of any size and color
+    // format, e.g. to composite it into another application's UI. the view's
+    // texture needs the OUTPUT_ATTACHMENT usage. hooks, overlays, imposters,
+    // the ground, gradient and skybox backgrounds and raymarched shapes are
+    // skipped since they're built for the main pass' format, and the image
+    // isn't tonemapped
+    pub fn render_to_texture(
+        &mut self,
+        scene: &Scene,
+        camera: &Camera,
+        view: &wgpu::TextureView,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Result<()> {
+        self.upload_transforms(scene);
+        self.create_external_pipelines(format);
+        let size = wgpu::Extent3d { width, height, depth: 1 };
+        if self.external_depth_texture.as_ref().map_or(true, |depth| depth.size != size) {
+            self.external_depth_texture = Some(DepthTexture::with_size(
+                &self.device, width, height, Some("external_depth_texture")));
+        }
+
+        let pipelines = match self.external_pipelines.get(&format) {
+            Some((pipeline, instanced_pipeline)) => (pipeline, instanced_pipeline),
+            None => (&self.render_pipeline, &self.instanced_pipeline),
+        };
+        let depth_view = &self.external_depth_texture.as_ref().unwrap().view;
+        self.draw_frame(scene, camera, view, Some((pipelines, depth_view)), None)
+    }
+
+    // external views are single sampled, so while multisampling the render
+    // target's format needs its own pipelines too
+    fn create_external_pipelines(&mut self, format: wgpu::TextureFormat) {
+        let own = format == self.color_format && self.sample_count == 1;
+        if own || self.external_pipelines.contains_key(&format) {
+            return;
+        }
+        let pipeline = create_render_pipeline(
+            &self.device, &self.render_pipeline_layout, &self.vs_module, &self.fs_module, format, false, 1);
+        let instanced_pipeline = create_render_pipeline(
+            &self.device, &self.instanced_pipeline_layout, &self.instanced_vs_module, &self.fs_module,
+            format, true, 1);
+        self.external_pipelines.insert(format, (pipeline, instanced_pipeline));
+    }
+
+    // what the built-in passes of a frame are decided by, checked every
+    // frame without allocating
+    fn graph_key(&self, external: bool, after: bool) -> GraphKey {
+        let run_hooks = !external;
+        let upscale = run_hooks && self.upscaler.is_enabled();
+        let hdr = run_hooks && self.tonemapper.is_some();
+        let gbuffer = run_hooks && self.deferred_shading && self.debug.view == DebugView::Shaded;
+        GraphKey {
+            external,
+            after,
+            heatmaps: run_hooks && self.debug.view.is_heatmap(),
+            upscale,
+            // the g-buffer and the occlusion are sized like the main pass
+            input_size: if upscale { self.upscaler.input_size() } else { (self.sc_desc.width, self.sc_desc.height) },
+            size: (self.sc_desc.width, self.sc_desc.height),
+            format: self.sc_desc.format,
+            color_format: self.color_format,
+            sample_count: self.sample_count,
+            fxaa: run_hooks && self.fxaa.is_enabled(),
+            hdr,
+            bloom: hdr && matches!(&self.bloom, Some(bloom) if bloom.is_enabled()),
+            // the blur reads the main pass' depth, which it can't while
+            // that's multisampled
+            motion_blur: run_hooks && self.motion_blur.is_enabled() && (upscale || self.sample_count == 1),
+            gbuffer,
+            ssao: gbuffer && self.ssao.is_enabled(),
+            pre_pass_hooks: run_hooks && !self.pre_pass_hooks.is_empty(),
+            post_pass_hooks: run_hooks && !self.post_pass_hooks.is_empty(),
+            minimap: run_hooks && self.minimap.is_some(),
+            nav_cube: run_hooks && self.show_nav_cube,
+        }
+    }
+
+    fn frame<'a>(&'a self, scene: &'a Scene, camera: &'a Camera, key: &GraphKey,
+                 pipelines: Option<(&'a wgpu::RenderPipeline, &'a wgpu::RenderPipeline)>) -> Frame<'a> {
+        Frame {
+            scene,
+            camera,
+            pipelines: pipelines.unwrap_or((&self.render_pipeline, &self.instanced_pipeline)),
+            jitter: if key.upscale { self.upscaler.jitter() } else { Vec2::zero() },
+        }
+    }
+
+    // the views of the resources `build_graph` imports, `depth` being the
+    // external depth buffer
+    fn imports<'a>(&'a self, view: &'a wgpu::TextureView,
+                   depth: Option<&'a wgpu::TextureView>) -> [(Resource, &'a wgpu::TextureView); 4] {
+        [
+            (FRAME, view),
+            ("depth", depth.unwrap_or(&self.depth_texture.view)),
+            ("upscale_color", self.upscaler.color_view()),
+            ("upscale_depth", self.upscaler.depth_view()),
+        ]
+    }
+
+    fn record_pass(&self, pass: FramePass, frame: &Frame, encoder: &mut wgpu::CommandEncoder,
+                   attachments: &Attachments) {
+        let &Frame { scene, camera, pipelines, jitter } = frame;
+        match pass {
+            FramePass::Heatmaps => {
+                self.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(
+                    &[Uniforms::new(camera, Vec2::zero(), &self.debug, self.time)]));
+                self.heatmaps.render(encoder, scene, self.debug.view, attachments.view(FRAME),
+                                     &self.uniform_bind_group, &self.model_bind_group);
+            }
+            FramePass::PrePassHooks => {
+                for hook in &self.pre_pass_hooks {
+                    hook(&self.device, encoder, attachments.view(FRAME));
+                }
+            }
+            FramePass::Shadows => {
+                self.shadows.render(encoder, &self.queue, scene, camera, self.lights.lighting(), self.lights.lights());
+            }
+            // the camera is uploaded by the main pass, which is submitted
+            // along with this one
+            FramePass::Gbuffer => {
+                let clear = wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), store: true };
+                let target = |index: usize| wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: attachments.view(deferred::TARGETS[index].0),
+                    resolve_target: None,
+                    ops: clear,
+                };
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    color_attachments: &[target(0), target(1), target(2), target(3)],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                        attachment: attachments.view(deferred::DEPTH),
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    }),
+                });
+                self.draw_meshes(&mut render_pass, scene, camera, self.deferred.gbuffer_pipelines(), true);
+            }
+            FramePass::Ssao => {
+                let normal = deferred::TARGETS[1].0;
+                self.ssao.prepare(&self.queue, camera, jitter);
+                self.ssao.render(&self.device, encoder,
+                                 (attachments.view(normal), attachments.view(deferred::DEPTH)),
+                                 (attachments.view(ssao::RAW), attachments.view(ssao::OCCLUSION)));
+            }
+            FramePass::LightClusters => self.lights.cluster(encoder, &self.queue, camera, jitter),
+            FramePass::Main { color, resolve, depth, gbuffer, ssao, run_hooks } => {
+                self.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(
+                    &[Uniforms::new(camera, jitter, &self.debug, self.time)]));
+                // made before the pass borrows it, and kept while the
+                // g-buffer's textures stay the same
+                let gbuffer = if gbuffer {
+                    self.deferred.prepare(&self.queue, camera, jitter);
+                    let view = |index: usize| attachments.view(deferred::TARGETS[index].0);
+                    let occlusion = if ssao { attachments.view(ssao::OCCLUSION) } else { self.ssao.unoccluded() };
+                    Some(self.deferred.bind(&self.device, attachments.generation(),
+                                            [view(0), view(1), view(2), view(3),
+                                             attachments.view(deferred::DEPTH), occlusion]))
+                } else {
+                    None
+                };
+                if run_hooks {
+                    self.sdf.prepare(&self.queue, &scene.sdf, camera, jitter);
+                    self.curves.prepare(&self.queue, camera, jitter, self.sc_desc.width, self.sc_desc.height);
+                    self.ground.prepare(&self.queue, scene);
+                    self.background.prepare(&self.queue, camera);
+                }
+                let mut render_pass = encoder
+                    .begin_render_pass(&wgpu::RenderPassDescriptor {
+                        color_attachments: &[
+                            wgpu::RenderPassColorAttachmentDescriptor {
+                                attachment: attachments.view(color),
+                                resolve_target: resolve.map(|resolve| attachments.view(resolve)),
+                                ops: wgpu::Operations {
+                                    load: wgpu::LoadOp::Clear(self.background.clear_color(self.lights.lighting().sky_color)),
+                                    store: true,
+                                },
+                            },
+                        ],
+                        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                            attachment: attachments.view(depth),
+                            depth_ops: Some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(1.0),
+                                store: true,
+                            }),
+                            stencil_ops: None,
+                        }),
+                    });
+                if run_hooks {
+                    self.background.draw(&mut render_pass);
+                }
+                // the lit g-buffer writes depth over everything, so it goes
+                // before the ground
+                if let Some(gbuffer) = &gbuffer {
+                    self.deferred.draw(&mut render_pass, gbuffer, self.lights.bind_group());
+                }
+                if run_hooks {
+                    self.ground.draw(&mut render_pass, &self.uniform_bind_group, self.lights.bind_group());
+                }
+                if gbuffer.is_none() {
+                    self.draw_meshes(&mut render_pass, scene, camera, pipelines, run_hooks);
+                }
+                if run_hooks {
+                    self.sdf.draw(&mut render_pass, &scene.sdf, self.lights.bind_group());
+                    self.curves.draw(&mut render_pass);
+                    self.imposters.draw(&mut render_pass, &self.uniform_bind_group);
+                    self.wireframes.draw(&mut render_pass, scene, &self.model_bind_group);
+                    self.lines.draw(&mut render_pass, &self.uniform_bind_group);
+                    for overlay in &self.overlays {
+                        overlay.draw(&self.device, &mut render_pass);
+                    }
+                }
+            }
+            FramePass::Upscale { output } => self.upscaler.resolve(encoder, attachments.view(output)),
+            FramePass::MotionBlur { depth, output } => {
+                self.motion_blur.render(&self.device, encoder,
+                                        (attachments.view(motion_blur::INPUT), attachments.view(depth)),
+                                        attachments.view(output));
+            }
+            FramePass::Bloom => {
+                if let Some(bloom) = &self.bloom {
+                    bloom.render(&self.device, &self.queue, encoder, attachments.view(HDR_COLOR));
+                }
+            }
+            FramePass::Tonemap { output } => {
+                if let Some(tonemapper) = &self.tonemapper {
+                    tonemapper.render(&self.device, &self.queue, encoder, attachments.view(HDR_COLOR),
+                                      attachments.view(output));
+                }
+            }
+            FramePass::Fxaa => {
+                self.fxaa.render(&self.device, encoder, attachments.view(LDR_COLOR), attachments.view(FRAME));
+            }
+            FramePass::PostPassHooks => {
+                for hook in &self.post_pass_hooks {
+                    hook(&self.device, encoder, attachments.view(FRAME));
+                }
+            }
+            FramePass::Minimap => {
+                if let Some(minimap) = &self.minimap {
+                    let size = winit::dpi::PhysicalSize::new(self.sc_desc.width, self.sc_desc.height);
+                    minimap.composite(encoder, attachments.view(FRAME), size);
+                }
+            }
+            FramePass::NavCube => {
+                self.nav_cube.prepare(&self.queue, camera);
+                let size = winit::dpi::PhysicalSize::new(self.sc_desc.width, self.sc_desc.height);
+                self.nav_cube.render(encoder, attachments.view(FRAME), size);
+            }
+            // only recorded by `draw_frame`, which has the closure
+            FramePass::AfterFrame => {}
+        }
+    }
+
+    // the scene's meshes in view. `main` is set for the render target's
+    // own frame, which leaves out the models drawn as imposters, culls
+    // against the frozen frustum if there is one and records the draw
+    // stats and culling time. views like the minimap's only cull against
+    // their own camera
+    fn draw_meshes<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, scene: &'a Scene, camera: &Camera,
+                       (pipeline, instanced_pipeline): (&'a wgpu::RenderPipeline, &'a wgpu::RenderPipeline),
+                       main: bool) {
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(3, self.lights.bind_group(), &[]);
+        let frustum = match self.frozen_frustum {
+            Some(frustum) if main => frustum,
+            _ => Frustum::from_matrix(camera.build_view_projection_matrix()),
+        };
+        let mut stats = DrawStats::default();
+        let mut culling = Duration::default();
+        for draw in &scene.draws {
+            if (main && self.imposters.is_active(draw.model)) || scene.is_instanced(draw.model)
+                || !scene.models[draw.model].is_drawn() {
+                continue;
+            }
+            let start = Instant::now();
+            let visible = frustum.intersects(&scene.models[draw.model].world_bounds());
+            culling += start.elapsed();
+            if !visible {
+                continue;
+            }
+            let material = &scene.materials[draw.material];
+            let mesh = &scene.models[draw.model].mesh;
+            render_pass.set_bind_group(0, &self.model_bind_group, &[ModelTransforms::offset(draw.model)]);
+            render_pass.set_bind_group(1, &material.texture_bind_group, &[]);
+            render_pass.set_bind_group(2, &material.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..));
+            render_pass.draw_indexed(draw.indices.clone(), 0, 0..1);
+            stats.add(&draw.indices, 1);
+        }
+        self.draw_instanced(render_pass, scene, instanced_pipeline, &mut stats);
+        if main {
+            self.draw_stats.set(stats);
+            self.culling.set(culling);
+        }
+    }
+
+    // one draw per sub-mesh of every instance group, covering all its copies
+    fn draw_instanced<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, scene: &'a Scene,
+                          pipeline: &'a wgpu::RenderPipeline, stats: &mut DrawStats) {
+        let drawn = |group: &&InstanceGroup| !group.buffer.is_empty() && scene.models[group.model].is_drawn();
+        if !scene.instances.iter().any(|group| drawn(&group)) {
+            return;
+        }
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(3, self.lights.bind_group(), &[]);
+        for group in scene.instances.iter().filter(drawn) {
+            // the copies share their model's parameters
+            render_pass.set_bind_group(0, &self.model_bind_group, &[ModelTransforms::offset(group.model)]);
+            let mesh = &scene.models[group.model].mesh;
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, group.buffer.slice());
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..));
+            for draw in scene.draws.iter().filter(|draw| draw.model == group.model) {
+                let material = &scene.materials[draw.material];
+                render_pass.set_bind_group(1, &material.texture_bind_group, &[]);
+                render_pass.set_bind_group(2, &material.bind_group, &[]);
+                render_pass.draw_indexed(draw.indices.clone(), 0, 0..group.buffer.len());
+                stats.add(&draw.indices, group.buffer.len());
+            }
+        }
+    }
+}
+
@@ -0,0 +1,900 @@
+use crate::assets::{Assets, Handle};
+use crate::batching;
+use crate::camera::Camera;
+use crate::collision::{Aabb, Bvh, Triangle};
+use crate::config::StreamingConfig;
+use crate::curve::Curve;
+use crate::gltf_loader;
+use crate::import::ImportOptions;
+use crate::pack;
+use crate::mesh::{self, DynamicMesh, Instance, InstanceBuffer, Mesh, Model, SubMesh, Transform};
+use crate::material::{self, Material};
+use crate::params;
+use crate::sdf::Primitive;
+use crate::stats::{ModelStats, SceneStats, StageTimer, TextureStats};
+use crate::streaming;
+use crate::texture::{SamplerDesc, Texture};
+use crate::vertex::Vertex;
+use glam::Vec3;
+use anyhow::{bail, Context, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tobj;
+use wgpu;
+
+static NEXT_SCENE_ID: AtomicU64 = AtomicU64::new(0);
+
+pub struct Scene {
+    // unique per loaded scene, so GPU caches built from one scene can tell
+    // when they're handed another
+    pub(crate) id: u64,
+    pub models: Vec<Model>,
+    pub materials: Vec<Material>,
+    pub(crate) draws: Vec<DrawCall>,
+    // models drawn as many copies with one draw call each
+    pub instances: Vec<InstanceGroup>,
+    // the scene's triangles on the CPU, for walk mode collision
+    pub collision: Bvh,
+    // raymarched shapes drawn alongside the meshes
+    pub sdf: Vec<Primitive>,
+    // lines drawn a fixed number of pixels wide, e.g. motion paths
+    pub curves: Vec<Curve>,
+    // what the models were loaded with, and are reloaded with
+    pub import: ImportOptions,
+    pub stats: SceneStats,
+}
+
+// the models and materials another scene added with `Scene::append`, and
+// its triangles, so `Scene::remove` can take them out again
+pub struct ScenePart {
+    pub models: Range<usize>,
+    pub materials: Range<usize>,
+    collision: Vec<Triangle>,
+}
+
+impl ScenePart {
+    // moves the ranges down when `removed`, which was added before this
+    // part, is taken out
+    pub fn follow_removal(&mut self, removed: &ScenePart) {
+        if self.models.start >= removed.models.end {
+            let len = removed.models.len();
+            self.models = self.models.start - len..self.models.end - len;
+        }
+        if self.materials.start >= removed.materials.end {
+            let len = removed.materials.len();
+            self.materials = self.materials.start - len..self.materials.end - len;
+        }
+    }
+}
+
+// copies of a model at the transforms in `buffer`. the model itself is
+// only drawn through its groups, and the copies aren't culled
+pub struct InstanceGroup {
+    pub model: usize,
+    pub buffer: InstanceBuffer,
+}
+
+// everything needed to issue a draw, resolved once at load time so the
+// render loop doesn't have to look anything up or allocate
+pub(crate) struct DrawCall {
+    pub model: usize,
+    pub material: usize,
+    // the material the file assigned, restored when an override is reset
+    pub own_material: usize,
+    pub indices: Range<u32>,
+}
+
+impl Scene {
+    // parses an obj, gltf or glb file, or reads a pack written by `wr3d
+    // bake`, told apart by the extension, and uploads its meshes and
+    // textures to the GPU
+    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue, path: &str) -> Result<Self> {
+        Self::load_with(device, queue, path, &mut Assets::new())
+    }
+
+    // the same, taking textures still in use from `assets` instead of
+    // uploading them again, and sharing the new ones through it
+    pub fn load_with(device: &wgpu::Device, queue: &wgpu::Queue, path: &str, assets: &mut Assets) -> Result<Self> {
+        Self::load_with_options(device, queue, path, assets, ImportOptions::default())
+    }
+
+    // the same, with the vertices fixed up by `options` as they're loaded
+    pub fn load_with_options(device: &wgpu::Device, queue: &wgpu::Queue, path: &str, assets: &mut Assets,
+                             options: ImportOptions) -> Result<Self> {
+        let mut scene = if is_gltf(Path::new(path)) {
+            Self::load_gltf(device, queue, path, assets, &options)?
+        } else if is_pack(Path::new(path)) {
+            // baked with its import options already applied
+            Self::load_pack(device, queue, path, assets)?
+        } else {
+            Self::load_obj(device, queue, path, assets, &options)?
+        };
+        // a broken parameter file leaves the models' parameters unset, and
+        // a broken material file the materials as loaded
+        if let Err(error) = params::load_into(&mut scene) {
+            scene.stats.problems.push(format!("{:#}", error));
+        }
+        if let Err(error) = params::load_materials_into(device, queue, &mut scene, assets) {
+            scene.stats.problems.push(format!("{:#}", error));
+        }
+        Ok(scene)
+    }
+
+    // broken parts, materials and maps are skipped or replaced, and listed
+    // in `stats.problems`
+    fn load_obj(device: &wgpu::Device, queue: &wgpu::Queue, path: &str, assets: &mut Assets,
+                options: &ImportOptions) -> Result<Self> {
+        let mut timer = StageTimer::new();
+        let mut problems = vec![];
+        let (mut obj_models, obj_materials) = parse_obj(path, &mut problems)?;
+        let positions = obj_models.iter()
+            .flat_map(|model| model.mesh.positions.chunks_exact(3).map(|p| Vec3::new(p[0], p[1], p[2])));
+        let options = &options.fitted(&Aabb::from_points(positions));
+        for model in &mut obj_models {
+            options.apply_to_obj(&mut model.mesh);
+        }
+        timer.stage("parse");
+        let mut models = vec![];
+        let mut model_stats = vec![];
+        let mut kept = vec![];
+        // tobj splits objects wherever the material changes, group the
+        // parts back together into one model each
+        let mut start = 0;
+        while start < obj_models.len() {
+            let name = &obj_models[start].name;
+            let end = obj_models[start..].iter()
+                .position(|model| &model.name != name)
+                .map_or(obj_models.len(), |len| start + len);
+            let parts: Vec<&tobj::Model> = obj_models[start..end].iter()
+                .filter(|part| match mesh::validate(&part.mesh) {
+                    Ok(()) => true,
+                    Err(error) => {
+                        problems.push(format!("model {}: skipped a part, {}", name, error));
+                        false
+                    }
+                })
+                .collect();
+            start = end;
+            if parts.is_empty() {
+                continue;
+            }
+            let model = Model::new(device, &parts);
+            model_stats.push(ModelStats {
+                missing_normals: parts.iter().any(|part| part.mesh.normals.len() != part.mesh.positions.len()),
+                missing_tex_coords: parts.iter()
+                    .any(|part| part.mesh.texcoords.len() / 2 != part.mesh.positions.len() / 3),
+                ..ModelStats::new(&model)
+            });
+            models.push(model);
+            kept.extend(parts);
+        }
+        timer.stage("meshes");
+        let mut materials: Vec<Material> = obj_materials.iter()
+            .map(|material| Material::new(device, queue, material, assets, &mut problems))
+            .collect();
+        // parts whose material is missing, e.g. because the mtl file didn't
+        // load, are drawn in plain grey
+        let fallback = materials.len();
+        let mut unresolved = 0;
+        for submesh in models.iter_mut().flat_map(|model| model.mesh.submeshes.iter_mut()) {
+            if submesh.material >= fallback {
+                submesh.material = fallback;
+                unresolved += 1;
+            }
+        }
+        if unresolved > 0 {
+            problems.push(format!("{} parts have no material, drawn in grey", unresolved));
+            let grey = Texture::from_color(
+                device, queue, [200, 200, 200, 255], wgpu::TextureFormat::Rgba8UnormSrgb, Some("albedo_texture"));
+            materials.push(Material::from_diffuse_texture(device, queue, "fallback", Handle::new(grey)));
+        }
+        timer.stage("materials");
+
+        let draws = draw_calls(&models);
+        let collision = Bvh::new(kept.iter().flat_map(|model| triangles(&model.mesh)).collect());
+        timer.stage("collision");
+        let stats = SceneStats {
+            source: path.to_string(),
+            models: model_stats,
+            materials: materials.len(),
+            textures: TextureStats::for_materials(&materials),
+            stages: timer.stages,
+            problems,
+        };
+        let id = NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed);
+        Ok(Self {
+            id,
+            models,
+            materials,
+            draws,
+            instances: vec![],
+            collision,
+            sdf: vec![],
+            curves: vec![],
+            import: *options,
+            stats,
+        })
+    }
+
+    fn load_gltf(device: &wgpu::Device, queue: &wgpu::Queue, path: &str, assets: &mut Assets,
+                 options: &ImportOptions) -> Result<Self> {
+        let mut timer = StageTimer::new();
+        let imported = gltf_loader::load(device, queue, path, assets, options, &mut timer)?;
+        let draws = draw_calls(&imported.models);
+        let collision = Bvh::new(imported.triangles);
+        timer.stage("collision");
+        let stats = SceneStats {
+            source: path.to_string(),
+            models: imported.model_stats,
+            materials: imported.materials.len(),
+            textures: TextureStats::for_materials(&imported.materials),
+            stages: timer.stages,
+            problems: imported.problems,
+        };
+        let id = NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed);
+        Ok(Self {
+            id,
+            models: imported.models,
+            materials: imported.materials,
+            draws,
+            instances: vec![],
+            collision,
+            sdf: vec![],
+            curves: vec![],
+            import: imported.import,
+            stats,
+        })
+    }
+
+    fn load_pack(device: &wgpu::Device, queue: &wgpu::Queue, path: &str, assets: &mut Assets) -> Result<Self> {
+        let mut timer = StageTimer::new();
+        let unpacked = pack::load(device, queue, path, assets)?;
+        timer.stage("upload");
+        let draws = draw_calls(&unpacked.models);
+        let collision = Bvh::new(unpacked.triangles);
+        timer.stage("collision");
+        let stats = SceneStats {
+            source: path.to_string(),
+            models: unpacked.models.iter().map(ModelStats::new).collect(),
+            materials: unpacked.materials.len(),
+            textures: TextureStats::for_materials(&unpacked.materials),
+            stages: timer.stages,
+            problems: vec![],
+        };
+        let id = NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed);
+        Ok(Self {
+            id,
+            models: unpacked.models,
+            materials: unpacked.materials,
+            draws,
+            instances: vec![],
+            collision,
+            sdf: vec![],
+            curves: vec![],
+            import: ImportOptions::default(),
+            stats,
+        })
+    }
+
+    // a checkerboard unit cube, shown while the real scene loads
+    pub fn placeholder(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let (vertices, indices) = unit_cube();
+        let submeshes = vec![SubMesh { material: 0, indices: 0..indices.len() as u32 }];
+        let mesh = Mesh::from_vertices(device, &vertices, &indices, submeshes, "placeholder");
+        let bounds = Aabb::from_points(vertices.iter().map(|vertex| Vec3::from(vertex.position)));
+        let models = vec![Model::from_mesh(mesh, bounds, "placeholder")];
+        let checkerboard = image::RgbaImage::from_fn(64, 64, |x, y| {
+            if (x / 8 + y / 8) % 2 == 0 { image::Rgba([200, 200, 200, 255]) } else { image::Rgba([90, 90, 90, 255]) }
+        });
+        let texture = Texture::from_image(
+            device, queue, &checkerboard, wgpu::TextureFormat::Rgba8UnormSrgb, Some("placeholder_texture"));
+        let materials = vec![Material::from_diffuse_texture(device, queue, "placeholder", Handle::new(texture))];
+        let collision = Bvh::new(indices.chunks_exact(3).map(|face| Triangle {
+            a: Vec3::from(vertices[face[0] as usize].position),
+            b: Vec3::from(vertices[face[1] as usize].position),
+            c: Vec3::from(vertices[face[2] as usize].position),
+        }).collect());
+        let stats = SceneStats {
+            source: "placeholder".to_string(),
+            models: models.iter().map(ModelStats::new).collect(),
+            materials: materials.len(),
+            textures: TextureStats::for_materials(&materials),
+            stages: vec![],
+            problems: vec![],
+        };
+        let id = NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed);
+        Self {
+            id,
+            draws: draw_calls(&models),
+            models,
+            materials,
+            instances: vec![],
+            collision,
+            sdf: vec![],
+            curves: vec![],
+            import: ImportOptions::default(),
+            stats,
+        }
+    }
+
+    // filters every material's maps with `anisotropy` samples, see
+    // `Material::set_anisotropy`
+    pub fn set_anisotropy(&mut self, device: &wgpu::Device, anisotropy: u8) {
+        for material in &mut self.materials {
+            material.set_anisotropy(device, anisotropy);
+        }
+    }
+
+    // uploads the finer mips of streamed textures that models close to
+    // `camera` need and drops those nothing on screen needs anymore, within
+    // `config`'s budget. call it every frame, see `Assets::set_streaming`
+    pub fn stream_textures(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera: &Camera,
+                           viewport_height: u32, config: &StreamingConfig) {
+        streaming::stream(self, device, queue, camera, viewport_height, config);
+    }
+
+    // moves the skinned models' animators on to `time`, in seconds, poses
+    // their joints, uploads the vertices of those that moved and fits their
+    // bounds to the pose, so culling follows them. returns how many were
+    // skinned. skins posed by hand through `Skin::joints_mut` follow too
+    pub fn animate(&mut self, queue: &wgpu::Queue, time: f32) -> usize {
+        let mut skinned = 0;
+        for model in &mut self.models {
+            let skin = match &mut model.skin {
+                Some(skin) => skin,
+                None => continue,
+            };
+            skin.animate(time);
+            if let Some(vertices) = skin.update() {
+                queue.write_buffer(&model.mesh.vertex_buffer, 0, bytemuck::cast_slice(vertices));
+                model.bounds = skin.bounds();
+                skinned += 1;
+            }
+        }
+        skinned
+    }
+
+    // adds generated models drawn with a single material, along with their
+    // triangles for collision
+    pub fn add_models(&mut self, models: Vec<Model>, material: Material, triangles: Vec<Triangle>) {
+        let material_index = self.add_material(material);
+        for model in models {
+            self.stats.models.push(ModelStats::new(&model));
+            let model_index = self.models.len();
+            for submesh in &model.mesh.submeshes {
+                self.draws.push(DrawCall {
+                    model: model_index,
+                    material: material_index,
+                    own_material: material_index,
+                    indices: submesh.indices.clone(),
+                });
+            }
+            self.models.push(model);
+        }
+        self.collision.extend(triangles);
+        // caches keyed on the scene have to be rebuilt
+        self.id = NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // adds another scene's models, materials, instance groups and
+    // collision after this one's. its raymarched shapes and curves are
+    // left out
+    pub fn append(&mut self, other: Scene) -> ScenePart {
+        let models = self.models.len()..self.models.len() + other.models.len();
+        let materials = self.materials.len()..self.materials.len() + other.materials.len();
+        self.draws.extend(other.draws.into_iter().map(|draw| DrawCall {
+            model: draw.model + models.start,
+            material: draw.material + materials.start,
+            own_material: draw.own_material + materials.start,
+            ..draw
+        }));
+        self.instances.extend(other.instances.into_iter().map(|group| InstanceGroup {
+            model: group.model + models.start,
+            ..group
+        }));
+        self.models.extend(other.models.into_iter().map(|mut model| {
+            model.parent = model.parent.map(|parent| parent + models.start);
+            model
+        }));
+        self.materials.extend(other.materials);
+        self.stats.models.extend(other.stats.models);
+        self.stats.materials += materials.len();
+        self.stats.textures = TextureStats::for_materials(&self.materials);
+        let source = other.stats.source;
+        self.stats.problems.extend(other.stats.problems.into_iter()
+            .map(|problem| format!("{}: {}", source, problem)));
+        let collision = other.collision.into_triangles();
+        self.collision.extend(collision.iter().copied());
+        // caches keyed on the scene have to be rebuilt
+        self.id = NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed);
+        ScenePart { models, materials, collision }
+    }
+
+    // takes out what `append` added. models overridden with one of its
+    // materials go back to their own, and the parts added after it move
+    // down, see `ScenePart::follow_removal`
+    pub fn remove(&mut self, part: &ScenePart) {
+        let ScenePart { models, materials, .. } = part;
+        let model_index = |index: usize| if index >= models.end { index - models.len() } else { index };
+        let material_index = |index: usize| if index >= materials.end { index - materials.len() } else { index };
+        self.draws.retain(|draw| !models.contains(&draw.model));
+        for draw in &mut self.draws {
+            draw.model = model_index(draw.model);
+            draw.own_material = material_index(draw.own_material);
+            draw.material = if materials.contains(&draw.material) {
+                draw.own_material
+            } else {
+                material_index(draw.material)
+            };
+        }
+        self.instances.retain(|group| !models.contains(&group.model));
+        for group in &mut self.instances {
+            group.model = model_index(group.model);
+        }
+        self.models.drain(models.clone());
+        for model in &mut self.models {
+            model.parent = model.parent.filter(|parent| !models.contains(parent)).map(model_index);
+        }
+        if self.stats.models.len() >= models.end {
+            self.stats.models.drain(models.clone());
+        }
+        self.materials.drain(materials.clone());
+        self.stats.materials = self.stats.materials.saturating_sub(materials.len());
+        self.stats.textures = TextureStats::for_materials(&self.materials);
+        self.collision.remove(&part.collision);
+        self.id = NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // merges the models flagged `is_static` into one model per material,
+    // flags and parameters they share, with their transforms baked into the
+    // vertices, so they're drawn with fewer draw calls and matrices. their
+    // vertices are read back from the GPU, which blocks until it's done.
+    // returns how many models were merged. instanced and skinned models are
+    // left alone, and the merged ones lose their names. don't batch a scene tiles are
+    // paged into, the tiles' models move
+    pub fn batch_static(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<usize> {
+        let statics: Vec<usize> = (0..self.models.len())
+            .filter(|&model| {
+                self.models[model].flags.is_static && self.models[model].mesh.num_indices > 0
+                    && !self.is_instanced(model) && self.models[model].skin.is_none()
+            })
+            .collect();
+        if statics.is_empty() {
+            return Ok(0);
+        }
+        let batches = batching::bake(device, queue, self, &statics)?;
+        // where the models that stay move to
+        let mut kept = 0;
+        let moved: Vec<Option<usize>> = (0..self.models.len())
+            .map(|model| if statics.binary_search(&model).is_ok() {
+                None
+            } else {
+                kept += 1;
+                Some(kept - 1)
+            })
+            .collect();
+        self.draws.retain(|draw| moved[draw.model].is_some());
+        for draw in &mut self.draws {
+            draw.model = moved[draw.model].unwrap();
+        }
+        for group in &mut self.instances {
+            group.model = moved[group.model].unwrap();
+        }
+        let has_stats = self.stats.models.len() == self.models.len();
+        let mut model = 0;
+        self.models.retain(|_| {
+            model += 1;
+            moved[model - 1].is_some()
+        });
+        // merged models leave what hung off them at the top
+        for model in &mut self.models {
+            model.parent = model.parent.and_then(|parent| moved[parent]);
+        }
+        if has_stats {
+            let mut model = 0;
+            self.stats.models.retain(|_| {
+                model += 1;
+                moved[model - 1].is_some()
+            });
+        }
+        for batch in batches {
+            self.draws.push(DrawCall {
+                model: self.models.len(),
+                material: batch.material,
+                own_material: batch.own_material,
+                indices: 0..batch.model.mesh.num_indices,
+            });
+            if has_stats {
+                self.stats.models.push(ModelStats::new(&batch.model));
+            }
+            self.models.push(batch.model);
+        }
+        self.id = NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed);
+        Ok(statics.len())
+    }
+
+    // adds a material that models can be switched to, returning its index
+    pub fn add_material(&mut self, material: Material) -> usize {
+        self.stats.materials += 1;
+        self.materials.push(material);
+        self.stats.textures = TextureStats::for_materials(&self.materials);
+        self.materials.len() - 1
+    }
+
+    // draws `model` once per instance instead of at its own transform,
+    // returning the group's index in `instances`
+    pub fn add_instances(&mut self, device: &wgpu::Device, model: usize, instances: &[Instance]) -> Result<usize> {
+        if model >= self.models.len() {
+            bail!("No model {}, the scene has {}", model, self.models.len());
+        }
+        self.instances.push(InstanceGroup { model, buffer: InstanceBuffer::new(device, instances) });
+        Ok(self.instances.len() - 1)
+    }
+
+    // whether the model is drawn through instance groups
+    pub fn is_instanced(&self, model: usize) -> bool {
+        self.instances.iter().any(|group| group.model == model)
+    }
+
+    pub fn model_index(&self, name: &str) -> Option<usize> {
+        self.models.iter().position(|model| model.name == name)
+    }
+
+    // the models hanging off `model` directly, or the top level ones for
+    // None
+    pub fn children(&self, model: Option<usize>) -> impl Iterator<Item = usize> + '_ {
+        (0..self.models.len()).filter(move |&child| self.models[child].parent == model)
+    }
+
+    // whether `model` hangs off `ancestor`, directly or further down
+    pub fn is_descendant(&self, model: usize, ancestor: usize) -> bool {
+        std::iter::successors(self.models[model].parent, |parent| self.models[*parent].parent)
+            .any(|parent| parent == ancestor)
+    }
+
+    // the model and everything hanging off it
+    pub fn subtree(&self, model: usize) -> Vec<usize> {
+        (0..self.models.len()).filter(|&other| other == model || self.is_descendant(other, model)).collect()
+    }
+
+    // hangs `model` off `parent`, or moves it to the top level for None. it
+    // stays where it is, and moves along with its parent from then on.
+    // fails when the parent hangs off the model itself
+    pub fn set_parent(&mut self, model: usize, parent: Option<usize>) -> Result<()> {
+        let count = self.models.len();
+        if model >= count || parent.is_some_and(|parent| parent >= count) {
+            bail!("No model {}, the scene has {}", parent.filter(|_| model < count).unwrap_or(model), count);
+        }
+        if let Some(parent) = parent.filter(|&parent| parent == model || self.is_descendant(parent, model)) {
+            bail!("{} can't hang off {}, which hangs off it", self.models[model].name, self.models[parent].name);
+        }
+        self.models[model].parent = parent;
+        Ok(())
+    }
+
+    // places a model, moving everything hanging off it along
+    pub fn set_transform(&mut self, model: usize, transform: Transform) {
+        let delta = transform.matrix() * self.models[model].transform.matrix().inverse();
+        for other in self.subtree(model) {
+            self.models[other].transform = if other == model {
+                transform
+            } else {
+                Transform::from_matrix(delta * self.models[other].transform.matrix())
+            };
+        }
+    }
+
+    // hides or shows a model along with everything hanging off it
+    pub fn set_hidden(&mut self, model: usize, hidden: bool) {
+        for other in self.subtree(model) {
+            self.models[other].hidden = hidden;
+        }
+    }
+
+    // locks or unlocks a model along with everything hanging off it
+    pub fn set_locked(&mut self, model: usize, locked: bool) {
+        for other in self.subtree(model) {
+            self.models[other].locked = locked;
+        }
+    }
+
+    pub fn material_index(&self, name: &str) -> Option<usize> {
+        self.materials.iter().position(|material| material.name == name)
+    }
+
+    // draws every part of a model with `material` instead of the materials
+    // its file assigned, until `reset_material` is called
+    pub fn override_material(&mut self, model: usize, material: usize) -> Result<()> {
+        if model >= self.models.len() {
+            bail!("No model {}, the scene has {}", model, self.models.len());
+        }
+        if material >= self.materials.len() {
+            bail!("No material {}, the scene has {}", material, self.materials.len());
+        }
+        for draw in self.draws.iter_mut().filter(|draw| draw.model == model) {
+            draw.material = material;
+        }
+        Ok(())
+    }
+
+    // uploads what changed in `mesh` into the model drawn from it, which was
+    // created with `DynamicMesh::create_mesh`, and points the model's draws
+    // at its current indices
+    pub fn update_dynamic(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, model: usize,
+                          mesh: &mut DynamicMesh) -> Result<()> {
+        if model >= self.models.len() {
+            bail!("No model {}, the scene has {}", model, self.models.len());
+        }
+        let target = &mut self.models[model];
+        if !mesh.upload(device, queue, &mut target.mesh) {
+            return Ok(());
+        }
+        target.bounds = mesh.bounds();
+        let indices = 0..target.mesh.num_indices;
+        for draw in self.draws.iter_mut().filter(|draw| draw.model == model) {
+            draw.indices = indices.clone();
+        }
+        if let Some(stats) = self.stats.models.get_mut(model) {
+            stats.vertices = target.mesh.num_vertices;
+            stats.indices = target.mesh.num_indices;
+        }
+        Ok(())
+    }
+
+    // restores the materials a model was loaded with
+    pub fn reset_material(&mut self, model: usize) {
+        for draw in self.draws.iter_mut().filter(|draw| draw.model == model) {
+            draw.material = draw.own_material;
+        }
+    }
+
+    // whether any part of the model is drawn with an overridden material
+    pub fn is_overridden(&self, model: usize) -> bool {
+        self.draws.iter().any(|draw| draw.model == model && draw.material != draw.own_material)
+    }
+
+    // the material set with `override_material`, if any
+    pub fn overridden_material(&self, model: usize) -> Option<usize> {
+        self.draws.iter()
+            .find(|draw| draw.model == model && draw.material != draw.own_material)
+            .map(|draw| draw.material)
+    }
+
+    // writes the models' shader parameters next to the scene's file, where
+    // loading it picks them up again, see `params::path_for`
+    pub fn save_params(&self) -> Result<PathBuf> {
+        params::save(self)
+    }
+
+    // writes the changes made to the materials next to the scene's file,
+    // see `params::materials_path_for`
+    pub fn save_materials(&self) -> Result<PathBuf> {
+        params::save_materials(self)
+    }
+
+    // picks up a file the scene was loaded from after it changed on disk.
+    // the model file, its mtl files or anything next to a gltf file rebuild
+    // the models, an image rebuilds the obj materials using it. returns
+    // whether the scene uses the file
+    pub fn reload_file(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, path: &Path) -> Result<bool> {
+        let source = Path::new(&self.stats.source);
+        let extension = path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        let gltf = is_gltf(source);
+        // gltf buffers and images can be anywhere, but they're usually
+        // next to the file
+        let models = same_file(path, source)
+            || (gltf && path.parent().zip(source.parent()).is_some_and(|(a, b)| same_file(a, b)))
+            || (!gltf && extension.as_deref() == Some("mtl"));
+        if models {
+            self.reload_models(device, queue)?;
+            return Ok(true);
+        }
+        match extension.as_deref() {
+            Some("png") | Some("jpg") | Some("jpeg") | Some("tga") | Some("bmp") if !gltf =>
+                Ok(self.reload_textures(device, queue, path)? > 0),
+            _ => Ok(false),
+        }
+    }
+
+    // loads the scene's file again and moves the new buffers into the models
+    // of the same name, along with their materials, keeping transforms,
+    // overrides and instance groups. models that are no longer in the file
+    // keep their old buffers and new ones are left out, and collision keeps
+    // the triangles it was loaded with
+    pub fn reload_models(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<()> {
+        let Scene { models, materials, stats, .. } =
+            Self::load_with_options(device, queue, &self.stats.source, &mut Assets::new(), self.import)?;
+        // where each of the fresh materials ended up
+        let materials: Vec<usize> = materials.into_iter()
+            .map(|material| match self.material_index(&material.name) {
+                Some(index) => {
+                    self.replace_material(device, index, material);
+                    index
+                }
+                None => self.add_material(material),
+            })
+            .collect();
+        for (model, model_stats) in models.into_iter().zip(stats.models) {
+            let index = match self.model_index(&model.name) {
+                Some(index) => index,
+                None => continue,
+            };
+            let overridden = self.draws.iter()
+                .find(|draw| draw.model == index && draw.material != draw.own_material)
+                .map(|draw| draw.material);
+            self.draws.retain(|draw| draw.model != index);
+            for submesh in &model.mesh.submeshes {
+                let material = materials[submesh.material];
+                self.draws.push(DrawCall {
+                    model: index,
+                    material: overridden.unwrap_or(material),
+                    own_material: material,
+                    indices: submesh.indices.clone(),
+                });
+            }
+            let target = &mut self.models[index];
+            target.mesh = model.mesh;
+            target.bounds = model.bounds;
+            if let Some(stats) = self.stats.models.get_mut(index) {
+                *stats = model_stats;
+            }
+        }
+        self.stats.textures = TextureStats::for_materials(&self.materials);
+        // caches keyed on the scene have to be rebuilt
+        self.id = NEXT_SCENE_ID.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // rebuilds the materials of an obj scene that have a map loaded from
+    // `path`, returning how many there were. a map that fails to load, e.g.
+    // because it's still being written, is an error and the old materials
+    // stay
+    pub fn reload_textures(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, path: &Path) -> Result<usize> {
+        let (_, obj_materials) = parse_obj(&self.stats.source, &mut vec![])?;
+        // shared by the rebuilt materials, but not with the old ones
+        let mut assets = Assets::new();
+        let mut reloaded = 0;
+        for obj_material in &obj_materials {
+            let index = match self.material_index(&obj_material.name) {
+                Some(index) => index,
+                None => continue,
+            };
+            if !material::map_paths(obj_material).iter().any(|map| same_file(Path::new(map), path)) {
+                continue;
+            }
+            let mut problems = vec![];
+            let material = Material::new(device, queue, obj_material, &mut assets, &mut problems);
+            if !problems.is_empty() {
+                bail!("{}", problems.join(", "));
+            }
+            self.replace_material(device, index, material);
+            reloaded += 1;
+        }
+        if reloaded > 0 {
+            self.stats.textures = TextureStats::for_materials(&self.materials);
+        }
+        Ok(reloaded)
+    }
+
+    // swaps in a rebuilt material, filtered like the one it replaces
+    fn replace_material(&mut self, device: &wgpu::Device, index: usize, mut material: Material) {
+        let desc = self.materials[index].albedo_texture.sampler_desc;
+        if desc != SamplerDesc::default() {
+            material.set_sampler(device, desc);
+        }
+        self.materials[index] = material;
+    }
+}
+
+// one draw per sub-mesh of every model, each with the material its file
+// assigned
+fn draw_calls(models: &[Model]) -> Vec<DrawCall> {
+    let mut draws = Vec::with_capacity(models.iter().map(|model| model.mesh.submeshes.len()).sum());
+    for (model_index, model) in models.iter().enumerate() {
+        for submesh in &model.mesh.submeshes {
+            draws.push(DrawCall {
+                model: model_index,
+                material: submesh.material,
+                own_material: submesh.material,
+                indices: submesh.indices.clone(),
+            });
+        }
+    }
+    draws
+}
+
+// a cube from -0.5 to 0.5 with each face mapped to the whole texture
+fn unit_cube() -> (Vec<Vertex>, Vec<u32>) {
+    // each face's normal and the direction u increases along it
+    let faces = [
+        (Vec3::unit_x(), -Vec3::unit_z()),
+        (-Vec3::unit_x(), Vec3::unit_z()),
+        (Vec3::unit_y(), Vec3::unit_x()),
+        (-Vec3::unit_y(), Vec3::unit_x()),
+        (Vec3::unit_z(), Vec3::unit_x()),
+        (-Vec3::unit_z(), -Vec3::unit_x()),
+    ];
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for (normal, tangent) in faces.iter().copied() {
+        let up = normal.cross(tangent);
+        let base = vertices.len() as u32;
+        for &(u, v) in &[(0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)] {
+            let position = normal * 0.5 + tangent * (u - 0.5) + up * (0.5 - v);
+            vertices.push(Vertex {
+                position: position.into(),
+                normal: normal.into(),
+                tex_coord: [u, v],
+                // v increases against `up`, which is cross(normal, tangent)
+                tangent: [tangent.x, tangent.y, tangent.z, -1.0],
+            });
+        }
+        indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+    (vertices, indices)
+}
+
+// whether `path` is a file `Scene::load` can open, by its extension
+pub fn is_model(path: &Path) -> bool {
+    let extension = path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase);
+    extension.as_deref() == Some("obj") || is_gltf(path) || is_pack(path)
+}
+
+fn is_gltf(path: &Path) -> bool {
+    let extension = path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase);
+    matches!(extension.as_deref(), Some("gltf") | Some("glb"))
+}
+
+fn is_pack(path: &Path) -> bool {
+    let extension = path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase);
+    extension.as_deref() == Some("pack")
+}
+
+// whether two paths lead to the same existing file, however they're written
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// like `tobj::load_obj`, except that an mtl file which can't be read only
+// loses the materials
+fn parse_obj(path: &str, problems: &mut Vec<String>) -> Result<(Vec<tobj::Model>, Vec<tobj::Material>)> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let mtl_problems = RefCell::new(vec![]);
+    let result = tobj::load_obj_buf(&mut BufReader::new(file), true, |mtl_path| {
+        let mtl_path = Path::new(path).parent().map_or_else(|| mtl_path.to_path_buf(), |dir| dir.join(mtl_path));
+        tobj::load_mtl(&mtl_path).or_else(|error| {
+            mtl_problems.borrow_mut().push(format!("{}: {}, materials skipped", mtl_path.display(), error));
+            Ok((vec![], HashMap::new()))
+        })
+    });
+    problems.extend(mtl_problems.into_inner());
+    result.with_context(|| format!("Failed to parse {}", path))
+}
+
+fn triangles(mesh: &tobj::Mesh) -> impl Iterator<Item = Triangle> + '_ {
+    let position = move |index: u32| {
+        let i = index as usize * 3;
+        Vec3::new(mesh.positions[i], mesh.positions[i + 1], mesh.positions[i + 2])
+    };
+    mesh.indices.chunks_exact(3).map(move |face| Triangle {
+        a: position(face[0]),
+        b: position(face[1]),
+        c: position(face[2]),
+    })
+}
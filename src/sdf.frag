@@ -0,0 +1,124 @@
+#version 450
+
+#define MAX_PRIMITIVES 32
+#define MAX_STEPS 128
+#define MAX_DISTANCE 100.0
+#define EPSILON 0.001
+
+#define SHAPE_SPHERE 0
+#define SHAPE_BOX 1
+#define SHAPE_TORUS 2
+#define SHAPE_CYLINDER 3
+
+#define OP_UNION 0
+#define OP_SUBTRACT 1
+#define OP_INTERSECT 2
+
+struct Primitive {
+  vec4 position;
+  vec4 size;
+  vec4 color;
+  vec4 blend;
+};
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0)
+  uniform Sdf {
+  mat4 u_view_proj;
+  mat4 u_inv_view_proj;
+  vec4 u_eye;
+  uvec4 u_count;
+  Primitive u_primitives[MAX_PRIMITIVES];
+};
+
+layout(set=1, binding=0) uniform Light {
+  vec3 l_position;
+  float l_exposure;
+  vec3 l_color;
+  float l_fog_density;
+  vec3 l_ambient;
+  vec3 l_fog_color;
+};
+
+float shape_distance(Primitive primitive, vec3 p) {
+  vec3 q = p - primitive.position.xyz;
+  int shape = int(primitive.position.w);
+  if (shape == SHAPE_SPHERE) {
+    return length(q) - primitive.size.x;
+  } else if (shape == SHAPE_BOX) {
+    vec3 d = abs(q) - primitive.size.xyz;
+    return length(max(d, 0.0)) + min(max(d.x, max(d.y, d.z)), 0.0);
+  } else if (shape == SHAPE_TORUS) {
+    vec2 t = vec2(length(q.xz) - primitive.size.x, q.y);
+    return length(t) - primitive.size.y;
+  } else {
+    vec2 d = abs(vec2(length(q.xz), q.y)) - primitive.size.xy;
+    return min(max(d.x, d.y), 0.0) + length(max(d, 0.0));
+  }
+}
+
+// the scene's distance in x and its color in yzw, folding the primitives
+// in order with polynomial smooth minimum blending
+vec4 scene(vec3 p) {
+  vec4 result = vec4(MAX_DISTANCE, 0.0, 0.0, 0.0);
+  for (uint i = 0; i < u_count.x; i++) {
+    Primitive primitive = u_primitives[i];
+    float d = shape_distance(primitive, p);
+    int op = int(primitive.color.w);
+    float k = max(primitive.blend.x, 1e-4);
+    if (i == 0 || op == OP_UNION) {
+      float h = clamp(0.5 + 0.5 * (d - result.x) / k, 0.0, 1.0);
+      result.x = mix(d, result.x, h) - k * h * (1.0 - h);
+      result.yzw = mix(primitive.color.rgb, result.yzw, h);
+    } else if (op == OP_SUBTRACT) {
+      float h = clamp(0.5 - 0.5 * (result.x + d) / k, 0.0, 1.0);
+      result.x = mix(result.x, -d, h) + k * h * (1.0 - h);
+    } else {
+      float h = clamp(0.5 - 0.5 * (d - result.x) / k, 0.0, 1.0);
+      result.x = mix(d, result.x, h) + k * h * (1.0 - h);
+    }
+  }
+  return result;
+}
+
+vec3 scene_normal(vec3 p) {
+  vec2 e = vec2(EPSILON, 0.0);
+  return normalize(vec3(
+    scene(p + e.xyy).x - scene(p - e.xyy).x,
+    scene(p + e.yxy).x - scene(p - e.yxy).x,
+    scene(p + e.yyx).x - scene(p - e.yyx).x));
+}
+
+void main() {
+  vec2 ndc = vec2(v_tex_coord.x * 2.0 - 1.0, 1.0 - v_tex_coord.y * 2.0);
+  vec4 far = u_inv_view_proj * vec4(ndc, 1.0, 1.0);
+  vec3 direction = normalize(far.xyz / far.w - u_eye.xyz);
+
+  float t = 0.0;
+  vec4 hit = vec4(MAX_DISTANCE, 0.0, 0.0, 0.0);
+  for (int i = 0; i < MAX_STEPS && t < MAX_DISTANCE; i++) {
+    hit = scene(u_eye.xyz + direction * t);
+    if (hit.x < EPSILON * max(t, 1.0)) {
+      break;
+    }
+    t += hit.x;
+  }
+  if (hit.x >= EPSILON * max(t, 1.0) || t >= MAX_DISTANCE) {
+    discard;
+  }
+
+  vec3 position = u_eye.xyz + direction * t;
+  vec3 normal = scene_normal(position);
+  vec3 light_dir = normalize(l_position - position);
+  vec3 half_dir = normalize(light_dir - direction);
+  float diffuse = max(dot(normal, light_dir), 0.0);
+  float specular = pow(max(dot(normal, half_dir), 0.0), 32.0) * 0.5;
+  vec3 color = (hit.yzw * (l_ambient + diffuse) * l_color + specular * l_color) * l_exposure;
+  float fog = exp(-l_fog_density * t);
+  f_color = vec4(mix(l_fog_color, color, fog), 1.0);
+
+  vec4 clip = u_view_proj * vec4(position, 1.0);
+  gl_FragDepth = clip.z / clip.w;
+}
@@ -0,0 +1,251 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use wgpu;
+use glam::Vec2;
+use crate::camera::Camera;
+use crate::lights::Lights;
+use crate::texture::DepthTexture;
+use crate::upscale;
+
+// must match MAX_PRIMITIVES in sdf.frag
+pub const MAX_PRIMITIVES: usize = 32;
+
+// an analytic shape combined with everything listed before it, e.g.
+//
+// [[primitive]]
+// shape = "box"
+// size = [0.5, 0.5, 0.5]
+// position = [0.0, 0.5, 0.0]
+//
+// [[primitive]]
+// shape = "sphere"
+// radius = 0.6
+// position = [0.0, 0.5, 0.0]
+// operation = "subtract"
+#[derive(Debug, Clone, Deserialize)]
+pub struct Primitive {
+    #[serde(flatten)]
+    pub shape: Shape,
+    #[serde(default)]
+    pub position: [f32; 3],
+    #[serde(default = "default_color")]
+    pub color: [f32; 3],
+    #[serde(default)]
+    pub operation: Operation,
+    // blends the seam between this shape and the ones before it over
+    // roughly this distance
+    #[serde(default)]
+    pub smoothness: f32,
+}
+
+fn default_color() -> [f32; 3] {
+    [0.8, 0.8, 0.8]
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "shape", rename_all = "lowercase")]
+pub enum Shape {
+    Sphere { radius: f32 },
+    // `size` holds the half extents
+    Box { size: [f32; 3] },
+    Torus { radius: f32, thickness: f32 },
+    Cylinder { radius: f32, height: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    #[default]
+    Union,
+    Subtract,
+    Intersect,
+}
+
+#[derive(Deserialize)]
+struct Description {
+    #[serde(default)]
+    primitive: Vec<Primitive>,
+}
+
+// reads a list of `[[primitive]]` tables from a toml file
+pub fn load(path: &str) -> Result<Vec<Primitive>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path))?;
+    let description: Description = toml::from_str(&text)
+        .with_context(|| format!("Failed to parse {}", path))?;
+    if description.primitive.len() > MAX_PRIMITIVES {
+        anyhow::bail!("{} has {} primitives, at most {} are supported",
+                      path, description.primitive.len(), MAX_PRIMITIVES);
+    }
+    Ok(description.primitive)
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PrimitiveRaw {
+    // xyz position, w the shape
+    position: [f32; 4],
+    // the shape's dimensions
+    size: [f32; 4],
+    // rgb color, w the operation
+    color: [f32; 4],
+    // x smoothness
+    blend: [f32; 4],
+}
+
+impl From<&Primitive> for PrimitiveRaw {
+    fn from(primitive: &Primitive) -> Self {
+        let (shape, size) = match primitive.shape {
+            Shape::Sphere { radius } => (0.0, [radius, 0.0, 0.0, 0.0]),
+            Shape::Box { size } => (1.0, [size[0], size[1], size[2], 0.0]),
+            Shape::Torus { radius, thickness } => (2.0, [radius, thickness, 0.0, 0.0]),
+            Shape::Cylinder { radius, height } => (3.0, [radius, height, 0.0, 0.0]),
+        };
+        let operation = match primitive.operation {
+            Operation::Union => 0.0,
+            Operation::Subtract => 1.0,
+            Operation::Intersect => 2.0,
+        };
+        let [x, y, z] = primitive.position;
+        let [r, g, b] = primitive.color;
+        Self {
+            position: [x, y, z, shape],
+            size,
+            color: [r, g, b, operation],
+            blend: [primitive.smoothness, 0.0, 0.0, 0.0],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SdfUniforms {
+    view_proj: [[f32; 4]; 4],
+    inv_view_proj: [[f32; 4]; 4],
+    eye: [f32; 4],
+    count: [u32; 4],
+    primitives: [PrimitiveRaw; MAX_PRIMITIVES],
+}
+
+// raymarches a scene's primitives in a fullscreen pass inside the main
+// render pass. it writes depth, so the shapes and meshes hide each other
+pub struct SdfPass {
+    format: wgpu::TextureFormat,
+    layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+}
+
+impl SdfPass {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32) -> Self {
+        let uniform_bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer {
+                            dynamic: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+                label: Some("sdf_bind_group_layout"),
+            }
+        );
+        let light_bind_group_layout = Lights::create_bind_group_layout(device);
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sdf_uniform_buffer"),
+            size: std::mem::size_of::<SdfUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+            }],
+            label: Some("sdf_bind_group"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("sdf_pipeline_layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout, &light_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &layout, format, sample_count);
+        Self { format, layout, pipeline, uniform_buffer, uniform_bind_group }
+    }
+
+    // rebuilds the pipeline for a main pass with `sample_count` samples
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.pipeline = create_pipeline(device, &self.layout, self.format, sample_count);
+    }
+
+    // uploads the primitives and camera for this frame, `jitter` has to
+    // match the projection offset the meshes are drawn with
+    pub fn prepare(&self, queue: &wgpu::Queue, primitives: &[Primitive], camera: &Camera, jitter: Vec2) {
+        if primitives.is_empty() {
+            return;
+        }
+        let view_proj = upscale::jittered(camera.build_view_projection_matrix(), jitter);
+        let mut uniforms = SdfUniforms {
+            view_proj: view_proj.to_cols_array_2d(),
+            inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+            eye: camera.eye.extend(1.0).into(),
+            count: [primitives.len().min(MAX_PRIMITIVES) as u32, 0, 0, 0],
+            primitives: [bytemuck::Zeroable::zeroed(); MAX_PRIMITIVES],
+        };
+        for (raw, primitive) in uniforms.primitives.iter_mut().zip(primitives) {
+            *raw = primitive.into();
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, primitives: &[Primitive],
+                    light_bind_group: &'a wgpu::BindGroup) {
+        if primitives.is_empty() {
+            return;
+        }
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        pass.set_bind_group(1, light_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, format: wgpu::TextureFormat,
+                   sample_count: u32) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("fullscreen.vert.spv"));
+    let fs_module = device.create_shader_module(wgpu::include_spirv!("sdf.frag.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("sdf_pipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        color_states: &[format.into()],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+            format: DepthTexture::FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilStateDescriptor::default(),
+        }),
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[],
+        },
+        sample_count,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
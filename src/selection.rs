@@ -0,0 +1,200 @@
+use crate::collision::Aabb;
+use crate::mesh::Transform;
+use crate::scene::Scene;
+use glam::{Mat4, Vec3};
+
+// a group transform as where each model it moved was placed before and
+// after, what's hanging off them moved along
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransformEdit {
+    pub moves: Vec<(usize, Transform, Transform)>,
+}
+
+// the models picked in the viewer, transformed together around their
+// common center, and the transforms made that can be undone. the models
+// are indices into the scene, so whatever reorders its models has to
+// `reset` this
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    models: Vec<usize>,
+    undo: Vec<TransformEdit>,
+    redo: Vec<TransformEdit>,
+}
+
+// transforms kept for undoing, older ones are dropped
+const HISTORY: usize = 100;
+
+impl Selection {
+    // sorted and without repeats
+    pub fn models(&self) -> &[usize] {
+        &self.models
+    }
+
+    pub fn contains(&self, model: usize) -> bool {
+        self.models.binary_search(&model).is_ok()
+    }
+
+    pub fn set(&mut self, mut models: Vec<usize>) {
+        models.sort_unstable();
+        models.dedup();
+        self.models = models;
+    }
+
+    pub fn add(&mut self, models: &[usize]) {
+        for &model in models {
+            if let Err(index) = self.models.binary_search(&model) {
+                self.models.insert(index, model);
+            }
+        }
+    }
+
+    // selects the model, or leaves it out if it's selected already
+    pub fn toggle(&mut self, model: usize) {
+        match self.models.binary_search(&model) {
+            Ok(index) => {
+                self.models.remove(index);
+            }
+            Err(index) => self.models.insert(index, model),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.models.clear();
+    }
+
+    // forgets the selection and the history, for a scene whose models
+    // changed
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    // the center of the selected models' bounds, which group transforms
+    // turn and scale around
+    pub fn pivot(&self, scene: &Scene) -> Option<Vec3> {
+        let bounds = self.models.iter()
+            .filter_map(|&model| scene.models.get(model))
+            .fold(Aabb::empty(), |bounds, model| bounds.grow(&model.world_bounds()));
+        Some(bounds.center()).filter(|_| !bounds.is_empty())
+    }
+
+    // moves, turns and scales the selected models by `delta` around the
+    // pivot, as one step of the history. locked models stay, and ones
+    // hanging off another selected model only follow it. false when
+    // nothing moved
+    pub fn transform(&mut self, scene: &mut Scene, delta: &Transform) -> bool {
+        let pivot = match self.pivot(scene) {
+            Some(pivot) => pivot,
+            None => return false,
+        };
+        let movable: Vec<usize> = self.models.iter().copied()
+            .filter(|&model| model < scene.models.len() && !scene.models[model].locked)
+            .collect();
+        let placements: Vec<(usize, Transform)> = movable.iter()
+            .filter(|&&model| !movable.iter().any(|&other| scene.is_descendant(model, other)))
+            .map(|&model| (model, scene.models[model].transform))
+            .collect();
+        if placements.is_empty() {
+            return false;
+        }
+        let moves = around(&placements, pivot, delta);
+        for &(model, _, after) in &moves {
+            scene.set_transform(model, after);
+        }
+        self.push(TransformEdit { moves });
+        true
+    }
+
+    // puts the models of the last transform back, false when there's
+    // nothing to undo
+    pub fn undo(&mut self, scene: &mut Scene) -> bool {
+        let edit = match self.undo.pop() {
+            Some(edit) => edit,
+            None => return false,
+        };
+        let count = scene.models.len();
+        for &(model, before, _) in edit.moves.iter().rev().filter(|(model, ..)| *model < count) {
+            scene.set_transform(model, before);
+        }
+        self.redo.push(edit);
+        true
+    }
+
+    // makes the last undone transform again
+    pub fn redo(&mut self, scene: &mut Scene) -> bool {
+        let edit = match self.redo.pop() {
+            Some(edit) => edit,
+            None => return false,
+        };
+        let count = scene.models.len();
+        for &(model, _, after) in edit.moves.iter().filter(|(model, ..)| *model < count) {
+            scene.set_transform(model, after);
+        }
+        self.undo.push(edit);
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    // a new transform drops what was undone before it
+    fn push(&mut self, edit: TransformEdit) {
+        self.redo.clear();
+        if self.undo.len() == HISTORY {
+            self.undo.remove(0);
+        }
+        self.undo.push(edit);
+    }
+}
+
+// each placement before and after applying `delta` around `pivot`
+fn around(placements: &[(usize, Transform)], pivot: Vec3, delta: &Transform) -> Vec<(usize, Transform, Transform)> {
+    let matrix = Mat4::from_translation(pivot) * delta.matrix() * Mat4::from_translation(-pivot);
+    placements.iter()
+        .map(|&(model, before)| (model, before, Transform::from_matrix(matrix * before.matrix())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Quat;
+
+    #[test]
+    fn transforms_around_the_pivot() {
+        let placements = [
+            (0, Transform { translation: Vec3::new(1.0, 0.0, 0.0), ..Transform::default() }),
+            (3, Transform { translation: Vec3::new(3.0, 0.0, 0.0), ..Transform::default() }),
+        ];
+        // half a turn around y at x = 2 swaps them
+        let delta = Transform { rotation: Quat::from_rotation_y(std::f32::consts::PI), ..Transform::default() };
+        let moves = around(&placements, Vec3::new(2.0, 0.0, 0.0), &delta);
+        assert_eq!(moves.len(), 2);
+        assert_eq!((moves[0].0, moves[0].1), placements[0]);
+        assert!((moves[0].2.translation - Vec3::new(3.0, 0.0, 0.0)).length() < 1e-5);
+        assert!((moves[1].2.translation - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-5);
+
+        // scaling doubles their distance to the pivot and their size
+        let delta = Transform { scale: Vec3::splat(2.0), ..Transform::default() };
+        let moves = around(&placements, Vec3::new(2.0, 0.0, 0.0), &delta);
+        assert!((moves[0].2.translation - Vec3::new(0.0, 0.0, 0.0)).length() < 1e-5);
+        assert!((moves[1].2.scale - Vec3::splat(2.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn toggles_and_keeps_models_sorted() {
+        let mut selection = Selection::default();
+        selection.set(vec![4, 1, 4]);
+        assert_eq!(selection.models(), [1, 4]);
+        selection.toggle(2);
+        selection.toggle(4);
+        assert_eq!(selection.models(), [1, 2]);
+        selection.add(&[0, 2]);
+        assert_eq!(selection.models(), [0, 1, 2]);
+        assert!(selection.contains(1) && !selection.contains(4));
+    }
+}
@@ -1,35 +1,508 @@
 #version 450
 
-layout(set=1, binding=0) uniform texture2D t_diffuse;
-layout(set=1, binding=1) uniform sampler s_diffuse;
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+  vec4 u_eye;
+  vec4 u_debug;
+};
+
+// members after the flags are custom parameters, set per object and
+// zero when an object doesn't set them, see params.rs
+layout(set=0, binding=1)
+  uniform Model {
+  mat4 u_model;
+  mat4 u_normal;
+  // x the OBJECT_ bits
+  uvec4 u_flags;
+  // rgb blended over the base color by a
+  vec4 p_tint;
+};
+
+// must match the OBJECT_ constants in renderer.rs
+#define OBJECT_UNLIT 1u
+#define OBJECT_NO_SHADOWS 2u
+
+layout(set=1, binding=0) uniform texture2D t_albedo;
+layout(set=1, binding=1) uniform sampler s_albedo;
+layout(set=1, binding=2) uniform texture2D t_metallic_roughness;
+layout(set=1, binding=3) uniform sampler s_metallic_roughness;
+layout(set=1, binding=4) uniform texture2D t_bump;
+layout(set=1, binding=5) uniform sampler s_bump;
+layout(set=1, binding=6) uniform texture2D t_dissolve;
+layout(set=1, binding=7) uniform sampler s_dissolve;
+layout(set=1, binding=8) uniform texture2D t_normal;
+layout(set=1, binding=9) uniform sampler s_normal;
+layout(set=1, binding=10) uniform texture2D t_occlusion;
+layout(set=1, binding=11) uniform sampler s_occlusion;
+layout(set=1, binding=12) uniform texture2D t_emissive;
+layout(set=1, binding=13) uniform sampler s_emissive;
 
 layout(set=2, binding=0) uniform MaterialRaw {
-  vec3 u_ambient;
-  vec3 u_diffuse;
-  vec3 u_specular;
-  float u_shininess;
+  vec3 u_albedo;
+  float u_metallic;
+  vec3 u_emissive;
+  float u_roughness;
+  float u_occlusion_strength;
+  float u_dissolve;
+  float u_bump_multiplier;
+  float u_normal_scale;
+  uint u_unlit;
 };
 
 layout(set=3, binding=0) uniform Light {
   vec3 l_position;
+  float l_exposure;
   vec3 l_color;
+  float l_fog_density;
+  vec3 l_ambient;
+  uint l_light_count;
+  vec3 l_fog_color;
+};
+
+// must match the LIGHT_* constants in lights.rs
+const uint LIGHT_DIRECTIONAL = 0;
+const uint LIGHT_POINT = 1;
+const uint LIGHT_SPOT = 2;
+
+struct LightRaw {
+  vec3 position;
+  float range;
+  vec3 color;
+  uint kind;
+  vec3 direction;
+  float cos_inner;
+  float cos_outer;
+  // the slot in the point shadow maps, negative without one
+  int shadow;
+};
+
+layout(set=3, binding=1) readonly buffer Lights {
+  LightRaw lights[];
+};
+
+// must match cluster.rs
+#define CLUSTERS_X 16
+#define CLUSTERS_Y 9
+#define CLUSTERS_Z 24
+#define CLUSTER_STRIDE 128
+const uint CLUSTER_OVERFLOW = 0xffffffffu;
+
+layout(set=3, binding=12) uniform Clusters {
+  mat4 c_view;
+  mat4 c_inv_proj;
+  float c_near;
+  float c_far;
+  uint c_light_count;
+  uint c_enabled;
+};
+
+// see cluster.comp
+layout(set=3, binding=13) readonly buffer ClusterLights {
+  uint c_lights[];
+};
+
+// must match MAX_CASCADES in shadow.rs
+#define MAX_CASCADES 4
+
+layout(set=3, binding=2) uniform Shadows {
+  mat4 sh_cascades[MAX_CASCADES];
+  vec4 sh_texel_sizes;
+  uint sh_cascade_count;
+  float sh_blend;
 };
+layout(set=3, binding=3) uniform texture2DArray t_shadow;
+layout(set=3, binding=4) uniform samplerShadow s_shadow;
+
+// must match MAX_POINT_SHADOWS in shadow.rs
+#define MAX_POINT_SHADOWS 8
+
+layout(set=3, binding=5) uniform PointShadows {
+  mat4 ps_faces[MAX_POINT_SHADOWS * 6];
+  // each face's corner and width in the atlas, and the width of its texels
+  // one unit from the light. 0 wide when the light didn't fit
+  vec4 ps_tiles[MAX_POINT_SHADOWS * 6];
+};
+// the atlas, a single layer
+layout(set=3, binding=6) uniform texture2DArray t_point_shadow;
+
+// image based ambient light, see ibl.rs. the maps are equirect like the
+// skybox
+layout(set=3, binding=7) uniform Environment {
+  float e_intensity;
+  uint e_enabled;
+  // mip levels of the specular map
+  float e_levels;
+  // added to the maps' u, turning them around the up axis
+  float e_offset;
+};
+layout(set=3, binding=8) uniform texture2D t_irradiance;
+layout(set=3, binding=9) uniform texture2D t_specular;
+layout(set=3, binding=10) uniform texture2D t_brdf_lut;
+layout(set=3, binding=11) uniform sampler s_environment;
+// the environment captured at a point in the scene, which surfaces within
+// its radius take their ambient light and reflections from
+layout(set=3, binding=14) uniform Probe {
+  vec3 p_position;
+  float p_radius;
+  // 0 until something is captured
+  uint p_enabled;
+};
+layout(set=3, binding=15) uniform texture2D t_probe_irradiance;
+layout(set=3, binding=16) uniform texture2D t_probe_specular;
 
 layout(location=0) in vec3 v_position;
 layout(location=1) in vec3 v_normal;
 layout(location=2) in vec2 v_tex_coord;
+layout(location=3) in vec4 v_color;
+layout(location=4) in vec4 v_tangent;
 
 layout(location=0) out vec4 f_color;
 
+#define PI 3.14159265
+
+const int DEBUG_UV_CHECKER = 1;
+const int DEBUG_TEXEL_DENSITY = 2;
+
+// 3x5 pixel digits, the top row in the highest bits
+const int DIGITS[10] = int[](
+  0x7B6F, 0x2C97, 0x73E7, 0x73CF, 0x5BC9, 0x79CF, 0x79EF, 0x7249, 0x7BEF, 0x7BCF);
+
+// perturbs the normal by the screen-space gradient of the bump map, which
+// works without tangents (Mikkelsen, "Bump Mapping Unparametrized Surfaces
+// on the GPU"). a constant bump map leaves the normal unchanged
+vec3 bump(vec3 n) {
+  float height = texture(sampler2D(t_bump, s_bump), v_tex_coord).r * u_bump_multiplier;
+  vec3 dp_dx = dFdx(v_position);
+  vec3 dp_dy = dFdy(v_position);
+  vec3 r1 = cross(dp_dy, n);
+  vec3 r2 = cross(n, dp_dx);
+  float det = dot(dp_dx, r1);
+  vec3 grad = sign(det) * (dFdx(height) * r1 + dFdy(height) * r2);
+  return normalize(abs(det) * n - grad);
+}
+
+// bends the normal by the tangent space normal map. the flat map of
+// materials without one leaves it unchanged
+vec3 normal_map(vec3 n) {
+  vec3 t = v_tangent.xyz - n * dot(n, v_tangent.xyz);
+  if (dot(t, t) < 1e-12) {
+    return n;
+  }
+  t = normalize(t);
+  vec3 b = cross(n, t) * v_tangent.w;
+  vec3 m = texture(sampler2D(t_normal, s_normal), v_tex_coord).xyz * 2.0 - 1.0;
+  m.xy *= u_normal_scale;
+  return normalize(mat3(t, b, n) * m);
+}
+
+// the cook-torrance specular term with the ggx distribution and the
+// schlick approximations of the smith geometry term and the fresnel
+// factor, `f0` being the reflectance head-on
+vec3 specular_brdf(vec3 n, vec3 v, vec3 l, vec3 f0, float roughness) {
+  vec3 h = normalize(l + v);
+  float n_dot_l = max(dot(n, l), 0.0);
+  float n_dot_v = max(dot(n, v), 1e-4);
+  float n_dot_h = max(dot(n, h), 0.0);
+  float a = roughness * roughness;
+  float a2 = a * a;
+  float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+  float distribution = a2 / (PI * d * d);
+  float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+  float geometry = n_dot_l / (n_dot_l * (1.0 - k) + k) * n_dot_v / (n_dot_v * (1.0 - k) + k);
+  vec3 fresnel = f0 + (1.0 - f0) * pow(1.0 - max(dot(h, v), 0.0), 5.0);
+  return distribution * geometry * fresnel / max(4.0 * n_dot_l * n_dot_v, 1e-4);
+}
+
+// what a light of `color` arriving from `l` reflects towards the eye. the
+// color is what a white lambertian surface facing the light shows, so it's
+// scaled by pi into radiance
+vec3 shade(vec3 n, vec3 v, vec3 l, vec3 color, vec3 base, float metallic, float roughness) {
+  vec3 f0 = mix(vec3(0.04), base, metallic);
+  vec3 diffuse = (1.0 - metallic) * base / PI;
+  vec3 specular = specular_brdf(n, v, l, f0, roughness);
+  return (diffuse + specular) * color * PI * max(dot(n, l), 0.0);
+}
+
+// where `p` lands in a cascade's map: uv, depth, and how close it is to
+// the map's edge, beyond 1 when it's outside
+vec4 cascade_coords(uint cascade, vec3 p) {
+  vec4 clip = sh_cascades[cascade] * vec4(p, 1.0);
+  vec2 uv = clip.xy * vec2(0.5, -0.5) + 0.5;
+  float edge = max(max(abs(clip.x), abs(clip.y)), clip.z > 1.0 ? 2.0 : 0.0);
+  return vec4(uv, clip.z, edge);
+}
+
+// the fraction of the sun reaching `v_position`, 3x3 filtered
+float cascade_shadow(uint cascade, vec4 coords) {
+  vec2 texel = 1.0 / vec2(textureSize(sampler2DArrayShadow(t_shadow, s_shadow), 0).xy);
+  float lit = 0.0;
+  for (int x = -1; x <= 1; x++) {
+    for (int y = -1; y <= 1; y++) {
+      vec4 lookup = vec4(coords.xy + vec2(x, y) * texel, float(cascade), coords.z);
+      lit += texture(sampler2DArrayShadow(t_shadow, s_shadow), lookup);
+    }
+  }
+  return lit / 9.0;
+}
+
+// picks the first cascade the fragment is in, pushed off the surface by
+// a few texels against acne, and fades into the next one near its edge.
+// the last cascade fades out to no shadow at all
+float sun_shadow(vec3 n) {
+  for (uint i = 0; i < sh_cascade_count; i++) {
+    vec3 p = v_position + n * sh_texel_sizes[i] * 1.5;
+    vec4 coords = cascade_coords(i, p);
+    if (coords.w > 1.0) {
+      continue;
+    }
+    float shadow = cascade_shadow(i, coords);
+    float fade = smoothstep(1.0 - sh_blend, 1.0, coords.w);
+    if (fade > 0.0) {
+      float next = 1.0;
+      if (i + 1 < sh_cascade_count) {
+        vec4 next_coords = cascade_coords(i + 1, v_position + n * sh_texel_sizes[i + 1] * 1.5);
+        next = cascade_shadow(i + 1, next_coords);
+      }
+      shadow = mix(shadow, next, fade);
+    }
+    return shadow;
+  }
+  return 1.0;
+}
+
+// the fraction of a point or spot light reaching `v_position`. the face of
+// the light's cube is the one facing the major axis of the direction, in
+// the order +X, -X, +Y, -Y, +Z, -Z, and the maps hold the distance to the
+// light over its range
+float point_shadow(LightRaw light, vec3 n) {
+  vec3 d = v_position - light.position;
+  vec3 a = abs(d);
+  int face;
+  if (a.x >= a.y && a.x >= a.z) {
+    face = d.x > 0.0 ? 0 : 1;
+  } else if (a.y >= a.z) {
+    face = d.y > 0.0 ? 2 : 3;
+  } else {
+    face = d.z > 0.0 ? 4 : 5;
+  }
+  int index = light.shadow * 6 + face;
+  vec4 tile = ps_tiles[index];
+  if (tile.z == 0.0) {
+    return 1.0;
+  }
+  // pushed off the surface by a few texels, which grow with the distance
+  vec3 p = v_position + n * tile.w * length(d) * 1.5;
+  d = p - light.position;
+  vec4 clip = ps_faces[index] * vec4(p, 1.0);
+  // kept half a texel inside the tile so filtering never reads its
+  // neighbours
+  vec2 uv = clamp(clip.xy / clip.w * vec2(0.5, -0.5) + 0.5, tile.w * 0.25, 1.0 - tile.w * 0.25);
+  float depth = length(d) / max(light.range, 1e-6) - 0.002;
+  return texture(sampler2DArrayShadow(t_point_shadow, s_shadow), vec4(tile.xy + uv * tile.z, 0.0, depth));
+}
+
+// where a direction lands in the environment's maps, as in background.frag
+vec2 panorama_uv(vec3 d) {
+  return vec2(atan(d.z, d.x) / (2.0 * PI) + 0.5, acos(clamp(d.y, -1.0, 1.0)) / PI);
+}
+
+// where the capture's maps are looked up for a ray from `p` within its
+// radius: the ray is followed out to the radius, as if everything captured
+// lay there, so nearby surfaces line up with what they reflect
+vec3 probe_direction(vec3 p, vec3 r) {
+  vec3 o = p - p_position;
+  float b = dot(o, r);
+  float c = dot(o, o) - p_radius * p_radius;
+  return o + r * (sqrt(max(b * b - c, 0.0)) - b);
+}
+
+// how much the capture replaces the environment at `p`, fading out over
+// the outer fifth of its radius
+float probe_weight(vec3 p) {
+  if (p_enabled == 0) {
+    return 0.0;
+  }
+  return clamp((p_radius - distance(p, p_position)) / max(p_radius * 0.2, 1e-4), 0.0, 1.0);
+}
+
+// the light from around the surface: the lighting's constant ambient, or
+// the environment's maps split into diffuse and specular, blended towards
+// the capture's near it
+vec3 ambient_light(vec3 n, vec3 v, vec3 base, float metallic, float roughness) {
+  float probe = probe_weight(v_position);
+  if (e_enabled == 0 && probe == 0.0) {
+    return l_ambient * base;
+  }
+  vec3 f0 = mix(vec3(0.04), base, metallic);
+  float n_dot_v = max(dot(n, v), 1e-4);
+  // rough surfaces get less of the fresnel brightening at grazing angles
+  vec3 fresnel = f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - n_dot_v, 5.0);
+  vec3 r = reflect(-v, n);
+  // the levels are explicit, the seam of the panorama breaks derivatives
+  float level = roughness * (e_levels - 1.0);
+  // without the environment's maps the constant ambient comes from everywhere
+  vec3 irradiance = l_ambient;
+  vec3 reflected = l_ambient;
+  if (e_enabled != 0) {
+    vec2 offset = vec2(e_offset, 0.0);
+    irradiance = textureLod(sampler2D(t_irradiance, s_environment), panorama_uv(n) + offset, 0.0).rgb * e_intensity;
+    reflected = textureLod(sampler2D(t_specular, s_environment), panorama_uv(r) + offset, level).rgb * e_intensity;
+  }
+  // the capture is the lit scene, it isn't scaled
+  if (probe > 0.0) {
+    irradiance = mix(irradiance, textureLod(sampler2D(t_probe_irradiance, s_environment),
+                                            panorama_uv(n), 0.0).rgb, probe);
+    reflected = mix(reflected, textureLod(sampler2D(t_probe_specular, s_environment),
+                                          panorama_uv(probe_direction(v_position, r)), level).rgb, probe);
+  }
+  vec3 diffuse = (1.0 - fresnel) * (1.0 - metallic) * base * irradiance;
+  vec2 lut_size = vec2(textureSize(sampler2D(t_brdf_lut, s_environment), 0));
+  vec2 lut_uv = clamp(vec2(n_dot_v, roughness), 0.5 / lut_size, 1.0 - 0.5 / lut_size);
+  vec2 brdf = textureLod(sampler2D(t_brdf_lut, s_environment), lut_uv, 0.0).rg;
+  return diffuse + reflected * (f0 * brdf.x + brdf.y);
+}
+
+// point and spot lights fall off with the inverse square of the distance,
+// windowed to reach zero at the light's range. spot lights fade from the
+// inner to the outer cone
+vec3 evaluate_light(LightRaw light, vec3 n, vec3 v, vec3 base, float metallic, float roughness) {
+  if (light.kind == LIGHT_DIRECTIONAL) {
+    return shade(n, v, -light.direction, light.color, base, metallic, roughness);
+  }
+  vec3 to_light = light.position - v_position;
+  float d2 = dot(to_light, to_light);
+  vec3 l = to_light * inversesqrt(max(d2, 1e-12));
+  float window = clamp(1.0 - pow(d2 / max(light.range * light.range, 1e-6), 2.0), 0.0, 1.0);
+  float attenuation = window * window / max(d2, 1e-4);
+  if (light.kind == LIGHT_SPOT) {
+    attenuation *= smoothstep(light.cos_outer, max(light.cos_inner, light.cos_outer + 1e-4),
+                              dot(-l, light.direction));
+  }
+  if (attenuation <= 0.0) {
+    return vec3(0.0);
+  }
+  if (light.shadow >= 0 && (u_flags.x & OBJECT_NO_SHADOWS) == 0) {
+    attenuation *= point_shadow(light, normalize(v_normal));
+  }
+  return shade(n, v, l, light.color * attenuation, base, metallic, roughness);
+}
+
+// where the list of lights reaching `position` starts in `c_lights`, `ndc`
+// being where it's seen on screen
+uint cluster_of(vec3 position, vec2 ndc) {
+  float depth = -(c_view * vec4(position, 1.0)).z;
+  float slice = log(max(depth, c_near) / c_near) / log(c_far / c_near) * CLUSTERS_Z;
+  uvec2 tile = uvec2(clamp((ndc * 0.5 + 0.5) * vec2(CLUSTERS_X, CLUSTERS_Y), vec2(0.0),
+                           vec2(CLUSTERS_X - 1, CLUSTERS_Y - 1)));
+  uint z = uint(clamp(slice, 0.0, CLUSTERS_Z - 1));
+  return ((z * CLUSTERS_Y + tile.y) * CLUSTERS_X + tile.x) * CLUSTER_STRIDE;
+}
+
+// the point and spot lights at `position`, only those reaching its cluster
+// when the lights are clustered
+vec3 evaluate_lights(vec3 position, vec2 ndc, vec3 n, vec3 v, vec3 base, float metallic, float roughness) {
+  vec3 color = vec3(0.0);
+  uint cluster = c_enabled != 0 ? cluster_of(position, ndc) : 0;
+  if (c_enabled == 0 || c_lights[cluster] == CLUSTER_OVERFLOW) {
+    for (uint i = 0; i < l_light_count; i++) {
+      color += evaluate_light(lights[i], n, v, base, metallic, roughness);
+    }
+    return color;
+  }
+  uint count = c_lights[cluster];
+  for (uint i = 0; i < count; i++) {
+    color += evaluate_light(lights[c_lights[cluster + 1 + i]], n, v, base, metallic, roughness);
+  }
+  return color;
+}
+
+// applies the exposure and exponential distance fog
+vec4 environment(vec3 color, float alpha) {
+  float fog = exp(-l_fog_density * distance(u_eye.xyz, v_position));
+  return vec4(mix(l_fog_color, color * l_exposure, fog), alpha);
+}
+
+// whether `p`, in units of digit pixels from the label's top left, is on
+// a lit pixel of `number` written with up to three digits
+bool label(vec2 p, int number) {
+  int digit = int(floor(p.x / 4.0));
+  ivec2 pixel = ivec2(int(p.x) - digit * 4, int(p.y));
+  if (p.x < 0.0 || p.y < 0.0 || digit > 2 || pixel.x > 2 || pixel.y > 4) {
+    return false;
+  }
+  int digits = number >= 100 ? 3 : (number >= 10 ? 2 : 1);
+  if (digit >= digits) {
+    return false;
+  }
+  int value = number / int(pow(10.0, float(digits - 1 - digit))) % 10;
+  return ((DIGITS[value] >> (14 - pixel.y * 3 - pixel.x)) & 1) != 0;
+}
+
+// alternating cells over uv space, each labeled with its index
+vec3 uv_checker(vec2 uv) {
+  int n = max(int(u_debug.y), 1);
+  float density = float(n);
+  ivec2 cell = ivec2(floor(uv * density));
+  ivec2 wrapped = ((cell % ivec2(n)) + ivec2(n)) % ivec2(n);
+  bool odd = ((cell.x + cell.y) & 1) != 0;
+  vec3 color = odd ? vec3(0.85) : vec3(0.35);
+  // tint along u and v so flipped or rotated islands stand out
+  color *= mix(vec3(1.0), vec3(1.0, 0.6, 0.6), float(wrapped.x) / density)
+    * mix(vec3(1.0), vec3(0.6, 0.6, 1.0), float(wrapped.y) / density);
+  vec2 local = fract(uv * density) * 16.0 - vec2(1.5);
+  if (label(local, wrapped.y * n + wrapped.x)) {
+    color = odd ? vec3(0.1) : vec3(1.0);
+  }
+  return color;
+}
+
+// texels of the albedo map per world unit, log scaled around the target
+vec3 texel_density(vec2 uv) {
+  vec2 size = vec2(textureSize(sampler2D(t_albedo, s_albedo), 0));
+  vec2 duv_dx = dFdx(uv) * size;
+  vec2 duv_dy = dFdy(uv) * size;
+  float texel_area = abs(duv_dx.x * duv_dy.y - duv_dx.y * duv_dy.x);
+  float world_area = length(cross(dFdx(v_position), dFdy(v_position)));
+  float density = sqrt(texel_area / max(world_area, 1e-12));
+  float t = clamp(log2(max(density, 1e-6) / u_debug.z) * 0.5, -1.0, 1.0);
+  return t < 0.0 ? mix(vec3(0.1, 0.8, 0.1), vec3(0.1, 0.2, 1.0), -t)
+                 : mix(vec3(0.1, 0.8, 0.1), vec3(1.0, 0.1, 0.1), t);
+}
+
 void main() {
-  vec4 obj_color = texture(sampler2D(t_diffuse, s_diffuse), v_tex_coord);
-  vec3 n = normalize(v_normal);
-  vec3 li = normalize(l_position - v_position);
-  vec3 v = normalize(vec3(0.0, 1.0, 2.0) - v_position);
-  vec3 h = normalize(li + v);
-  vec3 diffuse = u_diffuse * max(dot(li, n), 0.0);
-  vec3 specular = u_specular * pow(max(dot(n, h), 0.0), u_shininess);
-  vec3 ambient = u_ambient * 0.05;
-  vec3 result = (ambient + diffuse + specular) * l_color * obj_color.xyz;
-  f_color = vec4(result, obj_color.a);
+  int debug_view = int(u_debug.x);
+  if (debug_view != 0) {
+    // plain diffuse shading from the sun keeps the shape readable
+    vec3 n = normalize(v_normal);
+    float shade = 0.4 + 0.6 * max(dot(n, normalize(l_position - v_position)), 0.0);
+    vec3 color = debug_view == DEBUG_UV_CHECKER ? uv_checker(v_tex_coord) : texel_density(v_tex_coord);
+    f_color = vec4(color * shade, 1.0);
+    return;
+  }
+
+  vec4 albedo = texture(sampler2D(t_albedo, s_albedo), v_tex_coord) * v_color;
+  vec3 base = mix(u_albedo * albedo.rgb, p_tint.rgb, clamp(p_tint.a, 0.0, 1.0));
+  float alpha = albedo.a * u_dissolve
+    * texture(sampler2D(t_dissolve, s_dissolve), v_tex_coord).r;
+  vec3 emissive = u_emissive * texture(sampler2D(t_emissive, s_emissive), v_tex_coord).rgb;
+  if (u_unlit != 0 || (u_flags.x & OBJECT_UNLIT) != 0) {
+    f_color = environment(base + emissive, alpha);
+    return;
+  }
+
+  vec4 metallic_roughness = texture(sampler2D(t_metallic_roughness, s_metallic_roughness), v_tex_coord);
+  float metallic = u_metallic * metallic_roughness.b;
+  // fully smooth surfaces turn the highlight into a point
+  float roughness = clamp(u_roughness * metallic_roughness.g, 0.04, 1.0);
+  float occlusion = mix(1.0, texture(sampler2D(t_occlusion, s_occlusion), v_tex_coord).r, u_occlusion_strength);
+
+  vec3 n = bump(normal_map(normalize(v_normal)));
+  vec3 v = normalize(u_eye.xyz - v_position);
+  float shadow = (u_flags.x & OBJECT_NO_SHADOWS) == 0 ? sun_shadow(normalize(v_normal)) : 1.0;
+  vec3 direct = shade(n, v, normalize(l_position - v_position), l_color, base, metallic, roughness) * shadow;
+  vec4 clip = u_view_proj * vec4(v_position, 1.0);
+  direct += evaluate_lights(v_position, clip.xy / clip.w, n, v, base, metallic, roughness);
+  vec3 ambient = ambient_light(n, v, base, metallic, roughness) * occlusion;
+  f_color = environment(direct + ambient + emissive, alpha);
 }
@@ -0,0 +1,87 @@
+use anyhow::{Context, Result};
+use log::warn;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wgpu;
+
+// compiled SPIR-V kept on disk, one file per shader source, so shaders
+// compiled at runtime are only compiled again once their source changes.
+// entries are keyed by the source and the adapter, so a cache directory
+// shared between machines or drivers doesn't hand one another's artifacts
+// around. wgpu can't save pipelines yet, only the modules are kept
+pub struct ShaderCache {
+    dir: PathBuf,
+    // the adapter's part of every key
+    device: String,
+    hits: u32,
+    misses: u32,
+}
+
+impl ShaderCache {
+    pub fn new<P: AsRef<Path>>(dir: P, adapter: &wgpu::AdapterInfo) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let device = format!("{}:{}:{}:{:?}", adapter.name, adapter.vendor, adapter.device, adapter.backend);
+        Ok(Self { dir, device, hits: 0, misses: 0 })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // the SPIR-V of `source`, named `name` like its file, from the cache or
+    // from `compile`, which is then stored. a cache file that can't be read
+    // or written only costs the compile
+    pub fn get_or_compile<F>(&mut self, name: &str, source: &str, compile: F) -> Result<Vec<u32>>
+    where
+        F: FnOnce() -> Result<Vec<u32>>,
+    {
+        let path = self.dir.join(format!("{}-{:016x}.spv", name, self.key(name, source)));
+        if let Some(spirv) = fs::read(&path).ok().and_then(|bytes| from_bytes(&bytes)) {
+            self.hits += 1;
+            return Ok(spirv);
+        }
+        self.misses += 1;
+        let spirv = compile()?;
+        if let Err(error) = fs::write(&path, to_bytes(&spirv)) {
+            warn!("Failed to cache {}: {}", path.display(), error);
+        }
+        Ok(spirv)
+    }
+
+    // how many shaders came from the cache and how many were compiled
+    pub fn counts(&self) -> (u32, u32) {
+        (self.hits, self.misses)
+    }
+
+    // FNV-1a, which unlike std's hasher stays the same between builds
+    fn key(&self, name: &str, source: &str) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for part in &[name, "\0", &self.device, "\0", source] {
+            for byte in part.bytes() {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        }
+        hash
+    }
+}
+
+// SPIR-V words as stored, little endian
+fn to_bytes(spirv: &[u32]) -> Vec<u8> {
+    spirv.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect()
+}
+
+// none for files that can't be SPIR-V, e.g. ones cut short
+fn from_bytes(bytes: &[u8]) -> Option<Vec<u32>> {
+    let words = bytes.chunks_exact(4);
+    if !words.remainder().is_empty() {
+        return None;
+    }
+    let spirv: Vec<u32> = words.map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect();
+    // the magic number
+    match spirv.first() {
+        Some(&0x0723_0203) => Some(spirv),
+        _ => None,
+    }
+}
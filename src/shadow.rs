@@ -0,0 +1,665 @@
+use wgpu;
+use wgpu::util::DeviceExt;
+use bytemuck::Zeroable;
+use std::cmp::{Ordering, Reverse};
+use glam::{Mat4, Vec3};
+use crate::camera::Camera;
+use crate::caps::Capabilities;
+use crate::collision::Aabb;
+use crate::config::ShadowConfig;
+use crate::culling::Frustum;
+use crate::dump;
+use crate::lighting::Lighting;
+use crate::lights::Light;
+use crate::mesh::{InstanceBuffer, Model};
+use crate::renderer::ModelTransforms;
+use crate::scene::Scene;
+use crate::texture::DepthTexture;
+use crate::vertex::{Vertex, VertexAttribute};
+
+// must match MAX_CASCADES in shader.frag
+pub const MAX_CASCADES: usize = 4;
+// how many point and spot lights can cast shadows at once, must match
+// MAX_POINT_SHADOWS in shader.frag
+pub const MAX_POINT_SHADOWS: usize = 8;
+// the smallest face a light's cube gets in the atlas before it loses its
+// shadow, the atlas is allocated in cells this wide
+const MIN_TILE: u32 = 64;
+
+// the faces of a point light's cube, as the direction each looks along
+// and its up, in the order the shader picks them
+pub(crate) const FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    ([0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+    ([0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+];
+
+// what the lit shaders need to find a fragment in the shadow maps, bound
+// with the lights at set 3 binding 2
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniforms {
+    // world space to each cascade's clip space
+    cascades: [[[f32; 4]; 4]; MAX_CASCADES],
+    // the width of a texel in world units, per cascade
+    texel_sizes: [f32; MAX_CASCADES],
+    // 0 when shadows are off
+    cascade_count: u32,
+    blend: f32,
+    _padding: [f32; 2],
+}
+
+// the same for the point lights at binding 5. the faces of the light in
+// slot `i` are `faces[i * 6..i * 6 + 6]`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointShadowUniforms {
+    faces: [[[f32; 4]; 4]; MAX_POINT_SHADOWS * 6],
+    // where each face is in the atlas, its corner and width in uvs, then
+    // the width of its texels one unit from the light. all 0 for lights
+    // that didn't fit
+    tiles: [[f32; 4]; MAX_POINT_SHADOWS * 6],
+}
+
+// the camera a layer is drawn with. the start matches the renderer's
+// camera uniforms, which the shadow shaders are written against
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LayerUniforms {
+    view_proj: [[f32; 4]; 4],
+    // a point light's position and range, unused by the cascades
+    light: [f32; 4],
+}
+
+// one cascade or cube face: its light space camera, bound like the main
+// camera so the scene's meshes can be drawn into it
+struct Layer {
+    camera_buffer: wgpu::Buffer,
+    // with the model transforms, for the meshes
+    model_bind_group: wgpu::BindGroup,
+    // the camera alone, for the instance groups
+    camera_bind_group: wgpu::BindGroup,
+}
+
+impl Layer {
+    fn new(device: &wgpu::Device, transforms: &ModelTransforms,
+           model_bind_group_layout: &wgpu::BindGroupLayout,
+           camera_bind_group_layout: &wgpu::BindGroupLayout) -> Self {
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("shadow_camera_buffer"),
+            contents: bytemuck::bytes_of(&LayerUniforms::zeroed()),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        Self {
+            model_bind_group: transforms.create_bind_group(device, model_bind_group_layout, &camera_buffer),
+            camera_bind_group: device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: camera_bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(camera_buffer.slice(..)),
+                }],
+                label: Some("shadow_camera_bind_group"),
+            }),
+            camera_buffer,
+        }
+    }
+}
+
+// the pipelines for the meshes and for the instance groups
+struct Pipelines {
+    meshes: wgpu::RenderPipeline,
+    instanced: wgpu::RenderPipeline,
+}
+
+// the sun's cascaded shadow maps, layers of a depth texture array, and the
+// cubes of the point and spot lights that cast shadows, packed into one
+// depth atlas. all are redrawn every frame from the scene's meshes. the
+// cube faces are plain tiles rather than cube maps, the shader picks the
+// face itself
+pub(crate) struct ShadowMaps {
+    config: ShadowConfig,
+    // the cascades skip pulling back to catch casters towards the sun
+    depth_clamping: bool,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    cascade_views: Vec<wgpu::TextureView>,
+    point_texture: wgpu::Texture,
+    point_view: wgpu::TextureView,
+    // the atlas as a single layer to draw into
+    point_target: wgpu::TextureView,
+    point_atlas: u32,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    point_uniform_buffer: wgpu::Buffer,
+    model_bind_group_layout: wgpu::BindGroupLayout,
+    pipelines: Pipelines,
+    point_pipelines: Pipelines,
+    cascades: Vec<Layer>,
+    // six per slot, in the order of FACES
+    point_layers: Vec<Layer>,
+}
+
+impl ShadowMaps {
+    // with shadows off the maps are a single texel that's never drawn
+    pub(crate) fn new(device: &wgpu::Device, config: &ShadowConfig, transforms: &ModelTransforms,
+                      caps: &Capabilities) -> Self {
+        let depth_clamping = caps.depth_clamping();
+        let count = if config.enabled { (config.cascades as usize).clamp(1, MAX_CASCADES) } else { 0 };
+        let resolution = if config.enabled { config.resolution.max(1) } else { 1 };
+        let (texture, view) = create_texture(device, "shadow_texture", resolution, count);
+        let point_count = if config.enabled { MAX_POINT_SHADOWS * 6 } else { 0 };
+        // room for at least one light at the smallest faces
+        let point_atlas = if config.enabled { config.point_atlas.max(MIN_TILE * 4).next_power_of_two() } else { 1 };
+        let (point_texture, point_view) = create_texture(device, "point_shadow_texture", point_atlas, 1);
+        let point_target = layer_view(&point_texture, 0);
+        // linear filtering compares the four nearest texels, softening the
+        // edges for free
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("shadow_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("shadow_uniform_buffer"),
+            contents: bytemuck::bytes_of(&ShadowUniforms::zeroed()),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let point_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("point_shadow_uniform_buffer"),
+            contents: bytemuck::bytes_of(&PointShadowUniforms::zeroed()),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        // same as the renderer's camera layout
+        let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::UniformBuffer {
+                    dynamic: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("shadow_camera_bind_group_layout"),
+        });
+        let model_bind_group_layout = ModelTransforms::create_bind_group_layout(device);
+        let pipelines = Pipelines {
+            meshes: create_pipeline(device, &model_bind_group_layout, false, false, depth_clamping),
+            instanced: create_pipeline(device, &camera_bind_group_layout, true, false, depth_clamping),
+        };
+        let point_pipelines = Pipelines {
+            meshes: create_pipeline(device, &model_bind_group_layout, false, true, false),
+            instanced: create_pipeline(device, &camera_bind_group_layout, true, true, false),
+        };
+
+        let cascades = (0..count).map(|_| Layer::new(
+            device, transforms, &model_bind_group_layout, &camera_bind_group_layout,
+        )).collect();
+        let cascade_views = (0..count).map(|layer| layer_view(&texture, layer)).collect();
+        let point_layers = (0..point_count).map(|_| Layer::new(
+            device, transforms, &model_bind_group_layout, &camera_bind_group_layout,
+        )).collect();
+
+        Self {
+            config: config.clone(),
+            depth_clamping,
+            texture,
+            view,
+            cascade_views,
+            point_texture,
+            point_view,
+            point_target,
+            point_atlas,
+            sampler,
+            uniform_buffer,
+            point_uniform_buffer,
+            model_bind_group_layout,
+            pipelines,
+            point_pipelines,
+            cascades,
+            point_layers,
+        }
+    }
+
+    pub(crate) fn config(&self) -> &ShadowConfig {
+        &self.config
+    }
+
+    // the cascades' uniforms and texture array, the comparison sampler and
+    // the point lights' uniforms and atlas, in the order of the
+    // light bind group's bindings 2 to 6
+    pub(crate) fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 5] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Buffer(self.uniform_buffer.slice(..)),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&self.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::Buffer(self.point_uniform_buffer.slice(..)),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::TextureView(&self.point_view),
+            },
+        ]
+    }
+
+    // rebuilds the bind groups pointing at the model transforms after
+    // their buffer grew
+    pub(crate) fn rebind(&mut self, device: &wgpu::Device, transforms: &ModelTransforms) {
+        for layer in self.cascades.iter_mut().chain(self.point_layers.iter_mut()) {
+            layer.model_bind_group = transforms.create_bind_group(
+                device, &self.model_bind_group_layout, &layer.camera_buffer);
+        }
+    }
+
+    // draws the sun's cascades and the cubes of the lights casting shadows
+    // every cascade and the point lights' atlas, as a frame dump saves them
+    pub(crate) fn dump_targets(&self) -> Vec<dump::Target<'_>> {
+        let resolution = self.config.resolution.max(1);
+        let size = wgpu::Extent3d { width: resolution, height: resolution, depth: 1 };
+        let mut targets: Vec<dump::Target> = (0..self.cascades.len())
+            .map(|i| dump::Target::new(&format!("shadow_cascade_{}", i), &self.texture, size, DepthTexture::FORMAT)
+                .at_layer(i as u32))
+            .collect();
+        if !self.point_layers.is_empty() {
+            let size = wgpu::Extent3d { width: self.point_atlas, height: self.point_atlas, depth: 1 };
+            targets.push(dump::Target::new("point_shadow_atlas", &self.point_texture, size, DepthTexture::FORMAT));
+        }
+        targets
+    }
+
+    pub(crate) fn render(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, scene: &Scene,
+                         camera: &Camera, lighting: &Lighting, lights: &[Light]) {
+        self.render_cascades(encoder, queue, scene, camera, lighting);
+        self.render_points(encoder, queue, scene, camera, lights);
+    }
+
+    // fits the cascades to the camera and draws the scene's meshes into
+    // them from the sun
+    fn render_cascades(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, scene: &Scene,
+                       camera: &Camera, lighting: &Lighting) {
+        if self.cascades.is_empty() {
+            return;
+        }
+        let to_sun = Vec3::from(lighting.sun_position);
+        if to_sun.length_squared() == 0.0 {
+            return;
+        }
+        let to_sun = to_sun.normalize();
+        let bounds = Aabb::from_points(scene.models.iter()
+            .map(Model::world_bounds)
+            .filter(|bounds| !bounds.is_empty())
+            .flat_map(|bounds| vec![bounds.min, bounds.max]));
+        let mut uniforms = ShadowUniforms::zeroed();
+        uniforms.cascade_count = self.cascades.len() as u32;
+        uniforms.blend = self.config.blend.clamp(0.0, 1.0);
+        let splits = self.splits(camera);
+        for (i, (cascade, view)) in self.cascades.iter().zip(&self.cascade_views).enumerate() {
+            let (matrix, texel_size) = self.fit(camera, splits[i], splits[i + 1], to_sun, &bounds);
+            uniforms.cascades[i] = matrix.to_cols_array_2d();
+            uniforms.texel_sizes[i] = texel_size;
+            let layer_uniforms = LayerUniforms { view_proj: matrix.to_cols_array_2d(), light: [0.0; 4] };
+            queue.write_buffer(&cascade.camera_buffer, 0, bytemuck::bytes_of(&layer_uniforms));
+            let frustum = Frustum::from_matrix(matrix);
+            let frustum = if self.depth_clamping { frustum.without_near() } else { frustum };
+            let mut pass = begin_pass(encoder, view);
+            draw(&mut pass, cascade, &self.pipelines, scene, &frustum);
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    // looks out from each light casting shadows along the six axes, into
+    // faces of the atlas sized by how much of the view the light covers.
+    // the slots go to the first lights asking, like in `Lights`
+    fn render_points(&self, encoder: &mut wgpu::CommandEncoder, queue: &wgpu::Queue, scene: &Scene,
+                     camera: &Camera, lights: &[Light]) {
+        if self.point_layers.is_empty() {
+            return;
+        }
+        let casters: Vec<(Vec3, f32)> = lights.iter()
+            .filter(|light| light.wants_shadow())
+            .filter_map(Light::origin)
+            .take(MAX_POINT_SHADOWS)
+            .collect();
+        let importance: Vec<f32> = casters.iter()
+            .map(|(position, range)| coverage(camera, *position, *range))
+            .collect();
+        let sizes = budget(&importance, self.config.point_resolution, self.point_atlas);
+        let corners = pack(&sizes);
+        let atlas = self.point_atlas as f32;
+        let mut uniforms = PointShadowUniforms::zeroed();
+        // cleared once, each face only draws into its own tile
+        let mut pass = begin_pass(encoder, &self.point_target);
+        for (slot, (position, range)) in casters.into_iter().enumerate() {
+            let size = sizes[slot];
+            if size == 0 {
+                continue;
+            }
+            let near = (range * 1e-3).max(1e-2).min(range * 0.5);
+            for (face, (direction, up)) in FACES.iter().enumerate() {
+                let index = slot * 6 + face;
+                let layer = &self.point_layers[index];
+                let view = Mat4::look_at_rh(position, position + Vec3::from(*direction), Vec3::from(*up));
+                let matrix = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, near, range) * view;
+                let (x, y) = corners[slot][face];
+                uniforms.faces[index] = matrix.to_cols_array_2d();
+                // the faces are 90 degrees wide
+                uniforms.tiles[index] = [x as f32 / atlas, y as f32 / atlas, size as f32 / atlas, 2.0 / size as f32];
+                let layer_uniforms = LayerUniforms {
+                    view_proj: matrix.to_cols_array_2d(),
+                    light: position.extend(range).into(),
+                };
+                queue.write_buffer(&layer.camera_buffer, 0, bytemuck::bytes_of(&layer_uniforms));
+                pass.set_viewport(x as f32, y as f32, size as f32, size as f32, 0.0, 1.0);
+                draw(&mut pass, layer, &self.point_pipelines, scene, &Frustum::from_matrix(matrix));
+            }
+        }
+        drop(pass);
+        queue.write_buffer(&self.point_uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    // the view distances where the cascades start and end, blending even
+    // and logarithmic splits (the practical split scheme)
+    fn splits(&self, camera: &Camera) -> [f32; MAX_CASCADES + 1] {
+        let near = camera.z_near.max(1e-3);
+        let far = self.config.distance.min(camera.z_far).max(near * 1.01);
+        let lambda = self.config.split.clamp(0.0, 1.0);
+        let count = self.cascades.len();
+        let mut splits = [far; MAX_CASCADES + 1];
+        for (i, split) in splits.iter_mut().enumerate().take(count + 1) {
+            let t = i as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(t);
+            let even = near + (far - near) * t;
+            *split = lambda * logarithmic + (1.0 - lambda) * even;
+        }
+        splits
+    }
+
+    // an orthographic view from the sun enclosing the slice of the view
+    // between `near` and `far`, and the width of its texels. the slice is
+    // wrapped in a sphere so the cascade's size doesn't change as the camera
+    // turns, and it moves in whole texels, which keeps the edges of the
+    // shadows from shimmering
+    fn fit(&self, camera: &Camera, near: f32, far: f32, to_sun: Vec3, bounds: &Aabb) -> (Mat4, f32) {
+        let forward = (camera.center - camera.eye).normalize();
+        let right = forward.cross(camera.up).normalize();
+        let up = right.cross(forward);
+        let tan = (camera.fov * 0.5).tan();
+        let corners: Vec<Vec3> = [near, far].iter().flat_map(|&distance| {
+            let half_height = distance * tan;
+            let half_width = half_height * camera.aspect_ratio;
+            let center = camera.eye + forward * distance;
+            vec![
+                center - right * half_width - up * half_height,
+                center + right * half_width - up * half_height,
+                center + right * half_width + up * half_height,
+                center - right * half_width + up * half_height,
+            ]
+        }).collect();
+        let center = corners.iter().fold(Vec3::zero(), |sum, corner| sum + *corner) / corners.len() as f32;
+        let radius = corners.iter().map(|corner| (*corner - center).length()).fold(0.0, f32::max);
+        // rounded up so small changes of the slice don't resize it
+        let radius = (radius * 16.0).ceil() / 16.0;
+        let texel_size = 2.0 * radius / self.config.resolution.max(1) as f32;
+
+        let light_up = if to_sun.y.abs() > 0.99 { Vec3::unit_z() } else { Vec3::unit_y() };
+        let rotation = Mat4::look_at_rh(Vec3::zero(), -to_sun, light_up);
+        let snapped = rotation.transform_point3(center);
+        let snapped = Vec3::new(
+            (snapped.x / texel_size).floor() * texel_size,
+            (snapped.y / texel_size).floor() * texel_size,
+            snapped.z,
+        );
+        let center = rotation.inverse().transform_point3(snapped);
+
+        // back far enough towards the sun to catch everything in the scene
+        // that can cast into the slice. casters in front of the near plane
+        // are flattened onto it with depth clamping, which keeps the depth
+        // range tight
+        let behind = if bounds.is_empty() || self.depth_clamping {
+            0.0
+        } else {
+            let corners = (0..8).map(|i| Vec3::new(
+                if i & 1 == 0 { bounds.min.x } else { bounds.max.x },
+                if i & 2 == 0 { bounds.min.y } else { bounds.max.y },
+                if i & 4 == 0 { bounds.min.z } else { bounds.max.z },
+            ));
+            corners.map(|corner| (corner - center).dot(to_sun)).fold(0.0, f32::max)
+        };
+        let distance = radius.max(behind) + 1.0;
+        let view = Mat4::look_at_rh(center + to_sun * distance, center, light_up);
+        let projection = Mat4::orthographic_rh(-radius, radius, -radius, radius, 0.0, distance + radius);
+        (projection * view, texel_size)
+    }
+}
+
+// a depth texture array with `layers` layers, at least one so there's
+// always something to bind, and a view of all of them
+fn create_texture(device: &wgpu::Device, label: &str, resolution: u32,
+                  layers: usize) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d { width: resolution, height: resolution, depth: layers.max(1) as u32 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: DepthTexture::FORMAT,
+        usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_SRC,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some(label),
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+    (texture, view)
+}
+
+// a single layer view of a depth texture array, to draw into
+fn layer_view(texture: &wgpu::Texture, index: usize) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("shadow_layer_view"),
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_array_layer: index as u32,
+        array_layer_count: std::num::NonZeroU32::new(1),
+        ..Default::default()
+    })
+}
+
+// a depth only pass clearing `view`
+fn begin_pass<'a>(encoder: &'a mut wgpu::CommandEncoder, view: &'a wgpu::TextureView) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        color_attachments: &[],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+            attachment: view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+    })
+}
+
+// how much of the view a light's sphere of influence spans, 1 from inside
+// it and falling towards 0 with the distance
+fn coverage(camera: &Camera, position: Vec3, range: f32) -> f32 {
+    let distance = (position - camera.eye).length();
+    if distance <= range {
+        return 1.0;
+    }
+    ((range / distance).asin() / (camera.fov * 0.5).max(1e-3)).min(1.0)
+}
+
+// the face size of each light's cube: `max` scaled by its importance, as
+// a power of two. while the six faces of every light don't fit into the
+// atlas, the least important lights are halved, and dropped to 0 once
+// they're at MIN_TILE
+fn budget(importance: &[f32], max: u32, atlas: u32) -> Vec<u32> {
+    let max = max.next_power_of_two().clamp(MIN_TILE, atlas / 2);
+    let mut sizes: Vec<u32> = importance.iter()
+        .map(|importance| ((max as f32 * importance).ceil() as u32).clamp(MIN_TILE, max).next_power_of_two())
+        .collect();
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|a, b| importance[*a].partial_cmp(&importance[*b]).unwrap_or(Ordering::Equal));
+    let capacity = atlas as u64 * atlas as u64;
+    while sizes.iter().map(|&size| 6 * size as u64 * size as u64).sum::<u64>() > capacity {
+        if let Some(&i) = order.iter().find(|&&i| sizes[i] > MIN_TILE) {
+            sizes[i] /= 2;
+        } else if let Some(&i) = order.iter().find(|&&i| sizes[i] > 0) {
+            sizes[i] = 0;
+        }
+    }
+    sizes
+}
+
+// the corner of every face in the atlas. the faces are laid out largest
+// first along a z-order curve over MIN_TILE cells: the sizes are powers of
+// two that only shrink, so each face starts aligned to its own size and
+// the atlas fills without gaps
+fn pack(sizes: &[u32]) -> Vec<[(u32, u32); 6]> {
+    let mut order: Vec<usize> = (0..sizes.len()).filter(|&i| sizes[i] > 0).collect();
+    order.sort_by_key(|&i| Reverse(sizes[i]));
+    let mut corners = vec![[(0, 0); 6]; sizes.len()];
+    let mut cell = 0;
+    for i in order {
+        for corner in corners[i].iter_mut() {
+            let (x, y) = deinterleave(cell);
+            *corner = (x * MIN_TILE, y * MIN_TILE);
+            cell += (sizes[i] / MIN_TILE).pow(2);
+        }
+    }
+    corners
+}
+
+// the x and y of a z-order index, its even and odd bits
+fn deinterleave(index: u32) -> (u32, u32) {
+    let bits = |offset: u32| (0..16).fold(0, |value, bit| value | ((index >> (2 * bit + offset)) & 1) << bit);
+    (bits(0), bits(1))
+}
+
+// draws the scene's meshes casting shadows inside `frustum` from a layer's
+// camera
+fn draw<'a>(pass: &mut wgpu::RenderPass<'a>, layer: &'a Layer, pipelines: &'a Pipelines, scene: &'a Scene,
+            frustum: &Frustum) {
+    pass.set_pipeline(&pipelines.meshes);
+    for draw in &scene.draws {
+        let model = &scene.models[draw.model];
+        if !model.casts_shadows() || scene.is_instanced(draw.model) || !frustum.intersects(&model.world_bounds()) {
+            continue;
+        }
+        pass.set_bind_group(0, &layer.model_bind_group, &[ModelTransforms::offset(draw.model)]);
+        pass.set_vertex_buffer(0, model.mesh.vertex_buffer.slice(..));
+        pass.set_index_buffer(model.mesh.index_buffer.slice(..));
+        pass.draw_indexed(draw.indices.clone(), 0, 0..1);
+    }
+    pass.set_pipeline(&pipelines.instanced);
+    pass.set_bind_group(0, &layer.camera_bind_group, &[]);
+    for group in scene.instances.iter().filter(|group| !group.buffer.is_empty()) {
+        let model = &scene.models[group.model];
+        if !model.casts_shadows() {
+            continue;
+        }
+        let mesh = &model.mesh;
+        pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, group.buffer.slice());
+        pass.set_index_buffer(mesh.index_buffer.slice(..));
+        for draw in scene.draws.iter().filter(|draw| draw.model == group.model) {
+            pass.draw_indexed(draw.indices.clone(), 0, 0..group.buffer.len());
+        }
+    }
+}
+
+// depth only, with a slope scaled bias against shadow acne. the point
+// lights' pipelines write the distance to the light instead, and leave
+// the bias to the lookup since it doesn't apply to written depths.
+// `clamp_depth` needs the device's depth clamping
+fn create_pipeline(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout,
+                   instanced: bool, point: bool, clamp_depth: bool) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("shadow_pipeline_layout"),
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let vs_module = if instanced {
+        device.create_shader_module(wgpu::include_spirv!("shadow_instanced.vert.spv"))
+    } else {
+        device.create_shader_module(wgpu::include_spirv!("shadow.vert.spv"))
+    };
+    let fs_module = if point {
+        Some(device.create_shader_module(wgpu::include_spirv!("point_shadow.frag.spv")))
+    } else {
+        None
+    };
+    // only the position matters for depth
+    let vertex_layout = Vertex::layout().for_shader(&[VertexAttribute::Position]);
+    let buffers = [
+        vertex_layout.descriptor(),
+        // the model matrix's columns, the rest of the instance isn't needed
+        wgpu::VertexBufferDescriptor {
+            stride: InstanceBuffer::STRIDE,
+            step_mode: wgpu::InputStepMode::Instance,
+            attributes: &wgpu::vertex_attr_array![4 => Float4, 5 => Float4, 6 => Float4, 7 => Float4],
+        },
+    ];
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(match (point, instanced) {
+            (false, false) => "shadow_pipeline",
+            (false, true) => "shadow_instanced_pipeline",
+            (true, false) => "point_shadow_pipeline",
+            (true, true) => "point_shadow_instanced_pipeline",
+        }),
+        layout: Some(&layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: fs_module.as_ref().map(|module| wgpu::ProgrammableStageDescriptor {
+            module,
+            entry_point: "main",
+        }),
+        // both faces, so open meshes cast shadows from either side
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: wgpu::CullMode::None,
+            depth_bias: if point { 0 } else { 2 },
+            depth_bias_slope_scale: if point { 0.0 } else { 2.0 },
+            depth_bias_clamp: 0.0,
+            clamp_depth,
+        }),
+        color_states: &[],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+            format: DepthTexture::FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilStateDescriptor::default(),
+        }),
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: if instanced { &buffers } else { &buffers[..1] },
+        },
+        sample_count: 1,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
@@ -0,0 +1,24 @@
+#version 450
+
+// only the position matters for depth, the other attributes aren't bound
+layout(location=0) in vec3 a_position;
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+  // the light's position and range, read by point_shadow.frag
+  vec4 u_light;
+};
+layout(set=0, binding=1)
+  uniform Model {
+  mat4 u_model;
+  mat4 u_normal;
+};
+
+// world space, for the point shadows' distances
+layout(location=0) out vec3 v_position;
+
+void main() {
+  vec4 position = u_model * vec4(a_position, 1.0);
+  v_position = position.xyz;
+  gl_Position = u_view_proj * position;
+}
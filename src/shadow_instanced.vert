@@ -0,0 +1,24 @@
+#version 450
+
+layout(location=0) in vec3 a_position;
+// per instance, the model matrix's columns
+layout(location=4) in vec4 a_model_0;
+layout(location=5) in vec4 a_model_1;
+layout(location=6) in vec4 a_model_2;
+layout(location=7) in vec4 a_model_3;
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+  // the light's position and range, read by point_shadow.frag
+  vec4 u_light;
+};
+
+// world space, for the point shadows' distances
+layout(location=0) out vec3 v_position;
+
+void main() {
+  mat4 model = mat4(a_model_0, a_model_1, a_model_2, a_model_3);
+  vec4 position = model * vec4(a_position, 1.0);
+  v_position = position.xyz;
+  gl_Position = u_view_proj * position;
+}
@@ -0,0 +1,355 @@
+use crate::animator::Animator;
+use crate::collision::Aabb;
+use crate::import::ImportOptions;
+use crate::mesh::Transform;
+use crate::vertex::Vertex;
+use glam::{Mat4, Quat, Vec3};
+
+// weights below this don't move a vertex enough to count towards its
+// joint's extent
+const MIN_WEIGHT: f32 = 1e-4;
+
+// a bone of a skin, see `Skin`
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub name: String,
+    // the joint it hangs off, None for the skeleton's roots
+    pub parent: Option<usize>,
+    // its pose relative to its parent, the file's rest pose until animated
+    // or moved through `Skin::joints_mut`
+    pub local: Transform,
+    // the nodes between it and its parent joint, or the file's root, which
+    // don't move since they aren't joints
+    base: Mat4,
+    // from the mesh's bind pose into the joint's space
+    inverse_bind: Mat4,
+    // the vertices the joint moves, in its own space. the skinned vertices
+    // can't leave these boxes moved along with their joints, which is what
+    // the model's bounds are made of
+    extent: Aabb,
+}
+
+impl Joint {
+    pub(crate) fn new(name: String, parent: Option<usize>, local: Transform, base: Mat4, inverse_bind: Mat4) -> Self {
+        Self { name, parent, local, base, inverse_bind, extent: Aabb::empty() }
+    }
+}
+
+// a mesh whose vertices follow the joints of a skeleton, each by up to four
+// weighted joints. skinned on the CPU into the mesh's vertex buffer, so
+// every pipeline draws it without knowing about joints, see
+// `Scene::animate`
+pub struct Skin {
+    // parents always come before their children
+    joints: Vec<Joint>,
+    // the vertices in the bind pose, in the file's space
+    bind: Vec<Vertex>,
+    // per vertex, the joints moving it and their weights
+    influences: Vec<([u16; 4], [f32; 4])>,
+    // the vertices no joint moves, in the file's space
+    unskinned: Aabb,
+    // applied to the skinned vertices, as a matrix for the bounds
+    import: ImportOptions,
+    import_matrix: Mat4,
+    // the file's animations moving the joints, played by `animator`
+    pub animations: Vec<Animation>,
+    pub animator: Animator,
+    // the joints' local transforms as loaded, which the clips start from
+    rest: Vec<Transform>,
+    // the joints' local transforms, matrices and the skinned vertices,
+    // reused every frame
+    locals: Vec<Transform>,
+    worlds: Vec<Mat4>,
+    skinned: Vec<Vertex>,
+    // whether the joints moved since the vertices were skinned
+    dirty: bool,
+}
+
+impl Skin {
+    // `influences` go with `bind` and index `joints`, whose parents have to
+    // come first
+    pub(crate) fn new(mut joints: Vec<Joint>, bind: Vec<Vertex>, influences: Vec<([u16; 4], [f32; 4])>,
+                      import: ImportOptions) -> Self {
+        let mut unskinned = Aabb::empty();
+        for (vertex, (indices, weights)) in bind.iter().zip(&influences) {
+            let position = Vec3::from(vertex.position);
+            let mut skinned = false;
+            for (&joint, &weight) in indices.iter().zip(weights) {
+                if let Some(joint) = joints.get_mut(joint as usize).filter(|_| weight > MIN_WEIGHT) {
+                    let point = joint.inverse_bind.transform_point3(position);
+                    joint.extent = joint.extent.grow(&Aabb { min: point, max: point });
+                    skinned = true;
+                }
+            }
+            if !skinned {
+                unskinned = unskinned.grow(&Aabb { min: position, max: position });
+            }
+        }
+        let import_matrix = Mat4::from_cols(
+            import.position(Vec3::unit_x()).extend(0.0),
+            import.position(Vec3::unit_y()).extend(0.0),
+            import.position(Vec3::unit_z()).extend(0.0),
+            Vec3::zero().extend(1.0),
+        );
+        let count = joints.len();
+        let rest: Vec<Transform> = joints.iter().map(|joint| joint.local).collect();
+        Self {
+            joints,
+            skinned: bind.clone(),
+            bind,
+            influences,
+            unskinned,
+            import,
+            import_matrix,
+            animations: Vec::new(),
+            animator: Animator::default(),
+            locals: rest.clone(),
+            rest,
+            worlds: vec![Mat4::identity(); count],
+            dirty: true,
+        }
+    }
+
+    pub fn joints(&self) -> &[Joint] {
+        &self.joints
+    }
+
+    // to pose the skeleton by hand, e.g. from an animation system of the
+    // application's. the vertices follow on the next `Scene::animate`
+    pub fn joints_mut(&mut self) -> &mut [Joint] {
+        self.dirty = true;
+        &mut self.joints
+    }
+
+    // where the joint is in the pose last skinned, in the model's space
+    pub fn joint_position(&self, index: usize) -> Vec3 {
+        self.import_matrix.transform_point3(self.worlds[index].w_axis.truncate())
+    }
+
+    pub fn animation_index(&self, name: &str) -> Option<usize> {
+        self.animations.iter().position(|animation| animation.name == name)
+    }
+
+    // moves the animator's clips on to `time`, in seconds, and poses the
+    // joints as they have them. leaves the joints alone while nothing plays
+    pub fn animate(&mut self, time: f32) {
+        self.animator.advance(time, &self.animations);
+        if !self.animator.is_playing() {
+            return;
+        }
+        self.animator.pose(&self.animations, &self.rest, &mut self.locals);
+        for (joint, local) in self.joints.iter_mut().zip(&self.locals) {
+            joint.local = *local;
+        }
+        self.dirty = true;
+    }
+
+    // skins the vertices for the joints' current pose, None if they didn't
+    // move since the last time
+    pub fn update(&mut self) -> Option<&[Vertex]> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        self.pose();
+        for ((vertex, skinned), (indices, weights)) in self.bind.iter().zip(&mut self.skinned).zip(&self.influences) {
+            let (mut position, mut normal, mut tangent, mut total) = (Vec3::zero(), Vec3::zero(), Vec3::zero(), 0.0);
+            for (&joint, &weight) in indices.iter().zip(weights) {
+                if weight <= MIN_WEIGHT || joint as usize >= self.joints.len() {
+                    continue;
+                }
+                let matrix = self.worlds[joint as usize] * self.joints[joint as usize].inverse_bind;
+                position += matrix.transform_point3(Vec3::from(vertex.position)) * weight;
+                normal += matrix.transform_vector3(Vec3::from(vertex.normal)) * weight;
+                let [x, y, z, _] = vertex.tangent;
+                tangent += matrix.transform_vector3(Vec3::new(x, y, z)) * weight;
+                total += weight;
+            }
+            // vertices without joints stay in the bind pose
+            let (position, normal, tangent) = if total > 0.0 {
+                (position / total, normal.normalize(), tangent.normalize())
+            } else {
+                let [x, y, z, _] = vertex.tangent;
+                (Vec3::from(vertex.position), Vec3::from(vertex.normal), Vec3::new(x, y, z))
+            };
+            skinned.position = self.import.position(position).into();
+            skinned.normal = self.import.normal(normal).into();
+            skinned.tangent = self.import.tangent([tangent.x, tangent.y, tangent.z, vertex.tangent[3]]);
+        }
+        Some(&self.skinned)
+    }
+
+    // the space the skinned vertices can take up in the current pose, in
+    // the model's space. looser than the vertices' own bounds, but only
+    // the joints have to be moved to get it
+    pub fn bounds(&self) -> Aabb {
+        let mut bounds = transform_aabb(&self.import_matrix, &self.unskinned);
+        for (joint, world) in self.joints.iter().zip(&self.worlds) {
+            bounds = bounds.grow(&transform_aabb(&(self.import_matrix * *world), &joint.extent));
+        }
+        bounds
+    }
+
+    // the joints' matrices in the file's space, parents first
+    fn pose(&mut self) {
+        for index in 0..self.joints.len() {
+            let joint = &self.joints[index];
+            let parent = joint.parent.map_or_else(Mat4::identity, |parent| self.worlds[parent]);
+            self.worlds[index] = parent * joint.base * joint.local.matrix();
+        }
+    }
+}
+
+fn transform_aabb(matrix: &Mat4, bounds: &Aabb) -> Aabb {
+    if bounds.is_empty() {
+        return *bounds;
+    }
+    let Aabb { min, max } = *bounds;
+    Aabb::from_points((0..8).map(|corner| {
+        let x = if corner & 1 == 0 { min.x } else { max.x };
+        let y = if corner & 2 == 0 { min.y } else { max.y };
+        let z = if corner & 4 == 0 { min.z } else { max.z };
+        matrix.transform_point3(Vec3::new(x, y, z))
+    }))
+}
+
+// what an animation channel moves
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Property {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+// keyframes of one property of a joint
+#[derive(Debug, Clone)]
+pub(crate) struct Channel {
+    pub joint: usize,
+    pub property: Property,
+    // holds the value until the next key instead of blending
+    pub step: bool,
+    // seconds, ascending
+    pub times: Vec<f32>,
+    // xyz for translations and scales, xyzw for rotations
+    pub values: Vec<[f32; 4]>,
+}
+
+// keyframed joint poses, see `Animator` for playing them
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub name: String,
+    // seconds, the last keyframe of any channel
+    pub duration: f32,
+    channels: Vec<Channel>,
+}
+
+impl Animation {
+    pub(crate) fn new(name: String, channels: Vec<Channel>) -> Self {
+        let duration = channels.iter()
+            .filter_map(|channel| channel.times.last().copied())
+            .fold(0.0, f32::max);
+        Self { name, duration, channels }
+    }
+
+    // sets what the channels move in `locals`, one per joint, to their
+    // values `time` seconds in, holding the first and last keys outside the
+    // clip
+    pub(crate) fn sample(&self, time: f32, locals: &mut [Transform]) {
+        for channel in &self.channels {
+            let local = match locals.get_mut(channel.joint) {
+                Some(local) => local,
+                None => continue,
+            };
+            let (before, after, s) = match keys(&channel.times, time) {
+                Some(keys) => keys,
+                None => continue,
+            };
+            let (a, b) = (channel.values[before], channel.values[after]);
+            let s = if channel.step { 0.0 } else { s };
+            let vector = |[x, y, z, _]: [f32; 4]| Vec3::new(x, y, z);
+            match channel.property {
+                Property::Translation => local.translation = vector(a).lerp(vector(b), s),
+                Property::Scale => local.scale = vector(a).lerp(vector(b), s),
+                Property::Rotation => local.rotation = Quat::from(a).slerp(Quat::from(b), s).normalize(),
+            }
+        }
+    }
+}
+
+// the keys around `time` and how far it is from the first to the second,
+// holding the first and last keys outside them
+fn keys(times: &[f32], time: f32) -> Option<(usize, usize, f32)> {
+    let last = times.len().checked_sub(1)?;
+    let next = match times.iter().position(|key| *key > time) {
+        Some(0) => return Some((0, 0, 0.0)),
+        Some(next) => next,
+        None => return Some((last, last, 0.0)),
+    };
+    let (start, end) = (times[next - 1], times[next]);
+    Some((next - 1, next, (time - start) / (end - start)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a two joint arm along x, the second joint bending at x = 1
+    fn arm() -> Skin {
+        let vertex = |x: f32| Vertex { position: [x, 0.0, 0.0], normal: [0.0, 1.0, 0.0], ..Vertex::default() };
+        let root = Joint::new("root".to_string(), None, Transform::default(), Mat4::identity(), Mat4::identity());
+        let elbow = Joint::new("elbow".to_string(), Some(0), Transform {
+            translation: Vec3::new(1.0, 0.0, 0.0),
+            ..Transform::default()
+        }, Mat4::identity(), Mat4::from_translation(Vec3::new(-1.0, 0.0, 0.0)));
+        Skin::new(vec![root, elbow], vec![vertex(0.0), vertex(1.0), vertex(2.0)],
+                  vec![([0, 0, 0, 0], [1.0, 0.0, 0.0, 0.0]),
+                       ([0, 1, 0, 0], [0.5, 0.5, 0.0, 0.0]),
+                       ([1, 0, 0, 0], [1.0, 0.0, 0.0, 0.0])],
+                  ImportOptions::default())
+    }
+
+    fn contains(bounds: &Aabb, point: Vec3) -> bool {
+        let epsilon = Vec3::splat(1e-4);
+        (bounds.min - epsilon).cmple(point).all() && point.cmple(bounds.max + epsilon).all()
+    }
+
+    #[test]
+    fn bounds_follow_the_pose() {
+        let mut skin = arm();
+        skin.update();
+        assert!(contains(&skin.bounds(), Vec3::new(2.0, 0.0, 0.0)));
+        assert!((skin.joint_position(1) - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-4);
+
+        // bending the elbow up by 90 degrees lifts the hand out of the
+        // bind pose's bounds
+        skin.joints_mut()[1].local.rotation = Quat::from_rotation_z(std::f32::consts::FRAC_PI_2);
+        let vertices = skin.update().unwrap().to_vec();
+        let hand = Vec3::from(vertices[2].position);
+        assert!((hand - Vec3::new(1.0, 1.0, 0.0)).length() < 1e-4);
+        let bounds = skin.bounds();
+        for vertex in &vertices {
+            assert!(contains(&bounds, Vec3::from(vertex.position)));
+        }
+        assert!(!contains(&Aabb::from_points(skin.bind.iter().map(|vertex| Vec3::from(vertex.position))), hand));
+    }
+
+    #[test]
+    fn animations_loop_and_blend() {
+        let mut skin = arm();
+        skin.animations = vec![Animation::new("wave".to_string(), vec![Channel {
+            joint: 0,
+            property: Property::Translation,
+            step: false,
+            times: vec![0.0, 1.0],
+            values: vec![[0.0; 4], [0.0, 2.0, 0.0, 0.0]],
+        }])];
+        skin.animator.play(0, 0.0);
+        skin.animate(10.0);
+        skin.animate(11.5);
+        assert!((skin.joints()[0].local.translation - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-4);
+        let vertices = skin.update().unwrap();
+        assert!((Vec3::from(vertices[0].position) - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-4);
+        // nothing moved since
+        assert!(skin.update().is_none());
+    }
+}
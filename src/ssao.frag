@@ -0,0 +1,71 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out float f_occlusion;
+
+// must match MAX_SAMPLES in ssao.rs
+#define MAX_SAMPLES 64
+
+layout(set=0, binding=0) uniform Ssao {
+  mat4 s_proj;
+  mat4 s_inv_proj;
+  mat4 s_view;
+  float s_radius;
+  float s_intensity;
+  uint s_sample_count;
+  // offsets in the hemisphere around +z, within a radius of 1
+  vec4 s_kernel[MAX_SAMPLES];
+};
+// see gbuffer.frag
+layout(set=0, binding=1) uniform texture2D t_normal;
+layout(set=0, binding=2) uniform texture2D t_depth;
+
+#define PI 3.14159265
+
+// the kernel's turn in sixteenths, tiled every 4x4 pixels so the blur's
+// 4x4 box sees every angle once
+const float ROTATIONS[16] = float[](
+  0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0);
+
+vec3 view_position(vec2 uv, float depth) {
+  vec4 p = s_inv_proj * vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+  return p.xyz / p.w;
+}
+
+// the fraction of the hemisphere above the pixel that isn't inside the
+// surfaces in front of it, 1 for the background and unlit materials
+void main() {
+  ivec2 pixel = ivec2(gl_FragCoord.xy);
+  float depth = texelFetch(t_depth, pixel, 0).r;
+  vec4 normal = texelFetch(t_normal, pixel, 0);
+  if (depth >= 1.0 || normal.w > 0.5) {
+    f_occlusion = 1.0;
+    return;
+  }
+  vec2 size = vec2(textureSize(t_depth, 0));
+  vec3 p = view_position((vec2(pixel) + 0.5) / size, depth);
+  vec3 n = normalize(mat3(s_view) * normal.xyz);
+
+  float angle = (ROTATIONS[(pixel.y % 4) * 4 + pixel.x % 4] + 0.5) / 16.0 * 2.0 * PI;
+  vec3 r = vec3(cos(angle), sin(angle), 0.0);
+  vec3 t = r - n * dot(r, n);
+  // r can only be parallel to n for normals across the view
+  t = dot(t, t) > 1e-6 ? normalize(t) : normalize(cross(n, vec3(0.0, 0.0, 1.0)));
+  mat3 tbn = mat3(t, cross(n, t), n);
+
+  float occluded = 0.0;
+  for (uint i = 0; i < s_sample_count; i++) {
+    vec3 s = p + tbn * s_kernel[i].xyz * s_radius;
+    vec4 clip = s_proj * vec4(s, 1.0);
+    vec2 uv = clip.xy / clip.w * vec2(0.5, -0.5) + 0.5;
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+      continue;
+    }
+    float z = view_position(uv, texelFetch(t_depth, ivec2(uv * size), 0).r).z;
+    // what's in front by much more than the radius is another object
+    // the hemisphere doesn't reach, which fades out
+    float range = smoothstep(0.0, 1.0, s_radius / max(abs(p.z - z), 1e-4));
+    occluded += z >= s.z + 0.02 * s_radius ? range : 0.0;
+  }
+  f_occlusion = clamp(1.0 - occluded / float(max(s_sample_count, 1u)) * s_intensity, 0.0, 1.0);
+}
@@ -0,0 +1,263 @@
+use wgpu;
+use glam::{Vec2, Vec3};
+use crate::camera::Camera;
+use crate::config::SsaoConfig;
+use crate::graph::Resource;
+use crate::texture::Texture;
+use crate::upscale;
+
+// what the occlusion is drawn into before and after it's blurred, sized
+// like the g-buffer
+pub const RAW: Resource = "ssao_raw";
+pub const OCCLUSION: Resource = "ssao";
+pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+// must match MAX_SAMPLES in ssao.frag
+const MAX_SAMPLES: usize = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniforms {
+    proj: [[f32; 4]; 4],
+    inv_proj: [[f32; 4]; 4],
+    view: [[f32; 4]; 4],
+    radius: f32,
+    intensity: f32,
+    sample_count: u32,
+    _padding: f32,
+    // offsets in the hemisphere around +z, scaled by the radius
+    kernel: [[f32; 4]; MAX_SAMPLES],
+}
+
+// ambient occlusion from the g-buffer: every pixel's position is rebuilt
+// in view space from the depth, and the kernel's points in the hemisphere
+// around its normal are checked against the depth there. the kernel is
+// turned in a 4x4 pattern of angles, which a 4x4 blur averages out, and
+// deferred.frag scales the ambient light by the result
+pub struct Ssao {
+    config: SsaoConfig,
+    occlusion_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    occlusion_layout: wgpu::BindGroupLayout,
+    blur_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    // bound in the occlusion's place while it's off
+    unoccluded: Texture,
+}
+
+impl Ssao {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, config: &SsaoConfig) -> Self {
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::SampledTexture {
+                multisampled: false,
+                dimension: wgpu::TextureViewDimension::D2,
+                component_type: wgpu::TextureComponentType::Float,
+            },
+            count: None,
+        };
+        let occlusion_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture(1),
+                texture(2),
+            ],
+            label: Some("ssao_bind_group_layout"),
+        });
+        let blur_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[texture(0)],
+            label: Some("ssao_blur_bind_group_layout"),
+        });
+        let occlusion_module = device.create_shader_module(wgpu::include_spirv!("ssao.frag.spv"));
+        let blur_module = device.create_shader_module(wgpu::include_spirv!("ssao_blur.frag.spv"));
+        let occlusion_pipeline = create_pipeline(device, &occlusion_layout, &occlusion_module, "ssao_pipeline");
+        let blur_pipeline = create_pipeline(device, &blur_layout, &blur_module, "ssao_blur_pipeline");
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ssao_uniform_buffer"),
+            size: std::mem::size_of::<SsaoUniforms>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let unoccluded = Texture::from_color(
+            device, queue, [255; 4], wgpu::TextureFormat::Rgba8Unorm, Some("ssao_unoccluded_texture"));
+        Self {
+            config: config.clone(),
+            occlusion_pipeline,
+            blur_pipeline,
+            occlusion_layout,
+            blur_layout,
+            uniform_buffer,
+            unoccluded,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.config.enabled = enabled;
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.config.radius
+    }
+
+    pub fn set_radius(&mut self, radius: f32) {
+        self.config.radius = radius.max(0.01);
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.config.intensity
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.config.intensity = intensity.max(0.0);
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.config.samples
+    }
+
+    pub fn set_samples(&mut self, samples: u32) {
+        self.config.samples = samples.clamp(1, MAX_SAMPLES as u32);
+    }
+
+    // a white texel for deferred.frag while the occlusion is off
+    pub fn unoccluded(&self) -> &wgpu::TextureView {
+        &self.unoccluded.view
+    }
+
+    // `jitter` has to match the projection offset the g-buffer is drawn with
+    pub fn prepare(&self, queue: &wgpu::Queue, camera: &Camera, jitter: Vec2) {
+        let proj = upscale::jittered(camera.build_projection_matrix(), jitter);
+        let sample_count = self.config.samples.clamp(1, MAX_SAMPLES as u32);
+        let mut kernel = [[0.0; 4]; MAX_SAMPLES];
+        for (i, offset) in kernel.iter_mut().take(sample_count as usize).enumerate() {
+            *offset = kernel_offset(i, sample_count as usize).extend(0.0).into();
+        }
+        let uniforms = SsaoUniforms {
+            proj: proj.to_cols_array_2d(),
+            inv_proj: proj.inverse().to_cols_array_2d(),
+            view: camera.build_view_matrix().to_cols_array_2d(),
+            radius: self.config.radius,
+            intensity: self.config.intensity,
+            sample_count,
+            _padding: 0.0,
+            kernel,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    // writes the occlusion of the g-buffer's `normal` and `depth` into
+    // `raw` and blurs it into `target`. the views are transient, so the
+    // bind groups are made every frame
+    pub fn render(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder,
+                  (normal, depth): (&wgpu::TextureView, &wgpu::TextureView),
+                  (raw, target): (&wgpu::TextureView, &wgpu::TextureView)) {
+        let occlusion_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.occlusion_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(self.uniform_buffer.slice(..)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(normal),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(depth),
+                },
+            ],
+            label: Some("ssao_bind_group"),
+        });
+        let blur_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.blur_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(raw),
+                },
+            ],
+            label: Some("ssao_blur_bind_group"),
+        });
+        draw(encoder, raw, &self.occlusion_pipeline, &occlusion_bind_group);
+        draw(encoder, target, &self.blur_pipeline, &blur_bind_group);
+    }
+}
+
+// the `i`th of `count` points spread over the hemisphere around +z on a
+// golden angle spiral, pulled towards the center so close occluders count
+// more
+fn kernel_offset(i: usize, count: usize) -> Vec3 {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+    let t = (i as f32 + 0.5) / count as f32;
+    let z = 1.0 - t;
+    let ring = (1.0 - z * z).sqrt();
+    let angle = golden_angle * i as f32;
+    let scale = 0.1 + 0.9 * t * t;
+    Vec3::new(ring * angle.cos(), ring * angle.sin(), z.max(0.05)).normalize() * scale
+}
+
+// one fullscreen triangle into `target`
+fn draw(encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView, pipeline: &wgpu::RenderPipeline,
+        bind_group: &wgpu::BindGroup) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+            attachment: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: None,
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.draw(0..3, 0..1);
+}
+
+fn create_pipeline(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout,
+                   fs_module: &wgpu::ShaderModule, label: &str) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("fullscreen.vert.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(&layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        color_states: &[FORMAT.into()],
+        primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+        depth_stencil_state: None,
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[],
+        },
+        sample_count: 1,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
@@ -0,0 +1,20 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out float f_occlusion;
+
+layout(set=0, binding=0) uniform texture2D t_occlusion;
+
+// averages the 4x4 pixels around this one, which cover each of
+// ssao.frag's kernel rotations once
+void main() {
+  ivec2 pixel = ivec2(gl_FragCoord.xy);
+  ivec2 last = textureSize(t_occlusion, 0) - 1;
+  float sum = 0.0;
+  for (int x = -2; x < 2; x++) {
+    for (int y = -2; y < 2; y++) {
+      sum += texelFetch(t_occlusion, clamp(pixel + ivec2(x, y), ivec2(0), last), 0).r;
+    }
+  }
+  f_occlusion = sum / 16.0;
+}
@@ -1,337 +1,1907 @@
-use wgpu;
-use wgpu::util::DeviceExt;
 use winit::{
     event::*,
     window::*,
 };
-use anyhow::{Context, Result};
-use bytemuck;
-use tobj::*;
-use crate::camera::Camera;
-use crate::mesh::Model;
-use crate::material::Material;
-use crate::texture::DepthTexture;
-use glam::{vec3, Vec3};
+use anyhow::{anyhow, bail, Context, Result};
+use glam::Vec3;
+#[cfg(feature = "ui")]
+use glam::Quat;
+use log::{error, info};
+#[cfg(feature = "ui")]
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::assets::Assets;
+use crate::camera::{Camera, CameraMode, FlyCamera, OrbitCameraController, WalkController};
+use crate::config::{BackgroundMode, Config, SceneConfig, StreamingConfig};
+use crate::csg;
+#[cfg(feature = "ui")]
+use crate::config::{DebugView, Tonemap};
+use crate::diff::SceneDiff;
+use crate::import::ImportOptions;
+use crate::input::{InputEvent, InputRecording, RecordedInput};
+use crate::lighting::Preset;
+use crate::lights::Light;
+use crate::loading::SceneLoad;
+use crate::material::Factors;
+#[cfg(feature = "ui")]
+use crate::material::MAP_NAMES;
+use crate::mesh::{ObjectFlags, Transform};
+use crate::minimap::MinimapView;
+use crate::profiler::{Budget, Profile};
+use crate::params;
+#[cfg(feature = "ui")]
+use crate::params::ShaderParam;
+use crate::picking::Pick;
+use crate::quality::Quality;
+use crate::renderer::{DrawStats, Renderer};
+use crate::scene::{self, Scene};
+use crate::selection::Selection;
+#[cfg(feature = "ui")]
+use crate::skin::Joint;
+use crate::tiles::TilePager;
 
+// cells across the primitives M meshes them with
+const CSG_RESOLUTION: u32 = 96;
+
+// how long a message stays up
+const MESSAGE_SECONDS: f32 = 5.0;
+// seconds the timeline crossfades over when another clip is picked
+#[cfg(feature = "ui")]
+const TIMELINE_FADE: f32 = 0.25;
+
+// the bundled viewer: a renderer plus the scene and camera it shows
 pub struct State {
-    surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    sc_desc: wgpu::SwapChainDescriptor,
-    swap_chain: wgpu::SwapChain,
+    renderer: Renderer,
+    scene: Scene,
+    // the scene replacing `scene` once it's loaded
+    loading: Option<SceneLoad>,
+    // textures shared between the scenes it loads
+    assets: Assets,
+    // streams the scene's textures every update when on
+    streaming: Option<StreamingConfig>,
+    // pages tiles in and out of the scene around the camera every frame
+    tiles: Option<TilePager>,
+    // what models loaded after the first one are fixed up with
+    import: ImportOptions,
+    comparison: Option<Comparison>,
+    camera: Camera,
+    mode: CameraMode,
+    orbit: OrbitCameraController,
+    fly: FlyCamera,
+    walk: WalkController,
+    lighting: Preset,
+    last_update: Instant,
+    // replaces the time between updates when set, see `DeterminismConfig`
+    timestep: Option<f32>,
+    dump_dir: PathBuf,
+    stats: FrameStats,
+    // whether the stats HUD is up, toggled with F1
+    show_stats: bool,
+    // spent in `input` since the last frame
+    input_time: Duration,
+    // the parts of the last update and how long they took
+    update_timings: Vec<(&'static str, Duration)>,
+    // frames being copied back to be saved, see `capture_frame`
+    captures: Vec<(PathBuf, Receiver<Result<image::RgbaImage>>)>,
+    // picks under the cursor being read back, see `pick`, and whether
+    // they add or remove their model rather than select only it
+    picks: Vec<(bool, Receiver<Result<Option<Pick>>>)>,
+    // what the last pick found, whose material the material editor edits
+    selection: Option<Pick>,
+    // the models picked or boxed in and transformed as a group
+    selected: Selection,
+    // where the box being dragged out with shift started, and the boxes
+    // being read back, see `select_box`, and whether they add to the
+    // selection rather than replace it
+    box_start: Option<(u32, u32)>,
+    boxes: Vec<(bool, Receiver<Result<Vec<usize>>>)>,
+    // held down, for clicks and boxes adding to the selection and undoing
+    shift: bool,
+    control: bool,
+    // the map and image file typed into the material editor
+    #[cfg(feature = "ui")]
+    map_edit: (usize, String),
+    // the outliner's model being dragged and the ones expanded
+    #[cfg(feature = "ui")]
+    outliner_drag: Option<usize>,
+    #[cfg(feature = "ui")]
+    outliner_open: HashSet<usize>,
+    // the move, the turn around the up axis in degrees and the scale typed
+    // into the selection's section
+    #[cfg(feature = "ui")]
+    selection_edit: ([f32; 3], f32, f32),
+    // the session's input so far, see `start_recording`
+    recording: Option<InputRecording>,
+    // a recording played back instead of live input, see `replay`
+    replay: Option<Replay>,
+    // the seconds the last update stepped by
+    last_step: f32,
+    // where the cursor was last seen over the window
+    cursor: Option<winit::dpi::PhysicalPosition<f64>>,
+    // an error for the UI to show over the frame and when it came up
+    message: Option<(String, Instant)>,
     pub size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
-    uniform_bind_group: wgpu::BindGroup,
-    light_bind_group: wgpu::BindGroup,
-    models: Vec<Model>,
-    materials: Vec<Material>,
-    depth_texture: DepthTexture,
 }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct Uniforms {
-    view_proj: [[f32; 4]; 4],
+// frames the stats are averaged over, so they don't flicker
+const STATS_FRAMES: usize = 60;
+
+// what the last frames took and drew, for the stats HUD
+#[derive(Debug, Clone)]
+pub struct FrameStats {
+    // seconds between updates
+    frame_times: Samples,
+    // seconds spent encoding and submitting a frame
+    cpu_times: Samples,
+    // seconds a frame kept the CPU busy, handling input, updating and
+    // rendering
+    busy_times: Samples,
+    // in seconds
+    budget: f32,
+    profile: Profile,
+    pub draws: DrawStats,
 }
 
-impl Uniforms {
-    fn new(camera: &Camera) -> Self {
-        Uniforms {
-            view_proj: camera.build_view_projection_matrix().to_cols_array_2d()
-        }
+// the last STATS_FRAMES values, in a fixed array so recording them never
+// allocates
+#[derive(Debug, Clone)]
+struct Samples {
+    values: [f32; STATS_FRAMES],
+    count: usize,
+}
+
+// how far a recording has been played back
+struct Replay {
+    recording: InputRecording,
+    // updates stepped so far
+    frame: u64,
+    // the first event not applied yet
+    next: usize,
+}
+
+impl Replay {
+    // the next event due before the next update
+    fn next_event(&mut self) -> Option<InputEvent> {
+        let recorded = self.recording.events.get(self.next).filter(|recorded| recorded.frame <= self.frame)?;
+        self.next += 1;
+        Some(recorded.event)
+    }
+
+    fn is_done(&self) -> bool {
+        self.frame as usize >= self.recording.steps.len()
     }
 }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct Light {
-    position: [f32; 3],
-    _padding: u32,
-    color: [f32; 3],
+// another version of the scene to review the current one against, see
+// `State::compare_with`
+struct Comparison {
+    other: Scene,
+    diff: SceneDiff,
+    // whether the other version is the one shown, swapped into `scene`
+    swapped: bool,
 }
 
 impl State {
-    pub async fn new(window: &Window) -> Result<Self> {
+    // shows a placeholder until the scene has loaded in the background,
+    // see `poll_loading`
+    pub async fn new(window: &Window, config: &Config) -> Result<Self> {
         let size = window.inner_size();
+        let mut renderer = Renderer::new(window, &config.renderer).await?;
+        renderer.set_nav_cube(config.window.nav_cube);
+        if config.window.minimap {
+            renderer.set_minimap(Some(MinimapView::TopDown));
+        }
+        Self::with_renderer(renderer, size, config)
+    }
 
-        // initializing GPU
-        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
-        let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::Default,
-                compatible_surface: Some(&surface),
-            }
-        ).await.context("Failed to create adapter")?;
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
-                shader_validation: true,
-            },
-            None,
-        ).await?;
-        let sc_desc = wgpu::SwapChainDescriptor {
-            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+    // waits for the scene, since there's no window to keep responsive
+    pub async fn new_headless(width: u32, height: u32, config: &Config) -> Result<Self> {
+        let renderer = Renderer::new_headless(width, height, &config.renderer).await?;
+        let mut state = Self::with_renderer(renderer, winit::dpi::PhysicalSize::new(width, height), config)?;
+        state.finish_loading().await?;
+        Ok(state)
+    }
+
+    fn with_renderer(mut renderer: Renderer, size: winit::dpi::PhysicalSize<u32>, config: &Config) -> Result<Self> {
+        renderer.set_time(if config.determinism.enabled { config.determinism.time } else { 0.0 });
+        // load models and materials, drawing a placeholder meanwhile
+        let scene = Scene::placeholder(renderer.device(), renderer.queue());
+        let (device, queue) = renderer.shared_device();
+        let streaming = Some(config.scene.streaming.clone()).filter(|streaming| streaming.enabled);
+        let assets = || {
+            let mut assets = Assets::new();
+            assets.set_streaming(streaming.as_ref().map(|streaming| streaming.initial_size));
+            assets
         };
-        let swap_chain = device.create_swap_chain(&surface, &sc_desc);
-
-        // load models and materials
-        let (obj_models, obj_materials) = load_obj("data/cube.obj", true)?;
-        let mut models = vec![];
-        let mut materials = vec![];
-        obj_models.iter().for_each(|model| {
-            models.push(Model::new(&device, model));
-        });
-        obj_materials.iter().for_each(|material| {
-            materials.push(Material::new(&device, &queue, material).unwrap());
-        });
-        
+        let loading = SceneLoad::start(device, queue, config.scene.clone(), assets());
+        let tiles = match &config.scene.tiles {
+            Some(path) => Some(TilePager::load(path, &config.scene)?),
+            None => None,
+        };
+
         // setting up camera
-        let camera = Camera {
-            eye: vec3(0.0, 1.0, 2.0),
-            center: Vec3::zero(),
-            up: Vec3::unit_y(),
-            aspect_ratio: sc_desc.width as f32 / sc_desc.height as f32,
-            fov: 0.7,
-            z_near: 0.1,
-            z_far: 100.0,
+        let camera = Camera::from_config(&config.camera, size.width as f32 / size.height as f32);
+
+        Ok(Self {
+            renderer,
+            scene,
+            loading: Some(loading),
+            assets: assets(),
+            streaming,
+            tiles,
+            import: config.scene.import,
+            comparison: None,
+            camera,
+            mode: CameraMode::Orbit,
+            orbit: OrbitCameraController::new(&config.orbit),
+            fly: FlyCamera::new(&config.fly),
+            walk: WalkController::new(&config.walk),
+            lighting: config.renderer.lighting,
+            last_update: Instant::now(),
+            timestep: Some(config.determinism.timestep.max(0.0)).filter(|_| config.determinism.enabled),
+            dump_dir: PathBuf::from(&config.renderer.debug.dump_dir),
+            stats: FrameStats::new(config.stats.budget / 1000.0),
+            show_stats: false,
+            input_time: Duration::default(),
+            // updates mustn't allocate, there are at most three parts
+            update_timings: Vec::with_capacity(3),
+            captures: vec![],
+            picks: vec![],
+            selection: None,
+            selected: Selection::default(),
+            box_start: None,
+            boxes: vec![],
+            shift: false,
+            control: false,
+            #[cfg(feature = "ui")]
+            map_edit: (0, String::new()),
+            #[cfg(feature = "ui")]
+            outliner_drag: None,
+            #[cfg(feature = "ui")]
+            outliner_open: HashSet::new(),
+            #[cfg(feature = "ui")]
+            selection_edit: ([0.0; 3], 0.0, 1.0),
+            recording: None,
+            replay: None,
+            last_step: 0.0,
+            cursor: None,
+            message: None,
+            size,
+        })
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.size = new_size;
+        self.camera.aspect_ratio = new_size.width as f32 / new_size.height as f32;
+        self.renderer.resize(new_size);
+    }
+
+    pub fn renderer(&self) -> &Renderer {
+        &self.renderer
+    }
+
+    pub fn renderer_mut(&mut self) -> &mut Renderer {
+        &mut self.renderer
+    }
+
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    // replaces the current scene's model, keeping it when the new one fails
+    // to load. raymarched primitives carry over
+    pub fn load_scene(&mut self, path: &str) -> Result<()> {
+        // the scene still loading would replace this one
+        self.loading = None;
+        self.stop_comparing();
+        let mut scene = Scene::load_with_options(
+            self.renderer.device(), self.renderer.queue(), path, &mut self.assets, self.import)?;
+        scene.sdf = std::mem::take(&mut self.scene.sdf);
+        scene.curves = std::mem::take(&mut self.scene.curves);
+        apply_quality(&self.renderer, &mut scene);
+        info!("{}", scene.stats);
+        self.scene = scene;
+        self.reset_selection();
+        self.assets.purge();
+        if let Some(tiles) = &mut self.tiles {
+            tiles.reset();
+        }
+        Ok(())
+    }
+
+    // unloads the scene and loads the model at `path` in the background,
+    // e.g. a file dropped onto the window. it gets the import options but
+    // nothing else the configured scene had, tiles included. files of
+    // other formats are refused and shown as the message
+    pub fn open_file(&mut self, path: &Path) -> Result<()> {
+        if !scene::is_model(path) {
+            let error = anyhow!("Can't open {}, only .obj, .gltf, .glb and .pack models are supported", path.display());
+            self.show_message(format!("{:#}", error));
+            return Err(error);
+        }
+        self.stop_comparing();
+        self.tiles = None;
+        self.scene = Scene::placeholder(self.renderer.device(), self.renderer.queue());
+        self.reset_selection();
+        let config = SceneConfig {
+            model: path.to_string_lossy().into_owned(),
+            import: self.import,
+            ..SceneConfig::default()
         };
+        let mut assets = Assets::new();
+        assets.set_streaming(self.streaming.as_ref().map(|streaming| streaming.initial_size));
+        let (device, queue) = self.renderer.shared_device();
+        self.loading = Some(SceneLoad::start(device, queue, config, assets));
+        Ok(())
+    }
 
-        // setting up uniform buffer
-        let uniforms = Uniforms::new(&camera);
-        let uniform_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("uniform_buffer"),
-                contents: bytemuck::cast_slice(&[uniforms]),
-                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-            }
-        );
-        let uniform_bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStage::VERTEX,
-                        ty: wgpu::BindingType::UniformBuffer {
-                            dynamic: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
-                label: Some("uniform_bind_group_layout")
-            }
-        );
-        let uniform_bind_group = device.create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                layout: &uniform_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
-                    },
-                ],
-                label: Some("uniform_bind_group"),
+    // the last error shown to the user, until it's been up for a few
+    // seconds
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref()
+            .filter(|(_, shown)| shown.elapsed().as_secs_f32() < MESSAGE_SECONDS)
+            .map(|(message, _)| message.as_str())
+    }
+
+    pub fn show_message(&mut self, message: String) {
+        self.message = Some((message, Instant::now()));
+    }
+
+    pub fn import_options(&self) -> ImportOptions {
+        self.import
+    }
+
+    // what `load_scene` and `compare_with` fix the files up with from now
+    // on, the current scene keeps its own
+    pub fn set_import_options(&mut self, options: ImportOptions) {
+        self.import = options;
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    // swaps in the scene loading in the background once it's ready,
+    // returning whether it did. when it failed to load the placeholder
+    // stays and the error is returned
+    pub fn poll_loading(&mut self) -> Result<bool> {
+        match self.loading.as_mut().and_then(SceneLoad::poll) {
+            Some(loaded) => {
+                self.loading = None;
+                self.swap_in(loaded).map(|()| true)
             }
-        );
+            None => Ok(false),
+        }
+    }
 
-        let light = Light {
-            position: [0.0, 2.0, -3.0],
-            _padding: 0,
-            color: [1.0, 1.0, 1.0],
-        };
+    // blocks until the scene loading in the background is swapped in
+    pub async fn finish_loading(&mut self) -> Result<()> {
+        match self.loading.take() {
+            Some(loading) => self.swap_in(loading.finish().await),
+            None => Ok(()),
+        }
+    }
 
-        let light_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Light VB"),
-                contents: bytemuck::cast_slice(&[light]),
-                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-            });
+    // a scene that failed to load is shown as the message as well
+    fn swap_in(&mut self, (scene, assets): (Result<Scene>, Assets)) -> Result<()> {
+        self.stop_comparing();
+        self.assets = assets;
+        let mut scene = scene.map_err(|error| {
+            self.show_message(format!("{:#}", error));
+            error
+        })?;
+        apply_quality(&self.renderer, &mut scene);
+        info!("{}", scene.stats);
+        self.scene = scene;
+        self.reset_selection();
+        if let Some(tiles) = &mut self.tiles {
+            tiles.reset();
+        }
+        Ok(())
+    }
 
-        let light_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-                    ty: wgpu::BindingType::UniformBuffer {
-                        dynamic: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: None,
+    // rebuilds whatever the scene loaded from `path` after it changed on
+    // disk, see `Scene::reload_file`. both versions are reloaded while
+    // comparing, and the diff is redone
+    pub fn reload_file(&mut self, path: &Path) -> Result<bool> {
+        let mut reloaded = self.scene.reload_file(self.renderer.device(), self.renderer.queue(), path)?;
+        if reloaded {
+            apply_quality(&self.renderer, &mut self.scene);
+        }
+        if let Some(comparison) = &mut self.comparison {
+            if comparison.other.reload_file(self.renderer.device(), self.renderer.queue(), path)? {
+                apply_quality(&self.renderer, &mut comparison.other);
+                reloaded = true;
+            }
+            if reloaded {
+                comparison.diff = if comparison.swapped {
+                    SceneDiff::new(&comparison.other, &self.scene)
+                } else {
+                    SceneDiff::new(&self.scene, &comparison.other)
+                };
+                self.renderer.set_highlights(comparison.diff.highlights());
+            }
+        }
+        Ok(reloaded)
+    }
+
+    // bakes the scene's sdf primitives into a triangle mesh at
+    // `resolution` cells across, which then takes their place so it can be
+    // collided with and casts shadows like any other model
+    pub fn mesh_primitives(&mut self, resolution: u32) -> Result<()> {
+        let name = format!("csg{}", self.scene.models.len());
+        let baked = csg::mesh(self.renderer.device(), self.renderer.queue(), &self.scene.sdf, resolution, &name)?;
+        self.scene.add_models(vec![baked.model], baked.material, baked.triangles);
+        self.scene.sdf.clear();
+        Ok(())
+    }
+
+    // loads another version of the scene to review against the current
+    // one. models that were added, removed, moved or edited in it are
+    // outlined over both, and `toggle_comparison` switches between them
+    pub fn compare_with(&mut self, path: &str) -> Result<&SceneDiff> {
+        self.stop_comparing();
+        let mut other = Scene::load_with_options(
+            self.renderer.device(), self.renderer.queue(), path, &mut self.assets, self.import)?;
+        apply_quality(&self.renderer, &mut other);
+        let diff = SceneDiff::new(&self.scene, &other);
+        self.renderer.set_highlights(diff.highlights());
+        let comparison = self.comparison.insert(Comparison { other, diff, swapped: false });
+        Ok(&comparison.diff)
+    }
+
+    // sets one of the mesh shaders' custom parameters for a model, with as
+    // many values as the parameter has components
+    pub fn set_param(&mut self, model: usize, name: &str, value: &[f32]) -> Result<()> {
+        let param = self.renderer.shader_params().find(|param| param.name == name)
+            .with_context(|| format!("The mesh shaders have no parameter {}", name))?;
+        if value.len() != param.components {
+            bail!("{} takes {} values, not {}", name, param.components, value.len());
+        }
+        let count = self.scene.models.len();
+        let target = self.scene.models.get_mut(model)
+            .with_context(|| format!("No model {}, the scene has {}", model, count))?;
+        target.params.insert(name.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    // back to zeros, which is what the shader reads for unset parameters
+    pub fn reset_param(&mut self, model: usize, name: &str) {
+        if let Some(target) = self.scene.models.get_mut(model) {
+            target.params.remove(name);
+        }
+    }
+
+    // how a model is drawn, see `ObjectFlags`. saved along with the
+    // parameters
+    pub fn set_flags(&mut self, model: usize, flags: ObjectFlags) -> Result<()> {
+        let count = self.scene.models.len();
+        let target = self.scene.models.get_mut(model)
+            .with_context(|| format!("No model {}, the scene has {}", model, count))?;
+        target.flags = flags;
+        Ok(())
+    }
+
+    // see `Scene::save_params`
+    pub fn save_params(&self) -> Result<PathBuf> {
+        self.scene.save_params()
+    }
+
+    // what the last pick P started found, if it found anything
+    pub fn selection(&self) -> Option<Pick> {
+        self.selection
+    }
+
+    // changes a material's parameters, drawn with from the next frame.
+    // saved with `save_materials`
+    pub fn set_material_factors(&mut self, material: usize, factors: Factors) -> Result<()> {
+        let count = self.scene.materials.len();
+        let target = self.scene.materials.get_mut(material)
+            .with_context(|| format!("No material {}, the scene has {}", material, count))?;
+        target.set_factors(self.renderer.queue(), factors);
+        Ok(())
+    }
+
+    // replaces one of a material's maps, see `material::MAP_NAMES`, with
+    // the image at `path`. saved with `save_materials`
+    pub fn set_material_map(&mut self, material: usize, map: &str, path: &str) -> Result<()> {
+        let count = self.scene.materials.len();
+        let target = self.scene.materials.get_mut(material)
+            .with_context(|| format!("No material {}, the scene has {}", material, count))?;
+        let (device, queue) = (self.renderer.device(), self.renderer.queue());
+        let (slot, texture) = params::load_map(device, queue, &mut self.assets, map, path)?;
+        target.set_map(device, slot, texture, path);
+        Ok(())
+    }
+
+    // see `Scene::save_materials`
+    pub fn save_materials(&self) -> Result<PathBuf> {
+        self.scene.save_materials()
+    }
+
+    // shows the other version of the scene instead of the current one or
+    // back, returning the file now shown. does nothing without a comparison
+    pub fn toggle_comparison(&mut self) -> Option<&str> {
+        let comparison = self.comparison.as_mut()?;
+        std::mem::swap(&mut self.scene, &mut comparison.other);
+        comparison.swapped = !comparison.swapped;
+        self.reset_selection();
+        Some(&self.scene.stats.source)
+    }
+
+    // goes back to the scene shown before `compare_with`
+    pub fn stop_comparing(&mut self) {
+        if let Some(mut comparison) = self.comparison.take() {
+            if comparison.swapped {
+                std::mem::swap(&mut self.scene, &mut comparison.other);
+                self.reset_selection();
+            }
+            self.renderer.set_highlights(vec![]);
+            self.assets.purge();
+        }
+    }
+
+    // what changed in the version being compared against
+    pub fn diff(&self) -> Option<&SceneDiff> {
+        self.comparison.as_ref().map(|comparison| &comparison.diff)
+    }
+
+    // saves the current frame's render targets into a new folder under
+    // the configured `dump_dir`, returning the files written
+    pub fn dump_frame(&mut self) -> Result<Vec<PathBuf>> {
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis());
+        let dir = self.dump_dir.join(format!("frame-{}", millis));
+        self.renderer.dump_frame(&self.scene, &self.camera, &dir)
+    }
+
+    // saves the next frame to `path` as a png without stalling: it's
+    // copied back over the next frames and written on another thread,
+    // which reports where it went
+    pub fn capture_frame<P: Into<PathBuf>>(&mut self, path: P) -> Result<()> {
+        let receiver = self.renderer.capture_frame_async(&self.scene, &self.camera)?;
+        self.captures.push((path.into(), receiver));
+        Ok(())
+    }
+
+    // what's drawn at `x`, `y` in physical pixels, read back without
+    // waiting, see `Renderer::pick`
+    pub fn pick(&mut self, x: u32, y: u32) -> Receiver<Result<Option<Pick>>> {
+        self.renderer.pick(&self.scene, &self.camera, x, y)
+    }
+
+    // the models seen anywhere in the `x`, `y`, `width` by `height` box in
+    // physical pixels, read back without waiting, see
+    // `Renderer::select_region`
+    pub fn select_box(&mut self, x: u32, y: u32, width: u32, height: u32) -> Receiver<Result<Vec<usize>>> {
+        self.renderer.select_region(&self.scene, &self.camera, (x, y, width.max(1), height.max(1)))
+    }
+
+    // the models transformed as a group, sorted
+    pub fn selected(&self) -> &[usize] {
+        self.selected.models()
+    }
+
+    // replaces the group, leaving out locked models
+    pub fn select(&mut self, models: Vec<usize>) -> Result<()> {
+        let count = self.scene.models.len();
+        if let Some(model) = models.iter().find(|&&model| model >= count) {
+            bail!("No model {}, the scene has {}", model, count);
+        }
+        let scene = &self.scene;
+        self.selected.set(models.into_iter().filter(|&model| !scene.models[model].locked).collect());
+        self.renderer.set_selected(self.selected.models().to_vec());
+        Ok(())
+    }
+
+    // moves, turns and scales the selected models around the center of
+    // their bounds, what hangs off them along, as one step that `undo`
+    // takes back. false when nothing moved
+    pub fn transform_selection(&mut self, delta: &Transform) -> bool {
+        self.selected.transform(&mut self.scene, delta)
+    }
+
+    // puts the models of the last group transform back where they were,
+    // false when there's nothing to undo
+    pub fn undo(&mut self) -> bool {
+        self.selected.undo(&mut self.scene)
+    }
+
+    // makes the last undone group transform again
+    pub fn redo(&mut self) -> bool {
+        self.selected.redo(&mut self.scene)
+    }
+
+    // the selection and its history point at models by index, which don't
+    // hold across scenes
+    fn reset_selection(&mut self) {
+        self.selected.reset();
+        self.box_start = None;
+        self.renderer.set_selected(vec![]);
+        self.renderer.set_selection_box(None);
+    }
+
+    // the box from where shift was pressed to the cursor, as x, y, width
+    // and height
+    fn selection_box(&self) -> Option<(u32, u32, u32, u32)> {
+        let (start_x, start_y) = self.box_start?;
+        let cursor = self.cursor?;
+        let (x, y) = (cursor.x.max(0.0) as u32, cursor.y.max(0.0) as u32);
+        Some((start_x.min(x), start_y.min(y), start_x.max(x) - start_x.min(x), start_y.max(y) - start_y.min(y)))
+    }
+
+    // captures the scene around `position` for the reflections of what's
+    // near it, see `Renderer::capture_environment`
+    pub fn capture_environment(&mut self, position: Vec3) -> Result<()> {
+        self.renderer.capture_environment(&self.scene, &self.camera, position)
+    }
+
+    // prints what the picks P started found once they're back, selecting
+    // the last one, or adding it to the group or taking it out for clicks
+    // with control
+    fn report_picks(&mut self) {
+        let scene = &self.scene;
+        let selection = &mut self.selection;
+        let selected = &mut self.selected;
+        self.picks.retain(|(toggle, receiver)| {
+            let pick = match receiver.try_recv() {
+                Ok(pick) => pick,
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            };
+            match pick {
+                Ok(Some(pick)) => {
+                    let model = scene.models.get(pick.model).map_or("?", |model| model.name.as_str());
+                    let material = scene.materials.get(pick.material).map_or("?", |material| material.name.as_str());
+                    if scene.models.get(pick.model).is_some_and(|model| model.locked) {
+                        info!("picked {}, which is locked", model);
+                        return false;
+                    }
+                    if scene.is_instanced(pick.model) {
+                        info!("picked {}, sub-mesh {} ({}), triangle {}, instance {}", model, pick.submesh, material,
+                              pick.triangle, pick.instance);
+                    } else {
+                        info!("picked {}, sub-mesh {} ({}), triangle {}", model, pick.submesh, material, pick.triangle);
+                    }
+                    *selection = Some(pick);
+                    if *toggle {
+                        selected.toggle(pick.model);
+                    } else {
+                        selected.set(vec![pick.model]);
+                    }
+                }
+                Ok(None) => {
+                    info!("picked nothing");
+                    *selection = None;
+                    if !*toggle {
+                        selected.clear();
+                    }
+                }
+                Err(error) => error!("{:#}", error),
+            }
+            false
+        });
+    }
+
+    // selects the models the boxes found once they're back, leaving out
+    // locked ones
+    fn report_boxes(&mut self) {
+        let scene = &self.scene;
+        let selected = &mut self.selected;
+        self.boxes.retain(|(add, receiver)| {
+            let models = match receiver.try_recv() {
+                Ok(models) => models,
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            };
+            match models {
+                Ok(models) => {
+                    let models: Vec<usize> = models.into_iter()
+                        .filter(|&model| scene.models.get(model).is_some_and(|model| !model.locked))
+                        .collect();
+                    info!("boxed in {} models", models.len());
+                    if *add {
+                        selected.add(&models);
+                    } else {
+                        selected.set(models);
+                    }
+                }
+                Err(error) => error!("{:#}", error),
+            }
+            false
+        });
+    }
+
+    // hands the captures the GPU is done with over to be written
+    fn save_captures(&mut self) {
+        self.captures.retain(|(path, receiver)| {
+            let image = match receiver.try_recv() {
+                Ok(image) => image,
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            };
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let saved = image.and_then(|image| {
+                    image.save(&path).with_context(|| format!("Failed to write {}", path.display()))
+                });
+                match saved {
+                    Ok(()) => info!("saved {}", path.display()),
+                    Err(error) => error!("{:#}", error),
+                }
             });
+            false
+        });
+    }
 
-        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &light_bind_group_layout,
-            entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::Buffer(light_buffer.slice(..)),
-                    },
-                ],
-            label: None,
+    pub async fn screenshot(&mut self) -> Result<image::RgbaImage> {
+        self.renderer.screenshot(&self.scene, &self.camera).await
+    }
+
+    pub fn camera_mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    // whether a first-person mode is on, the window should grab the cursor
+    // while it is
+    pub fn grabs_cursor(&self) -> bool {
+        self.mode.grabs_cursor()
+    }
+
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    // whether the stats HUD should be shown. the state only collects the
+    // numbers, drawing or logging them is up to the application
+    pub fn shows_stats(&self) -> bool {
+        self.show_stats
+    }
+
+    pub fn set_show_stats(&mut self, show: bool) {
+        self.show_stats = show;
+    }
+
+    // where the joints of the skeleton overlay land on screen, in pixels
+    // from the top left, for drawing their names. empty while it's off
+    pub fn joint_labels(&self) -> Vec<(&str, [f32; 2])> {
+        if !self.renderer.shows_skeletons() {
+            return vec![];
+        }
+        let view_projection = self.camera.build_view_projection_matrix();
+        let (width, height) = (self.size.width as f32, self.size.height as f32);
+        let mut labels = vec![];
+        for model in &self.scene.models {
+            let skin = match model.skin.as_ref().filter(|_| !model.hidden) {
+                Some(skin) => skin,
+                None => continue,
+            };
+            let matrix = model.transform.matrix();
+            for (index, joint) in skin.joints().iter().enumerate() {
+                let clip = view_projection * matrix.transform_point3(skin.joint_position(index)).extend(1.0);
+                if clip.w <= 0.0 {
+                    continue;
+                }
+                let (x, y) = (clip.x / clip.w, clip.y / clip.w);
+                if x.abs() <= 1.0 && y.abs() <= 1.0 {
+                    labels.push((joint.name.as_str(), [(x * 0.5 + 0.5) * width, (0.5 - y * 0.5) * height]));
+                }
+            }
+        }
+        labels
+    }
+
+    // switches the controller moving the camera, picking up from the
+    // camera's current position
+    pub fn set_camera_mode(&mut self, mode: CameraMode) {
+        self.mode = mode;
+        match mode {
+            CameraMode::Orbit => {}
+            CameraMode::Fly => self.fly.enter(&self.camera),
+            CameraMode::Walk => self.walk.enter(&self.camera),
+        }
+    }
+
+    // switches to a lighting preset, replacing any light set with `set_light`
+    pub fn set_lighting(&mut self, preset: Preset) {
+        self.lighting = preset;
+        self.renderer.set_lighting(preset.lighting());
+    }
+
+    // switches every setting the graphics preset covers, rebuilding what
+    // depends on them
+    pub fn set_quality(&mut self, quality: Quality) -> Result<()> {
+        self.renderer.set_quality(quality)?;
+        self.scene.set_anisotropy(self.renderer.device(), quality.settings().anisotropy);
+        Ok(())
+    }
+
+    // applies the parts of `config` that can change while the viewer runs:
+    // the background's mode and color, the shadow resolution, bloom, fxaa,
+    // ssao, motion blur and the fly and walk cameras' speeds. the rest only
+    // takes effect on the next start. nothing is applied if the shadow
+    // resolution is invalid
+    pub fn apply_config(&mut self, config: &Config) -> Result<()> {
+        let renderer = &config.renderer;
+        // a quality preset overrides the resolution, like at startup
+        let resolution = renderer.quality.map_or(renderer.shadows.resolution, |quality| {
+            quality.settings().shadow_resolution
         });
+        self.renderer.set_shadow_resolution(resolution)?;
+        // the window's alpha comes from the cleared background
+        let mode = if config.window.transparent { BackgroundMode::Transparent } else { renderer.background.mode };
+        self.renderer.set_background_mode(mode);
+        self.renderer.set_background_color(renderer.background.color);
+        self.renderer.set_bloom(renderer.bloom.enabled);
+        self.renderer.set_fxaa(renderer.fxaa);
+        self.renderer.set_ssao(renderer.ssao.enabled);
+        self.renderer.set_motion_blur(renderer.motion_blur.enabled);
+        self.fly.set_speed(config.fly.speed);
+        self.walk.set_speed(config.walk.speed);
+        Ok(())
+    }
+
+    // multisampled antialiasing with 2, 4 or 8 samples, or 1 to turn it off
+    pub fn set_msaa(&mut self, samples: u32) -> Result<()> {
+        self.renderer.set_sample_count(samples)
+    }
+
+    // adds a light next to the sun, returning its index. lights
+    // stay when the lighting preset changes
+    pub fn add_light(&mut self, light: Light) -> usize {
+        self.renderer.add_light(light)
+    }
+
+    // the lights after `index` move down by one
+    pub fn remove_light(&mut self, index: usize) -> Result<Light> {
+        self.renderer.remove_light(index)
+    }
 
+    // whether the viewer used the event. while replaying, the recording
+    // is the only input
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        match InputEvent::from_window_event(event) {
+            Some(event) if self.replay.is_none() => {
+                self.record(event);
+                self.apply(&event)
+            }
+            _ => false,
+        }
+    }
+
+    fn record(&mut self, event: InputEvent) {
+        if let Some(recording) = &mut self.recording {
+            let frame = recording.steps.len() as u64;
+            recording.events.push(RecordedInput { frame, event });
+        }
+    }
+
+    fn apply(&mut self, event: &InputEvent) -> bool {
+        let start = Instant::now();
+        let used = self.handle_input(event);
+        self.input_time += start.elapsed();
+        used
+    }
 
-        let mut bind_group_layouts = vec![&uniform_bind_group_layout];
-        bind_group_layouts.push(&materials[models[0].mesh.material]
-                                .diffuse_texture.bind_group_layout);
-        bind_group_layouts.push(&materials[models[0].mesh.material]
-                                .bind_group_layout);
-        bind_group_layouts.push(&light_bind_group_layout);
-
-        // load shaders
-        let vs_module = device.create_shader_module(
-            wgpu::include_spirv!("shader.vert.spv"));
-        let fs_module = device.create_shader_module(
-            wgpu::include_spirv!("shader.frag.spv"));
-
-        let depth_texture = DepthTexture::new(&device, &sc_desc, Some("depth_texture"));
-        
-
-        // render pipeline
-        let render_pipeline_layout = device.create_pipeline_layout(
-            &wgpu::PipelineLayoutDescriptor {
-                label: Some("render_pipeline_layout"),
-                bind_group_layouts: &bind_group_layouts[..],
-                push_constant_ranges: &[],
-            }
-        );
-
-        let render_pipeline = device.create_render_pipeline(
-            &wgpu::RenderPipelineDescriptor {
-                label: Some("render_pipeline"),
-                layout: Some(&render_pipeline_layout),
-                vertex_stage: wgpu::ProgrammableStageDescriptor {
-                    module: &vs_module,
-                    entry_point: "main",
-                },
-                fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                    module: &fs_module,
-                    entry_point: "main",
-                }),
-                rasterization_state: Some(
-                    wgpu::RasterizationStateDescriptor {
-                        front_face: wgpu::FrontFace::Ccw,
-                        cull_mode: wgpu::CullMode::Back,
-                        depth_bias: 0,
-                        depth_bias_slope_scale: 0.0,
-                        depth_bias_clamp: 0.0,
-                        clamp_depth: false,
+    fn handle_input(&mut self, event: &InputEvent) -> bool {
+        match *event {
+            InputEvent::Key { key, state } => {
+                let pressed = state == ElementState::Pressed;
+                match key {
+                    VirtualKeyCode::LShift | VirtualKeyCode::RShift => self.shift = pressed,
+                    VirtualKeyCode::LControl | VirtualKeyCode::RControl => self.control = pressed,
+                    _ => {}
+                }
+                if self.control && (key == VirtualKeyCode::Z || key == VirtualKeyCode::Y) {
+                    if pressed {
+                        let (undo, done) = if key == VirtualKeyCode::Z && !self.shift {
+                            ("undid", self.undo())
+                        } else {
+                            ("redid", self.redo())
+                        };
+                        if done {
+                            info!("{} a transform of the selection", undo);
+                        }
                     }
-                ),
-                color_states: &[
-                    wgpu::ColorStateDescriptor {
-                        format: sc_desc.format,
-                        color_blend: wgpu::BlendDescriptor::REPLACE,
-                        alpha_blend: wgpu::BlendDescriptor::REPLACE,
-                        write_mask: wgpu::ColorWrite::ALL,
+                    true
+                } else if key == VirtualKeyCode::L {
+                    if state == ElementState::Pressed {
+                        self.set_lighting(self.lighting.next());
                     }
-                ],
-                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
-                depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
-                    format:  wgpu::TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil: wgpu::StencilStateDescriptor::default(),
-                }),
-                vertex_state: wgpu::VertexStateDescriptor {
-                    index_format: wgpu::IndexFormat::Uint32,
-                    vertex_buffers: &[
-                        wgpu::VertexBufferDescriptor {
-                            stride: 32,
-                            step_mode: wgpu::InputStepMode::Vertex,
-                            attributes: &wgpu::vertex_attr_array![0 => Float3,
-                                                                  1 => Float3,
-                                                                  2 => Float2],
-                        },
-                    ],
-                },
-                sample_count: 1,
-                sample_mask: !0,
-                alpha_to_coverage_enabled: false,
-            }
-        );
-
-        // creating buffers;
-        Ok(Self {
-            surface,
-            device,
-            queue,
-            sc_desc,
-            swap_chain,
-            size,
-            render_pipeline,
-            uniform_bind_group,
-            light_bind_group,
-            models,
-            materials,
-            depth_texture,
-        })
+                    true
+                } else if key == VirtualKeyCode::V {
+                    if state == ElementState::Pressed {
+                        self.renderer.set_debug_view(self.renderer.debug_view().next());
+                    }
+                    true
+                } else if key == VirtualKeyCode::B {
+                    if state == ElementState::Pressed {
+                        self.renderer.set_background_mode(self.renderer.background_mode().next());
+                    }
+                    true
+                } else if key == VirtualKeyCode::F {
+                    if state == ElementState::Pressed {
+                        if self.renderer.is_culling_frozen() {
+                            self.renderer.unfreeze_culling();
+                        } else {
+                            self.renderer.freeze_culling(&self.camera);
+                        }
+                    }
+                    true
+                } else if key == VirtualKeyCode::P {
+                    if state == ElementState::Pressed {
+                        if let Some(cursor) = self.cursor {
+                            let receiver = self.pick(cursor.x.max(0.0) as u32, cursor.y.max(0.0) as u32);
+                            self.picks.push((false, receiver));
+                        }
+                    }
+                    true
+                } else if key == VirtualKeyCode::H {
+                    if state == ElementState::Pressed {
+                        match self.capture_environment(self.camera.eye) {
+                            Ok(()) => info!("captured the environment at {:?}", self.camera.eye),
+                            Err(error) => error!("{:#}", error),
+                        }
+                    }
+                    true
+                } else if key == VirtualKeyCode::F1 {
+                    if state == ElementState::Pressed {
+                        self.show_stats = !self.show_stats;
+                    }
+                    true
+                } else if key == VirtualKeyCode::F9 {
+                    if state == ElementState::Pressed {
+                        match self.dump_frame() {
+                            Ok(paths) => for path in paths {
+                                info!("dumped {}", path.display());
+                            },
+                            Err(error) => error!("{:#}", error),
+                        }
+                    }
+                    true
+                } else if key == VirtualKeyCode::G {
+                    if state == ElementState::Pressed {
+                        let quality = self.renderer.quality().map_or(Quality::Low, Quality::next);
+                        match self.set_quality(quality) {
+                            Ok(()) => info!("{:?} quality", quality),
+                            Err(error) => error!("{:#}", error),
+                        }
+                    }
+                    true
+                } else if key == VirtualKeyCode::C && self.comparison.is_some() {
+                    if state == ElementState::Pressed {
+                        if let Some(source) = self.toggle_comparison() {
+                            info!("showing {}", source);
+                        }
+                    }
+                    true
+                } else if key == VirtualKeyCode::M {
+                    if state == ElementState::Pressed {
+                        match self.mesh_primitives(CSG_RESOLUTION) {
+                            Ok(()) => info!("meshed the primitives"),
+                            Err(error) => error!("{:#}", error),
+                        }
+                    }
+                    true
+                } else if key == VirtualKeyCode::R {
+                    if state == ElementState::Pressed {
+                        let deferred = !self.renderer.is_deferred();
+                        self.renderer.set_deferred(deferred);
+                        info!("{} shading", if deferred { "deferred" } else { "forward" });
+                    }
+                    true
+                } else if key == VirtualKeyCode::X {
+                    if state == ElementState::Pressed {
+                        let fxaa = !self.renderer.is_fxaa_enabled();
+                        self.renderer.set_fxaa(fxaa);
+                        info!("fxaa {}", if fxaa { "on" } else { "off" });
+                    }
+                    true
+                } else if key == VirtualKeyCode::N {
+                    if state == ElementState::Pressed {
+                        let view = match self.renderer.minimap_view() {
+                            Some(_) => None,
+                            None => Some(MinimapView::TopDown),
+                        };
+                        self.renderer.set_minimap(view);
+                    }
+                    true
+                } else if key == VirtualKeyCode::K {
+                    if state == ElementState::Pressed {
+                        let show = !self.renderer.shows_skeletons();
+                        self.renderer.set_skeletons(show);
+                    }
+                    true
+                } else if key == VirtualKeyCode::Tab {
+                    if state == ElementState::Pressed {
+                        self.set_camera_mode(self.mode.next());
+                    }
+                    true
+                } else {
+                    match self.mode {
+                        CameraMode::Orbit => false,
+                        CameraMode::Fly => self.fly.process_keyboard(key, state),
+                        CameraMode::Walk => self.walk.process_keyboard(key, state),
+                    }
+                }
+            }
+            InputEvent::CursorMoved { x, y } => {
+                let position = winit::dpi::PhysicalPosition::new(x, y);
+                self.cursor = Some(position);
+                if self.box_start.is_some() {
+                    self.renderer.set_selection_box(self.selection_box());
+                    return true;
+                }
+                let region = self.renderer.nav_cube_region(&self.camera, position);
+                self.renderer.hover_nav_cube(region);
+                self.mode == CameraMode::Orbit && self.orbit.process_events(event)
+            }
+            // with control a click adds the model under the cursor to the
+            // selection or takes it out, with shift dragging boxes models in,
+            // replacing the selection unless control is held too
+            InputEvent::Button { button: MouseButton::Left, state: ElementState::Pressed }
+                if self.shift || self.control => {
+                if let Some(cursor) = self.cursor {
+                    let (x, y) = (cursor.x.max(0.0) as u32, cursor.y.max(0.0) as u32);
+                    if self.shift {
+                        self.box_start = Some((x, y));
+                        self.renderer.set_selection_box(self.selection_box());
+                    } else {
+                        let receiver = self.pick(x, y);
+                        self.picks.push((true, receiver));
+                    }
+                }
+                true
+            }
+            InputEvent::Button { button: MouseButton::Left, state: ElementState::Released }
+                if self.box_start.is_some() => {
+                if let Some((x, y, width, height)) = self.selection_box() {
+                    let receiver = self.select_box(x, y, width, height);
+                    self.boxes.push((self.control, receiver));
+                }
+                self.box_start = None;
+                self.renderer.set_selection_box(None);
+                true
+            }
+            // clicks on the navigation cube or the minimap don't start dragging
+            InputEvent::Button { button: MouseButton::Left, state: ElementState::Pressed } => {
+                let minimap_point = self.cursor
+                    .and_then(|cursor| self.renderer.minimap_point(&self.scene, &self.camera, cursor));
+                if let Some(region) = self.nav_cube_region() {
+                    if self.mode == CameraMode::Orbit {
+                        self.orbit.snap_to(&self.camera, region);
+                    }
+                    true
+                } else if let Some(point) = minimap_point {
+                    self.teleport(point);
+                    true
+                } else {
+                    self.mode == CameraMode::Orbit && self.orbit.process_events(event)
+                }
+            }
+            InputEvent::MouseMotion { dx, dy } => match self.mode {
+                CameraMode::Orbit => false,
+                CameraMode::Fly => {
+                    self.fly.process_mouse(dx, dy);
+                    true
+                }
+                CameraMode::Walk => {
+                    self.walk.process_mouse(dx, dy);
+                    true
+                }
+            },
+            _ => self.mode == CameraMode::Orbit && self.orbit.process_events(event),
+        }
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        self.size = new_size;
-        self.sc_desc.width = new_size.width;
-        self.sc_desc.height = new_size.height;
-        self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
+    // moves the camera to `point`, looking the same way. orbiting centers
+    // on it, flying keeps the height and walking stands on it
+    pub fn teleport(&mut self, point: Vec3) {
+        let offset = match self.mode {
+            CameraMode::Orbit => point - self.camera.center,
+            CameraMode::Fly => Vec3::new(point.x - self.camera.eye.x, 0.0, point.z - self.camera.eye.z),
+            CameraMode::Walk => return self.walk.teleport(&mut self.camera, point),
+        };
+        self.camera.eye += offset;
+        self.camera.center += offset;
     }
 
-    pub fn input(&mut self, event: &WindowEvent) -> bool {
-        false
+    // the part of the navigation cube under the cursor, see
+    // `Renderer::nav_cube_region`
+    fn nav_cube_region(&self) -> Option<Vec3> {
+        self.cursor.and_then(|cursor| self.renderer.nav_cube_region(&self.camera, cursor))
+    }
+
+    // raw mouse movement, used for looking around in the first-person modes
+    pub fn mouse_motion(&mut self, (dx, dy): (f64, f64)) {
+        if self.replay.is_none() {
+            let event = InputEvent::MouseMotion { dx, dy };
+            self.record(event);
+            self.apply(&event);
+        }
+    }
+
+    // records the input from now on, along with how far every update
+    // steps, until `stop_recording`
+    pub fn start_recording(&mut self) {
+        self.recording = Some(InputRecording::new());
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        self.recording.take()
+    }
+
+    // plays `recording` back from now on, ignoring live input until it's
+    // done. the updates step by the recorded times rather than the clock,
+    // so it ends up where the session did as long as it starts from the
+    // same scene, camera and window size
+    pub fn replay(&mut self, recording: InputRecording) {
+        self.replay = Some(Replay { recording, frame: 0, next: 0 });
+        self.replay_events();
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    // applies the events due before the next update, finishing the replay
+    // after the last one
+    fn replay_events(&mut self) {
+        while let Some(event) = self.replay.as_mut().and_then(Replay::next_event) {
+            self.apply(&event);
+        }
+        if matches!(&self.replay, Some(replay) if replay.is_done()) {
+            self.replay = None;
+            info!("replay finished");
+        }
     }
 
+    // steps the camera and time, then animates the skinned models and
+    // streams textures
     pub fn update(&mut self) {
-        
-    }
-
-    pub fn render(&mut self) -> Result<(), wgpu::SwapChainError> {
-        let frame = self.swap_chain.get_current_frame()?.output;
-        let mut encoder = self.device.create_command_encoder(
-            &wgpu::CommandEncoderDescriptor { label: Some("Render Encoder") },
-        );
-        let mut render_pass = encoder
-            .begin_render_pass(&wgpu::RenderPassDescriptor {
-                color_attachments: &[
-                    wgpu::RenderPassColorAttachmentDescriptor {
-                        attachment: &frame.view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color {
-                                r: 0.1,
-                                g: 0.2,
-                                b: 0.3,
-                                a: 1.0,
-                            }),
-                            store: true,
-                        },
+        self.step();
+        self.animate();
+        self.stream_textures();
+    }
+
+    // advances time and the camera without allocating once warmed up, see
+    // `alloc::FrameAudit`
+    pub fn step(&mut self) {
+        let now = Instant::now();
+        // a fixed step in deterministic mode, otherwise the time since the
+        // last update, clamped so a stall doesn't tunnel through the floor
+        let elapsed = (now - self.last_update).as_secs_f32();
+        let mut dt = self.timestep.unwrap_or_else(|| elapsed.min(0.1));
+        // replays step like the recorded updates did
+        if let Some(replay) = &mut self.replay {
+            dt = replay.recording.steps.get(replay.frame as usize).copied().unwrap_or(dt);
+            replay.frame += 1;
+        }
+        self.last_update = now;
+        self.last_step = dt;
+        self.stats.frame_times.push(elapsed);
+        self.renderer.update(dt);
+        self.update_timings.clear();
+        match self.mode {
+            CameraMode::Orbit => self.orbit.update(&mut self.camera, dt),
+            CameraMode::Fly => self.fly.update(&mut self.camera, dt),
+            CameraMode::Walk => self.walk.update(&mut self.camera, &self.scene.collision, dt),
+        }
+        self.update_timings.push(("camera", now.elapsed()));
+    }
+
+    // poses the skinned models for the current time, see `Scene::animate`.
+    // uploads allocate, so it's kept apart from `step`
+    pub fn animate(&mut self) {
+        let start = Instant::now();
+        if self.scene.animate(self.renderer.queue(), self.renderer.time()) > 0 {
+            self.update_timings.push(("animation", start.elapsed()));
+        }
+    }
+
+    // uploads and drops texture mips for the camera with streaming on, see
+    // `Scene::stream_textures`. uploads allocate, so it's kept apart from `step`
+    pub fn stream_textures(&mut self) {
+        if let Some(streaming) = &self.streaming {
+            let start = Instant::now();
+            self.scene.stream_textures(self.renderer.device(), self.renderer.queue(), &self.camera,
+                                       self.size.height, streaming);
+            self.update_timings.push(("streaming", start.elapsed()));
+        }
+    }
+
+    // the animation time, which advances by a fixed step per update in
+    // deterministic mode
+    pub fn time(&self) -> f32 {
+        self.renderer.time()
+    }
+
+    // steps every update by `timestep` seconds from now on, or follows the
+    // clock again with None
+    pub fn set_timestep(&mut self, timestep: Option<f32>) {
+        self.timestep = timestep;
+    }
+
+    // executes the renderer's frame graph, see `Renderer::frame_graph`
+    pub fn render(&mut self) -> Result<()> {
+        let start = Instant::now();
+        let result = self.renderer.render_frame(&self.scene, &self.camera);
+        self.record_frame(start.elapsed());
+        result
+    }
+
+    // renders with `after` drawing on top, see `Renderer::render_frame_with`
+    pub fn render_with<F>(&mut self, after: F) -> Result<()>
+    where
+        F: FnMut(&wgpu::Device, &wgpu::Queue, &mut wgpu::CommandEncoder, &wgpu::TextureView),
+    {
+        let start = Instant::now();
+        let result = self.renderer.render_frame_with(&self.scene, &self.camera, after);
+        self.record_frame(start.elapsed());
+        result
+    }
+
+    // the frame's time broken down into input, update and the renderer's
+    // parts, see `Renderer::profile`
+    fn record_frame(&mut self, cpu_time: Duration) {
+        let paging_time = self.page_tiles();
+        let update_time: Duration = self.update_timings.iter().map(|(_, time)| *time).sum();
+        let busy_time = self.input_time + update_time + cpu_time + paging_time;
+        let profile = &mut self.stats.profile;
+        profile.begin_frame();
+        profile.add("frame", 0, busy_time);
+        profile.add("input", 1, self.input_time);
+        profile.add("update", 1, update_time);
+        for (name, time) in &self.update_timings {
+            profile.add(name, 2, *time);
+        }
+        profile.add("render", 1, cpu_time);
+        self.renderer.profile(profile, 2);
+        if self.tiles.is_some() {
+            profile.add("paging", 1, paging_time);
+        }
+        profile.end_frame();
+        self.input_time = Duration::default();
+        self.stats.cpu_times.push(cpu_time.as_secs_f32());
+        self.stats.busy_times.push(busy_time.as_secs_f32());
+        self.stats.draws = self.renderer.draw_stats();
+        // recordings grow here rather than in `update`, which mustn't
+        // allocate
+        if let Some(recording) = &mut self.recording {
+            recording.steps.push(self.last_step);
+        }
+        self.replay_events();
+        if !self.captures.is_empty() {
+            self.save_captures();
+        }
+        if !self.picks.is_empty() || !self.boxes.is_empty() {
+            self.report_picks();
+            self.report_boxes();
+            self.renderer.set_selected(self.selected.models().to_vec());
+        }
+    }
+
+    // tiles are paged after the frame rather than in `update`, which
+    // mustn't allocate. not while the scene loads, or while comparing,
+    // which swaps scenes
+    fn page_tiles(&mut self) -> Duration {
+        let start = Instant::now();
+        if let Some(tiles) = &mut self.tiles {
+            if self.loading.is_none() && self.comparison.is_none() {
+                let (device, queue) = self.renderer.shared_device();
+                let count = self.scene.models.len();
+                tiles.update(&device, &queue, &mut self.scene, &self.camera);
+                // paging tiles out moves the models after them
+                if self.scene.models.len() != count {
+                    self.reset_selection();
+                }
+            }
+        }
+        start.elapsed()
+    }
+
+    // the tiles paged around the camera, see `TilePager`
+    pub fn tiles(&self) -> Option<&TilePager> {
+        self.tiles.as_ref()
+    }
+
+    // the viewer's tweak panel, for any egui container, e.g.
+    // `egui::Window::new("wr3d").show(ctx, |ui| state.ui(ui))`
+    #[cfg(feature = "ui")]
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Camera", |ui| {
+            let mut mode = self.mode;
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut mode, CameraMode::Orbit, "Orbit");
+                ui.radio_value(&mut mode, CameraMode::Fly, "Fly");
+                ui.radio_value(&mut mode, CameraMode::Walk, "Walk");
+            });
+            if mode != self.mode {
+                self.set_camera_mode(mode);
+            }
+            ui.add(egui::Slider::f32(&mut self.camera.fov, 0.2..=2.5).text("fov"));
+        });
+        ui.collapsing("Lighting", |ui| {
+            let mut preset = self.lighting;
+            for candidate in Preset::ALL.iter() {
+                ui.radio_value(&mut preset, *candidate, format!("{:?}", candidate));
+            }
+            if preset != self.lighting {
+                self.set_lighting(preset);
+            }
+            let mut lighting = *self.renderer.lighting();
+            ui.add(egui::Slider::f32(&mut lighting.exposure, 0.1..=4.0).text("exposure"));
+            ui.add(egui::Slider::f32(&mut lighting.fog_density, 0.0..=0.1).text("fog"));
+            if lighting != *self.renderer.lighting() {
+                self.renderer.set_lighting(lighting);
+            }
+            let mut rotation = self.renderer.environment_rotation();
+            if ui.add(egui::Slider::f32(&mut rotation, -180.0..=180.0).text("environment rotation")).changed() {
+                self.renderer.set_environment_rotation(rotation);
+            }
+            let mut intensity = self.renderer.environment_intensity();
+            if ui.add(egui::Slider::f32(&mut intensity, 0.0..=4.0).text("environment intensity")).changed() {
+                self.renderer.set_environment_intensity(intensity);
+            }
+            ui.label(format!("{} lights besides the sun", self.renderer.lights().len()));
+        });
+        ui.collapsing("Materials", |ui| {
+            for model in 0..self.scene.models.len() {
+                let current = self.scene.overridden_material(model);
+                let mut chosen = current;
+                ui.collapsing(self.scene.models[model].name.clone(), |ui| {
+                    ui.radio_value(&mut chosen, None, "as loaded");
+                    for (index, material) in self.scene.materials.iter().enumerate() {
+                        ui.radio_value(&mut chosen, Some(index), material.name.clone());
+                    }
+                });
+                match chosen {
+                    _ if chosen == current => {}
+                    Some(material) => if let Err(error) = self.scene.override_material(model, material) {
+                        error!("{:#}", error);
                     },
-                ],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
-                    attachment: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
-                }),
+                    None => self.scene.reset_material(model),
+                }
+            }
+        });
+        ui.collapsing("Material editor", |ui| {
+            let material = self.selection.map(|pick| pick.material)
+                .filter(|material| *material < self.scene.materials.len());
+            let material = match material {
+                Some(material) => material,
+                None => {
+                    ui.label("pick an object with P to edit its material");
+                    return;
+                }
+            };
+            ui.label(self.scene.materials[material].name.clone());
+            let current = self.scene.materials[material].factors;
+            let mut factors = current;
+            ui.horizontal(|ui| {
+                ui.label("albedo");
+                for component in &mut factors.albedo {
+                    ui.add(egui::DragValue::f32(component).speed(0.01));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("emissive");
+                for component in &mut factors.emissive {
+                    ui.add(egui::DragValue::f32(component).speed(0.01));
+                }
+            });
+            ui.add(egui::Slider::f32(&mut factors.metallic, 0.0..=1.0).text("metallic"));
+            ui.add(egui::Slider::f32(&mut factors.roughness, 0.0..=1.0).text("roughness"));
+            if factors != current {
+                for component in &mut factors.albedo {
+                    *component = component.clamp(0.0, 1.0);
+                }
+                for component in &mut factors.emissive {
+                    *component = component.max(0.0);
+                }
+                if let Err(error) = self.set_material_factors(material, factors) {
+                    error!("{:#}", error);
+                }
+            }
+            let (slot, path) = &mut self.map_edit;
+            ui.horizontal(|ui| {
+                for (candidate, name) in MAP_NAMES.iter().enumerate() {
+                    ui.radio_value(slot, candidate, *name);
+                }
+            });
+            ui.add(egui::TextEdit::singleline(path));
+            if ui.button("assign map").clicked {
+                let (slot, path) = self.map_edit.clone();
+                match self.set_material_map(material, MAP_NAMES[slot], &path) {
+                    Ok(()) => info!("assigned {} to {}", path, MAP_NAMES[slot]),
+                    Err(error) => error!("{:#}", error),
+                }
+            }
+            if ui.button("save").clicked {
+                match self.save_materials() {
+                    Ok(path) => info!("saved {}", path.display()),
+                    Err(error) => error!("{:#}", error),
+                }
+            }
+        });
+        ui.collapsing("Objects", |ui| {
+            for model in &mut self.scene.models {
+                let flags = &mut model.flags;
+                ui.collapsing(model.name.clone(), |ui| {
+                    ui.checkbox(&mut flags.unlit, "Unlit");
+                    ui.checkbox(&mut flags.cast_shadows, "Casts shadows");
+                    ui.checkbox(&mut flags.receive_shadows, "Receives shadows");
+                    ui.checkbox(&mut flags.shadow_only, "Shadow only");
+                });
+            }
+        });
+        ui.collapsing("Selection", |ui| {
+            if self.selected.models().is_empty() {
+                ui.label("pick objects with control-click or drag a box with shift");
+            }
+            for &model in self.selected.models() {
+                ui.label(self.scene.models[model].name.clone());
+            }
+            let (translation, degrees, scale) = &mut self.selection_edit;
+            ui.horizontal(|ui| {
+                ui.label("move");
+                for component in translation.iter_mut() {
+                    ui.add(egui::DragValue::f32(component).speed(0.01));
+                }
+            });
+            ui.add(egui::Slider::f32(degrees, -180.0..=180.0).text("turn"));
+            ui.add(egui::Slider::f32(scale, 0.1..=10.0).text("scale"));
+            if ui.button("apply").clicked {
+                let (translation, degrees, scale) = self.selection_edit;
+                let delta = Transform {
+                    translation: translation.into(),
+                    rotation: Quat::from_rotation_y(degrees.to_radians()),
+                    scale: Vec3::splat(scale),
+                };
+                if self.transform_selection(&delta) {
+                    self.selection_edit = ([0.0; 3], 0.0, 1.0);
+                }
+            }
+            ui.horizontal(|ui| {
+                if ui.button("undo").clicked {
+                    self.undo();
+                }
+                if ui.button("redo").clicked {
+                    self.redo();
+                }
+            });
+        });
+        ui.collapsing("Skeletons", |ui| {
+            let mut show = self.renderer.shows_skeletons();
+            if ui.checkbox(&mut show, "Show skeletons").changed() {
+                self.renderer.set_skeletons(show);
+            }
+            let current = self.renderer.selected_joint();
+            let mut selected = current;
+            for (index, model) in self.scene.models.iter().enumerate() {
+                let skin = match &model.skin {
+                    Some(skin) => skin,
+                    None => continue,
+                };
+                ui.collapsing(model.name.clone(), |ui| {
+                    let joints = skin.joints();
+                    for (joint, Joint { name, .. }) in joints.iter().enumerate() {
+                        let depth = std::iter::successors(joints[joint].parent, |parent| joints[*parent].parent).count();
+                        ui.radio_value(&mut selected, Some((index, joint)), format!("{}{}", "  ".repeat(depth), name));
+                    }
+                });
+            }
+            if selected != current {
+                self.renderer.set_selected_joint(selected);
+            }
+            let joint = selected.and_then(|(model, joint)| {
+                self.scene.models.get(model)?.skin.as_ref()?.joints().get(joint)
             });
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-        render_pass.set_bind_group(1, &self.materials[self.models[0].mesh.material].diffuse_texture.bind_group, &[]);
-        render_pass.set_bind_group(2, &self.materials[self.models[0].mesh.material].bind_group, &[]);
-        render_pass.set_bind_group(3, &self.light_bind_group, &[]);
-        render_pass.set_vertex_buffer(0, self.models[0].mesh.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.models[0].mesh.index_buffer.slice(..));
-        render_pass.draw_indexed(0..self.models[0].mesh.num_indices, 0, 0..1);
-        drop(render_pass);
-        self.queue.submit(std::iter::once(encoder.finish()));
+            if let Some(Joint { name, local, .. }) = joint {
+                let Transform { translation: t, rotation: r, scale: s } = local;
+                ui.label(format!("{}, relative to its parent", name));
+                ui.label(format!("translation {:.3} {:.3} {:.3}", t.x, t.y, t.z));
+                ui.label(format!("rotation {:.3} {:.3} {:.3} {:.3}", r.x, r.y, r.z, r.w));
+                ui.label(format!("scale {:.3} {:.3} {:.3}", s.x, s.y, s.z));
+            }
+        });
+        ui.collapsing("Parameters", |ui| {
+            let params: Vec<ShaderParam> = self.renderer.shader_params().cloned().collect();
+            if params.is_empty() {
+                ui.label("the mesh shaders declare none");
+                return;
+            }
+            for model in 0..self.scene.models.len() {
+                let mut changes = vec![];
+                ui.collapsing(self.scene.models[model].name.clone(), |ui| {
+                    for param in &params {
+                        let current = self.scene.models[model].params.get(&param.name);
+                        let mut value = current.cloned().unwrap_or_else(|| vec![0.0; param.components]);
+                        value.resize(param.components, 0.0);
+                        ui.horizontal(|ui| {
+                            ui.label(param.name.clone());
+                            for component in &mut value {
+                                ui.add(egui::DragValue::f32(component).speed(0.01));
+                            }
+                        });
+                        if current != Some(&value) && (current.is_some() || value.iter().any(|v| *v != 0.0)) {
+                            changes.push((param.name.clone(), value));
+                        }
+                    }
+                });
+                for (name, value) in changes {
+                    if let Err(error) = self.set_param(model, &name, &value) {
+                        error!("{:#}", error);
+                    }
+                }
+            }
+            if ui.button("save").clicked {
+                match self.save_params() {
+                    Ok(path) => info!("saved {}", path.display()),
+                    Err(error) => error!("{:#}", error),
+                }
+            }
+        });
+        ui.collapsing("Quality", |ui| {
+            let current = self.renderer.quality();
+            let mut quality = current;
+            ui.horizontal(|ui| {
+                for candidate in Quality::ALL.iter() {
+                    ui.radio_value(&mut quality, Some(*candidate), format!("{:?}", candidate));
+                }
+            });
+            if let Some(quality) = quality.filter(|_| quality != current) {
+                if let Err(error) = self.set_quality(quality) {
+                    error!("{:#}", error);
+                }
+            }
+            let mut deferred = self.renderer.is_deferred();
+            if ui.checkbox(&mut deferred, "Deferred shading").changed() {
+                self.renderer.set_deferred(deferred);
+            }
+            if deferred {
+                let mut ssao = self.renderer.is_ssao_enabled();
+                if ui.checkbox(&mut ssao, "Ambient occlusion").changed() {
+                    self.renderer.set_ssao(ssao);
+                }
+                let mut radius = self.renderer.ssao_radius();
+                if ui.add(egui::Slider::f32(&mut radius, 0.05..=2.0).text("occlusion radius")).changed() {
+                    self.renderer.set_ssao_radius(radius);
+                }
+                let mut intensity = self.renderer.ssao_intensity();
+                if ui.add(egui::Slider::f32(&mut intensity, 0.0..=2.0).text("occlusion intensity")).changed() {
+                    self.renderer.set_ssao_intensity(intensity);
+                }
+            }
+            let mut clustered = self.renderer.is_clustered();
+            if ui.checkbox(&mut clustered, "Clustered lights").changed() {
+                self.renderer.set_clustered(clustered);
+            }
+            let mut motion_blur = self.renderer.is_motion_blur_enabled();
+            ui.checkbox(&mut motion_blur, "Motion blur");
+            if motion_blur != self.renderer.is_motion_blur_enabled() {
+                self.renderer.set_motion_blur(motion_blur);
+            }
+            if motion_blur {
+                let mut shutter = self.renderer.motion_blur_shutter();
+                if ui.add(egui::Slider::f32(&mut shutter, 0.0..=2.0).text("shutter")).changed() {
+                    self.renderer.set_motion_blur_shutter(shutter);
+                }
+                let mut samples = self.renderer.motion_blur_samples() as f32;
+                if ui.add(egui::Slider::f32(&mut samples, 2.0..=32.0).text("blur samples")).changed() {
+                    self.renderer.set_motion_blur_samples(samples.round() as u32);
+                }
+            }
+            let mut fxaa = self.renderer.is_fxaa_enabled();
+            if ui.checkbox(&mut fxaa, "FXAA").changed() {
+                self.renderer.set_fxaa(fxaa);
+            }
+            if let Some(current) = self.renderer.tonemap() {
+                let mut tonemap = current;
+                ui.horizontal(|ui| {
+                    for candidate in Tonemap::ALL.iter() {
+                        ui.radio_value(&mut tonemap, *candidate, format!("{:?}", candidate));
+                    }
+                });
+                if tonemap != current {
+                    self.renderer.set_tonemap(tonemap);
+                }
+                let mut exposure = self.renderer.tonemap_exposure();
+                if ui.add(egui::Slider::f32(&mut exposure, 0.1..=4.0).text("tonemap exposure")).changed() {
+                    self.renderer.set_tonemap_exposure(exposure);
+                }
+                let mut bloom = self.renderer.is_bloom_enabled();
+                if ui.checkbox(&mut bloom, "Bloom").changed() {
+                    self.renderer.set_bloom(bloom);
+                }
+                let mut threshold = self.renderer.bloom_threshold();
+                if ui.add(egui::Slider::f32(&mut threshold, 0.0..=4.0).text("bloom threshold")).changed() {
+                    self.renderer.set_bloom_threshold(threshold);
+                }
+                let mut intensity = self.renderer.bloom_intensity();
+                if ui.add(egui::Slider::f32(&mut intensity, 0.0..=1.0).text("bloom intensity")).changed() {
+                    self.renderer.set_bloom_intensity(intensity);
+                }
+            }
+        });
+        ui.collapsing("Debug", |ui| {
+            let mut view = self.renderer.debug_view();
+            for candidate in DebugView::ALL.iter() {
+                ui.radio_value(&mut view, *candidate, format!("{:?}", candidate));
+            }
+            if view != self.renderer.debug_view() {
+                self.renderer.set_debug_view(view);
+            }
+            let mut background = self.renderer.background_mode();
+            for candidate in BackgroundMode::ALL.iter() {
+                ui.radio_value(&mut background, *candidate, format!("{:?}", candidate));
+            }
+            if background != self.renderer.background_mode() {
+                self.renderer.set_background_mode(background);
+            }
+        });
+    }
+
+    // the models as a tree of what hangs off what: expanding and
+    // collapsing, renaming, hiding and locking, and dragging a model by its
+    // handle onto another to hang it off that one, or onto the line below
+    // the tree to move it to the top level
+    #[cfg(feature = "ui")]
+    pub fn outliner(&mut self, ui: &mut egui::Ui) {
+        let (released, pointer) = (ui.input().mouse.released, ui.input().mouse.pos);
+        let scene = &self.scene;
+        let (drag, open) = (&mut self.outliner_drag, &mut self.outliner_open);
+        let mut edits = vec![];
+        // depth first, in the scene's order
+        let mut pending: Vec<(usize, usize)> = scene.children(None).map(|model| (model, 0)).collect();
+        pending.reverse();
+        while let Some((model, depth)) = pending.pop() {
+            let node = &scene.models[model];
+            let children: Vec<usize> = scene.children(Some(model)).collect();
+            let expanded = open.contains(&model);
+            ui.horizontal(|ui| {
+                ui.label("    ".repeat(depth));
+                if children.is_empty() {
+                    ui.label("   ");
+                } else if ui.button(if expanded { "-" } else { "+" }).clicked && !open.remove(&model) {
+                    open.insert(model);
+                }
+                let mut visible = !node.hidden;
+                if ui.checkbox(&mut visible, "").changed() {
+                    edits.push(OutlinerEdit::Hide(model, !visible));
+                }
+                let mut locked = node.locked;
+                if ui.checkbox(&mut locked, "lock").changed() {
+                    edits.push(OutlinerEdit::Lock(model, locked));
+                }
+                let handle = ui.button("move");
+                if handle.active && drag.is_none() {
+                    *drag = Some(model);
+                }
+                let mut name = node.name.clone();
+                let row = ui.add(egui::TextEdit::singleline(&mut name));
+                if name != node.name {
+                    edits.push(OutlinerEdit::Rename(model, name));
+                }
+                let over = pointer.is_some_and(|pointer| row.rect.contains(pointer) || handle.rect.contains(pointer));
+                if let Some(dragged) = drag.filter(|&dragged| released && over && dragged != model) {
+                    edits.push(OutlinerEdit::Reparent(dragged, Some(model)));
+                }
+            });
+            if expanded {
+                pending.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+            }
+        }
+        let top = ui.label("drop here for the top level");
+        if let Some(dragged) = drag.filter(|_| released && pointer.is_some_and(|pointer| top.rect.contains(pointer))) {
+            edits.push(OutlinerEdit::Reparent(dragged, None));
+        }
+        if released {
+            *drag = None;
+        }
+        for edit in edits {
+            match edit {
+                OutlinerEdit::Rename(model, name) => self.scene.models[model].name = name,
+                OutlinerEdit::Hide(model, hidden) => self.scene.set_hidden(model, hidden),
+                OutlinerEdit::Lock(model, locked) => self.scene.set_locked(model, locked),
+                OutlinerEdit::Reparent(model, parent) => {
+                    if let Err(error) = self.scene.set_parent(model, parent) {
+                        error!("{:#}", error);
+                    } else if let Some(parent) = parent {
+                        self.outliner_open.insert(parent);
+                    }
+                }
+            }
+        }
+    }
+
+    // the skinned models' clips: which one plays, play and pause, scrubbing,
+    // speed and a region to loop, for any egui container like `ui`
+    #[cfg(feature = "ui")]
+    pub fn timeline(&mut self, ui: &mut egui::Ui) {
+        for model in &mut self.scene.models {
+            let skin = match &mut model.skin {
+                Some(skin) => skin,
+                None => continue,
+            };
+            ui.collapsing(model.name.clone(), |ui| {
+                let current = skin.animator.current().map(|playback| playback.clip);
+                let mut clip = current;
+                for (index, animation) in skin.animations.iter().enumerate() {
+                    ui.radio_value(&mut clip, Some(index), animation.name.clone());
+                }
+                if let Some(clip) = clip.filter(|_| clip != current) {
+                    skin.animator.play(clip, TIMELINE_FADE);
+                }
+                let paused = skin.animator.paused;
+                if ui.button(if paused { "play" } else { "pause" }).clicked {
+                    skin.animator.paused = !paused;
+                }
+                let animator = &mut skin.animator;
+                let playback = match animator.current_mut() {
+                    Some(playback) => playback,
+                    None => return,
+                };
+                let duration = skin.animations.get(playback.clip).map_or(0.0, |animation| animation.duration);
+                ui.add(egui::Slider::f32(&mut playback.time, 0.0..=duration).text("time"));
+                ui.add(egui::Slider::f32(&mut playback.speed, -2.0..=2.0).text("speed"));
+                let mut looping = playback.region.is_some();
+                if ui.checkbox(&mut looping, "loop region").changed() {
+                    playback.region = if looping { Some((0.0, duration)) } else { None };
+                }
+                if let Some((start, end)) = &mut playback.region {
+                    ui.add(egui::Slider::f32(start, 0.0..=duration).text("from"));
+                    ui.add(egui::Slider::f32(end, 0.0..=duration).text("to"));
+                    *start = start.min(*end);
+                }
+            });
+        }
+    }
+
+    // reads back the last rendered frame of a headless state
+    pub async fn capture(&self) -> Result<image::RgbaImage> {
+        self.renderer.capture().await
+    }
+}
+
+// the preset's anisotropy for a scene whose maps are as they were loaded
+fn apply_quality(renderer: &Renderer, scene: &mut Scene) {
+    if let Some(quality) = renderer.quality() {
+        scene.set_anisotropy(renderer.device(), quality.settings().anisotropy);
+    }
+}
+
+// a change made in the outliner, applied once it's drawn
+#[cfg(feature = "ui")]
+enum OutlinerEdit {
+    Rename(usize, String),
+    Hide(usize, bool),
+    Lock(usize, bool),
+    Reparent(usize, Option<usize>),
+}
+
+impl FrameStats {
+    fn new(budget: f32) -> Self {
+        Self {
+            frame_times: Samples::new(),
+            cpu_times: Samples::new(),
+            busy_times: Samples::new(),
+            budget,
+            profile: Profile::new(),
+            draws: DrawStats::default(),
+        }
+    }
+
+    // average seconds per frame
+    pub fn frame_time(&self) -> f32 {
+        self.frame_times.average()
+    }
+
+    pub fn fps(&self) -> f32 {
+        let frame_time = self.frame_time();
+        if frame_time > 0.0 { 1.0 / frame_time } else { 0.0 }
+    }
+
+    // average seconds the CPU spent on a frame, the rest of the frame
+    // time is waiting on the GPU, vsync or the event loop
+    pub fn cpu_time(&self) -> f32 {
+        self.cpu_times.average()
+    }
+
+    // the seconds a frame should keep the CPU busy at most, from
+    // `StatsConfig`
+    pub fn budget(&self) -> f32 {
+        self.budget
+    }
+
+    pub fn set_budget(&mut self, budget: f32) {
+        self.budget = budget;
+    }
+
+    // how many of the last frames kept the CPU busy for longer than the
+    // budget
+    pub fn frames_over_budget(&self) -> usize {
+        self.busy_times.count_above(self.budget)
+    }
+
+    // the last frames' time by system, see `State::record_frame`
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    // the HUD's lines, each with how its time compares to the budget
+    pub fn lines(&self) -> Vec<(String, Budget)> {
+        let mut lines = vec![(
+            format!("{:.0} fps, {:.2} ms frame, {:.2} ms cpu, {} draws, {} triangles",
+                    self.fps(), self.frame_time() * 1000.0, self.cpu_time() * 1000.0,
+                    self.draws.draw_calls, self.draws.triangles),
+            Budget::Within,
+        )];
+        let over = self.frames_over_budget();
+        lines.push((
+            format!("{:.2} ms budget, {} of the last {} frames over",
+                    self.budget * 1000.0, over, self.busy_times.len()),
+            if over > 0 { Budget::Over } else { Budget::Within },
+        ));
+        for entry in self.profile.entries() {
+            lines.push((
+                format!("{:indent$}{} {:.2} ms", "", entry.name, entry.seconds * 1000.0, indent = entry.depth * 2),
+                Budget::of(entry.seconds, self.budget),
+            ));
+        }
+        lines
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (line, budget)) in self.lines().into_iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", line)?;
+            match budget {
+                Budget::Within => {}
+                Budget::Near => write!(f, " (near budget)")?,
+                Budget::Over => write!(f, " (over budget)")?,
+            }
+        }
         Ok(())
     }
 }
+
+impl Samples {
+    fn new() -> Self {
+        Self { values: [0.0; STATS_FRAMES], count: 0 }
+    }
+
+    fn push(&mut self, value: f32) {
+        self.values[self.count % STATS_FRAMES] = value;
+        self.count += 1;
+    }
+
+    fn len(&self) -> usize {
+        self.count.min(STATS_FRAMES)
+    }
+
+    fn average(&self) -> f32 {
+        let len = self.len();
+        if len == 0 {
+            return 0.0;
+        }
+        self.values[..len].iter().sum::<f32>() / len as f32
+    }
+
+    fn count_above(&self, threshold: f32) -> usize {
+        self.values[..self.len()].iter().filter(|value| **value > threshold).count()
+    }
+}
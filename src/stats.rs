@@ -0,0 +1,167 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+use crate::material::Material;
+use crate::mesh::Model;
+use crate::texture::Texture;
+
+// what a loaded scene costs, per model and texture, and where the loading
+// time went. printed by the viewer after every load
+#[derive(Debug, Clone, Default)]
+pub struct SceneStats {
+    pub source: String,
+    pub models: Vec<ModelStats>,
+    pub materials: usize,
+    pub textures: Vec<TextureStats>,
+    pub stages: Vec<(&'static str, Duration)>,
+    // what was skipped or replaced because it failed to load, and why
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelStats {
+    pub name: String,
+    pub vertices: u32,
+    pub indices: u32,
+    // distinct materials its parts use
+    pub materials: usize,
+    pub missing_normals: bool,
+    pub missing_tex_coords: bool,
+}
+
+impl ModelStats {
+    pub fn new(model: &Model) -> Self {
+        let mut materials: Vec<usize> = model.mesh.submeshes.iter().map(|submesh| submesh.material).collect();
+        materials.sort_unstable();
+        materials.dedup();
+        Self {
+            name: model.name.clone(),
+            vertices: model.mesh.num_vertices,
+            indices: model.mesh.num_indices,
+            materials: materials.len(),
+            missing_normals: false,
+            missing_tex_coords: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TextureStats {
+    pub material: String,
+    pub map: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub bytes: u64,
+    // the image is another map's listed before, and only counted there
+    pub shared: bool,
+}
+
+impl TextureStats {
+    // the maps a material loaded from files, leaving out the single texel
+    // stand-ins for maps it doesn't have
+    pub fn for_material(material: &Material) -> Vec<Self> {
+        Self::for_materials(std::slice::from_ref(material))
+    }
+
+    // the same for several materials, marking maps that share an image
+    pub fn for_materials(materials: &[Material]) -> Vec<Self> {
+        let mut seen: Vec<&Texture> = vec![];
+        let mut stats = vec![];
+        for material in materials {
+            let maps = [
+                ("albedo", &material.albedo_texture),
+                ("metallic_roughness", &material.metallic_roughness_texture),
+                ("bump", &material.bump_texture),
+                ("dissolve", &material.dissolve_texture),
+                ("normal", &material.normal_texture),
+                ("occlusion", &material.occlusion_texture),
+                ("emissive", &material.emissive_texture),
+            ];
+            for (map, texture) in maps.iter().filter(|(_, texture)| texture.size.width * texture.size.height > 1) {
+                let shared = seen.iter().any(|other| other.shares_image(texture));
+                if !shared {
+                    seen.push(texture);
+                }
+                stats.push(Self {
+                    material: material.name.clone(),
+                    map,
+                    width: texture.size.width,
+                    height: texture.size.height,
+                    // every map is uploaded as 8 bit rgba without mipmaps
+                    bytes: texture.size.width as u64 * texture.size.height as u64 * 4,
+                    shared,
+                });
+            }
+        }
+        stats
+    }
+}
+
+// times consecutive stages of loading
+pub(crate) struct StageTimer {
+    start: Instant,
+    pub stages: Vec<(&'static str, Duration)>,
+}
+
+impl StageTimer {
+    pub fn new() -> Self {
+        Self { start: Instant::now(), stages: vec![] }
+    }
+
+    // ends the stage running since the last call
+    pub fn stage(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.stages.push((name, now - self.start));
+        self.start = now;
+    }
+}
+
+fn plural(count: usize, word: &str) -> String {
+    format!("{} {}{}", count, word, if count == 1 { "" } else { "s" })
+}
+
+fn bytes(bytes: u64) -> String {
+    if bytes >= 1 << 20 {
+        format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+    } else {
+        format!("{:.1} KiB", bytes as f64 / (1 << 10) as f64)
+    }
+}
+
+impl fmt::Display for SceneStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let vertices: u64 = self.models.iter().map(|model| model.vertices as u64).sum();
+        let indices: u64 = self.models.iter().map(|model| model.indices as u64).sum();
+        let uploaded: Vec<&TextureStats> = self.textures.iter().filter(|texture| !texture.shared).collect();
+        let texture_bytes: u64 = uploaded.iter().map(|texture| texture.bytes).sum();
+        writeln!(f, "{}: {}, {} vertices, {} triangles, {}, {} ({})",
+                 self.source, plural(self.models.len(), "model"), vertices, indices / 3,
+                 plural(self.materials, "material"), plural(uploaded.len(), "texture"),
+                 bytes(texture_bytes))?;
+        for model in &self.models {
+            write!(f, "  model {}: {} vertices, {} triangles, {}",
+                   model.name, model.vertices, model.indices / 3, plural(model.materials, "material"))?;
+            if model.missing_normals {
+                write!(f, ", no normals")?;
+            }
+            if model.missing_tex_coords {
+                write!(f, ", no uvs")?;
+            }
+            writeln!(f)?;
+        }
+        for texture in &self.textures {
+            write!(f, "  texture {} {}: {}x{}, {}",
+                   texture.material, texture.map, texture.width, texture.height, bytes(texture.bytes))?;
+            if texture.shared {
+                write!(f, ", shared")?;
+            }
+            writeln!(f)?;
+        }
+        for problem in &self.problems {
+            writeln!(f, "  problem: {}", problem)?;
+        }
+        let stages: Vec<String> = self.stages.iter()
+            .map(|(name, duration)| format!("{} {:.1} ms", name, duration.as_secs_f64() * 1000.0))
+            .collect();
+        write!(f, "  loaded in {}", stages.join(", "))
+    }
+}
@@ -0,0 +1,183 @@
+use image::imageops::{self, FilterType};
+use std::collections::HashMap;
+use std::sync::Arc;
+use wgpu;
+use crate::assets::Handle;
+use crate::camera::Camera;
+use crate::config::StreamingConfig;
+use crate::culling::Frustum;
+use crate::scene::Scene;
+use crate::stats::TextureStats;
+use crate::texture::Texture;
+
+// a streamed texture's image and every mip below it, halved down to a
+// single texel, kept on the CPU so any of them can be uploaded later
+pub struct MipChain {
+    levels: Vec<image::RgbaImage>,
+    format: wgpu::TextureFormat,
+    label: Option<String>,
+}
+
+impl MipChain {
+    pub fn new(image: image::RgbaImage, format: wgpu::TextureFormat, label: Option<&str>) -> Self {
+        let mut levels = vec![image];
+        loop {
+            let (width, height) = levels[levels.len() - 1].dimensions();
+            if width == 1 && height == 1 {
+                break;
+            }
+            let next = imageops::resize(
+                &levels[levels.len() - 1], (width / 2).max(1), (height / 2).max(1), FilterType::Triangle);
+            levels.push(next);
+        }
+        Self { levels, format, label: label.map(str::to_string) }
+    }
+
+    pub fn level_count(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    pub fn level(&self, level: u32) -> &image::RgbaImage {
+        &self.levels[level as usize]
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    // the first level no more than `size` texels across
+    pub fn level_for_size(&self, size: u32) -> u32 {
+        let (width, height) = self.levels[0].dimensions();
+        let mut level = 0;
+        while level + 1 < self.level_count() && (width >> level).max(height >> level) > size {
+            level += 1;
+        }
+        level
+    }
+
+    // what the levels from `first` down take on the GPU
+    pub fn bytes_from(&self, first: u32) -> u64 {
+        self.levels[first as usize..].iter()
+            .map(|level| u64::from(level.width()) * u64::from(level.height()) * 4)
+            .sum()
+    }
+}
+
+// one streamed image, however many maps share it
+struct Stream {
+    mips: Arc<MipChain>,
+    // the first level on the GPU now
+    first: u32,
+    // the first level its models need on screen
+    wanted: u32,
+    // the coarsest level it's kept at, what it was uploaded with
+    coarsest: u32,
+}
+
+// picks the mip level every streamed texture of `scene` needs from how
+// large its models' uv space appears on screen, then uploads finer levels
+// or drops unneeded ones, see `Scene::stream_textures`
+pub(crate) fn stream(scene: &mut Scene, device: &wgpu::Device, queue: &wgpu::Queue, camera: &Camera,
+                     viewport_height: u32, config: &StreamingConfig) {
+    let mut streams: HashMap<*const MipChain, Stream> = HashMap::new();
+    for material in &scene.materials {
+        for texture in material.textures().iter() {
+            if let Some((mips, first)) = &texture.mips {
+                let coarsest = mips.level_for_size(config.initial_size);
+                streams.entry(Arc::as_ptr(mips)).or_insert_with(|| Stream {
+                    mips: mips.clone(),
+                    first: *first,
+                    wanted: coarsest,
+                    coarsest,
+                });
+            }
+        }
+    }
+    if streams.is_empty() {
+        return;
+    }
+
+    // the uv units one pixel spans is the model's uv density times the
+    // world units it spans, which grow linearly with the distance
+    let frustum = Frustum::from_matrix(camera.build_view_projection_matrix());
+    let pixels_per_unit = viewport_height as f32 / (2.0 * (camera.fov * 0.5).tan());
+    let instanced: Vec<usize> = scene.instances.iter().map(|group| group.model).collect();
+    for draw in &scene.draws {
+        let model = &scene.models[draw.model];
+        let bounds = model.world_bounds();
+        // the copies of instanced models aren't known on the CPU
+        let instanced = instanced.contains(&draw.model);
+        if !instanced && !frustum.intersects(&bounds) {
+            continue;
+        }
+        let closest = camera.eye.max(bounds.min).min(bounds.max);
+        let distance = (closest - camera.eye).length().max(camera.z_near);
+        let scale = model.transform.scale.max_element().max(f32::EPSILON);
+        let uv_per_pixel = model.mesh.uv_density / scale * distance / pixels_per_unit;
+        for texture in scene.materials[draw.material].textures().iter() {
+            let stream = match &texture.mips {
+                Some((mips, _)) => streams.get_mut(&Arc::as_ptr(mips)).unwrap(),
+                None => continue,
+            };
+            // unknown densities get everything
+            let level = if instanced || uv_per_pixel <= 0.0 {
+                0
+            } else {
+                let (width, height) = stream.mips.level(0).dimensions();
+                let texels_per_pixel = width.max(height) as f32 * uv_per_pixel;
+                texels_per_pixel.max(1.0).log2().floor() as u32
+            };
+            stream.wanted = stream.wanted.min(level);
+        }
+    }
+
+    // over the budget, the largest levels wanted go first
+    let budget = u64::from(config.budget) * 1024 * 1024;
+    let mut total: u64 = streams.values().map(|stream| stream.mips.bytes_from(stream.wanted)).sum();
+    while total > budget {
+        let largest = streams.values_mut()
+            .filter(|stream| stream.wanted < stream.coarsest)
+            .max_by_key(|stream| stream.mips.bytes_from(stream.wanted) - stream.mips.bytes_from(stream.wanted + 1));
+        match largest {
+            Some(stream) => {
+                total -= stream.mips.bytes_from(stream.wanted) - stream.mips.bytes_from(stream.wanted + 1);
+                stream.wanted += 1;
+            }
+            None => break,
+        }
+    }
+
+    // dropping levels only frees memory, finer ones are limited per frame
+    // with the furthest from what's wanted first
+    let mut changes: Vec<&Stream> = streams.values().filter(|stream| stream.wanted > stream.first).collect();
+    let mut finer: Vec<&Stream> = streams.values().filter(|stream| stream.wanted < stream.first).collect();
+    finer.sort_by_key(|stream| stream.wanted as i64 - stream.first as i64);
+    changes.extend(finer.into_iter().take(config.uploads_per_frame));
+    if changes.is_empty() {
+        return;
+    }
+    let replacements: Vec<Texture> = changes.iter()
+        .map(|stream| Texture::from_mips(device, queue, stream.mips.clone(), stream.wanted))
+        .collect();
+    for material in &mut scene.materials {
+        let mut replaced = false;
+        for texture in material.textures_mut() {
+            let mips = match &texture.mips {
+                Some((mips, _)) => Arc::as_ptr(mips),
+                None => continue,
+            };
+            if let Some(index) = changes.iter().position(|stream| Arc::as_ptr(&stream.mips) == mips) {
+                *texture = Handle::new(replacements[index].with_sampler(device, texture.sampler_desc));
+                replaced = true;
+            }
+        }
+        if replaced {
+            material.rebind_textures(device);
+        }
+    }
+    scene.stats.textures = TextureStats::for_materials(&scene.materials);
+}
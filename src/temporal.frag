@@ -0,0 +1,54 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+layout(location=1) out vec4 f_history;
+
+layout(set=0, binding=0) uniform texture2D t_color;
+layout(set=0, binding=1) uniform sampler s_color;
+layout(set=0, binding=2)
+  uniform Upscale {
+  // from this frame's unjittered clip space to the previous frame's
+  mat4 u_reproject;
+  // this frame's projection offset, in uv units
+  vec2 u_jitter;
+  vec2 u_input_size;
+  float u_sharpness;
+  // 0 when there's no usable history, e.g. on the first frame
+  float u_history_weight;
+};
+layout(set=0, binding=3) uniform texture2D t_depth;
+layout(set=0, binding=4) uniform texture2D t_history;
+
+// accumulates jittered low resolution frames into a full resolution
+// history, reprojected through the depth buffer and clamped to the current
+// neighborhood so stale history doesn't ghost
+void main() {
+  vec2 uv = v_tex_coord + u_jitter;
+  vec3 current = texture(sampler2D(t_color, s_color), uv).rgb;
+
+  ivec2 texel = ivec2(uv * u_input_size);
+  ivec2 last = ivec2(u_input_size) - 1;
+  vec3 low = current;
+  vec3 high = current;
+  for (int y = -1; y <= 1; y++) {
+    for (int x = -1; x <= 1; x++) {
+      vec3 neighbor = texelFetch(sampler2D(t_color, s_color), clamp(texel + ivec2(x, y), ivec2(0), last), 0).rgb;
+      low = min(low, neighbor);
+      high = max(high, neighbor);
+    }
+  }
+
+  float depth = texelFetch(sampler2D(t_depth, s_color), clamp(texel, ivec2(0), last), 0).r;
+  vec4 previous = u_reproject * vec4(v_tex_coord.x * 2.0 - 1.0, 1.0 - v_tex_coord.y * 2.0, depth, 1.0);
+  vec2 history_uv = previous.xy / previous.w * vec2(0.5, -0.5) + 0.5;
+  float weight = u_history_weight;
+  if (any(lessThan(history_uv, vec2(0.0))) || any(greaterThan(history_uv, vec2(1.0)))) {
+    weight = 0.0;
+  }
+  vec3 history = clamp(texture(sampler2D(t_history, s_color), history_uv).rgb, low, high);
+
+  vec3 color = mix(current, history, weight);
+  f_history = vec4(color, 1.0);
+  f_color = vec4(color, 1.0);
+}
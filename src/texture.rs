@@ -1,19 +1,123 @@
 use image::io::Reader as ImageReader;
 use anyhow::Result;
+use std::num::NonZeroU8;
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
 use wgpu;
+use crate::dump;
+use crate::readback::{self, Readbacks};
+use crate::streaming::MipChain;
 
 pub struct Texture {
-    texture: wgpu::Texture,
+    // shared by copies with other samplers
+    texture: Arc<wgpu::Texture>,
+    // the size of the largest level on the GPU
+    pub size: wgpu::Extent3d,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
-    pub bind_group_layout: wgpu::BindGroupLayout,
-    pub bind_group: wgpu::BindGroup,
+    pub sampler_desc: SamplerDesc,
+    // the whole image and its mips on the CPU for streamed textures, see
+    // `streaming`, and the first of its levels on the GPU
+    pub(crate) mips: Option<(Arc<MipChain>, u32)>,
+}
+
+// how a texture is filtered and what happens outside 0..1. the default is
+// the unfiltered, clamped sampling textures always had
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerDesc {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    // samples taken at grazing angles, 1 (off), 2, 4, 8 or 16. adapters
+    // without anisotropic filtering ignore it
+    pub anisotropy: u8,
+    pub lod_min_clamp: f32,
+    pub lod_max_clamp: f32,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            anisotropy: 1,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: f32::MAX,
+        }
+    }
+}
+
+impl SamplerDesc {
+    // bilinear filtering, with trilinear across mip levels
+    pub fn linear() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Self::default()
+        }
+    }
+
+    // linear filtering with `anisotropy` samples, which has to be a power
+    // of two up to 16
+    pub fn anisotropic(anisotropy: u8) -> Self {
+        Self { anisotropy, ..Self::linear() }
+    }
+
+    // tiles the texture in both directions instead of clamping to its edges
+    pub fn repeat(self) -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            ..self
+        }
+    }
+
+    pub fn create(&self, device: &wgpu::Device, label: Option<&str>) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            lod_min_clamp: self.lod_min_clamp,
+            lod_max_clamp: self.lod_max_clamp,
+            anisotropy_clamp: NonZeroU8::new(self.anisotropy).filter(|anisotropy| anisotropy.get() > 1),
+            ..Default::default()
+        })
+    }
 }
 
 impl Texture {
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, path: &str, label: Option<&str>) -> Result<Self> {
-        let image = ImageReader::open(path)?.decode()?;
-        let rgba = image.into_rgba8();
+    // color textures should be Rgba8UnormSrgb, data like bump or alpha maps
+    // Rgba8Unorm so they're sampled without the srgb conversion
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, path: &str,
+               format: wgpu::TextureFormat, label: Option<&str>) -> Result<Self> {
+        Ok(Self::from_image(device, queue, &Self::load_image(path)?, format, label))
+    }
+
+    // decodes an image file to 8 bit rgba, for maps that are looked at
+    // before they're uploaded
+    pub fn load_image(path: &str) -> Result<image::RgbaImage> {
+        Ok(ImageReader::open(path)?.decode()?.into_rgba8())
+    }
+
+    // a single texel, used in place of maps a material doesn't have
+    pub fn from_color(device: &wgpu::Device, queue: &wgpu::Queue, color: [u8; 4],
+                      format: wgpu::TextureFormat, label: Option<&str>) -> Self {
+        let image = image::RgbaImage::from_pixel(1, 1, image::Rgba(color));
+        Self::from_image(device, queue, &image, format, label)
+    }
+
+    pub fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, rgba: &image::RgbaImage,
+                      format: wgpu::TextureFormat, label: Option<&str>) -> Self {
         let dimensions = rgba.dimensions();
         let size = wgpu::Extent3d {
             width: dimensions.0,
@@ -25,8 +129,9 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+            format,
+            // read back to bake it into a pack
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST | wgpu::TextureUsage::COPY_SRC,
             label,
         });
         queue.write_texture(
@@ -35,91 +140,130 @@ impl Texture {
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            &rgba,
+            rgba,
             wgpu::TextureDataLayout {
                 offset: 0,
                 bytes_per_row: 4 * dimensions.0,
-                rows_per_image: 4 * dimensions.1,
+                rows_per_image: dimensions.1,
             },
             size,
         );
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
+        let sampler_desc = SamplerDesc::default();
+        let sampler = sampler_desc.create(device, label);
+        Self {texture: Arc::new(texture), size, view, sampler, sampler_desc, mips: None}
+    }
+
+    // uploads only the levels of `mips` from `first` down, keeping the rest
+    // on the CPU for `streaming` to upload once they're needed
+    pub fn from_mips(device: &wgpu::Device, queue: &wgpu::Queue, mips: Arc<MipChain>, first: u32) -> Self {
+        let first = first.min(mips.level_count() - 1);
+        let (width, height) = mips.level(first).dimensions();
+        let size = wgpu::Extent3d { width, height, depth: 1 };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size,
+            mip_level_count: mips.level_count() - first,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: mips.format(),
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+            label: mips.label(),
         });
-        let bind_group_layout = device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::SampledTexture {
-                            multisampled: false,
-                            dimension: wgpu::TextureViewDimension::D2,
-                            component_type: wgpu::TextureComponentType::Uint,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStage::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler {
-                            comparison: false,
-                        },
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout")
-            }
-        );
-        let bind_group = device.create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                layout: &bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&sampler),
-                    }
-                ],
-                label: Some("texture_bind_group")
-            }
-        );
-        Ok(Self {texture, view, sampler, bind_group_layout, bind_group})
+        for level in first..mips.level_count() {
+            let image = mips.level(level);
+            let (width, height) = image.dimensions();
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &texture,
+                    mip_level: level - first,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                image,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: 4 * width,
+                    rows_per_image: height,
+                },
+                wgpu::Extent3d { width, height, depth: 1 },
+            );
+        }
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler_desc = SamplerDesc::default();
+        let sampler = sampler_desc.create(device, mips.label());
+        Self {texture: Arc::new(texture), size, view, sampler, sampler_desc, mips: Some((mips, first))}
+    }
+
+    // the same image with another sampler, without uploading it again
+    pub fn with_sampler(&self, device: &wgpu::Device, desc: SamplerDesc) -> Self {
+        Self {
+            texture: self.texture.clone(),
+            size: self.size,
+            view: self.texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            sampler: desc.create(device, None),
+            sampler_desc: desc,
+            mips: self.mips.clone(),
+        }
+    }
+
+    // the largest level on the GPU, see `Readbacks::read_texture`
+    pub(crate) fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue,
+                       readbacks: &mut Readbacks) -> Receiver<Result<image::RgbaImage>> {
+        readbacks.read_texture(device, queue, &self.texture, self.size)
+    }
+
+    // whether both were made from the same upload
+    pub(crate) fn shares_image(&self, other: &Texture) -> bool {
+        Arc::ptr_eq(&self.texture, &other.texture)
+    }
+
+    // replaces the sampler, anything bound to the old one has to be bound
+    // again
+    pub fn set_sampler(&mut self, device: &wgpu::Device, desc: SamplerDesc) {
+        self.sampler = desc.create(device, None);
+        self.sampler_desc = desc;
     }
 }
 
 pub struct DepthTexture {
     texture: wgpu::Texture,
+    pub size: wgpu::Extent3d,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
 }
 
 impl DepthTexture {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
     pub fn new(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor, label: Option<&str>) -> Self {
+        Self::with_size(device, sc_desc.width, sc_desc.height, label)
+    }
+
+    pub fn with_size(device: &wgpu::Device, width: u32, height: u32, label: Option<&str>) -> Self {
+        Self::multisampled(device, width, height, 1, label)
+    }
+
+    // for drawing into a `RenderTexture::multisampled` target
+    pub fn multisampled(device: &wgpu::Device, width: u32, height: u32, sample_count: u32,
+                        label: Option<&str>) -> Self {
         let size = wgpu::Extent3d {
-            width: sc_desc.width,
-            height: sc_desc.height,
+            width,
+            height,
             depth: 1,
         };
         let desc = wgpu::TextureDescriptor {
             label,
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT
-                | wgpu::TextureUsage::SAMPLED,
+            format: Self::FORMAT,
+            // multisampled textures can't be copied, single sampled ones
+            // are read back by frame dumps
+            usage: if sample_count == 1 {
+                wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_SRC
+            } else {
+                wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::SAMPLED
+            },
         };
         let texture = device.create_texture(&desc);
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
@@ -138,6 +282,99 @@ impl DepthTexture {
             }
         );
 
-        Self { texture, view, sampler }
+        Self { texture, size, view, sampler }
+    }
+
+    // the texture to save in a frame dump, single sampled ones only
+    pub(crate) fn dump_target(&self, name: &str) -> dump::Target<'_> {
+        dump::Target::new(name, &self.texture, self.size, Self::FORMAT)
+    }
+}
+
+// a color target that can be rendered into and read back, used in place of
+// the swap chain when there's no window
+pub struct RenderTexture {
+    texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    size: wgpu::Extent3d,
+    format: wgpu::TextureFormat,
+}
+
+impl RenderTexture {
+    pub fn new(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor, label: Option<&str>) -> Self {
+        Self::with_size(device, sc_desc.width, sc_desc.height, sc_desc.format, label)
+    }
+
+    pub fn with_size(device: &wgpu::Device, width: u32, height: u32,
+                     format: wgpu::TextureFormat, label: Option<&str>) -> Self {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT
+                | wgpu::TextureUsage::COPY_SRC
+                | wgpu::TextureUsage::SAMPLED,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view, size, format }
+    }
+
+    // a target with `sample_count` samples per pixel, resolved into a single
+    // sampled view at the end of a pass rather than read back or sampled
+    pub fn multisampled(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor, sample_count: u32,
+                        label: Option<&str>) -> Self {
+        let size = wgpu::Extent3d {
+            width: sc_desc.width,
+            height: sc_desc.height,
+            depth: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: sc_desc.format,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+        });
+        let format = sc_desc.format;
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view, size, format }
+    }
+
+    // copies the texture into a mappable buffer and waits for it, expects a
+    // four-byte-per-pixel format
+    pub async fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<image::RgbaImage> {
+        let (buffer, padded_bytes_per_row) = readback::copy_texture(device, queue, &self.texture, 0, self.size, 4);
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        mapping.await?;
+        let image = readback::unpad(&slice.get_mapped_range(), self.size, padded_bytes_per_row);
+        buffer.unmap();
+        image
+    }
+
+    // the texture to save in a frame dump, single sampled ones only
+    pub(crate) fn dump_target(&self, name: &str) -> dump::Target<'_> {
+        dump::Target::new(name, &self.texture, self.size, self.format)
+    }
+
+    // the same copy without waiting, the image arrives once `readbacks` is
+    // polled after the GPU is done with it. swap chain formats come back
+    // in RGBA order too
+    pub fn read_async(&self, device: &wgpu::Device, queue: &wgpu::Queue,
+                      readbacks: &mut Readbacks) -> Receiver<Result<image::RgbaImage>> {
+        readbacks.read_image(device, queue, &self.texture, self.size, dump::is_bgra(self.format))
     }
 }
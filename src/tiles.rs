@@ -0,0 +1,182 @@
+use crate::assets::Assets;
+use crate::camera::Camera;
+use crate::collision::Aabb;
+use crate::config::{PagingConfig, SceneConfig};
+use crate::loading::SceneLoad;
+use crate::scene::{Scene, ScenePart};
+use anyhow::{Context, Result};
+use glam::Vec3;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+use wgpu;
+
+// a tile manifest, e.g.
+//
+//     [[tile]]
+//     model = "block_0_0.glb"
+//     min = [0.0, 0.0, 0.0]
+//     max = [100.0, 60.0, 100.0]
+//
+// with the models relative to the manifest and their bounds in world
+// units, so tiles can be paged without opening their files
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    tile: Vec<TileDesc>,
+}
+
+#[derive(Deserialize)]
+struct TileDesc {
+    model: String,
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+enum TileState {
+    Unloaded,
+    Loading(SceneLoad),
+    Loaded(ScenePart),
+    // not tried again, the error is in the scene's problems
+    Failed,
+}
+
+struct Tile {
+    path: String,
+    bounds: Aabb,
+    state: TileState,
+    // from the camera as of the last update
+    distance: f32,
+}
+
+// a large scene split into model files, e.g. city blocks exported one by
+// one, of which only the ones around the camera are loaded. loaded tiles
+// are appended to the scene, with their own materials and collision, and
+// taken out again once the camera moves away
+pub struct TilePager {
+    tiles: Vec<Tile>,
+    config: PagingConfig,
+    // what tiles are loaded with, apart from the model
+    scene: SceneConfig,
+    // the tiles by distance, kept between updates
+    order: Vec<usize>,
+}
+
+impl TilePager {
+    // reads the manifest at `path`. tiles load with the scene's import
+    // options, except that they can't be scaled one by one
+    pub fn load(path: &str, scene: &SceneConfig) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path))?;
+        let manifest: Manifest = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse {}", path))?;
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let tiles: Vec<Tile> = manifest.tile.into_iter()
+            .map(|tile| Tile {
+                path: dir.join(&tile.model).to_string_lossy().into_owned(),
+                bounds: Aabb::from_points(vec![Vec3::from(tile.min), Vec3::from(tile.max)]),
+                state: TileState::Unloaded,
+                distance: f32::INFINITY,
+            })
+            .collect();
+        let mut config = scene.paging.clone();
+        config.unload_distance = config.unload_distance.max(config.load_distance);
+        let mut import = scene.import;
+        import.auto_scale = false;
+        Ok(Self {
+            order: (0..tiles.len()).collect(),
+            tiles,
+            config,
+            scene: SceneConfig { import, ..SceneConfig::default() },
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    // the tiles in the scene right now
+    pub fn loaded(&self) -> usize {
+        self.tiles.iter().filter(|tile| matches!(tile.state, TileState::Loaded(_))).count()
+    }
+
+    // forgets the tiles, for when the scene they were appended to was
+    // replaced
+    pub fn reset(&mut self) {
+        for tile in &mut self.tiles {
+            tile.state = TileState::Unloaded;
+        }
+    }
+
+    // starts loading the tiles the camera came close to, appends the ones
+    // that finished and takes out the ones it left behind. call it every
+    // frame with the same scene, see `reset`
+    pub fn update(&mut self, device: &Arc<wgpu::Device>, queue: &Arc<wgpu::Queue>, scene: &mut Scene,
+                  camera: &Camera) {
+        for tile in &mut self.tiles {
+            let closest = camera.eye.max(tile.bounds.min).min(tile.bounds.max);
+            tile.distance = (closest - camera.eye).length();
+        }
+        let tiles = &self.tiles;
+        self.order.sort_unstable_by(|a, b| {
+            tiles[*a].distance.partial_cmp(&tiles[*b].distance).unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let mut loading = self.tiles.iter().filter(|tile| matches!(tile.state, TileState::Loading(_))).count();
+        let mut kept = 0;
+        for i in 0..self.order.len() {
+            let tile = &mut self.tiles[self.order[i]];
+            let keep = tile.distance <= self.config.unload_distance && kept < self.config.max_loaded;
+            let mut removed = None;
+            tile.state = match std::mem::replace(&mut tile.state, TileState::Unloaded) {
+                TileState::Loaded(part) if !keep => {
+                    removed = Some(part);
+                    TileState::Unloaded
+                }
+                TileState::Loading(_) if !keep => {
+                    loading -= 1;
+                    TileState::Unloaded
+                }
+                TileState::Loading(mut load) => {
+                    kept += 1;
+                    match load.poll() {
+                        Some((Ok(loaded), _)) => {
+                            loading -= 1;
+                            TileState::Loaded(scene.append(loaded))
+                        }
+                        Some((Err(error), _)) => {
+                            loading -= 1;
+                            scene.stats.problems.push(format!("tile {}: {:#}", tile.path, error));
+                            TileState::Failed
+                        }
+                        None => TileState::Loading(load),
+                    }
+                }
+                TileState::Unloaded if keep && tile.distance <= self.config.load_distance
+                    && loading < self.config.loads_at_once => {
+                    kept += 1;
+                    loading += 1;
+                    let config = SceneConfig { model: tile.path.clone(), ..self.scene.clone() };
+                    TileState::Loading(SceneLoad::start(device.clone(), queue.clone(), config, Assets::new()))
+                }
+                TileState::Loaded(part) => {
+                    kept += 1;
+                    TileState::Loaded(part)
+                }
+                state => state,
+            };
+            if let Some(part) = removed {
+                scene.remove(&part);
+                for tile in &mut self.tiles {
+                    if let TileState::Loaded(other) = &mut tile.state {
+                        other.follow_removal(&part);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,32 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_color;
+layout(set=0, binding=1) uniform sampler s_color;
+layout(set=0, binding=2) uniform Tonemap {
+  float t_exposure;
+  uint t_operator;
+};
+
+// must match the Tonemap enum in config.rs
+const uint TONEMAP_REINHARD = 0;
+const uint TONEMAP_ACES = 1;
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 c) {
+  return clamp((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14), 0.0, 1.0);
+}
+
+// maps the linear HDR color into 0 to 1, the sRGB target encodes it
+void main() {
+  vec4 color = texture(sampler2D(t_color, s_color), v_tex_coord);
+  vec3 c = max(color.rgb * t_exposure, 0.0);
+  if (t_operator == TONEMAP_ACES) {
+    c = aces(c);
+  } else {
+    c = c / (1.0 + c);
+  }
+  f_color = vec4(c, clamp(color.a, 0.0, 1.0));
+}
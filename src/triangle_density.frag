@@ -0,0 +1,13 @@
+#version 450
+
+layout(location=0) flat in float v_area;
+layout(location=0) out vec4 f_color;
+
+// red for triangles of a pixel or less, through yellow to green at 100
+// pixels and above, where quad shading stops being wasteful
+void main() {
+  float t = clamp(log2(max(v_area, 1.0)) / log2(100.0), 0.0, 1.0);
+  vec3 color = t < 0.5 ? mix(vec3(1.0, 0.1, 0.1), vec3(1.0, 0.9, 0.1), t * 2.0)
+                       : mix(vec3(1.0, 0.9, 0.1), vec3(0.1, 0.8, 0.2), t * 2.0 - 1.0);
+  f_color = vec4(color, 1.0);
+}
@@ -0,0 +1,32 @@
+#version 450
+
+layout(location=0) in vec3 a_a;
+layout(location=1) in vec3 a_b;
+layout(location=2) in vec3 a_c;
+
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+};
+
+layout(set=1, binding=0)
+  uniform Heatmap {
+  vec4 h_size;
+};
+
+layout(location=0) flat out float v_area;
+
+vec2 to_pixels(vec4 clip) {
+  return clip.xy / max(abs(clip.w), 1e-6) * 0.5 * h_size.xy;
+}
+
+// expands the instance's triangle, passing on its area in pixels
+void main() {
+  vec4 a = u_view_proj * vec4(a_a, 1.0);
+  vec4 b = u_view_proj * vec4(a_b, 1.0);
+  vec4 c = u_view_proj * vec4(a_c, 1.0);
+  vec2 ab = to_pixels(b) - to_pixels(a);
+  vec2 ac = to_pixels(c) - to_pixels(a);
+  v_area = abs(ab.x * ac.y - ab.y * ac.x) * 0.5;
+  gl_Position = gl_VertexIndex == 0 ? a : (gl_VertexIndex == 1 ? b : c);
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use crate::state::State;
+use log::info;
+use std::time::Instant;
+use winit::event::Event;
+use winit::window::Window;
+
+// what draws the viewer's panels and stats HUD over the frame. the core
+// renderer knows nothing about it, a backend only gets the frame through
+// `State::render_with`. `backend` picks one by the enabled features
+pub trait UiBackend {
+    // sees every event before the viewer, returning whether the UI uses
+    // the input so the camera shouldn't react to it as well
+    fn handle_event(&mut self, event: &Event<()>) -> bool;
+
+    // whether the panels are up, F2 in the viewer
+    fn is_visible(&self) -> bool;
+
+    fn set_visible(&mut self, visible: bool);
+
+    // whether the next `render` draws or prints anything, which allocates
+    // and so isn't held to `alloc::FrameAudit`
+    fn is_drawing(&self, state: &State) -> bool;
+
+    // renders the state's frame with the UI on top
+    fn render(&mut self, state: &mut State, window: &Window) -> Result<()>;
+}
+
+// the egui overlay with the `ui` feature, no UI otherwise
+pub fn backend(window: &Window, state: &State) -> Box<dyn UiBackend> {
+    #[cfg(feature = "ui")]
+    {
+        Box::new(crate::egui_overlay::UiOverlay::new(window, state))
+    }
+    #[cfg(not(feature = "ui"))]
+    {
+        let _ = (window, state);
+        Box::new(NoUi::new())
+    }
+}
+
+// no panels. the stats HUD goes to the terminal instead, once a second
+pub struct NoUi {
+    stats_reported: Instant,
+}
+
+impl NoUi {
+    pub fn new() -> Self {
+        Self { stats_reported: Instant::now() }
+    }
+}
+
+impl Default for NoUi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UiBackend for NoUi {
+    fn handle_event(&mut self, _event: &Event<()>) -> bool {
+        false
+    }
+
+    fn is_visible(&self) -> bool {
+        false
+    }
+
+    fn set_visible(&mut self, _visible: bool) {}
+
+    fn is_drawing(&self, state: &State) -> bool {
+        state.shows_stats()
+    }
+
+    fn render(&mut self, state: &mut State, _window: &Window) -> Result<()> {
+        let result = state.render();
+        if state.shows_stats() && self.stats_reported.elapsed().as_secs_f32() >= 1.0 {
+            info!("{}", state.stats());
+            self.stats_reported = Instant::now();
+        }
+        result
+    }
+}
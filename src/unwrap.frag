@@ -0,0 +1,35 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+
+// the six faces of a capture, in the order +X, -X, +Y, -Y, +Z, -Z
+layout(set=0, binding=0) uniform texture2DArray t_faces;
+layout(set=0, binding=1) uniform sampler s_faces;
+layout(set=0, binding=2) uniform Unwrap {
+  // world space to each face's clip space
+  mat4 u_faces[6];
+  vec4 u_position;
+};
+
+#define PI 3.14159265
+
+// the direction each texel of the panorama looks along, the inverse of
+// `panorama_uv` in shader.frag, looked up in the face of its major axis
+void main() {
+  float phi = (v_tex_coord.x - 0.5) * 2.0 * PI;
+  float theta = v_tex_coord.y * PI;
+  vec3 d = vec3(sin(theta) * cos(phi), cos(theta), sin(theta) * sin(phi));
+  vec3 a = abs(d);
+  int face;
+  if (a.x >= a.y && a.x >= a.z) {
+    face = d.x > 0.0 ? 0 : 1;
+  } else if (a.y >= a.z) {
+    face = d.y > 0.0 ? 2 : 3;
+  } else {
+    face = d.z > 0.0 ? 4 : 5;
+  }
+  vec4 clip = u_faces[face] * vec4(u_position.xyz + d, 1.0);
+  vec2 uv = clamp(clip.xy / clip.w * vec2(0.5, -0.5) + 0.5, 0.0, 1.0);
+  f_color = vec4(textureLod(sampler2DArray(t_faces, s_faces), vec3(uv, float(face)), 0.0).rgb, 1.0);
+}
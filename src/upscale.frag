@@ -0,0 +1,28 @@
+#version 450
+
+layout(location=0) in vec2 v_tex_coord;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0) uniform texture2D t_color;
+layout(set=0, binding=1) uniform sampler s_color;
+layout(set=0, binding=2)
+  uniform Upscale {
+  mat4 u_reproject;
+  vec2 u_jitter;
+  vec2 u_input_size;
+  float u_sharpness;
+  float u_history_weight;
+};
+
+// bilinear upscale followed by an unsharp mask over the input texels
+void main() {
+  vec2 texel = 1.0 / u_input_size;
+  vec3 center = texture(sampler2D(t_color, s_color), v_tex_coord).rgb;
+  vec3 neighbors = texture(sampler2D(t_color, s_color), v_tex_coord + vec2(texel.x, 0.0)).rgb
+                 + texture(sampler2D(t_color, s_color), v_tex_coord - vec2(texel.x, 0.0)).rgb
+                 + texture(sampler2D(t_color, s_color), v_tex_coord + vec2(0.0, texel.y)).rgb
+                 + texture(sampler2D(t_color, s_color), v_tex_coord - vec2(0.0, texel.y)).rgb;
+  vec3 color = center + (center * 4.0 - neighbors) * u_sharpness;
+  // HDR input may go above 1
+  f_color = vec4(max(color, 0.0), 1.0);
+}
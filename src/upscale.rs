@@ -0,0 +1,344 @@
+use wgpu;
+use wgpu::util::DeviceExt;
+use glam::{Mat4, Vec2, Vec3};
+use crate::camera::Camera;
+use crate::config::{UpscaleConfig, UpscaleMode};
+use crate::dump;
+use crate::texture::{DepthTexture, RenderTexture};
+
+// how much of the accumulated history the temporal upscale keeps each frame
+const HISTORY_WEIGHT: f32 = 0.9;
+// the jitter sequence repeats after this many frames
+const JITTER_PHASES: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct UpscaleUniforms {
+    reproject: [[f32; 4]; 4],
+    jitter: [f32; 2],
+    input_size: [f32; 2],
+    sharpness: f32,
+    history_weight: f32,
+    _padding: [f32; 2],
+}
+
+// the textures sized after the output, recreated on resize
+struct Targets {
+    input_width: u32,
+    input_height: u32,
+    color: RenderTexture,
+    depth: DepthTexture,
+    history: [RenderTexture; 2],
+    // one per history texture read, writing into the other
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+// renders the scene at a fraction of the output resolution and scales it
+// up, either spatially with a bilinear filter and sharpening or temporally
+// by accumulating jittered frames
+pub struct Upscaler {
+    config: UpscaleConfig,
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    targets: Targets,
+    frame: u32,
+    jitter: Vec2,
+    // the history texture written this frame
+    history: usize,
+    previous_view_proj: Option<Mat4>,
+}
+
+// the low discrepancy sequence used for the sub-pixel jitter
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+impl Upscaler {
+    pub fn new(device: &wgpu::Device, config: &UpscaleConfig, format: wgpu::TextureFormat,
+               width: u32, height: u32) -> Self {
+        let temporal = config.mode == UpscaleMode::Temporal;
+        let texture = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::SampledTexture {
+                multisampled: false,
+                dimension: wgpu::TextureViewDimension::D2,
+                component_type: wgpu::TextureComponentType::Float,
+            },
+            count: None,
+        };
+        let mut entries = vec![
+            texture(0),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Sampler {
+                    comparison: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::UniformBuffer {
+                    dynamic: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        if temporal {
+            entries.push(texture(3));
+            entries.push(texture(4));
+        }
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: Some("upscale_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("upscale_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let vs_module = device.create_shader_module(wgpu::include_spirv!("fullscreen.vert.spv"));
+        let fs_module = if temporal {
+            device.create_shader_module(wgpu::include_spirv!("temporal.frag.spv"))
+        } else {
+            device.create_shader_module(wgpu::include_spirv!("upscale.frag.spv"))
+        };
+        // the temporal pass also writes the new history
+        let color_states = [format.into(), format.into()];
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("upscale_pipeline"),
+            layout: Some(&layout),
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: &vs_module,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: &fs_module,
+                entry_point: "main",
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+            color_states: if temporal { &color_states } else { &color_states[..1] },
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            depth_stencil_state: None,
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint32,
+                vertex_buffers: &[],
+            },
+            sample_count: 1,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("upscale_uniform_buffer"),
+            contents: bytemuck::cast_slice(&[UpscaleUniforms {
+                reproject: Mat4::identity().to_cols_array_2d(),
+                jitter: [0.0; 2],
+                input_size: [1.0; 2],
+                sharpness: 0.0,
+                history_weight: 0.0,
+                _padding: [0.0; 2],
+            }]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let targets = create_targets(
+            device, config, format, &bind_group_layout, &sampler, &uniform_buffer, (width, height));
+        Self {
+            config: config.clone(),
+            format,
+            pipeline,
+            bind_group_layout,
+            sampler,
+            uniform_buffer,
+            targets,
+            frame: 0,
+            jitter: Vec2::zero(),
+            history: 0,
+            previous_view_proj: None,
+        }
+    }
+
+    // whether the scene should go through the upscaler at all
+    pub fn is_enabled(&self) -> bool {
+        self.config.scale < 1.0 || self.config.mode == UpscaleMode::Temporal
+    }
+
+    pub fn config(&self) -> &UpscaleConfig {
+        &self.config
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.targets = create_targets(
+            device, &self.config, self.format, &self.bind_group_layout, &self.sampler,
+            &self.uniform_buffer, (width, height));
+        self.previous_view_proj = None;
+    }
+
+    // the offset to apply to this frame's projection, in normalized device
+    // coordinates
+    pub fn jitter(&self) -> Vec2 {
+        self.jitter
+    }
+
+    // the resolution the scene is drawn at before scaling up
+    pub fn input_size(&self) -> (u32, u32) {
+        (self.targets.input_width, self.targets.input_height)
+    }
+
+    pub fn color_view(&self) -> &wgpu::TextureView {
+        &self.targets.color.view
+    }
+
+    pub fn depth_view(&self) -> &wgpu::TextureView {
+        &self.targets.depth.view
+    }
+
+    // the low resolution frame and, when temporal, the history it was
+    // accumulated into, as a frame dump saves them
+    pub(crate) fn dump_targets(&self) -> Vec<dump::Target<'_>> {
+        let mut targets = vec![
+            self.targets.color.dump_target("upscale_input"),
+            self.targets.depth.dump_target("upscale_depth"),
+        ];
+        if self.config.mode == UpscaleMode::Temporal {
+            targets.push(self.targets.history[self.history].dump_target("upscale_history"));
+        }
+        targets
+    }
+
+    // picks this frame's jitter and reprojection
+    pub fn prepare(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let input_size = Vec2::new(self.targets.input_width as f32, self.targets.input_height as f32);
+        let view_proj = camera.build_view_projection_matrix();
+        let mut uniforms = UpscaleUniforms {
+            reproject: Mat4::identity().to_cols_array_2d(),
+            jitter: [0.0; 2],
+            input_size: input_size.into(),
+            sharpness: self.config.sharpness,
+            history_weight: 0.0,
+            _padding: [0.0; 2],
+        };
+        if self.config.mode == UpscaleMode::Temporal {
+            self.frame = self.frame.wrapping_add(1);
+            self.history = 1 - self.history;
+            let phase = self.frame % JITTER_PHASES + 1;
+            let offset = Vec2::new(halton(phase, 2), halton(phase, 3)) - Vec2::splat(0.5);
+            self.jitter = offset * 2.0 / input_size;
+            uniforms.jitter = [self.jitter.x * 0.5, -self.jitter.y * 0.5];
+            if let Some(previous) = self.previous_view_proj {
+                uniforms.reproject = (previous * view_proj.inverse()).to_cols_array_2d();
+                uniforms.history_weight = HISTORY_WEIGHT;
+            }
+            self.previous_view_proj = Some(view_proj);
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+    }
+
+    // scales the scene rendered into `color_view` up into `output`
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        let attachment = |view| wgpu::RenderPassColorAttachmentDescriptor {
+            attachment: view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: true,
+            },
+        };
+        let attachments = [attachment(output), attachment(&self.targets.history[self.history].view)];
+        let temporal = self.config.mode == UpscaleMode::Temporal;
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: if temporal { &attachments } else { &attachments[..1] },
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        // the bind group reading the history written last frame
+        pass.set_bind_group(0, &self.targets.bind_groups[1 - self.history], &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_targets(
+    device: &wgpu::Device,
+    config: &UpscaleConfig,
+    format: wgpu::TextureFormat,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    uniform_buffer: &wgpu::Buffer,
+    (width, height): (u32, u32),
+) -> Targets {
+    let scale = config.scale.clamp(0.1, 1.0);
+    let input_width = ((width as f32 * scale).round() as u32).max(1);
+    let input_height = ((height as f32 * scale).round() as u32).max(1);
+    let color = RenderTexture::with_size(
+        device, input_width, input_height, format, Some("upscale_input"));
+    let depth = DepthTexture::with_size(device, input_width, input_height, Some("upscale_depth"));
+    // only the temporal upscale reads the history, keep it tiny otherwise
+    let (history_width, history_height) = if config.mode == UpscaleMode::Temporal {
+        (width, height)
+    } else {
+        (1, 1)
+    };
+    let history = [
+        RenderTexture::with_size(device, history_width, history_height, format, Some("upscale_history")),
+        RenderTexture::with_size(device, history_width, history_height, format, Some("upscale_history")),
+    ];
+    let bind_group = |history: &RenderTexture| {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&color.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Buffer(uniform_buffer.slice(..)),
+            },
+        ];
+        if config.mode == UpscaleMode::Temporal {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&depth.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&history.view),
+            });
+        }
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: bind_group_layout,
+            entries: &entries,
+            label: Some("upscale_bind_group"),
+        })
+    };
+    let bind_groups = [bind_group(&history[0]), bind_group(&history[1])];
+    Targets { input_width, input_height, color, depth, history, bind_groups }
+}
+
+// offsets a view projection matrix by `jitter` in normalized device coordinates
+pub fn jittered(view_proj: Mat4, jitter: Vec2) -> Mat4 {
+    Mat4::from_translation(Vec3::new(jitter.x, jitter.y, 0.0)) * view_proj
+}
@@ -1,8 +1,132 @@
 use bytemuck;
+use wgpu;
+
 #[repr(C)]
 #[derive(Default, Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
     pub tex_coord: [f32; 2],
+    // along increasing u, w is the sign of the bitangent (along v) relative
+    // to cross(normal, tangent)
+    pub tangent: [f32; 4],
+}
+
+impl Vertex {
+    pub fn layout() -> VertexLayout {
+        VertexLayout::new()
+            .with(VertexAttribute::Position)
+            .with(VertexAttribute::Normal)
+            .with(VertexAttribute::TexCoord)
+            .with(VertexAttribute::Tangent)
+    }
+}
+
+// what a vertex can carry. each has a fixed format and shader location,
+// so a shader reads the same attribute from whatever layout it's given.
+// locations 4 to 11 belong to the instance buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VertexAttribute {
+    Position,
+    Normal,
+    TexCoord,
+    Tangent,
+    Color,
+    // the indices of the four joints skinning the vertex, and their weights
+    Joints,
+    Weights,
+}
+
+impl VertexAttribute {
+    pub fn format(self) -> wgpu::VertexFormat {
+        match self {
+            VertexAttribute::Position | VertexAttribute::Normal => wgpu::VertexFormat::Float3,
+            VertexAttribute::TexCoord => wgpu::VertexFormat::Float2,
+            VertexAttribute::Tangent | VertexAttribute::Color | VertexAttribute::Weights => {
+                wgpu::VertexFormat::Float4
+            }
+            VertexAttribute::Joints => wgpu::VertexFormat::Ushort4,
+        }
+    }
+
+    pub fn location(self) -> wgpu::ShaderLocation {
+        match self {
+            VertexAttribute::Position => 0,
+            VertexAttribute::Normal => 1,
+            VertexAttribute::TexCoord => 2,
+            VertexAttribute::Tangent => 3,
+            VertexAttribute::Color => 12,
+            VertexAttribute::Joints => 13,
+            VertexAttribute::Weights => 14,
+        }
+    }
+}
+
+// the attributes present in a vertex buffer, packed in the order they're
+// added
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct VertexLayout {
+    attributes: Vec<wgpu::VertexAttributeDescriptor>,
+    stride: wgpu::BufferAddress,
+}
+
+impl VertexLayout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // appends an attribute after the ones already there
+    pub fn with(mut self, attribute: VertexAttribute) -> Self {
+        if !self.has(attribute) {
+            self.attributes.push(wgpu::VertexAttributeDescriptor {
+                offset: self.stride,
+                format: attribute.format(),
+                shader_location: attribute.location(),
+            });
+            self.stride += attribute.format().size();
+        }
+        self
+    }
+
+    pub fn has(&self, attribute: VertexAttribute) -> bool {
+        self.attributes.iter().any(|descriptor| descriptor.shader_location == attribute.location())
+    }
+
+    // whether a shader reading `inputs` can draw vertices in this layout
+    pub fn supports(&self, inputs: &[VertexAttribute]) -> bool {
+        inputs.iter().all(|input| self.has(*input))
+    }
+
+    // the part of the layout a shader reading `inputs` binds, at the same
+    // offsets and stride, so e.g. a depth only pass skips everything but
+    // the position
+    pub fn for_shader(&self, inputs: &[VertexAttribute]) -> Self {
+        Self {
+            attributes: self.attributes.iter()
+                .filter(|descriptor| inputs.iter().any(|input| input.location() == descriptor.shader_location))
+                .cloned()
+                .collect(),
+            stride: self.stride,
+        }
+    }
+
+    // where the attribute starts in a vertex, None if it isn't there
+    pub fn offset(&self, attribute: VertexAttribute) -> Option<wgpu::BufferAddress> {
+        self.attributes.iter()
+            .find(|descriptor| descriptor.shader_location == attribute.location())
+            .map(|descriptor| descriptor.offset)
+    }
+
+    pub fn stride(&self) -> wgpu::BufferAddress {
+        self.stride
+    }
+
+    // for a per vertex buffer
+    pub fn descriptor(&self) -> wgpu::VertexBufferDescriptor<'_> {
+        wgpu::VertexBufferDescriptor {
+            stride: self.stride,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &self.attributes,
+        }
+    }
 }
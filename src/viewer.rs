@@ -0,0 +1,380 @@
+use winit::{
+    event::*,
+    event_loop::{ControlFlow, EventLoop},
+    dpi::LogicalSize,
+    window::WindowBuilder,
+};
+use anyhow::{bail, Context, Result};
+use log::{error, info};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use structopt::StructOpt;
+use crate::camera::Camera;
+use crate::assets::Assets;
+use crate::config::{Backend, BackgroundMode, Config};
+use crate::input::InputRecording;
+use crate::pack;
+use crate::renderer::Renderer;
+use crate::scene::Scene;
+use crate::state;
+#[cfg(debug_assertions)]
+use crate::alloc;
+
+// the viewer's command line, parsed with `StructOpt::from_args`
+#[derive(StructOpt)]
+#[structopt(about = "A small wgpu model viewer")]
+pub struct Opt {
+    #[structopt(flatten)]
+    render: RenderOptions,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+// settings from wr3d.toml that are often changed for a single run
+#[derive(StructOpt)]
+struct RenderOptions {
+    /// Window or image size as WIDTHxHEIGHT
+    #[structopt(long, global = true, parse(try_from_str = parse_size))]
+    size: Option<(u32, u32)>,
+    /// Waits for the display's refresh, on or off
+    #[structopt(long, global = true, parse(try_from_str = parse_switch))]
+    vsync: Option<bool>,
+    /// Graphics API: primary, vulkan, metal, dx12, dx11 or gl
+    #[structopt(long, global = true, parse(try_from_str = parse_backend))]
+    backend: Option<Backend>,
+    /// Samples per pixel: 1, 2, 4 or 8
+    #[structopt(long, global = true)]
+    msaa: Option<u32>,
+}
+
+impl RenderOptions {
+    fn apply(&self, config: &mut Config) {
+        if let Some((width, height)) = self.size {
+            config.window.width = width;
+            config.window.height = height;
+        }
+        if let Some(vsync) = self.vsync {
+            config.renderer.vsync = vsync;
+        }
+        if let Some(backend) = self.backend {
+            config.renderer.backend = backend;
+        }
+        if let Some(msaa) = self.msaa {
+            config.renderer.msaa = msaa;
+        }
+    }
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Opens a model in a window (the default)
+    View {
+        /// Models to load instead of the ones in wr3d.toml, drawn together
+        models: Vec<String>,
+        /// Another version of the model to review against, C switches between them
+        #[structopt(long)]
+        compare: Option<String>,
+        /// Records the session's input into a file, written on exit
+        #[structopt(long)]
+        record: Option<PathBuf>,
+        /// Plays back input recorded with --record once the model has loaded
+        #[structopt(long)]
+        replay: Option<PathBuf>,
+    },
+    /// Renders a single image of a model without opening a window
+    Screenshot {
+        model: String,
+        #[structopt(short, long, default_value = "screenshot.png")]
+        output: PathBuf,
+    },
+    /// Renders frames of the camera circling a model into a directory
+    Turntable {
+        model: String,
+        #[structopt(short, long, default_value = "frames")]
+        output: PathBuf,
+        /// Number of frames in a full revolution
+        #[structopt(long, default_value = "36")]
+        frames: u32,
+    },
+    /// Loads a scene described in ron and writes its meshes, materials and textures into a pack that loads without parsing
+    Bake {
+        scene: PathBuf,
+        #[structopt(short, long, default_value = "scene.pack")]
+        output: PathBuf,
+    },
+}
+
+impl Opt {
+    // runs the command on top of `config`, opening the viewer without one.
+    // viewing only returns on errors, the event loop exits the process
+    pub async fn run(self, mut config: Config) -> Result<()> {
+        self.render.apply(&mut config);
+        let size = (config.window.width, config.window.height);
+        match self.command {
+            None => view(config, None, None, None).await,
+            Some(Command::View { models, compare, record, replay }) => {
+                if let Some((model, extra)) = models.split_first() {
+                    config.scene.model = model.clone();
+                    config.scene.extra_models = extra.to_vec();
+                }
+                let replay = replay.map(InputRecording::load).transpose()?;
+                view(config, compare, record, replay).await
+            }
+            Some(Command::Screenshot { model, output }) => {
+                config.scene.model = model;
+                screenshot(&config, &output, size).await
+            }
+            Some(Command::Turntable { model, output, frames }) => {
+                config.scene.model = model;
+                turntable(&config, &output, size, frames).await
+            }
+            Some(Command::Bake { scene, output }) => {
+                let renderer = Renderer::new_headless(1, 1, &config.renderer).await?;
+                pack::bake(renderer.device(), renderer.queue(), &scene, &output)?;
+                info!("baked {} into {}", scene.display(), output.display());
+                Ok(())
+            }
+        }
+    }
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32)> {
+    let mut split = size.splitn(2, 'x');
+    match (split.next(), split.next()) {
+        (Some(width), Some(height)) => Ok((width.parse()?, height.parse()?)),
+        _ => bail!("Expected a size like 1920x1080, got {}", size),
+    }
+}
+
+fn parse_switch(switch: &str) -> Result<bool> {
+    match switch {
+        "on" | "true" => Ok(true),
+        "off" | "false" => Ok(false),
+        _ => bail!("Expected on or off, got {}", switch),
+    }
+}
+
+fn parse_backend(backend: &str) -> Result<Backend> {
+    Ok(match backend {
+        "primary" => Backend::Primary,
+        "vulkan" => Backend::Vulkan,
+        "metal" => Backend::Metal,
+        "dx12" => Backend::Dx12,
+        "dx11" => Backend::Dx11,
+        "gl" => Backend::Gl,
+        _ => bail!("Expected primary, vulkan, metal, dx12, dx11 or gl, got {}", backend),
+    })
+}
+
+async fn screenshot(config: &Config, output: &Path, (width, height): (u32, u32)) -> Result<()> {
+    let mut renderer = Renderer::new_headless(width, height, &config.renderer).await?;
+    let scene = Scene::load_with_options(
+        renderer.device(), renderer.queue(), &config.scene.model, &mut Assets::new(), config.scene.import)?;
+    let camera = Camera::from_config(&config.camera, width as f32 / height as f32);
+    renderer.render_frame(&scene, &camera)?;
+    renderer.capture().await?.save(output)
+        .with_context(|| format!("Failed to write {}", output.display()))
+}
+
+// rotates the camera's eye around its up axis through the center
+async fn turntable(config: &Config, output: &Path, (width, height): (u32, u32),
+                   frames: u32) -> Result<()> {
+    let mut renderer = Renderer::new_headless(width, height, &config.renderer).await?;
+    let scene = Scene::load_with_options(
+        renderer.device(), renderer.queue(), &config.scene.model, &mut Assets::new(), config.scene.import)?;
+    let mut camera = Camera::from_config(&config.camera, width as f32 / height as f32);
+    let offset = camera.eye - camera.center;
+    std::fs::create_dir_all(output)?;
+    for frame in 0..frames {
+        let angle = frame as f32 / frames as f32 * std::f32::consts::PI * 2.0;
+        camera.eye = camera.center + glam::Quat::from_axis_angle(camera.up, angle) * offset;
+        renderer.render_frame(&scene, &camera)?;
+        let path = output.join(format!("frame_{:04}.png", frame));
+        renderer.capture().await?.save(&path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+async fn view(mut config: Config, mut compare: Option<String>, record: Option<PathBuf>,
+              mut replay: Option<InputRecording>) -> Result<()> {
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title(&config.window.title)
+        .with_inner_size(LogicalSize::new(config.window.width, config.window.height))
+        .with_transparent(config.window.transparent)
+        .build(&event_loop)?;
+    // the window's alpha comes from the cleared background
+    if config.window.transparent {
+        config.renderer.background.mode = BackgroundMode::Transparent;
+    }
+    let mut state = state::State::new(&window, &config).await?;
+    let mut overlay = crate::ui::backend(&window, &state);
+    #[cfg(feature = "remote")]
+    let remote = if config.remote.enabled {
+        Some(crate::remote::RemoteServer::start(&config.remote.address)?)
+    } else {
+        None
+    };
+    #[cfg(feature = "hot-reload")]
+    let mut watchers = if config.hot_reload.enabled {
+        let mut shaders = crate::hot_reload::ShaderWatcher::new(&config.hot_reload.dir)?;
+        if let Some(dir) = &config.hot_reload.cache {
+            let adapter = &state.renderer().capabilities().adapter;
+            shaders.set_cache(crate::shader_cache::ShaderCache::new(dir, adapter)?);
+        }
+        shaders.queue_current();
+        Some((shaders, crate::hot_reload::AssetWatcher::new(&config.hot_reload.assets)?,
+              crate::hot_reload::ConfigWatcher::new(crate::config::PATH)?))
+    } else {
+        None
+    };
+    let mut grabbed = false;
+    // recording starts along with a replay, once the scene has loaded
+    let mut start_recording = record.is_some();
+    #[cfg(debug_assertions)]
+    let mut audit = alloc::FrameAudit::new(config.stats.allocation_budget);
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;        
+        // input the overlay uses doesn't reach the camera
+        let ui_input = overlay.handle_event(&event);
+        match event {
+            Event::WindowEvent {
+                ref event,
+                window_id,
+            } if window_id == window.id() => if ui_input || !state.input(event) {
+                match event {
+                    WindowEvent::CloseRequested =>
+                        *control_flow = ControlFlow::Exit,
+                    WindowEvent::KeyboardInput {
+                        input,
+                        ..
+                    } => {
+                        match input {
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            } => *control_flow = ControlFlow::Exit,
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F12),
+                                ..
+                            } => {
+                                let millis = SystemTime::now().duration_since(UNIX_EPOCH)
+                                    .map_or(0, |time| time.as_millis());
+                                if let Err(e) = state.capture_frame(format!("screenshot-{}.png", millis)) {
+                                    error!("{:#}", e);
+                                }
+                            }
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F2),
+                                ..
+                            } => overlay.set_visible(!overlay.is_visible()),
+                            _ => {}
+                        }
+                    },
+                    WindowEvent::Resized(physical_size) => {
+                        state.resize(*physical_size);
+                    }
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        state.resize(**new_inner_size);
+                    }
+                    // the scene is replaced by the dropped model
+                    WindowEvent::DroppedFile(path) => if let Err(e) = state.open_file(path) {
+                        error!("{:#}", e);
+                    },
+                    _ => (),
+                }
+            } else if grabbed != state.grabs_cursor() {
+                grabbed = state.grabs_cursor();
+                if let Err(e) = window.set_cursor_grab(grabbed) {
+                    error!("{}", e);
+                }
+                window.set_cursor_visible(!grabbed);
+            },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => state.mouse_motion(delta),
+            Event::MainEventsCleared => {
+                match state.poll_loading() {
+                    // the version to compare against loads once the scene is there
+                    Ok(true) => {
+                        if let Some(path) = compare.take() {
+                            match state.compare_with(&path) {
+                                Ok(diff) => info!("{}", diff),
+                                Err(e) => error!("{:#}", e),
+                            }
+                        }
+                        if std::mem::take(&mut start_recording) {
+                            state.start_recording();
+                        }
+                        if let Some(recording) = replay.take() {
+                            state.replay(recording);
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => error!("{:#}", e),
+                }
+                #[cfg(feature = "remote")]
+                if let Some(remote) = &remote {
+                    remote.poll(&mut state);
+                }
+                #[cfg(feature = "hot-reload")]
+                if let Some((shaders, assets, config)) = &mut watchers {
+                    let results = [shaders.poll(state.renderer_mut()), assets.poll(&mut state)];
+                    for result in results.iter() {
+                        match result {
+                            Ok(reloaded) => for path in reloaded {
+                                info!("reloaded {}", path.display());
+                            },
+                            Err(e) => error!("{:#}", e),
+                        }
+                    }
+                    match config.poll(&mut state) {
+                        Ok(true) => info!("reloaded {}", crate::config::PATH),
+                        Ok(false) => {}
+                        Err(e) => error!("{:#}", e),
+                    }
+                }
+                window.request_redraw();
+            },
+            Event::RedrawRequested(_) => {
+                #[cfg(debug_assertions)]
+                let update_scope = alloc::AllocationScope::begin();
+                state.step();
+                #[cfg(debug_assertions)]
+                let update_allocations = update_scope.end();
+                state.animate();
+                state.stream_textures();
+                #[cfg(debug_assertions)]
+                let drawing_ui = overlay.is_drawing(&state);
+                #[cfg(debug_assertions)]
+                let render_scope = alloc::AllocationScope::begin();
+                let result = overlay.render(&mut state, &window);
+                #[cfg(debug_assertions)]
+                {
+                    let render_allocations = render_scope.end();
+                    audit.check(update_allocations, if drawing_ui { None } else { Some(render_allocations) });
+                }
+                match result {
+                    Ok(_) => {},
+                    Err(e) => match e.downcast_ref::<wgpu::SwapChainError>() {
+                        Some(wgpu::SwapChainError::Lost) => state.resize(state.size),
+                        Some(wgpu::SwapChainError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                        _ => error!("{:#}", e),
+                    },
+                }
+            },
+            Event::LoopDestroyed => if let (Some(path), Some(recording)) = (&record, state.stop_recording()) {
+                match recording.save(path) {
+                    Ok(()) => info!("recorded {}", path.display()),
+                    Err(e) => error!("{:#}", e),
+                }
+            },
+            _ => (),
+        }
+    });
+}
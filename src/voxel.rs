@@ -0,0 +1,321 @@
+use anyhow::{bail, Context, Result};
+use glam::Vec3;
+use crate::assets::Handle;
+use crate::collision::{Aabb, Triangle};
+use crate::material::Material;
+use crate::mesh::{Mesh, Model, SubMesh};
+use crate::texture::Texture;
+use crate::vertex::Vertex;
+
+// voxels are meshed in cubes of this many voxels a side, one model each
+const CHUNK_SIZE: usize = 32;
+
+// the most voxels a model can have along each side, as in MagicaVoxel
+// itself. larger sizes come from broken files and would allocate far too much
+const MAX_SIZE: usize = 256;
+
+// a dense grid of palette indices in MagicaVoxel's z-up coordinates, 0 is
+// empty and 1..=255 index the palette
+pub struct Volume {
+    size: [usize; 3],
+    voxels: Vec<u8>,
+}
+
+impl Volume {
+    fn get(&self, x: i32, y: i32, z: i32) -> u8 {
+        let [sx, sy, sz] = self.size;
+        if x < 0 || y < 0 || z < 0 || x as usize >= sx || y as usize >= sy || z as usize >= sz {
+            return 0;
+        }
+        self.voxels[(z as usize * sy + y as usize) * sx + x as usize]
+    }
+}
+
+// reads a MagicaVoxel .vox file. files with several models have them all
+// overlaid at the origin, the scene graph chunks aren't read
+pub fn parse(bytes: &[u8]) -> Result<(Vec<Volume>, [[u8; 4]; 256])> {
+    let mut reader = Reader { bytes, offset: 0 };
+    if reader.take(4)? != b"VOX " {
+        bail!("Not a MagicaVoxel file");
+    }
+    let _version = reader.u32()?;
+    if reader.take(4)? != b"MAIN" {
+        bail!("Missing MAIN chunk");
+    }
+    let content = reader.u32()? as usize;
+    let _children = reader.u32()?;
+    reader.take(content)?;
+
+    let mut volumes = vec![];
+    let mut size = None;
+    // files without a palette chunk use a plain grey
+    let mut palette = [[200, 200, 200, 255]; 256];
+    while reader.offset < bytes.len() {
+        let id = reader.take(4)?;
+        let content = reader.u32()? as usize;
+        let children = reader.u32()? as usize;
+        let mut chunk = Reader { bytes: reader.take(content)?, offset: 0 };
+        match id {
+            b"SIZE" => {
+                let [x, y, z] = [chunk.u32()? as usize, chunk.u32()? as usize, chunk.u32()? as usize];
+                if x > MAX_SIZE || y > MAX_SIZE || z > MAX_SIZE {
+                    bail!("Model is {}x{}x{} voxels, at most {} a side are supported", x, y, z, MAX_SIZE);
+                }
+                size = Some([x, y, z]);
+            }
+            b"XYZI" => {
+                let size = size.take().context("XYZI chunk without a SIZE chunk")?;
+                let len = size[0].checked_mul(size[1]).and_then(|len| len.checked_mul(size[2]))
+                    .context("Model size overflows")?;
+                let mut voxels = vec![0; len];
+                for _ in 0..chunk.u32()? {
+                    let voxel = chunk.take(4)?;
+                    let (x, y, z) = (voxel[0] as usize, voxel[1] as usize, voxel[2] as usize);
+                    if x < size[0] && y < size[1] && z < size[2] {
+                        voxels[(z * size[1] + y) * size[0] + x] = voxel[3];
+                    }
+                }
+                volumes.push(Volume { size, voxels });
+            }
+            b"RGBA" => {
+                // color i of the chunk is palette index i + 1
+                for color in palette.iter_mut().skip(1) {
+                    let rgba = chunk.take(4)?;
+                    color.copy_from_slice(rgba);
+                }
+            }
+            _ => {}
+        }
+        reader.take(children)?;
+    }
+    Ok((volumes, palette))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.offset.checked_add(len).filter(|end| *end <= self.bytes.len())
+            .context("Unexpected end of file")?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+// the models, material and collision triangles of a voxel file
+pub struct Voxels {
+    pub models: Vec<Model>,
+    pub material: Material,
+    pub triangles: Vec<Triangle>,
+}
+
+// loads a .vox file as greedy meshed chunks. voxels are `voxel_size` wide,
+// the volume is centered on the origin and stands on y = 0
+pub fn load(device: &wgpu::Device, queue: &wgpu::Queue, path: &str, voxel_size: f32) -> Result<Voxels> {
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+    let (volumes, palette) = parse(&bytes).with_context(|| format!("Failed to parse {}", path))?;
+
+    // the palette as a 256x1 texture, each vertex samples its voxel's texel
+    let mut image = image::RgbaImage::new(256, 1);
+    for (i, color) in palette.iter().enumerate() {
+        image.put_pixel(i as u32, 0, image::Rgba(*color));
+    }
+    let texture = Texture::from_image(
+        device, queue, &image, wgpu::TextureFormat::Rgba8UnormSrgb, Some("voxel_palette"));
+    let material = Material::from_diffuse_texture(device, queue, path, Handle::new(texture));
+
+    let mut models = vec![];
+    let mut triangles = vec![];
+    for volume in &volumes {
+        let [sx, sy, _] = volume.size;
+        // MagicaVoxel is z-up, turn it to y-up keeping the handedness
+        let to_world = |p: [f32; 3]| {
+            Vec3::new(p[0] - sx as f32 * 0.5, p[2], sy as f32 * 0.5 - p[1]) * voxel_size
+        };
+        for cz in (0..volume.size[2]).step_by(CHUNK_SIZE) {
+            for cy in (0..volume.size[1]).step_by(CHUNK_SIZE) {
+                for cx in (0..volume.size[0]).step_by(CHUNK_SIZE) {
+                    let (vertices, indices) = mesh_chunk(volume, [cx, cy, cz], &to_world);
+                    if indices.is_empty() {
+                        continue;
+                    }
+                    triangles.extend(indices.chunks_exact(3).map(|face| Triangle {
+                        a: Vec3::from(vertices[face[0] as usize].position),
+                        b: Vec3::from(vertices[face[1] as usize].position),
+                        c: Vec3::from(vertices[face[2] as usize].position),
+                    }));
+                    let bounds = Aabb::from_points(vertices.iter().map(|vertex| Vec3::from(vertex.position)));
+                    let name = format!("{} chunk {} {} {}", path, cx, cy, cz);
+                    let submeshes = vec![SubMesh { material: 0, indices: 0..indices.len() as u32 }];
+                    let mesh = Mesh::from_vertices(device, &vertices, &indices, submeshes, &name);
+                    models.push(Model::from_mesh(mesh, bounds, &name));
+                }
+            }
+        }
+    }
+    Ok(Voxels { models, material, triangles })
+}
+
+// greedy meshing (Lysenko, "Meshing in a Minecraft Game"): for every plane
+// between voxels along each axis, faces of the same color are merged into
+// the largest rectangles that fit
+fn mesh_chunk<F>(volume: &Volume, origin: [usize; 3], to_world: &F) -> (Vec<Vertex>, Vec<u32>)
+where
+    F: Fn([f32; 3]) -> Vec3,
+{
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    let lo = [origin[0] as i32, origin[1] as i32, origin[2] as i32];
+    let hi = [
+        (origin[0] + CHUNK_SIZE).min(volume.size[0]) as i32,
+        (origin[1] + CHUNK_SIZE).min(volume.size[1]) as i32,
+        (origin[2] + CHUNK_SIZE).min(volume.size[2]) as i32,
+    ];
+    // positive for faces pointing along the axis, negative against it
+    let mut mask = vec![0i32; CHUNK_SIZE * CHUNK_SIZE];
+    for d in 0..3 {
+        let u = (d + 1) % 3;
+        let v = (d + 2) % 3;
+        let (width, height) = ((hi[u] - lo[u]) as usize, (hi[v] - lo[v]) as usize);
+        for plane in lo[d]..=hi[d] {
+            for j in 0..height {
+                for i in 0..width {
+                    let mut p = [0; 3];
+                    p[d] = plane;
+                    p[u] = lo[u] + i as i32;
+                    p[v] = lo[v] + j as i32;
+                    let front = volume.get(p[0], p[1], p[2]);
+                    p[d] -= 1;
+                    let back = volume.get(p[0], p[1], p[2]);
+                    // each face belongs to the chunk of the voxel it's on
+                    mask[j * width + i] = if back != 0 && front == 0 && plane > lo[d] {
+                        back as i32
+                    } else if front != 0 && back == 0 && plane < hi[d] {
+                        -(front as i32)
+                    } else {
+                        0
+                    };
+                }
+            }
+
+            for j in 0..height {
+                let mut i = 0;
+                while i < width {
+                    let face = mask[j * width + i];
+                    if face == 0 {
+                        i += 1;
+                        continue;
+                    }
+                    let mut w = 1;
+                    while i + w < width && mask[j * width + i + w] == face {
+                        w += 1;
+                    }
+                    let mut h = 1;
+                    while j + h < height && (0..w).all(|k| mask[(j + h) * width + i + k] == face) {
+                        h += 1;
+                    }
+                    for row in j..j + h {
+                        for cell in &mut mask[row * width + i..row * width + i + w] {
+                            *cell = 0;
+                        }
+                    }
+
+                    let mut corner = [0.0; 3];
+                    corner[d] = plane as f32;
+                    corner[u] = (lo[u] + i as i32) as f32;
+                    corner[v] = (lo[v] + j as i32) as f32;
+                    let mut du = [0.0; 3];
+                    du[u] = w as f32;
+                    let mut dv = [0.0; 3];
+                    dv[v] = h as f32;
+                    let mut normal = [0.0; 3];
+                    normal[d] = face.signum() as f32;
+                    let add = |a: [f32; 3], b: [f32; 3]| [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+                    let mut quad = [corner, add(corner, du), add(add(corner, du), dv), add(corner, dv)];
+                    if face < 0 {
+                        quad.reverse();
+                    }
+
+                    let color = face.unsigned_abs() as f32;
+                    let tex_coord = [(color + 0.5) / 256.0, 0.5];
+                    let normal = to_world(normal) - to_world([0.0; 3]);
+                    // the palette has no detail to map, any tangent in the face will do
+                    let mut along_u = [0.0; 3];
+                    along_u[u] = 1.0;
+                    let tangent = (to_world(along_u) - to_world([0.0; 3])).normalize();
+                    let base = vertices.len() as u32;
+                    for corner in &quad {
+                        vertices.push(Vertex {
+                            position: to_world(*corner).into(),
+                            normal: normal.normalize().into(),
+                            tex_coord,
+                            tangent: [tangent.x, tangent.y, tangent.z, 1.0],
+                        });
+                    }
+                    indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+                    i += w;
+                }
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(content);
+        bytes
+    }
+
+    // a file with one model of `size` holding a voxel at the origin
+    fn vox(size: [u32; 3]) -> Vec<u8> {
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150u32.to_le_bytes());
+        bytes.extend(chunk(b"MAIN", &[]));
+        let size: Vec<u8> = size.iter().flat_map(|side| side.to_le_bytes().to_vec()).collect();
+        bytes.extend(chunk(b"SIZE", &size));
+        let mut xyzi = 1u32.to_le_bytes().to_vec();
+        xyzi.extend_from_slice(&[0, 0, 0, 1]);
+        bytes.extend(chunk(b"XYZI", &xyzi));
+        bytes
+    }
+
+    #[test]
+    fn parses_a_model() {
+        let (volumes, _) = parse(&vox([2, 3, 4])).unwrap();
+        assert_eq!(volumes.len(), 1);
+        assert_eq!(volumes[0].size, [2, 3, 4]);
+        assert_eq!(volumes[0].get(0, 0, 0), 1);
+        assert_eq!(volumes[0].get(1, 2, 3), 0);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = vox([2, 2, 2]);
+        for len in [0, 3, 10, 24, bytes.len() - 1].iter() {
+            assert!(parse(&bytes[..*len]).is_err(), "parsed a file cut at {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_models() {
+        assert!(parse(&vox([256, 256, 256])).is_ok());
+        assert!(parse(&vox([257, 1, 1])).is_err());
+        assert!(parse(&vox([1, 1, u32::MAX])).is_err());
+    }
+}
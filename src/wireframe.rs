@@ -0,0 +1,161 @@
+use crate::renderer::ModelTransforms;
+use crate::scene::Scene;
+use crate::texture::DepthTexture;
+use crate::vertex::VertexAttribute;
+use wgpu;
+use wgpu::util::DeviceExt;
+
+// the models culling leaves out, drawn as red lines over the scene while
+// the culling frustum is frozen. the edges are pulled from the meshes'
+// buffers, see wireframe.vert, so nothing is kept on the CPU for them
+pub(crate) struct Wireframes {
+    format: wgpu::TextureFormat,
+    layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    mesh_bind_group_layout: wgpu::BindGroupLayout,
+    // one per model of the scene they were made for, with the buffer of
+    // its vertex layout
+    meshes: Option<(u64, Vec<(wgpu::Buffer, wgpu::BindGroup)>)>,
+    // the models drawn this frame, reused every frame
+    culled: Vec<usize>,
+}
+
+impl Wireframes {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32) -> Self {
+        // the same as the renderer's, so its model bind group can be reused
+        let model_bind_group_layout = ModelTransforms::create_bind_group_layout(device);
+        let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::VERTEX,
+            ty,
+            count: None,
+        };
+        let storage = wgpu::BindingType::StorageBuffer {
+            dynamic: false,
+            min_binding_size: None,
+            readonly: true,
+        };
+        let mesh_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                entry(0, wgpu::BindingType::UniformBuffer { dynamic: false, min_binding_size: None }),
+                entry(1, storage.clone()),
+                entry(2, storage),
+            ],
+            label: Some("wireframe_mesh_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("wireframe_pipeline_layout"),
+            bind_group_layouts: &[&model_bind_group_layout, &mesh_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_pipeline(device, &layout, format, sample_count);
+        Self {
+            format,
+            layout,
+            pipeline,
+            mesh_bind_group_layout,
+            meshes: None,
+            culled: vec![],
+        }
+    }
+
+    // rebuilds the pipeline for a main pass with `sample_count` samples
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        self.pipeline = create_pipeline(device, &self.layout, self.format, sample_count);
+    }
+
+    // sets the models to draw, binding the scene's meshes the first time
+    // it has any
+    pub fn prepare(&mut self, device: &wgpu::Device, scene: &Scene, culled: impl Iterator<Item = usize>) {
+        self.culled.clear();
+        self.culled.extend(culled);
+        if self.culled.is_empty() || matches!(&self.meshes, Some((id, _)) if *id == scene.id) {
+            return;
+        }
+        let float = std::mem::size_of::<f32>() as wgpu::BufferAddress;
+        let meshes = scene.models.iter()
+            .map(|model| {
+                let mesh = &model.mesh;
+                let vertex = [(mesh.layout.stride() / float) as u32,
+                              (mesh.layout.offset(VertexAttribute::Position).unwrap_or(0) / float) as u32, 0, 0];
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("wireframe_mesh_buffer"),
+                    contents: bytemuck::cast_slice(&vertex),
+                    usage: wgpu::BufferUsage::UNIFORM,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.mesh_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::Buffer(buffer.slice(..)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Buffer(mesh.vertex_buffer.slice(..)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::Buffer(mesh.index_buffer.slice(..)),
+                        },
+                    ],
+                    label: Some("wireframe_mesh_bind_group"),
+                });
+                (buffer, bind_group)
+            })
+            .collect();
+        self.meshes = Some((scene.id, meshes));
+    }
+
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, scene: &Scene, model_bind_group: &'a wgpu::BindGroup) {
+        let meshes = match &self.meshes {
+            Some((_, meshes)) if !self.culled.is_empty() => meshes,
+            _ => return,
+        };
+        pass.set_pipeline(&self.pipeline);
+        for &model in &self.culled {
+            let num_indices = scene.models[model].mesh.num_indices;
+            if num_indices == 0 {
+                continue;
+            }
+            pass.set_bind_group(0, model_bind_group, &[ModelTransforms::offset(model)]);
+            pass.set_bind_group(1, &meshes[model].1, &[]);
+            pass.draw(0..num_indices * 2, 0..1);
+        }
+    }
+}
+
+// no vertex buffers, and drawn through everything like the debug lines
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, format: wgpu::TextureFormat,
+                   sample_count: u32) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(wgpu::include_spirv!("wireframe.vert.spv"));
+    let fs_module = device.create_shader_module(wgpu::include_spirv!("wireframe.frag.spv"));
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("wireframe_pipeline"),
+        layout: Some(layout),
+        vertex_stage: wgpu::ProgrammableStageDescriptor {
+            module: &vs_module,
+            entry_point: "main",
+        },
+        fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+            module: &fs_module,
+            entry_point: "main",
+        }),
+        rasterization_state: Some(wgpu::RasterizationStateDescriptor::default()),
+        color_states: &[format.into()],
+        primitive_topology: wgpu::PrimitiveTopology::LineList,
+        depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+            format: DepthTexture::FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilStateDescriptor::default(),
+        }),
+        vertex_state: wgpu::VertexStateDescriptor {
+            index_format: wgpu::IndexFormat::Uint32,
+            vertex_buffers: &[],
+        },
+        sample_count,
+        sample_mask: !0,
+        alpha_to_coverage_enabled: false,
+    })
+}
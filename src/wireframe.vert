@@ -0,0 +1,35 @@
+#version 450
+
+// no vertex buffers, like pick.vert the positions are pulled from the
+// mesh's buffers. every triangle is drawn as its three edges, two
+// vertices each
+layout(set=0, binding=0)
+  uniform Uniforms {
+  mat4 u_view_proj;
+};
+layout(set=0, binding=1)
+  uniform Model {
+  mat4 u_model;
+  mat4 u_normal;
+};
+layout(set=1, binding=0)
+  uniform Mesh {
+  // x the vertex stride, y the position's offset in it, in floats
+  uvec4 u_vertex;
+};
+layout(set=1, binding=1) readonly buffer Vertices {
+  float b_vertices[];
+};
+layout(set=1, binding=2) readonly buffer Indices {
+  uint b_indices[];
+};
+
+void main() {
+  uint triangle = uint(gl_VertexIndex) / 6u;
+  uint end = uint(gl_VertexIndex) % 6u;
+  // edges 0-1, 1-2 and 2-0
+  uint corner = (end / 2u + end % 2u) % 3u;
+  uint base = b_indices[triangle * 3u + corner] * u_vertex.x + u_vertex.y;
+  vec3 position = vec3(b_vertices[base], b_vertices[base + 1u], b_vertices[base + 2u]);
+  gl_Position = u_view_proj * u_model * vec4(position, 1.0);
+}
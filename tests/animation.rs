@@ -0,0 +1,43 @@
+// Skinned models loaded into a headless viewer, checking that animating
+// them moves what culling sees along with the vertices.
+
+use async_std::task::block_on;
+use wr3d::config::Config;
+use wr3d::state::State;
+
+fn adapter_available() -> bool {
+    let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+    block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::Default,
+        compatible_surface: None,
+    }))
+    .is_some()
+}
+
+// data/arm.gltf is a strip along y from 0 to 2, its top half bound to an
+// elbow at y = 1 that its animation bends 90 degrees towards -x at 1s
+#[test]
+fn skinned_bounds_follow_the_animation() {
+    if !adapter_available() {
+        assert!(std::env::var_os("WR3D_REQUIRE_GPU").is_none(), "no GPU adapter available");
+        eprintln!("no GPU adapter available, skipping skinned_bounds_follow_the_animation");
+        return;
+    }
+    let mut config = Config::default();
+    config.scene.model = "data/arm.gltf".to_string();
+    config.scene.extra_models = vec![];
+    let mut state = block_on(State::new_headless(64, 64, &config)).unwrap();
+    let arm = state.scene_mut().model_index("arm").expect("data/arm.gltf has a model named arm");
+    assert!(state.scene_mut().models[arm].skin.is_some());
+
+    state.renderer_mut().set_time(0.0);
+    state.animate();
+    let rest = state.scene_mut().models[arm].bounds;
+    assert!(rest.min.x > -0.5, "the arm starts straight, its bounds are {:?}", rest);
+
+    state.renderer_mut().set_time(1.0);
+    state.animate();
+    let bent = state.scene_mut().models[arm].bounds;
+    // the hand ends up at x = -1
+    assert!(bent.min.x < -0.9, "the bent arm's bounds {:?} don't reach its hand", bent);
+}
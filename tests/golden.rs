@@ -0,0 +1,173 @@
+// Golden-image regression tests: render reference scenes headlessly and
+// compare them against the PNGs stored in tests/golden.
+//
+// A scene without a stored reference records its frame into tests/golden
+// as the reference and passes, so the first run on a machine with a GPU
+// blesses every new scene, to be looked at and committed. Set WR3D_BLESS=1
+// to overwrite the existing references too, after an intended change.
+// Failing comparisons write the rendered frame and a diff image under
+// target/golden. Without a GPU adapter the tests are skipped, unless
+// WR3D_REQUIRE_GPU is set, e.g. on CI machines that have one.
+
+use async_std::task::block_on;
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+use wr3d::config::Config;
+use wr3d::state::State;
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 256;
+
+// how far apart two pixels may be before they count as different, in
+// weighted RGB distance (0 - ~765)
+const PIXEL_TOLERANCE: f32 = 12.0;
+// the fraction of pixels allowed to differ, to absorb rasterization
+// differences between drivers along edges
+const MISMATCH_TOLERANCE: f32 = 0.005;
+
+fn adapter_available() -> bool {
+    let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+    block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::Default,
+        compatible_surface: None,
+    }))
+    .is_some()
+}
+
+// "redmean" weighted distance, a cheap approximation of perceived color
+// difference that's much closer to what a person notices than plain RGB
+fn pixel_distance(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let r_mean = (a[0] as f32 + b[0] as f32) / 2.0;
+    let dr = a[0] as f32 - b[0] as f32;
+    let dg = a[1] as f32 - b[1] as f32;
+    let db = a[2] as f32 - b[2] as f32;
+    let da = a[3] as f32 - b[3] as f32;
+    ((2.0 + r_mean / 256.0) * dr * dr
+        + 4.0 * dg * dg
+        + (2.0 + (255.0 - r_mean) / 256.0) * db * db
+        + da * da)
+        .sqrt()
+}
+
+fn output_dir() -> PathBuf {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn check_golden(name: &str, actual: &RgbaImage) {
+    let reference_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name));
+    if std::env::var_os("WR3D_BLESS").is_some() {
+        actual.save(&reference_path).unwrap();
+        eprintln!("blessed {}", reference_path.display());
+        return;
+    }
+    if !reference_path.exists() {
+        actual.save(&reference_path).unwrap();
+        eprintln!(
+            "{} had no reference image, recorded {}, check and commit it",
+            name,
+            reference_path.display()
+        );
+        return;
+    }
+
+    let expected = image::open(&reference_path).unwrap().into_rgba8();
+    assert_eq!(
+        expected.dimensions(),
+        actual.dimensions(),
+        "{} was rendered at a different size than its reference",
+        name
+    );
+
+    let mut diff = RgbaImage::new(actual.width(), actual.height());
+    let mut mismatched = 0;
+    for ((expected, actual), diff) in expected.pixels().zip(actual.pixels()).zip(diff.pixels_mut()) {
+        if pixel_distance(expected, actual) > PIXEL_TOLERANCE {
+            mismatched += 1;
+            *diff = Rgba([255, 0, 0, 255]);
+        } else {
+            *diff = Rgba([actual[0] / 4, actual[1] / 4, actual[2] / 4, 255]);
+        }
+    }
+
+    let ratio = mismatched as f32 / (actual.width() * actual.height()) as f32;
+    if ratio > MISMATCH_TOLERANCE {
+        let dir = output_dir();
+        actual.save(dir.join(format!("{}.actual.png", name))).unwrap();
+        diff.save(dir.join(format!("{}.diff.png", name))).unwrap();
+        panic!(
+            "{} differs from its reference in {:.2}% of pixels, see {}",
+            name,
+            ratio * 100.0,
+            dir.display()
+        );
+    }
+}
+
+// renders one deterministic frame of the scene `setup` configures
+fn render_scene(setup: impl FnOnce(&mut Config)) -> RgbaImage {
+    let mut config = Config::default();
+    config.determinism.enabled = true;
+    setup(&mut config);
+    let mut state = block_on(State::new_headless(WIDTH, HEIGHT, &config)).unwrap();
+    state.update();
+    state.render().unwrap();
+    block_on(state.capture()).unwrap()
+}
+
+fn golden(name: &str, setup: impl FnOnce(&mut Config)) {
+    if !adapter_available() {
+        assert!(
+            std::env::var_os("WR3D_REQUIRE_GPU").is_none(),
+            "no GPU adapter available for golden image test {}",
+            name
+        );
+        eprintln!("no GPU adapter available, skipping golden image test {}", name);
+        return;
+    }
+    check_golden(name, &render_scene(setup));
+}
+
+#[test]
+fn cube() {
+    golden("cube", |_| {});
+}
+
+#[test]
+fn multisampled() {
+    golden("multisampled", |config| config.renderer.msaa = 4);
+}
+
+#[test]
+fn raymarched_shapes() {
+    golden("raymarched_shapes", |config| config.scene.sdf = Some("data/shapes.toml".to_string()));
+}
+
+#[test]
+fn curves() {
+    golden("curves", |config| config.scene.curves = Some("data/curves.toml".to_string()));
+}
+
+#[test]
+fn deferred() {
+    golden("deferred", |config| {
+        config.renderer.deferred = true;
+        config.renderer.ssao.enabled = true;
+    });
+}
+
+#[test]
+fn hdr_bloom() {
+    golden("hdr_bloom", |config| {
+        config.renderer.hdr.enabled = true;
+        config.renderer.bloom.enabled = true;
+    });
+}
+
+#[test]
+fn fxaa() {
+    golden("fxaa", |config| config.renderer.fxaa = true);
+}
@@ -0,0 +1,53 @@
+// The outliner's hierarchy on a scene loaded into a headless viewer:
+// children follow their parents, loops are refused and hiding or locking
+// takes the whole branch.
+
+use async_std::task::block_on;
+use glam::Vec3;
+use wr3d::config::Config;
+use wr3d::mesh::Transform;
+use wr3d::state::State;
+
+fn adapter_available() -> bool {
+    let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+    block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::Default,
+        compatible_surface: None,
+    }))
+    .is_some()
+}
+
+#[test]
+fn children_follow_their_parents() {
+    if !adapter_available() {
+        assert!(std::env::var_os("WR3D_REQUIRE_GPU").is_none(), "no GPU adapter available");
+        eprintln!("no GPU adapter available, skipping children_follow_their_parents");
+        return;
+    }
+    let mut config = Config::default();
+    config.scene.model = "data/cube.obj".to_string();
+    config.scene.extra_models = vec!["data/cube.gltf".to_string()];
+    let mut state = block_on(State::new_headless(64, 64, &config)).unwrap();
+    let scene = state.scene_mut();
+    let (parent, child) = (0, scene.models.len() - 1);
+    assert_ne!(parent, child);
+
+    scene.set_parent(child, Some(parent)).unwrap();
+    assert_eq!(scene.children(Some(parent)).collect::<Vec<_>>(), [child]);
+    assert!(scene.set_parent(parent, Some(child)).is_err());
+
+    let start = scene.models[child].transform.translation;
+    scene.set_transform(parent, Transform { translation: Vec3::new(2.0, 0.0, 0.0), ..Transform::default() });
+    let moved = scene.models[child].transform.translation - start;
+    assert!((moved - Vec3::new(2.0, 0.0, 0.0)).length() < 1e-4, "the child moved by {}", moved);
+
+    scene.set_hidden(parent, true);
+    assert!(scene.models[child].hidden && !scene.models[child].is_drawn());
+    scene.set_locked(parent, true);
+    assert!(scene.models[child].locked);
+
+    // back at the top level, it stays where it is and no longer follows
+    scene.set_parent(child, None).unwrap();
+    scene.set_transform(parent, Transform::default());
+    assert!((scene.models[child].transform.translation - start - Vec3::new(2.0, 0.0, 0.0)).length() < 1e-4);
+}
@@ -0,0 +1,75 @@
+// Selecting models in a headless viewer by boxing them in through the id
+// buffer, and transforming them as a group that undo puts back.
+
+use async_std::task::block_on;
+use glam::{Quat, Vec3};
+use std::time::Duration;
+use wr3d::config::Config;
+use wr3d::mesh::Transform;
+use wr3d::state::State;
+
+fn adapter_available() -> bool {
+    let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+    block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::Default,
+        compatible_surface: None,
+    }))
+    .is_some()
+}
+
+#[test]
+fn boxes_in_and_transforms_the_selection() {
+    if !adapter_available() {
+        assert!(std::env::var_os("WR3D_REQUIRE_GPU").is_none(), "no GPU adapter available");
+        eprintln!("no GPU adapter available, skipping boxes_in_and_transforms_the_selection");
+        return;
+    }
+    let mut config = Config::default();
+    config.scene.model = "data/cube.obj".to_string();
+    config.scene.extra_models = vec!["data/cube.gltf".to_string()];
+    let mut state = block_on(State::new_headless(64, 64, &config)).unwrap();
+
+    // the default camera looks at the cube at the origin
+    let receiver = state.select_box(0, 0, 64, 64);
+    let boxed = (0..100)
+        .find_map(|_| {
+            state.renderer_mut().poll_readbacks();
+            std::thread::sleep(Duration::from_millis(10));
+            receiver.try_recv().ok()
+        })
+        .expect("the box wasn't read back")
+        .unwrap();
+    assert!(!boxed.is_empty(), "the box found no models");
+    let count = state.scene_mut().models.len();
+    assert!(boxed.iter().all(|&model| model < count));
+
+    let (first, last) = (0, count - 1);
+    state.select(vec![last, first]).unwrap();
+    assert_eq!(state.selected(), [first, last]);
+    let before: Vec<Transform> = state.scene_mut().models.iter().map(|model| model.transform).collect();
+
+    // half a turn around the shared center and a step up
+    let delta = Transform {
+        translation: Vec3::new(0.0, 1.0, 0.0),
+        rotation: Quat::from_rotation_y(std::f32::consts::PI),
+        ..Transform::default()
+    };
+    assert!(state.transform_selection(&delta));
+    let moved = state.scene_mut().models[first].transform.translation - before[first].translation;
+    assert!((moved.y - 1.0).abs() < 1e-4, "the first model moved by {}", moved);
+
+    assert!(state.undo());
+    for (model, transform) in state.scene_mut().models.iter().zip(&before) {
+        assert!((model.transform.translation - transform.translation).length() < 1e-4);
+    }
+    assert!(!state.undo());
+    assert!(state.redo());
+    let redone = state.scene_mut().models[first].transform.translation - before[first].translation;
+    assert!((redone - moved).length() < 1e-4);
+
+    // locked models are left out
+    state.scene_mut().set_locked(first, true);
+    state.select(vec![first]).unwrap();
+    assert!(state.selected().is_empty());
+    assert!(!state.transform_selection(&delta));
+}